@@ -0,0 +1,152 @@
+use crate::{
+    components::router::{AppRoute, Link},
+    infra::{
+        api::HostService,
+        common_component::{CommonComponent, CommonComponentParts},
+    },
+};
+use anyhow::Result;
+use lldap_auth::access_review::{ReviewDecision, ReviewPage, ReviewedMember};
+use yew::prelude::*;
+
+/// The page linked from the email sent to the reviewer of an access review: the reviewer
+/// confirms or removes each member of the group.
+pub struct AccessReviewPage {
+    common: CommonComponentParts<Self>,
+    page: Option<ReviewPage>,
+}
+
+#[derive(Clone, PartialEq, Eq, Properties)]
+pub struct Props {
+    pub token: String,
+}
+
+pub enum Msg {
+    PageResponse(Result<ReviewPage>),
+    Decide { user_id: String, keep: bool },
+}
+
+impl CommonComponent<AccessReviewPage> for AccessReviewPage {
+    fn handle_msg(
+        &mut self,
+        ctx: &Context<Self>,
+        msg: <Self as Component>::Message,
+    ) -> Result<bool> {
+        match msg {
+            Msg::PageResponse(page) => self.page = Some(page?),
+            Msg::Decide { user_id, keep } => {
+                self.common.call_backend(
+                    ctx,
+                    HostService::decide_access_review(
+                        ctx.props().token.clone(),
+                        ReviewDecision { user_id, keep },
+                    ),
+                    Msg::PageResponse,
+                );
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl AccessReviewPage {
+    fn view_member(&self, ctx: &Context<Self>, member: &ReviewedMember) -> Html {
+        let link = &ctx.link();
+        let decision = match member.keep {
+            Some(true) => html! { <span class="text-success">{"Kept"}</span> },
+            Some(false) => html! { <span class="text-danger">{"Removed"}</span> },
+            None => {
+                let keep_id = member.user_id.clone();
+                let remove_id = member.user_id.clone();
+                html! {
+                  <>
+                    <button
+                      class="btn btn-sm btn-success me-2"
+                      disabled={self.common.is_task_running()}
+                      onclick={link.callback(move |_| Msg::Decide { user_id: keep_id.clone(), keep: true })}>
+                      {"Keep"}
+                    </button>
+                    <button
+                      class="btn btn-sm btn-danger"
+                      disabled={self.common.is_task_running()}
+                      onclick={link.callback(move |_| Msg::Decide { user_id: remove_id.clone(), keep: false })}>
+                      {"Remove"}
+                    </button>
+                  </>
+                }
+            }
+        };
+        html! {
+          <tr key={member.user_id.clone()}>
+            <td>{&member.user_id}</td>
+            <td>{decision}</td>
+          </tr>
+        }
+    }
+}
+
+impl Component for AccessReviewPage {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let mut component = AccessReviewPage {
+            common: CommonComponentParts::<Self>::create(),
+            page: None,
+        };
+        component.common.call_backend(
+            ctx,
+            HostService::get_access_review(ctx.props().token.clone()),
+            Msg::PageResponse,
+        );
+        component
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        CommonComponentParts::<Self>::update(self, ctx, msg)
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let content = match &self.page {
+            Some(page) if page.members.is_empty() => html! {
+              <p>{format!("The group \"{}\" had no members to review.", page.group)}</p>
+            },
+            Some(page) => html! {
+              <>
+                <p>
+                  {format!("As part of the access review \"{}\", please confirm or remove each \
+                    member of the group \"{}\". Removed members lose their access immediately, \
+                    and the decisions can't be changed.", page.campaign, page.group)}
+                </p>
+                <table class="table table-hover">
+                  <thead>
+                    <tr>
+                      <th>{"User ID"}</th>
+                      <th>{"Decision"}</th>
+                    </tr>
+                  </thead>
+                  <tbody>
+                    {page.members.iter().map(|m| self.view_member(ctx, m)).collect::<Html>()}
+                  </tbody>
+                </table>
+              </>
+            },
+            None => html! {},
+        };
+        html! {
+          <div>
+            <h2>{"Access review"}</h2>
+            {content}
+            { if let Some(e) = &self.common.error {
+                html! { <div class="alert alert-danger">{e.to_string()}</div> }
+              } else { html! {} }
+            }
+            <Link to={AppRoute::Login}>{"Back to the login page"}</Link>
+          </div>
+        }
+    }
+}
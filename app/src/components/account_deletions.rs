@@ -0,0 +1,168 @@
+use crate::{
+    components::router::{AppRoute, Link},
+    infra::{
+        common_component::{CommonComponent, CommonComponentParts},
+        date_format::format_date_time,
+    },
+};
+use anyhow::Result;
+use graphql_client::GraphQLQuery;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/get_account_deletions.graphql",
+    response_derives = "Debug,Clone,PartialEq,Eq",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct GetAccountDeletions;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/cancel_account_deletion.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct CancelAccountDeletion;
+
+pub type AccountDeletion = get_account_deletions::GetAccountDeletionsAccountDeletions;
+
+/// The accounts that their users asked to delete, for the admins to review.
+pub struct AccountDeletionsTable {
+    common: CommonComponentParts<Self>,
+    deletions: Option<Vec<AccountDeletion>>,
+}
+
+pub enum Msg {
+    ListResponse(Result<get_account_deletions::ResponseData>),
+    Cancel(String),
+    CancelResponse(String, Result<cancel_account_deletion::ResponseData>),
+}
+
+impl CommonComponent<AccountDeletionsTable> for AccountDeletionsTable {
+    fn handle_msg(
+        &mut self,
+        ctx: &Context<Self>,
+        msg: <Self as Component>::Message,
+    ) -> Result<bool> {
+        match msg {
+            Msg::ListResponse(response) => {
+                self.deletions = Some(response?.account_deletions);
+            }
+            Msg::Cancel(user_id) => {
+                self.common.call_graphql::<CancelAccountDeletion, _>(
+                    ctx,
+                    cancel_account_deletion::Variables {
+                        user_id: user_id.clone(),
+                    },
+                    move |r| Msg::CancelResponse(user_id.clone(), r),
+                    "Error trying to cancel the account deletion",
+                );
+            }
+            Msg::CancelResponse(user_id, response) => {
+                response?;
+                if let Some(deletions) = self.deletions.as_mut() {
+                    deletions.retain(|d| d.user_id != user_id);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for AccountDeletionsTable {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let mut table = AccountDeletionsTable {
+            common: CommonComponentParts::<Self>::create(),
+            deletions: None,
+        };
+        table.common.call_graphql::<GetAccountDeletions, _>(
+            ctx,
+            get_account_deletions::Variables {},
+            Msg::ListResponse,
+            "Error trying to fetch the account deletions",
+        );
+        table
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        CommonComponentParts::<Self>::update(self, ctx, msg)
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        match &self.deletions {
+            // Nothing to show most of the time.
+            None => self.view_errors(),
+            Some(deletions) if deletions.is_empty() => html! {},
+            Some(deletions) => html! {
+                <div class="mt-4">
+                  <h5 class="fw-bold">{"Account deletions"}</h5>
+                  <div class="table-responsive">
+                    <table class="table table-hover">
+                      <thead>
+                        <tr>
+                          <th>{"User"}</th>
+                          <th>{"Request date"}</th>
+                          <th>{"Deletion date"}</th>
+                          <th></th>
+                        </tr>
+                      </thead>
+                      <tbody>
+                        {deletions.iter().map(|d| self.view_deletion(ctx, d)).collect::<Vec<_>>()}
+                      </tbody>
+                    </table>
+                  </div>
+                  {self.view_errors()}
+                </div>
+            },
+        }
+    }
+}
+
+impl AccountDeletionsTable {
+    fn view_deletion(&self, ctx: &Context<Self>, deletion: &AccountDeletion) -> Html {
+        let link = ctx.link();
+        let user_id = deletion.user_id.clone();
+        html! {
+          <tr key={deletion.user_id.clone()}>
+            <td>
+              <Link to={AppRoute::UserDetails{user_id: deletion.user_id.clone()}}>
+                {&deletion.user_id}
+              </Link>
+            </td>
+            <td>{format_date_time(&deletion.request_date)}</td>
+            <td>
+              {match &deletion.deletion_date {
+                  Some(date) => html! {{format_date_time(date)}},
+                  None => html! {{"Waiting for the email confirmation"}},
+              }}
+            </td>
+            <td>
+              <button
+                class="btn btn-secondary"
+                disabled={self.common.is_task_running()}
+                onclick={link.callback(move |_| Msg::Cancel(user_id.clone()))}>
+                <i class="bi-arrow-counterclockwise me-2"></i>
+                {"Cancel the deletion"}
+              </button>
+            </td>
+          </tr>
+        }
+    }
+
+    fn view_errors(&self) -> Html {
+        match &self.common.error {
+            None => html! {},
+            Some(e) => html! {<div>{"Error: "}{e.to_string()}</div>},
+        }
+    }
+}
@@ -1,5 +1,8 @@
 use crate::{
-    components::select::{Select, SelectOption, SelectOptionProps},
+    components::{
+        pending_changes::ensure_applied,
+        select::{Select, SelectOption, SelectOptionProps},
+    },
     infra::common_component::{CommonComponent, CommonComponentParts},
 };
 use anyhow::{Error, Result};
@@ -63,7 +66,7 @@ impl CommonComponent<AddGroupMemberComponent> for AddGroupMemberComponent {
             }
             Msg::SubmitAddMember => return self.submit_add_member(ctx),
             Msg::AddMemberResponse(response) => {
-                response?;
+                ensure_applied(response?.add_user_to_group.pending_change_id)?;
                 let user = self
                     .selected_user
                     .as_ref()
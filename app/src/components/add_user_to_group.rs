@@ -1,5 +1,6 @@
 use crate::{
     components::{
+        pending_changes::ensure_applied,
         select::{Select, SelectOption, SelectOptionProps},
         user_details::Group,
     },
@@ -75,7 +76,7 @@ impl CommonComponent<AddUserToGroupComponent> for AddUserToGroupComponent {
             }
             Msg::SubmitAddGroup => return self.submit_add_group(ctx),
             Msg::AddGroupResponse(response) => {
-                response?;
+                ensure_applied(response?.add_user_to_group.pending_change_id)?;
                 // Adding the user to the group succeeded, we're not in the process of adding a
                 // group anymore.
                 let group = self
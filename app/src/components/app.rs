@@ -1,15 +1,20 @@
 use crate::{
     components::{
+        access_review::AccessReviewPage,
+        account_deletions::AccountDeletionsTable,
         banner::Banner,
         change_password::ChangePasswordForm,
         create_group::CreateGroupForm,
         create_group_attribute::CreateGroupAttributeForm,
         create_user::CreateUserForm,
         create_user_attribute::CreateUserAttributeForm,
+        delete_account::ConfirmAccountDeletion,
+        directory::Directory,
         group_details::GroupDetails,
         group_schema_table::ListGroupSchema,
         group_table::GroupTable,
         login::LoginForm,
+        pending_changes::PendingChangesTable,
         reset_password_step1::ResetPasswordStep1Form,
         reset_password_step2::ResetPasswordStep2Form,
         router::{AppRoute, Link, Redirect},
@@ -17,10 +22,11 @@ use crate::{
         user_schema_table::ListUserSchema,
         user_table::UserTable,
     },
-    infra::{api::HostService, cookies::get_cookie},
+    infra::{api::HostService, cookies::get_cookie, date_format},
 };
 
 use gloo_console::error;
+use graphql_client::GraphQLQuery;
 use yew::{
     function_component,
     html::Scope,
@@ -33,6 +39,15 @@ use yew_router::{
     BrowserRouter, Switch,
 };
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/get_user_preferences.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct GetUserPreferences;
+
 #[function_component(AppContainer)]
 pub fn app_container() -> Html {
     html! {
@@ -46,12 +61,14 @@ pub struct App {
     user_info: Option<(String, bool)>,
     redirect_to: Option<AppRoute>,
     password_reset_enabled: Option<bool>,
+    directory_enabled: bool,
 }
 
 pub enum Msg {
     Login((String, bool)),
     Logout,
     PasswordResetProbeFinished(anyhow::Result<bool>),
+    UserPreferencesResponse(anyhow::Result<get_user_preferences::ResponseData>),
 }
 
 impl Component for App {
@@ -75,10 +92,14 @@ impl Component for App {
                 }),
             redirect_to: Self::get_redirect_route(ctx),
             password_reset_enabled: None,
+            directory_enabled: false,
         };
         ctx.link().send_future(async move {
             Msg::PasswordResetProbeFinished(HostService::probe_password_reset().await)
         });
+        if let Some((user_name, _)) = &app.user_info {
+            Self::fetch_user_preferences(ctx, user_name.clone());
+        }
         app.apply_initial_redirections(ctx);
         app
     }
@@ -88,6 +109,7 @@ impl Component for App {
         match msg {
             Msg::Login((user_name, is_admin)) => {
                 self.user_info = Some((user_name.clone(), is_admin));
+                Self::fetch_user_preferences(ctx, user_name.clone());
                 history.push(self.redirect_to.take().unwrap_or_else(|| {
                     if is_admin {
                         AppRoute::ListUsers
@@ -101,6 +123,8 @@ impl Component for App {
             Msg::Logout => {
                 self.user_info = None;
                 self.redirect_to = None;
+                self.directory_enabled = false;
+                date_format::set_preferences(None, None);
                 history.push(AppRoute::Login);
             }
             Msg::PasswordResetProbeFinished(Ok(enabled)) => {
@@ -112,6 +136,13 @@ impl Component for App {
                     "Could not probe for password reset support: {err:#}"
                 ));
             }
+            Msg::UserPreferencesResponse(Ok(data)) => {
+                self.directory_enabled = data.directory_enabled;
+                date_format::set_preferences(data.user.preferred_language, data.user.timezone);
+            }
+            Msg::UserPreferencesResponse(Err(err)) => {
+                error!(&format!("Could not fetch the user preferences: {err:#}"));
+            }
         }
         true
     }
@@ -123,7 +154,7 @@ impl Component for App {
         let password_reset_enabled = self.password_reset_enabled;
         html! {
           <div>
-            <Banner is_admin={is_admin} username={username} on_logged_out={link.callback(|_| Msg::Logout)} />
+            <Banner is_admin={is_admin} directory_enabled={self.directory_enabled} username={username} on_logged_out={link.callback(|_| Msg::Logout)} />
             <div class="container py-3 bg-kug">
               <div class="row justify-content-center" style="padding-bottom: 80px;">
                 <main class="py-3" style="max-width: 1000px">
@@ -140,6 +171,20 @@ impl Component for App {
 }
 
 impl App {
+    // The dates are displayed in the language and timezone of the logged-in user, and the
+    // directory link only when they can browse it.
+    fn fetch_user_preferences(ctx: &Context<Self>, user_id: String) {
+        ctx.link().send_future(async move {
+            Msg::UserPreferencesResponse(
+                HostService::graphql_query::<GetUserPreferences>(
+                    get_user_preferences::Variables { id: user_id },
+                    "Error trying to fetch the user preferences",
+                )
+                .await,
+            )
+        });
+    }
+
     // Get the page to land on after logging in, defaulting to the index.
     fn get_redirect_route(ctx: &Context<Self>) -> Option<AppRoute> {
         let route = ctx.link().history().unwrap().location().route::<AppRoute>();
@@ -150,6 +195,8 @@ impl App {
                     | AppRoute::Login
                     | AppRoute::StartResetPassword
                     | AppRoute::FinishResetPassword { token: _ }
+                    | AppRoute::ConfirmAccountDeletion { token: _ }
+                    | AppRoute::AccessReview { token: _ }
             )
         })
     }
@@ -169,6 +216,15 @@ impl App {
                     None
                 }
             }
+            // The link from the email works whether the user is logged in or not.
+            (
+                Some(
+                    AppRoute::ConfirmAccountDeletion { token: _ }
+                    | AppRoute::AccessReview { token: _ },
+                ),
+                _,
+                _,
+            ) => None,
             (None, _, _) | (_, None, _) => Some(AppRoute::Login),
             // User is logged in, a URL was given, don't redirect.
             (_, Some(_), Some(_)) => None,
@@ -207,6 +263,7 @@ impl App {
                     <i class="bi-person-plus me-2"></i>
                     {"Create a user"}
                   </Link>
+                  <AccountDeletionsTable />
                 </div>
             },
             AppRoute::CreateGroup => html! {
@@ -233,6 +290,12 @@ impl App {
             AppRoute::ListGroupSchema => html! {
                 <ListGroupSchema />
             },
+            AppRoute::ListPendingChanges => html! {
+                <PendingChangesTable />
+            },
+            AppRoute::Directory => html! {
+                <Directory />
+            },
             AppRoute::GroupDetails { group_id } => html! {
                 <GroupDetails group_id={*group_id} is_admin={is_admin} />
             },
@@ -242,6 +305,12 @@ impl App {
             AppRoute::ChangePassword { user_id } => html! {
                 <ChangePasswordForm username={user_id.clone()} is_admin={is_admin} />
             },
+            AppRoute::ConfirmAccountDeletion { token } => html! {
+                <ConfirmAccountDeletion token={token.clone()} />
+            },
+            AppRoute::AccessReview { token } => html! {
+                <AccessReviewPage token={token.clone()} />
+            },
             AppRoute::StartResetPassword => match password_reset_enabled {
                 Some(true) => html! { <ResetPasswordStep1Form /> },
                 Some(false) => {
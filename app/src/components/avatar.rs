@@ -60,7 +60,7 @@ pub fn avatar(props: &Props) -> Html {
 }
 
 #[derive(Properties, PartialEq)]
-struct BlankAvatarDisplayProps {
+pub struct BlankAvatarDisplayProps {
     #[prop_or(None)]
     pub error: Option<AttrValue>,
     pub width: i32,
@@ -68,7 +68,7 @@ struct BlankAvatarDisplayProps {
 }
 
 #[function_component(BlankAvatarDisplay)]
-fn blank_avatar_display(props: &BlankAvatarDisplayProps) -> Html {
+pub fn blank_avatar_display(props: &BlankAvatarDisplayProps) -> Html {
     let fill = match &props.error {
         Some(_) => "red",
         None => "currentColor",
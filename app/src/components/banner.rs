@@ -1,14 +1,17 @@
 use crate::components::{
     avatar::Avatar,
+    impersonate::StopImpersonationButton,
     logout::LogoutButton,
     router::{AppRoute, Link},
 };
+use crate::infra::cookies::get_cookie;
 use wasm_bindgen::prelude::wasm_bindgen;
 use yew::{function_component, html, Callback, Properties};
 
 #[derive(Properties, PartialEq)]
 pub struct Props {
     pub is_admin: bool,
+    pub directory_enabled: bool,
     pub username: Option<String>,
     pub on_logged_out: Callback<()>,
 }
@@ -58,9 +61,30 @@ pub fn banner(props: &Props) -> Html {
                       {"Group schema"}
                     </Link>
                   </li>
+                  <li>
+                    <Link
+                      classes="nav-link px-2 h6"
+                      to={AppRoute::ListPendingChanges}>
+                      <i class="bi-check2-square me-2"></i>
+                      {"Approvals"}
+                    </Link>
+                  </li>
                 </>
               } } else { html!{} } }
+              {if props.directory_enabled { html! {
+                <li>
+                  <Link
+                    classes="nav-link px-2 h6"
+                    to={AppRoute::Directory}>
+                    <i class="bi-person-lines-fill me-2"></i>
+                    {"Directory"}
+                  </Link>
+                </li>
+              } } else { html!{} } }
             </ul>
+            {if let Some(impersonator) = get_cookie("impersonator").ok().flatten() { html! {
+              <StopImpersonationButton impersonator={impersonator} />
+            } } else { html! {} } }
             <UserMenu username={props.username.clone()} on_logged_out={props.on_logged_out.clone()}/>
             <DarkModeToggle />
           </div>
@@ -105,7 +105,7 @@ impl CommonComponent<ChangePasswordForm> for ChangePasswordForm {
                     };
                     self.common.call_backend(
                         ctx,
-                        HostService::login_start(req),
+                        HostService::login_start(req, None),
                         Msg::AuthenticationStartResponse,
                     );
                     Ok(true)
@@ -150,6 +150,7 @@ impl CommonComponent<CreateUserForm> for CreateUserForm {
                         lastName: None,
                         avatar: None,
                         attributes,
+                        externalId: None,
                     },
                 };
                 self.common.call_graphql::<CreateUser, _>(
@@ -0,0 +1,248 @@
+use crate::{
+    components::router::{AppRoute, Link},
+    infra::{
+        api::HostService,
+        common_component::{CommonComponent, CommonComponentParts},
+        cookies::{delete_cookie, get_cookie},
+    },
+};
+use anyhow::Result;
+use lldap_auth::account_deletion::{DeletionPolicy, ServerDeletionConfirmation};
+use yew::prelude::*;
+
+/// Lets users request the deletion of their own account, if the server allows it.
+pub struct RequestAccountDeletionButton {
+    common: CommonComponentParts<Self>,
+    policy: Option<DeletionPolicy>,
+    confirming: bool,
+    email_sent: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Properties)]
+pub struct Props {
+    pub username: String,
+}
+
+pub enum Msg {
+    PolicyResponse(Result<DeletionPolicy>),
+    DeletionClicked,
+    DeletionAborted,
+    DeletionConfirmed,
+    DeletionRequested(Result<()>),
+}
+
+impl CommonComponent<RequestAccountDeletionButton> for RequestAccountDeletionButton {
+    fn handle_msg(
+        &mut self,
+        ctx: &Context<Self>,
+        msg: <Self as Component>::Message,
+    ) -> Result<bool> {
+        match msg {
+            Msg::PolicyResponse(policy) => self.policy = Some(policy?),
+            Msg::DeletionClicked => self.confirming = true,
+            Msg::DeletionAborted => self.confirming = false,
+            Msg::DeletionConfirmed => {
+                self.common.call_backend(
+                    ctx,
+                    HostService::request_account_deletion(),
+                    Msg::DeletionRequested,
+                );
+            }
+            Msg::DeletionRequested(res) => {
+                self.confirming = false;
+                res?;
+                self.email_sent = true;
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl RequestAccountDeletionButton {
+    // Only the users themselves can ask for their account to be deleted, not an admin looking
+    // at their page or impersonating them.
+    fn is_own_account(ctx: &Context<Self>) -> bool {
+        get_cookie("user_id").ok().flatten().as_ref() == Some(&ctx.props().username)
+            && get_cookie("impersonator").ok().flatten().is_none()
+    }
+}
+
+impl Component for RequestAccountDeletionButton {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let mut component = RequestAccountDeletionButton {
+            common: CommonComponentParts::<Self>::create(),
+            policy: None,
+            confirming: false,
+            email_sent: false,
+        };
+        if Self::is_own_account(ctx) {
+            component.common.call_backend(
+                ctx,
+                HostService::get_account_deletion_policy(),
+                Msg::PolicyResponse,
+            );
+        }
+        component
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        CommonComponentParts::<Self>::update(self, ctx, msg)
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = &ctx.link();
+        let grace_period_days = match &self.policy {
+            Some(policy) if policy.enabled => policy.grace_period_days,
+            _ => return html! {},
+        };
+        let content = if self.email_sent {
+            html! {
+              <div class="alert alert-success">
+                {"An email was sent to you: follow the link in it to confirm the deletion."}
+              </div>
+            }
+        } else if self.confirming {
+            html! {
+              <div class="alert alert-warning">
+                <p>
+                  {format!("Your account will be disabled as soon as you confirm the deletion \
+                    by email, and permanently deleted {} days later.", grace_period_days)}
+                </p>
+                <button
+                  class="btn btn-danger me-2"
+                  disabled={self.common.is_task_running()}
+                  onclick={link.callback(|_| Msg::DeletionConfirmed)}>
+                  {"Send the confirmation email"}
+                </button>
+                <button
+                  class="btn btn-secondary"
+                  disabled={self.common.is_task_running()}
+                  onclick={link.callback(|_| Msg::DeletionAborted)}>
+                  {"Cancel"}
+                </button>
+              </div>
+            }
+        } else {
+            html! {
+              <button
+                class="btn btn-outline-danger"
+                onclick={link.callback(|_| Msg::DeletionClicked)}>
+                <i class="bi-trash me-2"></i>
+                {"Delete my account"}
+              </button>
+            }
+        };
+        html! {
+          <div class="mt-4">
+            {content}
+            { if let Some(e) = &self.common.error {
+                html! { <div class="alert alert-danger">{e.to_string()}</div> }
+              } else { html! {} }
+            }
+          </div>
+        }
+    }
+}
+
+/// The page linked from the confirmation email.
+pub struct ConfirmAccountDeletion {
+    common: CommonComponentParts<Self>,
+    confirmation: Option<ServerDeletionConfirmation>,
+}
+
+#[derive(Clone, PartialEq, Eq, Properties)]
+pub struct ConfirmProps {
+    pub token: String,
+}
+
+pub enum ConfirmMsg {
+    Confirm,
+    ConfirmResponse(Result<ServerDeletionConfirmation>),
+}
+
+impl CommonComponent<ConfirmAccountDeletion> for ConfirmAccountDeletion {
+    fn handle_msg(
+        &mut self,
+        ctx: &Context<Self>,
+        msg: <Self as Component>::Message,
+    ) -> Result<bool> {
+        match msg {
+            ConfirmMsg::Confirm => {
+                self.common.call_backend(
+                    ctx,
+                    HostService::confirm_account_deletion(ctx.props().token.clone()),
+                    ConfirmMsg::ConfirmResponse,
+                );
+            }
+            ConfirmMsg::ConfirmResponse(res) => {
+                self.confirmation = Some(res?);
+                // The server logged the user out.
+                delete_cookie("user_id")?;
+                delete_cookie("is_admin")?;
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for ConfirmAccountDeletion {
+    type Message = ConfirmMsg;
+    type Properties = ConfirmProps;
+
+    fn create(_: &Context<Self>) -> Self {
+        ConfirmAccountDeletion {
+            common: CommonComponentParts::<Self>::create(),
+            confirmation: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        CommonComponentParts::<Self>::update(self, ctx, msg)
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = &ctx.link();
+        let content = match &self.confirmation {
+            Some(confirmation) => html! {
+              <div class="alert alert-success">
+                {format!("The account \"{}\" is now disabled, and will be deleted on {}. \
+                  Contact an administrator if you change your mind before then.",
+                  confirmation.user_id, confirmation.deletion_date.date())}
+              </div>
+            },
+            None => html! {
+              <>
+                <p>{"Your account will be disabled immediately, and permanently deleted after a grace period."}</p>
+                <button
+                  class="btn btn-danger"
+                  disabled={self.common.is_task_running()}
+                  onclick={link.callback(|_| ConfirmMsg::Confirm)}>
+                  {"Confirm the deletion of my account"}
+                </button>
+              </>
+            },
+        };
+        html! {
+          <div>
+            <h2>{"Account deletion"}</h2>
+            {content}
+            { if let Some(e) = &self.common.error {
+                html! { <div class="alert alert-danger">{e.to_string()}</div> }
+              } else { html! {} }
+            }
+            <Link to={AppRoute::Login}>{"Back to the login page"}</Link>
+          </div>
+        }
+    }
+}
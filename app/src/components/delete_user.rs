@@ -1,6 +1,9 @@
-use crate::infra::{
-    common_component::{CommonComponent, CommonComponentParts},
-    modal::Modal,
+use crate::{
+    components::pending_changes::ensure_applied,
+    infra::{
+        common_component::{CommonComponent, CommonComponentParts},
+        modal::Modal,
+    },
 };
 use anyhow::{Error, Result};
 use graphql_client::GraphQLQuery;
@@ -60,7 +63,7 @@ impl CommonComponent<DeleteUser> for DeleteUser {
                 self.modal.as_ref().expect("modal not initialized").hide();
             }
             Msg::DeleteUserResponse(response) => {
-                response?;
+                ensure_applied(response?.delete_user.pending_change_id)?;
                 ctx.props()
                     .on_user_deleted
                     .emit(ctx.props().username.clone());
@@ -0,0 +1,195 @@
+use crate::{
+    components::avatar::BlankAvatarDisplay,
+    infra::common_component::{CommonComponent, CommonComponentParts},
+};
+use anyhow::Result;
+use graphql_client::GraphQLQuery;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/get_directory.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct GetDirectory;
+
+use get_directory::ResponseData;
+
+type User = get_directory::GetDirectoryDirectory;
+
+/// A read-only address book of the users visible to the current user.
+pub struct Directory {
+    common: CommonComponentParts<Self>,
+    search: String,
+    users: Option<Vec<User>>,
+}
+
+pub enum Msg {
+    DirectoryResponse(Result<ResponseData>),
+    SearchChanged(String),
+    Search,
+}
+
+impl CommonComponent<Directory> for Directory {
+    fn handle_msg(
+        &mut self,
+        ctx: &Context<Self>,
+        msg: <Self as Component>::Message,
+    ) -> Result<bool> {
+        match msg {
+            Msg::DirectoryResponse(response) => {
+                self.users = Some(response?.directory);
+                Ok(true)
+            }
+            Msg::SearchChanged(search) => {
+                self.search = search;
+                Ok(false)
+            }
+            Msg::Search => {
+                self.get_users(ctx);
+                Ok(true)
+            }
+        }
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Directory {
+    fn get_users(&mut self, ctx: &Context<Self>) {
+        let search = Some(self.search.trim().to_owned()).filter(|s| !s.is_empty());
+        self.common.call_graphql::<GetDirectory, _>(
+            ctx,
+            get_directory::Variables { search },
+            Msg::DirectoryResponse,
+            "Error trying to fetch the directory",
+        );
+    }
+}
+
+impl Component for Directory {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let mut directory = Directory {
+            common: CommonComponentParts::<Self>::create(),
+            search: String::new(),
+            users: None,
+        };
+        directory.get_users(ctx);
+        directory
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        CommonComponentParts::<Self>::update(self, ctx, msg)
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        html! {
+            <div>
+              <h3>{"Directory"}</h3>
+              <form class="input-group mb-3">
+                <input
+                  type="text"
+                  class="form-control"
+                  placeholder="Search by name, user ID or email"
+                  aria-label="Search"
+                  value={self.search.clone()}
+                  oninput={link.callback(|e: InputEvent| {
+                      let input: HtmlInputElement = e.target_unchecked_into();
+                      Msg::SearchChanged(input.value())
+                  })} />
+                <button
+                  type="submit"
+                  class="btn btn-primary"
+                  disabled={self.common.is_task_running()}
+                  onclick={link.callback(|e: MouseEvent| {e.prevent_default(); Msg::Search})}>
+                  <i class="bi-search me-2"></i>
+                  {"Search"}
+                </button>
+              </form>
+              {self.view_users()}
+              {self.view_errors()}
+            </div>
+        }
+    }
+}
+
+/// The first attribute holding a phone number, e.g. `phone` or `mobile_phone`.
+fn get_phone(user: &User) -> Option<&str> {
+    user.attributes
+        .iter()
+        .find(|a| a.name.contains("phone"))
+        .and_then(|a| a.value.first())
+        .map(String::as_str)
+}
+
+impl Directory {
+    fn view_users(&self) -> Html {
+        match &self.users {
+            None => html! {{"Loading..."}},
+            Some(users) if users.is_empty() => html! {{"No user found."}},
+            Some(users) => html! {
+                <div class="table-responsive">
+                  <table class="table table-hover">
+                    <thead>
+                      <tr>
+                        <th></th>
+                        <th>{"Name"}</th>
+                        <th>{"Email"}</th>
+                        <th>{"Phone"}</th>
+                        <th>{"Groups"}</th>
+                      </tr>
+                    </thead>
+                    <tbody>
+                      {users.iter().map(Self::view_user).collect::<Vec<_>>()}
+                    </tbody>
+                  </table>
+                </div>
+            },
+        }
+    }
+
+    fn view_user(user: &User) -> Html {
+        let name = if user.display_name.is_empty() {
+            &user.id
+        } else {
+            &user.display_name
+        };
+        html! {
+          <tr key={user.id.clone()}>
+            <td>
+              {match &user.avatar {
+                  Some(data) => html! {
+                    <img
+                      src={format!("data:image/jpeg;base64, {}", data)}
+                      style="max-height:32px;max-width:32px;height:auto;width:auto;"
+                      alt="Avatar" />
+                  },
+                  None => html! {<BlankAvatarDisplay width={32} height={32} />},
+              }}
+            </td>
+            <td>{name}</td>
+            <td><a href={format!("mailto:{}", user.email)}>{&user.email}</a></td>
+            <td>{get_phone(user).unwrap_or_default()}</td>
+            <td>
+              {user.groups.iter().map(|g| g.display_name.clone()).collect::<Vec<_>>().join(", ")}
+            </td>
+          </tr>
+        }
+    }
+
+    fn view_errors(&self) -> Html {
+        match &self.common.error {
+            None => html! {},
+            Some(e) => html! {<div>{"Error: "}{e.to_string()}</div>},
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use crate::infra::common_component::{CommonComponent, CommonComponentParts};
+use anyhow::Result;
+use graphql_client::GraphQLQuery;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/export_user_data.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct ExportUserData;
+
+/// Fetches everything stored about the user, and offers it as a JSON file.
+pub struct ExportUserDataButton {
+    common: CommonComponentParts<Self>,
+    export: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Properties)]
+pub struct Props {
+    pub username: String,
+}
+
+pub enum Msg {
+    ExportRequested,
+    ExportResponse(Result<export_user_data::ResponseData>),
+}
+
+impl CommonComponent<ExportUserDataButton> for ExportUserDataButton {
+    fn handle_msg(
+        &mut self,
+        ctx: &Context<Self>,
+        msg: <Self as Component>::Message,
+    ) -> Result<bool> {
+        match msg {
+            Msg::ExportRequested => {
+                self.common.call_graphql::<ExportUserData, _>(
+                    ctx,
+                    export_user_data::Variables {
+                        user_id: ctx.props().username.clone(),
+                    },
+                    Msg::ExportResponse,
+                    "Error trying to export the user data",
+                );
+            }
+            Msg::ExportResponse(response) => {
+                self.export = Some(response?.export_user_data);
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for ExportUserDataButton {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(_: &Context<Self>) -> Self {
+        ExportUserDataButton {
+            common: CommonComponentParts::<Self>::create(),
+            export: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        CommonComponentParts::<Self>::update(self, ctx, msg)
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = &ctx.link();
+        let button = match &self.export {
+            None => html! {
+              <button
+                class="btn btn-secondary me-2"
+                disabled={self.common.is_task_running()}
+                onclick={link.callback(|_| Msg::ExportRequested)}>
+                <i class="bi-download me-2"></i>
+                {"Export data"}
+              </button>
+            },
+            Some(export) => html! {
+              <a
+                class="btn btn-success me-2"
+                download={format!("{}.json", ctx.props().username)}
+                href={format!(
+                    "data:application/json;charset=utf-8,{}",
+                    url_escape::encode_component(export)
+                )}>
+                <i class="bi-download me-2"></i>
+                {"Download the export"}
+              </a>
+            },
+        };
+        html! {
+          <>
+            {button}
+            { if let Some(e) = &self.common.error {
+                html! { <div class="alert alert-danger">{e.to_string()}</div> }
+              } else { html! {} }
+            }
+          </>
+        }
+    }
+}
@@ -51,8 +51,8 @@ fn to_base64(file: &JsFile) -> Result<String> {
             file: Some(_),
             contents: Some(data),
         } => {
-            if !is_valid_jpeg(data.as_slice()) {
-                bail!("Chosen image is not a valid JPEG");
+            if !is_supported_image(data.as_slice()) {
+                bail!("Chosen image is not a valid JPEG or PNG");
             }
             Ok(base64::encode(data))
         }
@@ -151,10 +151,10 @@ impl Component for JpegFileInput {
                     if let Some(file) = &avatar.file {
                         if file.name() == file_name {
                             if let Result::Ok(data) = data {
-                                if !is_valid_jpeg(data.as_slice()) {
+                                if !is_supported_image(data.as_slice()) {
                                     // Clear the selection.
                                     self.avatar = Some(JsFile::default());
-                                    // TODO: bail!("Chosen image is not a valid JPEG");
+                                    // TODO: bail!("Chosen image is not a valid JPEG or PNG");
                                 } else {
                                     avatar.contents = Some(data);
                                     return true;
@@ -187,7 +187,7 @@ impl Component for JpegFileInput {
                         class="form-control"
                         id="avatarInput"
                         type="file"
-                        accept="image/jpeg"
+                        accept="image/jpeg,image/png"
                         oninput={link.callback(|e: InputEvent| {
                             let input: HtmlInputElement = e.target_unchecked_into();
                             Self::upload_files(input.files())
@@ -231,8 +231,15 @@ impl JpegFileInput {
     }
 }
 
-fn is_valid_jpeg(bytes: &[u8]) -> bool {
-    image::io::Reader::with_format(std::io::Cursor::new(bytes), image::ImageFormat::Jpeg)
-        .decode()
-        .is_ok()
+/// The PNGs are converted to JPEG by the server, which checks them.
+fn is_supported_image(bytes: &[u8]) -> bool {
+    match image::guess_format(bytes) {
+        Ok(image::ImageFormat::Jpeg) => {
+            image::io::Reader::with_format(std::io::Cursor::new(bytes), image::ImageFormat::Jpeg)
+                .decode()
+                .is_ok()
+        }
+        Ok(image::ImageFormat::Png) => true,
+        _ => false,
+    }
 }
@@ -3,41 +3,81 @@ use crate::{
         delete_group::DeleteGroup,
         router::{AppRoute, Link},
     },
-    infra::common_component::{CommonComponent, CommonComponentParts},
+    infra::{
+        common_component::{CommonComponent, CommonComponentParts},
+        date_format::format_date,
+    },
 };
 use anyhow::{Error, Result};
 use graphql_client::GraphQLQuery;
+use web_sys::HtmlInputElement;
 use yew::prelude::*;
 
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "../schema.graphql",
-    query_path = "queries/get_group_list.graphql",
+    query_path = "queries/search_groups.graphql",
     response_derives = "Debug,Clone,PartialEq,Eq",
     custom_scalars_module = "crate::infra::graphql"
 )]
-pub struct GetGroupList;
+pub struct SearchGroups;
+
+use search_groups::{GroupFilter, ResponseData};
 
-use get_group_list::ResponseData;
+pub type Group = search_groups::SearchGroupsGroups;
 
-pub type Group = get_group_list::GetGroupListGroups;
+const GROUPS_PER_PAGE: i64 = 50;
 
 pub struct GroupTable {
     common: CommonComponentParts<Self>,
+    search: String,
+    offset: i64,
     groups: Option<Vec<Group>>,
+    has_next_page: bool,
 }
 
 pub enum Msg {
     ListGroupsResponse(Result<ResponseData>),
     OnGroupDeleted(i64),
     OnError(Error),
+    SearchChanged(String),
+    Search,
+    PreviousPage,
+    NextPage,
 }
 
 impl CommonComponent<GroupTable> for GroupTable {
-    fn handle_msg(&mut self, _: &Context<Self>, msg: <Self as Component>::Message) -> Result<bool> {
+    fn handle_msg(
+        &mut self,
+        ctx: &Context<Self>,
+        msg: <Self as Component>::Message,
+    ) -> Result<bool> {
         match msg {
             Msg::ListGroupsResponse(groups) => {
-                self.groups = Some(groups?.groups.into_iter().collect());
+                let mut groups = groups?.groups;
+                // One more group than displayed is requested, to know if there is another page.
+                self.has_next_page = groups.len() as i64 > GROUPS_PER_PAGE;
+                groups.truncate(GROUPS_PER_PAGE as usize);
+                self.groups = Some(groups);
+                Ok(true)
+            }
+            Msg::SearchChanged(search) => {
+                self.search = search;
+                Ok(false)
+            }
+            Msg::Search => {
+                self.offset = 0;
+                self.get_groups(ctx);
+                Ok(true)
+            }
+            Msg::PreviousPage => {
+                self.offset = (self.offset - GROUPS_PER_PAGE).max(0);
+                self.get_groups(ctx);
+                Ok(true)
+            }
+            Msg::NextPage => {
+                self.offset += GROUPS_PER_PAGE;
+                self.get_groups(ctx);
                 Ok(true)
             }
             Msg::OnError(e) => Err(e),
@@ -54,6 +94,30 @@ impl CommonComponent<GroupTable> for GroupTable {
     }
 }
 
+impl GroupTable {
+    fn get_groups(&mut self, ctx: &Context<Self>) {
+        let filter = Some(self.search.trim().to_owned())
+            .filter(|s| !s.is_empty())
+            .map(|search| GroupFilter {
+                any: None,
+                all: None,
+                not: None,
+                eq: None,
+                display_name_contains: Some(search),
+            });
+        self.common.call_graphql::<SearchGroups, _>(
+            ctx,
+            search_groups::Variables {
+                filter,
+                offset: Some(self.offset),
+                limit: Some(GROUPS_PER_PAGE + 1),
+            },
+            Msg::ListGroupsResponse,
+            "Error trying to fetch groups",
+        );
+    }
+}
+
 impl Component for GroupTable {
     type Message = Msg;
     type Properties = ();
@@ -61,14 +125,12 @@ impl Component for GroupTable {
     fn create(ctx: &Context<Self>) -> Self {
         let mut table = GroupTable {
             common: CommonComponentParts::<Self>::create(),
+            search: String::new(),
+            offset: 0,
             groups: None,
+            has_next_page: false,
         };
-        table.common.call_graphql::<GetGroupList, _>(
-            ctx,
-            get_group_list::Variables {},
-            Msg::ListGroupsResponse,
-            "Error trying to fetch groups",
-        );
+        table.get_groups(ctx);
         table
     }
 
@@ -77,9 +139,31 @@ impl Component for GroupTable {
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
         html! {
             <div>
+              <form class="input-group mb-3">
+                <input
+                  type="text"
+                  class="form-control"
+                  placeholder="Search by group name"
+                  aria-label="Search"
+                  value={self.search.clone()}
+                  oninput={link.callback(|e: InputEvent| {
+                      let input: HtmlInputElement = e.target_unchecked_into();
+                      Msg::SearchChanged(input.value())
+                  })} />
+                <button
+                  type="submit"
+                  class="btn btn-primary"
+                  disabled={self.common.is_task_running()}
+                  onclick={link.callback(|e: MouseEvent| {e.prevent_default(); Msg::Search})}>
+                  <i class="bi-search me-2"></i>
+                  {"Search"}
+                </button>
+              </form>
               {self.view_groups(ctx)}
+              {self.view_pagination(ctx)}
               {self.view_errors()}
             </div>
         }
@@ -108,10 +192,40 @@ impl GroupTable {
         };
         match &self.groups {
             None => html! {{"Loading..."}},
+            Some(groups) if groups.is_empty() => html! {{"No group found."}},
             Some(groups) => make_table(groups),
         }
     }
 
+    fn view_pagination(&self, ctx: &Context<Self>) -> Html {
+        if self.offset == 0 && !self.has_next_page {
+            return html! {};
+        }
+        let link = ctx.link();
+        html! {
+          <nav aria-label="Group pages">
+            <ul class="pagination">
+              <li class={classes!("page-item", (self.offset == 0).then_some("disabled"))}>
+                <button
+                  class="page-link"
+                  disabled={self.offset == 0 || self.common.is_task_running()}
+                  onclick={link.callback(|_| Msg::PreviousPage)}>
+                  {"Previous"}
+                </button>
+              </li>
+              <li class={classes!("page-item", (!self.has_next_page).then_some("disabled"))}>
+                <button
+                  class="page-link"
+                  disabled={!self.has_next_page || self.common.is_task_running()}
+                  onclick={link.callback(|_| Msg::NextPage)}>
+                  {"Next"}
+                </button>
+              </li>
+            </ul>
+          </nav>
+        }
+    }
+
     fn view_group(&self, ctx: &Context<Self>, group: &Group) -> Html {
         let link = ctx.link();
         html! {
@@ -122,7 +236,7 @@ impl GroupTable {
                 </Link>
               </td>
               <td>
-                {&group.creation_date.naive_local().date()}
+                {format_date(&group.creation_date)}
               </td>
               <td>
                 <DeleteGroup
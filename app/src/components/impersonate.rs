@@ -0,0 +1,157 @@
+use crate::infra::{
+    api::HostService,
+    common_component::{CommonComponent, CommonComponentParts},
+};
+use anyhow::{anyhow, Result};
+use yew::prelude::*;
+
+fn reload_page() -> Result<()> {
+    web_sys::window()
+        .ok_or_else(|| anyhow!("Could not get window"))?
+        .location()
+        .reload()
+        .map_err(|_| anyhow!("Could not reload the page"))
+}
+
+/// Lets an admin see the application as the given user (read-only).
+pub struct ImpersonateButton {
+    common: CommonComponentParts<Self>,
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct Props {
+    pub user_id: String,
+}
+
+pub enum Msg {
+    ImpersonationRequested,
+    ImpersonationStarted(Result<(String, bool)>),
+}
+
+impl CommonComponent<ImpersonateButton> for ImpersonateButton {
+    fn handle_msg(
+        &mut self,
+        ctx: &Context<Self>,
+        msg: <Self as Component>::Message,
+    ) -> Result<bool> {
+        match msg {
+            Msg::ImpersonationRequested => {
+                self.common.call_backend(
+                    ctx,
+                    HostService::impersonate(ctx.props().user_id.clone()),
+                    Msg::ImpersonationStarted,
+                );
+            }
+            Msg::ImpersonationStarted(res) => {
+                res?;
+                reload_page()?;
+            }
+        }
+        Ok(false)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for ImpersonateButton {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(_: &Context<Self>) -> Self {
+        ImpersonateButton {
+            common: CommonComponentParts::<Self>::create(),
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        CommonComponentParts::<Self>::update(self, ctx, msg)
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = &ctx.link();
+        html! {
+          <>
+            <button
+              class="btn btn-secondary me-2"
+              disabled={self.common.is_task_running()}
+              onclick={link.callback(|_| Msg::ImpersonationRequested)}>
+              <i class="bi-eye me-2"></i>
+              {"View as user"}
+            </button>
+            { if let Some(e) = &self.common.error {
+                html! { <div class="alert alert-danger">{e.to_string()}</div> }
+              } else { html! {} }
+            }
+          </>
+        }
+    }
+}
+
+/// Goes back to the admin's own session.
+pub struct StopImpersonationButton {
+    common: CommonComponentParts<Self>,
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct StopProps {
+    pub impersonator: String,
+}
+
+pub enum StopMsg {
+    StopRequested,
+    Stopped(Result<(String, bool)>),
+}
+
+impl CommonComponent<StopImpersonationButton> for StopImpersonationButton {
+    fn handle_msg(
+        &mut self,
+        ctx: &Context<Self>,
+        msg: <Self as Component>::Message,
+    ) -> Result<bool> {
+        match msg {
+            StopMsg::StopRequested => {
+                // The refresh token still belongs to the admin.
+                self.common
+                    .call_backend(ctx, HostService::refresh(), StopMsg::Stopped);
+            }
+            StopMsg::Stopped(res) => {
+                res?;
+                reload_page()?;
+            }
+        }
+        Ok(false)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for StopImpersonationButton {
+    type Message = StopMsg;
+    type Properties = StopProps;
+
+    fn create(_: &Context<Self>) -> Self {
+        StopImpersonationButton {
+            common: CommonComponentParts::<Self>::create(),
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        CommonComponentParts::<Self>::update(self, ctx, msg)
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = &ctx.link();
+        html! {
+          <button
+            class="btn btn-warning btn-sm me-3"
+            disabled={self.common.is_task_running()}
+            onclick={link.callback(|_| StopMsg::StopRequested)}>
+            {format!("Back to {}", ctx.props().impersonator)}
+          </button>
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{
     components::{
         form::submit::Submit,
+        login_page_help::LoginPageHelp,
         router::{AppRoute, Link},
     },
     infra::{
@@ -20,6 +21,7 @@ pub struct LoginForm {
     common: CommonComponentParts<Self>,
     form: Form<FormModel>,
     refreshing: bool,
+    captcha: captcha::Challenge,
 }
 
 /// The fields of the form, with the constraints.
@@ -29,6 +31,7 @@ pub struct FormModel {
     username: String,
     #[validate(length(min = 8, message = "Invalid password. Min length: 8"))]
     password: String,
+    remember_me: bool,
 }
 
 #[derive(Clone, PartialEq, Properties)]
@@ -41,6 +44,7 @@ pub enum Msg {
     Update,
     Submit,
     AuthenticationRefreshResponse(Result<(String, bool)>),
+    CaptchaResponse(Result<captcha::ServerChallengeResponse>),
     AuthenticationStartResponse(
         (
             opaque::client::login::ClientLogin,
@@ -63,7 +67,9 @@ impl CommonComponent<LoginForm> for LoginForm {
                 if !self.form.validate() {
                     bail!("Check the form for errors");
                 }
-                let FormModel { username, password } = self.form.model();
+                let FormModel {
+                    username, password, ..
+                } = self.form.model();
                 let mut rng = rand::rngs::OsRng;
                 let opaque::client::login::ClientLoginStartResult { state, message } =
                     opaque::client::login::start_login(&password, &mut rng)
@@ -72,13 +78,27 @@ impl CommonComponent<LoginForm> for LoginForm {
                     username: username.into(),
                     login_start_request: message,
                 };
-                self.common
-                    .call_backend(ctx, HostService::login_start(req), move |r| {
-                        Msg::AuthenticationStartResponse((state, r))
-                    });
+                let challenge = self.captcha.clone();
+                self.common.call_backend(
+                    ctx,
+                    async move {
+                        let captcha_response =
+                            crate::infra::captcha::get_response(&challenge).await?;
+                        HostService::login_start(req, captcha_response).await
+                    },
+                    move |r| Msg::AuthenticationStartResponse((state, r)),
+                );
+                Ok(true)
+            }
+            Msg::CaptchaResponse(response) => {
+                let response = response?;
+                if response.required_for_login {
+                    self.captcha = response.challenge;
+                }
                 Ok(true)
             }
             Msg::AuthenticationStartResponse((login_start, res)) => {
+                crate::infra::captcha::reset(&self.captcha);
                 let res = res.context("Could not log in (invalid response to login start)")?;
                 let login_finish =
                     match opaque::client::login::finish_login(login_start, res.credential_response)
@@ -95,6 +115,7 @@ impl CommonComponent<LoginForm> for LoginForm {
                 let req = login::ClientLoginFinishRequest {
                     server_data: res.server_data,
                     credential_finalization: login_finish.message,
+                    remember_me: self.form.model().remember_me,
                 };
                 self.common.call_backend(
                     ctx,
@@ -133,7 +154,10 @@ impl Component for LoginForm {
             common: CommonComponentParts::<Self>::create(),
             form: Form::<FormModel>::new(FormModel::default()),
             refreshing: true,
+            captcha: captcha::Challenge::None,
         };
+        ctx.link()
+            .send_future(async { Msg::CaptchaResponse(HostService::get_captcha().await) });
         app.common.call_backend(
             ctx,
             HostService::refresh(),
@@ -146,6 +170,12 @@ impl Component for LoginForm {
         CommonComponentParts::<Self>::update(self, ctx, msg)
     }
 
+    fn rendered(&mut self, _: &Context<Self>, _: bool) {
+        if let Err(e) = crate::infra::captcha::load(&self.captcha) {
+            error!(&format!("Could not load the CAPTCHA: {:#}", e));
+        }
+    }
+
     fn view(&self, ctx: &Context<Self>) -> Html {
         type Field = yew_form::Field<FormModel>;
         let password_reset_enabled = ctx.props().password_reset_enabled;
@@ -191,6 +221,13 @@ impl Component for LoginForm {
                     placeholder="Password"
                     autocomplete="current-password" />
                 </div>
+                <div class="form-check mb-2">
+                  <yew_form::CheckBox<FormModel>
+                    form={&self.form}
+                    field_name="remember_me" />
+                  <label class="form-check-label" for="remember_me">{"Remember me"}</label>
+                </div>
+                { crate::infra::captcha::view(&self.captcha) }
                 <Submit
                   text="Login"
                   disabled={self.common.is_task_running()}
@@ -214,6 +251,7 @@ impl Component for LoginForm {
                   } else { html! {} }
                 }
                 </div>
+                <LoginPageHelp />
               </form>
             }
         }
@@ -0,0 +1,52 @@
+use crate::infra::api::HostService;
+use gloo_console::error;
+use lldap_auth::branding;
+use wasm_bindgen_futures::spawn_local;
+use yew::{function_component, html, use_effect_with_deps, use_state_eq, Html};
+
+/// The links and help text configured by the admins, below the login and password reset forms.
+#[function_component(LoginPageHelp)]
+pub fn login_page_help() -> Html {
+    let branding = use_state_eq(branding::LoginPage::default);
+    {
+        let branding = branding.clone();
+        use_effect_with_deps(
+            move |_| {
+                spawn_local(async move {
+                    match HostService::get_branding().await {
+                        Ok(page) => branding.set(page),
+                        Err(e) => error!(&format!("Could not get the login page links: {e:#}")),
+                    }
+                });
+                || ()
+            },
+            (),
+        );
+    }
+    html! {
+      <div class="mt-3">
+        { if branding.links.is_empty() {
+            html! {}
+          } else {
+            html! {
+              <ul class="list-inline">
+                { for branding.links.iter().map(|link| html! {
+                  <li class="list-inline-item">
+                    <a href={link.url.clone()} target="_blank" rel="noopener noreferrer">
+                      {&link.label}
+                    </a>
+                  </li>
+                })}
+              </ul>
+            }
+          }
+        }
+        { if let Some(help_text) = &branding.help_text {
+            html! {
+              <p class="text-muted" style="white-space: pre-line">{help_text}</p>
+            }
+          } else { html! {} }
+        }
+      </div>
+    }
+}
@@ -1,3 +1,5 @@
+pub mod access_review;
+pub mod account_deletions;
 pub mod add_group_member;
 pub mod add_user_to_group;
 pub mod app;
@@ -8,17 +10,24 @@ pub mod create_group;
 pub mod create_group_attribute;
 pub mod create_user;
 pub mod create_user_attribute;
+pub mod delete_account;
 pub mod delete_group;
 pub mod delete_group_attribute;
 pub mod delete_user;
 pub mod delete_user_attribute;
+pub mod directory;
+pub mod export_user_data;
 pub mod form;
 pub mod group_details;
 pub mod group_details_form;
 pub mod group_schema_table;
 pub mod group_table;
+pub mod impersonate;
 pub mod login;
+pub mod login_page_help;
 pub mod logout;
+pub mod pending_changes;
+pub mod profile_history;
 pub mod remove_user_from_group;
 pub mod reset_password_step1;
 pub mod reset_password_step2;
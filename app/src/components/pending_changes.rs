@@ -0,0 +1,235 @@
+use crate::{
+    components::router::{AppRoute, Link},
+    infra::{
+        common_component::{CommonComponent, CommonComponentParts},
+        date_format::format_date_time,
+    },
+};
+use anyhow::{bail, Result};
+use graphql_client::GraphQLQuery;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/get_pending_changes.graphql",
+    response_derives = "Debug,Clone,PartialEq,Eq",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct GetPendingChanges;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/approve_pending_change.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct ApprovePendingChange;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/reject_pending_change.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct RejectPendingChange;
+
+use get_pending_changes::PendingChangeKind;
+
+pub type PendingChange = get_pending_changes::GetPendingChangesPendingChanges;
+
+/// Turns a pending change returned by a mutation into an error, so that the caller doesn't
+/// treat the change as applied.
+pub fn ensure_applied(pending_change_id: Option<i64>) -> Result<()> {
+    if let Some(id) = pending_change_id {
+        bail!(
+            "The change (#{}) is waiting for the approval of another admin",
+            id
+        );
+    }
+    Ok(())
+}
+
+pub struct PendingChangesTable {
+    common: CommonComponentParts<Self>,
+    changes: Option<Vec<PendingChange>>,
+}
+
+pub enum Msg {
+    ListResponse(Result<get_pending_changes::ResponseData>),
+    Approve(i64),
+    Reject(i64),
+    ApproveResponse(i64, Result<approve_pending_change::ResponseData>),
+    RejectResponse(i64, Result<reject_pending_change::ResponseData>),
+}
+
+impl CommonComponent<PendingChangesTable> for PendingChangesTable {
+    fn handle_msg(
+        &mut self,
+        ctx: &Context<Self>,
+        msg: <Self as Component>::Message,
+    ) -> Result<bool> {
+        match msg {
+            Msg::ListResponse(response) => {
+                self.changes = Some(response?.pending_changes);
+            }
+            Msg::Approve(id) => {
+                self.common.call_graphql::<ApprovePendingChange, _>(
+                    ctx,
+                    approve_pending_change::Variables { id },
+                    move |r| Msg::ApproveResponse(id, r),
+                    "Error trying to approve the change",
+                );
+            }
+            Msg::Reject(id) => {
+                self.common.call_graphql::<RejectPendingChange, _>(
+                    ctx,
+                    reject_pending_change::Variables { id },
+                    move |r| Msg::RejectResponse(id, r),
+                    "Error trying to reject the change",
+                );
+            }
+            Msg::ApproveResponse(id, response) => {
+                response?;
+                self.remove_change(id);
+            }
+            Msg::RejectResponse(id, response) => {
+                response?;
+                self.remove_change(id);
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for PendingChangesTable {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let mut table = PendingChangesTable {
+            common: CommonComponentParts::<Self>::create(),
+            changes: None,
+        };
+        table.common.call_graphql::<GetPendingChanges, _>(
+            ctx,
+            get_pending_changes::Variables {},
+            Msg::ListResponse,
+            "Error trying to fetch the pending changes",
+        );
+        table
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        CommonComponentParts::<Self>::update(self, ctx, msg)
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        html! {
+            <div>
+              {self.view_changes(ctx)}
+              {self.view_errors()}
+            </div>
+        }
+    }
+}
+
+impl PendingChangesTable {
+    fn remove_change(&mut self, id: i64) {
+        if let Some(changes) = self.changes.as_mut() {
+            changes.retain(|c| c.id != id);
+        }
+    }
+
+    fn view_changes(&self, ctx: &Context<Self>) -> Html {
+        match &self.changes {
+            None => html! {{"Loading..."}},
+            Some(changes) if changes.is_empty() => html! {{"No change is waiting for approval."}},
+            Some(changes) => html! {
+                <div class="table-responsive">
+                  <table class="table table-hover">
+                    <thead>
+                      <tr>
+                        <th>{"Change"}</th>
+                        <th>{"Requested by"}</th>
+                        <th>{"Request date"}</th>
+                        <th></th>
+                      </tr>
+                    </thead>
+                    <tbody>
+                      {changes.iter().map(|c| self.view_change(ctx, c)).collect::<Vec<_>>()}
+                    </tbody>
+                  </table>
+                </div>
+            },
+        }
+    }
+
+    fn view_change(&self, ctx: &Context<Self>, change: &PendingChange) -> Html {
+        let link = ctx.link();
+        let id = change.id;
+        let user = html! {
+          <Link to={AppRoute::UserDetails{user_id: change.user_id.clone()}}>
+            {&change.user_id}
+          </Link>
+        };
+        let group = match change.group_id {
+            Some(group_id) => html! {
+              <Link to={AppRoute::GroupDetails{group_id}}>
+                {format!("group #{}", group_id)}
+              </Link>
+            },
+            None => html! {},
+        };
+        let description = match change.kind {
+            PendingChangeKind::DELETE_USER => html! {<>{"Delete user "}{user}</>},
+            PendingChangeKind::ADD_USER_TO_GROUP => html! {<>{"Add "}{user}{" to "}{group}</>},
+            PendingChangeKind::REMOVE_USER_FROM_GROUP => {
+                html! {<>{"Remove "}{user}{" from "}{group}</>}
+            }
+            PendingChangeKind::SET_MEMBERSHIP_EXPIRY => match &change.expiry_date {
+                Some(date) => html! {
+                  <>{"Make "}{user}{" a member of "}{group}{" until "}{format_date_time(date)}</>
+                },
+                None => html! {<>{"Make "}{user}{" a permanent member of "}{group}</>},
+            },
+            PendingChangeKind::Other(_) => html! {{"Unknown change"}},
+        };
+        html! {
+          <tr key={id}>
+            <td>{description}</td>
+            <td>{&change.requested_by}</td>
+            <td>{format_date_time(&change.request_date)}</td>
+            <td>
+              <button
+                class="btn btn-success me-2"
+                disabled={self.common.is_task_running()}
+                onclick={link.callback(move |_| Msg::Approve(id))}>
+                <i class="bi-check-circle me-2"></i>
+                {"Approve"}
+              </button>
+              <button
+                class="btn btn-danger"
+                disabled={self.common.is_task_running()}
+                onclick={link.callback(move |_| Msg::Reject(id))}>
+                <i class="bi-x-circle me-2"></i>
+                {"Reject"}
+              </button>
+            </td>
+          </tr>
+        }
+    }
+
+    fn view_errors(&self) -> Html {
+        match &self.common.error {
+            None => html! {},
+            Some(e) => html! {<div>{"Error: "}{e.to_string()}</div>},
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::infra::{
+    common_component::{CommonComponent, CommonComponentParts},
+    date_format::format_date_time,
+};
+use anyhow::Result;
+use graphql_client::GraphQLQuery;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/get_user_profile_history.graphql",
+    response_derives = "Debug,Clone,PartialEq,Eq",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct GetUserProfileHistory;
+
+pub type ProfileChange = get_user_profile_history::GetUserProfileHistoryUserProfileHistory;
+
+/// The changes made to the profile of a user, most recent first.
+pub struct ProfileHistory {
+    common: CommonComponentParts<Self>,
+    changes: Option<Vec<ProfileChange>>,
+}
+
+pub enum Msg {
+    HistoryResponse(Result<get_user_profile_history::ResponseData>),
+}
+
+#[derive(yew::Properties, Clone, PartialEq, Eq)]
+pub struct Props {
+    pub username: String,
+}
+
+impl CommonComponent<ProfileHistory> for ProfileHistory {
+    fn handle_msg(&mut self, _: &Context<Self>, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::HistoryResponse(response) => {
+                let mut changes = response?.user.profile_history;
+                changes.reverse();
+                self.changes = Some(changes);
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for ProfileHistory {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let mut history = ProfileHistory {
+            common: CommonComponentParts::<Self>::create(),
+            changes: None,
+        };
+        history.common.call_graphql::<GetUserProfileHistory, _>(
+            ctx,
+            get_user_profile_history::Variables {
+                id: ctx.props().username.clone(),
+            },
+            Msg::HistoryResponse,
+            "Error trying to fetch the profile history",
+        );
+        history
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        CommonComponentParts::<Self>::update(self, ctx, msg)
+    }
+
+    fn view(&self, _: &Context<Self>) -> Html {
+        html! {
+          <>
+            <h5 class="row m-3 fw-bold">{"Profile history"}</h5>
+            {match &self.changes {
+                None => html! {},
+                Some(changes) if changes.is_empty() => html! {
+                  <p>{"The profile of this user has not changed yet."}</p>
+                },
+                Some(changes) => html! {
+                  <ul class="list-group list-group-flush">
+                    {changes.iter().map(view_change).collect::<Vec<_>>()}
+                  </ul>
+                },
+            }}
+            {match &self.common.error {
+                None => html! {},
+                Some(e) => html! {<div>{"Error: "}{e.to_string()}</div>},
+            }}
+          </>
+        }
+    }
+}
+
+fn view_value(value: &Option<String>) -> Html {
+    match value {
+        Some(value) => html! {<code>{value}</code>},
+        None => html! {<em>{"unset"}</em>},
+    }
+}
+
+fn view_change(change: &ProfileChange) -> Html {
+    html! {
+      <li class="list-group-item">
+        <div class="d-flex justify-content-between">
+          <span class="fw-bold">{&change.field}</span>
+          <small class="text-muted">
+            {format_date_time(&change.change_date)}
+            {" by "}
+            {change.actor.as_deref().unwrap_or("LLDAP")}
+          </small>
+        </div>
+        <div>
+          {view_value(&change.old_value)}
+          <i class="bi-arrow-right mx-2"></i>
+          {view_value(&change.new_value)}
+        </div>
+      </li>
+    }
+}
@@ -1,4 +1,7 @@
-use crate::infra::common_component::{CommonComponent, CommonComponentParts};
+use crate::{
+    components::pending_changes::ensure_applied,
+    infra::common_component::{CommonComponent, CommonComponentParts},
+};
 use anyhow::{Error, Result};
 use graphql_client::GraphQLQuery;
 use yew::prelude::*;
@@ -39,7 +42,7 @@ impl CommonComponent<RemoveUserFromGroupComponent> for RemoveUserFromGroupCompon
         match msg {
             Msg::SubmitRemoveGroup => self.submit_remove_group(ctx),
             Msg::RemoveGroupResponse(response) => {
-                response?;
+                ensure_applied(response?.remove_user_from_group.pending_change_id)?;
                 ctx.props()
                     .on_user_removed_from_group
                     .emit((ctx.props().username.clone(), ctx.props().group_id));
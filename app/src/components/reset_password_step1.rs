@@ -1,11 +1,17 @@
 use crate::{
-    components::router::{AppRoute, Link},
+    components::{
+        login_page_help::LoginPageHelp,
+        router::{AppRoute, Link},
+    },
     infra::{
         api::HostService,
+        captcha,
         common_component::{CommonComponent, CommonComponentParts},
     },
 };
 use anyhow::{bail, Result};
+use gloo_console::error;
+use lldap_auth::captcha::{Challenge, ServerChallengeResponse};
 use validator_derive::Validate;
 use yew::prelude::*;
 use yew_form::Form;
@@ -15,6 +21,7 @@ pub struct ResetPasswordStep1Form {
     common: CommonComponentParts<Self>,
     form: Form<FormModel>,
     just_succeeded: bool,
+    captcha: Challenge,
 }
 
 /// The fields of the form, with the constraints.
@@ -27,6 +34,7 @@ pub struct FormModel {
 pub enum Msg {
     Update,
     Submit,
+    CaptchaResponse(Result<ServerChallengeResponse>),
     PasswordResetResponse(Result<()>),
 }
 
@@ -43,14 +51,23 @@ impl CommonComponent<ResetPasswordStep1Form> for ResetPasswordStep1Form {
                     bail!("Check the form for errors");
                 }
                 let FormModel { username } = self.form.model();
+                let challenge = self.captcha.clone();
                 self.common.call_backend(
                     ctx,
-                    HostService::reset_password_step1(username),
+                    async move {
+                        let captcha_response = captcha::get_response(&challenge).await?;
+                        HostService::reset_password_step1(username, captcha_response).await
+                    },
                     Msg::PasswordResetResponse,
                 );
                 Ok(true)
             }
+            Msg::CaptchaResponse(response) => {
+                self.captcha = response?.challenge;
+                Ok(true)
+            }
             Msg::PasswordResetResponse(response) => {
+                captcha::reset(&self.captcha);
                 response?;
                 self.just_succeeded = true;
                 Ok(true)
@@ -67,11 +84,14 @@ impl Component for ResetPasswordStep1Form {
     type Message = Msg;
     type Properties = ();
 
-    fn create(_: &Context<Self>) -> Self {
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.link()
+            .send_future(async { Msg::CaptchaResponse(HostService::get_captcha().await) });
         ResetPasswordStep1Form {
             common: CommonComponentParts::<Self>::create(),
             form: Form::<FormModel>::new(FormModel::default()),
             just_succeeded: false,
+            captcha: Challenge::None,
         }
     }
 
@@ -80,6 +100,12 @@ impl Component for ResetPasswordStep1Form {
         CommonComponentParts::<Self>::update(self, ctx, msg)
     }
 
+    fn rendered(&mut self, _: &Context<Self>, _: bool) {
+        if let Err(e) = captcha::load(&self.captcha) {
+            error!(&format!("Could not load the CAPTCHA: {:#}", e));
+        }
+    }
+
     fn view(&self, ctx: &Context<Self>) -> Html {
         type Field = yew_form::Field<FormModel>;
         let link = &ctx.link();
@@ -102,6 +128,7 @@ impl Component for ResetPasswordStep1Form {
                     autocomplete="username"
                     oninput={link.callback(|_| Msg::Update)} />
                 </div>
+                { captcha::view(&self.captcha) }
                 { if self.just_succeeded {
                     html! {
                       {"If a user with this username or email exists, a password reset email will \
@@ -140,6 +167,7 @@ impl Component for ResetPasswordStep1Form {
                   } else { html! {} }
                 }
                 </div>
+                <LoginPageHelp />
             </form>
         }
     }
@@ -8,6 +8,10 @@ pub enum AppRoute {
     StartResetPassword,
     #[at("/reset-password/step2/:token")]
     FinishResetPassword { token: String },
+    #[at("/delete-account/:token")]
+    ConfirmAccountDeletion { token: String },
+    #[at("/access-review/:token")]
+    AccessReview { token: String },
     #[at("/users/create")]
     CreateUser,
     #[at("/users")]
@@ -30,6 +34,10 @@ pub enum AppRoute {
     ListGroupSchema,
     #[at("/group-attributes/create")]
     CreateGroupAttribute,
+    #[at("/directory")]
+    Directory,
+    #[at("/pending-changes")]
+    ListPendingChanges,
     #[at("/")]
     Index,
 }
@@ -1,6 +1,10 @@
 use crate::{
     components::{
         add_user_to_group::AddUserToGroupComponent,
+        delete_account::RequestAccountDeletionButton,
+        export_user_data::ExportUserDataButton,
+        impersonate::ImpersonateButton,
+        profile_history::ProfileHistory,
         remove_user_from_group::RemoveUserFromGroupComponent,
         router::{AppRoute, Link},
         user_details_form::UserDetailsForm,
@@ -225,6 +229,10 @@ impl Component for UserDetails {
                         <i class="bi-key me-2"></i>
                         {"Modify password"}
                       </Link>
+                      {if ctx.props().is_admin { html! {
+                        <ImpersonateButton user_id={u.id.clone()} />
+                      } } else { html! {} } }
+                      <ExportUserDataButton username={u.id.clone()} />
                     </div>
                     <div>
                       <h5 class="row m-3 fw-bold">{"User details"}</h5>
@@ -237,6 +245,8 @@ impl Component for UserDetails {
                     />
                     {self.view_group_memberships(ctx, u)}
                     {self.view_add_group_button(ctx, u)}
+                    <ProfileHistory username={u.id.clone()} />
+                    <RequestAccountDeletionButton username={u.id.clone()} />
                     {self.view_messages(error)}
                   </>
                 }
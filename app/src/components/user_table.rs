@@ -3,7 +3,10 @@ use crate::{
         delete_user::DeleteUser,
         router::{AppRoute, Link},
     },
-    infra::common_component::{CommonComponent, CommonComponentParts},
+    infra::{
+        common_component::{CommonComponent, CommonComponentParts},
+        date_format::format_date,
+    },
 };
 use anyhow::{Error, Result};
 use graphql_client::GraphQLQuery;
@@ -131,7 +134,7 @@ impl UserTable {
               <td>{&user.display_name}</td>
               <td>{&user.first_name}</td>
               <td>{&user.last_name}</td>
-              <td>{&user.creation_date.naive_local().date()}</td>
+              <td>{format_date(&user.creation_date)}</td>
               <td>
                 <DeleteUser
                   username={user.id.clone()}
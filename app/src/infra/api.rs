@@ -1,8 +1,8 @@
-use super::cookies::set_cookie;
+use super::cookies::{delete_cookie, get_cookie, set_cookie};
 use anyhow::{anyhow, Context, Result};
 use gloo_net::http::{Method, RequestBuilder};
 use graphql_client::GraphQLQuery;
-use lldap_auth::{login, registration, JWTClaims};
+use lldap_auth::{access_review, account_deletion, captcha, csrf, login, registration, JWTClaims};
 
 use serde::{de::DeserializeOwned, Serialize};
 use web_sys::RequestCredentials;
@@ -31,10 +31,29 @@ async fn call_server<Body: Serialize>(
     url: &str,
     body: RequestType<Body>,
     error_message: &'static str,
+) -> Result<String> {
+    call_server_with_captcha(url, body, None, error_message).await
+}
+
+/// Same as `call_server`, with the response to the CAPTCHA challenge, if any.
+async fn call_server_with_captcha<Body: Serialize>(
+    url: &str,
+    body: RequestType<Body>,
+    captcha_response: Option<String>,
+    error_message: &'static str,
 ) -> Result<String> {
     let request_builder = RequestBuilder::new(url)
         .header("Content-Type", "application/json")
         .credentials(RequestCredentials::SameOrigin);
+    let request_builder = match &captcha_response {
+        Some(response) => request_builder.header(captcha::CAPTCHA_HEADER, response),
+        None => request_builder,
+    };
+    // Proves to the server that the request comes from our pages, along with the session cookie.
+    let request_builder = match get_cookie(csrf::CSRF_COOKIE)? {
+        Some(token) => request_builder.header(csrf::CSRF_HEADER, &token),
+        None => request_builder,
+    };
     let request = if let RequestType::Post(b) = body {
         request_builder
             .method(Method::POST)
@@ -79,6 +98,13 @@ async fn call_server_empty_response_with_error_message<Body: Serialize>(
 fn set_cookies_from_jwt(response: login::ServerLoginResponse) -> Result<(String, bool)> {
     let jwt_claims = get_claims_from_jwt(response.token.as_str()).context("Could not parse JWT")?;
     let is_admin = jwt_claims.groups.contains("lldap_admin");
+    match &jwt_claims.impersonation {
+        Some(impersonation) => {
+            set_cookie("impersonator", &impersonation.impersonator, &jwt_claims.exp)
+        }
+        None => delete_cookie("impersonator"),
+    }
+    .context("Error setting cookie")?;
     set_cookie("user_id", &jwt_claims.user, &jwt_claims.exp)
         .map(|_| set_cookie("is_admin", &is_admin.to_string(), &jwt_claims.exp))
         .map(|_| (jwt_claims.user.clone(), is_admin))
@@ -118,13 +144,16 @@ impl HostService {
 
     pub async fn login_start(
         request: login::ClientLoginStartRequest,
+        captcha_response: Option<String>,
     ) -> Result<Box<login::ServerLoginStartResponse>> {
-        call_server_json_with_error_message(
+        let data = call_server_with_captcha(
             &(base_url() + "/auth/opaque/login/start"),
             RequestType::Post(request),
+            captcha_response,
             "Could not start authentication: ",
         )
-        .await
+        .await?;
+        serde_json::from_str(&data).context("Could not parse response")
     }
 
     pub async fn login_finish(request: login::ClientLoginFinishRequest) -> Result<(String, bool)> {
@@ -169,6 +198,20 @@ impl HostService {
         .and_then(set_cookies_from_jwt)
     }
 
+    pub async fn impersonate(user_id: String) -> Result<(String, bool)> {
+        call_server_json_with_error_message::<login::ServerLoginResponse, _>(
+            &format!(
+                "{}/auth/impersonate/{}",
+                base_url(),
+                url_escape::encode_query(&user_id)
+            ),
+            RequestType::Post(""),
+            "Could not impersonate user: ",
+        )
+        .await
+        .and_then(set_cookies_from_jwt)
+    }
+
     // The `_request` parameter is to make it the same shape as the other functions.
     pub async fn logout() -> Result<()> {
         call_server_empty_response_with_error_message(
@@ -179,17 +222,40 @@ impl HostService {
         .await
     }
 
-    pub async fn reset_password_step1(username: String) -> Result<()> {
-        call_server_empty_response_with_error_message(
+    pub async fn reset_password_step1(
+        username: String,
+        captcha_response: Option<String>,
+    ) -> Result<()> {
+        call_server_with_captcha(
             &format!(
                 "{}/auth/reset/step1/{}",
                 base_url(),
                 url_escape::encode_query(&username)
             ),
             RequestType::Post(""),
+            captcha_response,
             "Could not initiate password reset",
         )
         .await
+        .map(|_| ())
+    }
+
+    pub async fn get_captcha() -> Result<captcha::ServerChallengeResponse> {
+        call_server_json_with_error_message(
+            &(base_url() + "/auth/captcha"),
+            GET_REQUEST,
+            "Could not get the CAPTCHA challenge",
+        )
+        .await
+    }
+
+    pub async fn get_branding() -> Result<lldap_auth::branding::LoginPage> {
+        call_server_json_with_error_message(
+            &(base_url() + "/auth/branding"),
+            GET_REQUEST,
+            "Could not get the login page links",
+        )
+        .await
     }
 
     pub async fn reset_password_step2(
@@ -203,6 +269,56 @@ impl HostService {
         .await
     }
 
+    pub async fn get_account_deletion_policy() -> Result<account_deletion::DeletionPolicy> {
+        call_server_json_with_error_message(
+            &(base_url() + "/auth/deletion/policy"),
+            GET_REQUEST,
+            "Could not get the account deletion policy",
+        )
+        .await
+    }
+
+    pub async fn request_account_deletion() -> Result<()> {
+        call_server_empty_response_with_error_message(
+            &(base_url() + "/auth/deletion/request"),
+            RequestType::Post(""),
+            "Could not request the account deletion",
+        )
+        .await
+    }
+
+    pub async fn confirm_account_deletion(
+        token: String,
+    ) -> Result<account_deletion::ServerDeletionConfirmation> {
+        call_server_json_with_error_message(
+            &format!("{}/auth/deletion/confirm/{}", base_url(), token),
+            RequestType::Post(""),
+            "Could not confirm the account deletion",
+        )
+        .await
+    }
+
+    pub async fn get_access_review(token: String) -> Result<access_review::ReviewPage> {
+        call_server_json_with_error_message(
+            &format!("{}/auth/access-review/{}", base_url(), token),
+            GET_REQUEST,
+            "Could not get the access review",
+        )
+        .await
+    }
+
+    pub async fn decide_access_review(
+        token: String,
+        decision: access_review::ReviewDecision,
+    ) -> Result<access_review::ReviewPage> {
+        call_server_json_with_error_message(
+            &format!("{}/auth/access-review/{}", base_url(), token),
+            RequestType::Post(decision),
+            "Could not record the decision",
+        )
+        .await
+    }
+
     pub async fn probe_password_reset() -> Result<bool> {
         Ok(gloo_net::http::Request::post(
             &(base_url() + "/auth/reset/step1/lldap_unlikely_very_long_user_name"),
@@ -0,0 +1,139 @@
+//! The challenges protecting the public forms against bots.
+//!
+//! The third-party widgets (hCaptcha, Turnstile) are loaded from their scripts and solved by the
+//! user, while the proof of work is solved by the browser right before submitting the form.
+
+use crate::infra::api::HostService;
+use anyhow::{anyhow, bail, Result};
+use lldap_auth::captcha::{solve_proof_of_work, Challenge};
+use wasm_bindgen::{JsCast, JsValue};
+use yew::prelude::*;
+
+const WIDGET_ID: &str = "captcha-widget";
+const SCRIPT_ID: &str = "captcha-script";
+
+/// The script, the global object and the form field of the widget of a third-party provider.
+fn widget_details(challenge: &Challenge) -> Option<(&'static str, &'static str, &'static str)> {
+    match challenge {
+        Challenge::HCaptcha { .. } => Some((
+            "https://js.hcaptcha.com/1/api.js",
+            "hcaptcha",
+            "h-captcha-response",
+        )),
+        Challenge::Turnstile { .. } => Some((
+            "https://challenges.cloudflare.com/turnstile/v0/api.js",
+            "turnstile",
+            "cf-turnstile-response",
+        )),
+        Challenge::None | Challenge::ProofOfWork { .. } => None,
+    }
+}
+
+/// The container of the widget, to include in the form.
+pub fn view(challenge: &Challenge) -> Html {
+    let (class, site_key) = match challenge {
+        Challenge::HCaptcha { site_key } => ("h-captcha", site_key),
+        Challenge::Turnstile { site_key } => ("cf-turnstile", site_key),
+        Challenge::None | Challenge::ProofOfWork { .. } => return html! {},
+    };
+    html! {
+      <div class="form-group mt-3">
+        <div id={WIDGET_ID} class={class} data-sitekey={site_key.clone()} />
+      </div>
+    }
+}
+
+fn call_widget_method(global: &str, method: &str, args: &[JsValue]) -> Result<()> {
+    let window = web_sys::window().ok_or_else(|| anyhow!("Could not get the window"))?;
+    let object = js_sys::Reflect::get(&window, &global.into())
+        .map_err(|_| anyhow!("Could not get {}", global))?;
+    if object.is_undefined() {
+        // The script is still loading, it will render the widget itself.
+        return Ok(());
+    }
+    let function = js_sys::Reflect::get(&object, &method.into())
+        .ok()
+        .and_then(|f| f.dyn_into::<js_sys::Function>().ok())
+        .ok_or_else(|| anyhow!("Could not get {}.{}", global, method))?;
+    function
+        .apply(&object, &args.iter().collect())
+        .map_err(|_| anyhow!("Error calling {}.{}", global, method))?;
+    Ok(())
+}
+
+/// Loads the script of the widget, or renders the widget if the script is already loaded. To be
+/// called once the container is in the page.
+pub fn load(challenge: &Challenge) -> Result<()> {
+    let (script, global, _) = match widget_details(challenge) {
+        Some(details) => details,
+        None => return Ok(()),
+    };
+    let document = web_sys::window()
+        .and_then(|w| w.document())
+        .ok_or_else(|| anyhow!("Could not get the document"))?;
+    match document.get_element_by_id(WIDGET_ID) {
+        Some(widget) if widget.child_element_count() == 0 => (),
+        _ => return Ok(()),
+    }
+    if document.get_element_by_id(SCRIPT_ID).is_some() {
+        let target = if global == "turnstile" {
+            format!("#{}", WIDGET_ID)
+        } else {
+            WIDGET_ID.to_owned()
+        };
+        return call_widget_method(global, "render", &[target.into()]);
+    }
+    let element = document
+        .create_element("script")
+        .map_err(|_| anyhow!("Could not create the script"))?;
+    let head = document
+        .query_selector("head")
+        .ok()
+        .flatten()
+        .ok_or_else(|| anyhow!("Could not get the page head"))?;
+    element
+        .set_attribute("id", SCRIPT_ID)
+        .and_then(|_| element.set_attribute("src", script))
+        .and_then(|_| element.set_attribute("async", ""))
+        .and_then(|_| head.append_child(&element).map(|_| ()))
+        .map_err(|_| anyhow!("Could not load the CAPTCHA script"))
+}
+
+/// Clears the widget after its response was used, since a response is only valid once.
+pub fn reset(challenge: &Challenge) {
+    if let Some((_, global, _)) = widget_details(challenge) {
+        let _ = call_widget_method(global, "reset", &[]);
+    }
+}
+
+/// The response to send along with the form: the one of the widget, or the solution of a fresh
+/// proof of work.
+pub async fn get_response(challenge: &Challenge) -> Result<Option<String>> {
+    match challenge {
+        Challenge::None => Ok(None),
+        Challenge::ProofOfWork { .. } => match HostService::get_captcha().await?.challenge {
+            Challenge::ProofOfWork {
+                challenge,
+                difficulty,
+            } => Ok(Some(solve_proof_of_work(&challenge, difficulty))),
+            _ => bail!("The CAPTCHA configuration changed, reload the page"),
+        },
+        Challenge::HCaptcha { .. } | Challenge::Turnstile { .. } => {
+            let (_, _, field) = widget_details(challenge).unwrap();
+            let response = web_sys::window()
+                .and_then(|w| w.document())
+                .and_then(|d| {
+                    d.query_selector(&format!(r#"[name="{}"]"#, field))
+                        .ok()
+                        .flatten()
+                })
+                .and_then(|e| js_sys::Reflect::get(&e, &"value".into()).ok())
+                .and_then(|v| v.as_string())
+                .filter(|v| !v.is_empty());
+            match response {
+                Some(response) => Ok(Some(response)),
+                None => bail!("Please complete the CAPTCHA"),
+            }
+        }
+    }
+}
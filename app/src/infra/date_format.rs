@@ -0,0 +1,65 @@
+use std::cell::RefCell;
+
+use chrono::{DateTime, Utc};
+use js_sys::{Date, Function, Object, Reflect};
+use wasm_bindgen::{JsCast, JsValue};
+
+#[derive(Default)]
+struct Preferences {
+    language: Option<String>,
+    timezone: Option<String>,
+}
+
+thread_local! {
+    static PREFERENCES: RefCell<Preferences> = RefCell::new(Preferences::default());
+}
+
+/// Sets the language and timezone of the logged-in user, used to display the dates.
+pub fn set_preferences(language: Option<String>, timezone: Option<String>) {
+    PREFERENCES.with(|p| {
+        *p.borrow_mut() = Preferences {
+            language: language.filter(|l| !l.is_empty()),
+            timezone: timezone.filter(|t| !t.is_empty()),
+        }
+    });
+}
+
+fn to_locale_string(date: &DateTime<Utc>, style: &[(&str, &str)]) -> Option<String> {
+    let js_date = Date::new(&JsValue::from_f64(date.timestamp_millis() as f64));
+    let options = Object::new();
+    for (key, value) in style {
+        Reflect::set(&options, &(*key).into(), &(*value).into()).ok()?;
+    }
+    let language = PREFERENCES.with(|p| {
+        let preferences = p.borrow();
+        if let Some(timezone) = &preferences.timezone {
+            Reflect::set(&options, &"timeZone".into(), &timezone.into()).ok();
+        }
+        preferences
+            .language
+            .as_deref()
+            .map(JsValue::from)
+            .unwrap_or(JsValue::UNDEFINED)
+    });
+    // Called through the function object to get the RangeError of an unknown language or
+    // timezone as an error rather than a panic.
+    Reflect::get(&js_date, &"toLocaleString".into())
+        .ok()?
+        .dyn_into::<Function>()
+        .ok()?
+        .call2(&js_date, &language, &options)
+        .ok()?
+        .as_string()
+}
+
+/// Formats the date, e.g. "16.10.2026", falling back to the browser's timezone.
+pub fn format_date(date: &DateTime<Utc>) -> String {
+    to_locale_string(date, &[("dateStyle", "medium")])
+        .unwrap_or_else(|| date.naive_local().date().to_string())
+}
+
+/// Formats the date and time, e.g. "16.10.2026, 14:30:00".
+pub fn format_date_time(date: &DateTime<Utc>) -> String {
+    to_locale_string(date, &[("dateStyle", "medium"), ("timeStyle", "medium")])
+        .unwrap_or_else(|| date.naive_local().to_string())
+}
@@ -1,6 +1,8 @@
 pub mod api;
+pub mod captcha;
 pub mod common_component;
 pub mod cookies;
+pub mod date_format;
 pub mod form_utils;
 pub mod functional;
 pub mod graphql;
@@ -35,12 +35,17 @@ pub mod login {
         /// Encrypted ServerData from the previous step.
         pub server_data: String,
         pub credential_finalization: opaque::client::login::CredentialFinalization,
+        /// Whether to keep the session for longer.
+        #[serde(default)]
+        pub remember_me: bool,
     }
 
     #[derive(Serialize, Deserialize, Clone)]
     pub struct ClientSimpleLoginRequest {
         pub username: UserId,
         pub password: String,
+        #[serde(default)]
+        pub remember_me: bool,
     }
 
     impl fmt::Debug for ClientSimpleLoginRequest {
@@ -48,6 +53,7 @@ pub mod login {
             f.debug_struct("ClientSimpleLoginRequest")
                 .field("username", &self.username.as_str())
                 .field("password", &"***********")
+                .field("remember_me", &self.remember_me)
                 .finish()
         }
     }
@@ -104,6 +110,138 @@ pub mod password_reset {
     }
 }
 
+/// The messages for the self-service account deletion.
+pub mod account_deletion {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+    pub struct DeletionPolicy {
+        pub enabled: bool,
+        #[serde(rename = "gracePeriodDays")]
+        pub grace_period_days: u32,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct ServerDeletionConfirmation {
+        #[serde(rename = "userId")]
+        pub user_id: String,
+        #[serde(rename = "deletionDate")]
+        pub deletion_date: chrono::NaiveDateTime,
+    }
+}
+
+/// What the administrators add to the login and password reset pages.
+pub mod branding {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+    pub struct LoginPage {
+        pub links: Vec<Link>,
+        /// Plain text, e.g. who to contact for help.
+        #[serde(rename = "helpText")]
+        pub help_text: Option<String>,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+    pub struct Link {
+        pub label: String,
+        pub url: String,
+    }
+}
+
+/// The page of an access review, linked from the email sent to the reviewer.
+pub mod access_review {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+    pub struct ReviewPage {
+        pub campaign: String,
+        pub group: String,
+        pub reviewer: String,
+        pub members: Vec<ReviewedMember>,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+    pub struct ReviewedMember {
+        #[serde(rename = "userId")]
+        pub user_id: String,
+        /// None until the reviewer decides.
+        pub keep: Option<bool>,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+    pub struct ReviewDecision {
+        #[serde(rename = "userId")]
+        pub user_id: String,
+        pub keep: bool,
+    }
+}
+
+/// The protection of the session cookie against cross-site request forgery.
+pub mod csrf {
+    /// The cookie giving the CSRF token of the session to the web UI.
+    pub const CSRF_COOKIE: &str = "csrf_token";
+    /// The header carrying the CSRF token, required with the session cookie.
+    pub const CSRF_HEADER: &str = "X-CSRF-Token";
+}
+
+/// The challenges protecting the public forms against bots.
+pub mod captcha {
+    use super::*;
+    use sha2::{Digest, Sha256};
+
+    /// The header carrying the solution of the challenge.
+    pub const CAPTCHA_HEADER: &str = "X-Captcha-Response";
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+    #[serde(tag = "provider", rename_all = "snake_case")]
+    pub enum Challenge {
+        /// No challenge is configured, the forms can be submitted directly.
+        None,
+        #[serde(rename = "hcaptcha")]
+        HCaptcha {
+            #[serde(rename = "siteKey")]
+            site_key: String,
+        },
+        Turnstile {
+            #[serde(rename = "siteKey")]
+            site_key: String,
+        },
+        /// Find a counter such that the SHA-256 of "{challenge}:{counter}" starts with
+        /// `difficulty` zero bits, then send "{challenge}:{counter}" back.
+        ProofOfWork { challenge: String, difficulty: u8 },
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+    pub struct ServerChallengeResponse {
+        pub challenge: Challenge,
+        /// Whether the login forms need a solved challenge too.
+        #[serde(rename = "requiredForLogin")]
+        pub required_for_login: bool,
+    }
+
+    /// The number of leading zero bits of the hash of the candidate solution.
+    pub fn proof_of_work_zero_bits(challenge: &str, counter: u64) -> u32 {
+        let hash = Sha256::digest(format!("{}:{}", challenge, counter).as_bytes());
+        let mut bits = 0;
+        for byte in hash.iter() {
+            bits += byte.leading_zeros();
+            if *byte != 0 {
+                break;
+            }
+        }
+        bits
+    }
+
+    /// Solves the proof of work, returning the solution to send back.
+    pub fn solve_proof_of_work(challenge: &str, difficulty: u8) -> String {
+        let counter = (0..)
+            .find(|counter| proof_of_work_zero_bits(challenge, *counter) >= difficulty as u32)
+            .unwrap();
+        format!("{}:{}", challenge, counter)
+    }
+}
+
 pub mod types {
     use serde::{Deserialize, Serialize};
 
@@ -211,4 +349,15 @@ pub struct JWTClaims {
     pub iat: DateTime<Utc>,
     pub user: String,
     pub groups: HashSet<String>,
+    /// Set when an admin is using the application as `user`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub impersonation: Option<ImpersonationClaims>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ImpersonationClaims {
+    /// The admin doing the impersonation.
+    pub impersonator: String,
+    /// If true, the token cannot be used to modify anything.
+    pub read_only: bool,
 }
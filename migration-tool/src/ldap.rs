@@ -186,6 +186,8 @@ impl TryFrom<ResultEntry> for User {
             .and_then(|v| v.into_iter().next().filter(|s| !s.is_empty()));
         let password =
             get_optional_attribute("userPassword").or_else(|| get_optional_attribute("password"));
+        // Keeps the link to the source directory, e.g. for later syncs.
+        let external_id = get_optional_attribute("entryUUID");
         Ok(User::new(
             crate::lldap::CreateUserInput {
                 id,
@@ -195,6 +197,7 @@ impl TryFrom<ResultEntry> for User {
                 last_name,
                 avatar: avatar.map(base64::encode),
                 attributes: None,
+                external_id,
             },
             password,
             entry.dn,
@@ -305,6 +308,7 @@ pub fn get_users(connection: &mut LdapClient) -> Result<Vec<User>, anyhow::Error
                 "displayName",
                 "name",
                 "userPassword",
+                "entryUUID",
             ],
         )?
         .success()?
@@ -0,0 +1,143 @@
+//! LDAP throughput benchmarks.
+//!
+//! They start a server (like the integration tests do), seed it with users and groups, and
+//! measure binds and searches through a real LDAP client. Run with:
+//!
+//!     cargo bench -p lldap --bench ldap
+//!
+//! The number of seeded users can be changed with `LLDAP_BENCH_USERS`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ldap3::{LdapConn, Scope};
+
+#[allow(dead_code)]
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::{
+    env,
+    fixture::{new_id, LLDAPFixture, User},
+};
+
+const CACHE_ENABLED_KEY: &str = "LLDAP_CACHE_OPTIONS__ENABLED";
+
+fn seeded_users() -> usize {
+    std::env::var("LLDAP_BENCH_USERS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(200)
+}
+
+fn seed(fixture: &mut LLDAPFixture, prefix: &str) -> Vec<User> {
+    let groups: Vec<String> = (0..10).map(|_| new_id(Some(prefix))).collect();
+    let state: Vec<User> = (0..seeded_users())
+        .map(|i| {
+            User::new(
+                &new_id(Some(prefix)),
+                vec![&groups[i % groups.len()], &groups[(i + 1) % groups.len()]],
+            )
+        })
+        .collect();
+    fixture.load_state(&state);
+    state
+}
+
+fn bound_connection() -> LdapConn {
+    let mut ldap =
+        LdapConn::new(env::ldap_url().as_str()).expect("failed to create ldap connection");
+    let bind_dn = format!("uid={},ou=people,{}", env::admin_dn(), env::base_dn());
+    ldap.simple_bind(bind_dn.as_str(), env::admin_password().as_str())
+        .expect("failed to bind to ldap")
+        .success()
+        .expect("bind failed");
+    ldap
+}
+
+fn search_filters(users: &[User]) -> Vec<(&'static str, String)> {
+    let user = &users[users.len() / 2];
+    vec![
+        ("all_users", "(objectClass=person)".to_owned()),
+        ("uid_equality", format!("(uid={})", user.username)),
+        (
+            "member_of",
+            format!(
+                "(memberOf=cn={},ou=groups,{})",
+                user.groups[0],
+                env::base_dn()
+            ),
+        ),
+        ("substring", "(uid=*bench*)".to_owned()),
+        (
+            "complex",
+            format!(
+                "(&(objectClass=person)(|(uid={})(mail=*@lldap.test))(!(uid=admin)))",
+                user.username
+            ),
+        ),
+    ]
+}
+
+fn bench_binds(c: &mut Criterion) {
+    let mut fixture = LLDAPFixture::new();
+    seed(&mut fixture, "bench-bind-");
+    let mut ldap =
+        LdapConn::new(env::ldap_url().as_str()).expect("failed to create ldap connection");
+    let bind_dn = format!("uid={},ou=people,{}", env::admin_dn(), env::base_dn());
+    let mut group = c.benchmark_group("bind");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("admin", |b| {
+        b.iter(|| {
+            ldap.simple_bind(bind_dn.as_str(), env::admin_password().as_str())
+                .unwrap()
+                .success()
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn bench_searches(c: &mut Criterion, cache_enabled: bool) {
+    std::env::set_var(CACHE_ENABLED_KEY, cache_enabled.to_string());
+    let mut fixture = LLDAPFixture::new();
+    let users = seed(&mut fixture, "bench-search-");
+    let mut ldap = bound_connection();
+    let mut group = c.benchmark_group(if cache_enabled {
+        "search_warm_cache"
+    } else {
+        "search_no_cache"
+    });
+    group.throughput(Throughput::Elements(1));
+    for (name, filter) in search_filters(&users) {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &filter, |b, filter| {
+            b.iter(|| {
+                ldap.search(
+                    env::base_dn().as_str(),
+                    Scope::Subtree,
+                    filter,
+                    vec!["uid", "mail", "memberOf"],
+                )
+                .unwrap()
+                .success()
+                .unwrap()
+            })
+        });
+    }
+    group.finish();
+    std::env::remove_var(CACHE_ENABLED_KEY);
+}
+
+fn bench_searches_no_cache(c: &mut Criterion) {
+    bench_searches(c, false)
+}
+
+fn bench_searches_warm_cache(c: &mut Criterion) {
+    bench_searches(c, true)
+}
+
+criterion_group! {
+    name = benches;
+    // Each benchmark starts a server: keep the sample count modest.
+    config = Criterion::default().sample_size(30);
+    targets = bench_binds, bench_searches_no_cache, bench_searches_warm_cache
+}
+criterion_main!(benches);
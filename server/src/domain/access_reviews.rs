@@ -0,0 +1,33 @@
+use crate::domain::types::{GroupId, GroupName, UserId, Uuid};
+
+/// The review of the members of a group, by one reviewer, as part of a campaign started by an
+/// admin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessReview {
+    pub id: i32,
+    pub campaign_id: i32,
+    pub campaign_name: String,
+    pub created_by: UserId,
+    pub creation_date: chrono::NaiveDateTime,
+    pub group_id: GroupId,
+    pub group_name: GroupName,
+    pub group_uuid: Uuid,
+    pub reviewer: UserId,
+    /// The members of the group when the campaign started.
+    pub decisions: Vec<AccessReviewDecision>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessReviewDecision {
+    pub user_id: UserId,
+    /// None until the reviewer decides. A member that is not kept is removed from the group.
+    pub keep: Option<bool>,
+    pub decision_date: Option<chrono::NaiveDateTime>,
+}
+
+/// A review of a new campaign, with the token of the page to send to the reviewer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessReviewInvitation {
+    pub review: AccessReview,
+    pub token: String,
+}
@@ -0,0 +1,11 @@
+use crate::domain::types::UserId;
+
+/// A user's request to delete their own account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountDeletion {
+    pub user_id: UserId,
+    pub request_date: chrono::NaiveDateTime,
+    /// None until the request is confirmed by email. After that, the account is disabled, and
+    /// gets deleted at that date unless an admin cancels the deletion.
+    pub deletion_date: Option<chrono::NaiveDateTime>,
+}
@@ -0,0 +1,46 @@
+use crate::domain::types::{AttributeName, GroupId, UserId};
+
+/// A value given to a (string) user attribute when the user joins the group, e.g.
+/// `homeDirectory=/home/{uid}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeTemplate {
+    pub group_id: GroupId,
+    pub attribute_name: AttributeName,
+    /// `{uid}` and `{email}` are replaced by the user's ID and email.
+    pub value: String,
+    /// Whether to replace a value that the user already has. Otherwise, the existing value
+    /// (set manually or by another group) is kept.
+    pub overwrite: bool,
+}
+
+impl AttributeTemplate {
+    pub fn render(&self, user_id: &UserId, email: &str) -> String {
+        render_template(&self.value, user_id, email)
+    }
+}
+
+/// Replaces `{uid}` and `{email}` in the template with the user's ID and email.
+pub fn render_template(template: &str, user_id: &UserId, email: &str) -> String {
+    template
+        .replace("{uid}", user_id.as_str())
+        .replace("{email}", email)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let template = AttributeTemplate {
+            group_id: GroupId(1),
+            attribute_name: "home_directory".into(),
+            value: "/home/{uid}/{uid}@{email}".to_owned(),
+            overwrite: false,
+        };
+        assert_eq!(
+            template.render(&UserId::new("Bob"), "bob@bob.bob"),
+            "/home/bob/bob@bob@bob.bob"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::domain::types::{AttributeName, GroupId, LdapObjectClass, UserId};
+
+/// A modification of the directory, emitted by the backend after the change was committed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChangeEvent {
+    UserCreated(UserId),
+    UserUpdated(UserId),
+    UserDeleted(UserId),
+    PasswordChanged(UserId),
+    GroupCreated(GroupId),
+    GroupUpdated(GroupId),
+    GroupDeleted(GroupId),
+    MembershipAdded {
+        user_id: UserId,
+        group_id: GroupId,
+    },
+    MembershipRemoved {
+        user_id: UserId,
+        group_id: GroupId,
+    },
+    /// Made by the group rule with that name.
+    MembershipAddedByRule {
+        user_id: UserId,
+        group_id: GroupId,
+        rule: String,
+    },
+    MembershipRemovedByRule {
+        user_id: UserId,
+        group_id: GroupId,
+        rule: String,
+    },
+    /// The decision of a reviewer in an access review campaign. A removal is recorded separately.
+    AccessReviewDecided {
+        review_id: i32,
+        group_id: GroupId,
+        user_id: UserId,
+        reviewer: UserId,
+        keep: bool,
+    },
+    UserAttributeSchemaChanged(AttributeName),
+    GroupAttributeSchemaChanged(AttributeName),
+    UserObjectClassesChanged(LdapObjectClass),
+    GroupObjectClassesChanged(LdapObjectClass),
+}
+
+impl ChangeEvent {
+    /// Whether the change can affect the result of a user or group search.
+    pub fn affects_searches(&self) -> bool {
+        !matches!(
+            self,
+            ChangeEvent::PasswordChanged(_) | ChangeEvent::AccessReviewDecided { .. }
+        )
+    }
+
+    /// The user that the change is about, if any.
+    pub fn user_id(&self) -> Option<&UserId> {
+        match self {
+            ChangeEvent::UserCreated(user_id)
+            | ChangeEvent::UserUpdated(user_id)
+            | ChangeEvent::UserDeleted(user_id)
+            | ChangeEvent::PasswordChanged(user_id)
+            | ChangeEvent::MembershipAdded { user_id, .. }
+            | ChangeEvent::MembershipRemoved { user_id, .. }
+            | ChangeEvent::MembershipAddedByRule { user_id, .. }
+            | ChangeEvent::MembershipRemovedByRule { user_id, .. }
+            | ChangeEvent::AccessReviewDecided { user_id, .. } => Some(user_id),
+            _ => None,
+        }
+    }
+}
+
+/// A change, as recorded in the change feed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeFeedEntry {
+    /// Increases with every change: clients resume from the last one they saw.
+    pub sequence: i32,
+    pub change_date: chrono::NaiveDateTime,
+    pub event: ChangeEvent,
+}
+
+// Subscribers that fall further behind than this get a `Lagged` error and should treat it as
+// "everything changed".
+const CHANNEL_CAPACITY: usize = 256;
+
+/// Fan-out of the change events to any interested listener.
+#[derive(Clone)]
+pub struct ChangeEventBus {
+    sender: broadcast::Sender<ChangeEvent>,
+}
+
+impl ChangeEventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self { sender }
+    }
+
+    pub fn publish(&self, event: ChangeEvent) {
+        // An error only means that nobody is listening.
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ChangeEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for ChangeEventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_publish_subscribe() {
+        let bus = ChangeEventBus::new();
+        // Publishing without subscribers is fine.
+        bus.publish(ChangeEvent::UserDeleted(UserId::new("bob")));
+        let mut receiver = bus.subscribe();
+        bus.publish(ChangeEvent::GroupDeleted(GroupId(3)));
+        assert_eq!(
+            receiver.recv().await.unwrap(),
+            ChangeEvent::GroupDeleted(GroupId(3))
+        );
+    }
+}
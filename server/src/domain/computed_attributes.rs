@@ -0,0 +1,203 @@
+use crate::domain::{
+    ldap::utils::{map_user_field, UserFieldType},
+    schema::PublicSchema,
+    types::{AttributeName, AttributeType, User, UserColumn},
+};
+use chrono::TimeZone;
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    Lowercase,
+    Uppercase,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Part<'a> {
+    Text(&'a str),
+    Field(AttributeName, Option<Function>),
+}
+
+fn parse_placeholder(placeholder: &str) -> Result<Part<'_>, String> {
+    let placeholder = placeholder.trim();
+    let (function, field) = match placeholder.split_once('(') {
+        None => (None, placeholder),
+        Some((function, argument)) => {
+            let field = argument
+                .strip_suffix(')')
+                .ok_or_else(|| format!(r#"Missing ")" in "{{{}}}""#, placeholder))?;
+            let function = match function.trim() {
+                "lowercase" => Function::Lowercase,
+                "uppercase" => Function::Uppercase,
+                f => return Err(format!(r#"Unknown function "{}""#, f)),
+            };
+            (Some(function), field.trim())
+        }
+    };
+    if field.is_empty() {
+        return Err("Empty placeholder".to_owned());
+    }
+    Ok(Part::Field(AttributeName::from(field), function))
+}
+
+/// Splits the template into text and `{field}` or `{function(field)}` placeholders.
+fn parse(template: &str) -> Result<Vec<Part<'_>>, String> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| format!(r#"Unclosed "{{" in "{}""#, template))?;
+        parts.push(Part::Text(&rest[..start]));
+        parts.push(parse_placeholder(&rest[start + 1..end])?);
+        rest = &rest[end + 1..];
+    }
+    parts.push(Part::Text(rest));
+    Ok(parts)
+}
+
+/// Checks the syntax of a template, returning the reason why it is invalid.
+pub fn check_template(template: &str) -> Result<(), String> {
+    parse(template).map(|_| ())
+}
+
+/// The fields used by the template, that have to be loaded to render it.
+pub fn template_fields(template: &str) -> Vec<AttributeName> {
+    parse(template)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|part| match part {
+            Part::Text(_) => None,
+            Part::Field(field, _) => Some(field),
+        })
+        .collect()
+}
+
+/// The value of a single-valued user field, as text.
+pub(crate) fn get_user_field(
+    user: &User,
+    field: &AttributeName,
+    schema: &PublicSchema,
+) -> Option<String> {
+    let get_attribute = |name: &AttributeName| user.attributes.iter().find(|a| &a.name == name);
+    match map_user_field(field, schema) {
+        UserFieldType::PrimaryField(UserColumn::UserId) => Some(user.user_id.to_string()),
+        UserFieldType::PrimaryField(UserColumn::Email) => Some(user.email.to_string()),
+        UserFieldType::PrimaryField(UserColumn::DisplayName) => user.display_name.clone(),
+        UserFieldType::PrimaryField(UserColumn::Uuid) => Some(user.uuid.to_string()),
+        UserFieldType::PrimaryField(UserColumn::ExternalId) => user.external_id.clone(),
+        UserFieldType::PrimaryField(UserColumn::CreationDate) => Some(
+            chrono::Utc
+                .from_utc_datetime(&user.creation_date)
+                .to_rfc3339(),
+        ),
+        UserFieldType::PrimaryField(UserColumn::ModifiedDate) => Some(
+            chrono::Utc
+                .from_utc_datetime(&user.modified_date)
+                .to_rfc3339(),
+        ),
+        UserFieldType::Attribute(name, AttributeType::String, false) => {
+            get_attribute(&name).map(|a| a.value.unwrap::<String>())
+        }
+        UserFieldType::Attribute(name, AttributeType::Integer, false) => {
+            get_attribute(&name).map(|a| a.value.unwrap::<i64>().to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Renders the template for the user. Missing fields are left empty, and `None` is returned if
+/// the result is blank.
+pub fn render_user_template(template: &str, user: &User, schema: &PublicSchema) -> Option<String> {
+    let parts = match parse(template) {
+        Ok(parts) => parts,
+        Err(e) => {
+            warn!(
+                r#"Invalid computed attribute template "{}": {}"#,
+                template, e
+            );
+            return None;
+        }
+    };
+    let rendered = parts
+        .into_iter()
+        .map(|part| match part {
+            Part::Text(text) => text.to_owned(),
+            Part::Field(field, function) => {
+                let value = get_user_field(user, &field, schema).unwrap_or_default();
+                match function {
+                    None => value,
+                    Some(Function::Lowercase) => value.to_lowercase(),
+                    Some(Function::Uppercase) => value.to_uppercase(),
+                }
+            }
+        })
+        .collect::<String>();
+    let rendered = rendered.trim();
+    (!rendered.is_empty()).then(|| rendered.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{AttributeList, Schema},
+        types::{AttributeValue, Serialized, UserId},
+    };
+
+    #[test]
+    fn test_check_template() {
+        assert_eq!(check_template("{first_name} {last_name}"), Ok(()));
+        assert_eq!(check_template("{lowercase(uid)}@example.com"), Ok(()));
+        assert!(check_template("{uid").is_err());
+        assert!(check_template("{reverse(uid)}").is_err());
+        assert!(check_template("{lowercase(uid}").is_err());
+        assert!(check_template("{}").is_err());
+    }
+
+    #[test]
+    fn test_template_fields() {
+        assert_eq!(
+            template_fields("{first_name} {lowercase(last_name)}!"),
+            vec![
+                AttributeName::from("first_name"),
+                AttributeName::from("last_name")
+            ]
+        );
+        assert_eq!(template_fields("{uid"), Vec::<AttributeName>::new());
+    }
+
+    #[test]
+    fn test_render_user_template() {
+        let schema = PublicSchema::from(Schema {
+            user_attributes: AttributeList {
+                attributes: Vec::new(),
+            },
+            group_attributes: AttributeList {
+                attributes: Vec::new(),
+            },
+            extra_user_object_classes: Vec::new(),
+            extra_group_object_classes: Vec::new(),
+        });
+        let user = User {
+            user_id: UserId::new("Bob"),
+            email: "Bob@example.com".into(),
+            display_name: None,
+            attributes: vec![AttributeValue {
+                name: "first_name".into(),
+                value: Serialized::from("Bob"),
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            render_user_template("{firstName} {lastName}", &user, &schema),
+            Some("Bob".to_owned())
+        );
+        assert_eq!(
+            render_user_template("{uppercase(uid)} <{lowercase(mail)}>", &user, &schema),
+            Some("BOB <bob@example.com>".to_owned())
+        );
+        assert_eq!(render_user_template("{displayName}", &user, &schema), None);
+    }
+}
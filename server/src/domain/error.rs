@@ -1,3 +1,4 @@
+use crate::domain::types::AttributeName;
 use thiserror::Error;
 
 #[allow(clippy::enum_variant_names)]
@@ -6,7 +7,7 @@ pub enum DomainError {
     #[error("Authentication error {0}")]
     AuthenticationError(String),
     #[error("Database error: `{0}`")]
-    DatabaseError(#[from] sea_orm::DbErr),
+    DatabaseError(sea_orm::DbErr),
     #[error("Database transaction error: `{0}`")]
     DatabaseTransactionError(#[from] sea_orm::TransactionError<sea_orm::DbErr>),
     #[error("Authentication protocol error for `{0}`")]
@@ -19,10 +20,62 @@ pub enum DomainError {
     Base64DecodeError(#[from] base64::DecodeError),
     #[error("Entity not found: `{0}`")]
     EntityNotFound(String),
+    #[error("Conflict: `{0}`")]
+    Conflict(String),
+    #[error("Permission denied: `{0}`")]
+    PermissionDenied(String),
+    #[error("Validation error: `{0}`")]
+    ValidationError(String),
+    /// A value rejected by the `attribute_validation` rule of its attribute.
+    #[error(r#"Validation error: `Invalid value for the attribute "{attribute}": {message}`"#)]
+    AttributeValidationError {
+        attribute: AttributeName,
+        /// The option of the rule that failed, e.g. `regex`.
+        rule: &'static str,
+        message: String,
+    },
+    #[error("Quota exceeded: `{0}`")]
+    QuotaExceeded(String),
     #[error("Internal error: `{0}`")]
     InternalError(String),
 }
 
+impl DomainError {
+    /// A stable identifier of the kind of error, for API clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            DomainError::AuthenticationError(_) | DomainError::AuthenticationProtocolError(_) => {
+                "UNAUTHENTICATED"
+            }
+            DomainError::EntityNotFound(_) => "NOT_FOUND",
+            DomainError::Conflict(_) => "CONFLICT",
+            DomainError::PermissionDenied(_) => "PERMISSION_DENIED",
+            DomainError::QuotaExceeded(_) => "QUOTA_EXCEEDED",
+            DomainError::ValidationError(_)
+            | DomainError::AttributeValidationError { .. }
+            | DomainError::Base64DecodeError(_)
+            | DomainError::BinarySerializationError(_) => "VALIDATION",
+            DomainError::DatabaseError(_)
+            | DomainError::DatabaseTransactionError(_)
+            | DomainError::UnknownCryptoError(_)
+            | DomainError::InternalError(_) => "BACKEND",
+        }
+    }
+}
+
+impl From<sea_orm::DbErr> for DomainError {
+    fn from(value: sea_orm::DbErr) -> Self {
+        // Constraint violations come from the caller's input rather than from the backend.
+        match value.sql_err() {
+            Some(sea_orm::SqlErr::UniqueConstraintViolation(e)) => DomainError::Conflict(e),
+            Some(sea_orm::SqlErr::ForeignKeyConstraintViolation(e)) => {
+                DomainError::EntityNotFound(e)
+            }
+            _ => DomainError::DatabaseError(value),
+        }
+    }
+}
+
 impl From<sea_orm::TransactionError<DomainError>> for DomainError {
     fn from(value: sea_orm::TransactionError<DomainError>) -> Self {
         match value {
@@ -0,0 +1,213 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    domain::{
+        computed_attributes::get_user_field,
+        ldap::utils::{map_user_field, UserFieldType},
+        schema::PublicSchema,
+        types::{AttributeName, AttributeType, GroupId, GroupName, User, UserId},
+    },
+    infra::configuration::GroupRule,
+};
+
+/// A membership added or removed to follow the group rules.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupRuleChange {
+    pub user_id: UserId,
+    pub group_id: GroupId,
+    pub group_name: GroupName,
+    /// The rule the user matches when added, the first rule of the group when removed.
+    pub rule: String,
+    pub added: bool,
+}
+
+/// All the values of the user field, as text.
+fn get_user_field_values(user: &User, field: &AttributeName, schema: &PublicSchema) -> Vec<String> {
+    match map_user_field(field, schema) {
+        UserFieldType::Attribute(name, AttributeType::String, true) => user
+            .attributes
+            .iter()
+            .find(|a| a.name == name)
+            .map(|a| a.value.unwrap::<Vec<String>>())
+            .unwrap_or_default(),
+        _ => get_user_field(user, field, schema).into_iter().collect(),
+    }
+}
+
+pub fn rule_matches(rule: &GroupRule, user: &User, schema: &PublicSchema) -> bool {
+    let expected = rule.value.to_lowercase();
+    get_user_field_values(user, &rule.attribute, schema)
+        .iter()
+        .any(|v| v.to_lowercase() == expected)
+}
+
+/// The memberships to add and remove for the user to follow the rules. The rules of groups that
+/// don't exist are ignored.
+pub fn compute_changes(
+    rules: &[GroupRule],
+    user: &User,
+    user_groups: &HashSet<GroupId>,
+    groups: &HashMap<GroupName, GroupId>,
+    schema: &PublicSchema,
+) -> Vec<GroupRuleChange> {
+    let mut changes = Vec::new();
+    let mut seen_groups = HashSet::new();
+    for first_rule in rules {
+        if !seen_groups.insert(&first_rule.group) {
+            continue;
+        }
+        let group_id = match groups.get(&first_rule.group) {
+            Some(group_id) => *group_id,
+            None => continue,
+        };
+        let matching_rule = rules
+            .iter()
+            .filter(|r| r.group == first_rule.group)
+            .find(|r| rule_matches(r, user, schema));
+        let is_member = user_groups.contains(&group_id);
+        let (rule, added) = match (matching_rule, is_member) {
+            (Some(rule), false) => (rule, true),
+            (None, true) => (first_rule, false),
+            _ => continue,
+        };
+        changes.push(GroupRuleChange {
+            user_id: user.user_id.clone(),
+            group_id,
+            group_name: first_rule.group.clone(),
+            rule: rule.name.clone(),
+            added,
+        });
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{AttributeList, AttributeSchema, Schema},
+        types::{AttributeValue, Serialized},
+    };
+    use pretty_assertions::assert_eq;
+
+    fn rule(name: &str, attribute: &str, value: &str, group: &str) -> GroupRule {
+        GroupRule {
+            name: name.to_owned(),
+            attribute: attribute.into(),
+            value: value.to_owned(),
+            group: group.into(),
+        }
+    }
+
+    fn get_schema() -> PublicSchema {
+        let attribute = |name: &str, is_list| AttributeSchema {
+            name: name.into(),
+            attribute_type: AttributeType::String,
+            is_list,
+            is_visible: true,
+            is_editable: true,
+            is_hardcoded: false,
+            is_readonly: false,
+        };
+        PublicSchema::from(Schema {
+            user_attributes: AttributeList {
+                attributes: vec![attribute("department", false), attribute("roles", true)],
+            },
+            group_attributes: AttributeList {
+                attributes: Vec::new(),
+            },
+            extra_user_object_classes: Vec::new(),
+            extra_group_object_classes: Vec::new(),
+        })
+    }
+
+    fn get_user() -> User {
+        User {
+            user_id: UserId::new("bob"),
+            email: "bob@example.com".into(),
+            attributes: vec![
+                AttributeValue {
+                    name: "department".into(),
+                    value: Serialized::from("IT"),
+                },
+                AttributeValue {
+                    name: "roles".into(),
+                    value: Serialized::from(&vec!["oncall".to_owned(), "dba".to_owned()]),
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rule_matches() {
+        let schema = get_schema();
+        let user = get_user();
+        assert!(rule_matches(
+            &rule("it", "department", "it", "it-staff"),
+            &user,
+            &schema
+        ));
+        assert!(rule_matches(
+            &rule("dba", "roles", "DBA", "dbas"),
+            &user,
+            &schema
+        ));
+        assert!(rule_matches(
+            &rule("bob", "mail", "Bob@example.com", "bobs"),
+            &user,
+            &schema
+        ));
+        assert!(!rule_matches(
+            &rule("hr", "department", "HR", "hr"),
+            &user,
+            &schema
+        ));
+        assert!(!rule_matches(
+            &rule("unknown", "office", "Leipzig", "leipzig"),
+            &user,
+            &schema
+        ));
+    }
+
+    #[test]
+    fn test_compute_changes() {
+        let rules = vec![
+            rule("it", "department", "IT", "it-staff"),
+            rule("hr", "department", "HR", "hr"),
+            rule("oncall", "roles", "oncall", "it-staff"),
+            rule("missing", "department", "IT", "does-not-exist"),
+        ];
+        let groups = HashMap::from([
+            (GroupName::from("it-staff"), GroupId(1)),
+            (GroupName::from("hr"), GroupId(2)),
+        ]);
+        let schema = get_schema();
+        let user = get_user();
+        let user_groups = HashSet::from([GroupId(2)]);
+        assert_eq!(
+            compute_changes(&rules, &user, &user_groups, &groups, &schema),
+            vec![
+                GroupRuleChange {
+                    user_id: UserId::new("bob"),
+                    group_id: GroupId(1),
+                    group_name: GroupName::from("it-staff"),
+                    rule: "it".to_owned(),
+                    added: true,
+                },
+                GroupRuleChange {
+                    user_id: UserId::new("bob"),
+                    group_id: GroupId(2),
+                    group_name: GroupName::from("hr"),
+                    rule: "hr".to_owned(),
+                    added: false,
+                },
+            ]
+        );
+        let user_groups = HashSet::from([GroupId(1)]);
+        assert_eq!(
+            compute_changes(&rules, &user, &user_groups, &groups, &schema),
+            Vec::new()
+        );
+    }
+}
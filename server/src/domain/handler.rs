@@ -1,14 +1,24 @@
 use crate::domain::{
+    access_reviews::{AccessReview, AccessReviewInvitation},
+    account_deletions::AccountDeletion,
+    attribute_templates::AttributeTemplate,
+    change_events::{ChangeEvent, ChangeFeedEntry},
     error::Result,
+    group_rules::GroupRuleChange,
+    identity_links::{ExternalIdentity, IdentityLink},
+    nested_groups::GroupHierarchy,
+    pending_changes::{PendingChange, SensitiveChange},
+    profile_history::ProfileChange,
     types::{
         AttributeName, AttributeType, AttributeValue, Email, Group, GroupDetails, GroupId,
-        GroupName, JpegPhoto, LdapObjectClass, Serialized, User, UserAndGroups, UserColumn, UserId,
-        Uuid,
+        GroupMembership, GroupName, JpegPhoto, LdapObjectClass, Serialized, User, UserAndGroups,
+        UserColumn, UserId, UserSession, Uuid,
     },
 };
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use tokio::sync::broadcast;
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
 pub struct BindRequest {
@@ -16,7 +26,7 @@ pub struct BindRequest {
     pub password: String,
 }
 
-#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+#[derive(PartialEq, Eq, Hash, Debug, Serialize, Deserialize, Clone)]
 pub struct SubStringFilter {
     pub initial: Option<String>,
     pub any: Vec<String>,
@@ -47,7 +57,7 @@ impl SubStringFilter {
     }
 }
 
-#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+#[derive(PartialEq, Eq, Hash, Debug, Serialize, Deserialize, Clone)]
 pub enum UserRequestFilter {
     And(Vec<UserRequestFilter>),
     Or(Vec<UserRequestFilter>),
@@ -62,6 +72,8 @@ pub enum UserRequestFilter {
     // Same, by id.
     MemberOfId(GroupId),
     CustomAttributePresent(AttributeName),
+    // Modified at or after that date.
+    ModifiedSince(chrono::NaiveDateTime),
 }
 
 impl From<bool> for UserRequestFilter {
@@ -74,7 +86,7 @@ impl From<bool> for UserRequestFilter {
     }
 }
 
-#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+#[derive(PartialEq, Eq, Hash, Debug, Serialize, Deserialize, Clone)]
 pub enum GroupRequestFilter {
     And(Vec<GroupRequestFilter>),
     Or(Vec<GroupRequestFilter>),
@@ -87,6 +99,8 @@ pub enum GroupRequestFilter {
     Member(UserId),
     AttributeEquality(AttributeName, Serialized),
     CustomAttributePresent(AttributeName),
+    // Modified at or after that date.
+    ModifiedSince(chrono::NaiveDateTime),
 }
 
 impl From<bool> for GroupRequestFilter {
@@ -99,6 +113,25 @@ impl From<bool> for GroupRequestFilter {
     }
 }
 
+/// Where a new user comes from, to pick the default groups it joins.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum UserCreationSource {
+    /// The web UI and the GraphQL API.
+    Graphql,
+    /// An LDAP add request.
+    Ldap,
+    /// The sync from the upstream LDAP server of the proxy.
+    LdapSync,
+    /// An event of the provisioning webhook, e.g. from an HR system.
+    ProvisioningWebhook,
+    /// A Keycloak import or a seed.
+    Import,
+    /// LLDAP itself, e.g. the admin user created on the first start.
+    #[default]
+    Internal,
+}
+
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Default)]
 pub struct CreateUserRequest {
     // Same fields as User, but no creation_date, and with password.
@@ -109,6 +142,10 @@ pub struct CreateUserRequest {
     pub last_name: Option<String>,
     pub avatar: Option<JpegPhoto>,
     pub attributes: Vec<AttributeValue>,
+    /// Immutable identifier of the user in an external system.
+    pub external_id: Option<String>,
+    #[serde(default)]
+    pub source: UserCreationSource,
 }
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Default)]
@@ -122,6 +159,10 @@ pub struct UpdateUserRequest {
     pub avatar: Option<JpegPhoto>,
     pub delete_attributes: Vec<AttributeName>,
     pub insert_attributes: Vec<AttributeValue>,
+    /// The user making the change, recorded in the profile history. `None` for the changes made by
+    /// LLDAP itself.
+    #[serde(default)]
+    pub actor: Option<UserId>,
 }
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Default)]
@@ -191,11 +232,18 @@ pub trait LoginHandler: Send + Sync {
 #[async_trait]
 pub trait GroupListerBackendHandler: ReadSchemaBackendHandler {
     async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>>;
+    /// The groups that are members of other groups. Backends that don't support nested groups
+    /// can rely on this default implementation.
+    async fn get_group_hierarchy(&self) -> Result<GroupHierarchy> {
+        Ok(GroupHierarchy::default())
+    }
 }
 
 #[async_trait]
 pub trait GroupBackendHandler: ReadSchemaBackendHandler {
     async fn get_group_details(&self, group_id: GroupId) -> Result<GroupDetails>;
+    /// The members of the group, with the date they were added.
+    async fn list_group_memberships(&self, group_id: GroupId) -> Result<Vec<GroupMembership>>;
     async fn update_group(&self, request: UpdateGroupRequest) -> Result<()>;
     async fn create_group(&self, request: CreateGroupRequest) -> Result<GroupId>;
     async fn delete_group(&self, group_id: GroupId) -> Result<()>;
@@ -208,6 +256,17 @@ pub trait UserListerBackendHandler: ReadSchemaBackendHandler {
         filters: Option<UserRequestFilter>,
         get_groups: bool,
     ) -> Result<Vec<UserAndGroups>>;
+    /// Like `list_users`, but only the listed attributes (all of them if `None`) are loaded, and
+    /// the groups are only loaded if requested. Backends that can't narrow down the query can
+    /// rely on this default implementation.
+    async fn list_users_with_attributes(
+        &self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+        _attributes: Option<Vec<AttributeName>>,
+    ) -> Result<Vec<UserAndGroups>> {
+        self.list_users(filters, get_groups).await
+    }
 }
 
 #[async_trait]
@@ -219,6 +278,8 @@ pub trait UserBackendHandler: ReadSchemaBackendHandler {
     async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
     async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
     async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>>;
+    /// The sessions that haven't expired yet.
+    async fn list_user_sessions(&self, user_id: &UserId) -> Result<Vec<UserSession>>;
 }
 
 #[async_trait]
@@ -240,6 +301,158 @@ pub trait SchemaBackendHandler: ReadSchemaBackendHandler {
     async fn delete_group_object_class(&self, name: &LdapObjectClass) -> Result<()>;
 }
 
+#[async_trait]
+pub trait ChangeFeedBackendHandler {
+    /// Returns at most `limit` changes that happened after the one with the `since` sequence
+    /// number, oldest first. See `SqlBackendHandler::record_change` for the order of the sequence
+    /// numbers.
+    async fn list_changes(&self, since: i32, limit: u64) -> Result<Vec<ChangeFeedEntry>>;
+    /// Returns all the retained changes that concern the user, oldest first.
+    async fn list_changes_for_user(&self, user_id: &UserId) -> Result<Vec<ChangeFeedEntry>>;
+    /// The sequence number of the last retained change, 0 if there is none.
+    async fn get_last_change_sequence(&self) -> Result<i32>;
+    /// Listen to the changes as they are committed, to follow the feed without polling it.
+    fn subscribe_to_changes(&self) -> broadcast::Receiver<ChangeEvent>;
+}
+
+/// The changes of the profile fields of each user, recorded by `update_user`.
+#[async_trait]
+pub trait ProfileHistoryBackendHandler {
+    /// Returns the changes of the profile of the user, oldest first.
+    async fn list_profile_changes(&self, user_id: &UserId) -> Result<Vec<ProfileChange>>;
+}
+
+/// The templates are applied when users join or leave the group, not when they are modified.
+#[async_trait]
+pub trait AttributeTemplateBackendHandler {
+    async fn list_attribute_templates(&self, group_id: GroupId) -> Result<Vec<AttributeTemplate>>;
+    /// Note: It's up to the caller to make sure that the attribute is a single string.
+    async fn set_attribute_template(&self, template: AttributeTemplate) -> Result<()>;
+    async fn delete_attribute_template(
+        &self,
+        group_id: GroupId,
+        attribute_name: &AttributeName,
+    ) -> Result<()>;
+}
+
+#[async_trait]
+pub trait PendingChangeBackendHandler {
+    async fn create_pending_change(
+        &self,
+        requested_by: &UserId,
+        change: SensitiveChange,
+    ) -> Result<i32>;
+    async fn list_pending_changes(&self) -> Result<Vec<PendingChange>>;
+    async fn get_pending_change(&self, id: i32) -> Result<PendingChange>;
+    async fn delete_pending_change(&self, id: i32) -> Result<()>;
+}
+
+#[async_trait]
+pub trait AccountDeletionBackendHandler {
+    /// Records the user's wish to delete their account, and returns the token to confirm it.
+    /// A previous unconfirmed request is replaced.
+    async fn request_account_deletion(&self, user_id: &UserId) -> Result<String>;
+    /// Disables the account until its deletion, after the grace period. The token is only valid
+    /// for a day.
+    async fn confirm_account_deletion(
+        &self,
+        token: &str,
+        grace_period: chrono::Duration,
+    ) -> Result<AccountDeletion>;
+    /// Forgets the request, and restores the account if it was already disabled.
+    async fn cancel_account_deletion(&self, user_id: &UserId) -> Result<()>;
+    async fn get_account_deletion(&self, user_id: &UserId) -> Result<Option<AccountDeletion>>;
+    async fn list_account_deletions(&self) -> Result<Vec<AccountDeletion>>;
+    /// Deletes the accounts at the end of their grace period, and returns them.
+    async fn purge_deleted_accounts(&self) -> Result<Vec<UserId>>;
+}
+
+/// Memberships managed by the `group_rules` of the configuration. The rules are also applied
+/// whenever a user is created or updated.
+#[async_trait]
+pub trait GroupRuleBackendHandler {
+    /// What applying the rules to all the users would change, without changing anything.
+    async fn preview_group_rules(&self) -> Result<Vec<GroupRuleChange>>;
+    /// Applies the rules to all the users, e.g. after adding a rule.
+    async fn apply_group_rules(&self) -> Result<Vec<GroupRuleChange>>;
+}
+
+/// Avatars fetched from Gravatar/libravatar for the users that didn't upload one.
+#[async_trait]
+pub trait AvatarSyncBackendHandler {
+    async fn get_avatar_sync_opt_out(&self, user_id: &UserId) -> Result<bool>;
+    /// Opting out also removes the avatar stored by the previous syncs, unless it was replaced.
+    async fn set_avatar_sync_opt_out(&self, user_id: &UserId, opt_out: bool) -> Result<()>;
+    /// The users that didn't opt out, and whose avatar is either missing or synced.
+    async fn list_avatar_sync_candidates(&self) -> Result<Vec<(UserId, Email)>>;
+    /// Stores the fetched avatar, or removes the synced one if there is none anymore. An avatar
+    /// uploaded in the meantime is left untouched.
+    async fn store_synced_avatar(&self, user_id: &UserId, avatar: Option<JpegPhoto>) -> Result<()>;
+}
+
+/// The accounts at external identity providers (upstream OIDC, SAML...) that log in as local users.
+#[async_trait]
+pub trait IdentityLinkBackendHandler {
+    async fn list_identity_links(&self, user_id: &UserId) -> Result<Vec<IdentityLink>>;
+    /// Fails with a conflict if the identity is linked to another user, or if the user already
+    /// has an identity at that provider.
+    async fn add_identity_link(
+        &self,
+        user_id: &UserId,
+        provider: &str,
+        subject: &str,
+    ) -> Result<IdentityLink>;
+    async fn delete_identity_link(&self, provider: &str, subject: &str) -> Result<()>;
+    /// The local account of an external login: the linked user if there is one. Otherwise, the
+    /// identity is linked to the user with the same email if the provider verified it, and it is
+    /// a conflict if it didn't. None if no user matches.
+    async fn resolve_external_identity(
+        &self,
+        identity: &ExternalIdentity,
+    ) -> Result<Option<UserId>>;
+}
+
+/// Temporary access grants: the memberships with an expiry date are removed once it is past.
+#[async_trait]
+pub trait MembershipExpiryBackendHandler {
+    /// Sets or, with None, clears the expiry date of an existing membership.
+    async fn set_membership_expiry(
+        &self,
+        user_id: &UserId,
+        group_id: GroupId,
+        expiry_date: Option<chrono::NaiveDateTime>,
+    ) -> Result<()>;
+    /// Removes the memberships past their expiry date, and returns them.
+    async fn purge_expired_memberships(&self) -> Result<Vec<(UserId, GroupName)>>;
+}
+
+/// Groups can be members of other groups: the members of the member group are then members of the
+/// parent group as well, when served over LDAP.
+#[async_trait]
+pub trait NestedGroupBackendHandler {
+    /// Fails if the parent group is already a member of the member group, directly or not.
+    async fn add_group_to_group(&self, member: GroupId, parent: GroupId) -> Result<()>;
+    async fn remove_group_from_group(&self, member: GroupId, parent: GroupId) -> Result<()>;
+}
+
+/// Campaigns asking reviewers to confirm the members of some groups. The reviewers get a token
+/// to the review page, which works without logging in.
+#[async_trait]
+pub trait AccessReviewBackendHandler {
+    /// Starts a campaign reviewing each group, with its reviewer, and snapshots their members.
+    async fn start_access_review(
+        &self,
+        name: &str,
+        created_by: &UserId,
+        groups: &[(GroupId, UserId)],
+    ) -> Result<Vec<AccessReviewInvitation>>;
+    async fn list_access_reviews(&self) -> Result<Vec<AccessReview>>;
+    async fn get_access_review(&self, token: &str) -> Result<AccessReview>;
+    /// Records the decision of the reviewer, and removes the member from the group if they are
+    /// not kept. A decision can't be changed.
+    async fn decide_access_review(&self, token: &str, user_id: &UserId, keep: bool) -> Result<()>;
+}
+
 #[async_trait]
 pub trait BackendHandler:
     Send
@@ -250,6 +463,17 @@ pub trait BackendHandler:
     + GroupListerBackendHandler
     + ReadSchemaBackendHandler
     + SchemaBackendHandler
+    + ChangeFeedBackendHandler
+    + PendingChangeBackendHandler
+    + AccountDeletionBackendHandler
+    + AttributeTemplateBackendHandler
+    + AvatarSyncBackendHandler
+    + GroupRuleBackendHandler
+    + IdentityLinkBackendHandler
+    + MembershipExpiryBackendHandler
+    + AccessReviewBackendHandler
+    + NestedGroupBackendHandler
+    + ProfileHistoryBackendHandler
 {
 }
 
@@ -0,0 +1,24 @@
+use crate::domain::types::{Email, UserId};
+
+/// An account at an external identity provider (upstream OIDC, SAML...) that logs in as the local
+/// user. A user has at most one identity per provider.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentityLink {
+    /// The name of the provider in the configuration, e.g. "google".
+    pub provider: String,
+    /// The stable identifier of the account at the provider, e.g. the `sub` claim of OIDC or the
+    /// `NameID` of SAML.
+    pub subject: String,
+    pub user_id: UserId,
+    pub creation_date: chrono::NaiveDateTime,
+}
+
+/// An external login, to map to a local account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalIdentity {
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<Email>,
+    /// Whether the provider vouches for the email, e.g. the `email_verified` claim of OIDC.
+    pub email_verified: bool,
+}
@@ -3,13 +3,14 @@ use sea_orm::{
     ConnectionTrait, DeriveIden,
 };
 
-pub use crate::domain::{sql_migrations::Users, sql_tables::DbConnection};
+pub use super::{sql_migrations::Users, sql_tables::DbConnection};
 
 /// Contains the refresh tokens for a given user.
 #[derive(DeriveIden)]
 pub enum JwtRefreshStorage {
     Table,
     RefreshTokenHash,
+    VerifierHash,
     UserId,
     ExpiryDate,
 }
@@ -29,10 +30,39 @@ pub enum JwtStorage {
 pub enum PasswordResetTokens {
     Table,
     Token,
+    VerifierHash,
     UserId,
     ExpiryDate,
 }
 
+/// Which instance currently runs a given singleton job, see `leader_election`.
+#[derive(DeriveIden)]
+pub enum LeaderLeases {
+    Table,
+    Name,
+    Holder,
+    ExpiryDate,
+}
+
+/// Counts the failed logins of a user or a client address, see `LoginThrottlingOptions`.
+#[derive(DeriveIden)]
+pub enum LoginThrottles {
+    Table,
+    Key,
+    Failures,
+    WindowStart,
+    LockedUntil,
+    ExpiryDate,
+}
+
+/// The recent deliveries of the provisioning webhook, to refuse the replays.
+#[derive(DeriveIden)]
+pub enum WebhookDeliveries {
+    Table,
+    DeliveryId,
+    ExpiryDate,
+}
+
 /// This needs to be initialized after the domain tables are.
 pub async fn init_table(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
     let builder = pool.get_database_backend();
@@ -48,6 +78,11 @@ pub async fn init_table(pool: &DbConnection) -> std::result::Result<(), sea_orm:
                         .not_null()
                         .primary_key(),
                 )
+                .col(
+                    ColumnDef::new(JwtRefreshStorage::VerifierHash)
+                        .binary()
+                        .not_null(),
+                )
                 .col(
                     ColumnDef::new(JwtRefreshStorage::UserId)
                         .string_len(255)
@@ -120,6 +155,11 @@ pub async fn init_table(pool: &DbConnection) -> std::result::Result<(), sea_orm:
                         .not_null()
                         .primary_key(),
                 )
+                .col(
+                    ColumnDef::new(PasswordResetTokens::VerifierHash)
+                        .binary()
+                        .not_null(),
+                )
                 .col(
                     ColumnDef::new(PasswordResetTokens::UserId)
                         .string_len(255)
@@ -142,5 +182,81 @@ pub async fn init_table(pool: &DbConnection) -> std::result::Result<(), sea_orm:
     )
     .await?;
 
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(LeaderLeases::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(LeaderLeases::Name)
+                        .string_len(255)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(LeaderLeases::Holder)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(LeaderLeases::ExpiryDate)
+                        .date_time()
+                        .not_null(),
+                ),
+        ),
+    )
+    .await?;
+
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(LoginThrottles::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(LoginThrottles::Key)
+                        .string_len(255)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(LoginThrottles::Failures)
+                        .integer()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(LoginThrottles::WindowStart)
+                        .date_time()
+                        .not_null(),
+                )
+                .col(ColumnDef::new(LoginThrottles::LockedUntil).date_time())
+                .col(
+                    ColumnDef::new(LoginThrottles::ExpiryDate)
+                        .date_time()
+                        .not_null(),
+                ),
+        ),
+    )
+    .await?;
+
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(WebhookDeliveries::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(WebhookDeliveries::DeliveryId)
+                        .string_len(255)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(WebhookDeliveries::ExpiryDate)
+                        .date_time()
+                        .not_null(),
+                ),
+        ),
+    )
+    .await?;
+
     Ok(())
 }
@@ -1,3 +1,4 @@
+use crate::domain::error::DomainError;
 use ldap3_proto::LdapResultCode;
 
 #[derive(Debug, PartialEq)]
@@ -15,3 +16,24 @@ impl std::fmt::Display for LdapError {
 impl std::error::Error for LdapError {}
 
 pub type LdapResult<T> = std::result::Result<T, LdapError>;
+
+/// The LDAP result code closest to a backend error.
+pub fn domain_error_code(error: &DomainError) -> LdapResultCode {
+    match error {
+        DomainError::EntityNotFound(_) => LdapResultCode::NoSuchObject,
+        DomainError::Conflict(_) => LdapResultCode::EntryAlreadyExists,
+        DomainError::PermissionDenied(_) => LdapResultCode::InsufficentAccessRights,
+        DomainError::QuotaExceeded(_) => LdapResultCode::AdminLimitExceeded,
+        DomainError::ValidationError(_)
+        | DomainError::AttributeValidationError { .. }
+        | DomainError::Base64DecodeError(_)
+        | DomainError::BinarySerializationError(_) => LdapResultCode::ConstraintViolation,
+        DomainError::AuthenticationError(_) | DomainError::AuthenticationProtocolError(_) => {
+            LdapResultCode::InvalidCredentials
+        }
+        DomainError::DatabaseError(_)
+        | DomainError::DatabaseTransactionError(_)
+        | DomainError::UnknownCryptoError(_)
+        | DomainError::InternalError(_) => LdapResultCode::Other,
+    }
+}
@@ -0,0 +1,100 @@
+use ldap3_proto::proto::LdapFilter;
+
+use crate::{
+    domain::{
+        handler::{GroupRequestFilter, UserRequestFilter},
+        ldap::{
+            group::convert_group_filter,
+            user::{convert_user_filter, resolve_user_filter_attribute},
+            utils::{map_group_field, map_user_field, GroupFieldType, LdapInfo, UserFieldType},
+        },
+        schema::PublicSchema,
+        types::AttributeName,
+    },
+    infra::ldap_metrics::format_filter,
+};
+
+/// The kinds of entries that a search looks for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchedEntries {
+    pub users: bool,
+    pub groups: bool,
+}
+
+fn leaf_attribute(filter: &LdapFilter) -> Option<&str> {
+    match filter {
+        LdapFilter::Equality(field, _)
+        | LdapFilter::Substring(field, _)
+        | LdapFilter::GreaterOrEqual(field, _)
+        | LdapFilter::Present(field) => Some(field.as_str()),
+        _ => None,
+    }
+}
+
+/// Why no entry can match the filter, which is not an and, or or not filter. `None` if some
+/// entry could match.
+fn explain_leaf(
+    ldap_info: &LdapInfo,
+    filter: &LdapFilter,
+    schema: &PublicSchema,
+    searched: SearchedEntries,
+) -> Option<String> {
+    let mut errors = Vec::new();
+    if searched.users {
+        match convert_user_filter(ldap_info, filter, schema) {
+            Ok(f) if f == UserRequestFilter::from(false) => {}
+            Ok(_) => return None,
+            Err(e) => errors.push(e.message),
+        }
+    }
+    if searched.groups {
+        match convert_group_filter(ldap_info, filter, schema) {
+            Ok(f) if f == GroupRequestFilter::from(false) => {}
+            Ok(_) => return None,
+            Err(e) => errors.push(e.message),
+        }
+    }
+    let reason = if !errors.is_empty() {
+        format!("unsupported construct: {}", errors.join("; "))
+    } else if leaf_attribute(filter).is_some_and(|field| {
+        (!searched.users
+            || matches!(
+                map_user_field(&resolve_user_filter_attribute(ldap_info, field), schema),
+                UserFieldType::NoMatch
+            ))
+            && (!searched.groups
+                || matches!(
+                    map_group_field(
+                        &ldap_info.resolve_group_attribute(&AttributeName::from(field)),
+                        schema
+                    ),
+                    GroupFieldType::NoMatch
+                ))
+    }) {
+        "unknown attribute".to_owned()
+    } else {
+        "no entry can have this value".to_owned()
+    };
+    Some(format!("{}: {}", format_filter(filter), reason))
+}
+
+/// Explains, for each part of the filter, why it cannot match any of the searched entries. The
+/// parts under a negation are not explained: a part that never matches makes its negation match
+/// everything.
+pub fn explain_filter(
+    ldap_info: &LdapInfo,
+    filter: &LdapFilter,
+    schema: &PublicSchema,
+    searched: SearchedEntries,
+) -> Vec<String> {
+    match filter {
+        LdapFilter::And(filters) | LdapFilter::Or(filters) => filters
+            .iter()
+            .flat_map(|f| explain_filter(ldap_info, f, schema, searched))
+            .collect(),
+        LdapFilter::Not(_) => Vec::new(),
+        _ => explain_leaf(ldap_info, filter, schema, searched)
+            .into_iter()
+            .collect(),
+    }
+}
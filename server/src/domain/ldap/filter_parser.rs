@@ -0,0 +1,245 @@
+use std::{iter::Peekable, str::Chars};
+
+use ldap3_proto::{proto::LdapSubstringFilter, LdapFilter, LdapResultCode};
+
+use crate::domain::ldap::error::{LdapError, LdapResult};
+
+// Deeper filters are rejected rather than risking the stack.
+const MAX_FILTER_DEPTH: usize = 64;
+
+/// Parses a filter in the string representation of RFC 4515, e.g.
+/// `(&(objectClass=person)(uid=b*))`, the inverse of `format_filter`.
+///
+/// The filters sent by the clients are already decoded by the protocol layer: this is for the
+/// filters written by hand, e.g. in the test fixtures. The extensible match and `<=` filters,
+/// which the server doesn't evaluate, are not supported.
+pub fn parse_filter(filter: &str) -> LdapResult<LdapFilter> {
+    let mut chars = filter.chars().peekable();
+    let parsed = parse_filter_at_depth(&mut chars, 0).map_err(|message| LdapError {
+        code: LdapResultCode::ProtocolError,
+        message: format!(r#"Invalid filter "{}": {}"#, filter, message),
+    })?;
+    match chars.next() {
+        None => Ok(parsed),
+        Some(c) => Err(LdapError {
+            code: LdapResultCode::ProtocolError,
+            message: format!(
+                r#"Invalid filter "{}": unexpected "{}" at the end"#,
+                filter, c
+            ),
+        }),
+    }
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> Result<(), String> {
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        Some(c) => Err(format!(r#"expected "{}", got "{}""#, expected, c)),
+        None => Err(format!(r#"expected "{}", got the end"#, expected)),
+    }
+}
+
+fn parse_filter_at_depth(chars: &mut Peekable<Chars>, depth: usize) -> Result<LdapFilter, String> {
+    if depth > MAX_FILTER_DEPTH {
+        return Err("too deeply nested".to_string());
+    }
+    expect(chars, '(')?;
+    let filter = match chars.peek() {
+        Some('&') | Some('|') => {
+            let is_and = chars.next() == Some('&');
+            let mut filters = Vec::new();
+            while chars.peek() == Some(&'(') {
+                filters.push(parse_filter_at_depth(chars, depth + 1)?);
+            }
+            if is_and {
+                LdapFilter::And(filters)
+            } else {
+                LdapFilter::Or(filters)
+            }
+        }
+        Some('!') => {
+            chars.next();
+            LdapFilter::Not(Box::new(parse_filter_at_depth(chars, depth + 1)?))
+        }
+        _ => parse_item(chars)?,
+    };
+    expect(chars, ')')?;
+    Ok(filter)
+}
+
+fn parse_item(chars: &mut Peekable<Chars>) -> Result<LdapFilter, String> {
+    let attribute: String =
+        std::iter::from_fn(|| chars.next_if(|c| !"=~<>()".contains(*c))).collect();
+    if attribute.is_empty() {
+        return Err("missing attribute".to_string());
+    }
+    if attribute.contains(':') {
+        return Err("extensible match filters are not supported".to_string());
+    }
+    if !attribute
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.' || c == ';')
+    {
+        return Err(format!(r#"invalid attribute "{}""#, attribute));
+    }
+    match chars.next() {
+        Some('=') => {}
+        Some('~') => {
+            expect(chars, '=')?;
+            return Ok(LdapFilter::Approx(attribute, parse_value(chars)?));
+        }
+        Some('>') => {
+            expect(chars, '=')?;
+            return Ok(LdapFilter::GreaterOrEqual(attribute, parse_value(chars)?));
+        }
+        Some('<') => return Err("<= filters are not supported".to_string()),
+        _ => return Err(format!(r#"missing operator after "{}""#, attribute)),
+    }
+    // The parts of the value between the unescaped stars.
+    let mut pieces = vec![parse_value_until_star(chars)?];
+    while chars.next_if_eq(&'*').is_some() {
+        pieces.push(parse_value_until_star(chars)?);
+    }
+    if pieces.len() == 1 {
+        return Ok(LdapFilter::Equality(attribute, pieces.remove(0)));
+    }
+    let final_ = pieces.pop().filter(|s| !s.is_empty());
+    let initial = Some(pieces.remove(0)).filter(|s| !s.is_empty());
+    let any: Vec<String> = pieces.into_iter().filter(|s| !s.is_empty()).collect();
+    if initial.is_none() && any.is_empty() && final_.is_none() {
+        return Ok(LdapFilter::Present(attribute));
+    }
+    Ok(LdapFilter::Substring(
+        attribute,
+        LdapSubstringFilter {
+            initial,
+            any,
+            final_,
+        },
+    ))
+}
+
+/// A value where the stars are taken literally.
+fn parse_value(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    let mut value = String::new();
+    loop {
+        value.push_str(&parse_value_until_star(chars)?);
+        match chars.next_if_eq(&'*') {
+            Some(star) => value.push(star),
+            None => return Ok(value),
+        }
+    }
+}
+
+/// Parses a value up to the closing parenthesis or the next unescaped star, decoding the `\XX`
+/// escapes.
+fn parse_value_until_star(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    let mut value = Vec::new();
+    while let Some(c) = chars.next_if(|&c| c != ')' && c != '*') {
+        match c {
+            '(' => return Err(r#"unescaped "(" in a value"#.to_string()),
+            '\\' => {
+                let mut hex_digit = || {
+                    chars
+                        .next()
+                        .and_then(|c| c.to_digit(16))
+                        .ok_or_else(|| "invalid escape sequence".to_string())
+                };
+                let high = hex_digit()?;
+                let low = hex_digit()?;
+                value.push((high * 16 + low) as u8);
+            }
+            c => value.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    String::from_utf8(value).map_err(|_| "invalid UTF-8 in a value".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::ldap_metrics::format_filter;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_filter() {
+        assert_eq!(
+            parse_filter("(&(objectClass=person)(!(mail=*))(|(cn=b*o*)(uid>=m))(cn~=Bob))"),
+            Ok(LdapFilter::And(vec![
+                LdapFilter::Equality("objectClass".to_owned(), "person".to_owned()),
+                LdapFilter::Not(Box::new(LdapFilter::Present("mail".to_owned()))),
+                LdapFilter::Or(vec![
+                    LdapFilter::Substring(
+                        "cn".to_owned(),
+                        LdapSubstringFilter {
+                            initial: Some("b".to_owned()),
+                            any: vec!["o".to_owned()],
+                            final_: None,
+                        },
+                    ),
+                    LdapFilter::GreaterOrEqual("uid".to_owned(), "m".to_owned()),
+                ]),
+                LdapFilter::Approx("cn".to_owned(), "Bob".to_owned()),
+            ]))
+        );
+        assert_eq!(parse_filter("(&)"), Ok(LdapFilter::And(vec![])));
+        assert_eq!(
+            parse_filter(r"(cn=Best Group \28old\29 \2a)"),
+            Ok(LdapFilter::Equality(
+                "cn".to_owned(),
+                "Best Group (old) *".to_owned()
+            ))
+        );
+        assert_eq!(
+            parse_filter("(cn=*a**b)"),
+            Ok(LdapFilter::Substring(
+                "cn".to_owned(),
+                LdapSubstringFilter {
+                    initial: None,
+                    any: vec!["a".to_owned()],
+                    final_: Some("b".to_owned()),
+                },
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_filter() {
+        for filter in [
+            "",
+            "uid=bob",
+            "(uid=bob",
+            "(uid=bob))",
+            "(=bob)",
+            "(uid~bob)",
+            "(uid<=bob)",
+            "(uid:dn:=bob)",
+            "(u id=bob)",
+            "(uid=b(ob)",
+            r"(uid=\2)",
+            r"(uid=\ff)",
+            "(!(uid=bob)(uid=john))",
+        ] {
+            assert_eq!(
+                parse_filter(filter).map_err(|e| e.code),
+                Err(LdapResultCode::ProtocolError),
+                "{}",
+                filter
+            );
+        }
+        let nested = format!("{}(uid=bob){}", "(!".repeat(100), ")".repeat(100));
+        assert!(parse_filter(&nested).is_err());
+    }
+
+    #[test]
+    fn test_parse_format_round_trip() {
+        for filter in [
+            "(&(objectClass=person)(|(uid=b*o*)(mail=*))(!(cn=\\2a\\28\\29\\5c)))",
+            "(modifyTimestamp>=20240101000000Z)",
+            "(cn=*b)",
+        ] {
+            let parsed = parse_filter(filter).unwrap();
+            assert_eq!(format_filter(&parsed), filter);
+        }
+    }
+}
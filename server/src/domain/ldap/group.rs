@@ -1,3 +1,5 @@
+use std::{collections::HashMap, sync::Arc};
+
 use chrono::TimeZone;
 use ldap3_proto::{
     proto::LdapOp, LdapFilter, LdapPartialAttribute, LdapResultCode, LdapSearchResultEntry,
@@ -8,24 +10,25 @@ use crate::domain::{
     deserialize::deserialize_attribute_value,
     handler::{GroupListerBackendHandler, GroupRequestFilter},
     ldap::{
-        error::{LdapError, LdapResult},
+        error::{domain_error_code, LdapError, LdapResult},
         utils::{
             expand_attribute_wildcards, get_custom_attribute,
             get_group_id_from_distinguished_name_or_plain_name,
-            get_user_id_from_distinguished_name_or_plain_name, map_group_field, ExpandedAttributes,
-            GroupFieldType, LdapInfo,
+            get_user_id_from_distinguished_name_or_plain_name, map_group_field,
+            parse_ldap_timestamp, ExpandedAttributes, GroupFieldType, LdapInfo,
         },
     },
+    nested_groups::GroupHierarchy,
     schema::{PublicSchema, SchemaGroupAttributeExtractor},
-    types::{AttributeName, AttributeType, Group, LdapObjectClass, UserId, Uuid},
+    types::{AttributeName, AttributeType, Email, Group, LdapObjectClass, UserId, Uuid},
 };
 
 pub fn get_group_attribute(
     group: &Group,
-    base_dn_str: &str,
+    ldap_info: &LdapInfo,
     attribute: &AttributeName,
     user_filter: &Option<UserId>,
-    ignored_group_attributes: &[AttributeName],
+    member_emails: &HashMap<UserId, Email>,
     schema: &PublicSchema,
 ) -> Option<Vec<Vec<u8>>> {
     let attribute_values = match map_group_field(attribute, schema) {
@@ -38,40 +41,56 @@ pub fn get_group_attribute(
                     .iter()
                     .map(|c| c.as_str().as_bytes().to_vec()),
             );
+            classes.extend(
+                ldap_info
+                    .profile
+                    .group_object_classes
+                    .iter()
+                    .map(|c| c.as_bytes().to_vec()),
+            );
             classes
         }
         // Always returned as part of the base response.
         GroupFieldType::Dn => return None,
         GroupFieldType::EntryDn => {
-            vec![format!("uid={},ou=groups,{}", group.display_name, base_dn_str).into_bytes()]
+            vec![format!(
+                "uid={},ou=groups,{}",
+                group.display_name, ldap_info.base_dn_str
+            )
+            .into_bytes()]
         }
         GroupFieldType::DisplayName => vec![group.display_name.to_string().into_bytes()],
         GroupFieldType::CreationDate => vec![chrono::Utc
             .from_utc_datetime(&group.creation_date)
             .to_rfc3339()
             .into_bytes()],
+        GroupFieldType::ModifiedDate => vec![chrono::Utc
+            .from_utc_datetime(&group.modified_date)
+            .to_rfc3339()
+            .into_bytes()],
         GroupFieldType::Member => group
             .users
             .iter()
             .filter(|u| user_filter.as_ref().map(|f| *u == f).unwrap_or(true))
-            .map(|u| format!("uid={},ou=people,{}", u, base_dn_str).into_bytes())
+            .map(|u| ldap_info.user_dn(u).into_bytes())
             .collect(),
         GroupFieldType::Uuid => vec![group.uuid.to_string().into_bytes()],
         GroupFieldType::Attribute(attr, _, _) => {
             get_custom_attribute::<SchemaGroupAttributeExtractor>(&group.attributes, &attr, schema)?
         }
         GroupFieldType::NoMatch => match attribute.as_str() {
-            "1.1" => return None,
-            // We ignore the operational attribute wildcard
-            "+" => return None,
-            "*" => {
-                panic!(
-                    "Matched {}, * should have been expanded into attribute list and * removed",
-                    attribute
-                )
-            }
+            // The emails of the members, to use the group as a distribution list.
+            "mail" => group
+                .users
+                .iter()
+                .filter(|u| user_filter.as_ref().map(|f| *u == f).unwrap_or(true))
+                .filter_map(|u| member_emails.get(u))
+                .map(|e| e.as_str().as_bytes().to_vec())
+                .collect(),
+            // The selectors are expanded into the attribute list by expand_attribute_wildcards.
+            "1.1" | "+" | "*" => return None,
             _ => {
-                if ignored_group_attributes.contains(attribute) {
+                if ldap_info.ignored_group_attributes.contains(attribute) {
                     return None;
                 }
                 get_custom_attribute::<SchemaGroupAttributeExtractor>(
@@ -99,49 +118,66 @@ const ALL_GROUP_ATTRIBUTE_KEYS: &[&str] = &[
     "cn",
     "member",
     "uniquemember",
+    "createtimestamp",
+    "modifytimestamp",
     "entryuuid",
 ];
 
+/// Returned with "+".
+const OPERATIONAL_GROUP_ATTRIBUTE_KEYS: &[&str] =
+    &["createtimestamp", "modifytimestamp", "entrydn", "entryuuid"];
+
 fn expand_group_attribute_wildcards(attributes: &[String]) -> ExpandedAttributes {
-    expand_attribute_wildcards(attributes, ALL_GROUP_ATTRIBUTE_KEYS)
+    expand_attribute_wildcards(
+        attributes,
+        ALL_GROUP_ATTRIBUTE_KEYS,
+        OPERATIONAL_GROUP_ATTRIBUTE_KEYS,
+    )
 }
 
 fn make_ldap_search_group_result_entry(
     group: Group,
-    base_dn_str: &str,
+    ldap_info: &LdapInfo,
     mut expanded_attributes: ExpandedAttributes,
     user_filter: &Option<UserId>,
-    ignored_group_attributes: &[AttributeName],
+    member_emails: &HashMap<UserId, Email>,
     schema: &PublicSchema,
 ) -> LdapSearchResultEntry {
     if expanded_attributes.include_custom_attributes {
-        expanded_attributes.attribute_keys.extend(
-            group
-                .attributes
-                .iter()
-                .map(|a| (a.name.clone(), a.name.to_string())),
-        );
+        for attribute in &group.attributes {
+            expanded_attributes
+                .attribute_keys
+                .entry(attribute.name.clone())
+                .or_insert_with(|| attribute.name.to_string());
+        }
+        ldap_info.add_group_attribute_mappings(&mut expanded_attributes.attribute_keys);
     }
-    LdapSearchResultEntry {
-        dn: format!("cn={},ou=groups,{}", group.display_name, base_dn_str),
-        attributes: expanded_attributes
-            .attribute_keys
-            .into_iter()
-            .filter_map(|(attribute, name)| {
-                let values = get_group_attribute(
-                    &group,
-                    base_dn_str,
-                    &attribute,
-                    user_filter,
-                    ignored_group_attributes,
-                    schema,
-                )?;
-                Some(LdapPartialAttribute {
-                    atype: name,
-                    vals: values,
-                })
+    let mut attributes = expanded_attributes
+        .attribute_keys
+        .into_iter()
+        .filter(|(attribute, _)| !ldap_info.is_renamed_group_attribute(attribute))
+        .filter_map(|(attribute, name)| {
+            let values = get_group_attribute(
+                &group,
+                ldap_info,
+                &ldap_info.resolve_group_attribute(&attribute),
+                user_filter,
+                member_emails,
+                schema,
+            )?;
+            Some(LdapPartialAttribute {
+                atype: name,
+                vals: values,
             })
-            .collect::<Vec<LdapPartialAttribute>>(),
+        })
+        .collect::<Vec<LdapPartialAttribute>>();
+    ldap_info.sort_entry_attributes(&expanded_attributes.requested_order, &mut attributes);
+    LdapSearchResultEntry {
+        dn: format!(
+            "cn={},ou=groups,{}",
+            group.display_name, ldap_info.base_dn_str
+        ),
+        attributes,
     }
 }
 
@@ -159,7 +195,7 @@ fn get_group_attribute_equality_filter(
         })
 }
 
-fn convert_group_filter(
+pub fn convert_group_filter(
     ldap_info: &LdapInfo,
     filter: &LdapFilter,
     schema: &PublicSchema,
@@ -167,7 +203,7 @@ fn convert_group_filter(
     let rec = |f| convert_group_filter(ldap_info, f, schema);
     match filter {
         LdapFilter::Equality(field, value) => {
-            let field = AttributeName::from(field.as_str());
+            let field = ldap_info.resolve_group_attribute(&AttributeName::from(field.as_str()));
             let value = value.to_ascii_lowercase();
             match map_group_field(&field, schema) {
                 GroupFieldType::DisplayName => Ok(GroupRequestFilter::DisplayName(value.into())),
@@ -189,6 +225,11 @@ fn convert_group_filter(
                 })),
                 GroupFieldType::ObjectClass => Ok(GroupRequestFilter::from(
                     matches!(value.as_str(), "groupofuniquenames" | "groupofnames")
+                        || ldap_info
+                            .profile
+                            .group_object_classes
+                            .iter()
+                            .any(|c| c.eq_ignore_ascii_case(&value))
                         || schema
                             .get_schema()
                             .extra_group_object_classes
@@ -223,6 +264,25 @@ fn convert_group_filter(
                     code: LdapResultCode::UnwillingToPerform,
                     message: "Creation date filter for groups not supported".to_owned(),
                 }),
+                GroupFieldType::ModifiedDate => Err(LdapError {
+                    code: LdapResultCode::UnwillingToPerform,
+                    message: "Only >= filters are supported on modifyTimestamp".to_owned(),
+                }),
+            }
+        }
+        LdapFilter::GreaterOrEqual(field, value) => {
+            let field = ldap_info.resolve_group_attribute(&AttributeName::from(field.as_str()));
+            match map_group_field(&field, schema) {
+                GroupFieldType::ModifiedDate => parse_ldap_timestamp(value)
+                    .map(GroupRequestFilter::ModifiedSince)
+                    .ok_or_else(|| LdapError {
+                        code: LdapResultCode::InvalidAttributeSyntax,
+                        message: format!("Invalid timestamp: {}", value),
+                    }),
+                _ => Err(LdapError {
+                    code: LdapResultCode::UnwillingToPerform,
+                    message: format!("Unsupported group attribute for >= filter: \"{}\"", field),
+                }),
             }
         }
         LdapFilter::And(filters) => Ok(GroupRequestFilter::And(
@@ -233,7 +293,7 @@ fn convert_group_filter(
         )),
         LdapFilter::Not(filter) => Ok(GroupRequestFilter::Not(Box::new(rec(filter)?))),
         LdapFilter::Present(field) => {
-            let field = AttributeName::from(field.as_str());
+            let field = ldap_info.resolve_group_attribute(&AttributeName::from(field.as_str()));
             Ok(match map_group_field(&field, schema) {
                 GroupFieldType::Attribute(name, _, _) => {
                     GroupRequestFilter::CustomAttributePresent(name)
@@ -243,7 +303,7 @@ fn convert_group_filter(
             })
         }
         LdapFilter::Substring(field, substring_filter) => {
-            let field = AttributeName::from(field.as_str());
+            let field = ldap_info.resolve_group_attribute(&AttributeName::from(field.as_str()));
             match map_group_field(&field, schema) {
                 GroupFieldType::DisplayName => Ok(GroupRequestFilter::DisplayNameSubString(
                     substring_filter.clone().into(),
@@ -272,25 +332,31 @@ pub async fn get_groups_list<Backend: GroupListerBackendHandler>(
     base: &str,
     backend: &Backend,
     schema: &PublicSchema,
+    hierarchy: &GroupHierarchy,
 ) -> LdapResult<Vec<Group>> {
-    let filters = convert_group_filter(ldap_info, ldap_filter, schema)?;
+    let filters =
+        hierarchy.expand_group_filter(convert_group_filter(ldap_info, ldap_filter, schema)?);
     debug!(?filters);
-    backend
+    let mut groups = backend
         .list_groups(Some(filters))
         .await
         .map_err(|e| LdapError {
-            code: LdapResultCode::Other,
+            code: domain_error_code(&e),
             message: format!(r#"Error while listing groups "{}": {:#}"#, base, e),
-        })
+        })?;
+    hierarchy.add_nested_members(&mut groups);
+    Ok(groups)
 }
 
-pub fn convert_groups_to_ldap_op<'a>(
+/// Like the users, the entries are only built as the iterator is consumed.
+pub fn convert_groups_to_ldap_op(
     groups: Vec<Group>,
-    attributes: &'a [String],
-    ldap_info: &'a LdapInfo,
-    user_filter: &'a Option<UserId>,
-    schema: &'a PublicSchema,
-) -> impl Iterator<Item = LdapOp> + 'a {
+    attributes: &[String],
+    ldap_info: Arc<LdapInfo>,
+    user_filter: Option<UserId>,
+    member_emails: HashMap<UserId, Email>,
+    schema: Arc<PublicSchema>,
+) -> impl Iterator<Item = LdapOp> + Send + 'static {
     let expanded_attributes = if groups.is_empty() {
         None
     } else {
@@ -300,11 +366,11 @@ pub fn convert_groups_to_ldap_op<'a>(
     groups.into_iter().map(move |g| {
         LdapOp::SearchResultEntry(make_ldap_search_group_result_entry(
             g,
-            &ldap_info.base_dn_str,
+            &ldap_info,
             expanded_attributes.clone().unwrap(),
-            user_filter,
-            &ldap_info.ignored_group_attributes,
-            schema,
+            &user_filter,
+            &member_emails,
+            &schema,
         ))
     })
 }
@@ -1,4 +1,8 @@
 pub mod error;
+pub mod filter_debug;
+pub mod filter_parser;
 pub mod group;
+pub mod static_entry;
+pub mod subschema;
 pub mod user;
 pub mod utils;
@@ -0,0 +1,378 @@
+use ldap3_proto::proto::{
+    LdapFilter, LdapOp, LdapPartialAttribute, LdapSearchRequest, LdapSearchResultEntry,
+    LdapSearchScope, LdapSubstringFilter,
+};
+
+use crate::{
+    domain::ldap::{
+        error::{LdapError, LdapResult},
+        utils::{is_subtree, parse_distinguished_name},
+    },
+    infra::configuration::StaticLdapEntry,
+};
+
+/// An entry of the configuration, served as is alongside the users and groups.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StaticEntry {
+    dn: String,
+    dn_parts: Vec<(String, String)>,
+    attributes: Vec<(String, Vec<String>)>,
+}
+
+impl StaticEntry {
+    /// Checks that the entry is under the base DN, and outside of the user and group subtrees.
+    pub fn new(entry: &StaticLdapEntry, base_dn: &[(String, String)]) -> LdapResult<Self> {
+        let dn_parts = parse_distinguished_name(&entry.dn)?;
+        let invalid = |message: &str| {
+            Err(LdapError {
+                code: ldap3_proto::LdapResultCode::InvalidDNSyntax,
+                message: format!(r#"Invalid static entry "{}": {}"#, entry.dn, message),
+            })
+        };
+        if !is_subtree(&dn_parts, base_dn) {
+            return invalid("it is not under the base DN");
+        }
+        if dn_parts.len() > base_dn.len() {
+            let top = &dn_parts[dn_parts.len() - base_dn.len() - 1];
+            if top.0 == "ou" && (top.1 == "people" || top.1 == "groups") {
+                return invalid("the users and groups subtrees are managed by LLDAP");
+            }
+        }
+        Ok(Self {
+            dn: entry.dn.clone(),
+            dn_parts,
+            attributes: entry
+                .attributes
+                .iter()
+                .map(|(name, values)| (name.clone(), values.clone()))
+                .collect(),
+        })
+    }
+
+    /// An entry of the tree itself, above the users and groups.
+    fn builtin(dn: String, dn_parts: Vec<(String, String)>, object_classes: &[&str]) -> Self {
+        let (rdn_attribute, rdn_value) = dn_parts[0].clone();
+        Self {
+            dn,
+            dn_parts,
+            attributes: vec![
+                (
+                    "objectClass".to_owned(),
+                    object_classes.iter().map(|c| c.to_string()).collect(),
+                ),
+                (rdn_attribute, vec![rdn_value]),
+            ],
+        }
+    }
+
+    pub fn dn_parts(&self) -> &[(String, String)] {
+        &self.dn_parts
+    }
+
+    pub fn is_under(&self, dn_parts: &[(String, String)]) -> bool {
+        is_subtree(&self.dn_parts, dn_parts)
+    }
+
+    fn values(&self, attribute: &str) -> Option<&[String]> {
+        self.attributes
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(attribute))
+            .map(|(_, values)| values.as_slice())
+    }
+
+    fn is_in_scope(&self, base: &[(String, String)], scope: &LdapSearchScope) -> bool {
+        match scope {
+            LdapSearchScope::Base => self.dn_parts == base,
+            LdapSearchScope::OneLevel => {
+                self.dn_parts.len() == base.len() + 1 && is_subtree(&self.dn_parts, base)
+            }
+            _ => is_subtree(&self.dn_parts, base),
+        }
+    }
+
+    fn matches(&self, filter: &LdapFilter) -> bool {
+        match filter {
+            LdapFilter::And(filters) => filters.iter().all(|f| self.matches(f)),
+            LdapFilter::Or(filters) => filters.iter().any(|f| self.matches(f)),
+            LdapFilter::Not(filter) => !self.matches(filter),
+            LdapFilter::Present(attribute) => self.values(attribute).is_some(),
+            LdapFilter::Equality(attribute, value) => self
+                .values(attribute)
+                .unwrap_or_default()
+                .iter()
+                .any(|v| v.eq_ignore_ascii_case(value)),
+            LdapFilter::Substring(attribute, substring) => self
+                .values(attribute)
+                .unwrap_or_default()
+                .iter()
+                .any(|v| matches_substring(&v.to_ascii_lowercase(), substring)),
+            _ => false,
+        }
+    }
+
+    fn to_ldap_op(&self, attributes: &[String]) -> LdapOp {
+        let all = attributes.is_empty() || attributes.iter().any(|a| a == "*");
+        LdapOp::SearchResultEntry(LdapSearchResultEntry {
+            dn: self.dn.clone(),
+            attributes: self
+                .attributes
+                .iter()
+                .filter(|(name, _)| all || attributes.iter().any(|a| a.eq_ignore_ascii_case(name)))
+                .map(|(name, values)| LdapPartialAttribute {
+                    atype: name.clone(),
+                    vals: values.iter().map(|v| v.as_bytes().to_vec()).collect(),
+                })
+                .collect(),
+        })
+    }
+}
+
+fn matches_substring(value: &str, filter: &LdapSubstringFilter) -> bool {
+    let mut rest = value;
+    if let Some(initial) = &filter.initial {
+        match rest.strip_prefix(initial.to_ascii_lowercase().as_str()) {
+            Some(r) => rest = r,
+            None => return false,
+        }
+    }
+    for any in &filter.any {
+        let any = any.to_ascii_lowercase();
+        match rest.find(any.as_str()) {
+            Some(index) => rest = &rest[index + any.len()..],
+            None => return false,
+        }
+    }
+    match &filter.final_ {
+        Some(final_) => rest.ends_with(final_.to_ascii_lowercase().as_str()),
+        None => true,
+    }
+}
+
+/// The base DN entry, and the organizational units holding the users and the groups.
+pub fn get_builtin_entries(base_dn_str: &str, base_dn: &[(String, String)]) -> Vec<StaticEntry> {
+    let base_object_class = match base_dn.first().map(|(attribute, _)| attribute.as_str()) {
+        Some("dc") => "domain",
+        Some("o") => "organization",
+        _ => "organizationalUnit",
+    };
+    let mut entries = vec![StaticEntry::builtin(
+        base_dn_str.to_owned(),
+        base_dn.to_vec(),
+        &["top", base_object_class],
+    )];
+    for ou in ["people", "groups"] {
+        let mut dn_parts = vec![("ou".to_owned(), ou.to_owned())];
+        dn_parts.extend_from_slice(base_dn);
+        entries.push(StaticEntry::builtin(
+            format!("ou={},{}", ou, base_dn_str),
+            dn_parts,
+            &["top", "organizationalUnit"],
+        ));
+    }
+    entries
+}
+
+/// The static entries in the scope of the request that match its filter.
+pub fn get_static_entries(
+    entries: &[StaticEntry],
+    base: &[(String, String)],
+    request: &LdapSearchRequest,
+) -> Vec<LdapOp> {
+    entries
+        .iter()
+        .filter(|e| e.is_in_scope(base, &request.scope) && e.matches(&request.filter))
+        .map(|e| e.to_ldap_op(&request.attrs))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ldap3_proto::proto::LdapDerefAliases;
+    use pretty_assertions::assert_eq;
+
+    fn base_dn() -> Vec<(String, String)> {
+        parse_distinguished_name("dc=example,dc=com").unwrap()
+    }
+
+    fn make_entry(dn: &str, attributes: &[(&str, &[&str])]) -> LdapResult<StaticEntry> {
+        StaticEntry::new(
+            &StaticLdapEntry {
+                dn: dn.to_owned(),
+                attributes: attributes
+                    .iter()
+                    .map(|(name, values)| {
+                        (
+                            name.to_string(),
+                            values.iter().map(|v| v.to_string()).collect(),
+                        )
+                    })
+                    .collect(),
+            },
+            &base_dn(),
+        )
+    }
+
+    fn get_dns(
+        entries: &[StaticEntry],
+        base: &str,
+        scope: LdapSearchScope,
+        filter: LdapFilter,
+    ) -> Vec<String> {
+        let request = LdapSearchRequest {
+            base: base.to_owned(),
+            scope,
+            aliases: LdapDerefAliases::Never,
+            sizelimit: 0,
+            timelimit: 0,
+            typesonly: false,
+            filter,
+            attrs: vec![],
+        };
+        get_static_entries(entries, &parse_distinguished_name(base).unwrap(), &request)
+            .into_iter()
+            .map(|op| match op {
+                LdapOp::SearchResultEntry(e) => e.dn,
+                _ => panic!("Unexpected op {:?}", op),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_new_static_entry() {
+        assert!(make_entry("ou=policies,dc=example,dc=com", &[]).is_ok());
+        assert!(make_entry("ou=policies,dc=other,dc=com", &[]).is_err());
+        assert!(make_entry("cn=bob,ou=people,dc=example,dc=com", &[]).is_err());
+        assert!(make_entry("ou=groups,dc=example,dc=com", &[]).is_err());
+    }
+
+    #[test]
+    fn test_get_static_entries() {
+        let entries = vec![
+            make_entry(
+                "ou=Policies,dc=example,dc=com",
+                &[
+                    ("objectClass", &["top", "organizationalUnit"]),
+                    ("ou", &["Policies"]),
+                ],
+            )
+            .unwrap(),
+            make_entry(
+                "cn=default,ou=Policies,dc=example,dc=com",
+                &[
+                    ("objectClass", &["top", "pwdPolicy"]),
+                    ("pwdMinLength", &["12"]),
+                ],
+            )
+            .unwrap(),
+        ];
+        let object_class = || LdapFilter::Present("objectclass".to_owned());
+        assert_eq!(
+            get_dns(
+                &entries,
+                "dc=example,dc=com",
+                LdapSearchScope::Subtree,
+                object_class()
+            ),
+            vec![
+                "ou=Policies,dc=example,dc=com",
+                "cn=default,ou=Policies,dc=example,dc=com"
+            ]
+        );
+        assert_eq!(
+            get_dns(
+                &entries,
+                "dc=example,dc=com",
+                LdapSearchScope::OneLevel,
+                object_class()
+            ),
+            vec!["ou=Policies,dc=example,dc=com"]
+        );
+        assert_eq!(
+            get_dns(
+                &entries,
+                "ou=policies,dc=example,dc=com",
+                LdapSearchScope::Base,
+                object_class()
+            ),
+            vec!["ou=Policies,dc=example,dc=com"]
+        );
+        assert_eq!(
+            get_dns(
+                &entries,
+                "dc=example,dc=com",
+                LdapSearchScope::Subtree,
+                LdapFilter::And(vec![
+                    LdapFilter::Equality("objectClass".to_owned(), "PWDPOLICY".to_owned()),
+                    LdapFilter::Equality("pwdMinLength".to_owned(), "8".to_owned()),
+                ])
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            get_dns(
+                &entries,
+                "dc=example,dc=com",
+                LdapSearchScope::Subtree,
+                LdapFilter::Not(Box::new(LdapFilter::Present("pwdMinLength".to_owned())))
+            ),
+            vec!["ou=Policies,dc=example,dc=com"]
+        );
+    }
+
+    #[test]
+    fn test_get_builtin_entries() {
+        let entries = get_builtin_entries("dc=example,dc=com", &base_dn());
+        assert_eq!(
+            get_dns(
+                &entries,
+                "dc=example,dc=com",
+                LdapSearchScope::Base,
+                LdapFilter::Equality("objectClass".to_owned(), "domain".to_owned())
+            ),
+            vec!["dc=example,dc=com"]
+        );
+        assert_eq!(
+            get_dns(
+                &entries,
+                "dc=example,dc=com",
+                LdapSearchScope::OneLevel,
+                LdapFilter::Present("objectClass".to_owned())
+            ),
+            vec!["ou=people,dc=example,dc=com", "ou=groups,dc=example,dc=com"]
+        );
+    }
+
+    #[test]
+    fn test_matches_substring() {
+        let filter = LdapSubstringFilter {
+            initial: Some("Pol".to_owned()),
+            any: vec!["ic".to_owned()],
+            final_: Some("s".to_owned()),
+        };
+        assert!(matches_substring("policies", &filter));
+        assert!(!matches_substring("politics", &filter));
+    }
+
+    #[test]
+    fn test_selected_attributes() {
+        let entry = make_entry(
+            "ou=policies,dc=example,dc=com",
+            &[
+                ("objectClass", &["organizationalUnit"]),
+                ("ou", &["policies"]),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            entry.to_ldap_op(&["OU".to_owned()]),
+            LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                dn: "ou=policies,dc=example,dc=com".to_owned(),
+                attributes: vec![LdapPartialAttribute {
+                    atype: "ou".to_owned(),
+                    vals: vec![b"policies".to_vec()],
+                }],
+            })
+        );
+    }
+}
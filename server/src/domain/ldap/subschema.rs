@@ -0,0 +1,580 @@
+use ldap3_proto::proto::{LdapOp, LdapPartialAttribute, LdapSearchResultEntry};
+
+use crate::domain::{
+    handler::AttributeSchema,
+    schema::PublicSchema,
+    types::{AttributeType, LdapObjectClass},
+};
+
+/// The DN of the subschema subentry, advertised in the root DSE.
+pub const SUBSCHEMA_DN: &str = "cn=Subschema";
+
+const DIRECTORY_STRING: &str = "1.3.6.1.4.1.1466.115.121.1.15";
+const IA5_STRING: &str = "1.3.6.1.4.1.1466.115.121.1.26";
+const INTEGER: &str = "1.3.6.1.4.1.1466.115.121.1.27";
+const JPEG: &str = "1.3.6.1.4.1.1466.115.121.1.28";
+const GENERALIZED_TIME: &str = "1.3.6.1.4.1.1466.115.121.1.24";
+const DN: &str = "1.3.6.1.4.1.1466.115.121.1.12";
+const NAME_AND_OPTIONAL_UID: &str = "1.3.6.1.4.1.1466.115.121.1.34";
+const OID: &str = "1.3.6.1.4.1.1466.115.121.1.38";
+const OCTET_STRING: &str = "1.3.6.1.4.1.1466.115.121.1.40";
+const UUID: &str = "1.3.6.1.1.16.1";
+const ATTRIBUTE_TYPE_DESCRIPTION: &str = "1.3.6.1.4.1.1466.115.121.1.3";
+const OBJECT_CLASS_DESCRIPTION: &str = "1.3.6.1.4.1.1466.115.121.1.37";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Usage {
+    /// Can be set through LDAP or the web UI.
+    User,
+    /// Computed by LLDAP, but returned with the user attributes.
+    ReadOnly,
+    /// Only returned when requested by name, or with "+".
+    Operational,
+}
+
+struct AttributeTypeDefinition {
+    oid: String,
+    name: String,
+    syntax: &'static str,
+    single_value: bool,
+    usage: Usage,
+}
+
+impl AttributeTypeDefinition {
+    fn to_description(&self) -> String {
+        let mut description = format!("( {} NAME '{}' SYNTAX {}", self.oid, self.name, self.syntax);
+        if self.single_value {
+            description.push_str(" SINGLE-VALUE");
+        }
+        match self.usage {
+            Usage::User => (),
+            Usage::ReadOnly => description.push_str(" NO-USER-MODIFICATION"),
+            Usage::Operational => {
+                description.push_str(" NO-USER-MODIFICATION USAGE directoryOperation")
+            }
+        }
+        description.push_str(" )");
+        description
+    }
+}
+
+/// The attributes LLDAP serves regardless of the schema: (OID, name, syntax, single value, usage).
+const BUILTIN_ATTRIBUTES: &[(&str, &str, &str, bool, Usage)] = &[
+    ("2.5.4.0", "objectClass", OID, false, Usage::User),
+    ("2.5.4.3", "cn", DIRECTORY_STRING, true, Usage::User),
+    ("2.5.4.4", "sn", DIRECTORY_STRING, true, Usage::User),
+    ("2.5.4.42", "givenName", DIRECTORY_STRING, true, Usage::User),
+    (
+        "0.9.2342.19200300.100.1.1",
+        "uid",
+        DIRECTORY_STRING,
+        true,
+        Usage::User,
+    ),
+    (
+        "0.9.2342.19200300.100.1.3",
+        "mail",
+        IA5_STRING,
+        true,
+        Usage::User,
+    ),
+    (
+        "0.9.2342.19200300.100.1.60",
+        "jpegPhoto",
+        JPEG,
+        true,
+        Usage::User,
+    ),
+    (
+        "2.16.840.1.113730.3.1.35",
+        "thumbnailPhoto",
+        OCTET_STRING,
+        true,
+        Usage::User,
+    ),
+    (
+        "2.16.840.1.113730.3.1.39",
+        "preferredLanguage",
+        DIRECTORY_STRING,
+        true,
+        Usage::User,
+    ),
+    ("1.3.6.1.1.1.1.0", "uidNumber", INTEGER, true, Usage::User),
+    ("1.3.6.1.1.1.1.1", "gidNumber", INTEGER, true, Usage::User),
+    (
+        "1.3.6.1.1.1.1.3",
+        "homeDirectory",
+        IA5_STRING,
+        true,
+        Usage::User,
+    ),
+    (
+        "1.3.6.1.1.1.1.4",
+        "loginShell",
+        IA5_STRING,
+        true,
+        Usage::User,
+    ),
+    (
+        "1.3.6.1.4.1.24552.500.1.1.1.13",
+        "sshPublicKey",
+        OCTET_STRING,
+        false,
+        Usage::User,
+    ),
+    ("2.5.4.31", "member", DN, false, Usage::User),
+    (
+        "2.5.4.50",
+        "uniqueMember",
+        NAME_AND_OPTIONAL_UID,
+        false,
+        Usage::User,
+    ),
+    (
+        "1.2.840.113556.1.2.102",
+        "memberOf",
+        DN,
+        false,
+        Usage::ReadOnly,
+    ),
+    (
+        "externalId-oid",
+        "externalId",
+        DIRECTORY_STRING,
+        true,
+        Usage::ReadOnly,
+    ),
+    (
+        "2.5.18.1",
+        "createTimestamp",
+        GENERALIZED_TIME,
+        true,
+        Usage::Operational,
+    ),
+    (
+        "2.5.18.2",
+        "modifyTimestamp",
+        GENERALIZED_TIME,
+        true,
+        Usage::Operational,
+    ),
+    (
+        "1.3.6.1.1.16.4",
+        "entryUUID",
+        UUID,
+        true,
+        Usage::Operational,
+    ),
+    ("1.3.6.1.1.20", "entryDN", DN, true, Usage::Operational),
+    (
+        "2.5.18.10",
+        "subschemaSubentry",
+        DN,
+        true,
+        Usage::Operational,
+    ),
+    (
+        "2.5.21.5",
+        "attributeTypes",
+        ATTRIBUTE_TYPE_DESCRIPTION,
+        false,
+        Usage::Operational,
+    ),
+    (
+        "2.5.21.6",
+        "objectClasses",
+        OBJECT_CLASS_DESCRIPTION,
+        false,
+        Usage::Operational,
+    ),
+];
+
+/// Standard OIDs of attributes commonly added to the schema.
+fn well_known_oid(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "gecos" => "1.3.6.1.1.1.1.2",
+        "telephonenumber" => "2.5.4.20",
+        "mobile" => "0.9.2342.19200300.100.1.41",
+        "title" => "2.5.4.12",
+        "description" => "2.5.4.13",
+        _ => return None,
+    })
+}
+
+fn custom_attribute_definition(attribute: &AttributeSchema) -> AttributeTypeDefinition {
+    let name = attribute.name.as_str().to_owned();
+    AttributeTypeDefinition {
+        // Without an OID of its own, the attribute uses the "descriptor-oid" convention.
+        oid: well_known_oid(&name.to_ascii_lowercase())
+            .map(str::to_owned)
+            .unwrap_or_else(|| format!("{}-oid", name)),
+        syntax: match attribute.attribute_type {
+            AttributeType::String => DIRECTORY_STRING,
+            AttributeType::Integer => INTEGER,
+            AttributeType::JpegPhoto => JPEG,
+            AttributeType::DateTime => GENERALIZED_TIME,
+        },
+        single_value: !attribute.is_list,
+        usage: if attribute.is_readonly {
+            Usage::ReadOnly
+        } else {
+            Usage::User
+        },
+        name,
+    }
+}
+
+/// The custom attributes of the schema, the hardcoded ones being served under builtin names.
+fn custom_attributes(attributes: &[AttributeSchema]) -> impl Iterator<Item = &AttributeSchema> {
+    attributes.iter().filter(|a| {
+        !a.is_hardcoded
+            && !BUILTIN_ATTRIBUTES
+                .iter()
+                .any(|(_, name, _, _, _)| name.eq_ignore_ascii_case(a.name.as_str()))
+    })
+}
+
+fn object_class_description(
+    oid: &str,
+    name: &str,
+    superior: Option<&str>,
+    kind: &str,
+    must: &[String],
+    may: &[String],
+) -> String {
+    let mut description = format!("( {} NAME '{}'", oid, name);
+    if let Some(superior) = superior {
+        description.push_str(&format!(" SUP {}", superior));
+    }
+    description.push_str(&format!(" {}", kind));
+    for (keyword, attributes) in [("MUST", must), ("MAY", may)] {
+        match attributes {
+            [] => (),
+            [attribute] => description.push_str(&format!(" {} {}", keyword, attribute)),
+            _ => description.push_str(&format!(" {} ( {} )", keyword, attributes.join(" $ "))),
+        }
+    }
+    description.push_str(" )");
+    description
+}
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|n| n.to_string()).collect()
+}
+
+fn get_object_classes(schema: &PublicSchema) -> Vec<String> {
+    let schema = schema.get_schema();
+    let custom_names = |attributes: &[AttributeSchema]| -> Vec<String> {
+        custom_attributes(attributes)
+            .map(|a| a.name.as_str().to_owned())
+            .collect()
+    };
+    let user_attributes = custom_names(&schema.user_attributes.attributes);
+    let group_attributes = custom_names(&schema.group_attributes.attributes);
+    let mut person_attributes = names(&[
+        "uid",
+        "mail",
+        "givenName",
+        "jpegPhoto",
+        "thumbnailPhoto",
+        "preferredLanguage",
+        "memberOf",
+        "externalId",
+    ]);
+    person_attributes.extend_from_slice(&user_attributes);
+    let mut group_of_names_attributes =
+        names(&["uid", "uniqueMember", "member", "mail", "gidNumber"]);
+    group_of_names_attributes.extend_from_slice(&group_attributes);
+    let mut object_classes = vec![
+        object_class_description(
+            "2.5.6.0",
+            "top",
+            None,
+            "ABSTRACT",
+            &names(&["objectClass"]),
+            &[],
+        ),
+        object_class_description(
+            "2.5.6.6",
+            "person",
+            Some("top"),
+            "STRUCTURAL",
+            &[],
+            &names(&["cn", "sn"]),
+        ),
+        object_class_description(
+            "2.16.840.1.113730.3.2.2",
+            "inetOrgPerson",
+            Some("person"),
+            "STRUCTURAL",
+            &[],
+            &person_attributes,
+        ),
+        object_class_description(
+            "1.3.6.1.1.1.2.0",
+            "posixAccount",
+            Some("top"),
+            "AUXILIARY",
+            &[],
+            &names(&[
+                "cn",
+                "uid",
+                "uidNumber",
+                "gidNumber",
+                "homeDirectory",
+                "loginShell",
+            ]),
+        ),
+        object_class_description(
+            "mailAccount-oid",
+            "mailAccount",
+            Some("top"),
+            "AUXILIARY",
+            &[],
+            &names(&["mail"]),
+        ),
+        object_class_description(
+            "1.3.6.1.4.1.24552.500.1.1.2.0",
+            "ldapPublicKey",
+            Some("top"),
+            "AUXILIARY",
+            &[],
+            &names(&["sshPublicKey", "uid"]),
+        ),
+        object_class_description(
+            "2.5.6.17",
+            "groupOfUniqueNames",
+            Some("top"),
+            "STRUCTURAL",
+            &[],
+            &[names(&["cn"]), group_of_names_attributes].concat(),
+        ),
+        object_class_description(
+            "2.5.17.0",
+            "subentry",
+            Some("top"),
+            "STRUCTURAL",
+            &names(&["cn"]),
+            &[],
+        ),
+        object_class_description(
+            "2.5.20.1",
+            "subschema",
+            None,
+            "AUXILIARY",
+            &[],
+            &names(&["attributeTypes", "objectClasses"]),
+        ),
+    ];
+    let extra_classes = |classes: &[LdapObjectClass], attributes: &[String]| -> Vec<String> {
+        classes
+            .iter()
+            .map(|c| {
+                let name = c.as_str();
+                object_class_description(
+                    &format!("{}-oid", name),
+                    name,
+                    Some("top"),
+                    "AUXILIARY",
+                    &[],
+                    attributes,
+                )
+            })
+            .collect()
+    };
+    object_classes.extend(extra_classes(
+        &schema.extra_user_object_classes,
+        &user_attributes,
+    ));
+    object_classes.extend(extra_classes(
+        &schema.extra_group_object_classes,
+        &group_attributes,
+    ));
+    object_classes
+}
+
+fn get_attribute_types(schema: &PublicSchema) -> Vec<AttributeTypeDefinition> {
+    let mut definitions: Vec<_> = BUILTIN_ATTRIBUTES
+        .iter()
+        .map(
+            |(oid, name, syntax, single_value, usage)| AttributeTypeDefinition {
+                oid: oid.to_string(),
+                name: name.to_string(),
+                syntax,
+                single_value: *single_value,
+                usage: *usage,
+            },
+        )
+        .collect();
+    let schema = schema.get_schema();
+    for attribute in custom_attributes(&schema.user_attributes.attributes)
+        .chain(custom_attributes(&schema.group_attributes.attributes))
+    {
+        if !definitions
+            .iter()
+            .any(|d| d.name.eq_ignore_ascii_case(attribute.name.as_str()))
+        {
+            definitions.push(custom_attribute_definition(attribute));
+        }
+    }
+    definitions
+}
+
+/// The subschema subentry, describing the attributes and object classes LLDAP serves, with the
+/// requested attributes. As operational attributes, the definitions are only returned when they
+/// are requested by name or with "+".
+pub fn get_subschema_entry(schema: &PublicSchema, requested_attributes: &[String]) -> LdapOp {
+    let all_user_attributes =
+        requested_attributes.is_empty() || requested_attributes.iter().any(|a| a == "*");
+    let all_operational_attributes = requested_attributes.iter().any(|a| a == "+");
+    let is_requested = |name: &str, operational: bool| {
+        requested_attributes
+            .iter()
+            .any(|a| a.eq_ignore_ascii_case(name))
+            || if operational {
+                all_operational_attributes
+            } else {
+                all_user_attributes
+            }
+    };
+    let to_values = |values: Vec<String>| values.into_iter().map(String::into_bytes).collect();
+    let attributes = [
+        (
+            "objectClass",
+            false,
+            names(&["top", "subentry", "subschema"]),
+        ),
+        ("cn", false, names(&["Subschema"])),
+        (
+            "attributeTypes",
+            true,
+            get_attribute_types(schema)
+                .iter()
+                .map(AttributeTypeDefinition::to_description)
+                .collect(),
+        ),
+        ("objectClasses", true, get_object_classes(schema)),
+    ];
+    LdapOp::SearchResultEntry(LdapSearchResultEntry {
+        dn: SUBSCHEMA_DN.to_owned(),
+        attributes: attributes
+            .into_iter()
+            .filter(|(name, operational, _)| is_requested(name, *operational))
+            .map(|(name, _, values)| LdapPartialAttribute {
+                atype: name.to_owned(),
+                vals: to_values(values),
+            })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::handler::{AttributeList, Schema};
+    use pretty_assertions::assert_eq;
+
+    fn get_values(op: &LdapOp, name: &str) -> Option<Vec<String>> {
+        match op {
+            LdapOp::SearchResultEntry(entry) => {
+                entry.attributes.iter().find(|a| a.atype == name).map(|a| {
+                    a.vals
+                        .iter()
+                        .map(|v| String::from_utf8(v.clone()).unwrap())
+                        .collect()
+                })
+            }
+            _ => panic!("Unexpected result"),
+        }
+    }
+
+    fn test_schema() -> PublicSchema {
+        PublicSchema::from(Schema {
+            user_attributes: AttributeList {
+                attributes: vec![
+                    AttributeSchema {
+                        name: "uidNumber".into(),
+                        attribute_type: AttributeType::Integer,
+                        is_list: false,
+                        is_visible: true,
+                        is_editable: true,
+                        is_hardcoded: false,
+                        is_readonly: false,
+                    },
+                    AttributeSchema {
+                        name: "nicknames".into(),
+                        attribute_type: AttributeType::String,
+                        is_list: true,
+                        is_visible: true,
+                        is_editable: true,
+                        is_hardcoded: false,
+                        is_readonly: false,
+                    },
+                ],
+            },
+            group_attributes: AttributeList {
+                attributes: Vec::new(),
+            },
+            extra_user_object_classes: vec![LdapObjectClass::from("shadowAccount")],
+            extra_group_object_classes: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn test_subschema_entry() {
+        let entry = get_subschema_entry(
+            &test_schema(),
+            &["attributeTypes".to_owned(), "objectClasses".to_owned()],
+        );
+        assert_eq!(get_values(&entry, "cn"), None);
+        let attribute_types = get_values(&entry, "attributeTypes").unwrap();
+        assert!(attribute_types.contains(
+            &"( 0.9.2342.19200300.100.1.3 NAME 'mail' SYNTAX 1.3.6.1.4.1.1466.115.121.1.26 \
+              SINGLE-VALUE )"
+                .to_owned()
+        ));
+        // A custom attribute with a builtin name is served as the builtin one.
+        assert!(attribute_types.contains(
+            &"( 1.3.6.1.1.1.1.0 NAME 'uidNumber' SYNTAX 1.3.6.1.4.1.1466.115.121.1.27 \
+              SINGLE-VALUE )"
+                .to_owned()
+        ));
+        assert!(!attribute_types.iter().any(|a| a.contains("'uidnumber'")));
+        assert!(attribute_types.contains(
+            &"( nicknames-oid NAME 'nicknames' SYNTAX 1.3.6.1.4.1.1466.115.121.1.15 )".to_owned()
+        ));
+        // The hardcoded attributes are served under their LDAP names.
+        assert!(!attribute_types.iter().any(|a| a.contains("'first_name'")));
+        assert!(attribute_types.contains(
+            &"( 1.3.6.1.4.1.24552.500.1.1.1.13 NAME 'sshPublicKey' \
+              SYNTAX 1.3.6.1.4.1.1466.115.121.1.40 )"
+                .to_owned()
+        ));
+        let object_classes = get_values(&entry, "objectClasses").unwrap();
+        assert!(object_classes.contains(
+            &"( shadowAccount-oid NAME 'shadowAccount' SUP top AUXILIARY MAY nicknames )"
+                .to_owned()
+        ));
+        assert!(object_classes
+            .iter()
+            .any(|c| c.starts_with("( 2.16.840.1.113730.3.2.2 NAME 'inetOrgPerson'")));
+        assert!(object_classes.contains(
+            &"( 1.3.6.1.4.1.24552.500.1.1.2.0 NAME 'ldapPublicKey' SUP top AUXILIARY \
+              MAY ( sshPublicKey $ uid ) )"
+                .to_owned()
+        ));
+    }
+
+    #[test]
+    fn test_subschema_entry_default_attributes() {
+        let entry = get_subschema_entry(&test_schema(), &[]);
+        assert_eq!(
+            get_values(&entry, "objectClass"),
+            Some(names(&["top", "subentry", "subschema"]))
+        );
+        assert_eq!(get_values(&entry, "attributeTypes"), None);
+        let entry = get_subschema_entry(&test_schema(), &["+".to_owned()]);
+        assert_eq!(get_values(&entry, "objectClass"), None);
+        assert!(get_values(&entry, "objectClasses").is_some());
+    }
+}
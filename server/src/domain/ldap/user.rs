@@ -1,36 +1,96 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
 use chrono::TimeZone;
 use ldap3_proto::{
-    proto::LdapOp, LdapFilter, LdapPartialAttribute, LdapResultCode, LdapSearchResultEntry,
+    proto::{LdapModify, LdapModifyType, LdapOp},
+    LdapFilter, LdapPartialAttribute, LdapResultCode, LdapSearchResultEntry,
 };
 use tracing::{debug, instrument, warn};
 
-use crate::domain::{
-    deserialize::deserialize_attribute_value,
-    handler::{UserListerBackendHandler, UserRequestFilter},
-    ldap::{
-        error::{LdapError, LdapResult},
-        utils::{
-            expand_attribute_wildcards, get_custom_attribute,
-            get_group_id_from_distinguished_name_or_plain_name,
-            get_user_id_from_distinguished_name_or_plain_name, map_user_field, ExpandedAttributes,
-            LdapInfo, UserFieldType,
+use crate::{
+    domain::{
+        attribute_templates::render_template,
+        computed_attributes::{render_user_template, template_fields},
+        deserialize::deserialize_attribute_value,
+        handler::{UpdateUserRequest, UserListerBackendHandler, UserRequestFilter},
+        ldap::{
+            error::{domain_error_code, LdapError, LdapResult},
+            utils::{
+                expand_attribute_wildcards, get_custom_attribute,
+                get_group_id_from_distinguished_name_or_plain_name,
+                get_user_id_from_distinguished_name_or_plain_name, map_user_field,
+                parse_ldap_attribute_value, parse_ldap_timestamp, ExpandedAttributes, LdapInfo,
+                UserFieldType,
+            },
+        },
+        nested_groups::GroupHierarchy,
+        schema::{PublicSchema, SchemaUserAttributeExtractor},
+        ssh_keys::{has_ssh_public_key, SSH_PUBLIC_KEY_ATTRIBUTE},
+        types::{
+            AttributeName, AttributeType, AttributeValue, GroupDetails, LdapObjectClass, User,
+            UserAndGroups, UserColumn, UserId,
         },
     },
-    schema::{PublicSchema, SchemaUserAttributeExtractor},
-    types::{
-        AttributeName, AttributeType, GroupDetails, LdapObjectClass, User, UserAndGroups,
-        UserColumn, UserId,
-    },
+    infra::configuration::PosixDefaultsOptions,
 };
 
+const ACTIVE_DIRECTORY_USER_ATTRIBUTE_KEYS: &[&str] =
+    &["sAMAccountName", "userPrincipalName", "objectGUID"];
+
+/// The Active Directory attributes served in compatibility mode, computed from the user.
+fn get_active_directory_attribute(
+    user: &User,
+    attribute: &AttributeName,
+    ldap_info: &LdapInfo,
+) -> Option<Vec<Vec<u8>>> {
+    match attribute.as_str() {
+        "samaccountname" => Some(vec![user.user_id.to_string().into_bytes()]),
+        "userprincipalname" => {
+            let domain = ldap_info.active_directory_domain();
+            if domain.is_empty() {
+                return None;
+            }
+            Some(vec![format!("{}@{}", user.user_id, domain).into_bytes()])
+        }
+        // AD stores the GUIDs with the first three fields in little-endian order.
+        "objectguid" => Some(vec![uuid::Uuid::parse_str(user.uuid.as_str())
+            .ok()?
+            .to_bytes_le()
+            .to_vec()]),
+        _ => None,
+    }
+}
+
+/// In Active Directory compatibility mode, the attribute that an AD attribute is computed from,
+/// to use in filters.
+fn resolve_active_directory_attribute(ldap_info: &LdapInfo, field: AttributeName) -> AttributeName {
+    if !ldap_info.active_directory_compatibility {
+        return field;
+    }
+    match field.as_str() {
+        "samaccountname" | "userprincipalname" => AttributeName::from("uid"),
+        "objectguid" => AttributeName::from("entryuuid"),
+        _ => field,
+    }
+}
+
 pub fn get_user_attribute(
     user: &User,
     attribute: &AttributeName,
-    base_dn_str: &str,
+    ldap_info: &LdapInfo,
     groups: Option<&[GroupDetails]>,
-    ignored_user_attributes: &[AttributeName],
     schema: &PublicSchema,
 ) -> Option<Vec<Vec<u8>>> {
+    if ldap_info.active_directory_compatibility
+        && ACTIVE_DIRECTORY_USER_ATTRIBUTE_KEYS
+            .iter()
+            .any(|key| key.eq_ignore_ascii_case(attribute.as_str()))
+    {
+        return get_active_directory_attribute(user, attribute, ldap_info);
+    }
     let attribute_values = match map_user_field(attribute, schema) {
         UserFieldType::ObjectClass => {
             let mut classes = vec![
@@ -39,6 +99,12 @@ pub fn get_user_attribute(
                 b"mailAccount".to_vec(),
                 b"person".to_vec(),
             ];
+            if has_ssh_public_key(user) {
+                classes.push(b"ldapPublicKey".to_vec());
+            }
+            if ldap_info.active_directory_compatibility {
+                classes.push(b"user".to_vec());
+            }
             classes.extend(
                 schema
                     .get_schema()
@@ -46,18 +112,29 @@ pub fn get_user_attribute(
                     .iter()
                     .map(|c| c.as_str().as_bytes().to_vec()),
             );
+            classes.extend(
+                ldap_info
+                    .profile
+                    .user_object_classes
+                    .iter()
+                    .map(|c| c.as_bytes().to_vec()),
+            );
             classes
         }
         // dn is always returned as part of the base response.
         UserFieldType::Dn => return None,
         UserFieldType::EntryDn => {
-            vec![format!("uid={},ou=people,{}", &user.user_id, base_dn_str).into_bytes()]
+            vec![ldap_info.user_dn(&user.user_id).into_bytes()]
         }
         UserFieldType::MemberOf => groups
             .into_iter()
             .flatten()
             .map(|id_and_name| {
-                format!("cn={},ou=groups,{}", &id_and_name.display_name, base_dn_str).into_bytes()
+                format!(
+                    "cn={},ou=groups,{}",
+                    &id_and_name.display_name, ldap_info.base_dn_str
+                )
+                .into_bytes()
             })
             .collect(),
         UserFieldType::PrimaryField(UserColumn::UserId) => {
@@ -71,6 +148,9 @@ pub fn get_user_attribute(
             | UserColumn::MfaType,
         ) => panic!("Should not get here"),
         UserFieldType::PrimaryField(UserColumn::Uuid) => vec![user.uuid.to_string().into_bytes()],
+        UserFieldType::PrimaryField(UserColumn::ExternalId) => {
+            vec![user.external_id.clone()?.into_bytes()]
+        }
         UserFieldType::PrimaryField(UserColumn::DisplayName) => {
             vec![user.display_name.clone()?.into_bytes()]
         }
@@ -78,21 +158,18 @@ pub fn get_user_attribute(
             .from_utc_datetime(&user.creation_date)
             .to_rfc3339()
             .into_bytes()],
+        UserFieldType::PrimaryField(UserColumn::ModifiedDate) => vec![chrono::Utc
+            .from_utc_datetime(&user.modified_date)
+            .to_rfc3339()
+            .into_bytes()],
         UserFieldType::Attribute(attr, _, _) => {
             get_custom_attribute::<SchemaUserAttributeExtractor>(&user.attributes, &attr, schema)?
         }
         UserFieldType::NoMatch => match attribute.as_str() {
-            "1.1" => return None,
-            // We ignore the operational attribute wildcard.
-            "+" => return None,
-            "*" => {
-                panic!(
-                    "Matched {}, * should have been expanded into attribute list and * removed",
-                    attribute
-                )
-            }
+            // The selectors are expanded into the attribute list by expand_attribute_wildcards.
+            "1.1" | "+" | "*" => return None,
             _ => {
-                if ignored_user_attributes.contains(attribute) {
+                if ldap_info.ignored_user_attributes.contains(attribute) {
                     return None;
                 }
                 get_custom_attribute::<SchemaUserAttributeExtractor>(
@@ -102,7 +179,7 @@ pub fn get_user_attribute(
                 )
                 .or_else(|| {
                     warn!(
-                        r#"Ignoring unrecognized group attribute: {}\n\
+                        r#"Ignoring unrecognized user attribute: {}\n\
                       To disable this warning, add it to "ignored_user_attributes" in the config."#,
                         attribute
                     );
@@ -118,6 +195,35 @@ pub fn get_user_attribute(
     }
 }
 
+/// The configured default of `homeDirectory`, `loginShell` or `gidNumber`, if the user has no
+/// value stored.
+fn get_posix_default(
+    user: &User,
+    attribute: &AttributeName,
+    groups: Option<&[GroupDetails]>,
+    posix_defaults: &PosixDefaultsOptions,
+    schema: &PublicSchema,
+) -> Option<Vec<Vec<u8>>> {
+    let groups = groups.unwrap_or_default();
+    let value = if attribute.as_str() == "gidnumber" {
+        posix_defaults.get_gid_number(groups)?.to_string()
+    } else {
+        render_template(
+            posix_defaults.get_template(attribute, groups)?,
+            &user.user_id,
+            user.email.as_str(),
+        )
+    };
+    let stored_name = match map_user_field(attribute, schema) {
+        UserFieldType::Attribute(name, _, _) => name,
+        _ => attribute.clone(),
+    };
+    if user.attributes.iter().any(|a| a.name == stored_name) {
+        return None;
+    }
+    Some(vec![value.into_bytes()])
+}
+
 const ALL_USER_ATTRIBUTE_KEYS: &[&str] = &[
     "objectclass",
     "uid",
@@ -126,45 +232,84 @@ const ALL_USER_ATTRIBUTE_KEYS: &[&str] = &[
     "sn",
     "cn",
     "jpegPhoto",
+    "preferredLanguage",
     "createtimestamp",
+    "modifytimestamp",
     "entryuuid",
+    "externalId",
 ];
 
+/// Returned with "+".
+const OPERATIONAL_USER_ATTRIBUTE_KEYS: &[&str] =
+    &["createtimestamp", "modifytimestamp", "entrydn", "entryuuid"];
+
 fn make_ldap_search_user_result_entry(
     user: User,
-    base_dn_str: &str,
+    ldap_info: &LdapInfo,
     mut expanded_attributes: ExpandedAttributes,
     groups: Option<&[GroupDetails]>,
-    ignored_user_attributes: &[AttributeName],
     schema: &PublicSchema,
 ) -> LdapSearchResultEntry {
+    let posix_defaults = &ldap_info.posix_defaults;
     if expanded_attributes.include_custom_attributes {
-        expanded_attributes.attribute_keys.extend(
-            user.attributes
-                .iter()
-                .map(|a| (a.name.clone(), a.name.to_string())),
-        );
+        for attribute in &user.attributes {
+            expanded_attributes
+                .attribute_keys
+                .entry(attribute.name.clone())
+                .or_insert_with(|| attribute.name.to_string());
+        }
+        for name in posix_defaults.attributes() {
+            expanded_attributes
+                .attribute_keys
+                .entry(AttributeName::from(name))
+                .or_insert_with(|| name.to_owned());
+        }
+        for computed in &ldap_info.computed_user_attributes {
+            expanded_attributes
+                .attribute_keys
+                .entry(computed.attribute_name())
+                .or_insert_with(|| computed.name.clone());
+        }
+        if ldap_info.active_directory_compatibility {
+            for &name in ACTIVE_DIRECTORY_USER_ATTRIBUTE_KEYS {
+                expanded_attributes
+                    .attribute_keys
+                    .entry(AttributeName::from(name))
+                    .or_insert_with(|| name.to_owned());
+            }
+        }
+        ldap_info.add_user_attribute_mappings(&mut expanded_attributes.attribute_keys);
     }
-    LdapSearchResultEntry {
-        dn: format!("uid={},ou=people,{}", user.user_id.as_str(), base_dn_str),
-        attributes: expanded_attributes
-            .attribute_keys
-            .into_iter()
-            .filter_map(|(attribute, name)| {
-                let values = get_user_attribute(
-                    &user,
-                    &attribute,
-                    base_dn_str,
-                    groups,
-                    ignored_user_attributes,
-                    schema,
-                )?;
-                Some(LdapPartialAttribute {
-                    atype: name,
-                    vals: values,
-                })
+    let mut attributes = expanded_attributes
+        .attribute_keys
+        .into_iter()
+        .filter(|(attribute, _)| !ldap_info.is_renamed_user_attribute(attribute))
+        .filter_map(|(attribute, name)| {
+            let attribute = &ldap_info.resolve_user_attribute(&attribute);
+            if let Some(computed) = ldap_info
+                .computed_user_attributes
+                .iter()
+                .find(|c| &c.attribute_name() == attribute)
+            {
+                return render_user_template(&computed.template, &user, schema).map(|value| {
+                    LdapPartialAttribute {
+                        atype: name,
+                        vals: vec![value.into_bytes()],
+                    }
+                });
+            }
+            let values = get_posix_default(&user, attribute, groups, posix_defaults, schema)
+                .or_else(|| get_user_attribute(&user, attribute, ldap_info, groups, schema))?;
+            Some(LdapPartialAttribute {
+                atype: name,
+                vals: values,
             })
-            .collect::<Vec<LdapPartialAttribute>>(),
+        })
+        .collect::<Vec<LdapPartialAttribute>>();
+    ldap_info.sort_entry_attributes(&expanded_attributes.requested_order, &mut attributes);
+    LdapSearchResultEntry {
+        dn: ldap_info.user_dn(&user.user_id),
+        attributes,
     }
 }
 
@@ -182,12 +327,22 @@ fn get_user_attribute_equality_filter(
         })
 }
 
-fn convert_user_filter(
+/// The attribute that a filter on `field` applies to, after resolving the mappings, the profile
+/// aliases and the Active Directory attributes.
+pub fn resolve_user_filter_attribute(ldap_info: &LdapInfo, field: &str) -> AttributeName {
+    resolve_active_directory_attribute(
+        ldap_info,
+        ldap_info.resolve_user_attribute(&AttributeName::from(field)),
+    )
+}
+
+pub fn convert_user_filter(
     ldap_info: &LdapInfo,
     filter: &LdapFilter,
     schema: &PublicSchema,
 ) -> LdapResult<UserRequestFilter> {
     let rec = |f| convert_user_filter(ldap_info, f, schema);
+    let resolve_alias = |field: &String| resolve_user_filter_attribute(ldap_info, field);
     match filter {
         LdapFilter::And(filters) => Ok(UserRequestFilter::And(
             filters.iter().map(rec).collect::<LdapResult<_>>()?,
@@ -197,8 +352,17 @@ fn convert_user_filter(
         )),
         LdapFilter::Not(filter) => Ok(UserRequestFilter::Not(Box::new(rec(filter)?))),
         LdapFilter::Equality(field, value) => {
-            let field = AttributeName::from(field.as_str());
-            let value = value.to_ascii_lowercase();
+            let mut value = value.to_ascii_lowercase();
+            if ldap_info.active_directory_compatibility
+                && field.eq_ignore_ascii_case("userPrincipalName")
+            {
+                let suffix = format!("@{}", ldap_info.active_directory_domain());
+                match value.strip_suffix(&suffix) {
+                    Some(user_id) => value = user_id.to_owned(),
+                    None => return Ok(UserRequestFilter::from(false)),
+                }
+            }
+            let field = resolve_alias(field);
             match map_user_field(&field, schema) {
                 UserFieldType::PrimaryField(UserColumn::UserId) => {
                     Ok(UserRequestFilter::UserId(UserId::new(&value)))
@@ -207,6 +371,10 @@ fn convert_user_filter(
                     UserColumn::LowercaseEmail,
                     value,
                 )),
+                UserFieldType::PrimaryField(UserColumn::ModifiedDate) => Err(LdapError {
+                    code: LdapResultCode::UnwillingToPerform,
+                    message: "Only >= filters are supported on modifyTimestamp".to_owned(),
+                }),
                 UserFieldType::PrimaryField(field) => Ok(UserRequestFilter::Equality(field, value)),
                 UserFieldType::Attribute(field, typ, is_list) => Ok(
                     get_user_attribute_equality_filter(&field, typ, is_list, &value),
@@ -221,14 +389,23 @@ fn convert_user_filter(
                     }
                     Ok(UserRequestFilter::from(false))
                 }
+                UserFieldType::ObjectClass if value == "ldappublickey" => Ok(
+                    UserRequestFilter::CustomAttributePresent(SSH_PUBLIC_KEY_ATTRIBUTE.into()),
+                ),
                 UserFieldType::ObjectClass => Ok(UserRequestFilter::from(
                     matches!(
                         value.as_str(),
                         "person" | "inetorgperson" | "posixaccount" | "mailaccount"
-                    ) || schema
-                        .get_schema()
-                        .extra_user_object_classes
-                        .contains(&LdapObjectClass::from(value)),
+                    ) || (ldap_info.active_directory_compatibility && value == "user")
+                        || ldap_info
+                            .profile
+                            .user_object_classes
+                            .iter()
+                            .any(|c| c.eq_ignore_ascii_case(&value))
+                        || schema
+                            .get_schema()
+                            .extra_user_object_classes
+                            .contains(&LdapObjectClass::from(value)),
                 )),
                 UserFieldType::MemberOf => Ok(get_group_id_from_distinguished_name_or_plain_name(
                     &value,
@@ -255,7 +432,7 @@ fn convert_user_filter(
             }
         }
         LdapFilter::Present(field) => {
-            let field = AttributeName::from(field.as_str());
+            let field = resolve_alias(field);
             Ok(match map_user_field(&field, schema) {
                 UserFieldType::Attribute(name, _, _) => {
                     UserRequestFilter::CustomAttributePresent(name)
@@ -264,8 +441,25 @@ fn convert_user_filter(
                 _ => UserRequestFilter::from(true),
             })
         }
+        LdapFilter::GreaterOrEqual(field, value) => {
+            let field = resolve_alias(field);
+            match map_user_field(&field, schema) {
+                UserFieldType::PrimaryField(UserColumn::ModifiedDate) => {
+                    parse_ldap_timestamp(value)
+                        .map(UserRequestFilter::ModifiedSince)
+                        .ok_or_else(|| LdapError {
+                            code: LdapResultCode::InvalidAttributeSyntax,
+                            message: format!("Invalid timestamp: {}", value),
+                        })
+                }
+                _ => Err(LdapError {
+                    code: LdapResultCode::UnwillingToPerform,
+                    message: format!("Unsupported user attribute for >= filter: {:?}", field),
+                }),
+            }
+        }
         LdapFilter::Substring(field, substring_filter) => {
-            let field = AttributeName::from(field.as_str());
+            let field = resolve_alias(field);
             match map_user_field(&field, schema) {
                 UserFieldType::PrimaryField(UserColumn::UserId) => Ok(
                     UserRequestFilter::UserIdSubString(substring_filter.clone().into()),
@@ -276,7 +470,9 @@ fn convert_user_filter(
                 | UserFieldType::Dn
                 | UserFieldType::EntryDn
                 | UserFieldType::PrimaryField(UserColumn::CreationDate)
-                | UserFieldType::PrimaryField(UserColumn::Uuid) => Err(LdapError {
+                | UserFieldType::PrimaryField(UserColumn::ModifiedDate)
+                | UserFieldType::PrimaryField(UserColumn::Uuid)
+                | UserFieldType::PrimaryField(UserColumn::ExternalId) => Err(LdapError {
                     code: LdapResultCode::UnwillingToPerform,
                     message: format!(
                         "Unsupported user attribute for substring filter: {:?}",
@@ -302,7 +498,64 @@ fn convert_user_filter(
 }
 
 fn expand_user_attribute_wildcards(attributes: &[String]) -> ExpandedAttributes {
-    expand_attribute_wildcards(attributes, ALL_USER_ATTRIBUTE_KEYS)
+    expand_attribute_wildcards(
+        attributes,
+        ALL_USER_ATTRIBUTE_KEYS,
+        OPERATIONAL_USER_ATTRIBUTE_KEYS,
+    )
+}
+
+/// The user attributes that need to be loaded to answer a request for the given LDAP attributes,
+/// or `None` if all of them are needed.
+fn get_user_attributes_to_load(
+    ldap_info: &LdapInfo,
+    attributes: &[String],
+    schema: &PublicSchema,
+) -> Option<Vec<AttributeName>> {
+    let expanded_attributes = expand_user_attribute_wildcards(attributes);
+    if expanded_attributes.include_custom_attributes {
+        return None;
+    }
+    let attributes: BTreeSet<_> = expanded_attributes
+        .attribute_keys
+        .into_keys()
+        .map(|attribute| ldap_info.resolve_user_attribute(&attribute))
+        // The computed attributes are rendered from the fields of their template.
+        .flat_map(|attribute| {
+            match ldap_info
+                .computed_user_attributes
+                .iter()
+                .find(|c| c.attribute_name() == attribute)
+            {
+                Some(computed) => template_fields(&computed.template),
+                None => vec![attribute],
+            }
+        })
+        .map(|attribute| resolve_active_directory_attribute(ldap_info, attribute))
+        .filter_map(|attribute| match map_user_field(&attribute, schema) {
+            UserFieldType::Attribute(name, _, _) => Some(name),
+            // The users with an SSH key are also `ldapPublicKey`.
+            UserFieldType::ObjectClass => Some(SSH_PUBLIC_KEY_ATTRIBUTE.into()),
+            // Could be a custom attribute hidden from the public schema.
+            UserFieldType::NoMatch => Some(attribute),
+            _ => None,
+        })
+        .collect();
+    Some(attributes.into_iter().collect())
+}
+
+/// Whether the groups of the users are needed to render the defaults of the requested
+/// attributes.
+fn posix_defaults_need_groups(ldap_info: &LdapInfo, attributes: &[String]) -> bool {
+    if !ldap_info.posix_defaults.depends_on_groups() {
+        return false;
+    }
+    let expanded_attributes = expand_user_attribute_wildcards(attributes);
+    expanded_attributes.include_custom_attributes
+        || expanded_attributes
+            .attribute_keys
+            .keys()
+            .any(|a| matches!(a.as_str(), "homedirectory" | "loginshell" | "gidnumber"))
 }
 
 #[instrument(skip_all, level = "debug", fields(ldap_filter, request_groups))]
@@ -310,27 +563,36 @@ pub async fn get_user_list<Backend: UserListerBackendHandler>(
     ldap_info: &LdapInfo,
     ldap_filter: &LdapFilter,
     request_groups: bool,
+    attributes: &[String],
     base: &str,
     backend: &Backend,
     schema: &PublicSchema,
+    hierarchy: &GroupHierarchy,
 ) -> LdapResult<Vec<UserAndGroups>> {
-    let filters = convert_user_filter(ldap_info, ldap_filter, schema)?;
-    debug!(?filters);
-    backend
-        .list_users(Some(filters), request_groups)
+    let filters =
+        hierarchy.expand_user_filter(convert_user_filter(ldap_info, ldap_filter, schema)?);
+    let request_groups = request_groups || posix_defaults_need_groups(ldap_info, attributes);
+    let attributes = get_user_attributes_to_load(ldap_info, attributes, schema);
+    debug!(?filters, ?attributes);
+    let mut users = backend
+        .list_users_with_attributes(Some(filters), request_groups, attributes)
         .await
         .map_err(|e| LdapError {
-            code: LdapResultCode::Other,
+            code: domain_error_code(&e),
             message: format!(r#"Error while searching user "{}": {:#}"#, base, e),
-        })
+        })?;
+    hierarchy.add_inherited_groups(&mut users);
+    Ok(users)
 }
 
-pub fn convert_users_to_ldap_op<'a>(
+/// The entries are only built as the iterator is consumed, so that a large search doesn't hold all
+/// of them in memory at once.
+pub fn convert_users_to_ldap_op(
     users: Vec<UserAndGroups>,
-    attributes: &'a [String],
-    ldap_info: &'a LdapInfo,
-    schema: &'a PublicSchema,
-) -> impl Iterator<Item = LdapOp> + 'a {
+    attributes: &[String],
+    ldap_info: Arc<LdapInfo>,
+    schema: Arc<PublicSchema>,
+) -> impl Iterator<Item = LdapOp> + Send + 'static {
     let expanded_attributes = if users.is_empty() {
         None
     } else {
@@ -339,11 +601,157 @@ pub fn convert_users_to_ldap_op<'a>(
     users.into_iter().map(move |u| {
         LdapOp::SearchResultEntry(make_ldap_search_user_result_entry(
             u.user,
-            &ldap_info.base_dn_str,
+            &ldap_info,
             expanded_attributes.clone().unwrap(),
             u.groups.as_deref(),
-            &ldap_info.ignored_user_attributes,
-            schema,
+            &schema,
         ))
     })
 }
+
+/// A user field that can be written over LDAP.
+enum WritableUserField {
+    Email,
+    DisplayName,
+    Attribute(AttributeName, AttributeType, bool),
+}
+
+fn apply_modification(values: &mut Vec<Vec<u8>>, change: &LdapModify) -> LdapResult<()> {
+    let attribute = &change.modification.atype;
+    let new_values = &change.modification.vals;
+    match change.operation {
+        LdapModifyType::Replace => *values = new_values.clone(),
+        LdapModifyType::Add => {
+            for value in new_values {
+                if values.contains(value) {
+                    return Err(LdapError {
+                        code: LdapResultCode::AttributeOrValueExists,
+                        message: format!(
+                            "The value already exists for the attribute `{}`",
+                            attribute
+                        ),
+                    });
+                }
+                values.push(value.clone());
+            }
+        }
+        LdapModifyType::Delete if new_values.is_empty() => values.clear(),
+        LdapModifyType::Delete => {
+            for value in new_values {
+                match values.iter().position(|v| v == value) {
+                    Some(index) => {
+                        values.remove(index);
+                    }
+                    None => {
+                        return Err(LdapError {
+                            code: LdapResultCode::NoSuchAttribute,
+                            message: format!(
+                                "The value doesn't exist for the attribute `{}`",
+                                attribute
+                            ),
+                        })
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Converts the changes of a modify request, other than the password, to an update of the user.
+/// As LDAP requires, each change applies to the values left by the previous ones.
+pub fn get_user_update(
+    user: &User,
+    changes: &[&LdapModify],
+    ldap_info: &LdapInfo,
+    schema: &PublicSchema,
+    is_admin: bool,
+) -> LdapResult<UpdateUserRequest> {
+    // The values of the changed fields, as they would be returned by a search.
+    let mut fields = BTreeMap::<AttributeName, (WritableUserField, Vec<Vec<u8>>)>::new();
+    for change in changes {
+        let attribute = &change.modification.atype;
+        let field = match map_user_field(
+            &ldap_info.resolve_user_attribute(&AttributeName::from(attribute.as_str())),
+            schema,
+        ) {
+            UserFieldType::PrimaryField(UserColumn::Email) => WritableUserField::Email,
+            UserFieldType::PrimaryField(UserColumn::DisplayName) => WritableUserField::DisplayName,
+            UserFieldType::Attribute(name, typ, is_list) => {
+                WritableUserField::Attribute(name, typ, is_list)
+            }
+            _ => {
+                return Err(LdapError {
+                    code: LdapResultCode::UnwillingToPerform,
+                    message: format!(
+                        r#"Unsupported operation: `{:?}` for `{}`"#,
+                        change.operation, attribute
+                    ),
+                })
+            }
+        };
+        let name = match &field {
+            WritableUserField::Email => AttributeName::from("mail"),
+            WritableUserField::DisplayName => AttributeName::from("display_name"),
+            WritableUserField::Attribute(name, _, _) => name.clone(),
+        };
+        if let Some(attribute_schema) = schema
+            .get_schema()
+            .user_attributes
+            .get_attribute_schema(&name)
+        {
+            if attribute_schema.is_readonly || (!is_admin && !attribute_schema.is_editable) {
+                return Err(LdapError {
+                    code: LdapResultCode::InsufficentAccessRights,
+                    message: format!("The attribute `{}` cannot be modified", attribute),
+                });
+            }
+        }
+        let (_, values) = fields.entry(name.clone()).or_insert_with(|| {
+            let values = get_user_attribute(user, &name, ldap_info, None, schema);
+            (field, values.unwrap_or_default())
+        });
+        apply_modification(values, change)?;
+    }
+    let mut update = UpdateUserRequest {
+        user_id: user.user_id.clone(),
+        ..Default::default()
+    };
+    for (name, (field, values)) in fields {
+        let invalid_value = |message: String| LdapError {
+            code: LdapResultCode::InvalidAttributeSyntax,
+            message: format!("Invalid value for the attribute `{}`: {}", name, message),
+        };
+        match field {
+            WritableUserField::Email => {
+                update.email = Some(
+                    parse_ldap_attribute_value(&values, AttributeType::String, false)
+                        .map_err(invalid_value)?
+                        .unwrap::<String>()
+                        .into(),
+                )
+            }
+            WritableUserField::DisplayName if values.is_empty() => {
+                update.display_name = Some(String::new())
+            }
+            WritableUserField::DisplayName => {
+                update.display_name = Some(
+                    parse_ldap_attribute_value(&values, AttributeType::String, false)
+                        .map_err(invalid_value)?
+                        .unwrap::<String>(),
+                )
+            }
+            WritableUserField::Attribute(name, _, _) if values.is_empty() => {
+                update.delete_attributes.push(name)
+            }
+            WritableUserField::Attribute(name, typ, is_list) => {
+                let value =
+                    parse_ldap_attribute_value(&values, typ, is_list).map_err(invalid_value)?;
+                update
+                    .insert_attributes
+                    .push(AttributeValue { name, value })
+            }
+        }
+    }
+    Ok(update)
+}
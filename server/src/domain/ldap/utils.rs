@@ -1,15 +1,25 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, iter::Peekable, str::Chars};
 
 use chrono::{NaiveDateTime, TimeZone};
-use ldap3_proto::{proto::LdapSubstringFilter, LdapResultCode};
+use ldap3_proto::{proto::LdapSubstringFilter, LdapPartialAttribute, LdapResultCode};
 use tracing::{debug, instrument, warn};
 
-use crate::domain::{
-    handler::SubStringFilter,
-    ldap::error::{LdapError, LdapResult},
-    schema::{PublicSchema, SchemaAttributeExtractor},
-    types::{
-        AttributeName, AttributeType, AttributeValue, GroupName, JpegPhoto, UserColumn, UserId,
+use crate::{
+    domain::{
+        handler::SubStringFilter,
+        ldap::{
+            error::{LdapError, LdapResult},
+            static_entry::StaticEntry,
+        },
+        schema::{PublicSchema, SchemaAttributeExtractor},
+        types::{
+            AttributeName, AttributeType, AttributeValue, GroupName, JpegPhoto, Serialized,
+            UserColumn, UserId,
+        },
+    },
+    infra::configuration::{
+        ComputedAttribute, IntegrationProfile, LdapAttributeLimit, LdapAttributeMapping,
+        LdapAttributeOrder, PosixDefaultsOptions, UserRdnAttribute,
     },
 };
 
@@ -29,35 +39,109 @@ impl From<LdapSubstringFilter> for SubStringFilter {
     }
 }
 
-fn make_dn_pair<I>(mut iter: I) -> LdapResult<(String, String)>
-where
-    I: Iterator<Item = String>,
-{
-    (|| {
-        let pair = (
-            iter.next().ok_or_else(|| "Empty DN element".to_string())?,
-            iter.next().ok_or_else(|| "Missing DN value".to_string())?,
-        );
-        if let Some(e) = iter.next() {
-            Err(format!(
-                r#"Too many elements in distinguished name: "{:?}", "{:?}", "{:?}""#,
-                pair.0, pair.1, e
-            ))
+fn push_char(value: &mut Vec<u8>, c: char) {
+    value.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+}
+
+/// Parses an attribute value of a DN up to the next unescaped comma, without the unescaped spaces
+/// around it.
+fn parse_dn_value(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    let mut value = Vec::new();
+    // The length of the value without its trailing unescaped spaces.
+    let mut significant_len = 0;
+    while chars.next_if_eq(&' ').is_some() {}
+    while let Some(c) = chars.next_if(|&c| c != ',') {
+        if c == '\\' {
+            let escaped = chars
+                .next()
+                .ok_or_else(|| "Unfinished escape sequence in DN".to_string())?;
+            match escaped.to_digit(16) {
+                Some(high) => {
+                    let low = chars
+                        .next()
+                        .and_then(|c| c.to_digit(16))
+                        .ok_or_else(|| format!(r#"Invalid hex escape "\{}" in DN"#, escaped))?;
+                    value.push((high * 16 + low) as u8);
+                }
+                None => push_char(&mut value, escaped),
+            }
+            significant_len = value.len();
         } else {
-            Ok(pair)
+            push_char(&mut value, c);
+            if c != ' ' {
+                significant_len = value.len();
+            }
         }
-    })()
-    .map_err(|s| LdapError {
-        code: LdapResultCode::InvalidDNSyntax,
-        message: s,
-    })
+    }
+    value.truncate(significant_len);
+    String::from_utf8(value).map_err(|_| "Invalid UTF-8 in DN value".to_string())
 }
 
+fn parse_dn_pair(chars: &mut Peekable<Chars>) -> Result<(String, String), String> {
+    let attribute_type: String =
+        std::iter::from_fn(|| chars.next_if(|&c| c != '=' && c != ',')).collect();
+    let attribute_type = attribute_type.trim();
+    if attribute_type.is_empty() {
+        return Err("Empty DN element".to_string());
+    }
+    if chars.next_if_eq(&'=').is_none() {
+        return Err(format!(r#"Missing DN value for "{}""#, attribute_type));
+    }
+    if !attribute_type
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+    {
+        return Err(format!(
+            r#"Invalid attribute type "{}" in DN"#,
+            attribute_type
+        ));
+    }
+    let value = parse_dn_value(chars)?;
+    Ok((attribute_type.to_ascii_lowercase(), value.to_lowercase()))
+}
+
+/// Parses a DN as described in RFC 4514, normalized for comparisons: the attribute types and
+/// values are lowercased, and the spaces around them are ignored.
+///
+/// Since the DNs we return are not escaped, only backslashes and commas need to be escaped in the
+/// values: the other special characters (`+`, `;`, `"`...) are taken literally.
 pub fn parse_distinguished_name(dn: &str) -> LdapResult<Vec<(String, String)>> {
-    assert!(dn == dn.to_ascii_lowercase());
-    dn.split(',')
-        .map(|s| make_dn_pair(s.split('=').map(str::trim).map(String::from)))
-        .collect()
+    let mut chars = dn.chars().peekable();
+    let mut parts = Vec::new();
+    loop {
+        parts.push(parse_dn_pair(&mut chars).map_err(|message| LdapError {
+            code: LdapResultCode::InvalidDNSyntax,
+            message,
+        })?);
+        // Either the end of the DN, or a comma.
+        if chars.next().is_none() {
+            return Ok(parts);
+        }
+    }
+}
+
+fn escape_dn_value(value: &str) -> String {
+    let last = value.chars().count().saturating_sub(1);
+    let mut escaped = String::with_capacity(value.len());
+    for (i, c) in value.chars().enumerate() {
+        if matches!(c, '\\' | ',' | '+' | '"' | ';' | '<' | '>')
+            || (c == ' ' && (i == 0 || i == last))
+            || (c == '#' && i == 0)
+        {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The canonical form of a parsed DN, e.g. "dc=example,dc=com".
+pub fn format_distinguished_name(parts: &[(String, String)]) -> String {
+    parts
+        .iter()
+        .map(|(attribute_type, value)| format!("{}={}", attribute_type, escape_dn_value(value)))
+        .collect::<Vec<_>>()
+        .join(",")
 }
 
 fn get_id_from_distinguished_name(
@@ -143,33 +227,53 @@ pub struct ExpandedAttributes {
     // Lowercase name to original name.
     pub attribute_keys: BTreeMap<AttributeName, String>,
     pub include_custom_attributes: bool,
+    // The attributes listed explicitly, in the order of the request.
+    pub requested_order: Vec<AttributeName>,
 }
 
-#[instrument(skip(all_attribute_keys), level = "debug")]
+/// Expands the selectors of the requested attributes: `*` (or no attribute) for all the user
+/// attributes, `+` for the operational ones, and `1.1` alone for none of them.
+///
+/// The attributes are returned with the case of their first mention in the request.
+#[instrument(skip(all_attribute_keys, operational_attribute_keys), level = "debug")]
 pub fn expand_attribute_wildcards(
     ldap_attributes: &[String],
     all_attribute_keys: &[&'static str],
+    operational_attribute_keys: &[&'static str],
 ) -> ExpandedAttributes {
     let mut include_custom_attributes = false;
-    let mut attributes_out: BTreeMap<_, _> = ldap_attributes
+    let mut attributes_out = BTreeMap::new();
+    let mut requested_order = Vec::new();
+    for s in ldap_attributes
         .iter()
         .filter(|&s| s != "*" && s != "+" && s != "1.1")
-        .map(|s| (AttributeName::from(s), s.to_string()))
-        .collect();
-    attributes_out.extend(
-        if ldap_attributes.iter().any(|x| x == "*") || ldap_attributes.is_empty() {
-            include_custom_attributes = true;
-            all_attribute_keys
-        } else {
-            &[]
+    {
+        let name = AttributeName::from(s);
+        if !attributes_out.contains_key(&name) {
+            requested_order.push(name.clone());
+            attributes_out.insert(name, s.to_string());
         }
-        .iter()
-        .map(|&s| (AttributeName::from(s), s.to_string())),
-    );
+    }
+    if ldap_attributes.iter().any(|x| x == "*") || ldap_attributes.is_empty() {
+        include_custom_attributes = true;
+        for &s in all_attribute_keys {
+            attributes_out
+                .entry(AttributeName::from(s))
+                .or_insert_with(|| s.to_string());
+        }
+    }
+    if ldap_attributes.iter().any(|x| x == "+") {
+        for &s in operational_attribute_keys {
+            attributes_out
+                .entry(AttributeName::from(s))
+                .or_insert_with(|| s.to_string());
+        }
+    }
     debug!(?attributes_out);
     ExpandedAttributes {
         attribute_keys: attributes_out,
         include_custom_attributes,
+        requested_order,
     }
 }
 
@@ -225,15 +329,24 @@ pub fn map_user_field(field: &AttributeName, schema: &PublicSchema) -> UserField
             AttributeType::String,
             false,
         ),
-        "avatar" | "jpegphoto" => UserFieldType::Attribute(
+        "preferredlanguage" | "preferred_language" => UserFieldType::Attribute(
+            AttributeName::from("preferred_language"),
+            AttributeType::String,
+            false,
+        ),
+        "avatar" | "jpegphoto" | "thumbnailphoto" => UserFieldType::Attribute(
             AttributeName::from("avatar"),
             AttributeType::JpegPhoto,
             false,
         ),
-        "creationdate" | "createtimestamp" | "modifytimestamp" | "creation_date" => {
+        "creationdate" | "createtimestamp" | "creation_date" => {
             UserFieldType::PrimaryField(UserColumn::CreationDate)
         }
+        "modifytimestamp" | "modified_date" => {
+            UserFieldType::PrimaryField(UserColumn::ModifiedDate)
+        }
         "entryuuid" | "uuid" => UserFieldType::PrimaryField(UserColumn::Uuid),
+        "externalid" | "external_id" => UserFieldType::PrimaryField(UserColumn::ExternalId),
         _ => schema
             .get_schema()
             .user_attributes
@@ -247,6 +360,7 @@ pub enum GroupFieldType {
     NoMatch,
     DisplayName,
     CreationDate,
+    ModifiedDate,
     ObjectClass,
     Dn,
     // Like Dn, but returned as part of the attributes.
@@ -262,9 +376,8 @@ pub fn map_group_field(field: &AttributeName, schema: &PublicSchema) -> GroupFie
         "entrydn" => GroupFieldType::EntryDn,
         "objectclass" => GroupFieldType::ObjectClass,
         "cn" | "displayname" | "uid" | "display_name" | "id" => GroupFieldType::DisplayName,
-        "creationdate" | "createtimestamp" | "modifytimestamp" | "creation_date" => {
-            GroupFieldType::CreationDate
-        }
+        "creationdate" | "createtimestamp" | "creation_date" => GroupFieldType::CreationDate,
+        "modifytimestamp" | "modified_date" => GroupFieldType::ModifiedDate,
         "member" | "uniquemember" => GroupFieldType::Member,
         "entryuuid" | "uuid" => GroupFieldType::Uuid,
         _ => schema
@@ -276,11 +389,130 @@ pub fn map_group_field(field: &AttributeName, schema: &PublicSchema) -> GroupFie
     }
 }
 
+#[derive(Clone)]
 pub struct LdapInfo {
     pub base_dn: Vec<(String, String)>,
     pub base_dn_str: String,
     pub ignored_user_attributes: Vec<AttributeName>,
     pub ignored_group_attributes: Vec<AttributeName>,
+    pub posix_defaults: PosixDefaultsOptions,
+    pub user_rdn: UserRdnAttribute,
+    /// The integration profile of the bound service account, if any.
+    pub profile: IntegrationProfile,
+    pub attribute_limits: Vec<LdapAttributeLimit>,
+    pub attribute_mappings: Vec<LdapAttributeMapping>,
+    pub computed_user_attributes: Vec<ComputedAttribute>,
+    pub static_entries: Vec<StaticEntry>,
+    pub active_directory_compatibility: bool,
+    pub attribute_order: LdapAttributeOrder,
+    /// Explains in the logs why the searches that found nothing could not match.
+    pub filter_debug: bool,
+}
+
+impl LdapInfo {
+    pub fn effective_user_rdn(&self) -> UserRdnAttribute {
+        self.profile.user_rdn.unwrap_or(self.user_rdn)
+    }
+
+    pub fn user_dn(&self, user_id: &UserId) -> String {
+        format!(
+            "{}={},ou=people,{}",
+            self.effective_user_rdn().as_str(),
+            user_id,
+            self.base_dn_str
+        )
+    }
+
+    fn user_attribute_mappings(&self) -> impl Iterator<Item = &LdapAttributeMapping> {
+        self.attribute_mappings
+            .iter()
+            .filter(|m| m.applies_to_users())
+    }
+
+    fn group_attribute_mappings(&self) -> impl Iterator<Item = &LdapAttributeMapping> {
+        self.attribute_mappings
+            .iter()
+            .filter(|m| m.applies_to_groups())
+    }
+
+    /// The attribute to read when the client asks for `attribute` on a user: the configured
+    /// mappings come first, then the aliases of the integration profile.
+    pub fn resolve_user_attribute(&self, attribute: &AttributeName) -> AttributeName {
+        self.user_attribute_mappings()
+            .find(|m| &m.attribute_name() == attribute)
+            .map(|m| m.attribute.clone())
+            .unwrap_or_else(|| self.profile.resolve_user_attribute(attribute).clone())
+    }
+
+    /// The attribute to read when the client asks for `attribute` on a group.
+    pub fn resolve_group_attribute(&self, attribute: &AttributeName) -> AttributeName {
+        self.group_attribute_mappings()
+            .find(|m| &m.attribute_name() == attribute)
+            .map(|m| m.attribute.clone())
+            .unwrap_or_else(|| attribute.clone())
+    }
+
+    /// Whether the attribute was renamed, and is only returned under its new name.
+    pub fn is_renamed_user_attribute(&self, attribute: &AttributeName) -> bool {
+        self.user_attribute_mappings()
+            .any(|m| m.rename && &m.attribute == attribute)
+    }
+
+    pub fn is_renamed_group_attribute(&self, attribute: &AttributeName) -> bool {
+        self.group_attribute_mappings()
+            .any(|m| m.rename && &m.attribute == attribute)
+    }
+
+    /// Adds the names of the mappings to the attributes returned with "*".
+    pub fn add_user_attribute_mappings(
+        &self,
+        attribute_keys: &mut BTreeMap<AttributeName, String>,
+    ) {
+        for mapping in self.user_attribute_mappings() {
+            attribute_keys
+                .entry(mapping.attribute_name())
+                .or_insert_with(|| mapping.name.clone());
+        }
+    }
+
+    pub fn add_group_attribute_mappings(
+        &self,
+        attribute_keys: &mut BTreeMap<AttributeName, String>,
+    ) {
+        for mapping in self.group_attribute_mappings() {
+            attribute_keys
+                .entry(mapping.attribute_name())
+                .or_insert_with(|| mapping.name.clone());
+        }
+    }
+
+    /// Sorts the attributes of an entry, which come in alphabetical order.
+    pub fn sort_entry_attributes(
+        &self,
+        requested_order: &[AttributeName],
+        attributes: &mut [LdapPartialAttribute],
+    ) {
+        if self.attribute_order == LdapAttributeOrder::Requested {
+            // The sort is stable: the other attributes stay in alphabetical order.
+            attributes.sort_by_key(|attribute| {
+                let name = AttributeName::from(attribute.atype.as_str());
+                requested_order
+                    .iter()
+                    .position(|requested| requested == &name)
+                    .unwrap_or(usize::MAX)
+            });
+        }
+    }
+
+    /// The domain of the `userPrincipalName`, made of the "dc" components of the base DN.
+    pub fn active_directory_domain(&self) -> String {
+        self.base_dn
+            .iter()
+            .filter(|(key, _)| key == "dc")
+            .map(|(_, value)| value.as_str())
+            .collect::<Vec<_>>()
+            .join(".")
+    }
 }
 
 pub fn get_custom_attribute<Extractor: SchemaAttributeExtractor>(
@@ -342,3 +574,91 @@ pub fn get_custom_attribute<Extractor: SchemaAttributeExtractor>(
                 })
         })
 }
+
+/// Converts the values of an attribute written over LDAP, the inverse of `get_custom_attribute`.
+/// The dates can also be given as GeneralizedTime.
+pub fn parse_ldap_attribute_value(
+    values: &[Vec<u8>],
+    attribute_type: AttributeType,
+    is_list: bool,
+) -> Result<Serialized, String> {
+    if !is_list && values.len() != 1 {
+        return Err("expected a single value".to_string());
+    }
+    fn serialize<T: serde::Serialize>(values: Vec<T>, is_list: bool) -> Serialized {
+        if is_list {
+            Serialized::from(&values)
+        } else {
+            Serialized::from(&values[0])
+        }
+    }
+    let decode_string = |value: &Vec<u8>| {
+        String::from_utf8(value.clone()).map_err(|_| "invalid UTF-8 value".to_string())
+    };
+    let parse_int = |value: &Vec<u8>| {
+        let value = decode_string(value)?;
+        value
+            .parse::<i64>()
+            .map_err(|_| format!("invalid integer {}", value))
+    };
+    let parse_date = |value: &Vec<u8>| {
+        let value = decode_string(value)?;
+        parse_ldap_timestamp(&value).ok_or_else(|| format!("invalid date {}", value))
+    };
+    let parse_photo = |value: &Vec<u8>| {
+        JpegPhoto::try_from(value.as_slice()).map_err(|e| format!("invalid JPEG photo: {:#}", e))
+    };
+    Ok(match attribute_type {
+        AttributeType::String => serialize(
+            values
+                .iter()
+                .map(decode_string)
+                .collect::<Result<Vec<_>, _>>()?,
+            is_list,
+        ),
+        AttributeType::Integer => serialize(
+            values
+                .iter()
+                .map(parse_int)
+                .collect::<Result<Vec<_>, _>>()?,
+            is_list,
+        ),
+        AttributeType::DateTime => serialize(
+            values
+                .iter()
+                .map(parse_date)
+                .collect::<Result<Vec<_>, _>>()?,
+            is_list,
+        ),
+        AttributeType::JpegPhoto => serialize(
+            values
+                .iter()
+                .map(parse_photo)
+                .collect::<Result<Vec<_>, _>>()?,
+            is_list,
+        ),
+    })
+}
+
+/// Parses a timestamp used in a filter: either a GeneralizedTime, e.g. `20240131235959Z`, or
+/// the RFC 3339 format that the timestamps are served in.
+pub fn parse_ldap_timestamp(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, "%Y%m%d%H%M%S%.fZ")
+        .ok()
+        .or_else(|| {
+            chrono::DateTime::parse_from_rfc3339(value)
+                .ok()
+                .map(|date| date.naive_utc())
+        })
+}
+
+/// Whether the attribute holds a password or password policy state, which the read-only
+/// accounts are not allowed to see.
+pub fn is_password_attribute(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    name == "userpassword"
+        || name == "authpassword"
+        || name == "sambantpassword"
+        || name.starts_with("pwd")
+        || name.starts_with("shadow")
+}
@@ -0,0 +1,124 @@
+//! The preferred language and timezone of the users, stored as hardcoded user attributes.
+
+use crate::domain::{
+    error::{DomainError, Result},
+    types::{AttributeName, AttributeType, AttributeValue, User},
+};
+
+pub const PREFERRED_LANGUAGE_ATTRIBUTE: &str = "preferred_language";
+pub const TIMEZONE_ATTRIBUTE: &str = "timezone";
+
+/// A BCP 47 language tag, e.g. "de" or "pt-BR". Only the shape is checked.
+pub fn is_valid_language_tag(tag: &str) -> bool {
+    let mut subtags = tag.split('-');
+    let primary = subtags.next().unwrap_or_default();
+    (2..=3).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags
+            .all(|s| (1..=8).contains(&s.len()) && s.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// An IANA timezone name, e.g. "Europe/Berlin" or "UTC". Only the shape is checked: the
+/// browsers fall back to their own timezone for unknown names.
+pub fn is_valid_timezone(timezone: &str) -> bool {
+    timezone.len() <= 64
+        && timezone.starts_with(|c: char| c.is_ascii_alphabetic())
+        && timezone.split('/').all(|s| {
+            !s.is_empty()
+                && s.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+'))
+        })
+}
+
+/// Rejects the malformed values of the language and timezone attributes.
+pub fn check_locale_attribute(
+    attribute: &AttributeValue,
+    attribute_type: AttributeType,
+    is_list: bool,
+) -> Result<()> {
+    if attribute_type != AttributeType::String || is_list {
+        // A custom attribute created before they were hardcoded.
+        return Ok(());
+    }
+    let (is_valid, example): (fn(&str) -> bool, _) = match attribute.name.as_str() {
+        PREFERRED_LANGUAGE_ATTRIBUTE => (is_valid_language_tag, "en-US"),
+        TIMEZONE_ATTRIBUTE => (is_valid_timezone, "Europe/Berlin"),
+        _ => return Ok(()),
+    };
+    let value = attribute.value.unwrap::<String>();
+    if is_valid(&value) {
+        Ok(())
+    } else {
+        Err(DomainError::ValidationError(format!(
+            r#"Invalid value for the attribute "{}": "{}", expected something like "{}""#,
+            attribute.name, value, example
+        )))
+    }
+}
+
+fn get_string_attribute(user: &User, name: &str) -> Option<String> {
+    let name = AttributeName::from(name);
+    user.attributes
+        .iter()
+        .find(|a| a.name == name)
+        .map(|a| a.value.unwrap::<String>())
+        .filter(|v| !v.is_empty())
+}
+
+pub fn get_preferred_language(user: &User) -> Option<String> {
+    get_string_attribute(user, PREFERRED_LANGUAGE_ATTRIBUTE)
+}
+
+pub fn get_timezone(user: &User) -> Option<String> {
+    get_string_attribute(user, TIMEZONE_ATTRIBUTE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::Serialized;
+
+    #[test]
+    fn test_is_valid_language_tag() {
+        for tag in ["de", "en-US", "pt-BR", "zh-Hant-TW", "gsw"] {
+            assert!(is_valid_language_tag(tag), "{}", tag);
+        }
+        for tag in ["", "e", "en_US", "en-", "de-DE-verylongsubtag", "français"] {
+            assert!(!is_valid_language_tag(tag), "{}", tag);
+        }
+    }
+
+    #[test]
+    fn test_is_valid_timezone() {
+        for timezone in [
+            "UTC",
+            "Europe/Berlin",
+            "America/Argentina/Buenos_Aires",
+            "Etc/GMT+1",
+        ] {
+            assert!(is_valid_timezone(timezone), "{}", timezone);
+        }
+        for timezone in ["", "/Europe", "Europe/", "Europe Berlin", "+01:00"] {
+            assert!(!is_valid_timezone(timezone), "{}", timezone);
+        }
+    }
+
+    #[test]
+    fn test_check_locale_attribute() {
+        let check = |name: &str, value: &str| {
+            check_locale_attribute(
+                &AttributeValue {
+                    name: name.into(),
+                    value: Serialized::from(value),
+                },
+                AttributeType::String,
+                false,
+            )
+        };
+        assert!(check("timezone", "Europe/Berlin").is_ok());
+        assert!(check("timezone", "Berlin, Germany").is_err());
+        assert!(check("preferred_language", "de-DE").is_ok());
+        assert!(check("preferred_language", "German").is_err());
+        assert!(check("first_name", "German").is_ok());
+    }
+}
@@ -1,15 +1,42 @@
+pub mod access_reviews;
+pub mod account_deletions;
+pub mod attribute_templates;
+pub mod change_events;
+pub mod computed_attributes;
 pub mod deserialize;
 pub mod error;
+pub mod group_rules;
 pub mod handler;
+pub mod identity_links;
+pub mod jwt_sql_tables;
 pub mod ldap;
+pub mod locale;
 pub mod model;
+pub mod nested_groups;
 pub mod opaque_handler;
+pub mod pending_changes;
+pub mod posix_ids;
+pub mod profile_history;
 pub mod schema;
+pub mod search_cache;
+pub mod secret_tokens;
+pub mod sql_access_review_backend_handler;
+pub mod sql_account_deletion_backend_handler;
+pub mod sql_attribute_template_backend_handler;
+pub mod sql_avatar_sync_backend_handler;
 pub mod sql_backend_handler;
+pub mod sql_change_feed_backend_handler;
 pub mod sql_group_backend_handler;
+pub mod sql_group_rule_backend_handler;
+pub mod sql_identity_link_backend_handler;
+pub mod sql_membership_expiry_backend_handler;
 pub mod sql_migrations;
+pub mod sql_nested_group_backend_handler;
 pub mod sql_opaque_handler;
+pub mod sql_pending_change_backend_handler;
+pub mod sql_profile_history_backend_handler;
 pub mod sql_schema_backend_handler;
 pub mod sql_tables;
 pub mod sql_user_backend_handler;
+pub mod ssh_keys;
 pub mod types;
@@ -0,0 +1,24 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::types::{GroupId, UserId};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[sea_orm(table_name = "access_reviews")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub campaign_id: i32,
+    pub group_id: GroupId,
+    pub reviewer: UserId,
+    /// The selector of the token that gives access to the review page without logging in, see
+    /// `secret_tokens`.
+    pub token: String,
+    /// Null for the tokens that couldn't be migrated, which are then invalid.
+    pub verifier_hash: Option<Vec<u8>>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,17 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[sea_orm(table_name = "change_feed")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub sequence: i32,
+    pub change_date: chrono::NaiveDateTime,
+    /// The JSON-serialized `ChangeEvent`.
+    pub event: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -14,6 +14,7 @@ pub struct Model {
     pub lowercase_display_name: String,
     pub creation_date: chrono::NaiveDateTime,
     pub uuid: Uuid,
+    pub modified_date: chrono::NaiveDateTime,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -36,6 +37,7 @@ impl From<Model> for crate::domain::types::Group {
             id: group.group_id,
             display_name: group.display_name,
             creation_date: group.creation_date,
+            modified_date: group.modified_date,
             uuid: group.uuid,
             users: vec![],
             attributes: Vec::new(),
@@ -49,6 +51,7 @@ impl From<Model> for crate::domain::types::GroupDetails {
             group_id: group.group_id,
             display_name: group.display_name,
             creation_date: group.creation_date,
+            modified_date: group.modified_date,
             uuid: group.uuid,
             attributes: Vec::new(),
         }
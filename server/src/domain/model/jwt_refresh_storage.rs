@@ -8,8 +8,10 @@ use crate::domain::types::UserId;
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
 #[sea_orm(table_name = "jwt_refresh_storage")]
 pub struct Model {
+    /// The hash of the selector of the token, see `secret_tokens`.
     #[sea_orm(primary_key, auto_increment = false)]
     pub refresh_token_hash: i64,
+    pub verifier_hash: Vec<u8>,
     pub user_id: UserId,
     pub expiry_date: chrono::NaiveDateTime,
 }
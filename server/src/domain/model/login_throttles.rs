@@ -0,0 +1,21 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[sea_orm(table_name = "login_throttles")]
+pub struct Model {
+    /// The user or the client address, e.g. "user:bob" or "ip:192.0.2.1".
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub key: String,
+    /// The failed logins since the start of the window.
+    pub failures: i32,
+    pub window_start: chrono::NaiveDateTime,
+    pub locked_until: Option<chrono::NaiveDateTime>,
+    /// When the row can be cleaned up: the end of the window or of the lockout.
+    pub expiry_date: chrono::NaiveDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -12,6 +12,8 @@ pub struct Model {
     pub user_id: UserId,
     #[sea_orm(primary_key)]
     pub group_id: GroupId,
+    pub creation_date: Option<chrono::NaiveDateTime>,
+    pub expiry_date: Option<chrono::NaiveDateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -1,18 +1,32 @@
 pub mod prelude;
 
+pub mod access_review_campaigns;
+pub mod access_review_decisions;
+pub mod access_reviews;
+pub mod account_deletions;
+pub mod avatar_syncs;
+pub mod change_feed;
 pub mod groups;
+pub mod identity_links;
 pub mod jwt_refresh_storage;
 pub mod jwt_storage;
+pub mod leader_leases;
+pub mod login_throttles;
 pub mod memberships;
 pub mod password_reset_tokens;
+pub mod pending_changes;
 pub mod users;
+pub mod webhook_deliveries;
 
 pub mod user_attribute_schema;
 pub mod user_attributes;
 pub mod user_object_classes;
+pub mod user_profile_changes;
 
 pub mod group_attribute_schema;
+pub mod group_attribute_templates;
 pub mod group_attributes;
+pub mod group_memberships;
 pub mod group_object_classes;
 
 pub use prelude::*;
@@ -8,8 +8,10 @@ use crate::domain::types::UserId;
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
 #[sea_orm(table_name = "password_reset_tokens")]
 pub struct Model {
+    /// The selector of the token, see `secret_tokens`.
     #[sea_orm(primary_key, auto_increment = false)]
     pub token: String,
+    pub verifier_hash: Vec<u8>,
     pub user_id: UserId,
     pub expiry_date: chrono::NaiveDateTime,
 }
@@ -1,26 +1,54 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.10.3
 
+pub use super::access_review_campaigns::Column as AccessReviewCampaignsColumn;
+pub use super::access_review_campaigns::Entity as AccessReviewCampaigns;
+pub use super::access_review_decisions::Column as AccessReviewDecisionsColumn;
+pub use super::access_review_decisions::Entity as AccessReviewDecisions;
+pub use super::access_reviews::Column as AccessReviewsColumn;
+pub use super::access_reviews::Entity as AccessReviews;
+pub use super::account_deletions::Column as AccountDeletionsColumn;
+pub use super::account_deletions::Entity as AccountDeletions;
+pub use super::avatar_syncs::Column as AvatarSyncsColumn;
+pub use super::avatar_syncs::Entity as AvatarSyncs;
+pub use super::change_feed::Column as ChangeFeedColumn;
+pub use super::change_feed::Entity as ChangeFeed;
 pub use super::group_attribute_schema::Column as GroupAttributeSchemaColumn;
 pub use super::group_attribute_schema::Entity as GroupAttributeSchema;
+pub use super::group_attribute_templates::Column as GroupAttributeTemplatesColumn;
+pub use super::group_attribute_templates::Entity as GroupAttributeTemplates;
 pub use super::group_attributes::Column as GroupAttributesColumn;
 pub use super::group_attributes::Entity as GroupAttributes;
+pub use super::group_memberships::Column as GroupMembershipsColumn;
+pub use super::group_memberships::Entity as GroupMemberships;
 pub use super::group_object_classes::Column as GroupObjectClassesColumn;
 pub use super::group_object_classes::Entity as GroupObjectClasses;
 pub use super::groups::Column as GroupColumn;
 pub use super::groups::Entity as Group;
+pub use super::identity_links::Column as IdentityLinksColumn;
+pub use super::identity_links::Entity as IdentityLinks;
 pub use super::jwt_refresh_storage::Column as JwtRefreshStorageColumn;
 pub use super::jwt_refresh_storage::Entity as JwtRefreshStorage;
 pub use super::jwt_storage::Column as JwtStorageColumn;
 pub use super::jwt_storage::Entity as JwtStorage;
+pub use super::leader_leases::Column as LeaderLeasesColumn;
+pub use super::leader_leases::Entity as LeaderLeases;
+pub use super::login_throttles::Column as LoginThrottlesColumn;
+pub use super::login_throttles::Entity as LoginThrottles;
 pub use super::memberships::Column as MembershipColumn;
 pub use super::memberships::Entity as Membership;
 pub use super::password_reset_tokens::Column as PasswordResetTokensColumn;
 pub use super::password_reset_tokens::Entity as PasswordResetTokens;
+pub use super::pending_changes::Column as PendingChangesColumn;
+pub use super::pending_changes::Entity as PendingChanges;
 pub use super::user_attribute_schema::Column as UserAttributeSchemaColumn;
 pub use super::user_attribute_schema::Entity as UserAttributeSchema;
 pub use super::user_attributes::Column as UserAttributesColumn;
 pub use super::user_attributes::Entity as UserAttributes;
 pub use super::user_object_classes::Column as UserObjectClassesColumn;
 pub use super::user_object_classes::Entity as UserObjectClasses;
+pub use super::user_profile_changes::Column as UserProfileChangesColumn;
+pub use super::user_profile_changes::Entity as UserProfileChanges;
 pub use super::users::Column as UserColumn;
 pub use super::users::Entity as User;
+pub use super::webhook_deliveries::Column as WebhookDeliveriesColumn;
+pub use super::webhook_deliveries::Entity as WebhookDeliveries;
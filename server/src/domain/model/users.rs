@@ -21,6 +21,8 @@ pub struct Model {
     pub totp_secret: Option<String>,
     pub mfa_type: Option<String>,
     pub uuid: Uuid,
+    pub external_id: Option<String>,
+    pub modified_date: chrono::NaiveDateTime,
 }
 
 impl EntityName for Entity {
@@ -29,7 +31,9 @@ impl EntityName for Entity {
     }
 }
 
-#[derive(Copy, Clone, Debug, EnumIter, DeriveColumn, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(
+    Copy, Clone, Debug, EnumIter, DeriveColumn, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
 pub enum Column {
     UserId,
     Email,
@@ -40,6 +44,8 @@ pub enum Column {
     TotpSecret,
     MfaType,
     Uuid,
+    ExternalId,
+    ModifiedDate,
 }
 
 impl ColumnTrait for Column {
@@ -56,6 +62,8 @@ impl ColumnTrait for Column {
             Column::TotpSecret => ColumnType::String(Some(64)),
             Column::MfaType => ColumnType::String(Some(64)),
             Column::Uuid => ColumnType::String(Some(36)),
+            Column::ExternalId => ColumnType::String(Some(255)),
+            Column::ModifiedDate => ColumnType::DateTime,
         }
         .def()
     }
@@ -71,6 +79,8 @@ pub enum Relation {
     JwtStorage,
     #[sea_orm(has_many = "super::password_reset_tokens::Entity")]
     PasswordResetTokens,
+    #[sea_orm(has_one = "super::account_deletions::Entity")]
+    AccountDeletions,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DerivePrimaryKey)]
@@ -110,6 +120,12 @@ impl Related<super::password_reset_tokens::Entity> for Entity {
     }
 }
 
+impl Related<super::account_deletions::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AccountDeletions.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
 
 impl From<Model> for crate::domain::types::User {
@@ -119,7 +135,9 @@ impl From<Model> for crate::domain::types::User {
             email: user.email,
             display_name: user.display_name,
             creation_date: user.creation_date,
+            modified_date: user.modified_date,
             uuid: user.uuid,
+            external_id: user.external_id,
             attributes: Vec::new(),
         }
     }
@@ -0,0 +1,350 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::domain::{
+    handler::{GroupRequestFilter, UserRequestFilter},
+    types::{Group, GroupDetails, GroupId, UserAndGroups, UserId},
+};
+
+/// The groups that grant permissions. They cannot contain other groups: their members are only
+/// added one by one, through the checks (and the four-eyes approval) of the user memberships.
+pub const PERMISSION_GROUPS: [&str; 3] = [
+    "lldap_admin",
+    "lldap_password_manager",
+    "lldap_strict_readonly",
+];
+
+/// The groups that are members of other groups. Only the direct memberships are stored: the
+/// transitive ones are resolved from here when serving the memberships over LDAP.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GroupHierarchy {
+    /// The groups that have a parent or a member group, with their direct members.
+    groups: HashMap<GroupId, Group>,
+    parents: HashMap<GroupId, Vec<GroupId>>,
+    members: HashMap<GroupId, Vec<GroupId>>,
+}
+
+/// All the groups reachable through the links, excluding the group itself. Each group is only
+/// visited once, so that a cycle doesn't loop forever.
+fn reachable(links: &HashMap<GroupId, Vec<GroupId>>, group_id: GroupId) -> BTreeSet<GroupId> {
+    let mut seen = BTreeSet::new();
+    let mut to_visit = vec![group_id];
+    while let Some(id) = to_visit.pop() {
+        for next in links.get(&id).into_iter().flatten() {
+            if seen.insert(*next) {
+                to_visit.push(*next);
+            }
+        }
+    }
+    seen.remove(&group_id);
+    seen
+}
+
+fn to_group_details(group: &Group) -> GroupDetails {
+    GroupDetails {
+        group_id: group.id,
+        display_name: group.display_name.clone(),
+        creation_date: group.creation_date,
+        modified_date: group.modified_date,
+        uuid: group.uuid.clone(),
+        attributes: group.attributes.clone(),
+    }
+}
+
+impl GroupHierarchy {
+    /// The links go from the parent group to its member group. The groups are only needed to
+    /// resolve the members and the names, they can be left out to only walk the hierarchy.
+    pub fn new(groups: Vec<Group>, links: &[(GroupId, GroupId)]) -> Self {
+        let mut parents: HashMap<GroupId, Vec<GroupId>> = HashMap::new();
+        let mut members: HashMap<GroupId, Vec<GroupId>> = HashMap::new();
+        for (parent, member) in links {
+            parents.entry(*member).or_default().push(*parent);
+            members.entry(*parent).or_default().push(*member);
+        }
+        Self {
+            groups: groups.into_iter().map(|g| (g.id, g)).collect(),
+            parents,
+            members,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// The groups that contain the group, directly or through other groups.
+    pub fn ancestors(&self, group_id: GroupId) -> BTreeSet<GroupId> {
+        reachable(&self.parents, group_id)
+    }
+
+    /// The groups that the group contains, directly or through other groups.
+    pub fn descendants(&self, group_id: GroupId) -> BTreeSet<GroupId> {
+        reachable(&self.members, group_id)
+    }
+
+    /// Whether making `member` a member of `parent` would make a group a member of itself.
+    pub fn would_create_cycle(&self, parent: GroupId, member: GroupId) -> bool {
+        parent == member || self.descendants(member).contains(&parent)
+    }
+
+    /// The groups containing the user, through another group.
+    fn inherited_groups_of_user(&self, user_id: &UserId) -> BTreeSet<GroupId> {
+        self.groups
+            .values()
+            .filter(|g| g.users.contains(user_id))
+            .flat_map(|g| self.ancestors(g.id))
+            .collect()
+    }
+
+    /// Makes the membership conditions also match the members of the nested groups.
+    pub fn expand_user_filter(&self, filter: UserRequestFilter) -> UserRequestFilter {
+        if self.is_empty() {
+            return filter;
+        }
+        let with_descendants = |filter, group_id: Option<GroupId>| {
+            let descendants = group_id.map(|id| self.descendants(id)).unwrap_or_default();
+            if descendants.is_empty() {
+                filter
+            } else {
+                UserRequestFilter::Or(
+                    std::iter::once(filter)
+                        .chain(descendants.into_iter().map(UserRequestFilter::MemberOfId))
+                        .collect(),
+                )
+            }
+        };
+        match filter {
+            UserRequestFilter::And(filters) => UserRequestFilter::And(
+                filters
+                    .into_iter()
+                    .map(|f| self.expand_user_filter(f))
+                    .collect(),
+            ),
+            UserRequestFilter::Or(filters) => UserRequestFilter::Or(
+                filters
+                    .into_iter()
+                    .map(|f| self.expand_user_filter(f))
+                    .collect(),
+            ),
+            UserRequestFilter::Not(filter) => {
+                UserRequestFilter::Not(Box::new(self.expand_user_filter(*filter)))
+            }
+            UserRequestFilter::MemberOf(name) => {
+                let group_id = self
+                    .groups
+                    .values()
+                    .find(|g| g.display_name == name)
+                    .map(|g| g.id);
+                with_descendants(UserRequestFilter::MemberOf(name), group_id)
+            }
+            UserRequestFilter::MemberOfId(group_id) => {
+                with_descendants(UserRequestFilter::MemberOfId(group_id), Some(group_id))
+            }
+            filter => filter,
+        }
+    }
+
+    /// Makes the member conditions also match the groups containing the user through another
+    /// group.
+    pub fn expand_group_filter(&self, filter: GroupRequestFilter) -> GroupRequestFilter {
+        if self.is_empty() {
+            return filter;
+        }
+        match filter {
+            GroupRequestFilter::And(filters) => GroupRequestFilter::And(
+                filters
+                    .into_iter()
+                    .map(|f| self.expand_group_filter(f))
+                    .collect(),
+            ),
+            GroupRequestFilter::Or(filters) => GroupRequestFilter::Or(
+                filters
+                    .into_iter()
+                    .map(|f| self.expand_group_filter(f))
+                    .collect(),
+            ),
+            GroupRequestFilter::Not(filter) => {
+                GroupRequestFilter::Not(Box::new(self.expand_group_filter(*filter)))
+            }
+            GroupRequestFilter::Member(user_id) => {
+                let inherited = self.inherited_groups_of_user(&user_id);
+                if inherited.is_empty() {
+                    GroupRequestFilter::Member(user_id)
+                } else {
+                    GroupRequestFilter::Or(
+                        std::iter::once(GroupRequestFilter::Member(user_id))
+                            .chain(inherited.into_iter().map(GroupRequestFilter::GroupId))
+                            .collect(),
+                    )
+                }
+            }
+            filter => filter,
+        }
+    }
+
+    /// Adds the groups that the users are members of through another group, to the users whose
+    /// groups were loaded.
+    pub fn add_inherited_groups(&self, users: &mut [UserAndGroups]) {
+        if self.is_empty() {
+            return;
+        }
+        for groups in users.iter_mut().filter_map(|u| u.groups.as_mut()) {
+            let inherited: BTreeSet<GroupId> = groups
+                .iter()
+                .flat_map(|g| self.ancestors(g.group_id))
+                .collect();
+            for group_id in inherited {
+                if groups.iter().any(|g| g.group_id == group_id) {
+                    continue;
+                }
+                if let Some(group) = self.groups.get(&group_id) {
+                    groups.push(to_group_details(group));
+                }
+            }
+        }
+    }
+
+    /// Adds the members of the nested groups to the members of the groups.
+    pub fn add_nested_members(&self, groups: &mut [Group]) {
+        if self.is_empty() {
+            return;
+        }
+        for group in groups.iter_mut() {
+            let descendants = self.descendants(group.id);
+            if descendants.is_empty() {
+                continue;
+            }
+            let mut users: BTreeSet<UserId> = group.users.drain(..).collect();
+            users.extend(
+                descendants
+                    .iter()
+                    .filter_map(|id| self.groups.get(id))
+                    .flat_map(|g| g.users.iter().cloned()),
+            );
+            group.users = users.into_iter().collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::{GroupName, User, Uuid};
+    use pretty_assertions::assert_eq;
+
+    fn make_group(id: i32, name: &str, users: &[&str]) -> Group {
+        Group {
+            id: GroupId(id),
+            display_name: GroupName::from(name),
+            creation_date: chrono::Utc::now().naive_utc(),
+            modified_date: chrono::Utc::now().naive_utc(),
+            uuid: Uuid::from_name_and_date(name, &chrono::Utc::now().naive_utc()),
+            users: users.iter().map(|u| UserId::new(u)).collect(),
+            attributes: Vec::new(),
+        }
+    }
+
+    // staff contains admins, which contains ops.
+    fn make_hierarchy() -> GroupHierarchy {
+        GroupHierarchy::new(
+            vec![
+                make_group(1, "staff", &["carol"]),
+                make_group(2, "admins", &["alice"]),
+                make_group(3, "ops", &["bob"]),
+            ],
+            &[(GroupId(1), GroupId(2)), (GroupId(2), GroupId(3))],
+        )
+    }
+
+    #[test]
+    fn test_transitive_groups() {
+        let hierarchy = make_hierarchy();
+        assert_eq!(
+            hierarchy.ancestors(GroupId(3)),
+            BTreeSet::from([GroupId(1), GroupId(2)])
+        );
+        assert_eq!(
+            hierarchy.descendants(GroupId(1)),
+            BTreeSet::from([GroupId(2), GroupId(3)])
+        );
+        assert!(hierarchy.would_create_cycle(GroupId(3), GroupId(1)));
+        assert!(hierarchy.would_create_cycle(GroupId(2), GroupId(2)));
+        assert!(!hierarchy.would_create_cycle(GroupId(1), GroupId(3)));
+    }
+
+    #[test]
+    fn test_cycle_terminates() {
+        let hierarchy = GroupHierarchy::new(
+            Vec::new(),
+            &[(GroupId(1), GroupId(2)), (GroupId(2), GroupId(1))],
+        );
+        assert_eq!(
+            hierarchy.ancestors(GroupId(1)),
+            BTreeSet::from([GroupId(2)])
+        );
+        assert_eq!(
+            hierarchy.descendants(GroupId(2)),
+            BTreeSet::from([GroupId(1)])
+        );
+    }
+
+    #[test]
+    fn test_expand_filters() {
+        let hierarchy = make_hierarchy();
+        assert_eq!(
+            hierarchy.expand_user_filter(UserRequestFilter::MemberOf(GroupName::from("Admins"))),
+            UserRequestFilter::Or(vec![
+                UserRequestFilter::MemberOf(GroupName::from("Admins")),
+                UserRequestFilter::MemberOfId(GroupId(3)),
+            ])
+        );
+        assert_eq!(
+            hierarchy.expand_user_filter(UserRequestFilter::MemberOfId(GroupId(3))),
+            UserRequestFilter::MemberOfId(GroupId(3))
+        );
+        assert_eq!(
+            hierarchy.expand_group_filter(GroupRequestFilter::Not(Box::new(
+                GroupRequestFilter::Member(UserId::new("bob"))
+            ))),
+            GroupRequestFilter::Not(Box::new(GroupRequestFilter::Or(vec![
+                GroupRequestFilter::Member(UserId::new("bob")),
+                GroupRequestFilter::GroupId(GroupId(1)),
+                GroupRequestFilter::GroupId(GroupId(2)),
+            ])))
+        );
+    }
+
+    #[test]
+    fn test_add_transitive_memberships() {
+        let hierarchy = make_hierarchy();
+        let ops = hierarchy.groups[&GroupId(3)].clone();
+        let mut users = vec![UserAndGroups {
+            user: User {
+                user_id: UserId::new("bob"),
+                ..Default::default()
+            },
+            groups: Some(vec![to_group_details(&ops)]),
+        }];
+        hierarchy.add_inherited_groups(&mut users);
+        assert_eq!(
+            users[0]
+                .groups
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|g| g.group_id)
+                .collect::<Vec<_>>(),
+            vec![GroupId(3), GroupId(1), GroupId(2)]
+        );
+        let mut groups = vec![hierarchy.groups[&GroupId(1)].clone(), ops];
+        hierarchy.add_nested_members(&mut groups);
+        assert_eq!(
+            groups[0].users,
+            vec![
+                UserId::new("alice"),
+                UserId::new("bob"),
+                UserId::new("carol")
+            ]
+        );
+        assert_eq!(groups[1].users, vec![UserId::new("bob")]);
+    }
+}
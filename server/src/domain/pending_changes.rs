@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+use crate::domain::types::{GroupId, UserId};
+
+/// A modification that, in four-eyes mode, needs to be approved by a second admin.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SensitiveChange {
+    DeleteUser(UserId),
+    AddUserToGroup {
+        user_id: UserId,
+        group_id: GroupId,
+    },
+    RemoveUserFromGroup {
+        user_id: UserId,
+        group_id: GroupId,
+    },
+    SetMembershipExpiry {
+        user_id: UserId,
+        group_id: GroupId,
+        expiry_date: Option<chrono::NaiveDateTime>,
+    },
+}
+
+impl SensitiveChange {
+    /// Whether, in four-eyes mode, the change has to be approved by a second admin.
+    pub fn needs_approval(&self) -> bool {
+        match self {
+            SensitiveChange::DeleteUser(_) => true,
+            SensitiveChange::AddUserToGroup { group_id, .. }
+            | SensitiveChange::RemoveUserFromGroup { group_id, .. }
+            | SensitiveChange::SetMembershipExpiry { group_id, .. } => *group_id == GroupId(1),
+        }
+    }
+}
+
+/// A sensitive change waiting for approval.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingChange {
+    pub id: i32,
+    pub requested_by: UserId,
+    pub request_date: chrono::NaiveDateTime,
+    pub change: SensitiveChange,
+}
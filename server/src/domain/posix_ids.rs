@@ -0,0 +1,235 @@
+//! The numeric POSIX IDs (`uidNumber`, `gidNumber`), stored as hardcoded attributes and
+//! allocated by the server: an ID never changes once assigned.
+
+use crate::domain::{
+    error::Result,
+    handler::{AttributeList, ReadSchemaBackendHandler},
+    model,
+    sql_backend_handler::SqlBackendHandler,
+    sql_tables::DbConnection,
+    types::{AttributeName, AttributeType, GroupId, Serialized, UserId},
+};
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder, Set, TransactionTrait,
+};
+use std::collections::HashSet;
+use tracing::{info, instrument};
+
+pub const UID_NUMBER_ATTRIBUTE: &str = "uidnumber";
+pub const GID_NUMBER_ATTRIBUTE: &str = "gidnumber";
+
+/// Whether the IDs are allocated: not if a custom attribute with the same name, but another
+/// type, predates them.
+pub fn is_allocated(attributes: &AttributeList, name: &str) -> bool {
+    attributes.get_attribute_type(&AttributeName::from(name))
+        == Some((AttributeType::Integer, false))
+}
+
+fn next_id(values: impl Iterator<Item = Serialized>, start: i64) -> i64 {
+    values
+        .map(|v| v.unwrap::<i64>() + 1)
+        .max()
+        .unwrap_or(start)
+        .max(start)
+}
+
+/// The smallest `uidNumber` above all the assigned ones, and at least `start`.
+pub async fn next_uid_number(connection: &impl ConnectionTrait, start: i64) -> Result<i64> {
+    let values = model::UserAttributes::find()
+        .filter(model::UserAttributesColumn::AttributeName.eq(UID_NUMBER_ATTRIBUTE))
+        .all(connection)
+        .await?;
+    Ok(next_id(values.into_iter().map(|a| a.value), start))
+}
+
+/// The smallest `gidNumber` above all the assigned ones, and at least `start`.
+pub async fn next_gid_number(connection: &impl ConnectionTrait, start: i64) -> Result<i64> {
+    let values = model::GroupAttributes::find()
+        .filter(model::GroupAttributesColumn::AttributeName.eq(GID_NUMBER_ATTRIBUTE))
+        .all(connection)
+        .await?;
+    Ok(next_id(values.into_iter().map(|a| a.value), start))
+}
+
+async fn assign_uid_numbers(connection: &DbConnection, start: i64) -> Result<usize> {
+    let transaction = connection.begin().await?;
+    let assigned: HashSet<UserId> = model::UserAttributes::find()
+        .filter(model::UserAttributesColumn::AttributeName.eq(UID_NUMBER_ATTRIBUTE))
+        .all(&transaction)
+        .await?
+        .into_iter()
+        .map(|a| a.user_id)
+        .collect();
+    let users = model::User::find()
+        .order_by_asc(model::UserColumn::CreationDate)
+        .all(&transaction)
+        .await?;
+    let mut next = next_uid_number(&transaction, start).await?;
+    let mut count = 0;
+    for user_id in users
+        .into_iter()
+        .map(|u| u.user_id)
+        .filter(|u| !assigned.contains(u))
+    {
+        model::UserAttributes::insert(model::user_attributes::ActiveModel {
+            user_id: Set(user_id),
+            attribute_name: Set(UID_NUMBER_ATTRIBUTE.into()),
+            value: Set(Serialized::from(&next)),
+        })
+        .exec(&transaction)
+        .await?;
+        next += 1;
+        count += 1;
+    }
+    transaction.commit().await?;
+    Ok(count)
+}
+
+async fn assign_gid_numbers(connection: &DbConnection, start: i64) -> Result<usize> {
+    let transaction = connection.begin().await?;
+    let assigned: HashSet<GroupId> = model::GroupAttributes::find()
+        .filter(model::GroupAttributesColumn::AttributeName.eq(GID_NUMBER_ATTRIBUTE))
+        .all(&transaction)
+        .await?
+        .into_iter()
+        .map(|a| a.group_id)
+        .collect();
+    let groups = model::Group::find()
+        .order_by_asc(model::GroupColumn::GroupId)
+        .all(&transaction)
+        .await?;
+    let mut next = next_gid_number(&transaction, start).await?;
+    let mut count = 0;
+    for group_id in groups
+        .into_iter()
+        .map(|g| g.group_id)
+        .filter(|g| !assigned.contains(g))
+    {
+        model::GroupAttributes::insert(model::group_attributes::ActiveModel {
+            group_id: Set(group_id),
+            attribute_name: Set(GID_NUMBER_ATTRIBUTE.into()),
+            value: Set(Serialized::from(&next)),
+        })
+        .exec(&transaction)
+        .await?;
+        next += 1;
+        count += 1;
+    }
+    transaction.commit().await?;
+    Ok(count)
+}
+
+impl SqlBackendHandler {
+    /// Gives an ID to the users and groups created before the IDs were allocated, in the order
+    /// they were created.
+    #[instrument(skip(self), level = "debug", err)]
+    pub async fn assign_missing_posix_ids(&self) -> Result<()> {
+        let schema = self.get_schema().await?;
+        let options = &self.config.posix_defaults;
+        if is_allocated(&schema.user_attributes, UID_NUMBER_ATTRIBUTE) {
+            let count = assign_uid_numbers(&self.sql_pool, options.uid_number_start).await?;
+            if count > 0 {
+                info!("Assigned a uidNumber to {} users", count);
+            }
+        }
+        if is_allocated(&schema.group_attributes, GID_NUMBER_ATTRIBUTE) {
+            let count = assign_gid_numbers(&self.sql_pool, options.gid_number_start).await?;
+            if count > 0 {
+                info!("Assigned a gidNumber to {} groups", count);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{CreateGroupRequest, CreateUserRequest, GroupBackendHandler, UserBackendHandler},
+        sql_backend_handler::tests::{get_default_config, get_initialized_db, insert_user},
+        types::AttributeValue,
+    };
+    use pretty_assertions::assert_eq;
+
+    async fn get_uid_number(handler: &SqlBackendHandler, user_id: &str) -> Option<i64> {
+        model::UserAttributes::find_by_id((UserId::new(user_id), UID_NUMBER_ATTRIBUTE.into()))
+            .one(&handler.sql_pool)
+            .await
+            .unwrap()
+            .map(|a| a.value.unwrap::<i64>())
+    }
+
+    async fn get_gid_number(handler: &SqlBackendHandler, group_id: GroupId) -> Option<i64> {
+        model::GroupAttributes::find_by_id((group_id, GID_NUMBER_ATTRIBUTE.into()))
+            .one(&handler.sql_pool)
+            .await
+            .unwrap()
+            .map(|a| a.value.unwrap::<i64>())
+    }
+
+    #[tokio::test]
+    async fn test_allocate_ids_on_creation() {
+        let handler = SqlBackendHandler::new(get_default_config(), get_initialized_db().await);
+        insert_user(&handler, "bob", "bob00000").await;
+        insert_user(&handler, "patrick", "pass").await;
+        handler
+            .create_user(CreateUserRequest {
+                user_id: UserId::new("jim"),
+                email: "jim@example.com".into(),
+                attributes: vec![AttributeValue {
+                    name: UID_NUMBER_ATTRIBUTE.into(),
+                    value: Serialized::from(&20000i64),
+                }],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        insert_user(&handler, "john", "pass").await;
+        assert_eq!(get_uid_number(&handler, "bob").await, Some(10000));
+        assert_eq!(get_uid_number(&handler, "patrick").await, Some(10001));
+        assert_eq!(get_uid_number(&handler, "jim").await, Some(20000));
+        assert_eq!(get_uid_number(&handler, "john").await, Some(20001));
+
+        let group = handler
+            .create_group(CreateGroupRequest {
+                display_name: "admins".into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(get_gid_number(&handler, group).await, Some(10000));
+    }
+
+    #[tokio::test]
+    async fn test_assign_missing_ids() {
+        let handler = SqlBackendHandler::new(get_default_config(), get_initialized_db().await);
+        insert_user(&handler, "bob", "bob00000").await;
+        insert_user(&handler, "patrick", "pass").await;
+        let group = handler
+            .create_group(CreateGroupRequest {
+                display_name: "admins".into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        // As if they were created before the IDs were allocated.
+        model::UserAttributes::delete_by_id((UserId::new("bob"), UID_NUMBER_ATTRIBUTE.into()))
+            .exec(&handler.sql_pool)
+            .await
+            .unwrap();
+        model::GroupAttributes::delete_many()
+            .exec(&handler.sql_pool)
+            .await
+            .unwrap();
+
+        handler.assign_missing_posix_ids().await.unwrap();
+        assert_eq!(get_uid_number(&handler, "bob").await, Some(10002));
+        assert_eq!(get_uid_number(&handler, "patrick").await, Some(10001));
+        assert_eq!(get_gid_number(&handler, group).await, Some(10000));
+
+        // Nothing changes the second time.
+        handler.assign_missing_posix_ids().await.unwrap();
+        assert_eq!(get_uid_number(&handler, "bob").await, Some(10002));
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::BTreeSet;
+
+use chrono::{NaiveDateTime, TimeZone};
+
+use crate::domain::{
+    handler::AttributeList,
+    types::{AttributeName, AttributeType, Serialized, User, UserId},
+};
+
+/// A change of one field of a user profile, kept in the history of the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileChange {
+    pub id: i32,
+    pub user_id: UserId,
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    /// The user who made the change, `None` if it was made by LLDAP itself, e.g. by a group rule.
+    pub actor: Option<UserId>,
+    pub change_date: NaiveDateTime,
+}
+
+/// A field that differs between two versions of a profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+/// The value of an attribute as shown in the history. The photos are not copied in the history,
+/// only the fact that they changed.
+fn render_attribute_value(
+    value: &Serialized,
+    attribute_type: AttributeType,
+    is_list: bool,
+) -> String {
+    let convert_date = |date: NaiveDateTime| chrono::Utc.from_utc_datetime(&date).to_rfc3339();
+    match (attribute_type, is_list) {
+        (AttributeType::String, false) => value.unwrap::<String>(),
+        (AttributeType::Integer, false) => value.unwrap::<i64>().to_string(),
+        (AttributeType::DateTime, false) => convert_date(value.unwrap::<NaiveDateTime>()),
+        (AttributeType::JpegPhoto, _) => "<photo>".to_owned(),
+        (AttributeType::String, true) => value.unwrap::<Vec<String>>().join(", "),
+        (AttributeType::Integer, true) => value
+            .unwrap::<Vec<i64>>()
+            .iter()
+            .map(i64::to_string)
+            .collect::<Vec<_>>()
+            .join(", "),
+        (AttributeType::DateTime, true) => value
+            .unwrap::<Vec<NaiveDateTime>>()
+            .into_iter()
+            .map(convert_date)
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+/// The fields that changed between the two versions of the user, in a stable order: the email,
+/// the display name, then the attributes by name.
+pub fn diff_profiles(before: &User, after: &User, attributes: &AttributeList) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    let field_change = |field: &str, old_value, new_value| FieldChange {
+        field: field.to_owned(),
+        old_value,
+        new_value,
+    };
+    if before.email != after.email {
+        changes.push(field_change(
+            "email",
+            Some(before.email.to_string()),
+            Some(after.email.to_string()),
+        ));
+    }
+    if before.display_name != after.display_name {
+        changes.push(field_change(
+            "display_name",
+            before.display_name.clone(),
+            after.display_name.clone(),
+        ));
+    }
+    let names: BTreeSet<&AttributeName> = before
+        .attributes
+        .iter()
+        .chain(after.attributes.iter())
+        .map(|a| &a.name)
+        .collect();
+    for name in names {
+        let (attribute_type, is_list) = match attributes.get_attribute_type(name) {
+            Some(t) => t,
+            None => continue,
+        };
+        let get_value = |user: &User| {
+            user.attributes
+                .iter()
+                .find(|a| &a.name == name)
+                .map(|a| &a.value)
+        };
+        let (old_value, new_value) = (get_value(before), get_value(after));
+        if old_value != new_value {
+            let render = |value: Option<&Serialized>| {
+                value.map(|v| render_attribute_value(v, attribute_type, is_list))
+            };
+            changes.push(field_change(
+                name.as_str(),
+                render(old_value),
+                render(new_value),
+            ));
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{handler::AttributeSchema, types::AttributeValue};
+    use pretty_assertions::assert_eq;
+
+    fn make_schema() -> AttributeList {
+        let attribute = |name: &str, attribute_type, is_list| AttributeSchema {
+            name: name.into(),
+            attribute_type,
+            is_list,
+            is_visible: true,
+            is_editable: true,
+            is_hardcoded: false,
+            is_readonly: false,
+        };
+        AttributeList {
+            attributes: vec![
+                attribute("first_name", AttributeType::String, false),
+                attribute("mail_aliases", AttributeType::String, true),
+                attribute("shoe_size", AttributeType::Integer, false),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_diff_profiles() {
+        let before = User {
+            user_id: UserId::new("bob"),
+            email: "bob@example.com".into(),
+            display_name: Some("Bob".to_owned()),
+            attributes: vec![
+                AttributeValue {
+                    name: "first_name".into(),
+                    value: Serialized::from("Bob"),
+                },
+                AttributeValue {
+                    name: "shoe_size".into(),
+                    value: Serialized::from(&42i64),
+                },
+            ],
+            ..Default::default()
+        };
+        let after = User {
+            email: "robert@example.com".into(),
+            attributes: vec![
+                AttributeValue {
+                    name: "first_name".into(),
+                    value: Serialized::from("Bob"),
+                },
+                AttributeValue {
+                    name: "mail_aliases".into(),
+                    value: Serialized::from(&vec!["b@example.com", "r@example.com"]),
+                },
+            ],
+            ..before.clone()
+        };
+        assert_eq!(
+            diff_profiles(&before, &after, &make_schema()),
+            vec![
+                FieldChange {
+                    field: "email".to_owned(),
+                    old_value: Some("bob@example.com".to_owned()),
+                    new_value: Some("robert@example.com".to_owned()),
+                },
+                FieldChange {
+                    field: "mail_aliases".to_owned(),
+                    old_value: None,
+                    new_value: Some("b@example.com, r@example.com".to_owned()),
+                },
+                FieldChange {
+                    field: "shoe_size".to_owned(),
+                    old_value: Some("42".to_owned()),
+                    new_value: None,
+                },
+            ]
+        );
+        assert_eq!(diff_profiles(&before, &before, &make_schema()), Vec::new());
+    }
+}
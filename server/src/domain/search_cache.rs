@@ -0,0 +1,214 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    domain::{
+        handler::{GroupRequestFilter, UserRequestFilter},
+        types::{AttributeName, Group, UserAndGroups},
+    },
+    infra::configuration::CacheOptions,
+};
+
+struct CacheEntry<T> {
+    inserted: Instant,
+    value: T,
+}
+
+struct TtlMap<K, V> {
+    entries: HashMap<K, CacheEntry<V>>,
+}
+
+impl<K: Eq + Hash, V: Clone> TtlMap<K, V> {
+    fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+
+    fn get(&self, key: &K, ttl: Duration) -> Option<V> {
+        self.entries
+            .get(key)
+            .filter(|e| e.inserted.elapsed() < ttl)
+            .map(|e| e.value.clone())
+    }
+
+    fn insert(&mut self, key: K, value: V, ttl: Duration, max_entries: usize) {
+        if self.entries.len() >= max_entries {
+            self.entries.retain(|_, e| e.inserted.elapsed() < ttl);
+        }
+        if self.entries.len() >= max_entries {
+            // Still full of fresh entries: start over rather than tracking usage.
+            self.entries.clear();
+        }
+        self.entries.insert(
+            key,
+            CacheEntry {
+                inserted: Instant::now(),
+                value,
+            },
+        );
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// The parameters of a user search, see `UserListerBackendHandler::list_users_with_attributes`.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub struct UserSearchKey {
+    pub filters: Option<UserRequestFilter>,
+    pub get_groups: bool,
+    pub attributes: Option<Vec<AttributeName>>,
+}
+
+/// In-process cache of the results of `list_users` and `list_groups`, keyed by the request
+/// filter. Entries expire after the configured TTL, and the whole cache is dropped whenever the
+/// directory is modified.
+pub struct SearchCache {
+    ttl: Duration,
+    max_entries: usize,
+    // Bumped on every invalidation, so that results read from the DB before a write are not
+    // inserted after it.
+    generation: AtomicU64,
+    users: RwLock<TtlMap<UserSearchKey, Vec<UserAndGroups>>>,
+    groups: RwLock<TtlMap<Option<GroupRequestFilter>, Vec<Group>>>,
+}
+
+impl SearchCache {
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries,
+            generation: AtomicU64::new(0),
+            users: RwLock::new(TtlMap::new()),
+            groups: RwLock::new(TtlMap::new()),
+        }
+    }
+
+    pub fn from_options(options: &CacheOptions) -> Option<Self> {
+        options.enabled.then(|| {
+            Self::new(
+                Duration::from_secs(options.ttl_seconds),
+                options.max_entries,
+            )
+        })
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    pub fn get_users(&self, key: &UserSearchKey) -> Option<Vec<UserAndGroups>> {
+        self.users.read().unwrap().get(key, self.ttl)
+    }
+
+    pub fn insert_users(&self, generation: u64, key: UserSearchKey, users: Vec<UserAndGroups>) {
+        let mut map = self.users.write().unwrap();
+        if generation == self.generation() {
+            map.insert(key, users, self.ttl, self.max_entries);
+        }
+    }
+
+    pub fn get_groups(&self, filters: &Option<GroupRequestFilter>) -> Option<Vec<Group>> {
+        self.groups.read().unwrap().get(filters, self.ttl)
+    }
+
+    pub fn insert_groups(
+        &self,
+        generation: u64,
+        filters: Option<GroupRequestFilter>,
+        groups: Vec<Group>,
+    ) {
+        let mut map = self.groups.write().unwrap();
+        if generation == self.generation() {
+            map.insert(filters, groups, self.ttl, self.max_entries);
+        }
+    }
+
+    pub fn invalidate(&self) {
+        let mut users = self.users.write().unwrap();
+        let mut groups = self.groups.write().unwrap();
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        users.clear();
+        groups.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::{User, UserId};
+    use pretty_assertions::assert_eq;
+
+    fn make_key(filters: Option<UserRequestFilter>) -> UserSearchKey {
+        UserSearchKey {
+            filters,
+            get_groups: true,
+            attributes: None,
+        }
+    }
+
+    fn make_users(name: &str) -> Vec<UserAndGroups> {
+        vec![UserAndGroups {
+            user: User {
+                user_id: UserId::new(name),
+                ..Default::default()
+            },
+            groups: None,
+        }]
+    }
+
+    #[test]
+    fn test_cache_hit_and_invalidate() {
+        let cache = SearchCache::new(Duration::from_secs(60), 10);
+        let key = make_key(Some(UserRequestFilter::UserId(UserId::new("bob"))));
+        assert_eq!(cache.get_users(&key), None);
+        cache.insert_users(cache.generation(), key.clone(), make_users("bob"));
+        assert_eq!(cache.get_users(&key), Some(make_users("bob")));
+        assert_eq!(cache.get_users(&make_key(None)), None);
+        assert_eq!(
+            cache.get_users(&UserSearchKey {
+                get_groups: false,
+                ..key.clone()
+            }),
+            None
+        );
+        cache.invalidate();
+        assert_eq!(cache.get_users(&key), None);
+    }
+
+    #[test]
+    fn test_cache_stale_generation() {
+        let cache = SearchCache::new(Duration::from_secs(60), 10);
+        let generation = cache.generation();
+        cache.invalidate();
+        cache.insert_users(generation, make_key(None), make_users("bob"));
+        assert_eq!(cache.get_users(&make_key(None)), None);
+    }
+
+    #[test]
+    fn test_cache_expiry() {
+        let cache = SearchCache::new(Duration::ZERO, 10);
+        cache.insert_users(cache.generation(), make_key(None), make_users("bob"));
+        assert_eq!(cache.get_users(&make_key(None)), None);
+    }
+
+    #[test]
+    fn test_cache_max_entries() {
+        let cache = SearchCache::new(Duration::from_secs(60), 1);
+        let bob = make_key(Some(UserRequestFilter::UserId(UserId::new("bob"))));
+        let john = make_key(Some(UserRequestFilter::UserId(UserId::new("john"))));
+        cache.insert_users(cache.generation(), bob.clone(), make_users("bob"));
+        cache.insert_users(cache.generation(), john.clone(), make_users("john"));
+        assert_eq!(cache.get_users(&bob), None);
+        assert_eq!(cache.get_users(&john), Some(make_users("john")));
+    }
+}
@@ -0,0 +1,134 @@
+//! The tokens handed out by the server (refresh tokens, and the links sent by email to reset a
+//! password, confirm an account deletion or answer an access review) are never stored as is.
+//!
+//! The first characters of a token are its selector, stored in clear to find the token. Only a
+//! salted HMAC of the rest, the verifier, is stored, and it is checked in constant time: neither a
+//! database dump nor the timing of the checks gives away a usable token.
+
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+
+const TOKEN_LENGTH: usize = 100;
+const SELECTOR_LENGTH: usize = 20;
+const SALT_LENGTH: usize = 16;
+
+pub(crate) fn gen_random_string(len: usize) -> String {
+    use rand::{distributions::Alphanumeric, Rng};
+    // The strings are used as secrets: this needs a cryptographically secure generator.
+    let mut rng = rand::thread_rng();
+    std::iter::repeat(())
+        .map(|()| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(len)
+        .collect()
+}
+
+pub struct SecretToken {
+    /// What is handed out.
+    pub token: String,
+    pub selector: String,
+    /// The salt followed by the HMAC of the verifier.
+    pub hash: Vec<u8>,
+}
+
+impl SecretToken {
+    pub fn generate() -> Self {
+        let token = gen_random_string(TOKEN_LENGTH);
+        let (selector, verifier) = split_token(&token).unwrap();
+        Self {
+            selector: selector.to_owned(),
+            hash: hash_verifier(verifier),
+            token,
+        }
+    }
+}
+
+/// Splits a token into its selector and its verifier.
+pub fn split_token(token: &str) -> Option<(&str, &str)> {
+    if token.len() <= SELECTOR_LENGTH || !token.is_char_boundary(SELECTOR_LENGTH) {
+        return None;
+    }
+    Some(token.split_at(SELECTOR_LENGTH))
+}
+
+fn mac(salt: &[u8], verifier: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(salt).expect("HMAC accepts keys of any size");
+    mac.update(verifier.as_bytes());
+    mac
+}
+
+/// Hashes a verifier with a new random salt.
+pub fn hash_verifier(verifier: &str) -> Vec<u8> {
+    let mut salt = [0; SALT_LENGTH];
+    rand::rngs::OsRng.fill_bytes(&mut salt);
+    let mut hash = salt.to_vec();
+    hash.extend_from_slice(&mac(&salt, verifier).finalize().into_bytes());
+    hash
+}
+
+/// Checks the verifier of a token against the stored hash, in constant time.
+pub fn check_verifier(verifier: &str, hash: &[u8]) -> bool {
+    if hash.len() <= SALT_LENGTH {
+        return false;
+    }
+    let (salt, expected) = hash.split_at(SALT_LENGTH);
+    mac(salt, verifier).verify_slice(expected).is_ok()
+}
+
+/// Compares two secrets without stopping at the first difference: only their length leaks.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && std::hint::black_box(a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y))) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::{assert_eq, assert_ne};
+
+    #[test]
+    fn test_secret_token() {
+        let token = SecretToken::generate();
+        assert_eq!(token.token.len(), TOKEN_LENGTH);
+        let (selector, verifier) = split_token(&token.token).unwrap();
+        assert_eq!(selector, token.selector);
+        assert!(check_verifier(verifier, &token.hash));
+        assert!(!check_verifier(&verifier[1..], &token.hash));
+        assert!(!check_verifier(verifier, &token.hash[..SALT_LENGTH]));
+        assert!(!check_verifier(verifier, &[]));
+        // Nothing of the verifier is stored.
+        assert!(!String::from_utf8_lossy(&token.hash).contains(verifier));
+    }
+
+    #[test]
+    fn test_hash_verifier_is_salted() {
+        let first = hash_verifier("verifier");
+        let second = hash_verifier("verifier");
+        assert_ne!(first, second);
+        assert!(check_verifier("verifier", &first));
+        assert!(check_verifier("verifier", &second));
+    }
+
+    #[test]
+    fn test_split_token() {
+        assert_eq!(split_token(""), None);
+        assert_eq!(split_token(&"a".repeat(SELECTOR_LENGTH)), None);
+        assert_eq!(
+            split_token(&format!("{}b", "a".repeat(SELECTOR_LENGTH))),
+            Some(("a".repeat(SELECTOR_LENGTH).as_str(), "b"))
+        );
+        assert_eq!(
+            split_token(&format!("a{}", "é".repeat(SELECTOR_LENGTH))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(constant_time_eq(b"", b""));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secrets"));
+    }
+}
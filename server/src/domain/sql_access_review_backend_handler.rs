@@ -0,0 +1,442 @@
+use crate::domain::{
+    access_reviews::{AccessReview, AccessReviewDecision, AccessReviewInvitation},
+    change_events::ChangeEvent,
+    error::{DomainError, Result},
+    handler::AccessReviewBackendHandler,
+    model::{self, AccessReviewDecisionsColumn, AccessReviewsColumn, MembershipColumn},
+    pending_changes::SensitiveChange,
+    secret_tokens::{check_verifier, split_token, SecretToken},
+    sql_backend_handler::SqlBackendHandler,
+    types::{GroupId, UserId},
+};
+use async_trait::async_trait;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter,
+    QueryOrder, Set, TransactionTrait,
+};
+use tracing::{info, instrument};
+
+impl From<model::access_review_decisions::Model> for AccessReviewDecision {
+    fn from(model: model::access_review_decisions::Model) -> Self {
+        Self {
+            user_id: model.user_id,
+            keep: model.keep,
+            decision_date: model.decision_date,
+        }
+    }
+}
+
+impl SqlBackendHandler {
+    async fn load_access_review(
+        connection: &impl ConnectionTrait,
+        review: model::access_reviews::Model,
+    ) -> Result<AccessReview> {
+        let campaign = model::AccessReviewCampaigns::find_by_id(review.campaign_id)
+            .one(connection)
+            .await?
+            .ok_or_else(|| {
+                DomainError::InternalError(format!(
+                    "Missing campaign for the access review {}",
+                    review.id
+                ))
+            })?;
+        let group = model::Group::find_by_id(review.group_id)
+            .one(connection)
+            .await?
+            .ok_or_else(|| {
+                DomainError::InternalError(format!(
+                    "Missing group for the access review {}",
+                    review.id
+                ))
+            })?;
+        let decisions = model::AccessReviewDecisions::find()
+            .filter(AccessReviewDecisionsColumn::ReviewId.eq(review.id))
+            .order_by_asc(AccessReviewDecisionsColumn::UserId)
+            .all(connection)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        Ok(AccessReview {
+            id: review.id,
+            campaign_id: campaign.id,
+            campaign_name: campaign.name,
+            created_by: campaign.created_by,
+            creation_date: campaign.creation_date,
+            group_id: review.group_id,
+            group_name: group.display_name,
+            group_uuid: group.uuid,
+            reviewer: review.reviewer,
+            decisions,
+        })
+    }
+
+    async fn find_access_review(&self, token: &str) -> Result<model::access_reviews::Model> {
+        let invalid = || DomainError::EntityNotFound("Invalid access review token".to_owned());
+        let (selector, verifier) = split_token(token).ok_or_else(invalid)?;
+        model::AccessReviews::find()
+            .filter(AccessReviewsColumn::Token.eq(selector))
+            .one(&self.sql_pool)
+            .await?
+            .filter(|r| check_verifier(verifier, r.verifier_hash.as_deref().unwrap_or_default()))
+            .ok_or_else(invalid)
+    }
+}
+
+#[async_trait]
+impl AccessReviewBackendHandler for SqlBackendHandler {
+    #[instrument(skip(self), level = "debug", err)]
+    async fn start_access_review(
+        &self,
+        name: &str,
+        created_by: &UserId,
+        groups: &[(GroupId, UserId)],
+    ) -> Result<Vec<AccessReviewInvitation>> {
+        if groups.is_empty() {
+            return Err(DomainError::ValidationError(
+                "An access review needs at least one group".to_owned(),
+            ));
+        }
+        let campaign_name = name.to_owned();
+        let name = campaign_name.clone();
+        let created_by = created_by.clone();
+        let groups = groups.to_vec();
+        let invitations = self
+            .sql_pool
+            .transaction::<_, Vec<AccessReviewInvitation>, DomainError>(|transaction| {
+                Box::pin(async move {
+                    let campaign = model::access_review_campaigns::ActiveModel {
+                        name: Set(name),
+                        created_by: Set(created_by),
+                        creation_date: Set(chrono::Utc::now().naive_utc()),
+                        ..Default::default()
+                    }
+                    .insert(transaction)
+                    .await?;
+                    let mut invitations = Vec::new();
+                    for (group_id, reviewer) in groups {
+                        let token = SecretToken::generate();
+                        let review = model::access_reviews::ActiveModel {
+                            campaign_id: Set(campaign.id),
+                            group_id: Set(group_id),
+                            reviewer: Set(reviewer),
+                            token: Set(token.selector),
+                            verifier_hash: Set(Some(token.hash)),
+                            ..Default::default()
+                        }
+                        .insert(transaction)
+                        .await?;
+                        let members = model::Membership::find()
+                            .filter(MembershipColumn::GroupId.eq(group_id))
+                            .all(transaction)
+                            .await?;
+                        if !members.is_empty() {
+                            model::AccessReviewDecisions::insert_many(members.into_iter().map(
+                                |m| model::access_review_decisions::ActiveModel {
+                                    review_id: Set(review.id),
+                                    user_id: Set(m.user_id),
+                                    keep: Set(None),
+                                    decision_date: Set(None),
+                                },
+                            ))
+                            .exec(transaction)
+                            .await?;
+                        }
+                        invitations.push(AccessReviewInvitation {
+                            review: Self::load_access_review(transaction, review).await?,
+                            token: token.token,
+                        });
+                    }
+                    Ok(invitations)
+                })
+            })
+            .await?;
+        info!(
+            r#"Started the access review "{}" of {} group(s)"#,
+            campaign_name,
+            invitations.len()
+        );
+        Ok(invitations)
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn list_access_reviews(&self) -> Result<Vec<AccessReview>> {
+        let mut reviews = Vec::new();
+        for review in model::AccessReviews::find()
+            .order_by_desc(AccessReviewsColumn::CampaignId)
+            .order_by_asc(AccessReviewsColumn::Id)
+            .all(&self.sql_pool)
+            .await?
+        {
+            reviews.push(Self::load_access_review(&self.sql_pool, review).await?);
+        }
+        Ok(reviews)
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn get_access_review(&self, token: &str) -> Result<AccessReview> {
+        let review = self.find_access_review(token).await?;
+        Self::load_access_review(&self.sql_pool, review).await
+    }
+
+    #[instrument(skip(self, token), level = "debug", err)]
+    async fn decide_access_review(&self, token: &str, user_id: &UserId, keep: bool) -> Result<()> {
+        let review = self.find_access_review(token).await?;
+        let event = ChangeEvent::AccessReviewDecided {
+            review_id: review.id,
+            group_id: review.group_id,
+            user_id: user_id.clone(),
+            reviewer: review.reviewer.clone(),
+            keep,
+        };
+        let removal = SensitiveChange::RemoveUserFromGroup {
+            user_id: user_id.clone(),
+            group_id: review.group_id,
+        };
+        // A revoked admin membership waits for the approval of a second admin, like the same
+        // change made through the API.
+        let needs_approval = !keep && self.config.four_eyes_approval && removal.needs_approval();
+        let review_id = review.id;
+        let group_id = review.group_id;
+        let reviewer = review.reviewer.clone();
+        let member = user_id.clone();
+        let (events, pending_change) = self
+            .sql_pool
+            .transaction::<_, (Vec<ChangeEvent>, Option<i32>), DomainError>(|transaction| {
+                Box::pin(async move {
+                    // The decisions are final: only an undecided membership is updated.
+                    let res = model::AccessReviewDecisions::update_many()
+                        .col_expr(AccessReviewDecisionsColumn::Keep, Expr::value(keep))
+                        .col_expr(
+                            AccessReviewDecisionsColumn::DecisionDate,
+                            Expr::value(chrono::Utc::now().naive_utc()),
+                        )
+                        .filter(AccessReviewDecisionsColumn::ReviewId.eq(review_id))
+                        .filter(AccessReviewDecisionsColumn::UserId.eq(member.clone()))
+                        .filter(AccessReviewDecisionsColumn::Keep.is_null())
+                        .exec(transaction)
+                        .await?;
+                    if res.rows_affected == 0 {
+                        let decision =
+                            model::AccessReviewDecisions::find_by_id((review_id, member.clone()))
+                                .one(transaction)
+                                .await?;
+                        return Err(match decision {
+                            Some(_) => DomainError::Conflict(format!(
+                                "The membership of '{}' was already reviewed",
+                                member
+                            )),
+                            None => DomainError::EntityNotFound(format!(
+                                "'{}' is not part of the review",
+                                member
+                            )),
+                        });
+                    }
+                    Self::record_change(transaction, &event).await?;
+                    let mut events = vec![event];
+                    if keep {
+                        return Ok((events, None));
+                    }
+                    if needs_approval {
+                        let id =
+                            Self::insert_pending_change(transaction, &reviewer, &removal).await?;
+                        return Ok((events, Some(id)));
+                    }
+                    let removed = ChangeEvent::MembershipRemoved {
+                        user_id: member.clone(),
+                        group_id,
+                    };
+                    match Self::delete_membership(transaction, removed, member, group_id).await {
+                        Ok(removed) => events.extend(removed),
+                        // The member may have left the group since the start of the campaign.
+                        Err(DomainError::EntityNotFound(_)) => {}
+                        Err(e) => return Err(e),
+                    }
+                    Ok((events, None))
+                })
+            })
+            .await?;
+        for event in events {
+            self.publish_change(event);
+        }
+        info!(
+            r#"Access review {}: "{}" {} "{}" in {:?}"#,
+            review_id,
+            &review.reviewer,
+            if keep { "kept" } else { "revoked" },
+            user_id,
+            review.group_id
+        );
+        if let Some(id) = pending_change {
+            info!(
+                r#"Change {} by "{}" is waiting for approval"#,
+                id, &review.reviewer
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{ChangeFeedBackendHandler, PendingChangeBackendHandler, UserBackendHandler},
+        sql_backend_handler::tests::*,
+    };
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_access_review_lifecycle() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let bob = UserId::new("bob");
+        let patrick = UserId::new("patrick");
+        let invitations = handler
+            .start_access_review(
+                "Q3 review",
+                &UserId::new("admin"),
+                &[(fixture.groups[0], UserId::new("John"))],
+            )
+            .await
+            .unwrap();
+        assert_eq!(invitations.len(), 1);
+        let review = &invitations[0].review;
+        assert_eq!(review.campaign_name, "Q3 review");
+        assert_eq!(review.reviewer, UserId::new("John"));
+        assert_eq!(
+            review
+                .decisions
+                .iter()
+                .map(|d| (d.user_id.clone(), d.keep))
+                .collect::<Vec<_>>(),
+            vec![(bob.clone(), None), (patrick.clone(), None)]
+        );
+        let token = &invitations[0].token;
+        assert_eq!(&handler.get_access_review(token).await.unwrap(), review);
+        // Only the selector is stored in clear.
+        let stored = model::AccessReviews::find_by_id(review.id)
+            .one(&handler.sql_pool)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!serde_json::to_string(&stored)
+            .unwrap()
+            .contains(split_token(token).unwrap().1));
+        assert!(handler.get_access_review(&stored.token).await.is_err());
+        handler
+            .decide_access_review(token, &bob, false)
+            .await
+            .unwrap();
+        handler
+            .decide_access_review(token, &patrick, true)
+            .await
+            .unwrap();
+        // The decisions are final.
+        assert!(handler
+            .decide_access_review(token, &patrick, false)
+            .await
+            .is_err());
+        assert!(handler.get_user_groups(&bob).await.unwrap().is_empty());
+        assert_eq!(handler.get_user_groups(&patrick).await.unwrap().len(), 2);
+        let reviews = handler.list_access_reviews().await.unwrap();
+        assert_eq!(
+            reviews[0]
+                .decisions
+                .iter()
+                .map(|d| (d.user_id.clone(), d.keep))
+                .collect::<Vec<_>>(),
+            vec![(bob.clone(), Some(false)), (patrick.clone(), Some(true))]
+        );
+        // The decisions are recorded in the change feed.
+        assert!(handler
+            .list_changes_for_user(&patrick)
+            .await
+            .unwrap()
+            .iter()
+            .any(|c| matches!(
+                &c.event,
+                ChangeEvent::AccessReviewDecided { keep: true, .. }
+            )));
+    }
+
+    #[tokio::test]
+    async fn test_access_review_admin_removal_needs_approval() {
+        let mut fixture = TestFixture::new().await;
+        fixture.handler.config.four_eyes_approval = true;
+        let handler = &fixture.handler;
+        // The first group of the fixture has the id of lldap_admin.
+        assert_eq!(fixture.groups[0], GroupId(1));
+        let bob = UserId::new("bob");
+        let invitations = handler
+            .start_access_review(
+                "Q3 review",
+                &UserId::new("admin"),
+                &[(fixture.groups[0], UserId::new("John"))],
+            )
+            .await
+            .unwrap();
+        handler
+            .decide_access_review(&invitations[0].token, &bob, false)
+            .await
+            .unwrap();
+        assert_eq!(handler.get_user_groups(&bob).await.unwrap().len(), 1);
+        let changes = handler.list_pending_changes().await.unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].requested_by, UserId::new("John"));
+        assert_eq!(
+            changes[0].change,
+            SensitiveChange::RemoveUserFromGroup {
+                user_id: bob,
+                group_id: GroupId(1),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_access_review_invalid_token() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        handler
+            .start_access_review(
+                "Q3 review",
+                &UserId::new("admin"),
+                &[(fixture.groups[0], UserId::new("John"))],
+            )
+            .await
+            .unwrap();
+        assert!(handler.get_access_review("not a token").await.is_err());
+        assert!(handler
+            .decide_access_review("not a token", &UserId::new("bob"), false)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_access_review_not_a_member() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let invitations = handler
+            .start_access_review(
+                "Q3 review",
+                &UserId::new("admin"),
+                &[(fixture.groups[0], UserId::new("John"))],
+            )
+            .await
+            .unwrap();
+        assert!(handler
+            .decide_access_review(&invitations[0].token, &UserId::new("NoGroup"), false)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_access_review_needs_groups() {
+        let fixture = TestFixture::new().await;
+        assert!(fixture
+            .handler
+            .start_access_review("Q3 review", &UserId::new("admin"), &[])
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,240 @@
+use crate::domain::{
+    account_deletions::AccountDeletion,
+    error::{DomainError, Result},
+    handler::{AccountDeletionBackendHandler, UserBackendHandler},
+    model::{self, AccountDeletionsColumn, JwtRefreshStorageColumn},
+    secret_tokens::{check_verifier, split_token, SecretToken},
+    sql_backend_handler::SqlBackendHandler,
+    types::UserId,
+};
+use async_trait::async_trait;
+use sea_orm::{
+    sea_query::OnConflict, ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use tracing::{info, instrument};
+
+// How long the user has to click the link in the confirmation email.
+fn confirmation_validity() -> chrono::Duration {
+    chrono::Duration::days(1)
+}
+
+impl From<model::account_deletions::Model> for AccountDeletion {
+    fn from(model: model::account_deletions::Model) -> Self {
+        Self {
+            user_id: model.user_id,
+            request_date: model.request_date,
+            deletion_date: model.deletion_date,
+        }
+    }
+}
+
+#[async_trait]
+impl AccountDeletionBackendHandler for SqlBackendHandler {
+    #[instrument(skip(self), level = "debug", err)]
+    async fn request_account_deletion(&self, user_id: &UserId) -> Result<String> {
+        let token = SecretToken::generate();
+        model::AccountDeletions::insert(model::account_deletions::ActiveModel {
+            user_id: Set(user_id.clone()),
+            token: Set(token.selector),
+            verifier_hash: Set(Some(token.hash)),
+            request_date: Set(chrono::Utc::now().naive_utc()),
+            deletion_date: Set(None),
+        })
+        .on_conflict(
+            OnConflict::column(AccountDeletionsColumn::UserId)
+                .update_columns([
+                    AccountDeletionsColumn::Token,
+                    AccountDeletionsColumn::VerifierHash,
+                    AccountDeletionsColumn::RequestDate,
+                ])
+                .to_owned(),
+        )
+        .exec(&self.sql_pool)
+        .await?;
+        Ok(token.token)
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn confirm_account_deletion(
+        &self,
+        token: &str,
+        grace_period: chrono::Duration,
+    ) -> Result<AccountDeletion> {
+        let now = chrono::Utc::now().naive_utc();
+        let invalid = || DomainError::EntityNotFound("Invalid account deletion token".to_owned());
+        let (selector, verifier) = split_token(token).ok_or_else(invalid)?;
+        let request = model::AccountDeletions::find()
+            .filter(AccountDeletionsColumn::Token.eq(selector))
+            .filter(AccountDeletionsColumn::DeletionDate.is_null())
+            .filter(AccountDeletionsColumn::RequestDate.gt(now - confirmation_validity()))
+            .one(&self.sql_pool)
+            .await?
+            .filter(|r| check_verifier(verifier, r.verifier_hash.as_deref().unwrap_or_default()))
+            .ok_or_else(invalid)?;
+        let user_id = request.user_id.clone();
+        let mut request: model::account_deletions::ActiveModel = request.into();
+        request.deletion_date = Set(Some(now + grace_period));
+        let request = request.update(&self.sql_pool).await?;
+        // The user can't log in anymore, they shouldn't be able to refresh their session either.
+        model::JwtRefreshStorage::delete_many()
+            .filter(JwtRefreshStorageColumn::UserId.eq(&user_id))
+            .exec(&self.sql_pool)
+            .await?;
+        info!(
+            r#"The account of "{}" will be deleted on {}"#,
+            &user_id,
+            now + grace_period
+        );
+        Ok(request.into())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn cancel_account_deletion(&self, user_id: &UserId) -> Result<()> {
+        let res = model::AccountDeletions::delete_by_id(user_id.clone())
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No deletion request for '{}'",
+                user_id
+            )));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", ret, err)]
+    async fn get_account_deletion(&self, user_id: &UserId) -> Result<Option<AccountDeletion>> {
+        Ok(model::AccountDeletions::find_by_id(user_id.clone())
+            .one(&self.sql_pool)
+            .await?
+            .map(Into::into))
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn list_account_deletions(&self) -> Result<Vec<AccountDeletion>> {
+        Ok(model::AccountDeletions::find()
+            .order_by_asc(AccountDeletionsColumn::RequestDate)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn purge_deleted_accounts(&self) -> Result<Vec<UserId>> {
+        let now = chrono::Utc::now().naive_utc();
+        model::AccountDeletions::delete_many()
+            .filter(AccountDeletionsColumn::DeletionDate.is_null())
+            .filter(AccountDeletionsColumn::RequestDate.lt(now - confirmation_validity()))
+            .exec(&self.sql_pool)
+            .await?;
+        let expired = model::AccountDeletions::find()
+            .filter(AccountDeletionsColumn::DeletionDate.lt(now))
+            .all(&self.sql_pool)
+            .await?;
+        let mut deleted = Vec::new();
+        for deletion in expired {
+            // Goes through the regular deletion, so that the change gets recorded.
+            self.delete_user(&deletion.user_id).await?;
+            info!(r#"Deleted the account of "{}""#, &deletion.user_id);
+            deleted.push(deletion.user_id);
+        }
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{BindRequest, LoginHandler},
+        sql_backend_handler::tests::*,
+    };
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_account_deletion_lifecycle() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        insert_user(handler, "alice", "alice_pass").await;
+        let alice = UserId::new("alice");
+        let token = handler.request_account_deletion(&alice).await.unwrap();
+        // Requesting again replaces the token.
+        let token2 = handler.request_account_deletion(&alice).await.unwrap();
+        assert!(handler
+            .confirm_account_deletion(&token, chrono::Duration::days(30))
+            .await
+            .is_err());
+        // Only the selector is stored in clear.
+        let stored = model::AccountDeletions::find_by_id(alice.clone())
+            .one(&handler.sql_pool)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.token, split_token(&token2).unwrap().0);
+        assert!(!serde_json::to_string(&stored)
+            .unwrap()
+            .contains(split_token(&token2).unwrap().1));
+        assert!(handler
+            .confirm_account_deletion(&stored.token, chrono::Duration::days(30))
+            .await
+            .is_err());
+        assert_eq!(
+            handler
+                .get_account_deletion(&alice)
+                .await
+                .unwrap()
+                .unwrap()
+                .deletion_date,
+            None
+        );
+        let deletion = handler
+            .confirm_account_deletion(&token2, chrono::Duration::days(30))
+            .await
+            .unwrap();
+        assert!(deletion.deletion_date.is_some());
+        // Not deleted yet: still in the grace period.
+        assert_eq!(handler.purge_deleted_accounts().await.unwrap(), vec![]);
+        assert_eq!(
+            handler.list_account_deletions().await.unwrap(),
+            vec![deletion]
+        );
+        // Soft-deleted users can't log in.
+        assert!(handler
+            .bind(BindRequest {
+                name: alice.clone(),
+                password: "alice_pass".to_string(),
+            })
+            .await
+            .is_err());
+        handler.cancel_account_deletion(&alice).await.unwrap();
+        assert_eq!(handler.get_account_deletion(&alice).await.unwrap(), None);
+        handler
+            .bind(BindRequest {
+                name: alice.clone(),
+                password: "alice_pass".to_string(),
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_purge_deleted_accounts() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let bob = UserId::new("bob");
+        let token = handler.request_account_deletion(&bob).await.unwrap();
+        handler
+            .confirm_account_deletion(&token, chrono::Duration::zero())
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        assert_eq!(
+            handler.purge_deleted_accounts().await.unwrap(),
+            vec![bob.clone()]
+        );
+        assert!(handler.get_user_details(&bob).await.is_err());
+        assert_eq!(handler.list_account_deletions().await.unwrap(), vec![]);
+    }
+}
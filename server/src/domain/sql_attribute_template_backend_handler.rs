@@ -0,0 +1,382 @@
+use crate::domain::{
+    attribute_templates::AttributeTemplate,
+    error::{DomainError, Result},
+    handler::AttributeTemplateBackendHandler,
+    model::{self, GroupAttributeTemplatesColumn, MembershipColumn, UserAttributesColumn},
+    sql_backend_handler::SqlBackendHandler,
+    types::{AttributeName, GroupId, Serialized, UserId},
+};
+use async_trait::async_trait;
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Set,
+};
+use tracing::{debug, instrument};
+
+#[async_trait]
+impl AttributeTemplateBackendHandler for SqlBackendHandler {
+    #[instrument(skip(self), level = "debug", ret, err)]
+    async fn list_attribute_templates(&self, group_id: GroupId) -> Result<Vec<AttributeTemplate>> {
+        Ok(model::GroupAttributeTemplates::find()
+            .filter(GroupAttributeTemplatesColumn::GroupId.eq(group_id))
+            .order_by_asc(GroupAttributeTemplatesColumn::AttributeName)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn set_attribute_template(&self, template: AttributeTemplate) -> Result<()> {
+        model::GroupAttributeTemplates::insert(model::group_attribute_templates::ActiveModel {
+            group_id: Set(template.group_id),
+            attribute_name: Set(template.attribute_name),
+            value: Set(template.value),
+            overwrite: Set(template.overwrite),
+        })
+        .on_conflict(
+            OnConflict::columns([
+                GroupAttributeTemplatesColumn::GroupId,
+                GroupAttributeTemplatesColumn::AttributeName,
+            ])
+            .update_columns([
+                GroupAttributeTemplatesColumn::Value,
+                GroupAttributeTemplatesColumn::Overwrite,
+            ])
+            .to_owned(),
+        )
+        .exec(&self.sql_pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn delete_attribute_template(
+        &self,
+        group_id: GroupId,
+        attribute_name: &AttributeName,
+    ) -> Result<()> {
+        let res = model::GroupAttributeTemplates::delete_by_id((group_id, attribute_name.clone()))
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No template for '{}' in group {:?}",
+                attribute_name, group_id
+            )));
+        }
+        Ok(())
+    }
+}
+
+async fn get_user_email(connection: &impl ConnectionTrait, user_id: &UserId) -> Result<String> {
+    Ok(model::User::find_by_id(user_id.clone())
+        .one(connection)
+        .await?
+        .ok_or_else(|| DomainError::EntityNotFound(user_id.to_string()))?
+        .email
+        .into_string())
+}
+
+async fn get_user_attribute(
+    connection: &impl ConnectionTrait,
+    user_id: &UserId,
+    attribute_name: &AttributeName,
+) -> Result<Option<Serialized>> {
+    Ok(
+        model::UserAttributes::find_by_id((user_id.clone(), attribute_name.clone()))
+            .one(connection)
+            .await?
+            .map(|a| a.value),
+    )
+}
+
+async fn set_user_attribute(
+    connection: &impl ConnectionTrait,
+    user_id: &UserId,
+    attribute_name: &AttributeName,
+    value: Serialized,
+) -> Result<()> {
+    model::UserAttributes::insert(model::user_attributes::ActiveModel {
+        user_id: Set(user_id.clone()),
+        attribute_name: Set(attribute_name.clone()),
+        value: Set(value),
+    })
+    .on_conflict(
+        OnConflict::columns([
+            UserAttributesColumn::UserId,
+            UserAttributesColumn::AttributeName,
+        ])
+        .update_column(UserAttributesColumn::Value)
+        .to_owned(),
+    )
+    .exec(connection)
+    .await?;
+    Ok(())
+}
+
+impl SqlBackendHandler {
+    /// Applies the templates of the group that the user just joined. Returns whether any of
+    /// the user's attributes changed.
+    pub(crate) async fn apply_attribute_templates(
+        connection: &impl ConnectionTrait,
+        user_id: &UserId,
+        group_id: GroupId,
+    ) -> Result<bool> {
+        let templates = model::GroupAttributeTemplates::find()
+            .filter(GroupAttributeTemplatesColumn::GroupId.eq(group_id))
+            .all(connection)
+            .await?;
+        if templates.is_empty() {
+            return Ok(false);
+        }
+        let email = get_user_email(connection, user_id).await?;
+        let mut changed = false;
+        for template in templates.into_iter().map(AttributeTemplate::from) {
+            let value = Serialized::from(&template.render(user_id, &email));
+            match get_user_attribute(connection, user_id, &template.attribute_name).await? {
+                Some(existing) if existing == value => continue,
+                Some(_) if !template.overwrite => {
+                    debug!(
+                        "Keeping the existing value of {} for {}",
+                        &template.attribute_name, user_id
+                    );
+                    continue;
+                }
+                _ => {}
+            }
+            set_user_attribute(connection, user_id, &template.attribute_name, value).await?;
+            changed = true;
+        }
+        Ok(changed)
+    }
+
+    /// Removes the values set by the templates of the group that the user just left, unless
+    /// they were modified since. The templates of the remaining groups (lowest ID first) then
+    /// fill the gaps. Returns whether any of the user's attributes changed.
+    pub(crate) async fn revert_attribute_templates(
+        connection: &impl ConnectionTrait,
+        user_id: &UserId,
+        group_id: GroupId,
+    ) -> Result<bool> {
+        let templates = model::GroupAttributeTemplates::find()
+            .filter(GroupAttributeTemplatesColumn::GroupId.eq(group_id))
+            .all(connection)
+            .await?;
+        if templates.is_empty() {
+            return Ok(false);
+        }
+        let email = get_user_email(connection, user_id).await?;
+        let remaining_groups = model::Membership::find()
+            .select_only()
+            .column(MembershipColumn::GroupId)
+            .filter(MembershipColumn::UserId.eq(user_id))
+            .into_tuple::<GroupId>()
+            .all(connection)
+            .await?;
+        let mut changed = false;
+        for template in templates.into_iter().map(AttributeTemplate::from) {
+            let value = Serialized::from(&template.render(user_id, &email));
+            if get_user_attribute(connection, user_id, &template.attribute_name).await?
+                != Some(value)
+            {
+                continue;
+            }
+            model::UserAttributes::delete_by_id((user_id.clone(), template.attribute_name.clone()))
+                .exec(connection)
+                .await?;
+            changed = true;
+            if let Some(replacement) = model::GroupAttributeTemplates::find()
+                .filter(GroupAttributeTemplatesColumn::AttributeName.eq(&template.attribute_name))
+                .filter(GroupAttributeTemplatesColumn::GroupId.is_in(remaining_groups.clone()))
+                .order_by_asc(GroupAttributeTemplatesColumn::GroupId)
+                .one(connection)
+                .await?
+            {
+                let replacement = AttributeTemplate::from(replacement);
+                set_user_attribute(
+                    connection,
+                    user_id,
+                    &replacement.attribute_name,
+                    Serialized::from(&replacement.render(user_id, &email)),
+                )
+                .await?;
+            }
+        }
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{
+            CreateAttributeRequest, SchemaBackendHandler, UpdateUserRequest, UserBackendHandler,
+        },
+        sql_backend_handler::tests::*,
+        types::{AttributeType, AttributeValue},
+    };
+    use pretty_assertions::assert_eq;
+
+    async fn setup_home_directory(fixture: &TestFixture) {
+        fixture
+            .handler
+            .add_user_attribute(CreateAttributeRequest {
+                name: "home_directory".into(),
+                attribute_type: AttributeType::String,
+                is_list: false,
+                is_visible: true,
+                is_editable: false,
+            })
+            .await
+            .unwrap();
+        for (group_id, value) in [
+            (fixture.groups[0], "/home/{uid}"),
+            (fixture.groups[1], "/home/other/{uid}"),
+        ] {
+            fixture
+                .handler
+                .set_attribute_template(AttributeTemplate {
+                    group_id,
+                    attribute_name: "home_directory".into(),
+                    value: value.to_owned(),
+                    overwrite: false,
+                })
+                .await
+                .unwrap();
+        }
+    }
+
+    async fn get_home_directory(handler: &SqlBackendHandler, user: &str) -> Option<String> {
+        handler
+            .get_user_details(&UserId::new(user))
+            .await
+            .unwrap()
+            .attributes
+            .into_iter()
+            .find(|a| a.name.as_str() == "home_directory")
+            .map(|a| a.value.unwrap::<String>())
+    }
+
+    #[tokio::test]
+    async fn test_set_and_list_attribute_templates() {
+        let fixture = TestFixture::new().await;
+        setup_home_directory(&fixture).await;
+        fixture
+            .handler
+            .set_attribute_template(AttributeTemplate {
+                group_id: fixture.groups[0],
+                attribute_name: "home_directory".into(),
+                value: "/srv/{uid}".to_owned(),
+                overwrite: true,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            fixture
+                .handler
+                .list_attribute_templates(fixture.groups[0])
+                .await
+                .unwrap(),
+            vec![AttributeTemplate {
+                group_id: fixture.groups[0],
+                attribute_name: "home_directory".into(),
+                value: "/srv/{uid}".to_owned(),
+                overwrite: true,
+            }]
+        );
+        fixture
+            .handler
+            .delete_attribute_template(fixture.groups[0], &"home_directory".into())
+            .await
+            .unwrap();
+        assert_eq!(
+            fixture
+                .handler
+                .list_attribute_templates(fixture.groups[0])
+                .await
+                .unwrap(),
+            vec![]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_templates_follow_memberships() {
+        let fixture = TestFixture::new().await;
+        setup_home_directory(&fixture).await;
+        // patrick is in groups[0] and groups[1], but the templates were created afterwards.
+        let handler = &fixture.handler;
+        assert_eq!(get_home_directory(handler, "patrick").await, None);
+        handler
+            .remove_user_from_group(&UserId::new("patrick"), fixture.groups[0])
+            .await
+            .unwrap();
+        handler
+            .add_user_to_group(&UserId::new("patrick"), fixture.groups[0])
+            .await
+            .unwrap();
+        assert_eq!(
+            get_home_directory(handler, "patrick").await,
+            Some("/home/patrick".to_owned())
+        );
+        // The first value is kept.
+        handler
+            .remove_user_from_group(&UserId::new("patrick"), fixture.groups[1])
+            .await
+            .unwrap();
+        handler
+            .add_user_to_group(&UserId::new("patrick"), fixture.groups[1])
+            .await
+            .unwrap();
+        assert_eq!(
+            get_home_directory(handler, "patrick").await,
+            Some("/home/patrick".to_owned())
+        );
+        // Leaving the group falls back to the template of the remaining group.
+        handler
+            .remove_user_from_group(&UserId::new("patrick"), fixture.groups[0])
+            .await
+            .unwrap();
+        assert_eq!(
+            get_home_directory(handler, "patrick").await,
+            Some("/home/other/patrick".to_owned())
+        );
+        handler
+            .remove_user_from_group(&UserId::new("patrick"), fixture.groups[1])
+            .await
+            .unwrap();
+        assert_eq!(get_home_directory(handler, "patrick").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_templates_keep_manual_values() {
+        let fixture = TestFixture::new().await;
+        setup_home_directory(&fixture).await;
+        let handler = &fixture.handler;
+        handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("john"),
+                insert_attributes: vec![AttributeValue {
+                    name: "home_directory".into(),
+                    value: Serialized::from("/manual"),
+                }],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        handler
+            .add_user_to_group(&UserId::new("john"), fixture.groups[0])
+            .await
+            .unwrap();
+        handler
+            .remove_user_from_group(&UserId::new("john"), fixture.groups[0])
+            .await
+            .unwrap();
+        assert_eq!(
+            get_home_directory(handler, "john").await,
+            Some("/manual".to_owned())
+        );
+    }
+}
@@ -0,0 +1,353 @@
+use crate::domain::{
+    change_events::ChangeEvent,
+    error::{DomainError, Result},
+    handler::AvatarSyncBackendHandler,
+    model::{self, AvatarSyncsColumn, UserAttributesColumn},
+    sql_backend_handler::SqlBackendHandler,
+    types::{AttributeName, Email, JpegPhoto, Serialized, UserId},
+};
+use async_trait::async_trait;
+use base64::Engine;
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder, Set,
+    TransactionTrait,
+};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use tracing::{debug, instrument};
+
+fn avatar_attribute() -> AttributeName {
+    "avatar".into()
+}
+
+fn hash_avatar(avatar: &Serialized) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(avatar.clone().unwrap::<JpegPhoto>().into_bytes());
+    base64::engine::general_purpose::STANDARD.encode(hasher.finalize())
+}
+
+async fn get_avatar(
+    connection: &impl ConnectionTrait,
+    user_id: &UserId,
+) -> Result<Option<Serialized>> {
+    Ok(
+        model::UserAttributes::find_by_id((user_id.clone(), avatar_attribute()))
+            .one(connection)
+            .await?
+            .map(|a| a.value),
+    )
+}
+
+async fn save_avatar_sync(
+    connection: &impl ConnectionTrait,
+    sync: model::avatar_syncs::ActiveModel,
+    update_columns: impl IntoIterator<Item = AvatarSyncsColumn>,
+) -> Result<()> {
+    model::AvatarSyncs::insert(sync)
+        .on_conflict(
+            OnConflict::column(AvatarSyncsColumn::UserId)
+                .update_columns(update_columns)
+                .to_owned(),
+        )
+        .exec(connection)
+        .await?;
+    Ok(())
+}
+
+impl SqlBackendHandler {
+    // Removes the avatar if it's the one stored by the last sync. Returns whether it was removed.
+    async fn remove_synced_avatar(
+        connection: &impl ConnectionTrait,
+        user_id: &UserId,
+        sync: Option<&model::avatar_syncs::Model>,
+    ) -> Result<bool> {
+        let synced_hash = sync.and_then(|s| s.synced_avatar_hash.as_ref());
+        match get_avatar(connection, user_id).await? {
+            Some(avatar) if Some(&hash_avatar(&avatar)) == synced_hash => {
+                model::UserAttributes::delete_by_id((user_id.clone(), avatar_attribute()))
+                    .exec(connection)
+                    .await?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+#[async_trait]
+impl AvatarSyncBackendHandler for SqlBackendHandler {
+    #[instrument(skip(self), level = "debug", ret, err)]
+    async fn get_avatar_sync_opt_out(&self, user_id: &UserId) -> Result<bool> {
+        Ok(model::AvatarSyncs::find_by_id(user_id.clone())
+            .one(&self.sql_pool)
+            .await?
+            .map(|s| s.opt_out)
+            .unwrap_or(false))
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn set_avatar_sync_opt_out(&self, user_id: &UserId, opt_out: bool) -> Result<()> {
+        let user_id_clone = user_id.clone();
+        let avatar_removed = self
+            .sql_pool
+            .transaction::<_, bool, DomainError>(|transaction| {
+                Box::pin(async move {
+                    let user_id = user_id_clone;
+                    let sync = model::AvatarSyncs::find_by_id(user_id.clone())
+                        .one(transaction)
+                        .await?;
+                    let avatar_removed = opt_out
+                        && Self::remove_synced_avatar(transaction, &user_id, sync.as_ref()).await?;
+                    let mut update_columns = vec![AvatarSyncsColumn::OptOut];
+                    if avatar_removed {
+                        update_columns.push(AvatarSyncsColumn::SyncedAvatarHash);
+                        Self::record_change(
+                            transaction,
+                            &ChangeEvent::UserUpdated(user_id.clone()),
+                        )
+                        .await?;
+                    }
+                    save_avatar_sync(
+                        transaction,
+                        model::avatar_syncs::ActiveModel {
+                            user_id: Set(user_id),
+                            opt_out: Set(opt_out),
+                            synced_avatar_hash: Set(None),
+                            sync_date: Set(None),
+                        },
+                        update_columns,
+                    )
+                    .await?;
+                    Ok(avatar_removed)
+                })
+            })
+            .await?;
+        if avatar_removed {
+            self.publish_change(ChangeEvent::UserUpdated(user_id.clone()));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn list_avatar_sync_candidates(&self) -> Result<Vec<(UserId, Email)>> {
+        let syncs = model::AvatarSyncs::find()
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(|s| (s.user_id.clone(), s))
+            .collect::<HashMap<_, _>>();
+        let avatars = model::UserAttributes::find()
+            .filter(UserAttributesColumn::AttributeName.eq(&avatar_attribute()))
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(|a| (a.user_id, a.value))
+            .collect::<HashMap<_, _>>();
+        Ok(model::User::find()
+            .order_by_asc(model::UserColumn::UserId)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .filter(|user| {
+                let sync = syncs.get(&user.user_id);
+                if sync.map(|s| s.opt_out).unwrap_or(false) {
+                    return false;
+                }
+                match avatars.get(&user.user_id) {
+                    None => true,
+                    Some(avatar) => {
+                        sync.and_then(|s| s.synced_avatar_hash.as_ref())
+                            == Some(&hash_avatar(avatar))
+                    }
+                }
+            })
+            .map(|user| (user.user_id, user.email))
+            .collect())
+    }
+
+    #[instrument(skip(self, avatar), level = "debug", err)]
+    async fn store_synced_avatar(&self, user_id: &UserId, avatar: Option<JpegPhoto>) -> Result<()> {
+        let user_id_clone = user_id.clone();
+        let changed = self
+            .sql_pool
+            .transaction::<_, bool, DomainError>(|transaction| {
+                Box::pin(async move {
+                    let user_id = user_id_clone;
+                    let sync = model::AvatarSyncs::find_by_id(user_id.clone())
+                        .one(transaction)
+                        .await?;
+                    if sync.as_ref().map(|s| s.opt_out).unwrap_or(false) {
+                        debug!("{} opted out of the avatar sync", &user_id);
+                        return Ok(false);
+                    }
+                    let current = get_avatar(transaction, &user_id).await?;
+                    let current_hash = current.as_ref().map(hash_avatar);
+                    if current_hash.is_some()
+                        && current_hash.as_ref()
+                            != sync.as_ref().and_then(|s| s.synced_avatar_hash.as_ref())
+                    {
+                        debug!("{} uploaded their own avatar", &user_id);
+                        return Ok(false);
+                    }
+                    let (changed, synced_avatar_hash) = match avatar {
+                        Some(avatar) => {
+                            let value = Serialized::from(&avatar);
+                            let hash = hash_avatar(&value);
+                            let changed = current_hash.as_ref() != Some(&hash);
+                            if changed {
+                                model::UserAttributes::insert(
+                                    model::user_attributes::ActiveModel {
+                                        user_id: Set(user_id.clone()),
+                                        attribute_name: Set(avatar_attribute()),
+                                        value: Set(value),
+                                    },
+                                )
+                                .on_conflict(
+                                    OnConflict::columns([
+                                        UserAttributesColumn::UserId,
+                                        UserAttributesColumn::AttributeName,
+                                    ])
+                                    .update_column(UserAttributesColumn::Value)
+                                    .to_owned(),
+                                )
+                                .exec(transaction)
+                                .await?;
+                            }
+                            (changed, Some(hash))
+                        }
+                        None => (
+                            Self::remove_synced_avatar(transaction, &user_id, sync.as_ref())
+                                .await?,
+                            None,
+                        ),
+                    };
+                    if changed {
+                        Self::record_change(
+                            transaction,
+                            &ChangeEvent::UserUpdated(user_id.clone()),
+                        )
+                        .await?;
+                    }
+                    save_avatar_sync(
+                        transaction,
+                        model::avatar_syncs::ActiveModel {
+                            user_id: Set(user_id),
+                            opt_out: Set(false),
+                            synced_avatar_hash: Set(synced_avatar_hash),
+                            sync_date: Set(Some(chrono::Utc::now().naive_utc())),
+                        },
+                        [
+                            AvatarSyncsColumn::SyncedAvatarHash,
+                            AvatarSyncsColumn::SyncDate,
+                        ],
+                    )
+                    .await?;
+                    Ok(changed)
+                })
+            })
+            .await?;
+        if changed {
+            self.publish_change(ChangeEvent::UserUpdated(user_id.clone()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{UpdateUserRequest, UserBackendHandler},
+        sql_backend_handler::tests::*,
+    };
+    use pretty_assertions::assert_eq;
+
+    async fn get_candidates(handler: &SqlBackendHandler) -> Vec<String> {
+        handler
+            .list_avatar_sync_candidates()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(user_id, _)| user_id.to_string())
+            .collect()
+    }
+
+    async fn get_user_avatar(handler: &SqlBackendHandler, user: &str) -> Option<JpegPhoto> {
+        handler
+            .get_user_details(&UserId::new(user))
+            .await
+            .unwrap()
+            .attributes
+            .into_iter()
+            .find(|a| a.name.as_str() == "avatar")
+            .map(|a| a.value.unwrap::<JpegPhoto>())
+    }
+
+    #[tokio::test]
+    async fn test_synced_avatar_lifecycle() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        assert_eq!(
+            get_candidates(handler).await,
+            vec!["bob", "john", "nogroup", "patrick"]
+        );
+        handler
+            .store_synced_avatar(&UserId::new("bob"), Some(JpegPhoto::for_tests()))
+            .await
+            .unwrap();
+        assert_eq!(
+            get_user_avatar(handler, "bob").await,
+            Some(JpegPhoto::for_tests())
+        );
+        // The synced avatar can be refreshed.
+        assert_eq!(
+            get_candidates(handler).await,
+            vec!["bob", "john", "nogroup", "patrick"]
+        );
+        // Not anymore once the user opts out, and the synced avatar is removed.
+        handler
+            .set_avatar_sync_opt_out(&UserId::new("bob"), true)
+            .await
+            .unwrap();
+        assert!(handler
+            .get_avatar_sync_opt_out(&UserId::new("bob"))
+            .await
+            .unwrap());
+        assert_eq!(get_user_avatar(handler, "bob").await, None);
+        assert_eq!(
+            get_candidates(handler).await,
+            vec!["john", "nogroup", "patrick"]
+        );
+        handler
+            .store_synced_avatar(&UserId::new("bob"), Some(JpegPhoto::for_tests()))
+            .await
+            .unwrap();
+        assert_eq!(get_user_avatar(handler, "bob").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_uploaded_avatar_is_kept() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("patrick"),
+                avatar: Some(JpegPhoto::for_tests()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            get_candidates(handler).await,
+            vec!["bob", "john", "nogroup"]
+        );
+        handler
+            .store_synced_avatar(&UserId::new("patrick"), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            get_user_avatar(handler, "patrick").await,
+            Some(JpegPhoto::for_tests())
+        );
+    }
+}
@@ -1,19 +1,174 @@
-use crate::domain::{handler::BackendHandler, sql_tables::DbConnection};
-use crate::infra::configuration::Configuration;
+use crate::domain::{
+    change_events::{ChangeEvent, ChangeEventBus},
+    error::{DomainError, Result},
+    handler::BackendHandler,
+    model::{self, GroupColumn, UserColumn},
+    search_cache::SearchCache,
+    sql_tables::DbConnection,
+    types::{AttributeType, AttributeValue},
+};
+use crate::infra::{
+    configuration::{AttributeValidationRule, Configuration},
+    validation_webhook::{ValidationWebhook, WebhookOperation},
+};
 use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue::NotSet, ColumnTrait, DatabaseTransaction,
+    EntityTrait, QueryFilter, Set,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct SqlBackendHandler {
     pub(crate) config: Configuration,
     pub(crate) sql_pool: DbConnection,
+    pub(crate) search_cache: Option<Arc<SearchCache>>,
+    validation_webhook: Option<Arc<ValidationWebhook>>,
+    pub(crate) change_events: ChangeEventBus,
 }
 
 impl SqlBackendHandler {
     pub fn new(config: Configuration, sql_pool: DbConnection) -> Self {
-        SqlBackendHandler { config, sql_pool }
+        let search_cache = SearchCache::from_options(&config.cache_options).map(Arc::new);
+        let validation_webhook =
+            ValidationWebhook::from_options(&config.validation_webhook_options).map(Arc::new);
+        SqlBackendHandler {
+            config,
+            sql_pool,
+            search_cache,
+            validation_webhook,
+            change_events: ChangeEventBus::new(),
+        }
+    }
+
+    /// Submits the change to the validation webhook, if any, which may reject or modify it.
+    pub(crate) async fn review_change<T: Serialize + DeserializeOwned>(
+        &self,
+        operation: WebhookOperation,
+        request: T,
+    ) -> Result<T> {
+        match &self.validation_webhook {
+            Some(webhook) => webhook.review(operation, request).await,
+            None => Ok(request),
+        }
+    }
+
+    /// Bumps the modification date of the entries that the change modified. The membership
+    /// changes modify both the group (`member`) and the user (`memberOf`).
+    async fn touch_modified_entries(
+        transaction: &DatabaseTransaction,
+        event: &ChangeEvent,
+        now: NaiveDateTime,
+    ) -> Result<()> {
+        let (user_id, group_id) = match event {
+            ChangeEvent::UserUpdated(user_id) => (Some(user_id), None),
+            ChangeEvent::GroupUpdated(group_id) => (None, Some(*group_id)),
+            ChangeEvent::MembershipAdded { user_id, group_id }
+            | ChangeEvent::MembershipRemoved { user_id, group_id }
+            | ChangeEvent::MembershipAddedByRule {
+                user_id, group_id, ..
+            }
+            | ChangeEvent::MembershipRemovedByRule {
+                user_id, group_id, ..
+            } => (Some(user_id), Some(*group_id)),
+            _ => (None, None),
+        };
+        if let Some(user_id) = user_id {
+            model::User::update_many()
+                .col_expr(UserColumn::ModifiedDate, Expr::value(now))
+                .filter(UserColumn::UserId.eq(user_id))
+                .exec(transaction)
+                .await?;
+        }
+        if let Some(group_id) = group_id {
+            model::Group::update_many()
+                .col_expr(GroupColumn::ModifiedDate, Expr::value(now))
+                .filter(GroupColumn::GroupId.eq(group_id))
+                .exec(transaction)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Must be called by every write path, in the transaction of the write: the change is in the
+    /// change feed if and only if it is committed. It must then be published with
+    /// `publish_change`, once committed.
+    ///
+    /// The sequence number is assigned on insertion, not on commit: with MySQL or PostgreSQL,
+    /// concurrent writes can commit out of order, and a reader of the feed can briefly see a change
+    /// before one with a lower sequence number (SQLite serializes the writes, so it can't happen).
+    pub(crate) async fn record_change(
+        transaction: &DatabaseTransaction,
+        event: &ChangeEvent,
+    ) -> Result<()> {
+        let now = chrono::Utc::now().naive_utc();
+        Self::touch_modified_entries(transaction, event, now).await?;
+        model::change_feed::ActiveModel {
+            sequence: NotSet,
+            change_date: Set(now),
+            event: Set(serde_json::to_string(event).unwrap()),
+        }
+        .insert(transaction)
+        .await?;
+        Ok(())
+    }
+
+    /// Records the change in the transaction of the write, commits it, and publishes the change.
+    pub(crate) async fn commit_change(
+        &self,
+        transaction: DatabaseTransaction,
+        event: ChangeEvent,
+    ) -> Result<()> {
+        Self::record_change(&transaction, &event).await?;
+        transaction.commit().await?;
+        self.publish_change(event);
+        Ok(())
+    }
+
+    /// Notifies the listeners of a change recorded by `record_change`, once it is committed.
+    pub(crate) fn publish_change(&self, event: ChangeEvent) {
+        // The cache is invalidated first, so that the writer never reads stale data.
+        if event.affects_searches() {
+            if let Some(cache) = &self.search_cache {
+                cache.invalidate();
+            }
+        }
+        self.change_events.publish(event);
+    }
+}
+
+/// Checks the value against the validation rule of its attribute, if any.
+pub(crate) fn check_attribute_value(
+    rules: &[AttributeValidationRule],
+    attribute: &AttributeValue,
+    attribute_type: AttributeType,
+    is_list: bool,
+) -> Result<()> {
+    match rules.iter().find(|r| r.attribute == attribute.name) {
+        Some(rule) => rule
+            .check(&attribute.value, attribute_type, is_list)
+            .map_err(|e| DomainError::AttributeValidationError {
+                attribute: attribute.name.clone(),
+                rule: e.rule,
+                message: e.message,
+            }),
+        None => Ok(()),
     }
 }
 
+/// Fails when there are already `max` entities of the kind, e.g. users, before creating another.
+pub(crate) fn check_quota(count: u64, max: u64, entities: &str) -> Result<()> {
+    if count >= max {
+        return Err(DomainError::QuotaExceeded(format!(
+            "The maximum number of {} ({}) is reached",
+            entities, max
+        )));
+    }
+    Ok(())
+}
+
 #[async_trait]
 impl BackendHandler for SqlBackendHandler {}
 
@@ -23,8 +178,9 @@ pub mod tests {
     use crate::{
         domain::{
             handler::{
-                CreateGroupRequest, CreateUserRequest, GroupBackendHandler, UserBackendHandler,
-                UserListerBackendHandler, UserRequestFilter,
+                ChangeFeedBackendHandler, CreateGroupRequest, CreateUserRequest,
+                GroupBackendHandler, UserBackendHandler, UserListerBackendHandler,
+                UserRequestFilter,
             },
             sql_tables::init_table,
             types::{GroupId, UserId},
@@ -174,4 +330,68 @@ pub mod tests {
             assert_eq!(user.user_id, user_name);
         }
     }
+
+    #[tokio::test]
+    async fn test_search_cache_invalidated_on_write() {
+        let sql_pool = get_initialized_db().await;
+        let mut config = get_default_config();
+        config.cache_options.enabled = true;
+        let handler = SqlBackendHandler::new(config, sql_pool);
+        insert_user_no_password(&handler, "bob").await;
+        assert_eq!(get_user_names(&handler, None).await, vec!["bob"]);
+        insert_user_no_password(&handler, "john").await;
+        assert_eq!(get_user_names(&handler, None).await, vec!["bob", "john"]);
+        handler.delete_user(&UserId::new("bob")).await.unwrap();
+        assert_eq!(get_user_names(&handler, None).await, vec!["john"]);
+    }
+
+    #[tokio::test]
+    async fn test_change_feed_is_written_with_the_change() {
+        use sea_orm::ConnectionTrait;
+        let sql_pool = get_initialized_db().await;
+        let handler = SqlBackendHandler::new(get_default_config(), sql_pool.clone());
+        insert_user_no_password(&handler, "bob").await;
+        assert_eq!(handler.get_last_change_sequence().await.unwrap(), 1);
+        // Without the feed, the write is rolled back.
+        sql_pool
+            .execute_unprepared("DROP TABLE change_feed")
+            .await
+            .unwrap();
+        let mut events = handler.subscribe_to_changes();
+        assert!(handler.delete_user(&UserId::new("bob")).await.is_err());
+        assert!(handler.get_user_details(&UserId::new("bob")).await.is_ok());
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_write_paths_emit_change_events() {
+        let sql_pool = get_initialized_db().await;
+        let handler = SqlBackendHandler::new(get_default_config(), sql_pool);
+        let mut events = handler.subscribe_to_changes();
+        insert_user_no_password(&handler, "bob").await;
+        let group_id = insert_group(&handler, "group").await;
+        insert_membership(&handler, group_id, "bob").await;
+        handler.delete_group(group_id).await.unwrap();
+        let bob = UserId::new("bob");
+        assert_eq!(
+            events.recv().await.unwrap(),
+            ChangeEvent::UserCreated(bob.clone())
+        );
+        assert_eq!(
+            events.recv().await.unwrap(),
+            ChangeEvent::GroupCreated(group_id)
+        );
+        assert_eq!(
+            events.recv().await.unwrap(),
+            ChangeEvent::MembershipAdded {
+                user_id: bob,
+                group_id
+            }
+        );
+        assert_eq!(
+            events.recv().await.unwrap(),
+            ChangeEvent::GroupDeleted(group_id)
+        );
+        assert!(events.try_recv().is_err());
+    }
 }
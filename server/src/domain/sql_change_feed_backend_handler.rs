@@ -0,0 +1,212 @@
+use crate::domain::{
+    change_events::{ChangeEvent, ChangeFeedEntry},
+    error::{DomainError, Result},
+    handler::ChangeFeedBackendHandler,
+    model::{self, ChangeFeedColumn},
+    sql_backend_handler::SqlBackendHandler,
+    types::UserId,
+};
+use async_trait::async_trait;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+use tokio::sync::broadcast;
+use tracing::instrument;
+
+impl TryFrom<model::change_feed::Model> for ChangeFeedEntry {
+    type Error = DomainError;
+
+    fn try_from(model: model::change_feed::Model) -> Result<Self> {
+        Ok(Self {
+            sequence: model.sequence,
+            change_date: model.change_date,
+            event: serde_json::from_str::<ChangeEvent>(&model.event).map_err(|e| {
+                DomainError::InternalError(format!(
+                    "Invalid change feed entry {}: {}",
+                    model.sequence, e
+                ))
+            })?,
+        })
+    }
+}
+
+// Number of changes read at once when catching up with the feed.
+const CATCH_UP_BATCH: u64 = 1000;
+
+impl SqlBackendHandler {
+    /// Drops the search cache if changes that affect the searches were recorded after `since`,
+    /// e.g. by another instance sharing the database. Returns the sequence of the last change
+    /// read, to pass as `since` the next time.
+    ///
+    /// A change that commits after one with a higher sequence number (see `record_change`) is
+    /// missed, and only expires with the TTL of the cache.
+    pub async fn invalidate_cache_after(&self, since: i32) -> Result<i32> {
+        let Some(cache) = &self.search_cache else {
+            return Ok(since);
+        };
+        let mut last_sequence = since;
+        let mut affects_searches = false;
+        loop {
+            let changes = self.list_changes(last_sequence, CATCH_UP_BATCH).await?;
+            let Some(last) = changes.last() else {
+                break;
+            };
+            last_sequence = last.sequence;
+            affects_searches |= changes.iter().any(|c| c.event.affects_searches());
+        }
+        if affects_searches {
+            cache.invalidate();
+        }
+        Ok(last_sequence)
+    }
+}
+
+#[async_trait]
+impl ChangeFeedBackendHandler for SqlBackendHandler {
+    #[instrument(skip(self), level = "debug", err)]
+    async fn list_changes(&self, since: i32, limit: u64) -> Result<Vec<ChangeFeedEntry>> {
+        model::ChangeFeed::find()
+            .filter(ChangeFeedColumn::Sequence.gt(since))
+            .order_by_asc(ChangeFeedColumn::Sequence)
+            .limit(limit)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(ChangeFeedEntry::try_from)
+            .collect()
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn list_changes_for_user(&self, user_id: &UserId) -> Result<Vec<ChangeFeedEntry>> {
+        // The events are stored as JSON: narrow down the candidates in the DB, then check the
+        // actual user.
+        let mut changes = Vec::new();
+        for model in model::ChangeFeed::find()
+            .filter(ChangeFeedColumn::Event.contains(user_id.as_str()))
+            .order_by_asc(ChangeFeedColumn::Sequence)
+            .all(&self.sql_pool)
+            .await?
+        {
+            let entry = ChangeFeedEntry::try_from(model)?;
+            if entry.event.user_id() == Some(user_id) {
+                changes.push(entry);
+            }
+        }
+        Ok(changes)
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn get_last_change_sequence(&self) -> Result<i32> {
+        Ok(model::ChangeFeed::find()
+            .order_by_desc(ChangeFeedColumn::Sequence)
+            .one(&self.sql_pool)
+            .await?
+            .map(|model| model.sequence)
+            .unwrap_or(0))
+    }
+
+    fn subscribe_to_changes(&self) -> broadcast::Receiver<ChangeEvent> {
+        self.change_events.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::UserBackendHandler, sql_backend_handler::tests::*, types::UserId,
+    };
+    use pretty_assertions::assert_eq;
+
+    async fn list_events(handler: &SqlBackendHandler, since: i32, limit: u64) -> Vec<ChangeEvent> {
+        handler
+            .list_changes(since, limit)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|c| c.event)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_list_changes_since() {
+        let fixture = TestFixture::new().await;
+        let changes = fixture.handler.list_changes(0, 1000).await.unwrap();
+        assert!(!changes.is_empty());
+        assert!(changes.windows(2).all(|w| w[0].sequence < w[1].sequence));
+        let last = changes.last().unwrap().sequence;
+        assert_eq!(
+            fixture.handler.get_last_change_sequence().await.unwrap(),
+            last
+        );
+        assert_eq!(list_events(&fixture.handler, last, 1000).await, vec![]);
+
+        fixture
+            .handler
+            .remove_user_from_group(&UserId::new("bob"), fixture.groups[0])
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .delete_user(&UserId::new("bob"))
+            .await
+            .unwrap();
+        assert_eq!(
+            list_events(&fixture.handler, last, 1000).await,
+            vec![
+                ChangeEvent::MembershipRemoved {
+                    user_id: UserId::new("bob"),
+                    group_id: fixture.groups[0],
+                },
+                ChangeEvent::UserDeleted(UserId::new("bob")),
+            ]
+        );
+        assert_eq!(
+            list_events(&fixture.handler, last, 1).await,
+            vec![ChangeEvent::MembershipRemoved {
+                user_id: UserId::new("bob"),
+                group_id: fixture.groups[0],
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_cache_after_remote_change() {
+        let sql_pool = get_initialized_db().await;
+        let mut config = get_default_config();
+        config.cache_options.enabled = true;
+        // Two instances sharing the database.
+        let local = SqlBackendHandler::new(config.clone(), sql_pool.clone());
+        let remote = SqlBackendHandler::new(config, sql_pool);
+        insert_user_no_password(&local, "bob").await;
+        let since = local.get_last_change_sequence().await.unwrap();
+        assert_eq!(local.invalidate_cache_after(since).await.unwrap(), since);
+        assert_eq!(get_user_names(&local, None).await, vec!["bob"]);
+        insert_user_no_password(&remote, "john").await;
+        assert_eq!(get_user_names(&local, None).await, vec!["bob"]);
+        let last = local.invalidate_cache_after(since).await.unwrap();
+        assert!(last > since);
+        assert_eq!(get_user_names(&local, None).await, vec!["bob", "john"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_changes_for_user() {
+        let fixture = TestFixture::new().await;
+        let changes = fixture
+            .handler
+            .list_changes_for_user(&UserId::new("patrick"))
+            .await
+            .unwrap();
+        assert!(!changes.is_empty());
+        assert!(changes
+            .iter()
+            .all(|c| c.event.user_id() == Some(&UserId::new("patrick"))));
+        // A mere substring of a user ID doesn't match.
+        assert_eq!(
+            fixture
+                .handler
+                .list_changes_for_user(&UserId::new("pat"))
+                .await
+                .unwrap(),
+            vec![]
+        );
+    }
+}
@@ -1,18 +1,28 @@
-use crate::domain::{
-    error::{DomainError, Result},
-    handler::{
-        CreateGroupRequest, GroupBackendHandler, GroupListerBackendHandler, GroupRequestFilter,
-        UpdateGroupRequest,
+use crate::{
+    domain::{
+        change_events::ChangeEvent,
+        error::{DomainError, Result},
+        handler::{
+            CreateGroupRequest, GroupBackendHandler, GroupListerBackendHandler, GroupRequestFilter,
+            UpdateGroupRequest,
+        },
+        model::{self, GroupColumn, MembershipColumn},
+        nested_groups::GroupHierarchy,
+        posix_ids::{is_allocated, next_gid_number, GID_NUMBER_ATTRIBUTE},
+        sql_backend_handler::{check_attribute_value, check_quota, SqlBackendHandler},
+        types::{
+            AttributeName, AttributeValue, Group, GroupDetails, GroupId, GroupMembership,
+            Serialized, Uuid,
+        },
     },
-    model::{self, GroupColumn, MembershipColumn},
-    sql_backend_handler::SqlBackendHandler,
-    types::{AttributeName, AttributeValue, Group, GroupDetails, GroupId, Serialized, Uuid},
+    infra::{configuration::AttributeValidationRule, validation_webhook::WebhookOperation},
 };
 use async_trait::async_trait;
+use futures_util::TryStreamExt;
 use sea_orm::{
     sea_query::{Alias, Cond, Expr, Func, IntoCondition, OnConflict, SimpleExpr},
-    ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter, QueryOrder,
-    QuerySelect, QueryTrait, Set, TransactionTrait,
+    ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, PaginatorTrait, QueryFilter,
+    QueryOrder, QuerySelect, QueryTrait, Set, TransactionTrait,
 };
 use tracing::instrument;
 
@@ -77,6 +87,7 @@ fn get_group_filter_expr(filter: GroupRequestFilter) -> Cond {
         .into_condition(),
         AttributeEquality(name, value) => attribute_condition(name, Some(value)),
         CustomAttributePresent(name) => attribute_condition(name, None),
+        ModifiedSince(date) => GroupColumn::ModifiedDate.gte(date).into_condition(),
     }
 }
 
@@ -84,6 +95,28 @@ fn get_group_filter_expr(filter: GroupRequestFilter) -> Cond {
 impl GroupListerBackendHandler for SqlBackendHandler {
     #[instrument(skip(self), level = "debug", ret, err)]
     async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>> {
+        match &self.search_cache {
+            None => self.list_groups_from_db(filters).await,
+            Some(cache) => {
+                if let Some(groups) = cache.get_groups(&filters) {
+                    return Ok(groups);
+                }
+                let generation = cache.generation();
+                let groups = self.list_groups_from_db(filters.clone()).await?;
+                cache.insert_groups(generation, filters, groups.clone());
+                Ok(groups)
+            }
+        }
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn get_group_hierarchy(&self) -> Result<GroupHierarchy> {
+        self.get_group_hierarchy_from_db().await
+    }
+}
+
+impl SqlBackendHandler {
+    async fn list_groups_from_db(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>> {
         let filters = filters
             .map(|f| {
                 GroupColumn::GroupId
@@ -98,22 +131,26 @@ impl GroupListerBackendHandler for SqlBackendHandler {
                     .into_condition()
             })
             .unwrap_or_else(|| SimpleExpr::Value(true.into()).into_condition());
-        let results = model::Group::find()
-            .order_by_asc(GroupColumn::GroupId)
-            .find_with_related(model::Membership)
-            .filter(filters.clone())
-            .all(&self.sql_pool)
-            .await?;
-        let mut groups: Vec<_> = results
-            .into_iter()
-            .map(|(group, users)| {
-                let users: Vec<_> = users.into_iter().map(|u| u.user_id).collect();
-                Group {
-                    users,
-                    ..group.into()
+        // Rows are processed as they come in, without materializing the intermediate results.
+        let mut groups: Vec<Group> = Vec::new();
+        {
+            let rows = model::Group::find()
+                .order_by_asc(GroupColumn::GroupId)
+                .find_also_related(model::Membership)
+                .order_by_asc(MembershipColumn::UserId)
+                .filter(filters.clone())
+                .stream(&self.sql_pool)
+                .await?;
+            futures_util::pin_mut!(rows);
+            while let Some((group, membership)) = rows.try_next().await? {
+                if groups.last().map(|g| g.id) != Some(group.group_id) {
+                    groups.push(group.into());
                 }
-            })
-            .collect();
+                if let Some(membership) = membership {
+                    groups.last_mut().unwrap().users.push(membership.user_id);
+                }
+            }
+        }
         let attributes = model::GroupAttributes::find()
             .filter(
                 model::GroupAttributesColumn::GroupId.in_subquery(
@@ -126,15 +163,18 @@ impl GroupListerBackendHandler for SqlBackendHandler {
             )
             .order_by_asc(model::GroupAttributesColumn::GroupId)
             .order_by_asc(model::GroupAttributesColumn::AttributeName)
-            .all(&self.sql_pool)
+            .stream(&self.sql_pool)
             .await?;
-        let mut attributes_iter = attributes.into_iter().peekable();
-        use itertools::Itertools; // For take_while_ref
-        for group in groups.iter_mut() {
-            group.attributes = attributes_iter
-                .take_while_ref(|u| u.group_id == group.id)
-                .map(AttributeValue::from)
-                .collect();
+        futures_util::pin_mut!(attributes);
+        let mut groups_iter = groups.iter_mut().peekable();
+        while let Some(attribute) = attributes.try_next().await? {
+            while groups_iter
+                .next_if(|g| g.id != attribute.group_id)
+                .is_some()
+            {}
+            if let Some(group) = groups_iter.peek_mut() {
+                group.attributes.push(AttributeValue::from(attribute));
+            }
         }
         groups.sort_by(|g1, g2| g1.display_name.cmp(&g2.display_name));
         Ok(groups)
@@ -159,20 +199,51 @@ impl GroupBackendHandler for SqlBackendHandler {
         Ok(group_details)
     }
 
+    #[instrument(skip(self), level = "debug", ret, err)]
+    async fn list_group_memberships(&self, group_id: GroupId) -> Result<Vec<GroupMembership>> {
+        Ok(model::Membership::find()
+            .filter(MembershipColumn::GroupId.eq(group_id))
+            .order_by_asc(MembershipColumn::UserId)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(|m| GroupMembership {
+                user_id: m.user_id,
+                creation_date: m.creation_date,
+                expiry_date: m.expiry_date,
+            })
+            .collect())
+    }
+
     #[instrument(skip(self), level = "debug", err, fields(group_id = ?request.group_id))]
     async fn update_group(&self, request: UpdateGroupRequest) -> Result<()> {
-        Ok(self
-            .sql_pool
+        let group_id = request.group_id;
+        let request = self
+            .review_change(WebhookOperation::UpdateGroup, request)
+            .await?;
+        if request.group_id != group_id {
+            return Err(DomainError::ValidationError(
+                "The validation webhook cannot change the group ID of an update".to_owned(),
+            ));
+        }
+        let rules = self.config.attribute_validation.groups.clone();
+        self.sql_pool
             .transaction::<_, (), DomainError>(|transaction| {
-                Box::pin(
-                    async move { Self::update_group_with_transaction(request, transaction).await },
-                )
+                Box::pin(async move {
+                    Self::update_group_with_transaction(request, transaction, &rules).await?;
+                    Self::record_change(transaction, &ChangeEvent::GroupUpdated(group_id)).await
+                })
             })
-            .await?)
+            .await?;
+        self.publish_change(ChangeEvent::GroupUpdated(group_id));
+        Ok(())
     }
 
     #[instrument(skip(self), level = "debug", ret, err)]
     async fn create_group(&self, request: CreateGroupRequest) -> Result<GroupId> {
+        let request = self
+            .review_change(WebhookOperation::CreateGroup, request)
+            .await?;
         let now = chrono::Utc::now().naive_utc();
         let uuid = Uuid::from_name_and_date(request.display_name.as_str(), &now);
         let lower_display_name = request.display_name.as_str().to_lowercase();
@@ -180,50 +251,75 @@ impl GroupBackendHandler for SqlBackendHandler {
             display_name: Set(request.display_name),
             lowercase_display_name: Set(lower_display_name),
             creation_date: Set(now),
+            modified_date: Set(now),
             uuid: Set(uuid),
             ..Default::default()
         };
-        Ok(self
+        let rules = self.config.attribute_validation.groups.clone();
+        let gid_number_start = self.config.posix_defaults.gid_number_start;
+        let max_groups = self.config.quota_options.max_groups;
+        let group_id = self
             .sql_pool
             .transaction::<_, GroupId, DomainError>(|transaction| {
                 Box::pin(async move {
+                    if let Some(max_groups) = max_groups {
+                        let count = model::Group::find().count(transaction).await?;
+                        check_quota(count, max_groups, "groups")?;
+                    }
                     let schema = Self::get_schema_with_transaction(transaction).await?;
                     let group_id = new_group.insert(transaction).await?.group_id;
                     let mut new_group_attributes = Vec::new();
+                    let mut needs_gid_number =
+                        is_allocated(&schema.group_attributes, GID_NUMBER_ATTRIBUTE);
                     for attribute in request.attributes {
-                        if schema
-                            .group_attributes
-                            .get_attribute_type(&attribute.name)
-                            .is_some()
+                        if attribute.name.as_str() == GID_NUMBER_ATTRIBUTE {
+                            needs_gid_number = false;
+                        }
+                        if let Some((attribute_type, is_list)) =
+                            schema.group_attributes.get_attribute_type(&attribute.name)
                         {
+                            check_attribute_value(&rules, &attribute, attribute_type, is_list)?;
                             new_group_attributes.push(model::group_attributes::ActiveModel {
                                 group_id: Set(group_id),
                                 attribute_name: Set(attribute.name),
                                 value: Set(attribute.value),
                             });
                         } else {
-                            return Err(DomainError::InternalError(format!(
+                            return Err(DomainError::ValidationError(format!(
                                 "Attribute name {} doesn't exist in the group schema,
                                     yet was attempted to be inserted in the database",
                                 &attribute.name
                             )));
                         }
                     }
+                    if needs_gid_number {
+                        new_group_attributes.push(model::group_attributes::ActiveModel {
+                            group_id: Set(group_id),
+                            attribute_name: Set(GID_NUMBER_ATTRIBUTE.into()),
+                            value: Set(Serialized::from(
+                                &next_gid_number(transaction, gid_number_start).await?,
+                            )),
+                        });
+                    }
                     if !new_group_attributes.is_empty() {
                         model::GroupAttributes::insert_many(new_group_attributes)
                             .exec(transaction)
                             .await?;
                     }
+                    Self::record_change(transaction, &ChangeEvent::GroupCreated(group_id)).await?;
                     Ok(group_id)
                 })
             })
-            .await?)
+            .await?;
+        self.publish_change(ChangeEvent::GroupCreated(group_id));
+        Ok(group_id)
     }
 
     #[instrument(skip(self), level = "debug", err)]
     async fn delete_group(&self, group_id: GroupId) -> Result<()> {
+        let transaction = self.sql_pool.begin().await?;
         let res = model::Group::delete_by_id(group_id)
-            .exec(&self.sql_pool)
+            .exec(&transaction)
             .await?;
         if res.rows_affected == 0 {
             return Err(DomainError::EntityNotFound(format!(
@@ -231,7 +327,8 @@ impl GroupBackendHandler for SqlBackendHandler {
                 group_id
             )));
         }
-        Ok(())
+        self.commit_change(transaction, ChangeEvent::GroupDeleted(group_id))
+            .await
     }
 }
 
@@ -239,6 +336,7 @@ impl SqlBackendHandler {
     async fn update_group_with_transaction(
         request: UpdateGroupRequest,
         transaction: &DatabaseTransaction,
+        rules: &[AttributeValidationRule],
     ) -> Result<()> {
         let lower_display_name = request
             .display_name
@@ -255,18 +353,17 @@ impl SqlBackendHandler {
         let mut remove_group_attributes = Vec::new();
         let schema = Self::get_schema_with_transaction(transaction).await?;
         for attribute in request.insert_attributes {
-            if schema
-                .group_attributes
-                .get_attribute_type(&attribute.name)
-                .is_some()
+            if let Some((attribute_type, is_list)) =
+                schema.group_attributes.get_attribute_type(&attribute.name)
             {
+                check_attribute_value(rules, &attribute, attribute_type, is_list)?;
                 update_group_attributes.push(model::group_attributes::ActiveModel {
                     group_id: Set(request.group_id),
                     attribute_name: Set(attribute.name.to_owned()),
                     value: Set(attribute.value),
                 });
             } else {
-                return Err(DomainError::InternalError(format!(
+                return Err(DomainError::ValidationError(format!(
                     "Group attribute name {} doesn't exist in the schema, yet was attempted to be inserted in the database",
                     &attribute.name
                 )));
@@ -280,7 +377,7 @@ impl SqlBackendHandler {
             {
                 remove_group_attributes.push(attribute);
             } else {
-                return Err(DomainError::InternalError(format!(
+                return Err(DomainError::ValidationError(format!(
                     "Group attribute name {} doesn't exist in the schema, yet was attempted to be removed from the database",
                     attribute
                 )));
@@ -484,6 +581,30 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_list_group_memberships() {
+        let fixture = TestFixture::new().await;
+        let memberships = fixture
+            .handler
+            .list_group_memberships(fixture.groups[1])
+            .await
+            .unwrap();
+        assert_eq!(
+            memberships
+                .iter()
+                .map(|m| m.user_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["john", "patrick"]
+        );
+        assert!(memberships.iter().all(|m| m.creation_date.is_some()));
+        assert!(fixture
+            .handler
+            .list_group_memberships(fixture.groups[2])
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
     #[tokio::test]
     async fn test_update_group() {
         let fixture = TestFixture::new().await;
@@ -560,10 +681,16 @@ mod tests {
         assert_eq!(group_details.display_name, "New Group".into());
         assert_eq!(
             group_details.attributes,
-            vec![AttributeValue {
-                name: "new_attribute".into(),
-                value: Serialized::from("value"),
-            }]
+            vec![
+                AttributeValue {
+                    name: "gidnumber".into(),
+                    value: Serialized::from(&10003i64),
+                },
+                AttributeValue {
+                    name: "new_attribute".into(),
+                    value: Serialized::from("value"),
+                }
+            ]
         );
     }
 
@@ -582,6 +709,10 @@ mod tests {
             .await
             .unwrap();
         let group_id = fixture.groups[0];
+        let gid_number = AttributeValue {
+            name: "gidnumber".into(),
+            value: Serialized::from(&10000i64),
+        };
         let attributes = vec![AttributeValue {
             name: "new_attribute".into(),
             value: Serialized::from(&42i64),
@@ -597,7 +728,10 @@ mod tests {
             .await
             .unwrap();
         let details = fixture.handler.get_group_details(group_id).await.unwrap();
-        assert_eq!(details.attributes, attributes);
+        assert_eq!(
+            details.attributes,
+            [vec![gid_number.clone()], attributes].concat()
+        );
         fixture
             .handler
             .update_group(UpdateGroupRequest {
@@ -609,7 +743,7 @@ mod tests {
             .await
             .unwrap();
         let details = fixture.handler.get_group_details(group_id).await.unwrap();
-        assert_eq!(details.attributes, Vec::new());
+        assert_eq!(details.attributes, vec![gid_number]);
     }
 
     #[tokio::test]
@@ -632,4 +766,21 @@ mod tests {
             .await
             .unwrap_err();
     }
+
+    #[tokio::test]
+    async fn test_create_group_max_groups() {
+        let mut config = get_default_config();
+        config.quota_options.max_groups = Some(2);
+        let handler = SqlBackendHandler::new(config, get_initialized_db().await);
+        insert_group(&handler, "Group 1").await;
+        insert_group(&handler, "Group 2").await;
+        let error = handler
+            .create_group(CreateGroupRequest {
+                display_name: "Group 3".into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(error, DomainError::QuotaExceeded(_)), "{}", error);
+    }
 }
@@ -0,0 +1,236 @@
+use crate::domain::{
+    error::Result,
+    group_rules::{compute_changes, GroupRuleChange},
+    handler::{
+        GroupListerBackendHandler, GroupRuleBackendHandler, ReadSchemaBackendHandler,
+        UserBackendHandler, UserListerBackendHandler,
+    },
+    schema::PublicSchema,
+    sql_backend_handler::SqlBackendHandler,
+    types::{GroupId, GroupName, UserId},
+};
+use async_trait::async_trait;
+use std::collections::{HashMap, HashSet};
+use tracing::{error, info, instrument};
+
+impl SqlBackendHandler {
+    pub(crate) async fn get_groups_by_name(&self) -> Result<HashMap<GroupName, GroupId>> {
+        Ok(self
+            .list_groups(None)
+            .await?
+            .into_iter()
+            .map(|g| (g.display_name, g.id))
+            .collect())
+    }
+
+    async fn compute_group_rule_changes(&self) -> Result<Vec<GroupRuleChange>> {
+        let rules = &self.config.group_rules;
+        if rules.is_empty() {
+            return Ok(Vec::new());
+        }
+        let groups = self.get_groups_by_name().await?;
+        let schema = PublicSchema::from(self.get_schema().await?);
+        Ok(self
+            .list_users(None, true)
+            .await?
+            .into_iter()
+            .flat_map(|u| {
+                let user_groups = u
+                    .groups
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|g| g.group_id)
+                    .collect::<HashSet<_>>();
+                compute_changes(rules, &u.user, &user_groups, &groups, &schema)
+            })
+            .collect())
+    }
+
+    async fn apply_group_rule_changes(&self, changes: &[GroupRuleChange]) -> Result<()> {
+        for change in changes {
+            info!(
+                r#"Group rule "{}": {} {} {} {}"#,
+                change.rule,
+                if change.added { "adding" } else { "removing" },
+                change.user_id,
+                if change.added { "to" } else { "from" },
+                change.group_name,
+            );
+            if change.added {
+                self.add_membership(&change.user_id, change.group_id, Some(change.rule.clone()))
+                    .await?;
+            } else {
+                self.remove_membership(&change.user_id, change.group_id, Some(change.rule.clone()))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn try_apply_group_rules_to_user(&self, user_id: &UserId) -> Result<()> {
+        let user = self.get_user_details(user_id).await?;
+        let user_groups = self
+            .get_user_groups(user_id)
+            .await?
+            .into_iter()
+            .map(|g| g.group_id)
+            .collect::<HashSet<_>>();
+        let changes = compute_changes(
+            &self.config.group_rules,
+            &user,
+            &user_groups,
+            &self.get_groups_by_name().await?,
+            &PublicSchema::from(self.get_schema().await?),
+        );
+        self.apply_group_rule_changes(&changes).await
+    }
+
+    /// Called after the creation or update of the user. That change is already committed, so a
+    /// failure is only logged: the next update or `apply_group_rules` catches up.
+    pub(crate) async fn apply_group_rules_to_user(&self, user_id: &UserId) {
+        if self.config.group_rules.is_empty() {
+            return;
+        }
+        if let Err(e) = self.try_apply_group_rules_to_user(user_id).await {
+            error!("Could not apply the group rules to {}: {}", user_id, e);
+        }
+    }
+}
+
+#[async_trait]
+impl GroupRuleBackendHandler for SqlBackendHandler {
+    #[instrument(skip(self), level = "debug", err)]
+    async fn preview_group_rules(&self) -> Result<Vec<GroupRuleChange>> {
+        self.compute_group_rule_changes().await
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn apply_group_rules(&self) -> Result<Vec<GroupRuleChange>> {
+        let changes = self.compute_group_rule_changes().await?;
+        self.apply_group_rule_changes(&changes).await?;
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::{
+            change_events::ChangeEvent,
+            handler::{
+                ChangeFeedBackendHandler, CreateAttributeRequest, SchemaBackendHandler,
+                UpdateUserRequest,
+            },
+            sql_backend_handler::tests::*,
+            types::{AttributeType, AttributeValue, Serialized},
+        },
+        infra::configuration::GroupRule,
+    };
+    use pretty_assertions::assert_eq;
+
+    async fn set_department(handler: &SqlBackendHandler, user: &str, department: &str) {
+        handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new(user),
+                insert_attributes: vec![AttributeValue {
+                    name: "department".into(),
+                    value: Serialized::from(department),
+                }],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+
+    async fn get_group_ids(handler: &SqlBackendHandler, user: &str) -> Vec<GroupId> {
+        let mut ids = handler
+            .get_user_groups(&UserId::new(user))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|g| g.group_id)
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn test_group_rules() {
+        let mut config = get_default_config();
+        config.group_rules = vec![GroupRule {
+            name: "it".to_owned(),
+            attribute: "department".into(),
+            value: "IT".to_owned(),
+            group: "it-staff".into(),
+        }];
+        let handler = SqlBackendHandler::new(config, get_initialized_db().await);
+        handler
+            .add_user_attribute(CreateAttributeRequest {
+                name: "department".into(),
+                attribute_type: AttributeType::String,
+                is_list: false,
+                is_visible: true,
+                is_editable: true,
+            })
+            .await
+            .unwrap();
+        insert_user_no_password(&handler, "bob").await;
+        insert_user_no_password(&handler, "patrick").await;
+        set_department(&handler, "patrick", "IT").await;
+        // The group doesn't exist yet: the rule is ignored.
+        assert_eq!(get_group_ids(&handler, "patrick").await, Vec::new());
+        let it_staff = insert_group(&handler, "it-staff").await;
+        assert_eq!(
+            handler.preview_group_rules().await.unwrap(),
+            vec![GroupRuleChange {
+                user_id: UserId::new("patrick"),
+                group_id: it_staff,
+                group_name: "it-staff".into(),
+                rule: "it".to_owned(),
+                added: true,
+            }]
+        );
+        // The preview doesn't change anything.
+        assert_eq!(get_group_ids(&handler, "patrick").await, Vec::new());
+        assert_eq!(handler.apply_group_rules().await.unwrap().len(), 1);
+        assert_eq!(get_group_ids(&handler, "patrick").await, vec![it_staff]);
+        assert_eq!(handler.preview_group_rules().await.unwrap(), Vec::new());
+
+        // Applied on update.
+        set_department(&handler, "bob", "it").await;
+        assert_eq!(get_group_ids(&handler, "bob").await, vec![it_staff]);
+        set_department(&handler, "patrick", "HR").await;
+        assert_eq!(get_group_ids(&handler, "patrick").await, Vec::new());
+
+        let events = handler
+            .list_changes_for_user(&UserId::new("patrick"))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|c| c.event)
+            .filter(|e| {
+                matches!(
+                    e,
+                    ChangeEvent::MembershipAddedByRule { .. }
+                        | ChangeEvent::MembershipRemovedByRule { .. }
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                ChangeEvent::MembershipAddedByRule {
+                    user_id: UserId::new("patrick"),
+                    group_id: it_staff,
+                    rule: "it".to_owned(),
+                },
+                ChangeEvent::MembershipRemovedByRule {
+                    user_id: UserId::new("patrick"),
+                    group_id: it_staff,
+                    rule: "it".to_owned(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use crate::domain::{
+    error::{DomainError, Result},
+    handler::IdentityLinkBackendHandler,
+    identity_links::{ExternalIdentity, IdentityLink},
+    model::{self, IdentityLinksColumn, UserColumn},
+    sql_backend_handler::SqlBackendHandler,
+    types::UserId,
+};
+use async_trait::async_trait;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder, Set};
+use tracing::{info, instrument};
+
+#[async_trait]
+impl IdentityLinkBackendHandler for SqlBackendHandler {
+    #[instrument(skip(self), level = "debug", err)]
+    async fn list_identity_links(&self, user_id: &UserId) -> Result<Vec<IdentityLink>> {
+        Ok(model::IdentityLinks::find()
+            .filter(IdentityLinksColumn::UserId.eq(user_id))
+            .order_by_asc(IdentityLinksColumn::Provider)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn add_identity_link(
+        &self,
+        user_id: &UserId,
+        provider: &str,
+        subject: &str,
+    ) -> Result<IdentityLink> {
+        if let Some(link) =
+            model::IdentityLinks::find_by_id((provider.to_owned(), subject.to_owned()))
+                .one(&self.sql_pool)
+                .await?
+        {
+            return Err(DomainError::Conflict(format!(
+                "The {} identity '{}' is already linked to '{}'",
+                provider, subject, link.user_id
+            )));
+        }
+        if model::IdentityLinks::find()
+            .filter(IdentityLinksColumn::UserId.eq(user_id))
+            .filter(IdentityLinksColumn::Provider.eq(provider))
+            .one(&self.sql_pool)
+            .await?
+            .is_some()
+        {
+            return Err(DomainError::Conflict(format!(
+                "'{}' already has a {} identity",
+                user_id, provider
+            )));
+        }
+        let link = IdentityLink {
+            provider: provider.to_owned(),
+            subject: subject.to_owned(),
+            user_id: user_id.clone(),
+            creation_date: chrono::Utc::now().naive_utc(),
+        };
+        model::IdentityLinks::insert(model::identity_links::ActiveModel {
+            provider: Set(link.provider.clone()),
+            subject: Set(link.subject.clone()),
+            user_id: Set(link.user_id.clone()),
+            creation_date: Set(link.creation_date),
+        })
+        .exec(&self.sql_pool)
+        .await?;
+        Ok(link)
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn delete_identity_link(&self, provider: &str, subject: &str) -> Result<()> {
+        let res = model::IdentityLinks::delete_by_id((provider.to_owned(), subject.to_owned()))
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No {} identity '{}'",
+                provider, subject
+            )));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", ret, err)]
+    async fn resolve_external_identity(
+        &self,
+        identity: &ExternalIdentity,
+    ) -> Result<Option<UserId>> {
+        if let Some(link) =
+            model::IdentityLinks::find_by_id((identity.provider.clone(), identity.subject.clone()))
+                .one(&self.sql_pool)
+                .await?
+        {
+            return Ok(Some(link.user_id));
+        }
+        let email = match &identity.email {
+            Some(email) => email,
+            None => return Ok(None),
+        };
+        let user = match model::User::find()
+            .filter(UserColumn::LowercaseEmail.eq(email.as_str().to_lowercase()))
+            .one(&self.sql_pool)
+            .await?
+        {
+            Some(user) => user,
+            None => return Ok(None),
+        };
+        // Anyone can claim an unverified email: linking it would hand over the account.
+        if !identity.email_verified {
+            return Err(DomainError::Conflict(format!(
+                "The email of the {} identity '{}' belongs to '{}', but is not verified",
+                identity.provider, identity.subject, user.user_id
+            )));
+        }
+        self.add_identity_link(&user.user_id, &identity.provider, &identity.subject)
+            .await?;
+        info!(
+            r#"Linked the {} identity "{}" to "{}" by email"#,
+            identity.provider, identity.subject, user.user_id
+        );
+        Ok(Some(user.user_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{handler::UserBackendHandler, sql_backend_handler::tests::*};
+    use pretty_assertions::assert_eq;
+
+    fn identity(subject: &str, email: Option<&str>, email_verified: bool) -> ExternalIdentity {
+        ExternalIdentity {
+            provider: "google".to_owned(),
+            subject: subject.to_owned(),
+            email: email.map(Into::into),
+            email_verified,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_and_delete_identity_links() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let bob = UserId::new("bob");
+        handler
+            .add_identity_link(&bob, "google", "1234")
+            .await
+            .unwrap();
+        handler
+            .add_identity_link(&bob, "saml", "bob")
+            .await
+            .unwrap();
+        assert_eq!(
+            handler
+                .list_identity_links(&bob)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|l| (l.provider, l.subject))
+                .collect::<Vec<_>>(),
+            vec![
+                ("google".to_owned(), "1234".to_owned()),
+                ("saml".to_owned(), "bob".to_owned())
+            ]
+        );
+        // The identity belongs to bob.
+        assert!(matches!(
+            handler
+                .add_identity_link(&UserId::new("patrick"), "google", "1234")
+                .await,
+            Err(DomainError::Conflict(_))
+        ));
+        // Bob already has a google identity.
+        assert!(matches!(
+            handler.add_identity_link(&bob, "google", "5678").await,
+            Err(DomainError::Conflict(_))
+        ));
+        assert!(matches!(
+            handler
+                .add_identity_link(&UserId::new("unknown"), "google", "5678")
+                .await,
+            Err(DomainError::EntityNotFound(_))
+        ));
+
+        handler
+            .delete_identity_link("google", "1234")
+            .await
+            .unwrap();
+        assert!(matches!(
+            handler.delete_identity_link("google", "1234").await,
+            Err(DomainError::EntityNotFound(_))
+        ));
+        assert_eq!(handler.list_identity_links(&bob).await.unwrap().len(), 1);
+
+        // The links go away with the user.
+        handler.delete_user(&bob).await.unwrap();
+        handler
+            .add_identity_link(&UserId::new("patrick"), "saml", "bob")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resolve_external_identity() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let bob = UserId::new("bob");
+        handler
+            .add_identity_link(&bob, "google", "1234")
+            .await
+            .unwrap();
+        // The link wins over the email.
+        assert_eq!(
+            handler
+                .resolve_external_identity(&identity("1234", Some("patrick@bob.bob"), true))
+                .await
+                .unwrap(),
+            Some(bob)
+        );
+        assert_eq!(
+            handler
+                .resolve_external_identity(&identity("5678", Some("unknown@bob.bob"), true))
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            handler
+                .resolve_external_identity(&identity("5678", None, true))
+                .await
+                .unwrap(),
+            None
+        );
+        assert!(matches!(
+            handler
+                .resolve_external_identity(&identity("5678", Some("Patrick@Bob.bob"), false))
+                .await,
+            Err(DomainError::Conflict(_))
+        ));
+        // A verified email links the identity, case-insensitively.
+        let patrick = UserId::new("patrick");
+        assert_eq!(
+            handler
+                .resolve_external_identity(&identity("5678", Some("Patrick@Bob.bob"), true))
+                .await
+                .unwrap(),
+            Some(patrick.clone())
+        );
+        assert_eq!(
+            handler
+                .resolve_external_identity(&identity("5678", None, false))
+                .await
+                .unwrap(),
+            Some(patrick.clone())
+        );
+        // Patrick already has another google identity.
+        assert!(matches!(
+            handler
+                .resolve_external_identity(&identity("9999", Some("patrick@bob.bob"), true))
+                .await,
+            Err(DomainError::Conflict(_))
+        ));
+    }
+}
@@ -0,0 +1,147 @@
+use crate::domain::{
+    error::{DomainError, Result},
+    handler::MembershipExpiryBackendHandler,
+    model::{self, MembershipColumn},
+    sql_backend_handler::SqlBackendHandler,
+    types::{GroupId, GroupName, UserId},
+};
+use async_trait::async_trait;
+use sea_orm::{sea_query::Expr, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+use tracing::{info, instrument};
+
+#[async_trait]
+impl MembershipExpiryBackendHandler for SqlBackendHandler {
+    #[instrument(skip(self), level = "debug", err)]
+    async fn set_membership_expiry(
+        &self,
+        user_id: &UserId,
+        group_id: GroupId,
+        expiry_date: Option<chrono::NaiveDateTime>,
+    ) -> Result<()> {
+        let res = model::Membership::update_many()
+            .col_expr(MembershipColumn::ExpiryDate, Expr::value(expiry_date))
+            .filter(MembershipColumn::UserId.eq(user_id))
+            .filter(MembershipColumn::GroupId.eq(group_id))
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such membership: '{}' -> {:?}",
+                user_id, group_id
+            )));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn purge_expired_memberships(&self) -> Result<Vec<(UserId, GroupName)>> {
+        let now = chrono::Utc::now().naive_utc();
+        let expired = model::Membership::find()
+            .select_only()
+            .column(MembershipColumn::UserId)
+            .column(MembershipColumn::GroupId)
+            .column(model::GroupColumn::DisplayName)
+            .inner_join(model::Group)
+            .filter(MembershipColumn::ExpiryDate.lt(now))
+            .order_by_asc(MembershipColumn::ExpiryDate)
+            .into_tuple::<(UserId, GroupId, GroupName)>()
+            .all(&self.sql_pool)
+            .await?;
+        let mut removed = Vec::new();
+        for (user_id, group_id, group_name) in expired {
+            // Goes through the regular removal, so that the change gets recorded.
+            self.remove_membership(&user_id, group_id, None).await?;
+            info!(
+                r#"The membership of "{}" in "{}" expired"#,
+                &user_id, &group_name
+            );
+            removed.push((user_id, group_name));
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{GroupBackendHandler, UserBackendHandler},
+        sql_backend_handler::tests::*,
+    };
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_membership_expiry() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let bob = UserId::new("bob");
+        let patrick = UserId::new("patrick");
+        let now = chrono::Utc::now().naive_utc();
+        handler
+            .set_membership_expiry(
+                &bob,
+                fixture.groups[0],
+                Some(now - chrono::Duration::hours(1)),
+            )
+            .await
+            .unwrap();
+        handler
+            .set_membership_expiry(
+                &patrick,
+                fixture.groups[0],
+                Some(now + chrono::Duration::days(1)),
+            )
+            .await
+            .unwrap();
+        let memberships = handler
+            .list_group_memberships(fixture.groups[0])
+            .await
+            .unwrap();
+        assert_eq!(
+            memberships
+                .iter()
+                .map(|m| (m.user_id.clone(), m.expiry_date.is_some()))
+                .collect::<Vec<_>>(),
+            vec![(bob.clone(), true), (patrick.clone(), true)]
+        );
+        assert_eq!(
+            handler.purge_expired_memberships().await.unwrap(),
+            vec![(bob.clone(), GroupName::from("Best Group"))]
+        );
+        assert!(handler.get_user_groups(&bob).await.unwrap().is_empty());
+        assert_eq!(handler.get_user_groups(&patrick).await.unwrap().len(), 2);
+        // Nothing left to purge.
+        assert_eq!(handler.purge_expired_memberships().await.unwrap(), vec![]);
+    }
+
+    #[tokio::test]
+    async fn test_membership_expiry_cleared() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let bob = UserId::new("bob");
+        handler
+            .set_membership_expiry(
+                &bob,
+                fixture.groups[0],
+                Some(chrono::Utc::now().naive_utc() - chrono::Duration::hours(1)),
+            )
+            .await
+            .unwrap();
+        handler
+            .set_membership_expiry(&bob, fixture.groups[0], None)
+            .await
+            .unwrap();
+        assert_eq!(handler.purge_expired_memberships().await.unwrap(), vec![]);
+        assert_eq!(handler.get_user_groups(&bob).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_membership_expiry_unknown_membership() {
+        let fixture = TestFixture::new().await;
+        assert!(fixture
+            .handler
+            .set_membership_expiry(&UserId::new("NoGroup"), fixture.groups[0], None)
+            .await
+            .is_err());
+    }
+}
@@ -1,4 +1,6 @@
 use crate::domain::{
+    jwt_sql_tables::{JwtRefreshStorage, PasswordResetTokens},
+    secret_tokens::{hash_verifier, split_token},
     sql_tables::{DbConnection, SchemaVersion, LAST_SCHEMA_VERSION},
     types::{AttributeType, GroupId, JpegPhoto, Serialized, UserId, Uuid},
 };
@@ -29,6 +31,8 @@ pub enum Users {
     TotpSecret,
     MfaType,
     Uuid,
+    ExternalId,
+    ModifiedDate,
 }
 
 #[derive(DeriveIden, PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy)]
@@ -39,6 +43,7 @@ pub enum Groups {
     LowercaseDisplayName,
     CreationDate,
     Uuid,
+    ModifiedDate,
 }
 
 #[derive(DeriveIden, Clone, Copy)]
@@ -46,6 +51,8 @@ pub enum Memberships {
     Table,
     UserId,
     GroupId,
+    CreationDate,
+    ExpiryDate,
 }
 
 #[allow(clippy::enum_variant_names)] // The table names are generated from the enum.
@@ -102,6 +109,118 @@ pub enum GroupObjectClasses {
     ObjectClass,
 }
 
+#[derive(DeriveIden, Clone, Copy)]
+pub enum ChangeFeed {
+    Table,
+    Sequence,
+    ChangeDate,
+    Event,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+pub enum AccountDeletions {
+    Table,
+    UserId,
+    Token,
+    VerifierHash,
+    RequestDate,
+    DeletionDate,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+pub enum PendingChanges {
+    Table,
+    Id,
+    RequestedBy,
+    RequestDate,
+    Change,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+pub enum GroupAttributeTemplates {
+    Table,
+    GroupId,
+    AttributeName,
+    Value,
+    Overwrite,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+pub enum AvatarSyncs {
+    Table,
+    UserId,
+    OptOut,
+    SyncedAvatarHash,
+    SyncDate,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+pub enum IdentityLinks {
+    Table,
+    Provider,
+    Subject,
+    UserId,
+    CreationDate,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+pub enum PendingProvisionings {
+    Table,
+    Id,
+    Provider,
+    Subject,
+    RequestDate,
+    Request,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+pub enum AccessReviewCampaigns {
+    Table,
+    Id,
+    Name,
+    CreatedBy,
+    CreationDate,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+pub enum AccessReviews {
+    Table,
+    Id,
+    CampaignId,
+    GroupId,
+    Reviewer,
+    Token,
+    VerifierHash,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+pub enum AccessReviewDecisions {
+    Table,
+    ReviewId,
+    UserId,
+    Keep,
+    DecisionDate,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+pub enum GroupMemberships {
+    Table,
+    ParentGroupId,
+    MemberGroupId,
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+pub enum UserProfileChanges {
+    Table,
+    Id,
+    UserId,
+    Field,
+    OldValue,
+    NewValue,
+    Actor,
+    ChangeDate,
+}
+
 // Metadata about the SQL DB.
 #[derive(DeriveIden)]
 pub enum Metadata {
@@ -1116,6 +1235,981 @@ async fn migrate_to_v10(transaction: DatabaseTransaction) -> Result<DatabaseTran
     Ok(transaction)
 }
 
+async fn migrate_to_v11(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(ChangeFeed::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ChangeFeed::Sequence)
+                            .integer()
+                            .auto_increment()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ChangeFeed::ChangeDate)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ChangeFeed::Event).text().not_null()),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v12(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(PendingChanges::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PendingChanges::Id)
+                            .integer()
+                            .auto_increment()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(PendingChanges::RequestedBy)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PendingChanges::RequestDate)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(PendingChanges::Change).text().not_null()),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v13(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(AccountDeletions::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AccountDeletions::UserId)
+                            .string_len(255)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(AccountDeletions::Token)
+                            .string_len(255)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(AccountDeletions::RequestDate)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AccountDeletions::DeletionDate).date_time())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("AccountDeletionsUserForeignKey")
+                            .from(AccountDeletions::Table, AccountDeletions::UserId)
+                            .to(Users::Table, Users::UserId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    ),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v14(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(GroupAttributeTemplates::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(GroupAttributeTemplates::GroupId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(GroupAttributeTemplates::AttributeName)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(GroupAttributeTemplates::Value)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(GroupAttributeTemplates::Overwrite)
+                            .boolean()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("GroupAttributeTemplatesGroupIdForeignKey")
+                            .from(
+                                GroupAttributeTemplates::Table,
+                                GroupAttributeTemplates::GroupId,
+                            )
+                            .to(Groups::Table, Groups::GroupId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("GroupAttributeTemplatesNameForeignKey")
+                            .from(
+                                GroupAttributeTemplates::Table,
+                                GroupAttributeTemplates::AttributeName,
+                            )
+                            .to(
+                                UserAttributeSchema::Table,
+                                UserAttributeSchema::UserAttributeSchemaName,
+                            )
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(GroupAttributeTemplates::GroupId)
+                            .col(GroupAttributeTemplates::AttributeName),
+                    ),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v15(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(AvatarSyncs::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AvatarSyncs::UserId)
+                            .string_len(255)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AvatarSyncs::OptOut).boolean().not_null())
+                    .col(ColumnDef::new(AvatarSyncs::SyncedAvatarHash).string_len(255))
+                    .col(ColumnDef::new(AvatarSyncs::SyncDate).date_time())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("AvatarSyncsUserForeignKey")
+                            .from(AvatarSyncs::Table, AvatarSyncs::UserId)
+                            .to(Users::Table, Users::UserId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    ),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v16(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The existing memberships have no known creation date.
+    transaction
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(Memberships::Table)
+                    .add_column(ColumnDef::new(Memberships::CreationDate).date_time()),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v17(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // A custom attribute with the same name is kept as is.
+    for name in ["preferred_language", "timezone"] {
+        if transaction
+            .query_one(
+                builder.build(
+                    Query::select()
+                        .from(UserAttributeSchema::Table)
+                        .column(UserAttributeSchema::UserAttributeSchemaName)
+                        .cond_where(
+                            Expr::col(UserAttributeSchema::UserAttributeSchemaName).eq(name),
+                        ),
+                ),
+            )
+            .await?
+            .is_some()
+        {
+            warn!(
+                r#"The user attribute "{}" already exists, keeping its definition"#,
+                name
+            );
+            continue;
+        }
+        transaction
+            .execute(
+                builder.build(
+                    Query::insert()
+                        .into_table(UserAttributeSchema::Table)
+                        .columns([
+                            UserAttributeSchema::UserAttributeSchemaName,
+                            UserAttributeSchema::UserAttributeSchemaType,
+                            UserAttributeSchema::UserAttributeSchemaIsList,
+                            UserAttributeSchema::UserAttributeSchemaIsUserVisible,
+                            UserAttributeSchema::UserAttributeSchemaIsUserEditable,
+                            UserAttributeSchema::UserAttributeSchemaIsHardcoded,
+                        ])
+                        .values_panic([
+                            name.into(),
+                            AttributeType::String.into(),
+                            false.into(),
+                            true.into(),
+                            true.into(),
+                            true.into(),
+                        ]),
+                ),
+            )
+            .await?;
+    }
+    Ok(transaction)
+}
+
+async fn migrate_to_v18(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    transaction
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::ExternalId).string_len(255)),
+            ),
+        )
+        .await?;
+    // Several users can have no external ID: NULLs are distinct in unique indices.
+    transaction
+        .execute(
+            builder.build(
+                Index::create()
+                    .if_not_exists()
+                    .name("unique-user-external-id")
+                    .table(Users::Table)
+                    .col(Users::ExternalId)
+                    .unique(),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v19(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The IDs are allocated by the server, and the existing users and groups get theirs at
+    // startup. A custom attribute with the same name is kept as is.
+    if transaction
+        .query_one(
+            builder.build(
+                Query::select()
+                    .from(UserAttributeSchema::Table)
+                    .column(UserAttributeSchema::UserAttributeSchemaName)
+                    .cond_where(
+                        Expr::col(UserAttributeSchema::UserAttributeSchemaName).eq("uidnumber"),
+                    ),
+            ),
+        )
+        .await?
+        .is_some()
+    {
+        warn!(r#"The user attribute "uidnumber" already exists, keeping its definition"#);
+    } else {
+        transaction
+            .execute(
+                builder.build(
+                    Query::insert()
+                        .into_table(UserAttributeSchema::Table)
+                        .columns([
+                            UserAttributeSchema::UserAttributeSchemaName,
+                            UserAttributeSchema::UserAttributeSchemaType,
+                            UserAttributeSchema::UserAttributeSchemaIsList,
+                            UserAttributeSchema::UserAttributeSchemaIsUserVisible,
+                            UserAttributeSchema::UserAttributeSchemaIsUserEditable,
+                            UserAttributeSchema::UserAttributeSchemaIsHardcoded,
+                        ])
+                        .values_panic([
+                            "uidnumber".into(),
+                            AttributeType::Integer.into(),
+                            false.into(),
+                            true.into(),
+                            false.into(),
+                            true.into(),
+                        ]),
+                ),
+            )
+            .await?;
+    }
+    if transaction
+        .query_one(
+            builder.build(
+                Query::select()
+                    .from(GroupAttributeSchema::Table)
+                    .column(GroupAttributeSchema::GroupAttributeSchemaName)
+                    .cond_where(
+                        Expr::col(GroupAttributeSchema::GroupAttributeSchemaName).eq("gidnumber"),
+                    ),
+            ),
+        )
+        .await?
+        .is_some()
+    {
+        warn!(r#"The group attribute "gidnumber" already exists, keeping its definition"#);
+    } else {
+        transaction
+            .execute(
+                builder.build(
+                    Query::insert()
+                        .into_table(GroupAttributeSchema::Table)
+                        .columns([
+                            GroupAttributeSchema::GroupAttributeSchemaName,
+                            GroupAttributeSchema::GroupAttributeSchemaType,
+                            GroupAttributeSchema::GroupAttributeSchemaIsList,
+                            GroupAttributeSchema::GroupAttributeSchemaIsGroupVisible,
+                            GroupAttributeSchema::GroupAttributeSchemaIsGroupEditable,
+                            GroupAttributeSchema::GroupAttributeSchemaIsHardcoded,
+                        ])
+                        .values_panic([
+                            "gidnumber".into(),
+                            AttributeType::Integer.into(),
+                            false.into(),
+                            true.into(),
+                            false.into(),
+                            true.into(),
+                        ]),
+                ),
+            )
+            .await?;
+    }
+    Ok(transaction)
+}
+
+async fn migrate_to_v20(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(IdentityLinks::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(IdentityLinks::Provider)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(IdentityLinks::Subject)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(IdentityLinks::UserId)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(IdentityLinks::CreationDate)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("IdentityLinksUserForeignKey")
+                            .from(IdentityLinks::Table, IdentityLinks::UserId)
+                            .to(Users::Table, Users::UserId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(IdentityLinks::Provider)
+                            .col(IdentityLinks::Subject),
+                    ),
+            ),
+        )
+        .await?;
+    // At most one identity per provider for each user.
+    transaction
+        .execute(
+            builder.build(
+                Index::create()
+                    .if_not_exists()
+                    .name("unique-identity-link-user-provider")
+                    .table(IdentityLinks::Table)
+                    .col(IdentityLinks::UserId)
+                    .col(IdentityLinks::Provider)
+                    .unique(),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v21(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(PendingProvisionings::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PendingProvisionings::Id)
+                            .integer()
+                            .auto_increment()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(PendingProvisionings::Provider)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PendingProvisionings::Subject)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PendingProvisionings::RequestDate)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PendingProvisionings::Request)
+                            .text()
+                            .not_null(),
+                    ),
+            ),
+        )
+        .await?;
+    // An identity waits for a single approval.
+    transaction
+        .execute(
+            builder.build(
+                Index::create()
+                    .if_not_exists()
+                    .name("unique-pending-provisioning-identity")
+                    .table(PendingProvisionings::Table)
+                    .col(PendingProvisionings::Provider)
+                    .col(PendingProvisionings::Subject)
+                    .unique(),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v22(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    let now = chrono::Utc::now().naive_utc();
+    transaction
+        .execute(
+            builder.build(
+                Table::alter().table(Users::Table).add_column(
+                    ColumnDef::new(Users::ModifiedDate)
+                        .date_time()
+                        .not_null()
+                        .default(now),
+                ),
+            ),
+        )
+        .await?;
+    transaction
+        .execute(
+            builder.build(
+                Table::alter().table(Groups::Table).add_column(
+                    ColumnDef::new(Groups::ModifiedDate)
+                        .date_time()
+                        .not_null()
+                        .default(now),
+                ),
+            ),
+        )
+        .await?;
+    // The existing entries are considered unmodified since their creation.
+    transaction
+        .execute(
+            builder.build(
+                Query::update()
+                    .table(Users::Table)
+                    .value(Users::ModifiedDate, Expr::col(Users::CreationDate)),
+            ),
+        )
+        .await?;
+    transaction
+        .execute(
+            builder.build(
+                Query::update()
+                    .table(Groups::Table)
+                    .value(Groups::ModifiedDate, Expr::col(Groups::CreationDate)),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v23(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // Null for the permanent memberships.
+    transaction
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(Memberships::Table)
+                    .add_column(ColumnDef::new(Memberships::ExpiryDate).date_time()),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v24(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(AccessReviewCampaigns::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AccessReviewCampaigns::Id)
+                            .integer()
+                            .auto_increment()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(AccessReviewCampaigns::Name)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    // Not a foreign key: the campaign outlives its creator.
+                    .col(
+                        ColumnDef::new(AccessReviewCampaigns::CreatedBy)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(AccessReviewCampaigns::CreationDate)
+                            .date_time()
+                            .not_null(),
+                    ),
+            ),
+        )
+        .await?;
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(AccessReviews::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AccessReviews::Id)
+                            .integer()
+                            .auto_increment()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(AccessReviews::CampaignId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AccessReviews::GroupId).integer().not_null())
+                    .col(
+                        ColumnDef::new(AccessReviews::Reviewer)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(AccessReviews::Token)
+                            .string_len(255)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("AccessReviewsCampaignForeignKey")
+                            .from(AccessReviews::Table, AccessReviews::CampaignId)
+                            .to(AccessReviewCampaigns::Table, AccessReviewCampaigns::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("AccessReviewsGroupForeignKey")
+                            .from(AccessReviews::Table, AccessReviews::GroupId)
+                            .to(Groups::Table, Groups::GroupId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("AccessReviewsReviewerForeignKey")
+                            .from(AccessReviews::Table, AccessReviews::Reviewer)
+                            .to(Users::Table, Users::UserId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    ),
+            ),
+        )
+        .await?;
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(AccessReviewDecisions::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AccessReviewDecisions::ReviewId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(AccessReviewDecisions::UserId)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    // Null until the reviewer decides.
+                    .col(ColumnDef::new(AccessReviewDecisions::Keep).boolean())
+                    .col(ColumnDef::new(AccessReviewDecisions::DecisionDate).date_time())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("AccessReviewDecisionsReviewForeignKey")
+                            .from(
+                                AccessReviewDecisions::Table,
+                                AccessReviewDecisions::ReviewId,
+                            )
+                            .to(AccessReviews::Table, AccessReviews::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("AccessReviewDecisionsUserForeignKey")
+                            .from(AccessReviewDecisions::Table, AccessReviewDecisions::UserId)
+                            .to(Users::Table, Users::UserId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(AccessReviewDecisions::ReviewId)
+                            .col(AccessReviewDecisions::UserId),
+                    ),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v25(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The groups that are members of other groups. Their members are members of the parent group.
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(GroupMemberships::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(GroupMemberships::ParentGroupId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(GroupMemberships::MemberGroupId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("GroupMembershipsParentGroupForeignKey")
+                            .from(GroupMemberships::Table, GroupMemberships::ParentGroupId)
+                            .to(Groups::Table, Groups::GroupId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("GroupMembershipsMemberGroupForeignKey")
+                            .from(GroupMemberships::Table, GroupMemberships::MemberGroupId)
+                            .to(Groups::Table, Groups::GroupId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(GroupMemberships::ParentGroupId)
+                            .col(GroupMemberships::MemberGroupId),
+                    ),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v26(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The history of the profile fields of each user. The actor is not a foreign key: the
+    // changes they made are kept after their account is deleted.
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(UserProfileChanges::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserProfileChanges::Id)
+                            .integer()
+                            .auto_increment()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(UserProfileChanges::UserId)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(UserProfileChanges::Field)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(UserProfileChanges::OldValue).text())
+                    .col(ColumnDef::new(UserProfileChanges::NewValue).text())
+                    .col(ColumnDef::new(UserProfileChanges::Actor).string_len(255))
+                    .col(
+                        ColumnDef::new(UserProfileChanges::ChangeDate)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("UserProfileChangesUserForeignKey")
+                            .from(UserProfileChanges::Table, UserProfileChanges::UserId)
+                            .to(Users::Table, Users::UserId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    ),
+            ),
+        )
+        .await?;
+    transaction
+        .execute(
+            builder.build(
+                Index::create()
+                    .if_not_exists()
+                    .name("user-profile-changes-user-id")
+                    .table(UserProfileChanges::Table)
+                    .col(UserProfileChanges::UserId),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v27(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The SSH keys were often stored in a custom attribute with the same name: a string attribute
+    // is adopted with its values, anything else is kept as is.
+    let adopted = transaction
+        .execute(
+            builder.build(
+                Query::update()
+                    .table(UserAttributeSchema::Table)
+                    .value(UserAttributeSchema::UserAttributeSchemaIsHardcoded, true)
+                    .cond_where(
+                        Expr::col(UserAttributeSchema::UserAttributeSchemaName)
+                            .eq("sshpublickey")
+                            .and(
+                                Expr::col(UserAttributeSchema::UserAttributeSchemaType)
+                                    .eq(AttributeType::String),
+                            ),
+                    ),
+            ),
+        )
+        .await?
+        .rows_affected();
+    if adopted > 0 {
+        info!(r#"Using the existing user attribute "sshpublickey" for the SSH keys"#);
+        return Ok(transaction);
+    }
+    if transaction
+        .query_one(
+            builder.build(
+                Query::select()
+                    .from(UserAttributeSchema::Table)
+                    .column(UserAttributeSchema::UserAttributeSchemaName)
+                    .cond_where(
+                        Expr::col(UserAttributeSchema::UserAttributeSchemaName).eq("sshpublickey"),
+                    ),
+            ),
+        )
+        .await?
+        .is_some()
+    {
+        warn!(r#"The user attribute "sshpublickey" already exists, keeping its definition"#);
+        return Ok(transaction);
+    }
+    transaction
+        .execute(
+            builder.build(
+                Query::insert()
+                    .into_table(UserAttributeSchema::Table)
+                    .columns([
+                        UserAttributeSchema::UserAttributeSchemaName,
+                        UserAttributeSchema::UserAttributeSchemaType,
+                        UserAttributeSchema::UserAttributeSchemaIsList,
+                        UserAttributeSchema::UserAttributeSchemaIsUserVisible,
+                        UserAttributeSchema::UserAttributeSchemaIsUserEditable,
+                        UserAttributeSchema::UserAttributeSchemaIsHardcoded,
+                    ])
+                    .values_panic([
+                        "sshpublickey".into(),
+                        AttributeType::String.into(),
+                        true.into(),
+                        true.into(),
+                        true.into(),
+                        true.into(),
+                    ]),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v28(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The tokens used to be stored as is, see `secret_tokens`. The refresh and password reset
+    // tokens are short-lived: their tables are recreated by `jwt_sql_tables`, which logs the users
+    // out once.
+    transaction
+        .execute(builder.build(Table::drop().table(JwtRefreshStorage::Table).if_exists()))
+        .await?;
+    transaction
+        .execute(builder.build(Table::drop().table(PasswordResetTokens::Table).if_exists()))
+        .await?;
+    // The links already sent by email keep working: the start of each token becomes its selector,
+    // and the rest is hashed.
+    transaction
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(AccountDeletions::Table)
+                    .add_column(ColumnDef::new(AccountDeletions::VerifierHash).binary()),
+            ),
+        )
+        .await?;
+    for row in transaction
+        .query_all(
+            builder.build(
+                Query::select()
+                    .from(AccountDeletions::Table)
+                    .columns([AccountDeletions::UserId, AccountDeletions::Token]),
+            ),
+        )
+        .await?
+    {
+        let user_id = row.try_get::<UserId>("", &AccountDeletions::UserId.to_string())?;
+        let token = row.try_get::<String>("", &AccountDeletions::Token.to_string())?;
+        if let Some((selector, verifier)) = split_token(&token) {
+            transaction
+                .execute(
+                    builder.build(
+                        Query::update()
+                            .table(AccountDeletions::Table)
+                            .values([
+                                (AccountDeletions::Token, selector.into()),
+                                (
+                                    AccountDeletions::VerifierHash,
+                                    hash_verifier(verifier).into(),
+                                ),
+                            ])
+                            .and_where(Expr::col(AccountDeletions::UserId).eq(user_id)),
+                    ),
+                )
+                .await?;
+        }
+    }
+    transaction
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(AccessReviews::Table)
+                    .add_column(ColumnDef::new(AccessReviews::VerifierHash).binary()),
+            ),
+        )
+        .await?;
+    for row in transaction
+        .query_all(
+            builder.build(
+                Query::select()
+                    .from(AccessReviews::Table)
+                    .columns([AccessReviews::Id, AccessReviews::Token]),
+            ),
+        )
+        .await?
+    {
+        let id = row.try_get::<i32>("", &AccessReviews::Id.to_string())?;
+        let token = row.try_get::<String>("", &AccessReviews::Token.to_string())?;
+        if let Some((selector, verifier)) = split_token(&token) {
+            transaction
+                .execute(
+                    builder.build(
+                        Query::update()
+                            .table(AccessReviews::Table)
+                            .values([
+                                (AccessReviews::Token, selector.into()),
+                                (AccessReviews::VerifierHash, hash_verifier(verifier).into()),
+                            ])
+                            .and_where(Expr::col(AccessReviews::Id).eq(id)),
+                    ),
+                )
+                .await?;
+        }
+    }
+    Ok(transaction)
+}
+
+async fn migrate_to_v29(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The approvals of the just-in-time provisioning are gone until there is an upstream login.
+    transaction
+        .execute(builder.build(Table::drop().table(PendingProvisionings::Table).if_exists()))
+        .await?;
+    Ok(transaction)
+}
+
 // This is needed to make an array of async functions.
 macro_rules! to_sync {
     ($l:ident) => {
@@ -1146,6 +2240,25 @@ pub async fn migrate_from_version(
         to_sync!(migrate_to_v8),
         to_sync!(migrate_to_v9),
         to_sync!(migrate_to_v10),
+        to_sync!(migrate_to_v11),
+        to_sync!(migrate_to_v12),
+        to_sync!(migrate_to_v13),
+        to_sync!(migrate_to_v14),
+        to_sync!(migrate_to_v15),
+        to_sync!(migrate_to_v16),
+        to_sync!(migrate_to_v17),
+        to_sync!(migrate_to_v18),
+        to_sync!(migrate_to_v19),
+        to_sync!(migrate_to_v20),
+        to_sync!(migrate_to_v21),
+        to_sync!(migrate_to_v22),
+        to_sync!(migrate_to_v23),
+        to_sync!(migrate_to_v24),
+        to_sync!(migrate_to_v25),
+        to_sync!(migrate_to_v26),
+        to_sync!(migrate_to_v27),
+        to_sync!(migrate_to_v28),
+        to_sync!(migrate_to_v29),
     ];
     assert_eq!(migrations.len(), (LAST_SCHEMA_VERSION.0 - 1) as usize);
     for migration in 2..=last_version.0 {
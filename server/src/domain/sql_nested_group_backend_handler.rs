@@ -0,0 +1,184 @@
+use crate::domain::{
+    change_events::ChangeEvent,
+    error::{DomainError, Result},
+    handler::{GroupListerBackendHandler, GroupRequestFilter, NestedGroupBackendHandler},
+    model::{self, GroupMembershipsColumn},
+    nested_groups::{GroupHierarchy, PERMISSION_GROUPS},
+    sql_backend_handler::SqlBackendHandler,
+    types::GroupId,
+};
+use async_trait::async_trait;
+use sea_orm::{ActiveModelTrait, ConnectionTrait, EntityTrait, QueryOrder, Set, TransactionTrait};
+use std::collections::BTreeSet;
+use tracing::instrument;
+
+/// The links between the groups, from the parent group to the member group.
+async fn list_group_links(connection: &impl ConnectionTrait) -> Result<Vec<(GroupId, GroupId)>> {
+    Ok(model::GroupMemberships::find()
+        .order_by_asc(GroupMembershipsColumn::ParentGroupId)
+        .order_by_asc(GroupMembershipsColumn::MemberGroupId)
+        .all(connection)
+        .await?
+        .into_iter()
+        .map(|m| (m.parent_group_id, m.member_group_id))
+        .collect())
+}
+
+impl SqlBackendHandler {
+    pub(crate) async fn get_group_hierarchy_from_db(&self) -> Result<GroupHierarchy> {
+        let links = list_group_links(&self.sql_pool).await?;
+        if links.is_empty() {
+            return Ok(GroupHierarchy::default());
+        }
+        let group_ids: BTreeSet<GroupId> = links.iter().flat_map(|(p, m)| [*p, *m]).collect();
+        let groups = self
+            .list_groups(Some(GroupRequestFilter::Or(
+                group_ids
+                    .into_iter()
+                    .map(GroupRequestFilter::GroupId)
+                    .collect(),
+            )))
+            .await?;
+        Ok(GroupHierarchy::new(groups, &links))
+    }
+}
+
+#[async_trait]
+impl NestedGroupBackendHandler for SqlBackendHandler {
+    #[instrument(skip(self), level = "debug", err)]
+    async fn add_group_to_group(&self, member: GroupId, parent: GroupId) -> Result<()> {
+        let transaction = self.sql_pool.begin().await?;
+        for group_id in [member, parent] {
+            let group = model::Group::find_by_id(group_id)
+                .one(&transaction)
+                .await?
+                .ok_or_else(|| {
+                    DomainError::EntityNotFound(format!("No such group: '{:?}'", group_id))
+                })?;
+            if group_id == parent
+                && PERMISSION_GROUPS.contains(&group.lowercase_display_name.as_str())
+            {
+                return Err(DomainError::ValidationError(format!(
+                    "Group {:?} grants permissions: it cannot contain other groups",
+                    parent
+                )));
+            }
+        }
+        let hierarchy = GroupHierarchy::new(Vec::new(), &list_group_links(&transaction).await?);
+        if hierarchy.would_create_cycle(parent, member) {
+            return Err(DomainError::ValidationError(format!(
+                "Group {:?} cannot be a member of group {:?}: it already contains it",
+                member, parent
+            )));
+        }
+        model::group_memberships::ActiveModel {
+            parent_group_id: Set(parent),
+            member_group_id: Set(member),
+        }
+        .insert(&transaction)
+        .await?;
+        self.commit_change(transaction, ChangeEvent::GroupUpdated(parent))
+            .await
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn remove_group_from_group(&self, member: GroupId, parent: GroupId) -> Result<()> {
+        let transaction = self.sql_pool.begin().await?;
+        let res = model::GroupMemberships::delete_by_id((parent, member))
+            .exec(&transaction)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such group membership: {:?} -> {:?}",
+                member, parent
+            )));
+        }
+        self.commit_change(transaction, ChangeEvent::GroupUpdated(parent))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{UserListerBackendHandler, UserRequestFilter},
+        sql_backend_handler::tests::*,
+        types::UserId,
+    };
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_nested_groups() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let (best, worst, empty) = (fixture.groups[0], fixture.groups[1], fixture.groups[2]);
+        assert_eq!(
+            handler.get_group_hierarchy().await.unwrap(),
+            GroupHierarchy::default()
+        );
+        handler.add_group_to_group(worst, empty).await.unwrap();
+        handler.add_group_to_group(best, worst).await.unwrap();
+        let hierarchy = handler.get_group_hierarchy().await.unwrap();
+        assert_eq!(hierarchy.ancestors(best), BTreeSet::from([worst, empty]));
+        // Only the direct members are stored: bob is resolved through the hierarchy.
+        let users = handler
+            .list_users(
+                Some(hierarchy.expand_user_filter(UserRequestFilter::MemberOfId(empty))),
+                false,
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|u| u.user.user_id)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            users,
+            vec![
+                UserId::new("bob"),
+                UserId::new("john"),
+                UserId::new("patrick")
+            ]
+        );
+        handler.remove_group_from_group(worst, empty).await.unwrap();
+        assert_eq!(
+            handler.get_group_hierarchy().await.unwrap().ancestors(best),
+            BTreeSet::from([worst])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_nested_groups_cycle() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let (best, worst) = (fixture.groups[0], fixture.groups[1]);
+        handler.add_group_to_group(best, worst).await.unwrap();
+        handler
+            .add_group_to_group(worst, best)
+            .await
+            .expect_err("Should not create a cycle");
+        handler
+            .add_group_to_group(best, best)
+            .await
+            .expect_err("A group cannot contain itself");
+        handler
+            .add_group_to_group(best, GroupId(1000))
+            .await
+            .expect_err("The group doesn't exist");
+    }
+
+    #[tokio::test]
+    async fn test_nested_groups_permission_group() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let admin = insert_group(handler, "lldap_admin").await;
+        handler
+            .add_group_to_group(fixture.groups[0], admin)
+            .await
+            .expect_err("The admin group cannot contain groups");
+        handler
+            .add_group_to_group(admin, fixture.groups[0])
+            .await
+            .unwrap();
+    }
+}
@@ -1,15 +1,20 @@
 use super::{
+    change_events::ChangeEvent,
     error::{DomainError, Result},
     handler::{BindRequest, LoginHandler},
-    model::{self, UserColumn},
+    model::{self, AccountDeletionsColumn, UserColumn},
     opaque_handler::{login, registration, OpaqueHandler},
     sql_backend_handler::SqlBackendHandler,
     types::UserId,
 };
+use crate::infra::{ldap_proxy::check_upstream_password, pam_auth::check_pam_password};
 use async_trait::async_trait;
 use base64::Engine;
 use lldap_auth::opaque;
-use sea_orm::{ActiveModelTrait, ActiveValue, EntityTrait, QuerySelect};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter, QuerySelect,
+    TransactionTrait,
+};
 use secstr::SecUtf8;
 use tracing::{debug, info, instrument, warn};
 
@@ -49,8 +54,29 @@ impl SqlBackendHandler {
         )?)
     }
 
+    // Accounts waiting for their deletion are disabled.
+    async fn is_scheduled_for_deletion(&self, user_id: &UserId) -> Result<bool> {
+        if model::AccountDeletions::find_by_id(user_id.clone())
+            .filter(AccountDeletionsColumn::DeletionDate.is_not_null())
+            .one(&self.sql_pool)
+            .await?
+            .is_some()
+        {
+            info!(r#"The account of "{}" is scheduled for deletion"#, user_id);
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
     #[instrument(skip(self), level = "debug", err)]
     async fn get_password_file_for_user(&self, user_id: UserId) -> Result<Option<Vec<u8>>> {
+        if self.is_scheduled_for_deletion(&user_id).await? {
+            return Ok(None);
+        }
+        // The users authenticated by PAM can't use a password stored in lldap.
+        if self.config.pam_options.is_pam_user(&user_id) {
+            return Ok(None);
+        }
         // Fetch the previously registered password file from the DB.
         Ok(model::User::find_by_id(user_id)
             .select_only()
@@ -66,6 +92,22 @@ impl SqlBackendHandler {
 impl LoginHandler for SqlBackendHandler {
     #[instrument(skip_all, level = "debug", err)]
     async fn bind(&self, request: BindRequest) -> Result<()> {
+        if self.config.pam_options.is_pam_user(&request.name) {
+            if !self.is_scheduled_for_deletion(&request.name).await? {
+                info!(r#"PAM login attempt for "{}""#, &request.name);
+                match check_pam_password(&self.config.pam_options, &request.name, &request.password)
+                    .await
+                {
+                    Ok(true) => return Ok(()),
+                    Ok(false) => {}
+                    Err(e) => warn!("Could not check the password with PAM: {:#}", e),
+                }
+            }
+            return Err(DomainError::AuthenticationError(format!(
+                r#"for user "{}""#,
+                request.name
+            )));
+        }
         if let Some(password_hash) = self
             .get_password_file_for_user(request.name.clone())
             .await?
@@ -87,6 +129,14 @@ impl LoginHandler for SqlBackendHandler {
                 &request.name
             );
         }
+        let proxy_options = &self.config.ldap_proxy_options;
+        if proxy_options.enabled && !self.is_scheduled_for_deletion(&request.name).await? {
+            match check_upstream_password(proxy_options, &request.name, &request.password).await {
+                Ok(true) => return Ok(()),
+                Ok(false) => {}
+                Err(e) => warn!("Could not check the password upstream: {:#}", e),
+            }
+        }
         Err(DomainError::AuthenticationError(format!(
             r#"for user "{}""#,
             request.name
@@ -202,9 +252,11 @@ impl OpaqueHandler for SqlOpaqueHandler {
             password_hash: ActiveValue::Set(Some(password_file.serialize())),
             ..Default::default()
         };
-        user_update.update(&self.sql_pool).await?;
+        let transaction = self.sql_pool.begin().await?;
+        user_update.update(&transaction).await?;
         info!(r#"Successfully (re)set password for "{}""#, &username);
-        Ok(())
+        self.commit_change(transaction, ChangeEvent::PasswordChanged(username))
+            .await
     }
 }
 
@@ -265,6 +317,7 @@ mod tests {
             .login_finish(ClientLoginFinishRequest {
                 server_data: start_response.server_data,
                 credential_finalization: login_finish.message,
+                remember_me: false,
             })
             .await?;
         Ok(())
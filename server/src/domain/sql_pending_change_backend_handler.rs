@@ -0,0 +1,133 @@
+use crate::domain::{
+    error::{DomainError, Result},
+    handler::PendingChangeBackendHandler,
+    model::{self, PendingChangesColumn},
+    pending_changes::{PendingChange, SensitiveChange},
+    sql_backend_handler::SqlBackendHandler,
+    types::UserId,
+};
+use async_trait::async_trait;
+use sea_orm::{ActiveModelTrait, ConnectionTrait, EntityTrait, QueryOrder, Set};
+use tracing::instrument;
+
+impl TryFrom<model::pending_changes::Model> for PendingChange {
+    type Error = DomainError;
+
+    fn try_from(model: model::pending_changes::Model) -> Result<Self> {
+        Ok(Self {
+            id: model.id,
+            requested_by: model.requested_by,
+            request_date: model.request_date,
+            change: serde_json::from_str::<SensitiveChange>(&model.change).map_err(|e| {
+                DomainError::InternalError(format!("Invalid pending change {}: {}", model.id, e))
+            })?,
+        })
+    }
+}
+
+impl SqlBackendHandler {
+    pub(crate) async fn insert_pending_change(
+        connection: &impl ConnectionTrait,
+        requested_by: &UserId,
+        change: &SensitiveChange,
+    ) -> Result<i32> {
+        let new_change = model::pending_changes::ActiveModel {
+            requested_by: Set(requested_by.clone()),
+            request_date: Set(chrono::Utc::now().naive_utc()),
+            change: Set(serde_json::to_string(change).unwrap()),
+            ..Default::default()
+        };
+        Ok(new_change.insert(connection).await?.id)
+    }
+}
+
+#[async_trait]
+impl PendingChangeBackendHandler for SqlBackendHandler {
+    #[instrument(skip(self), level = "debug", ret, err)]
+    async fn create_pending_change(
+        &self,
+        requested_by: &UserId,
+        change: SensitiveChange,
+    ) -> Result<i32> {
+        Self::insert_pending_change(&self.sql_pool, requested_by, &change).await
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn list_pending_changes(&self) -> Result<Vec<PendingChange>> {
+        model::PendingChanges::find()
+            .order_by_asc(PendingChangesColumn::Id)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(PendingChange::try_from)
+            .collect()
+    }
+
+    #[instrument(skip(self), level = "debug", ret, err)]
+    async fn get_pending_change(&self, id: i32) -> Result<PendingChange> {
+        model::PendingChanges::find_by_id(id)
+            .one(&self.sql_pool)
+            .await?
+            .ok_or_else(|| DomainError::EntityNotFound(format!("Pending change {}", id)))?
+            .try_into()
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn delete_pending_change(&self, id: i32) -> Result<()> {
+        let res = model::PendingChanges::delete_by_id(id)
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such pending change: '{}'",
+                id
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{sql_backend_handler::tests::*, types::GroupId};
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_pending_change_lifecycle() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        assert_eq!(handler.list_pending_changes().await.unwrap(), vec![]);
+        let deletion = handler
+            .create_pending_change(
+                &UserId::new("bob"),
+                SensitiveChange::DeleteUser(UserId::new("patrick")),
+            )
+            .await
+            .unwrap();
+        let membership = handler
+            .create_pending_change(
+                &UserId::new("bob"),
+                SensitiveChange::AddUserToGroup {
+                    user_id: UserId::new("patrick"),
+                    group_id: GroupId(1),
+                },
+            )
+            .await
+            .unwrap();
+        let changes = handler.list_pending_changes().await.unwrap();
+        assert_eq!(
+            changes.iter().map(|c| c.id).collect::<Vec<_>>(),
+            vec![deletion, membership]
+        );
+        assert_eq!(changes[0].requested_by, UserId::new("bob"));
+        assert_eq!(
+            handler.get_pending_change(deletion).await.unwrap().change,
+            SensitiveChange::DeleteUser(UserId::new("patrick"))
+        );
+        handler.delete_pending_change(deletion).await.unwrap();
+        assert!(handler.get_pending_change(deletion).await.is_err());
+        assert!(handler.delete_pending_change(deletion).await.is_err());
+        assert_eq!(handler.list_pending_changes().await.unwrap().len(), 1);
+    }
+}
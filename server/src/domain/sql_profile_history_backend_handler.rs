@@ -0,0 +1,162 @@
+use crate::domain::{
+    error::Result,
+    handler::ProfileHistoryBackendHandler,
+    model::{self, UserProfileChangesColumn},
+    profile_history::{FieldChange, ProfileChange},
+    sql_backend_handler::SqlBackendHandler,
+    types::UserId,
+};
+use async_trait::async_trait;
+use sea_orm::{ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder, Set};
+use tracing::instrument;
+
+impl From<model::user_profile_changes::Model> for ProfileChange {
+    fn from(model: model::user_profile_changes::Model) -> Self {
+        Self {
+            id: model.id,
+            user_id: model.user_id,
+            field: model.field,
+            old_value: model.old_value,
+            new_value: model.new_value,
+            actor: model.actor.as_deref().map(UserId::new),
+            change_date: model.change_date,
+        }
+    }
+}
+
+impl SqlBackendHandler {
+    /// Stores the changes in the history of the user, in the transaction of the update.
+    pub(crate) async fn record_profile_changes(
+        connection: &impl ConnectionTrait,
+        user_id: &UserId,
+        actor: Option<UserId>,
+        changes: Vec<FieldChange>,
+    ) -> Result<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let now = chrono::Utc::now().naive_utc();
+        model::UserProfileChanges::insert_many(changes.into_iter().map(|change| {
+            model::user_profile_changes::ActiveModel {
+                user_id: Set(user_id.clone()),
+                field: Set(change.field),
+                old_value: Set(change.old_value),
+                new_value: Set(change.new_value),
+                actor: Set(actor.as_ref().map(UserId::to_string)),
+                change_date: Set(now),
+                ..Default::default()
+            }
+        }))
+        .exec(connection)
+        .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ProfileHistoryBackendHandler for SqlBackendHandler {
+    #[instrument(skip(self), level = "debug", err)]
+    async fn list_profile_changes(&self, user_id: &UserId) -> Result<Vec<ProfileChange>> {
+        Ok(model::UserProfileChanges::find()
+            .filter(UserProfileChangesColumn::UserId.eq(user_id))
+            .order_by_asc(UserProfileChangesColumn::Id)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(ProfileChange::from)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{UpdateUserRequest, UserBackendHandler},
+        sql_backend_handler::tests::*,
+        types::{AttributeValue, Serialized},
+    };
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_profile_history() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let bob = UserId::new("bob");
+        assert_eq!(handler.list_profile_changes(&bob).await.unwrap(), vec![]);
+        handler
+            .update_user(UpdateUserRequest {
+                user_id: bob.clone(),
+                email: Some("robert@example.com".into()),
+                first_name: Some("Robert".to_owned()),
+                actor: Some(UserId::new("admin")),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        // Nothing changes: nothing is recorded.
+        handler
+            .update_user(UpdateUserRequest {
+                user_id: bob.clone(),
+                first_name: Some("Robert".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        handler
+            .update_user(UpdateUserRequest {
+                user_id: bob.clone(),
+                delete_attributes: vec!["first_name".into()],
+                insert_attributes: vec![AttributeValue {
+                    name: "last_name".into(),
+                    value: Serialized::from("Bobberson"),
+                }],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let changes = handler
+            .list_profile_changes(&bob)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.field, c.old_value, c.new_value, c.actor))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                (
+                    "email".to_owned(),
+                    Some("bob@bob.bob".to_owned()),
+                    Some("robert@example.com".to_owned()),
+                    Some(UserId::new("admin"))
+                ),
+                (
+                    "first_name".to_owned(),
+                    Some("first bob".to_owned()),
+                    Some("Robert".to_owned()),
+                    Some(UserId::new("admin"))
+                ),
+                (
+                    "first_name".to_owned(),
+                    Some("Robert".to_owned()),
+                    None,
+                    None
+                ),
+                (
+                    "last_name".to_owned(),
+                    Some("last bob".to_owned()),
+                    Some("Bobberson".to_owned()),
+                    None
+                ),
+            ]
+        );
+        assert_eq!(
+            handler
+                .list_profile_changes(&UserId::new("patrick"))
+                .await
+                .unwrap(),
+            vec![]
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use crate::domain::{
+    change_events::ChangeEvent,
     error::{DomainError, Result},
     handler::{
         AttributeList, AttributeSchema, CreateAttributeRequest, ReadSchemaBackendHandler, Schema,
@@ -29,80 +30,120 @@ impl ReadSchemaBackendHandler for SqlBackendHandler {
 impl SchemaBackendHandler for SqlBackendHandler {
     async fn add_user_attribute(&self, request: CreateAttributeRequest) -> Result<()> {
         let new_attribute = model::user_attribute_schema::ActiveModel {
-            attribute_name: Set(request.name),
+            attribute_name: Set(request.name.clone()),
             attribute_type: Set(request.attribute_type),
             is_list: Set(request.is_list),
             is_user_visible: Set(request.is_visible),
             is_user_editable: Set(request.is_editable),
             is_hardcoded: Set(false),
         };
-        new_attribute.insert(&self.sql_pool).await?;
-        Ok(())
+        let transaction = self.sql_pool.begin().await?;
+        new_attribute.insert(&transaction).await?;
+        self.commit_change(
+            transaction,
+            ChangeEvent::UserAttributeSchemaChanged(request.name),
+        )
+        .await
     }
 
     async fn add_group_attribute(&self, request: CreateAttributeRequest) -> Result<()> {
         let new_attribute = model::group_attribute_schema::ActiveModel {
-            attribute_name: Set(request.name),
+            attribute_name: Set(request.name.clone()),
             attribute_type: Set(request.attribute_type),
             is_list: Set(request.is_list),
             is_group_visible: Set(request.is_visible),
             is_group_editable: Set(request.is_editable),
             is_hardcoded: Set(false),
         };
-        new_attribute.insert(&self.sql_pool).await?;
-        Ok(())
+        let transaction = self.sql_pool.begin().await?;
+        new_attribute.insert(&transaction).await?;
+        self.commit_change(
+            transaction,
+            ChangeEvent::GroupAttributeSchemaChanged(request.name),
+        )
+        .await
     }
 
     async fn delete_user_attribute(&self, name: &AttributeName) -> Result<()> {
+        let transaction = self.sql_pool.begin().await?;
         model::UserAttributeSchema::delete_by_id(name.clone())
-            .exec(&self.sql_pool)
+            .exec(&transaction)
             .await?;
-        Ok(())
+        self.commit_change(
+            transaction,
+            ChangeEvent::UserAttributeSchemaChanged(name.clone()),
+        )
+        .await
     }
 
     async fn delete_group_attribute(&self, name: &AttributeName) -> Result<()> {
+        let transaction = self.sql_pool.begin().await?;
         model::GroupAttributeSchema::delete_by_id(name.clone())
-            .exec(&self.sql_pool)
+            .exec(&transaction)
             .await?;
-        Ok(())
+        self.commit_change(
+            transaction,
+            ChangeEvent::GroupAttributeSchemaChanged(name.clone()),
+        )
+        .await
     }
 
     async fn add_user_object_class(&self, name: &LdapObjectClass) -> Result<()> {
         let mut name_key = name.to_string();
         name_key.make_ascii_lowercase();
+        let transaction = self.sql_pool.begin().await?;
         model::user_object_classes::ActiveModel {
             lower_object_class: Set(name_key),
             object_class: Set(name.clone()),
         }
-        .insert(&self.sql_pool)
+        .insert(&transaction)
         .await?;
-        Ok(())
+        self.commit_change(
+            transaction,
+            ChangeEvent::UserObjectClassesChanged(name.clone()),
+        )
+        .await
     }
 
     async fn add_group_object_class(&self, name: &LdapObjectClass) -> Result<()> {
         let mut name_key = name.to_string();
         name_key.make_ascii_lowercase();
+        let transaction = self.sql_pool.begin().await?;
         model::group_object_classes::ActiveModel {
             lower_object_class: Set(name_key),
             object_class: Set(name.clone()),
         }
-        .insert(&self.sql_pool)
+        .insert(&transaction)
         .await?;
-        Ok(())
+        self.commit_change(
+            transaction,
+            ChangeEvent::GroupObjectClassesChanged(name.clone()),
+        )
+        .await
     }
 
     async fn delete_user_object_class(&self, name: &LdapObjectClass) -> Result<()> {
+        let transaction = self.sql_pool.begin().await?;
         model::UserObjectClasses::delete_by_id(name.as_str().to_ascii_lowercase())
-            .exec(&self.sql_pool)
+            .exec(&transaction)
             .await?;
-        Ok(())
+        self.commit_change(
+            transaction,
+            ChangeEvent::UserObjectClassesChanged(name.clone()),
+        )
+        .await
     }
 
     async fn delete_group_object_class(&self, name: &LdapObjectClass) -> Result<()> {
+        let transaction = self.sql_pool.begin().await?;
         model::GroupObjectClasses::delete_by_id(name.as_str().to_ascii_lowercase())
-            .exec(&self.sql_pool)
+            .exec(&transaction)
             .await?;
-        Ok(())
+        self.commit_change(
+            transaction,
+            ChangeEvent::GroupObjectClassesChanged(name.clone()),
+        )
+        .await
     }
 }
 
@@ -215,11 +256,55 @@ mod tests {
                             is_editable: true,
                             is_hardcoded: true,
                             is_readonly: false,
+                        },
+                        AttributeSchema {
+                            name: "preferred_language".into(),
+                            attribute_type: AttributeType::String,
+                            is_list: false,
+                            is_visible: true,
+                            is_editable: true,
+                            is_hardcoded: true,
+                            is_readonly: false,
+                        },
+                        AttributeSchema {
+                            name: "sshpublickey".into(),
+                            attribute_type: AttributeType::String,
+                            is_list: true,
+                            is_visible: true,
+                            is_editable: true,
+                            is_hardcoded: true,
+                            is_readonly: false,
+                        },
+                        AttributeSchema {
+                            name: "timezone".into(),
+                            attribute_type: AttributeType::String,
+                            is_list: false,
+                            is_visible: true,
+                            is_editable: true,
+                            is_hardcoded: true,
+                            is_readonly: false,
+                        },
+                        AttributeSchema {
+                            name: "uidnumber".into(),
+                            attribute_type: AttributeType::Integer,
+                            is_list: false,
+                            is_visible: true,
+                            is_editable: false,
+                            is_hardcoded: true,
+                            is_readonly: false,
                         }
                     ]
                 },
                 group_attributes: AttributeList {
-                    attributes: Vec::new()
+                    attributes: vec![AttributeSchema {
+                        name: "gidnumber".into(),
+                        attribute_type: AttributeType::Integer,
+                        is_list: false,
+                        is_visible: true,
+                        is_editable: false,
+                        is_hardcoded: true,
+                        is_readonly: false,
+                    }]
                 },
                 extra_user_object_classes: Vec::new(),
                 extra_group_object_classes: Vec::new(),
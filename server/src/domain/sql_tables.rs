@@ -11,7 +11,7 @@ pub type DbConnection = sea_orm::DatabaseConnection;
 #[derive(Copy, PartialEq, Eq, Debug, Clone, PartialOrd, Ord, DeriveValueType)]
 pub struct SchemaVersion(pub i16);
 
-pub const LAST_SCHEMA_VERSION: SchemaVersion = SchemaVersion(10);
+pub const LAST_SCHEMA_VERSION: SchemaVersion = SchemaVersion(29);
 
 #[derive(Copy, PartialEq, Eq, Debug, Clone, PartialOrd, Ord)]
 pub struct PrivateKeyHash(pub [u8; 32]);
@@ -124,6 +124,7 @@ pub async fn set_private_key_info(pool: &DbConnection, info: PrivateKeyInfo) ->
 #[cfg(test)]
 mod tests {
     use crate::domain::{
+        secret_tokens::check_verifier,
         sql_migrations,
         types::{GroupId, JpegPhoto, Serialized, Uuid},
     };
@@ -550,6 +551,70 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_migration_to_v28() {
+        crate::infra::logging::init_for_tests();
+        let sql_pool = get_in_memory_db().await;
+        upgrade_to_v1(&sql_pool).await.unwrap();
+        migrate_from_version(&sql_pool, SchemaVersion(1), SchemaVersion(27))
+            .await
+            .unwrap();
+        let token = "0123456789abcdefghijklmnopqrstuvwxyz";
+        for statement in [
+            r#"INSERT INTO users
+               (user_id, email, lowercase_email, display_name, creation_date, password_hash, uuid)
+               VALUES ("bob", "bob@bob.bob", "bob@bob.bob", "", "1970-01-01 00:00:00", "bob00", "abc")"#,
+            r#"INSERT INTO account_deletions (user_id, token, request_date)
+               VALUES ("bob", "0123456789abcdefghijklmnopqrstuvwxyz", "1970-01-01 00:00:00")"#,
+            r#"CREATE TABLE jwt_refresh_storage (refresh_token_hash INTEGER PRIMARY KEY)"#,
+        ] {
+            sql_pool.execute(raw_statement(statement)).await.unwrap();
+        }
+        migrate_from_version(&sql_pool, SchemaVersion(27), SchemaVersion(28))
+            .await
+            .unwrap();
+        #[derive(FromQueryResult)]
+        struct StoredToken {
+            token: String,
+            verifier_hash: Option<Vec<u8>>,
+        }
+        let stored = StoredToken::find_by_statement(raw_statement(
+            r#"SELECT token, verifier_hash FROM account_deletions"#,
+        ))
+        .one(&sql_pool)
+        .await
+        .unwrap()
+        .unwrap();
+        // The link already sent keeps working.
+        assert_eq!(stored.token, &token[..20]);
+        assert!(check_verifier(&token[20..], &stored.verifier_hash.unwrap()));
+        // The refresh tokens are dropped, the table is recreated with the hashes.
+        assert!(sql_pool
+            .execute(raw_statement("SELECT * FROM jwt_refresh_storage"))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_migration_to_v29() {
+        let sql_pool = get_in_memory_db().await;
+        upgrade_to_v1(&sql_pool).await.unwrap();
+        migrate_from_version(&sql_pool, SchemaVersion(1), SchemaVersion(28))
+            .await
+            .unwrap();
+        sql_pool
+            .execute(raw_statement("SELECT * FROM pending_provisionings"))
+            .await
+            .unwrap();
+        migrate_from_version(&sql_pool, SchemaVersion(28), SchemaVersion(29))
+            .await
+            .unwrap();
+        assert!(sql_pool
+            .execute(raw_statement("SELECT * FROM pending_provisionings"))
+            .await
+            .is_err());
+    }
+
     #[tokio::test]
     async fn test_too_high_version() {
         let sql_pool = get_in_memory_db().await;
@@ -1,26 +1,40 @@
-use crate::domain::{
-    error::{DomainError, Result},
-    handler::{
-        CreateUserRequest, UpdateUserRequest, UserBackendHandler, UserListerBackendHandler,
-        UserRequestFilter,
+use crate::{
+    domain::{
+        change_events::ChangeEvent,
+        error::{DomainError, Result},
+        handler::{
+            CreateUserRequest, UpdateUserRequest, UserBackendHandler, UserCreationSource,
+            UserListerBackendHandler, UserRequestFilter,
+        },
+        locale::check_locale_attribute,
+        model::{self, GroupColumn, JwtRefreshStorageColumn, JwtStorageColumn, UserColumn},
+        posix_ids::{is_allocated, next_uid_number, UID_NUMBER_ATTRIBUTE},
+        profile_history::diff_profiles,
+        search_cache::UserSearchKey,
+        sql_backend_handler::{check_attribute_value, check_quota, SqlBackendHandler},
+        ssh_keys::check_ssh_public_key_attribute,
+        types::{
+            AttributeName, AttributeValue, GroupDetails, GroupId, Serialized, SessionKind, User,
+            UserAndGroups, UserId, UserSession, Uuid,
+        },
     },
-    model::{self, GroupColumn, UserColumn},
-    sql_backend_handler::SqlBackendHandler,
-    types::{
-        AttributeName, AttributeValue, GroupDetails, GroupId, Serialized, User, UserAndGroups,
-        UserId, Uuid,
+    infra::{
+        configuration::{AttributeValidationRule, DisplayNameUniqueness},
+        validation_webhook::WebhookOperation,
     },
 };
 use async_trait::async_trait;
+use futures_util::TryStreamExt;
 use sea_orm::{
     sea_query::{
         query::OnConflict, Alias, Cond, Expr, Func, IntoColumnRef, IntoCondition, SimpleExpr,
     },
-    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseTransaction, EntityTrait, IntoActiveValue,
-    ModelTrait, QueryFilter, QueryOrder, QuerySelect, QueryTrait, Set, TransactionTrait,
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseTransaction, EntityTrait,
+    IntoActiveValue, ModelTrait, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, QueryTrait,
+    Set, TransactionTrait,
 };
 use std::collections::HashSet;
-use tracing::instrument;
+use tracing::{error, instrument, warn};
 
 fn attribute_condition(name: AttributeName, value: Option<Serialized>) -> Cond {
     Expr::in_subquery(
@@ -39,6 +53,25 @@ fn attribute_condition(name: AttributeName, value: Option<Serialized>) -> Cond {
     .into_condition()
 }
 
+async fn get_user_with_attributes(
+    connection: &impl ConnectionTrait,
+    user_id: &UserId,
+) -> Result<User> {
+    let mut user = User::from(
+        model::User::find_by_id(user_id.to_owned())
+            .one(connection)
+            .await?
+            .ok_or_else(|| DomainError::EntityNotFound(user_id.to_string()))?,
+    );
+    let attributes = model::UserAttributes::find()
+        .filter(model::UserAttributesColumn::UserId.eq(user_id))
+        .order_by_asc(model::UserAttributesColumn::AttributeName)
+        .all(connection)
+        .await?;
+    user.attributes = attributes.into_iter().map(AttributeValue::from).collect();
+    Ok(user)
+}
+
 fn user_id_subcondition(filter: Cond) -> Cond {
     Expr::in_subquery(
         Expr::col(UserColumn::UserId.as_column_ref()),
@@ -79,6 +112,11 @@ fn get_user_filter_expr(filter: UserRequestFilter) -> Cond {
             } else if column == UserColumn::Email {
                 ColumnTrait::eq(&UserColumn::LowercaseEmail, value.as_str().to_lowercase())
                     .into_condition()
+            } else if column == UserColumn::ExternalId {
+                // LDAP filters are lowercased.
+                SimpleExpr::FunctionCall(Func::lower(Expr::col(column.as_column_ref())))
+                    .eq(value.to_lowercase())
+                    .into_condition()
             } else {
                 ColumnTrait::eq(&column, value).into_condition()
             }
@@ -103,6 +141,7 @@ fn get_user_filter_expr(filter: UserRequestFilter) -> Cond {
                 .into_condition()
         }
         CustomAttributePresent(name) => attribute_condition(name, None),
+        ModifiedSince(date) => UserColumn::ModifiedDate.gte(date).into_condition(),
     }
 }
 
@@ -125,58 +164,199 @@ impl UserListerBackendHandler for SqlBackendHandler {
         filters: Option<UserRequestFilter>,
         // To simplify the query, we always fetch groups. TODO: cleanup.
         _get_groups: bool,
+    ) -> Result<Vec<UserAndGroups>> {
+        self.list_users_with_attributes(filters, true, None).await
+    }
+
+    #[instrument(skip(self), level = "debug", ret, err)]
+    async fn list_users_with_attributes(
+        &self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+        attributes: Option<Vec<AttributeName>>,
+    ) -> Result<Vec<UserAndGroups>> {
+        let key = UserSearchKey {
+            filters,
+            get_groups,
+            attributes,
+        };
+        match &self.search_cache {
+            None => self.list_users_from_db(key).await,
+            Some(cache) => {
+                if let Some(users) = cache.get_users(&key) {
+                    return Ok(users);
+                }
+                let generation = cache.generation();
+                let users = self.list_users_from_db(key.clone()).await?;
+                cache.insert_users(generation, key, users.clone());
+                Ok(users)
+            }
+        }
+    }
+}
+
+impl SqlBackendHandler {
+    async fn list_users_from_db(
+        &self,
+        UserSearchKey {
+            filters,
+            get_groups,
+            attributes,
+        }: UserSearchKey,
     ) -> Result<Vec<UserAndGroups>> {
         let filters = filters
             .map(get_user_filter_expr)
             .unwrap_or_else(|| SimpleExpr::Value(true.into()).into_condition());
-        let mut users: Vec<_> = model::User::find()
+        // Rows are processed as they come in, without materializing the intermediate results.
+        let mut users: Vec<UserAndGroups> = Vec::new();
+        let users_query = model::User::find()
             .filter(filters.clone())
-            .order_by_asc(UserColumn::UserId)
-            .find_with_linked(model::memberships::UserToGroup)
-            .order_by_asc(SimpleExpr::Column(
-                (Alias::new("r1"), GroupColumn::DisplayName).into_column_ref(),
-            ))
-            .all(&self.sql_pool)
-            .await?
-            .into_iter()
-            .map(|(user, groups)| UserAndGroups {
-                user: user.into(),
-                groups: Some(groups.into_iter().map(Into::<GroupDetails>::into).collect()),
-            })
-            .collect();
+            .order_by_asc(UserColumn::UserId);
+        if get_groups {
+            let rows = users_query
+                .find_also_linked(model::memberships::UserToGroup)
+                .order_by_asc(SimpleExpr::Column(
+                    (Alias::new("r1"), GroupColumn::DisplayName).into_column_ref(),
+                ))
+                .stream(&self.sql_pool)
+                .await?;
+            futures_util::pin_mut!(rows);
+            while let Some((user, group)) = rows.try_next().await? {
+                if users.last().map(|u| &u.user.user_id) != Some(&user.user_id) {
+                    users.push(UserAndGroups {
+                        user: user.into(),
+                        groups: Some(Vec::new()),
+                    });
+                }
+                if let Some(group) = group {
+                    let current_user = users.last_mut().unwrap();
+                    current_user
+                        .groups
+                        .get_or_insert_with(Vec::new)
+                        .push(group.into());
+                }
+            }
+        } else {
+            let rows = users_query.stream(&self.sql_pool).await?;
+            futures_util::pin_mut!(rows);
+            while let Some(user) = rows.try_next().await? {
+                users.push(UserAndGroups {
+                    user: user.into(),
+                    groups: None,
+                });
+            }
+        }
 
+        if attributes.as_ref().map(Vec::is_empty).unwrap_or(false) {
+            // No attribute was requested, skip the query entirely.
+            return Ok(users);
+        }
         // At this point, the users don't have attributes, we need to populate it with another query.
-        let attributes = model::UserAttributes::find()
-            .filter(
-                model::UserAttributesColumn::UserId.in_subquery(
-                    model::User::find()
-                        .filter(filters)
-                        .select_only()
-                        .column(model::users::Column::UserId)
-                        .into_query(),
-                ),
-            )
+        let mut attributes_query = model::UserAttributes::find().filter(
+            model::UserAttributesColumn::UserId.in_subquery(
+                model::User::find()
+                    .filter(filters)
+                    .select_only()
+                    .column(model::users::Column::UserId)
+                    .into_query(),
+            ),
+        );
+        if let Some(attributes) = attributes {
+            attributes_query = attributes_query
+                .filter(model::UserAttributesColumn::AttributeName.is_in(attributes));
+        }
+        let attributes = attributes_query
             .order_by_asc(model::UserAttributesColumn::UserId)
             .order_by_asc(model::UserAttributesColumn::AttributeName)
-            .all(&self.sql_pool)
+            .stream(&self.sql_pool)
             .await?;
-        let mut attributes_iter = attributes.into_iter().peekable();
-        use itertools::Itertools; // For take_while_ref
-        for user in users.iter_mut() {
-            user.user.attributes = attributes_iter
-                .take_while_ref(|u| u.user_id == user.user.user_id)
-                .map(AttributeValue::from)
-                .collect();
+        futures_util::pin_mut!(attributes);
+        let mut users_iter = users.iter_mut().peekable();
+        while let Some(attribute) = attributes.try_next().await? {
+            while users_iter
+                .next_if(|u| u.user.user_id != attribute.user_id)
+                .is_some()
+            {}
+            if let Some(user) = users_iter.peek_mut() {
+                user.user.attributes.push(AttributeValue::from(attribute));
+            }
         }
         Ok(users)
     }
-}
 
-impl SqlBackendHandler {
+    async fn check_display_name_uniqueness(
+        transaction: &DatabaseTransaction,
+        policy: DisplayNameUniqueness,
+        user_id: &UserId,
+        display_name: &Option<String>,
+    ) -> Result<()> {
+        let display_name = match display_name {
+            Some(name) if !name.is_empty() && policy != DisplayNameUniqueness::Allow => name,
+            _ => return Ok(()),
+        };
+        let duplicate = model::User::find()
+            .filter(UserColumn::UserId.ne(user_id.clone()))
+            .filter(
+                SimpleExpr::FunctionCall(Func::lower(Expr::col(
+                    UserColumn::DisplayName.as_column_ref(),
+                )))
+                .eq(display_name.to_lowercase()),
+            )
+            .one(transaction)
+            .await?;
+        match (duplicate, policy) {
+            (Some(other), DisplayNameUniqueness::Enforce) => Err(DomainError::Conflict(format!(
+                r#"The display name "{}" is already used by the user "{}""#,
+                display_name, other.user_id
+            ))),
+            (Some(other), _) => {
+                warn!(
+                    r#"The display name "{}" of the user "{}" is already used by the user "{}""#,
+                    display_name, user_id, other.user_id
+                );
+                Ok(())
+            }
+            (None, _) => Ok(()),
+        }
+    }
+
+    async fn check_external_id_uniqueness(
+        transaction: &DatabaseTransaction,
+        external_id: &Option<String>,
+    ) -> Result<()> {
+        let external_id = match external_id {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        if let Some(other) = model::User::find()
+            .filter(UserColumn::ExternalId.eq(external_id.as_str()))
+            .one(transaction)
+            .await?
+        {
+            return Err(DomainError::Conflict(format!(
+                r#"The external ID "{}" is already used by the user "{}""#,
+                external_id, other.user_id
+            )));
+        }
+        Ok(())
+    }
+
     async fn update_user_with_transaction(
         transaction: &DatabaseTransaction,
         request: UpdateUserRequest,
+        rules: &[AttributeValidationRule],
+        display_name_uniqueness: DisplayNameUniqueness,
     ) -> Result<()> {
+        Self::check_display_name_uniqueness(
+            transaction,
+            display_name_uniqueness,
+            &request.user_id,
+            &request.display_name,
+        )
+        .await?;
+        let user_id = request.user_id.clone();
+        let actor = request.actor.clone();
+        let before = get_user_with_attributes(transaction, &user_id).await?;
         let lower_email = request.email.as_ref().map(|s| s.as_str().to_lowercase());
         let update_user = model::users::ActiveModel {
             user_id: ActiveValue::Set(request.user_id.clone()),
@@ -217,14 +397,15 @@ impl SqlBackendHandler {
         }
         let schema = Self::get_schema_with_transaction(transaction).await?;
         for attribute in request.insert_attributes {
-            if schema
-                .user_attributes
-                .get_attribute_type(&attribute.name)
-                .is_some()
+            if let Some((attribute_type, is_list)) =
+                schema.user_attributes.get_attribute_type(&attribute.name)
             {
+                check_attribute_value(rules, &attribute, attribute_type, is_list)?;
+                check_locale_attribute(&attribute, attribute_type, is_list)?;
+                check_ssh_public_key_attribute(&attribute, attribute_type, is_list)?;
                 process_serialized(ActiveValue::Set(attribute.value), attribute.name.clone());
             } else {
-                return Err(DomainError::InternalError(format!(
+                return Err(DomainError::ValidationError(format!(
                     "User attribute name {} doesn't exist in the schema, yet was attempted to be inserted in the database",
                     &attribute.name
                 )));
@@ -238,7 +419,7 @@ impl SqlBackendHandler {
             {
                 remove_user_attributes.push(attribute);
             } else {
-                return Err(DomainError::InternalError(format!(
+                return Err(DomainError::ValidationError(format!(
                     "User attribute name {} doesn't exist in the schema, yet was attempted to be removed from the database",
                     attribute
                 )));
@@ -265,27 +446,171 @@ impl SqlBackendHandler {
                 .exec(transaction)
                 .await?;
         }
+        let after = get_user_with_attributes(transaction, &user_id).await?;
+        Self::record_profile_changes(
+            transaction,
+            &user_id,
+            actor,
+            diff_profiles(&before, &after, &schema.user_attributes),
+        )
+        .await
+    }
+
+    /// Adds the user to the group, recording the group rule that made the change, if any.
+    pub(crate) async fn add_membership(
+        &self,
+        user_id: &UserId,
+        group_id: GroupId,
+        rule: Option<String>,
+    ) -> Result<()> {
+        let new_membership = model::memberships::ActiveModel {
+            user_id: ActiveValue::Set(user_id.clone()),
+            group_id: ActiveValue::Set(group_id),
+            creation_date: ActiveValue::Set(Some(chrono::Utc::now().naive_utc())),
+            expiry_date: ActiveValue::Set(None),
+        };
+        let event = match rule {
+            None => ChangeEvent::MembershipAdded {
+                user_id: user_id.clone(),
+                group_id,
+            },
+            Some(rule) => ChangeEvent::MembershipAddedByRule {
+                user_id: user_id.clone(),
+                group_id,
+                rule,
+            },
+        };
+        let member = user_id.clone();
+        let events = self
+            .sql_pool
+            .transaction::<_, Vec<ChangeEvent>, DomainError>(|transaction| {
+                Box::pin(async move {
+                    new_membership.insert(transaction).await?;
+                    let attributes_changed =
+                        Self::apply_attribute_templates(transaction, &member, group_id).await?;
+                    Self::record_membership_change(transaction, event, member, attributes_changed)
+                        .await
+                })
+            })
+            .await?;
+        for event in events {
+            self.publish_change(event);
+        }
+        Ok(())
+    }
+
+    /// Records the membership change and, if the attribute templates changed the attributes of
+    /// the user, its update.
+    async fn record_membership_change(
+        transaction: &DatabaseTransaction,
+        event: ChangeEvent,
+        user_id: UserId,
+        attributes_changed: bool,
+    ) -> Result<Vec<ChangeEvent>> {
+        let mut events = vec![event];
+        if attributes_changed {
+            events.push(ChangeEvent::UserUpdated(user_id));
+        }
+        for event in &events {
+            Self::record_change(transaction, event).await?;
+        }
+        Ok(events)
+    }
+
+    /// Called after the creation of the user, which is already committed: like for the group
+    /// rules, a failure is only logged.
+    async fn add_user_to_default_groups(&self, user_id: &UserId, source: UserCreationSource) {
+        let names = self
+            .config
+            .default_groups
+            .iter()
+            .filter(|g| g.applies_to(source))
+            .map(|g| &g.group)
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            return;
+        }
+        let groups = match self.get_groups_by_name().await {
+            Ok(groups) => groups,
+            Err(e) => {
+                error!("Could not add {} to the default groups: {}", user_id, e);
+                return;
+            }
+        };
+        let mut added = HashSet::new();
+        for name in names {
+            match groups.get(name) {
+                None => warn!(r#"The default group "{}" doesn't exist"#, name),
+                Some(&group_id) if added.insert(group_id) => {
+                    if let Err(e) = self.add_membership(user_id, group_id, None).await {
+                        error!("Could not add {} to the group {}: {}", user_id, name, e);
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    pub(crate) async fn remove_membership(
+        &self,
+        user_id: &UserId,
+        group_id: GroupId,
+        rule: Option<String>,
+    ) -> Result<()> {
+        let event = match rule {
+            None => ChangeEvent::MembershipRemoved {
+                user_id: user_id.clone(),
+                group_id,
+            },
+            Some(rule) => ChangeEvent::MembershipRemovedByRule {
+                user_id: user_id.clone(),
+                group_id,
+                rule,
+            },
+        };
+        let member = user_id.clone();
+        let events = self
+            .sql_pool
+            .transaction::<_, Vec<ChangeEvent>, DomainError>(|transaction| {
+                Box::pin(async move {
+                    Self::delete_membership(transaction, event, member, group_id).await
+                })
+            })
+            .await?;
+        for event in events {
+            self.publish_change(event);
+        }
         Ok(())
     }
+
+    /// Deletes the membership and records the change, without publishing it: the caller does
+    /// once the transaction is committed.
+    pub(crate) async fn delete_membership(
+        transaction: &DatabaseTransaction,
+        event: ChangeEvent,
+        user_id: UserId,
+        group_id: GroupId,
+    ) -> Result<Vec<ChangeEvent>> {
+        let res = model::Membership::delete_by_id((user_id.clone(), group_id))
+            .exec(transaction)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such membership: '{}' -> {:?}",
+                user_id, group_id
+            )));
+        }
+        let attributes_changed =
+            Self::revert_attribute_templates(transaction, &user_id, group_id).await?;
+        Self::record_membership_change(transaction, event, user_id, attributes_changed).await
+    }
 }
 
 #[async_trait]
 impl UserBackendHandler for SqlBackendHandler {
     #[instrument(skip_all, level = "debug", ret, fields(user_id = ?user_id.as_str()))]
     async fn get_user_details(&self, user_id: &UserId) -> Result<User> {
-        let mut user = User::from(
-            model::User::find_by_id(user_id.to_owned())
-                .one(&self.sql_pool)
-                .await?
-                .ok_or_else(|| DomainError::EntityNotFound(user_id.to_string()))?,
-        );
-        let attributes = model::UserAttributes::find()
-            .filter(model::UserAttributesColumn::UserId.eq(user_id))
-            .order_by_asc(model::UserAttributesColumn::AttributeName)
-            .all(&self.sql_pool)
-            .await?;
-        user.attributes = attributes.into_iter().map(AttributeValue::from).collect();
-        Ok(user)
+        get_user_with_attributes(&self.sql_pool, user_id).await
     }
 
     #[instrument(skip_all, level = "debug", ret, err, fields(user_id = ?user_id.as_str()))]
@@ -305,16 +630,24 @@ impl UserBackendHandler for SqlBackendHandler {
 
     #[instrument(skip(self), level = "debug", err, fields(user_id = ?request.user_id.as_str()))]
     async fn create_user(&self, request: CreateUserRequest) -> Result<()> {
+        let request = self
+            .review_change(WebhookOperation::CreateUser, request)
+            .await?;
+        let user_id = request.user_id.clone();
+        let source = request.source;
         let now = chrono::Utc::now().naive_utc();
         let uuid = Uuid::from_name_and_date(request.user_id.as_str(), &now);
         let lower_email = request.email.as_str().to_lowercase();
+        let external_id = request.external_id.filter(|id| !id.is_empty());
         let new_user = model::users::ActiveModel {
             user_id: Set(request.user_id.clone()),
             email: Set(request.email),
             lowercase_email: Set(lower_email),
             display_name: to_value(&request.display_name),
             creation_date: ActiveValue::Set(now),
+            modified_date: ActiveValue::Set(now),
             uuid: ActiveValue::Set(uuid),
+            external_id: Set(external_id.clone()),
             ..Default::default()
         };
         let mut new_user_attributes = Vec::new();
@@ -339,58 +672,115 @@ impl UserBackendHandler for SqlBackendHandler {
                 value: Set(Serialized::from(&avatar)),
             });
         }
+        let rules = self.config.attribute_validation.users.clone();
+        let display_name_uniqueness = self.config.display_name_uniqueness;
+        let uid_number_start = self.config.posix_defaults.uid_number_start;
+        let max_users = self.config.quota_options.max_users;
         self.sql_pool
             .transaction::<_, (), DomainError>(|transaction| {
                 Box::pin(async move {
+                    if let Some(max_users) = max_users {
+                        let count = model::User::find().count(transaction).await?;
+                        check_quota(count, max_users, "users")?;
+                    }
+                    Self::check_display_name_uniqueness(
+                        transaction,
+                        display_name_uniqueness,
+                        &request.user_id,
+                        &request.display_name,
+                    )
+                    .await?;
+                    Self::check_external_id_uniqueness(transaction, &external_id).await?;
                     let schema = Self::get_schema_with_transaction(transaction).await?;
+                    let mut needs_uid_number =
+                        is_allocated(&schema.user_attributes, UID_NUMBER_ATTRIBUTE);
                     for attribute in request.attributes {
-                        if schema
-                            .user_attributes
-                            .get_attribute_type(&attribute.name)
-                            .is_some()
+                        if attribute.name.as_str() == UID_NUMBER_ATTRIBUTE {
+                            needs_uid_number = false;
+                        }
+                        if let Some((attribute_type, is_list)) =
+                            schema.user_attributes.get_attribute_type(&attribute.name)
                         {
+                            check_attribute_value(&rules, &attribute, attribute_type, is_list)?;
+                            check_locale_attribute(&attribute, attribute_type, is_list)?;
+                            check_ssh_public_key_attribute(&attribute, attribute_type, is_list)?;
                             new_user_attributes.push(model::user_attributes::ActiveModel {
                                 user_id: Set(request.user_id.clone()),
                                 attribute_name: Set(attribute.name),
                                 value: Set(attribute.value),
                             });
                         } else {
-                            return Err(DomainError::InternalError(format!(
+                            return Err(DomainError::ValidationError(format!(
                                 "Attribute name {} doesn't exist in the user schema,
                                     yet was attempted to be inserted in the database",
                                 &attribute.name
                             )));
                         }
                     }
+                    if needs_uid_number {
+                        new_user_attributes.push(model::user_attributes::ActiveModel {
+                            user_id: Set(request.user_id.clone()),
+                            attribute_name: Set(UID_NUMBER_ATTRIBUTE.into()),
+                            value: Set(Serialized::from(
+                                &next_uid_number(transaction, uid_number_start).await?,
+                            )),
+                        });
+                    }
                     new_user.insert(transaction).await?;
                     if !new_user_attributes.is_empty() {
                         model::UserAttributes::insert_many(new_user_attributes)
                             .exec(transaction)
                             .await?;
                     }
-                    Ok(())
+                    Self::record_change(transaction, &ChangeEvent::UserCreated(request.user_id))
+                        .await
                 })
             })
             .await?;
+        self.publish_change(ChangeEvent::UserCreated(user_id.clone()));
+        self.add_user_to_default_groups(&user_id, source).await;
+        self.apply_group_rules_to_user(&user_id).await;
         Ok(())
     }
 
     #[instrument(skip(self), level = "debug", err, fields(user_id = ?request.user_id.as_str()))]
     async fn update_user(&self, request: UpdateUserRequest) -> Result<()> {
+        let user_id = request.user_id.clone();
+        let request = self
+            .review_change(WebhookOperation::UpdateUser, request)
+            .await?;
+        if request.user_id != user_id {
+            return Err(DomainError::ValidationError(
+                "The validation webhook cannot change the user ID of an update".to_owned(),
+            ));
+        }
+        let rules = self.config.attribute_validation.users.clone();
+        let display_name_uniqueness = self.config.display_name_uniqueness;
         self.sql_pool
             .transaction::<_, (), DomainError>(|transaction| {
-                Box::pin(
-                    async move { Self::update_user_with_transaction(transaction, request).await },
-                )
+                Box::pin(async move {
+                    let event = ChangeEvent::UserUpdated(request.user_id.clone());
+                    Self::update_user_with_transaction(
+                        transaction,
+                        request,
+                        &rules,
+                        display_name_uniqueness,
+                    )
+                    .await?;
+                    Self::record_change(transaction, &event).await
+                })
             })
             .await?;
+        self.publish_change(ChangeEvent::UserUpdated(user_id.clone()));
+        self.apply_group_rules_to_user(&user_id).await;
         Ok(())
     }
 
     #[instrument(skip_all, level = "debug", err, fields(user_id = ?user_id.as_str()))]
     async fn delete_user(&self, user_id: &UserId) -> Result<()> {
+        let transaction = self.sql_pool.begin().await?;
         let res = model::User::delete_by_id(user_id.clone())
-            .exec(&self.sql_pool)
+            .exec(&transaction)
             .await?;
         if res.rows_affected == 0 {
             return Err(DomainError::EntityNotFound(format!(
@@ -398,41 +788,61 @@ impl UserBackendHandler for SqlBackendHandler {
                 user_id
             )));
         }
-        Ok(())
+        self.commit_change(transaction, ChangeEvent::UserDeleted(user_id.clone()))
+            .await
     }
 
     #[instrument(skip_all, level = "debug", err, fields(user_id = ?user_id.as_str(), group_id))]
     async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()> {
-        let new_membership = model::memberships::ActiveModel {
-            user_id: ActiveValue::Set(user_id.clone()),
-            group_id: ActiveValue::Set(group_id),
-        };
-        new_membership.insert(&self.sql_pool).await?;
-        Ok(())
+        self.add_membership(user_id, group_id, None).await
     }
 
     #[instrument(skip_all, level = "debug", err, fields(user_id = ?user_id.as_str(), group_id))]
     async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()> {
-        let res = model::Membership::delete_by_id((user_id.clone(), group_id))
-            .exec(&self.sql_pool)
-            .await?;
-        if res.rows_affected == 0 {
-            return Err(DomainError::EntityNotFound(format!(
-                "No such membership: '{}' -> {:?}",
-                user_id, group_id
-            )));
-        }
-        Ok(())
+        self.remove_membership(user_id, group_id, None).await
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn list_user_sessions(&self, user_id: &UserId) -> Result<Vec<UserSession>> {
+        let now = chrono::Utc::now().naive_utc();
+        let refresh_tokens = model::JwtRefreshStorage::find()
+            .filter(JwtRefreshStorageColumn::UserId.eq(user_id))
+            .filter(JwtRefreshStorageColumn::ExpiryDate.gt(now))
+            .order_by_asc(JwtRefreshStorageColumn::ExpiryDate)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(|t| UserSession {
+                kind: SessionKind::RefreshToken,
+                expiry_date: t.expiry_date,
+                revoked: false,
+            });
+        let access_tokens = model::JwtStorage::find()
+            .filter(JwtStorageColumn::UserId.eq(user_id))
+            .filter(JwtStorageColumn::ExpiryDate.gt(now))
+            .order_by_asc(JwtStorageColumn::ExpiryDate)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(|t| UserSession {
+                kind: SessionKind::AccessToken,
+                expiry_date: t.expiry_date,
+                revoked: t.blacklisted,
+            });
+        Ok(refresh_tokens.chain(access_tokens).collect())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::{
-        handler::SubStringFilter,
-        sql_backend_handler::tests::*,
-        types::{JpegPhoto, UserColumn},
+    use crate::{
+        domain::{
+            handler::{ChangeFeedBackendHandler, SubStringFilter},
+            sql_backend_handler::tests::*,
+            types::{JpegPhoto, UserColumn},
+        },
+        infra::configuration::DefaultGroup,
     };
     use pretty_assertions::{assert_eq, assert_ne};
 
@@ -748,6 +1158,46 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_list_users_with_attributes() {
+        let fixture = TestFixture::new().await;
+        let filter = Some(UserRequestFilter::UserId(UserId::new("bob")));
+        let users = fixture
+            .handler
+            .list_users_with_attributes(
+                filter.clone(),
+                false,
+                Some(vec![AttributeName::from("first_name")]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].groups, None);
+        assert_eq!(
+            users[0].user.attributes,
+            vec![AttributeValue {
+                name: "first_name".into(),
+                value: Serialized::from("first bob"),
+            }]
+        );
+        let users = fixture
+            .handler
+            .list_users_with_attributes(filter, true, Some(Vec::new()))
+            .await
+            .unwrap();
+        assert_eq!(users[0].user.attributes, Vec::new());
+        assert_eq!(
+            users[0]
+                .groups
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|g| g.group_id)
+                .collect::<Vec<_>>(),
+            vec![fixture.groups[0]]
+        );
+    }
+
     #[tokio::test]
     async fn test_get_user_details() {
         let handler = SqlBackendHandler::new(get_default_config(), get_initialized_db().await);
@@ -851,6 +1301,7 @@ mod tests {
                 avatar: Some(JpegPhoto::for_tests()),
                 delete_attributes: Vec::new(),
                 insert_attributes: Vec::new(),
+                actor: None,
             })
             .await
             .unwrap();
@@ -876,6 +1327,10 @@ mod tests {
                 AttributeValue {
                     name: "last_name".into(),
                     value: Serialized::from("last_name")
+                },
+                AttributeValue {
+                    name: "uidnumber".into(),
+                    value: Serialized::from(&10000i64)
                 }
             ]
         );
@@ -913,6 +1368,10 @@ mod tests {
                 AttributeValue {
                     name: "first_name".into(),
                     value: Serialized::from("first bob")
+                },
+                AttributeValue {
+                    name: "uidnumber".into(),
+                    value: Serialized::from(&10000i64)
                 }
             ]
         );
@@ -953,6 +1412,10 @@ mod tests {
                 AttributeValue {
                     name: "last_name".into(),
                     value: Serialized::from("last bob")
+                },
+                AttributeValue {
+                    name: "uidnumber".into(),
+                    value: Serialized::from(&10000i64)
                 }
             ]
         );
@@ -982,10 +1445,16 @@ mod tests {
             .unwrap();
         assert_eq!(
             user.attributes,
-            vec![AttributeValue {
-                name: "last_name".into(),
-                value: Serialized::from("last bob")
-            }]
+            vec![
+                AttributeValue {
+                    name: "last_name".into(),
+                    value: Serialized::from("last bob")
+                },
+                AttributeValue {
+                    name: "uidnumber".into(),
+                    value: Serialized::from(&10000i64)
+                }
+            ]
         );
     }
 
@@ -1026,6 +1495,10 @@ mod tests {
                     name: "last_name".into(),
                     value: Serialized::from("last bob")
                 },
+                AttributeValue {
+                    name: "uidnumber".into(),
+                    value: Serialized::from(&10000i64)
+                },
             ]
         );
     }
@@ -1089,6 +1562,7 @@ mod tests {
                     name: "first_name".into(),
                     value: Serialized::from("First Name"),
                 }],
+                external_id: None,
             })
             .await
             .unwrap();
@@ -1114,6 +1588,10 @@ mod tests {
                 AttributeValue {
                     name: "last_name".into(),
                     value: Serialized::from("last_name")
+                },
+                AttributeValue {
+                    name: "uidnumber".into(),
+                    value: Serialized::from(&10004i64)
                 }
             ]
         );
@@ -1191,4 +1669,300 @@ mod tests {
             .await
             .unwrap_err();
     }
+
+    #[tokio::test]
+    async fn test_display_name_uniqueness() {
+        let mut config = get_default_config();
+        config.display_name_uniqueness = DisplayNameUniqueness::Enforce;
+        let handler = SqlBackendHandler::new(config, get_initialized_db().await);
+        insert_user_no_password(&handler, "bob").await;
+        insert_user_no_password(&handler, "john").await;
+
+        let err = handler
+            .create_user(CreateUserRequest {
+                user_id: UserId::new("james"),
+                email: "james@bob.bob".into(),
+                display_name: Some("Display BOB".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DomainError::Conflict(_)), "{:?}", err);
+        handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("john"),
+                display_name: Some("display bob".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        // Keeping the same display name is not a conflict with oneself.
+        handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                display_name: Some("display bob".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_attribute_validation_error() {
+        let mut config = get_default_config();
+        config.attribute_validation.users = vec![AttributeValidationRule {
+            attribute: "first_name".into(),
+            regex: Some("[A-Z][a-z]+".to_owned().try_into().unwrap()),
+            ..Default::default()
+        }];
+        let handler = SqlBackendHandler::new(config, get_initialized_db().await);
+        insert_user_no_password(&handler, "bob").await;
+        let update = |first_name: &str| {
+            handler.update_user(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                insert_attributes: vec![AttributeValue {
+                    name: "first_name".into(),
+                    value: Serialized::from(first_name),
+                }],
+                ..Default::default()
+            })
+        };
+        update("Bob").await.unwrap();
+        let err = update("bob").await.unwrap_err();
+        match err {
+            DomainError::AttributeValidationError {
+                attribute, rule, ..
+            } => {
+                assert_eq!(attribute, "first_name".into());
+                assert_eq!(rule, "regex");
+            }
+            err => panic!("{:?}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_display_name_uniqueness_warn() {
+        let mut config = get_default_config();
+        config.display_name_uniqueness = DisplayNameUniqueness::Warn;
+        let handler = SqlBackendHandler::new(config, get_initialized_db().await);
+        insert_user_no_password(&handler, "bob").await;
+        handler
+            .create_user(CreateUserRequest {
+                user_id: UserId::new("james"),
+                email: "james@bob.bob".into(),
+                display_name: Some("display bob".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_default_groups() {
+        let mut config = get_default_config();
+        config.default_groups = vec![
+            DefaultGroup {
+                group: "Staff".into(),
+                sources: vec![],
+            },
+            DefaultGroup {
+                group: "ldap users".into(),
+                sources: vec![UserCreationSource::Ldap, UserCreationSource::LdapSync],
+            },
+            DefaultGroup {
+                group: "Missing".into(),
+                sources: vec![],
+            },
+        ];
+        let handler = SqlBackendHandler::new(config, get_initialized_db().await);
+        insert_group(&handler, "staff").await;
+        insert_group(&handler, "LDAP users").await;
+        let mut events = handler.subscribe_to_changes();
+        for (user, source) in [
+            ("james", UserCreationSource::Graphql),
+            ("jim", UserCreationSource::Ldap),
+        ] {
+            handler
+                .create_user(CreateUserRequest {
+                    user_id: UserId::new(user),
+                    email: format!("{}@bob.bob", user).into(),
+                    source,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        async fn get_group_names(handler: &SqlBackendHandler, user: &str) -> Vec<String> {
+            let mut names = handler
+                .get_user_groups(&UserId::new(user))
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|g| g.display_name.into_string())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        }
+        assert_eq!(get_group_names(&handler, "james").await, vec!["staff"]);
+        assert_eq!(
+            get_group_names(&handler, "jim").await,
+            vec!["LDAP users", "staff"]
+        );
+        let mut membership_events = 0;
+        while let Ok(event) = events.try_recv() {
+            if matches!(event, ChangeEvent::MembershipAdded { .. }) {
+                membership_events += 1;
+            }
+        }
+        assert_eq!(membership_events, 3);
+    }
+
+    #[tokio::test]
+    async fn test_external_id() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .create_user(CreateUserRequest {
+                user_id: UserId::new("james"),
+                email: "james@bob.bob".into(),
+                external_id: Some("HR-0042".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let err = fixture
+            .handler
+            .create_user(CreateUserRequest {
+                user_id: UserId::new("jim"),
+                email: "jim@bob.bob".into(),
+                external_id: Some("HR-0042".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DomainError::Conflict(_)), "{:?}", err);
+        // Users without an external ID don't conflict.
+        fixture
+            .handler
+            .create_user(CreateUserRequest {
+                user_id: UserId::new("jim"),
+                email: "jim@bob.bob".into(),
+                external_id: Some(String::new()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        // Renames and email changes keep the link.
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("james"),
+                email: Some("james@example.com".into()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::Equality(
+                UserColumn::ExternalId,
+                "hr-0042".to_owned(),
+            )),
+        )
+        .await;
+        assert_eq!(users, vec!["james"]);
+        let james = fixture
+            .handler
+            .get_user_details(&UserId::new("james"))
+            .await
+            .unwrap();
+        assert_eq!(james.external_id.as_deref(), Some("HR-0042"));
+    }
+
+    #[tokio::test]
+    async fn test_modified_since() {
+        let fixture = TestFixture::new().await;
+        let since = chrono::Utc::now().naive_utc();
+        let modified_since = || {
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::ModifiedSince(since)),
+            )
+        };
+        assert!(modified_since().await.is_empty());
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                display_name: Some("Bob".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(modified_since().await, vec!["bob"]);
+        // Joining a group changes the memberOf of the user.
+        fixture
+            .handler
+            .add_user_to_group(&UserId::new("NoGroup"), fixture.groups[2])
+            .await
+            .unwrap();
+        assert_eq!(modified_since().await, vec!["bob", "nogroup"]);
+        let bob = fixture
+            .handler
+            .get_user_details(&UserId::new("bob"))
+            .await
+            .unwrap();
+        assert!(bob.modified_date >= since);
+        assert!(bob.creation_date < since);
+    }
+
+    #[tokio::test]
+    async fn test_create_user_max_users() {
+        let mut config = get_default_config();
+        config.quota_options.max_users = Some(2);
+        let handler = SqlBackendHandler::new(config, get_initialized_db().await);
+        insert_user_no_password(&handler, "bob").await;
+        insert_user_no_password(&handler, "patrick").await;
+        let error = handler
+            .create_user(CreateUserRequest {
+                user_id: UserId::new("john"),
+                email: "john@bob.bob".into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(error, DomainError::QuotaExceeded(_)), "{}", error);
+        assert_eq!(handler.list_users(None, false).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_list_user_sessions() {
+        let fixture = TestFixture::new().await;
+        let now = chrono::Utc::now().naive_utc();
+        for (hash, user, expiry_date) in [
+            (1, "bob", now + chrono::Duration::days(1)),
+            (2, "bob", now - chrono::Duration::days(1)),
+            (3, "patrick", now + chrono::Duration::days(1)),
+        ] {
+            model::jwt_refresh_storage::ActiveModel {
+                refresh_token_hash: Set(hash),
+                verifier_hash: Set(Vec::new()),
+                user_id: Set(UserId::new(user)),
+                expiry_date: Set(expiry_date),
+            }
+            .insert(&fixture.handler.sql_pool)
+            .await
+            .unwrap();
+        }
+        let sessions = fixture
+            .handler
+            .list_user_sessions(&UserId::new("bob"))
+            .await
+            .unwrap();
+        assert_eq!(
+            sessions.iter().map(|s| s.kind).collect::<Vec<_>>(),
+            vec![SessionKind::RefreshToken]
+        );
+        assert!(!sessions[0].revoked);
+    }
 }
@@ -420,10 +420,30 @@ pub struct User {
     pub email: Email,
     pub display_name: Option<String>,
     pub creation_date: NaiveDateTime,
+    /// The last change of the user, its attributes or its groups.
+    pub modified_date: NaiveDateTime,
     pub uuid: Uuid,
+    pub external_id: Option<String>,
     pub attributes: Vec<AttributeValue>,
 }
 
+/// What kind of token backs a [`UserSession`].
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum SessionKind {
+    /// Long-lived token, created when logging in through the web UI.
+    RefreshToken,
+    /// Short-lived token, only recorded once revoked.
+    AccessToken,
+}
+
+/// A login session of a user, as known from the tokens stored for it.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct UserSession {
+    pub kind: SessionKind,
+    pub expiry_date: NaiveDateTime,
+    pub revoked: bool,
+}
+
 #[cfg(test)]
 impl Default for User {
     fn default() -> Self {
@@ -433,7 +453,9 @@ impl Default for User {
             email: Email::default(),
             display_name: None,
             creation_date: epoch,
+            modified_date: epoch,
             uuid: Uuid::from_name_and_date("", &epoch),
+            external_id: None,
             attributes: Vec::new(),
         }
     }
@@ -522,11 +544,13 @@ impl ValueType for AttributeType {
     }
 }
 
-#[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
 pub struct Group {
     pub id: GroupId,
     pub display_name: GroupName,
     pub creation_date: NaiveDateTime,
+    /// The last change of the group, its attributes or its members.
+    pub modified_date: NaiveDateTime,
     pub uuid: Uuid,
     pub users: Vec<UserId>,
     pub attributes: Vec<AttributeValue>,
@@ -537,10 +561,22 @@ pub struct GroupDetails {
     pub group_id: GroupId,
     pub display_name: GroupName,
     pub creation_date: NaiveDateTime,
+    /// The last change of the group, its attributes or its members.
+    pub modified_date: NaiveDateTime,
     pub uuid: Uuid,
     pub attributes: Vec<AttributeValue>,
 }
 
+/// A member of a group.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupMembership {
+    pub user_id: UserId,
+    /// Unknown for the memberships created before it was recorded.
+    pub creation_date: Option<NaiveDateTime>,
+    /// None for the permanent memberships.
+    pub expiry_date: Option<NaiveDateTime>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UserAndGroups {
     pub user: User,
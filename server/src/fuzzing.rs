@@ -0,0 +1,36 @@
+//! Entry points for the fuzz targets of `server/fuzz`, which run them with arbitrary inputs:
+//!
+//! ```text
+//! cargo +nightly fuzz run ldap_dn
+//! ```
+//!
+//! Beyond not panicking, the parsers must accept what they print back.
+
+use crate::{
+    domain::ldap::{
+        filter_parser::parse_filter,
+        utils::{format_distinguished_name, parse_distinguished_name},
+    },
+    infra::ldap_metrics::format_filter,
+};
+
+/// Parses a DN, and checks that its canonical form parses to the same DN.
+pub fn fuzz_distinguished_name(data: &[u8]) {
+    if let Ok(Ok(parts)) = std::str::from_utf8(data).map(parse_distinguished_name) {
+        let formatted = format_distinguished_name(&parts);
+        assert_eq!(
+            parse_distinguished_name(&formatted).ok(),
+            Some(parts),
+            "{}",
+            formatted
+        );
+    }
+}
+
+/// Parses a filter, and checks that its string representation parses to the same filter.
+pub fn fuzz_filter(data: &[u8]) {
+    if let Ok(Ok(parsed)) = std::str::from_utf8(data).map(parse_filter) {
+        let formatted = format_filter(&parsed);
+        assert_eq!(parse_filter(&formatted).ok(), Some(parsed), "{}", formatted);
+    }
+}
@@ -3,21 +3,38 @@ use std::collections::HashSet;
 use async_trait::async_trait;
 use tracing::info;
 
-use crate::domain::{
-    error::Result,
-    handler::{
-        AttributeSchema, BackendHandler, CreateAttributeRequest, CreateGroupRequest,
-        CreateUserRequest, GroupBackendHandler, GroupListerBackendHandler, GroupRequestFilter,
-        ReadSchemaBackendHandler, Schema, SchemaBackendHandler, UpdateGroupRequest,
-        UpdateUserRequest, UserBackendHandler, UserListerBackendHandler, UserRequestFilter,
-    },
-    schema::PublicSchema,
-    types::{
-        AttributeName, Group, GroupDetails, GroupId, GroupName, LdapObjectClass, User,
-        UserAndGroups, UserId,
+use crate::{
+    domain::{
+        access_reviews::{AccessReview, AccessReviewInvitation},
+        account_deletions::AccountDeletion,
+        attribute_templates::AttributeTemplate,
+        change_events::ChangeFeedEntry,
+        error::Result,
+        group_rules::GroupRuleChange,
+        handler::{
+            AccessReviewBackendHandler, AccountDeletionBackendHandler, AttributeSchema,
+            AttributeTemplateBackendHandler, AvatarSyncBackendHandler, BackendHandler,
+            ChangeFeedBackendHandler, CreateAttributeRequest, CreateGroupRequest,
+            CreateUserRequest, GroupBackendHandler, GroupListerBackendHandler, GroupRequestFilter,
+            GroupRuleBackendHandler, IdentityLinkBackendHandler, MembershipExpiryBackendHandler,
+            NestedGroupBackendHandler, PendingChangeBackendHandler, ProfileHistoryBackendHandler,
+            ReadSchemaBackendHandler, Schema, SchemaBackendHandler, UpdateGroupRequest,
+            UpdateUserRequest, UserBackendHandler, UserListerBackendHandler, UserRequestFilter,
+        },
+        identity_links::IdentityLink,
+        nested_groups::GroupHierarchy,
+        pending_changes::{PendingChange, SensitiveChange},
+        profile_history::ProfileChange,
+        schema::PublicSchema,
+        types::{
+            AttributeName, Group, GroupDetails, GroupId, GroupMembership, GroupName,
+            LdapObjectClass, User, UserAndGroups, UserId, UserSession,
+        },
     },
+    infra::configuration::DirectoryVisibility,
 };
 
+/// What a bound user is allowed to do, derived from their membership in the special groups.
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Permission {
     Admin,
@@ -26,10 +43,19 @@ pub enum Permission {
     Regular,
 }
 
+/// An admin acting as another user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Impersonation {
+    pub impersonator: UserId,
+    /// A read-only impersonation can see what the user sees, but not modify anything.
+    pub read_only: bool,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ValidationResults {
     pub user: UserId,
     pub permission: Permission,
+    pub impersonation: Option<Impersonation>,
 }
 
 impl ValidationResults {
@@ -38,12 +64,34 @@ impl ValidationResults {
         Self {
             user: UserId::new("admin"),
             permission: Permission::Admin,
+            impersonation: None,
         }
     }
 
+    /// The user really making the changes: the admin, when impersonating another user.
+    pub fn actor(&self) -> &UserId {
+        self.impersonation
+            .as_ref()
+            .map_or(&self.user, |i| &i.impersonator)
+    }
+
+    fn is_read_only_impersonation(&self) -> bool {
+        self.impersonation
+            .as_ref()
+            .map(|i| i.read_only)
+            .unwrap_or(false)
+    }
+
     #[must_use]
     pub fn is_admin(&self) -> bool {
-        self.permission == Permission::Admin
+        self.permission == Permission::Admin && !self.is_read_only_impersonation()
+    }
+
+    /// A read-only account can search the whole directory, but never sees the passwords and
+    /// cannot modify anything, not even its own entry.
+    #[must_use]
+    pub fn is_readonly(&self) -> bool {
+        self.permission == Permission::Readonly
     }
 
     #[must_use]
@@ -63,14 +111,16 @@ impl ValidationResults {
 
     #[must_use]
     pub fn can_change_password(&self, user: &UserId, user_is_admin: bool) -> bool {
-        self.permission == Permission::Admin
-            || (self.permission == Permission::PasswordManager && !user_is_admin)
-            || &self.user == user
+        !self.is_read_only_impersonation()
+            && (self.permission == Permission::Admin
+                || (self.permission == Permission::PasswordManager && !user_is_admin)
+                || &self.user == user)
     }
 
     #[must_use]
     pub fn can_write(&self, user: &UserId) -> bool {
-        self.permission == Permission::Admin || &self.user == user
+        !self.is_read_only_impersonation()
+            && (self.permission == Permission::Admin || &self.user == user)
     }
 }
 
@@ -79,6 +129,12 @@ pub trait UserReadableBackendHandler: ReadSchemaBackendHandler {
     async fn get_user_details(&self, user_id: &UserId) -> Result<User>;
     async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>>;
     async fn get_schema(&self) -> Result<PublicSchema>;
+    async fn get_account_deletion(&self, user_id: &UserId) -> Result<Option<AccountDeletion>>;
+    async fn list_user_sessions(&self, user_id: &UserId) -> Result<Vec<UserSession>>;
+    async fn list_changes_for_user(&self, user_id: &UserId) -> Result<Vec<ChangeFeedEntry>>;
+    async fn get_avatar_sync_opt_out(&self, user_id: &UserId) -> Result<bool>;
+    async fn list_identity_links(&self, user_id: &UserId) -> Result<Vec<IdentityLink>>;
+    async fn list_profile_changes(&self, user_id: &UserId) -> Result<Vec<ProfileChange>>;
 }
 
 #[async_trait]
@@ -90,11 +146,17 @@ pub trait ReadonlyBackendHandler: UserReadableBackendHandler {
     ) -> Result<Vec<UserAndGroups>>;
     async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>>;
     async fn get_group_details(&self, group_id: GroupId) -> Result<GroupDetails>;
+    async fn list_group_memberships(&self, group_id: GroupId) -> Result<Vec<GroupMembership>>;
+    async fn list_changes(&self, since: i32, limit: u64) -> Result<Vec<ChangeFeedEntry>>;
+    async fn list_account_deletions(&self) -> Result<Vec<AccountDeletion>>;
+    async fn list_attribute_templates(&self, group_id: GroupId) -> Result<Vec<AttributeTemplate>>;
+    async fn list_access_reviews(&self) -> Result<Vec<AccessReview>>;
 }
 
 #[async_trait]
 pub trait UserWriteableBackendHandler: UserReadableBackendHandler {
     async fn update_user(&self, request: UpdateUserRequest) -> Result<()>;
+    async fn set_avatar_sync_opt_out(&self, user_id: &UserId, opt_out: bool) -> Result<()>;
 }
 
 #[async_trait]
@@ -119,6 +181,44 @@ pub trait AdminBackendHandler:
     async fn add_group_object_class(&self, name: &LdapObjectClass) -> Result<()>;
     async fn delete_user_object_class(&self, name: &LdapObjectClass) -> Result<()>;
     async fn delete_group_object_class(&self, name: &LdapObjectClass) -> Result<()>;
+    async fn create_pending_change(
+        &self,
+        requested_by: &UserId,
+        change: SensitiveChange,
+    ) -> Result<i32>;
+    async fn list_pending_changes(&self) -> Result<Vec<PendingChange>>;
+    async fn get_pending_change(&self, id: i32) -> Result<PendingChange>;
+    async fn delete_pending_change(&self, id: i32) -> Result<()>;
+    async fn cancel_account_deletion(&self, user_id: &UserId) -> Result<()>;
+    async fn set_attribute_template(&self, template: AttributeTemplate) -> Result<()>;
+    async fn delete_attribute_template(
+        &self,
+        group_id: GroupId,
+        attribute_name: &AttributeName,
+    ) -> Result<()>;
+    async fn preview_group_rules(&self) -> Result<Vec<GroupRuleChange>>;
+    async fn apply_group_rules(&self) -> Result<Vec<GroupRuleChange>>;
+    async fn add_identity_link(
+        &self,
+        user_id: &UserId,
+        provider: &str,
+        subject: &str,
+    ) -> Result<IdentityLink>;
+    async fn delete_identity_link(&self, provider: &str, subject: &str) -> Result<()>;
+    async fn set_membership_expiry(
+        &self,
+        user_id: &UserId,
+        group_id: GroupId,
+        expiry_date: Option<chrono::NaiveDateTime>,
+    ) -> Result<()>;
+    async fn add_group_to_group(&self, member: GroupId, parent: GroupId) -> Result<()>;
+    async fn remove_group_from_group(&self, member: GroupId, parent: GroupId) -> Result<()>;
+    async fn start_access_review(
+        &self,
+        name: &str,
+        created_by: &UserId,
+        groups: &[(GroupId, UserId)],
+    ) -> Result<Vec<AccessReviewInvitation>>;
 }
 
 #[async_trait]
@@ -134,6 +234,24 @@ impl<Handler: BackendHandler> UserReadableBackendHandler for Handler {
             <Handler as ReadSchemaBackendHandler>::get_schema(self).await?,
         ))
     }
+    async fn get_account_deletion(&self, user_id: &UserId) -> Result<Option<AccountDeletion>> {
+        <Handler as AccountDeletionBackendHandler>::get_account_deletion(self, user_id).await
+    }
+    async fn list_user_sessions(&self, user_id: &UserId) -> Result<Vec<UserSession>> {
+        <Handler as UserBackendHandler>::list_user_sessions(self, user_id).await
+    }
+    async fn list_changes_for_user(&self, user_id: &UserId) -> Result<Vec<ChangeFeedEntry>> {
+        <Handler as ChangeFeedBackendHandler>::list_changes_for_user(self, user_id).await
+    }
+    async fn get_avatar_sync_opt_out(&self, user_id: &UserId) -> Result<bool> {
+        <Handler as AvatarSyncBackendHandler>::get_avatar_sync_opt_out(self, user_id).await
+    }
+    async fn list_identity_links(&self, user_id: &UserId) -> Result<Vec<IdentityLink>> {
+        <Handler as IdentityLinkBackendHandler>::list_identity_links(self, user_id).await
+    }
+    async fn list_profile_changes(&self, user_id: &UserId) -> Result<Vec<ProfileChange>> {
+        <Handler as ProfileHistoryBackendHandler>::list_profile_changes(self, user_id).await
+    }
 }
 
 #[async_trait]
@@ -151,6 +269,21 @@ impl<Handler: BackendHandler> ReadonlyBackendHandler for Handler {
     async fn get_group_details(&self, group_id: GroupId) -> Result<GroupDetails> {
         <Handler as GroupBackendHandler>::get_group_details(self, group_id).await
     }
+    async fn list_group_memberships(&self, group_id: GroupId) -> Result<Vec<GroupMembership>> {
+        <Handler as GroupBackendHandler>::list_group_memberships(self, group_id).await
+    }
+    async fn list_changes(&self, since: i32, limit: u64) -> Result<Vec<ChangeFeedEntry>> {
+        <Handler as ChangeFeedBackendHandler>::list_changes(self, since, limit).await
+    }
+    async fn list_account_deletions(&self) -> Result<Vec<AccountDeletion>> {
+        <Handler as AccountDeletionBackendHandler>::list_account_deletions(self).await
+    }
+    async fn list_attribute_templates(&self, group_id: GroupId) -> Result<Vec<AttributeTemplate>> {
+        <Handler as AttributeTemplateBackendHandler>::list_attribute_templates(self, group_id).await
+    }
+    async fn list_access_reviews(&self) -> Result<Vec<AccessReview>> {
+        <Handler as AccessReviewBackendHandler>::list_access_reviews(self).await
+    }
 }
 
 #[async_trait]
@@ -158,6 +291,9 @@ impl<Handler: BackendHandler> UserWriteableBackendHandler for Handler {
     async fn update_user(&self, request: UpdateUserRequest) -> Result<()> {
         <Handler as UserBackendHandler>::update_user(self, request).await
     }
+    async fn set_avatar_sync_opt_out(&self, user_id: &UserId, opt_out: bool) -> Result<()> {
+        <Handler as AvatarSyncBackendHandler>::set_avatar_sync_opt_out(self, user_id, opt_out).await
+    }
 }
 #[async_trait]
 impl<Handler: BackendHandler> AdminBackendHandler for Handler {
@@ -206,6 +342,88 @@ impl<Handler: BackendHandler> AdminBackendHandler for Handler {
     async fn delete_group_object_class(&self, name: &LdapObjectClass) -> Result<()> {
         <Handler as SchemaBackendHandler>::delete_group_object_class(self, name).await
     }
+    async fn create_pending_change(
+        &self,
+        requested_by: &UserId,
+        change: SensitiveChange,
+    ) -> Result<i32> {
+        <Handler as PendingChangeBackendHandler>::create_pending_change(self, requested_by, change)
+            .await
+    }
+    async fn list_pending_changes(&self) -> Result<Vec<PendingChange>> {
+        <Handler as PendingChangeBackendHandler>::list_pending_changes(self).await
+    }
+    async fn get_pending_change(&self, id: i32) -> Result<PendingChange> {
+        <Handler as PendingChangeBackendHandler>::get_pending_change(self, id).await
+    }
+    async fn delete_pending_change(&self, id: i32) -> Result<()> {
+        <Handler as PendingChangeBackendHandler>::delete_pending_change(self, id).await
+    }
+    async fn cancel_account_deletion(&self, user_id: &UserId) -> Result<()> {
+        <Handler as AccountDeletionBackendHandler>::cancel_account_deletion(self, user_id).await
+    }
+    async fn set_attribute_template(&self, template: AttributeTemplate) -> Result<()> {
+        <Handler as AttributeTemplateBackendHandler>::set_attribute_template(self, template).await
+    }
+    async fn delete_attribute_template(
+        &self,
+        group_id: GroupId,
+        attribute_name: &AttributeName,
+    ) -> Result<()> {
+        <Handler as AttributeTemplateBackendHandler>::delete_attribute_template(
+            self,
+            group_id,
+            attribute_name,
+        )
+        .await
+    }
+    async fn preview_group_rules(&self) -> Result<Vec<GroupRuleChange>> {
+        <Handler as GroupRuleBackendHandler>::preview_group_rules(self).await
+    }
+    async fn apply_group_rules(&self) -> Result<Vec<GroupRuleChange>> {
+        <Handler as GroupRuleBackendHandler>::apply_group_rules(self).await
+    }
+    async fn add_identity_link(
+        &self,
+        user_id: &UserId,
+        provider: &str,
+        subject: &str,
+    ) -> Result<IdentityLink> {
+        <Handler as IdentityLinkBackendHandler>::add_identity_link(self, user_id, provider, subject)
+            .await
+    }
+    async fn delete_identity_link(&self, provider: &str, subject: &str) -> Result<()> {
+        <Handler as IdentityLinkBackendHandler>::delete_identity_link(self, provider, subject).await
+    }
+    async fn set_membership_expiry(
+        &self,
+        user_id: &UserId,
+        group_id: GroupId,
+        expiry_date: Option<chrono::NaiveDateTime>,
+    ) -> Result<()> {
+        <Handler as MembershipExpiryBackendHandler>::set_membership_expiry(
+            self,
+            user_id,
+            group_id,
+            expiry_date,
+        )
+        .await
+    }
+    async fn add_group_to_group(&self, member: GroupId, parent: GroupId) -> Result<()> {
+        <Handler as NestedGroupBackendHandler>::add_group_to_group(self, member, parent).await
+    }
+    async fn remove_group_from_group(&self, member: GroupId, parent: GroupId) -> Result<()> {
+        <Handler as NestedGroupBackendHandler>::remove_group_from_group(self, member, parent).await
+    }
+    async fn start_access_review(
+        &self,
+        name: &str,
+        created_by: &UserId,
+        groups: &[(GroupId, UserId)],
+    ) -> Result<Vec<AccessReviewInvitation>> {
+        <Handler as AccessReviewBackendHandler>::start_access_review(self, name, created_by, groups)
+            .await
+    }
 }
 
 pub struct AccessControlledBackendHandler<Handler> {
@@ -278,6 +496,42 @@ impl<Handler: BackendHandler> AccessControlledBackendHandler<Handler> {
         }
     }
 
+    /// The handler listing the users that the user can see, or None if they can only see
+    /// themselves.
+    pub async fn get_directory_lister_handler(
+        &self,
+        validation_result: &ValidationResults,
+        visibility: DirectoryVisibility,
+    ) -> Result<Option<DirectoryListerBackendHandler<'_, Handler>>> {
+        let user_filter = if validation_result.can_read_all() {
+            None
+        } else {
+            match visibility {
+                DirectoryVisibility::Nobody => return Ok(None),
+                DirectoryVisibility::Everyone => None,
+                DirectoryVisibility::GroupMembers => {
+                    let groups = self
+                        .handler
+                        .get_user_groups(&validation_result.user)
+                        .await?;
+                    Some(UserRequestFilter::Or(
+                        std::iter::once(UserRequestFilter::UserId(validation_result.user.clone()))
+                            .chain(
+                                groups
+                                    .into_iter()
+                                    .map(|g| UserRequestFilter::MemberOfId(g.group_id)),
+                            )
+                            .collect(),
+                    ))
+                }
+            }
+        };
+        Ok(Some(DirectoryListerBackendHandler {
+            handler: &self.handler,
+            user_filter,
+        }))
+    }
+
     pub async fn get_permissions_for_user(&self, user_id: UserId) -> Result<ValidationResults> {
         let user_groups = self.handler.get_user_groups(&user_id).await?;
         Ok(self.get_permissions_from_groups(user_id, user_groups.iter().map(|g| &g.display_name)))
@@ -304,6 +558,7 @@ impl<Handler: BackendHandler> AccessControlledBackendHandler<Handler> {
             } else {
                 Permission::Regular
             },
+            impersonation: None,
         }
     }
 }
@@ -339,17 +594,38 @@ impl<'a, Handler: UserListerBackendHandler + Sync> UserListerBackendHandler
         filters: Option<UserRequestFilter>,
         get_groups: bool,
     ) -> Result<Vec<UserAndGroups>> {
+        self.handler
+            .list_users(self.restrict_user_filters(filters), get_groups)
+            .await
+    }
+
+    async fn list_users_with_attributes(
+        &self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+        attributes: Option<Vec<AttributeName>>,
+    ) -> Result<Vec<UserAndGroups>> {
+        self.handler
+            .list_users_with_attributes(self.restrict_user_filters(filters), get_groups, attributes)
+            .await
+    }
+}
+
+impl<'a, Handler> UserRestrictedListerBackendHandler<'a, Handler> {
+    fn restrict_user_filters(
+        &self,
+        filters: Option<UserRequestFilter>,
+    ) -> Option<UserRequestFilter> {
         let user_filter = self
             .user_filter
             .as_ref()
             .map(|u| UserRequestFilter::UserId(u.clone()));
-        let filters = match (filters, user_filter) {
+        match (filters, user_filter) {
             (None, None) => None,
             (None, u) => u,
             (f, None) => f,
             (Some(f), Some(u)) => Some(UserRequestFilter::And(vec![f, u])),
-        };
-        self.handler.list_users(filters, get_groups).await
+        }
     }
 }
 
@@ -370,6 +646,57 @@ impl<'a, Handler: GroupListerBackendHandler + Sync> GroupListerBackendHandler
         };
         self.handler.list_groups(filters).await
     }
+
+    async fn get_group_hierarchy(&self) -> Result<GroupHierarchy> {
+        self.handler.get_group_hierarchy().await
+    }
+}
+
+/// Lists the users of the directory, restricted by the `directory_visibility` option for the
+/// regular users.
+pub struct DirectoryListerBackendHandler<'a, Handler> {
+    handler: &'a Handler,
+    user_filter: Option<UserRequestFilter>,
+}
+
+impl<'a, Handler> DirectoryListerBackendHandler<'a, Handler> {
+    fn restrict_user_filters(
+        &self,
+        filters: Option<UserRequestFilter>,
+    ) -> Option<UserRequestFilter> {
+        match (filters, self.user_filter.clone()) {
+            (None, None) => None,
+            (None, u) => u,
+            (f, None) => f,
+            (Some(f), Some(u)) => Some(UserRequestFilter::And(vec![f, u])),
+        }
+    }
+}
+
+#[async_trait]
+impl<'a, Handler: UserListerBackendHandler + Sync> UserListerBackendHandler
+    for DirectoryListerBackendHandler<'a, Handler>
+{
+    async fn list_users(
+        &self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+    ) -> Result<Vec<UserAndGroups>> {
+        self.handler
+            .list_users(self.restrict_user_filters(filters), get_groups)
+            .await
+    }
+
+    async fn list_users_with_attributes(
+        &self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+        attributes: Option<Vec<AttributeName>>,
+    ) -> Result<Vec<UserAndGroups>> {
+        self.handler
+            .list_users_with_attributes(self.restrict_user_filters(filters), get_groups, attributes)
+            .await
+    }
 }
 
 #[async_trait]
@@ -383,3 +710,28 @@ impl<'a, Handler: GroupListerBackendHandler + UserListerBackendHandler + Sync>
     UserAndGroupListerBackendHandler for UserRestrictedListerBackendHandler<'a, Handler>
 {
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_only_impersonation_cannot_write() {
+        let mut validation_result = ValidationResults::admin();
+        validation_result.impersonation = Some(Impersonation {
+            impersonator: UserId::new("root"),
+            read_only: true,
+        });
+        assert!(!validation_result.is_admin());
+        assert!(validation_result.can_read_all());
+        assert!(!validation_result.can_write(&UserId::new("admin")));
+        assert!(!validation_result.can_change_password(&UserId::new("admin"), true));
+
+        validation_result.impersonation = Some(Impersonation {
+            impersonator: UserId::new("root"),
+            read_only: false,
+        });
+        assert!(validation_result.is_admin());
+        assert!(validation_result.can_write(&UserId::new("bob")));
+    }
+}
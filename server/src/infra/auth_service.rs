@@ -1,16 +1,18 @@
 use actix_web::{
     cookie::{Cookie, SameSite},
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
-    error::{ErrorBadRequest, ErrorUnauthorized},
+    error::{ErrorBadRequest, ErrorForbidden, ErrorUnauthorized},
     web, HttpRequest, HttpResponse,
 };
 use actix_web_httpauth::extractors::bearer::BearerAuth;
 use anyhow::Result;
+use base64::Engine;
 use chrono::prelude::*;
 use futures::future::{ok, Ready};
 use futures_util::FutureExt;
 use hmac::Hmac;
 use jwt::{SignWithKey, VerifyWithKey};
+use serde::Deserialize;
 use sha2::Sha512;
 use std::{
     collections::HashSet,
@@ -19,19 +21,30 @@ use std::{
     task::{Context, Poll},
 };
 use time::ext::NumericalDuration;
-use tracing::{debug, info, instrument, warn};
+use tracing::{debug, error, info, instrument, warn};
 
-use lldap_auth::{login, password_reset, registration, JWTClaims};
+use lldap_auth::{
+    access_review, account_deletion, branding, captcha,
+    csrf::{CSRF_COOKIE, CSRF_HEADER},
+    login, password_reset, registration, ImpersonationClaims, JWTClaims,
+};
 
 use crate::{
     domain::{
+        access_reviews::AccessReview,
         error::DomainError,
-        handler::{BackendHandler, BindRequest, LoginHandler, UserRequestFilter},
+        handler::{
+            AccessReviewBackendHandler, AccountDeletionBackendHandler, BackendHandler, BindRequest,
+            LoginHandler, UserRequestFilter,
+        },
+        locale::get_preferred_language,
         opaque_handler::OpaqueHandler,
         types::{GroupDetails, GroupName, UserColumn, UserId},
     },
     infra::{
-        access_control::{ReadonlyBackendHandler, UserReadableBackendHandler, ValidationResults},
+        access_control::{
+            Impersonation, ReadonlyBackendHandler, UserReadableBackendHandler, ValidationResults,
+        },
         tcp_backend_handler::*,
         tcp_server::{error_to_http_response, AppState, TcpError, TcpResult},
     },
@@ -40,6 +53,10 @@ use crate::{
 type Token<S> = jwt::Token<jwt::Header, JWTClaims, S>;
 type SignedToken = Token<jwt::token::Signed>;
 
+const IMPERSONATION_LIFETIME: chrono::Duration = chrono::Duration::hours(1);
+/// Members of this group can impersonate users with full (write) access.
+const IMPERSONATOR_GROUP: &str = "lldap_impersonator";
+
 fn default_hash<T: Hash + ?Sized>(token: &T) -> u64 {
     use std::collections::hash_map::DefaultHasher;
     use std::hash::Hasher;
@@ -48,20 +65,50 @@ fn default_hash<T: Hash + ?Sized>(token: &T) -> u64 {
     s.finish()
 }
 
+/// The CSRF token of a session, derived from its JWT: a cross-site page can neither read nor forge
+/// it, and checking it needs no state.
+fn csrf_token(jwt: &str) -> String {
+    use sha2::{Digest, Sha256};
+    let hash = Sha256::new()
+        .chain_update("lldap_csrf:")
+        .chain_update(jwt)
+        .finalize();
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(hash)
+}
+
+/// The cookie giving the CSRF token to the web UI. Unlike the JWT, it is readable by the scripts of
+/// every page.
+fn csrf_cookie(jwt: &str, path: &str, max_age: time::Duration) -> Cookie<'static> {
+    Cookie::build(CSRF_COOKIE, csrf_token(jwt))
+        .max_age(max_age)
+        .path(path.to_owned())
+        .http_only(false)
+        .same_site(SameSite::Strict)
+        .finish()
+}
+
+/// The max age of a cookie holding a token with the given lifetime.
+fn cookie_max_age(lifetime: chrono::Duration) -> time::Duration {
+    time::Duration::seconds(lifetime.num_seconds())
+}
+
 async fn create_jwt<Handler: TcpBackendHandler>(
     handler: &Handler,
     key: &Hmac<Sha512>,
     user: &UserId,
     groups: HashSet<GroupDetails>,
+    impersonation: Option<ImpersonationClaims>,
+    lifetime: chrono::Duration,
 ) -> SignedToken {
     let claims = JWTClaims {
-        exp: Utc::now() + chrono::Duration::days(1),
+        exp: Utc::now() + lifetime,
         iat: Utc::now(),
         user: user.to_string(),
         groups: groups
             .into_iter()
             .map(|g| g.display_name.into_string())
             .collect(),
+        impersonation,
     };
     let expiry = claims.exp.naive_utc();
     let header = jwt::Header {
@@ -76,14 +123,32 @@ async fn create_jwt<Handler: TcpBackendHandler>(
     token
 }
 
-fn parse_refresh_token(token: &str) -> TcpResult<(u64, UserId)> {
+/// Refuses the logins of the non-admin users during maintenance.
+fn check_maintenance<Backend>(
+    data: &AppState<Backend>,
+    user: &UserId,
+    groups: &HashSet<GroupDetails>,
+) -> TcpResult<()> {
+    let is_admin = groups
+        .iter()
+        .any(|g| g.display_name == "lldap_admin".into());
+    match data.maintenance.refusal(is_admin) {
+        Some(message) => {
+            info!(r#"Refusing the login of "{}" during maintenance"#, user);
+            Err(TcpError::ServiceUnavailable(message))
+        }
+        None => Ok(()),
+    }
+}
+
+fn parse_refresh_token(token: &str) -> TcpResult<(String, UserId)> {
     match token.split_once('+') {
         None => Err(DomainError::AuthenticationError("Invalid refresh token".to_string()).into()),
-        Some((token, u)) => Ok((default_hash(token), UserId::new(u))),
+        Some((token, u)) => Ok((token.to_owned(), UserId::new(u))),
     }
 }
 
-fn get_refresh_token(request: HttpRequest) -> TcpResult<(u64, UserId)> {
+fn get_refresh_token(request: HttpRequest) -> TcpResult<(String, UserId)> {
     match (
         request.cookie("refresh_token"),
         request.headers().get("refresh-token"),
@@ -105,31 +170,46 @@ where
     Backend: TcpBackendHandler + BackendHandler + 'static,
 {
     let jwt_key = &data.jwt_key;
-    let (refresh_token_hash, user) = get_refresh_token(request)?;
-    let found = data
+    let (refresh_token, user) = get_refresh_token(request)?;
+    let session_expiry = data
         .get_tcp_handler()
-        .check_token(refresh_token_hash, &user)
-        .await?;
-    if !found {
-        return Err(TcpError::DomainError(DomainError::AuthenticationError(
-            "Invalid refresh token".to_string(),
-        )));
-    }
+        .check_token(&refresh_token, &user)
+        .await?
+        .ok_or_else(|| {
+            TcpError::DomainError(DomainError::AuthenticationError(
+                "Invalid refresh token".to_string(),
+            ))
+        })?;
     let mut path = data.server_url.path().to_string();
     if !path.ends_with('/') {
         path.push('/');
     };
     let groups = data.get_readonly_handler().get_user_groups(&user).await?;
-    let token = create_jwt(data.get_tcp_handler(), jwt_key, &user, groups).await;
+    check_maintenance(&data, &user, &groups)?;
+    // The JWT never outlives the session, so that refreshing doesn't extend it.
+    let lifetime = std::cmp::min(
+        data.session_options.access_token_lifetime(),
+        session_expiry - Utc::now().naive_utc(),
+    );
+    let token = create_jwt(
+        data.get_tcp_handler(),
+        jwt_key,
+        &user,
+        groups,
+        None,
+        lifetime,
+    )
+    .await;
     Ok(HttpResponse::Ok()
         .cookie(
             Cookie::build("token", token.as_str())
-                .max_age(1.days())
+                .max_age(cookie_max_age(lifetime))
                 .path(&path)
                 .http_only(true)
                 .same_site(SameSite::Strict)
                 .finish(),
         )
+        .cookie(csrf_cookie(token.as_str(), &path, cookie_max_age(lifetime)))
         .json(&login::ServerLoginResponse {
             token: token.as_str().to_owned(),
             refresh_token: None,
@@ -149,6 +229,49 @@ where
 }
 
 #[instrument(skip_all, level = "debug")]
+async fn get_captcha<Backend>(data: web::Data<AppState<Backend>>) -> HttpResponse
+where
+    Backend: BackendHandler + 'static,
+{
+    HttpResponse::Ok().json(&captcha::ServerChallengeResponse {
+        challenge: data
+            .captcha
+            .as_ref()
+            .map_or(captcha::Challenge::None, |c| c.new_challenge()),
+        required_for_login: data.captcha.is_some() && data.captcha_required_for_login,
+    })
+}
+
+/// Refuses the request if a challenge is configured and the request doesn't carry a valid
+/// solution.
+async fn check_captcha<Backend>(data: &AppState<Backend>, request: &HttpRequest) -> TcpResult<()> {
+    let provider = match &data.captcha {
+        None => return Ok(()),
+        Some(provider) => provider,
+    };
+    let solution = request
+        .headers()
+        .get(captcha::CAPTCHA_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| TcpError::BadRequest("Missing CAPTCHA response".to_owned()))?;
+    let remote_ip = request
+        .connection_info()
+        .realip_remote_addr()
+        .and_then(parse_ip)
+        .map(|ip| ip.to_string());
+    match provider.verify(solution, remote_ip.as_deref()).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(TcpError::BadRequest("Invalid CAPTCHA response".to_owned())),
+        Err(e) => {
+            error!("Could not verify the CAPTCHA response: {:#}", e);
+            Err(TcpError::InternalServerError(
+                "Could not verify the CAPTCHA response".to_owned(),
+            ))
+        }
+    }
+}
+
 async fn get_password_reset_step1<Backend>(
     data: web::Data<AppState<Backend>>,
     request: HttpRequest,
@@ -156,6 +279,8 @@ async fn get_password_reset_step1<Backend>(
 where
     Backend: TcpBackendHandler + BackendHandler + 'static,
 {
+    data.check_read_only()?;
+    check_captcha(&data, &request).await?;
     let user_string = request
         .match_info()
         .get("user_id")
@@ -186,21 +311,15 @@ where
         None => return Ok(()),
         Some(token) => token,
     };
-    if let Err(e) = super::mail::send_password_reset_email(
-        user.display_name
-            .as_deref()
-            .unwrap_or_else(|| user.user_id.as_str()),
-        user.email.as_str(),
-        &token,
-        &data.server_url,
-        &data.mail_options,
-    )
-    .await
-    {
-        warn!("Error sending email: {:#?}", e);
+    let delivery = data
+        .password_reset_delivery
+        .as_ref()
+        .ok_or_else(|| TcpError::InternalServerError("Password reset is disabled".to_owned()))?;
+    if let Err(e) = delivery.send_reset_link(user, &token).await {
+        warn!("Error sending the reset link: {:#?}", e);
         info!("Reset token: {}", token);
         return Err(TcpError::InternalServerError(format!(
-            "Could not send email: {}",
+            "Could not send the reset link: {}",
             e
         )));
     }
@@ -245,7 +364,15 @@ where
         .delete_password_reset_token(token)
         .await;
     let groups = HashSet::new();
-    let token = create_jwt(data.get_tcp_handler(), &data.jwt_key, &user_id, groups).await;
+    let token = create_jwt(
+        data.get_tcp_handler(),
+        &data.jwt_key,
+        &user_id,
+        groups,
+        None,
+        data.session_options.access_token_lifetime(),
+    )
+    .await;
     let mut path = data.server_url.path().to_string();
     if !path.ends_with('/') {
         path.push('/');
@@ -260,6 +387,7 @@ where
                 .same_site(SameSite::Strict)
                 .finish(),
         )
+        .cookie(csrf_cookie(token.as_str(), &path, 5.minutes()))
         .json(&password_reset::ServerPasswordResetResponse {
             user_id: user_id.to_string(),
             token: token.as_str().to_owned(),
@@ -286,9 +414,9 @@ async fn get_logout<Backend>(
 where
     Backend: TcpBackendHandler + BackendHandler + 'static,
 {
-    let (refresh_token_hash, user) = get_refresh_token(request)?;
+    let (refresh_token, user) = get_refresh_token(request)?;
     data.get_tcp_handler()
-        .delete_refresh_token(refresh_token_hash)
+        .delete_refresh_token(&refresh_token)
         .await?;
     let new_blacklisted_jwt_hashes = data.get_tcp_handler().blacklist_jwts(&user).await?;
     let mut jwt_blacklist = data.jwt_blacklist.write().unwrap();
@@ -308,6 +436,13 @@ where
                 .same_site(SameSite::Strict)
                 .finish(),
         )
+        .cookie(
+            Cookie::build(CSRF_COOKIE, "")
+                .max_age(0.days())
+                .path(&path)
+                .same_site(SameSite::Strict)
+                .finish(),
+        )
         .cookie(
             Cookie::build("refresh_token", "")
                 .max_age(0.days())
@@ -337,25 +472,159 @@ pub(crate) fn error_to_api_response<T, E: Into<TcpError>>(error: E) -> ApiResult
 
 pub type ApiResult<M> = actix_web::Either<web::Json<M>, HttpResponse>;
 
+fn user_throttling_key(user_id: &UserId) -> String {
+    format!("user:{}", user_id)
+}
+
+/// The keys under which the failed logins are counted: the user, if known, and the client
+/// address.
+fn get_throttling_keys(http_request: &HttpRequest, user_id: Option<&UserId>) -> Vec<String> {
+    let connection_info = http_request.connection_info();
+    let address = connection_info.realip_remote_addr().map(|address| {
+        let ip = parse_ip(address).map_or_else(|| address.to_owned(), |ip| ip.to_string());
+        format!("ip:{}", ip)
+    });
+    user_id
+        .map(user_throttling_key)
+        .into_iter()
+        .chain(address)
+        .collect()
+}
+
+/// Refuses the login if the user or the client address is locked out.
+async fn check_login_throttling<Backend>(data: &AppState<Backend>, keys: &[String]) -> TcpResult<()>
+where
+    Backend: TcpBackendHandler,
+{
+    if !data.login_throttling_options.enabled {
+        return Ok(());
+    }
+    for key in keys {
+        if let Some(locked_until) = data.get_tcp_handler().get_login_lockout(key).await? {
+            warn!(
+                r#"Refusing the login for "{}", locked out until {}"#,
+                key, locked_until
+            );
+            return Err(TcpError::TooManyRequests(format!(
+                "Too many failed logins, try again after {} UTC",
+                locked_until.format("%Y-%m-%d %H:%M:%S")
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Counts a failed login. Errors are only logged, the login failed anyway.
+async fn record_login_failure<Backend>(data: &AppState<Backend>, keys: &[String])
+where
+    Backend: TcpBackendHandler,
+{
+    let options = &data.login_throttling_options;
+    if !options.enabled {
+        return;
+    }
+    for key in keys {
+        match data
+            .get_tcp_handler()
+            .record_login_failure(key, options)
+            .await
+        {
+            Ok(Some(locked_until)) => warn!(
+                r#"Too many failed logins for "{}", locked out until {}"#,
+                key, locked_until
+            ),
+            Ok(None) => (),
+            Err(e) => error!("Could not record the failed login: {}", e),
+        }
+    }
+}
+
+async fn clear_login_failures<Backend>(data: &AppState<Backend>, user_id: &UserId)
+where
+    Backend: TcpBackendHandler,
+{
+    if !data.login_throttling_options.enabled {
+        return;
+    }
+    if let Err(e) = data
+        .get_tcp_handler()
+        .clear_login_failures(&user_throttling_key(user_id))
+        .await
+    {
+        error!("Could not reset the failed logins: {}", e);
+    }
+}
+
 #[instrument(skip_all, level = "debug")]
 async fn opaque_login_start<Backend>(
     data: web::Data<AppState<Backend>>,
+    http_request: HttpRequest,
     request: web::Json<login::ClientLoginStartRequest>,
 ) -> ApiResult<login::ServerLoginStartResponse>
 where
-    Backend: OpaqueHandler + 'static,
+    Backend: TcpBackendHandler + OpaqueHandler + 'static,
 {
+    let request = request.into_inner();
+    let keys = get_throttling_keys(&http_request, Some(&request.username));
+    if let Err(e) = check_login_throttling(&data, &keys).await {
+        return error_to_api_response(e);
+    }
+    // Changing the password starts with a login, which doesn't need a challenge for the user
+    // who is already logged in.
+    let logged_in = http_request
+        .cookie("token")
+        .and_then(|token| check_if_token_is_valid(&data, token.value()).ok())
+        .map_or(false, |validation| validation.user == request.username);
+    if data.captcha_required_for_login && !logged_in {
+        if let Err(e) = check_captcha(&data, &http_request).await {
+            return error_to_api_response(e);
+        }
+    }
+    // A wrong password is only detected when finishing the login, which doesn't know the user:
+    // the attempt counts against the user until the login succeeds.
+    record_login_failure(&data, &[user_throttling_key(&request.username)]).await;
     data.get_opaque_handler()
-        .login_start(request.into_inner())
+        .login_start(request)
         .await
         .map(|res| ApiResult::Left(web::Json(res)))
         .unwrap_or_else(error_to_api_response)
 }
 
+// The address may come with a port, or be an IPv6 in brackets.
+fn parse_ip(address: &str) -> Option<std::net::IpAddr> {
+    address
+        .parse::<std::net::SocketAddr>()
+        .map(|a| a.ip())
+        .or_else(|_| address.parse::<std::net::IpAddr>())
+        .ok()
+}
+
+fn log_login<Backend>(data: &AppState<Backend>, http_request: &HttpRequest, name: &UserId) {
+    let connection_info = http_request.connection_info();
+    let address = match connection_info.realip_remote_addr() {
+        Some(address) => address,
+        None => {
+            info!(r#"Successful login for "{}""#, name);
+            return;
+        }
+    };
+    match (&data.geoip, parse_ip(address)) {
+        (Some(geoip), Some(ip)) => info!(
+            r#"Successful login for "{}" from {} ({})"#,
+            name,
+            address,
+            geoip.lookup(ip)
+        ),
+        _ => info!(r#"Successful login for "{}" from {}"#, name, address),
+    }
+}
+
 #[instrument(skip_all, level = "debug")]
 async fn get_login_successful_response<Backend>(
     data: &web::Data<AppState<Backend>>,
+    http_request: &HttpRequest,
     name: &UserId,
+    remember_me: bool,
 ) -> TcpResult<HttpResponse>
 where
     Backend: TcpBackendHandler + BackendHandler,
@@ -363,8 +632,26 @@ where
     // The authentication was successful, we need to fetch the groups to create the JWT
     // token.
     let groups = data.get_readonly_handler().get_user_groups(name).await?;
-    let (refresh_token, max_age) = data.get_tcp_handler().create_refresh_token(name).await?;
-    let token = create_jwt(data.get_tcp_handler(), &data.jwt_key, name, groups).await;
+    check_maintenance(data, name, &groups)?;
+    log_login(data, http_request, name);
+    let session_lifetime = data.session_options.refresh_token_lifetime(remember_me);
+    let refresh_token = data
+        .get_tcp_handler()
+        .create_refresh_token(name, session_lifetime)
+        .await?;
+    let lifetime = std::cmp::min(
+        data.session_options.access_token_lifetime(),
+        session_lifetime,
+    );
+    let token = create_jwt(
+        data.get_tcp_handler(),
+        &data.jwt_key,
+        name,
+        groups,
+        None,
+        lifetime,
+    )
+    .await;
     let refresh_token_plus_name = refresh_token + "+" + name.as_str();
     let mut path = data.server_url.path().to_string();
     if !path.ends_with('/') {
@@ -373,15 +660,16 @@ where
     Ok(HttpResponse::Ok()
         .cookie(
             Cookie::build("token", token.as_str())
-                .max_age(1.days())
+                .max_age(cookie_max_age(lifetime))
                 .path(&path)
                 .http_only(true)
                 .same_site(SameSite::Strict)
                 .finish(),
         )
+        .cookie(csrf_cookie(token.as_str(), &path, cookie_max_age(lifetime)))
         .cookie(
             Cookie::build("refresh_token", refresh_token_plus_name.clone())
-                .max_age(max_age.num_days().days())
+                .max_age(cookie_max_age(session_lifetime))
                 .path(format!("{}auth", path))
                 .http_only(true)
                 .same_site(SameSite::Strict)
@@ -396,29 +684,35 @@ where
 #[instrument(skip_all, level = "debug")]
 async fn opaque_login_finish<Backend>(
     data: web::Data<AppState<Backend>>,
+    http_request: HttpRequest,
     request: web::Json<login::ClientLoginFinishRequest>,
 ) -> TcpResult<HttpResponse>
 where
     Backend: TcpBackendHandler + BackendHandler + OpaqueHandler + 'static,
 {
-    match data
-        .get_opaque_handler()
-        .login_finish(request.into_inner())
-        .await
-    {
-        Ok(name) => get_login_successful_response(&data, &name).await,
-        Err(e) => Err(e.into()),
+    let request = request.into_inner();
+    let remember_me = request.remember_me;
+    match data.get_opaque_handler().login_finish(request).await {
+        Ok(name) => {
+            clear_login_failures(&data, &name).await;
+            get_login_successful_response(&data, &http_request, &name, remember_me).await
+        }
+        Err(e) => {
+            record_login_failure(&data, &get_throttling_keys(&http_request, None)).await;
+            Err(e.into())
+        }
     }
 }
 
 async fn opaque_login_finish_handler<Backend>(
     data: web::Data<AppState<Backend>>,
+    http_request: HttpRequest,
     request: web::Json<login::ClientLoginFinishRequest>,
 ) -> HttpResponse
 where
     Backend: TcpBackendHandler + BackendHandler + OpaqueHandler + 'static,
 {
-    opaque_login_finish(data, request)
+    opaque_login_finish(data, http_request, request)
         .await
         .unwrap_or_else(error_to_http_response)
 }
@@ -426,28 +720,43 @@ where
 #[instrument(skip_all, level = "debug")]
 async fn simple_login<Backend>(
     data: web::Data<AppState<Backend>>,
+    http_request: HttpRequest,
     request: web::Json<login::ClientSimpleLoginRequest>,
 ) -> TcpResult<HttpResponse>
 where
     Backend: TcpBackendHandler + BackendHandler + OpaqueHandler + LoginHandler + 'static,
 {
-    let login::ClientSimpleLoginRequest { username, password } = request.into_inner();
+    let login::ClientSimpleLoginRequest {
+        username,
+        password,
+        remember_me,
+    } = request.into_inner();
+    let keys = get_throttling_keys(&http_request, Some(&username));
+    check_login_throttling(&data, &keys).await?;
+    if data.captcha_required_for_login {
+        check_captcha(&data, &http_request).await?;
+    }
     let bind_request = BindRequest {
         name: username.clone(),
         password,
     };
-    data.get_login_handler().bind(bind_request).await?;
-    get_login_successful_response(&data, &username).await
+    if let Err(e) = data.get_login_handler().bind(bind_request).await {
+        record_login_failure(&data, &keys).await;
+        return Err(e.into());
+    }
+    clear_login_failures(&data, &username).await;
+    get_login_successful_response(&data, &http_request, &username, remember_me).await
 }
 
 async fn simple_login_handler<Backend>(
     data: web::Data<AppState<Backend>>,
+    http_request: HttpRequest,
     request: web::Json<login::ClientSimpleLoginRequest>,
 ) -> HttpResponse
 where
     Backend: TcpBackendHandler + BackendHandler + OpaqueHandler + LoginHandler + 'static,
 {
-    simple_login(data, request)
+    simple_login(data, http_request, request)
         .await
         .unwrap_or_else(error_to_http_response)
 }
@@ -462,6 +771,7 @@ where
     Backend: BackendHandler + OpaqueHandler + 'static,
 {
     use actix_web::FromRequest;
+    data.check_read_only()?;
     let inner_payload = &mut payload.into_inner();
     let validation_result = BearerAuth::from_request(&request, inner_payload)
         .await
@@ -536,7 +846,379 @@ where
         .unwrap_or_else(error_to_http_response)
 }
 
-pub struct CookieToHeaderTranslatorFactory;
+#[derive(Deserialize)]
+struct ImpersonateQuery {
+    #[serde(default)]
+    full: bool,
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn impersonate<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    query: web::Query<ImpersonateQuery>,
+    bearer: BearerAuth,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let validation_result = check_if_token_is_valid(&data, bearer.token())
+        .map_err(|e| TcpError::UnauthorizedError(e.to_string()))?;
+    if !validation_result.is_admin() || validation_result.impersonation.is_some() {
+        return Err(TcpError::UnauthorizedError(
+            "Only admins can impersonate users".to_owned(),
+        ));
+    }
+    let admin = validation_result.user;
+    let read_only = !query.full;
+    if !read_only
+        && !data
+            .get_readonly_handler()
+            .get_user_groups(&admin)
+            .await?
+            .iter()
+            .any(|g| g.display_name == IMPERSONATOR_GROUP.into())
+    {
+        return Err(TcpError::UnauthorizedError(format!(
+            "Full impersonation requires being a member of {}",
+            IMPERSONATOR_GROUP
+        )));
+    }
+    let user_id = UserId::new(
+        request
+            .match_info()
+            .get("user_id")
+            .ok_or_else(|| TcpError::BadRequest("Missing user ID".to_string()))?,
+    );
+    let groups = data
+        .get_readonly_handler()
+        .get_user_groups(&user_id)
+        .await?;
+    info!(
+        r#"Admin "{}" started impersonating "{}" (read-only: {})"#,
+        &admin, &user_id, read_only
+    );
+    let token = create_jwt(
+        data.get_tcp_handler(),
+        &data.jwt_key,
+        &user_id,
+        groups,
+        Some(ImpersonationClaims {
+            impersonator: admin.to_string(),
+            read_only,
+        }),
+        IMPERSONATION_LIFETIME,
+    )
+    .await;
+    let mut path = data.server_url.path().to_string();
+    if !path.ends_with('/') {
+        path.push('/');
+    };
+    // The refresh token cookie is left untouched: it still belongs to the admin, and refreshing
+    // ends the impersonation.
+    Ok(HttpResponse::Ok()
+        .cookie(
+            Cookie::build("token", token.as_str())
+                .max_age(1.hours())
+                .path(&path)
+                .http_only(true)
+                .same_site(SameSite::Strict)
+                .finish(),
+        )
+        .cookie(csrf_cookie(token.as_str(), &path, 1.hours()))
+        .json(&login::ServerLoginResponse {
+            token: token.as_str().to_owned(),
+            refresh_token: None,
+        }))
+}
+
+async fn impersonate_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    query: web::Query<ImpersonateQuery>,
+    bearer: BearerAuth,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    impersonate(data, request, query, bearer)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+async fn get_account_deletion_policy<Backend>(data: web::Data<AppState<Backend>>) -> HttpResponse
+where
+    Backend: BackendHandler + 'static,
+{
+    HttpResponse::Ok().json(&account_deletion::DeletionPolicy {
+        enabled: data.account_deletion_options.enable_self_service,
+        grace_period_days: data.account_deletion_options.grace_period_days,
+    })
+}
+
+async fn get_branding<Backend>(data: web::Data<AppState<Backend>>) -> HttpResponse
+where
+    Backend: BackendHandler + 'static,
+{
+    let options = &data.branding_options;
+    HttpResponse::Ok().json(&branding::LoginPage {
+        links: options
+            .login_links
+            .iter()
+            .map(|link| branding::Link {
+                label: link.label.clone(),
+                url: link.url.to_string(),
+            })
+            .collect(),
+        help_text: options.help_text.clone(),
+    })
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn request_account_deletion<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+) -> TcpResult<()>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    data.check_read_only()?;
+    let validation_result = check_if_token_is_valid(&data, bearer.token())
+        .map_err(|e| TcpError::UnauthorizedError(e.to_string()))?;
+    // Someone impersonating the user can't delete their account.
+    if validation_result.impersonation.is_some() {
+        return Err(TcpError::UnauthorizedError(
+            "Cannot delete an impersonated account".to_owned(),
+        ));
+    }
+    let user_id = validation_result.user;
+    let user = data
+        .get_readonly_handler()
+        .get_user_details(&user_id)
+        .await?;
+    let token = data
+        .get_account_deletion_handler()
+        .request_account_deletion(&user_id)
+        .await?;
+    if let Err(e) = super::mail::send_account_deletion_email(
+        user.display_name
+            .as_deref()
+            .unwrap_or_else(|| user.user_id.as_str()),
+        user.email.as_str(),
+        get_preferred_language(&user).as_deref(),
+        &token,
+        &data.server_url,
+        &data.mail_options,
+    )
+    .await
+    {
+        warn!("Error sending email: {:#?}", e);
+        return Err(TcpError::InternalServerError(format!(
+            "Could not send email: {}",
+            e
+        )));
+    }
+    info!(
+        r#"User "{}" requested the deletion of their account"#,
+        &user_id
+    );
+    Ok(())
+}
+
+async fn request_account_deletion_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    request_account_deletion(data, bearer)
+        .await
+        .map(|()| HttpResponse::Ok().finish())
+        .unwrap_or_else(error_to_http_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn confirm_account_deletion<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    data.check_read_only()?;
+    let token = request
+        .match_info()
+        .get("token")
+        .ok_or_else(|| TcpError::BadRequest("Missing deletion token".to_owned()))?;
+    let deletion = data
+        .get_account_deletion_handler()
+        .confirm_account_deletion(
+            token,
+            chrono::Duration::days(data.account_deletion_options.grace_period_days.into()),
+        )
+        .await
+        .map_err(|e| {
+            debug!("Deletion token error: {e:#}");
+            TcpError::NotFoundError("Wrong or expired deletion token".to_owned())
+        })?;
+    // Log the user out everywhere.
+    let new_blacklisted_jwt_hashes = data
+        .get_tcp_handler()
+        .blacklist_jwts(&deletion.user_id)
+        .await?;
+    {
+        let mut jwt_blacklist = data.jwt_blacklist.write().unwrap();
+        for jwt_hash in new_blacklisted_jwt_hashes {
+            jwt_blacklist.insert(jwt_hash);
+        }
+    }
+    let mut path = data.server_url.path().to_string();
+    if !path.ends_with('/') {
+        path.push('/');
+    };
+    Ok(HttpResponse::Ok()
+        .cookie(
+            Cookie::build("token", "")
+                .max_age(0.days())
+                .path(&path)
+                .http_only(true)
+                .same_site(SameSite::Strict)
+                .finish(),
+        )
+        .cookie(
+            Cookie::build(CSRF_COOKIE, "")
+                .max_age(0.days())
+                .path(&path)
+                .same_site(SameSite::Strict)
+                .finish(),
+        )
+        .cookie(
+            Cookie::build("refresh_token", "")
+                .max_age(0.days())
+                .path(format!("{}auth", path))
+                .http_only(true)
+                .same_site(SameSite::Strict)
+                .finish(),
+        )
+        .json(&account_deletion::ServerDeletionConfirmation {
+            user_id: deletion.user_id.to_string(),
+            // Always set once confirmed.
+            deletion_date: deletion.deletion_date.unwrap_or_default(),
+        }))
+}
+
+async fn confirm_account_deletion_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    confirm_account_deletion(data, request)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+fn access_review_page(review: AccessReview) -> access_review::ReviewPage {
+    access_review::ReviewPage {
+        campaign: review.campaign_name,
+        group: review.group_name.to_string(),
+        reviewer: review.reviewer.to_string(),
+        members: review
+            .decisions
+            .into_iter()
+            .map(|d| access_review::ReviewedMember {
+                user_id: d.user_id.to_string(),
+                keep: d.keep,
+            })
+            .collect(),
+    }
+}
+
+fn get_access_review_token(request: &HttpRequest) -> TcpResult<&str> {
+    request
+        .match_info()
+        .get("token")
+        .ok_or_else(|| TcpError::BadRequest("Missing access review token".to_owned()))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn get_access_review<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+) -> TcpResult<access_review::ReviewPage>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let review = data
+        .get_access_review_handler()
+        .get_access_review(get_access_review_token(&request)?)
+        .await
+        .map_err(|e| {
+            debug!("Access review token error: {e:#}");
+            TcpError::NotFoundError("Wrong access review token".to_owned())
+        })?;
+    Ok(access_review_page(review))
+}
+
+async fn get_access_review_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    get_access_review(data, request)
+        .await
+        .map(|page| HttpResponse::Ok().json(page))
+        .unwrap_or_else(error_to_http_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn decide_access_review<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    decision: web::Json<access_review::ReviewDecision>,
+) -> TcpResult<access_review::ReviewPage>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    data.check_read_only()?;
+    let token = get_access_review_token(&request)?;
+    let handler = data.get_access_review_handler();
+    handler
+        .decide_access_review(token, &UserId::new(&decision.user_id), decision.keep)
+        .await?;
+    Ok(access_review_page(handler.get_access_review(token).await?))
+}
+
+async fn decide_access_review_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    decision: web::Json<access_review::ReviewDecision>,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    decide_access_review(data, request, decision)
+        .await
+        .map(|page| HttpResponse::Ok().json(page))
+        .unwrap_or_else(error_to_http_response)
+}
+
+/// Marks the requests authenticated with the session cookie rather than an `Authorization` header.
+#[derive(Clone, Copy, Debug)]
+pub struct CookieSession {
+    /// Whether the request carried the CSRF token of the session, or doesn't need to.
+    pub csrf_verified: bool,
+}
+
+/// Authenticates the requests with the session cookie. With `csrf_protection`, the requests that
+/// are not read-only must also carry the CSRF token.
+pub struct CookieToHeaderTranslatorFactory {
+    pub csrf_protection: bool,
+}
 
 impl<S> Transform<S, ServiceRequest> for CookieToHeaderTranslatorFactory
 where
@@ -550,12 +1232,16 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ok(CookieToHeaderTranslator { service })
+        ok(CookieToHeaderTranslator {
+            service,
+            csrf_protection: self.csrf_protection,
+        })
     }
 }
 
 pub struct CookieToHeaderTranslator<S> {
     service: S,
+    csrf_protection: bool,
 }
 
 impl<S> Service<ServiceRequest> for CookieToHeaderTranslator<S>
@@ -574,6 +1260,21 @@ where
 
     fn call(&self, mut req: ServiceRequest) -> Self::Future {
         if let Some(token_cookie) = req.cookie("token") {
+            let has_csrf_token = req
+                .headers()
+                .get(CSRF_HEADER)
+                .and_then(|h| h.to_str().ok())
+                .map(|h| h == csrf_token(token_cookie.value()))
+                .unwrap_or(false);
+            if self.csrf_protection && !has_csrf_token && !req.method().is_safe() {
+                return async move {
+                    Ok(req.error_response(ErrorForbidden("Missing or invalid CSRF token")))
+                }
+                .boxed_local();
+            }
+            req.extensions_mut().insert(CookieSession {
+                csrf_verified: has_csrf_token || !self.csrf_protection,
+            });
             if let Ok(header_value) = actix_http::header::HeaderValue::from_str(&format!(
                 "Bearer {}",
                 token_cookie.value()
@@ -612,18 +1313,33 @@ pub(crate) fn check_if_token_is_valid<Backend: BackendHandler>(
     if state.jwt_blacklist.read().unwrap().contains(&jwt_hash) {
         return Err(ErrorUnauthorized("JWT was logged out"));
     }
-    Ok(state.backend_handler.get_permissions_from_groups(
+    let mut validation_result = state.backend_handler.get_permissions_from_groups(
         UserId::new(&token.claims().user),
         token
             .claims()
             .groups
             .iter()
             .map(|s| GroupName::from(s.as_str())),
-    ))
+    );
+    if let Some(impersonation) = &token.claims().impersonation {
+        info!(
+            r#"Request by "{}" impersonating "{}" (read-only: {})"#,
+            &impersonation.impersonator, &validation_result.user, impersonation.read_only
+        );
+        validation_result.impersonation = Some(Impersonation {
+            impersonator: UserId::new(&impersonation.impersonator),
+            read_only: impersonation.read_only,
+        });
+    }
+    Ok(validation_result)
 }
 
-pub fn configure_server<Backend>(cfg: &mut web::ServiceConfig, enable_password_reset: bool)
-where
+pub fn configure_server<Backend>(
+    cfg: &mut web::ServiceConfig,
+    enable_password_reset: bool,
+    enable_account_deletion: bool,
+    csrf_protection: bool,
+) where
     Backend: TcpBackendHandler + LoginHandler + OpaqueHandler + BackendHandler + 'static,
 {
     cfg.service(
@@ -635,10 +1351,17 @@ where
     )
     .service(web::resource("/simple/login").route(web::post().to(simple_login_handler::<Backend>)))
     .service(web::resource("/refresh").route(web::get().to(get_refresh_handler::<Backend>)))
+    .service(web::resource("/captcha").route(web::get().to(get_captcha::<Backend>)))
+    .service(web::resource("/branding").route(web::get().to(get_branding::<Backend>)))
     .service(web::resource("/logout").route(web::get().to(get_logout_handler::<Backend>)))
+    .service(
+        web::resource("/impersonate/{user_id}")
+            .wrap(CookieToHeaderTranslatorFactory { csrf_protection })
+            .route(web::post().to(impersonate_handler::<Backend>)),
+    )
     .service(
         web::scope("/opaque/register")
-            .wrap(CookieToHeaderTranslatorFactory)
+            .wrap(CookieToHeaderTranslatorFactory { csrf_protection })
             .service(
                 web::resource("/start")
                     .route(web::post().to(opaque_register_start_handler::<Backend>)),
@@ -662,4 +1385,28 @@ where
             web::resource("/reset/step1/{user_id}").route(web::post().to(HttpResponse::NotFound)),
         );
     }
+    cfg.service(
+        web::resource("/deletion/policy")
+            .route(web::get().to(get_account_deletion_policy::<Backend>)),
+    );
+    if enable_account_deletion {
+        cfg.service(
+            web::resource("/deletion/request")
+                .wrap(CookieToHeaderTranslatorFactory { csrf_protection })
+                .route(web::post().to(request_account_deletion_handler::<Backend>)),
+        )
+        .service(
+            web::resource("/deletion/confirm/{token}")
+                .route(web::post().to(confirm_account_deletion_handler::<Backend>)),
+        );
+    } else {
+        cfg.service(
+            web::resource("/deletion/request").route(web::post().to(HttpResponse::NotFound)),
+        );
+    }
+    cfg.service(
+        web::resource("/access-review/{token}")
+            .route(web::get().to(get_access_review_handler::<Backend>))
+            .route(web::post().to(decide_access_review_handler::<Backend>)),
+    );
 }
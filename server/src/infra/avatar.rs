@@ -0,0 +1,123 @@
+//! The processing of the avatars uploaded through the API. The LDAP clients expect JPEGs in
+//! `jpegPhoto`, so the other formats are converted, and the large images are scaled down.
+
+use crate::{domain::types::JpegPhoto, infra::configuration::AvatarOptions};
+use anyhow::{bail, Context, Result};
+use image::{imageops::FilterType, io::Limits, ImageFormat, ImageOutputFormat};
+
+// Images with more pixels than that are rejected before decoding them, whatever their file size.
+const MAX_SOURCE_DIMENSION: u32 = 10_000;
+const JPEG_QUALITY: u8 = 85;
+
+/// Checks an uploaded image, and converts it to a JPEG that fits in the configured size. A JPEG
+/// that already fits is kept as is. An empty image clears the avatar.
+pub fn process_avatar(bytes: &[u8], options: &AvatarOptions) -> Result<JpegPhoto> {
+    if bytes.is_empty() {
+        return Ok(JpegPhoto::null());
+    }
+    if bytes.len() as u64 > options.max_upload_size_kb * 1024 {
+        bail!(
+            "The image is too large ({} kB), the maximum is {} kB",
+            bytes.len() / 1024,
+            options.max_upload_size_kb
+        );
+    }
+    let format = image::guess_format(bytes).context("Unknown image format")?;
+    if !matches!(format, ImageFormat::Jpeg | ImageFormat::Png) {
+        bail!(
+            "Unsupported image format {:?}, expected a JPEG or PNG image",
+            format
+        );
+    }
+    let mut reader = image::io::Reader::with_format(std::io::Cursor::new(bytes), format);
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_SOURCE_DIMENSION);
+    limits.max_image_height = Some(MAX_SOURCE_DIMENSION);
+    reader.limits(limits);
+    let image = reader.decode().context("Invalid image")?;
+    let max_dimension = options.max_dimension.max(1);
+    let fits = image.width() <= max_dimension && image.height() <= max_dimension;
+    if fits && format == ImageFormat::Jpeg {
+        return JpegPhoto::try_from(bytes);
+    }
+    let image = if fits {
+        image
+    } else {
+        // Keeps the aspect ratio.
+        image.resize(max_dimension, max_dimension, FilterType::Lanczos3)
+    };
+    let mut jpeg = Vec::new();
+    image
+        .to_rgb8()
+        .write_to(
+            &mut std::io::Cursor::new(&mut jpeg),
+            ImageOutputFormat::Jpeg(JPEG_QUALITY),
+        )
+        .context("Could not convert the image to JPEG")?;
+    JpegPhoto::try_from(jpeg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+    use pretty_assertions::assert_eq;
+
+    fn make_png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        RgbaImage::from_pixel(width, height, Rgba([255, 0, 0, 128]))
+            .write_to(
+                &mut std::io::Cursor::new(&mut bytes),
+                ImageOutputFormat::Png,
+            )
+            .unwrap();
+        bytes
+    }
+
+    fn dimensions(photo: JpegPhoto) -> (u32, u32) {
+        let image =
+            image::load_from_memory_with_format(&photo.into_bytes(), ImageFormat::Jpeg).unwrap();
+        (image.width(), image.height())
+    }
+
+    #[test]
+    fn test_process_avatar_keeps_small_jpeg() {
+        let jpeg = JpegPhoto::for_tests();
+        assert_eq!(
+            process_avatar(&jpeg.clone().into_bytes(), &AvatarOptions::default()).unwrap(),
+            jpeg
+        );
+        assert!(process_avatar(&[], &AvatarOptions::default())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_process_avatar_converts_and_scales_down() {
+        let options = AvatarOptions {
+            max_dimension: 100,
+            ..Default::default()
+        };
+        assert_eq!(
+            dimensions(process_avatar(&make_png(400, 200), &options).unwrap()),
+            (100, 50)
+        );
+        assert_eq!(
+            dimensions(process_avatar(&make_png(40, 20), &options).unwrap()),
+            (40, 20)
+        );
+    }
+
+    #[test]
+    fn test_process_avatar_rejects_invalid_images() {
+        let options = AvatarOptions {
+            max_upload_size_kb: 1,
+            ..Default::default()
+        };
+        assert!(process_avatar(&[0; 2048], &options).is_err());
+        let options = AvatarOptions::default();
+        assert!(process_avatar(b"GIF89a\x01\x00\x01\x00", &options).is_err());
+        assert!(process_avatar(b"not an image", &options).is_err());
+        assert!(process_avatar(&make_png(10, 10)[..50], &options).is_err());
+    }
+}
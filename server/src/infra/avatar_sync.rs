@@ -0,0 +1,159 @@
+use crate::{
+    domain::{
+        handler::AvatarSyncBackendHandler,
+        sql_tables::DbConnection,
+        types::{Email, JpegPhoto},
+    },
+    infra::{configuration::AvatarSyncOptions, leader_election::LeaderElection},
+};
+use actix::prelude::{Actor, AsyncContext, Context};
+use anyhow::{Context as AnyhowContext, Result};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use tracing::{debug, error, info, instrument, warn};
+
+// Name of the lease in the leader election.
+const JOB_NAME: &str = "avatar_sync";
+// Extra time on top of the interval between runs before the lease expires.
+const LEASE_MARGIN: Duration = Duration::from_secs(5 * 60);
+// Size (in pixels) of the requested avatars.
+const AVATAR_SIZE: u32 = 256;
+
+/// Periodically fetches the avatars of the users from Gravatar/libravatar.
+pub struct AvatarSync<Backend> {
+    options: AvatarSyncOptions,
+    backend_handler: Backend,
+    leader_election: LeaderElection,
+    client: reqwest::Client,
+}
+
+impl<Backend> Actor for AvatarSync<Backend>
+where
+    Backend: AvatarSyncBackendHandler + Clone + Send + Sync + Unpin + 'static,
+{
+    type Context = Context<Self>;
+
+    fn started(&mut self, context: &mut Context<Self>) {
+        info!("Avatar sync started, from {}", &self.options.url);
+        self.schedule_task(context);
+        context.run_interval(self.interval(), |this, ctx| this.schedule_task(ctx));
+    }
+}
+
+impl<Backend> AvatarSync<Backend>
+where
+    Backend: AvatarSyncBackendHandler + Clone + Send + Sync + Unpin + 'static,
+{
+    pub fn new(
+        options: AvatarSyncOptions,
+        sql_pool: DbConnection,
+        backend_handler: Backend,
+    ) -> Self {
+        Self {
+            options,
+            backend_handler,
+            leader_election: LeaderElection::new(sql_pool),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn schedule_task(&self, ctx: &mut Context<Self>) {
+        let future = actix::fut::wrap_future::<_, Self>(Self::sync_avatars_if_leader(
+            self.backend_handler.clone(),
+            self.leader_election.clone(),
+            self.client.clone(),
+            self.options.url.clone(),
+            self.interval() + LEASE_MARGIN,
+        ));
+        ctx.spawn(future);
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs(self.options.refresh_interval_hours.max(1) * 60 * 60)
+    }
+
+    // With several instances sharing the DB, only one of them fetches the avatars.
+    async fn sync_avatars_if_leader(
+        backend_handler: Backend,
+        leader_election: LeaderElection,
+        client: reqwest::Client,
+        url: String,
+        lease: Duration,
+    ) {
+        match leader_election.try_acquire(JOB_NAME, lease).await {
+            Ok(true) => Self::sync_avatars(backend_handler, client, url).await,
+            Ok(false) => debug!("Another instance is running the avatar sync"),
+            Err(e) => error!("DB error while acquiring the avatar sync lease: {}", e),
+        }
+    }
+
+    #[instrument(skip_all)]
+    async fn sync_avatars(backend_handler: Backend, client: reqwest::Client, url: String) {
+        let candidates = match backend_handler.list_avatar_sync_candidates().await {
+            Ok(candidates) => candidates,
+            Err(e) => {
+                error!("DB error while listing the users to sync: {}", e);
+                return;
+            }
+        };
+        for (user_id, email) in candidates {
+            let avatar = match fetch_avatar(&client, &avatar_url(&url, &email)).await {
+                Ok(avatar) => avatar,
+                Err(e) => {
+                    warn!(r#"Could not fetch the avatar of "{}": {:#}"#, &user_id, e);
+                    continue;
+                }
+            };
+            if let Err(e) = backend_handler.store_synced_avatar(&user_id, avatar).await {
+                error!(
+                    r#"DB error while storing the avatar of "{}": {}"#,
+                    &user_id, e
+                );
+            }
+        }
+    }
+}
+
+/// Both Gravatar and libravatar look up the avatars by the SHA-256 of the normalized email.
+fn avatar_url(base_url: &str, email: &Email) -> String {
+    let hash = Sha256::digest(email.as_str().trim().to_lowercase());
+    format!(
+        "{}/{:x}?d=404&s={}",
+        base_url.trim_end_matches('/'),
+        hash,
+        AVATAR_SIZE
+    )
+}
+
+// Returns `None` if the user has no avatar.
+async fn fetch_avatar(client: &reqwest::Client, url: &str) -> Result<Option<JpegPhoto>> {
+    let response = client.get(url).send().await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let bytes = response.error_for_status()?.bytes().await?;
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+    // Only JPEG avatars are supported, others (e.g. PNG) are skipped.
+    Ok(Some(
+        JpegPhoto::try_from(bytes.to_vec()).context("the avatar is not a JPEG")?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_avatar_url() {
+        assert_eq!(
+            avatar_url(
+                "https://www.gravatar.com/avatar/",
+                &Email::from(" Bob@Example.com ")
+            ),
+            "https://www.gravatar.com/avatar/5ff860bf1190596c7188ab851db691f0f3169c453936e9e1eba2f9a47f7a0018?d=404&s=256"
+        );
+    }
+}
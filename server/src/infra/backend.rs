@@ -0,0 +1,62 @@
+use crate::{
+    domain::{
+        handler::{BackendHandler, LoginHandler},
+        opaque_handler::OpaqueHandler,
+    },
+    infra::{
+        configuration::{BackendKind, Configuration},
+        database_string::DatabaseUrl,
+        tcp_backend_handler::TcpBackendHandler,
+    },
+};
+use anyhow::{bail, Result};
+use tracing::warn;
+
+/// Everything the LDAP and HTTP servers need from a backend. The servers, like the background jobs,
+/// only depend on the backend traits, so that alternative stores can be plugged in instead of the
+/// SQL one. The SQL database is still needed for the coordination of the instances: the leases of
+/// the singleton jobs, and the cleanup of the expired tokens.
+pub trait ServerBackendHandler:
+    BackendHandler + TcpBackendHandler + LoginHandler + OpaqueHandler + Clone + 'static
+{
+}
+
+impl<T> ServerBackendHandler for T where
+    T: BackendHandler + TcpBackendHandler + LoginHandler + OpaqueHandler + Clone + 'static
+{
+}
+
+/// The database backing the configured backend.
+pub fn get_database_url(config: &Configuration) -> Result<DatabaseUrl> {
+    match config.backend {
+        BackendKind::Sql => Ok(config.database_url.clone()),
+        // The SQL backend, on a database that only lives as long as the process.
+        BackendKind::Memory => {
+            if !cfg!(feature = "memory-backend") {
+                bail!("The memory backend is not available, LLDAP was built without the `memory-backend` feature");
+            }
+            warn!("Using the in-memory backend: all the data will be lost when the server stops");
+            Ok(DatabaseUrl::from("sqlite::memory:"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::configuration::ConfigurationBuilder;
+
+    #[test]
+    fn test_get_database_url() {
+        let mut config = ConfigurationBuilder::for_tests();
+        assert_eq!(
+            get_database_url(&config).unwrap().to_string(),
+            config.database_url.to_string()
+        );
+        config.backend = BackendKind::Memory;
+        assert_eq!(
+            get_database_url(&config).is_ok(),
+            cfg!(feature = "memory-backend")
+        );
+    }
+}
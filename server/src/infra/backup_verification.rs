@@ -0,0 +1,327 @@
+use crate::{
+    domain::{
+        handler::{
+            BindRequest, GroupListerBackendHandler, LoginHandler, UserListerBackendHandler,
+            UserRequestFilter,
+        },
+        sql_backend_handler::SqlBackendHandler,
+        sql_tables::get_private_key_info,
+        types::UserId,
+    },
+    infra::{
+        configuration::{compare_private_key_hashes, Configuration},
+        database_string::DatabaseUrl,
+        diagnostics::{Finding, Severity},
+    },
+    setup_sql_tables,
+};
+use anyhow::{bail, Context, Result};
+use sea_orm::{ConnectionTrait, Database, DatabaseConnection, DbBackend, Statement};
+use serde::Serialize;
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+use tracing::{error, info, instrument, warn};
+
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// What was found in a backup restored into a temporary database.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct BackupReport {
+    pub users: usize,
+    pub groups: usize,
+    pub findings: Vec<Finding>,
+}
+
+impl BackupReport {
+    /// Whether the server could start from the backup, with working passwords.
+    pub fn is_restorable(&self) -> bool {
+        !self
+            .findings
+            .iter()
+            .any(|finding| finding.severity == Severity::Error)
+    }
+
+    pub fn log(&self) {
+        info!(
+            "Backup contents: {} users, {} groups",
+            self.users, self.groups
+        );
+        for finding in &self.findings {
+            match finding.severity {
+                Severity::Warning => warn!(
+                    "Backup [{}]: {} Hint: {}",
+                    finding.check, finding.message, finding.hint
+                ),
+                Severity::Error => error!(
+                    "Backup [{}]: {} Hint: {}",
+                    finding.check, finding.message, finding.hint
+                ),
+            }
+        }
+    }
+}
+
+/// A copy of the backup, removed when dropped so that the backup itself is never modified.
+struct TemporaryCopy(PathBuf);
+
+impl TemporaryCopy {
+    fn new(archive: &Path) -> Result<Self> {
+        let mut header = [0u8; 16];
+        std::fs::File::open(archive)
+            .and_then(|mut file| file.read_exact(&mut header))
+            .with_context(|| format!("while reading {}", archive.display()))?;
+        if header != SQLITE_HEADER {
+            bail!(
+                "{} is not a SQLite database. Only the backups of the SQLite database can be \
+                 verified; for MySQL or PostgreSQL, use the tools of the database.",
+                archive.display()
+            );
+        }
+        let path = std::env::temp_dir().join(format!(
+            "lldap-verify-backup-{:016x}.db",
+            rand::random::<u64>()
+        ));
+        std::fs::copy(archive, &path).with_context(|| {
+            format!("while copying {} to {}", archive.display(), path.display())
+        })?;
+        Ok(Self(path))
+    }
+
+    fn database_url(&self) -> DatabaseUrl {
+        DatabaseUrl::from(format!("sqlite://{}?mode=rw", self.0.display()).as_str())
+    }
+}
+
+impl Drop for TemporaryCopy {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.0) {
+            warn!("Could not remove the temporary database: {:#}", e);
+        }
+    }
+}
+
+async fn query_strings(sql_pool: &DatabaseConnection, query: &str) -> Result<Vec<String>> {
+    sql_pool
+        .query_all(Statement::from_string(DbBackend::Sqlite, query.to_owned()))
+        .await?
+        .into_iter()
+        .map(|row| Ok(row.try_get_by_index::<String>(0)?))
+        .collect()
+}
+
+/// Checks the file itself, before touching it with the migrations.
+async fn check_integrity(database_url: &DatabaseUrl) -> Result<Vec<Finding>> {
+    let sql_pool = Database::connect(database_url.to_string()).await?;
+    let problems = query_strings(&sql_pool, "PRAGMA integrity_check").await?;
+    let mut findings: Vec<_> = problems
+        .into_iter()
+        .filter(|problem| problem != "ok")
+        .map(|problem| {
+            Finding::new(
+                "integrity",
+                Severity::Error,
+                format!("The database is corrupted: {}", problem),
+                "Use an older backup, and check the storage of the server.",
+            )
+        })
+        .collect();
+    findings.extend(
+        query_strings(&sql_pool, "PRAGMA foreign_key_check")
+            .await?
+            .into_iter()
+            .map(|table| {
+                Finding::new(
+                    "consistency",
+                    Severity::Error,
+                    format!("A row of the table {} references a missing entry.", table),
+                    "The backup was probably taken while LLDAP was writing: stop LLDAP, or use \
+                     `sqlite3 users.db .backup <file>`, to take the backups.",
+                )
+            }),
+    );
+    sql_pool.close().await?;
+    Ok(findings)
+}
+
+async fn check_server_key(config: &Configuration, sql_pool: &DatabaseConnection) -> Vec<Finding> {
+    let stored_key = match get_private_key_info(sql_pool).await {
+        Ok(stored_key) => stored_key,
+        Err(e) => {
+            return vec![Finding::new(
+                "server_key",
+                Severity::Error,
+                format!("Could not read the server key information: {:#}", e),
+                "The backup may come from an unsupported version of LLDAP.",
+            )]
+        }
+    };
+    match compare_private_key_hashes(stored_key.as_ref(), &config.get_private_key_info()) {
+        Ok(_) => Vec::new(),
+        Err(e) => vec![Finding::new(
+            "server_key",
+            Severity::Error,
+            format!("{:#}", e),
+            "The passwords can only be checked with the server key they were set with: keep a \
+             copy of the key file (or seed) along with the backups.",
+        )],
+    }
+}
+
+async fn check_admins(handler: &SqlBackendHandler) -> Result<Vec<Finding>> {
+    let admins = handler
+        .list_users(
+            Some(UserRequestFilter::MemberOf("lldap_admin".into())),
+            false,
+        )
+        .await?;
+    Ok(if admins.is_empty() {
+        vec![Finding::new(
+            "consistency",
+            Severity::Warning,
+            "No member of lldap_admin: the server would create the admin user from the \
+             configuration."
+                .to_owned(),
+            "Check that the backup comes from the right server.",
+        )]
+    } else {
+        Vec::new()
+    })
+}
+
+async fn check_bind(handler: &SqlBackendHandler, bind_request: BindRequest) -> Option<Finding> {
+    let name = bind_request.name.clone();
+    match handler.bind(bind_request).await {
+        Ok(()) => None,
+        Err(e) => Some(Finding::new(
+            "bind",
+            Severity::Error,
+            format!(r#"Could not bind as "{}": {:#}"#, name, e),
+            "Check the password, and that the server key is the one the backup was taken with.",
+        )),
+    }
+}
+
+/// Restores the SQLite backup `archive` into a temporary database, upgrades it to the current
+/// schema, checks its consistency and binds as `bind_user`. The backup is left untouched.
+#[instrument(skip_all, level = "info", fields(archive = %archive.display()))]
+pub async fn verify_backup(
+    archive: &Path,
+    config: &Configuration,
+    bind_user: UserId,
+    bind_password: &str,
+) -> Result<BackupReport> {
+    let copy = TemporaryCopy::new(archive)?;
+    let database_url = copy.database_url();
+    let mut report = BackupReport {
+        findings: check_integrity(&database_url)
+            .await
+            .context("while checking the integrity of the database")?,
+        ..Default::default()
+    };
+    if !report.is_restorable() {
+        return Ok(report);
+    }
+    let sql_pool = match setup_sql_tables(&database_url).await {
+        Ok(sql_pool) => sql_pool,
+        Err(e) => {
+            report.findings.push(Finding::new(
+                "migrations",
+                Severity::Error,
+                format!("Could not upgrade the database schema: {:#}", e),
+                "The backup may come from a newer version of LLDAP.",
+            ));
+            return Ok(report);
+        }
+    };
+    report
+        .findings
+        .extend(check_server_key(config, &sql_pool).await);
+    let handler = SqlBackendHandler::new(config.clone(), sql_pool.clone());
+    report.users = handler.list_users(None, false).await?.len();
+    report.groups = handler.list_groups(None).await?.len();
+    report.findings.extend(check_admins(&handler).await?);
+    report.findings.extend(
+        check_bind(
+            &handler,
+            BindRequest {
+                name: bind_user,
+                password: bind_password.to_owned(),
+            },
+        )
+        .await,
+    );
+    sql_pool.close().await?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{CreateGroupRequest, GroupBackendHandler},
+        sql_backend_handler::tests::{get_default_config, insert_membership, insert_user},
+    };
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_verify_backup() {
+        crate::infra::logging::init_for_tests();
+        let config = get_default_config();
+        let backup = TemporaryCopy(std::env::temp_dir().join(format!(
+            "lldap-backup-test-{:016x}.db",
+            rand::random::<u64>()
+        )));
+        let sql_pool = setup_sql_tables(&DatabaseUrl::from(
+            format!("sqlite://{}?mode=rwc", backup.0.display()).as_str(),
+        ))
+        .await
+        .unwrap();
+        let handler = SqlBackendHandler::new(config.clone(), sql_pool.clone());
+        insert_user(&handler, "bob", "bob00000").await;
+        let admin_group = handler
+            .create_group(CreateGroupRequest {
+                display_name: "lldap_admin".into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        insert_membership(&handler, admin_group, "bob").await;
+        sql_pool.close().await.unwrap();
+
+        let report = verify_backup(&backup.0, &config, UserId::new("bob"), "bob00000")
+            .await
+            .unwrap();
+        assert_eq!(
+            report,
+            BackupReport {
+                users: 1,
+                groups: 1,
+                findings: Vec::new(),
+            }
+        );
+
+        let report = verify_backup(&backup.0, &config, UserId::new("bob"), "wrong")
+            .await
+            .unwrap();
+        assert!(!report.is_restorable());
+        assert_eq!(report.findings[0].check, "bind");
+    }
+
+    #[test]
+    fn test_not_a_sqlite_database() {
+        let file = std::env::temp_dir().join(format!(
+            "lldap-backup-test-{:016x}.db",
+            rand::random::<u64>()
+        ));
+        std::fs::write(&file, "-- MySQL dump 10.13").unwrap();
+        let result = TemporaryCopy::new(&file);
+        std::fs::remove_file(&file).unwrap();
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("is not a SQLite database"));
+    }
+}
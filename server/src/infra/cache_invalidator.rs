@@ -0,0 +1,62 @@
+use std::{sync::Arc, time::Duration};
+
+use actix::prelude::{Actor, AsyncContext, Context};
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+use crate::domain::{handler::ChangeFeedBackendHandler, sql_backend_handler::SqlBackendHandler};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Follows the change feed to drop the search cache when another instance sharing the database
+/// modifies the directory. The changes made by this instance already drop it when they are
+/// published.
+pub struct CacheInvalidator {
+    backend_handler: SqlBackendHandler,
+    // The sequence of the last change read. The lock is held during a poll, so that a slow one
+    // doesn't overlap with the next.
+    last_sequence: Arc<Mutex<Option<i32>>>,
+}
+
+impl Actor for CacheInvalidator {
+    type Context = Context<Self>;
+
+    fn started(&mut self, context: &mut Context<Self>) {
+        info!("Following the change feed to invalidate the search cache");
+        self.poll(context);
+        context.run_interval(POLL_INTERVAL, |this, ctx| this.poll(ctx));
+    }
+}
+
+impl CacheInvalidator {
+    pub fn new(backend_handler: SqlBackendHandler) -> Self {
+        Self {
+            backend_handler,
+            last_sequence: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn poll(&self, ctx: &mut Context<Self>) {
+        let backend_handler = self.backend_handler.clone();
+        let last_sequence = self.last_sequence.clone();
+        ctx.spawn(actix::fut::wrap_future::<_, Self>(async move {
+            let mut last_sequence = last_sequence.lock().await;
+            let since = match *last_sequence {
+                Some(since) => since,
+                // The first poll runs at startup, before anything is cached.
+                None => match backend_handler.get_last_change_sequence().await {
+                    Ok(since) => since,
+                    Err(e) => {
+                        error!("Could not read the change feed: {}", e);
+                        return;
+                    }
+                },
+            };
+            *last_sequence = Some(since);
+            match backend_handler.invalidate_cache_after(since).await {
+                Ok(sequence) => *last_sequence = Some(sequence),
+                Err(e) => error!("Could not read the change feed: {}", e),
+            }
+        }));
+    }
+}
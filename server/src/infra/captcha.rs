@@ -0,0 +1,260 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use base64::Engine;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use lldap_auth::captcha::{proof_of_work_zero_bits, Challenge};
+use secstr::SecUtf8;
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::{
+    domain::secret_tokens::gen_random_string,
+    infra::configuration::{CaptchaOptions, CaptchaProvider},
+};
+
+/// How long a proof of work challenge can be solved for.
+const PROOF_OF_WORK_VALIDITY_SECONDS: i64 = 5 * 60;
+
+/// A source of challenges that the clients solve to prove they are not bots.
+#[async_trait]
+pub trait ChallengeProvider: Send + Sync {
+    /// The challenge given to the client, with what it needs to display or solve it.
+    fn new_challenge(&self) -> Challenge;
+    /// Whether the solution sent back by the client is valid.
+    async fn verify(&self, solution: &str, remote_ip: Option<&str>) -> Result<bool>;
+}
+
+#[derive(Deserialize)]
+struct SiteVerifyResponse {
+    success: bool,
+}
+
+/// hCaptcha and Turnstile share the same "siteverify" API.
+struct SiteVerifyProvider {
+    client: reqwest::Client,
+    provider: CaptchaProvider,
+    verify_url: &'static str,
+    site_key: String,
+    secret_key: SecUtf8,
+}
+
+#[async_trait]
+impl ChallengeProvider for SiteVerifyProvider {
+    fn new_challenge(&self) -> Challenge {
+        let site_key = self.site_key.clone();
+        match self.provider {
+            CaptchaProvider::Turnstile => Challenge::Turnstile { site_key },
+            _ => Challenge::HCaptcha { site_key },
+        }
+    }
+
+    async fn verify(&self, solution: &str, remote_ip: Option<&str>) -> Result<bool> {
+        let mut form = vec![
+            ("secret", self.secret_key.unsecure()),
+            ("response", solution),
+        ];
+        if let Some(remote_ip) = remote_ip {
+            form.push(("remoteip", remote_ip));
+        }
+        let response = self
+            .client
+            .post(self.verify_url)
+            .form(&form)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("while calling the {:?} verification", self.provider))?
+            .json::<SiteVerifyResponse>()
+            .await?;
+        Ok(response.success)
+    }
+}
+
+/// A hashcash-like challenge, verified locally. The challenges are signed so that the server
+/// doesn't need to remember them, only the recently used ones to prevent replays.
+struct ProofOfWorkProvider {
+    key: Hmac<Sha256>,
+    difficulty: u8,
+    used: Mutex<HashMap<String, i64>>,
+}
+
+impl ProofOfWorkProvider {
+    fn new(secret: &SecUtf8, difficulty: u8) -> Self {
+        Self {
+            key: Hmac::new_from_slice(secret.unsecure().as_bytes())
+                .expect("HMAC accepts keys of any size"),
+            difficulty,
+            used: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn mac(&self, payload: &str) -> Hmac<Sha256> {
+        let mut mac = self.key.clone();
+        mac.update(payload.as_bytes());
+        mac
+    }
+
+    fn challenge_at(&self, timestamp: i64) -> String {
+        let payload = format!("{}.{}", timestamp, gen_random_string(16));
+        let signature = self.mac(&payload).finalize().into_bytes();
+        format!(
+            "{}.{}",
+            payload,
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    fn check(&self, solution: &str, now: i64) -> bool {
+        let (challenge, counter) = match solution.rsplit_once(':') {
+            Some((challenge, counter)) => (challenge, counter),
+            None => return false,
+        };
+        let counter = match counter.parse::<u64>() {
+            Ok(counter) => counter,
+            Err(_) => return false,
+        };
+        let (payload, signature) = match challenge.rsplit_once('.') {
+            Some(parts) => parts,
+            None => return false,
+        };
+        let timestamp = match payload.split_once('.').map(|(t, _)| t.parse::<i64>()) {
+            Some(Ok(timestamp)) => timestamp,
+            _ => return false,
+        };
+        let signature = match base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(signature) {
+            Ok(signature) => signature,
+            Err(_) => return false,
+        };
+        if self.mac(payload).verify_slice(&signature).is_err()
+            || timestamp > now
+            || now - timestamp > PROOF_OF_WORK_VALIDITY_SECONDS
+            || proof_of_work_zero_bits(challenge, counter) < self.difficulty as u32
+        {
+            return false;
+        }
+        let mut used = self.used.lock().unwrap();
+        used.retain(|_, timestamp| now - *timestamp <= PROOF_OF_WORK_VALIDITY_SECONDS);
+        used.insert(challenge.to_owned(), timestamp).is_none()
+    }
+}
+
+#[async_trait]
+impl ChallengeProvider for ProofOfWorkProvider {
+    fn new_challenge(&self) -> Challenge {
+        Challenge::ProofOfWork {
+            challenge: self.challenge_at(Utc::now().timestamp()),
+            difficulty: self.difficulty,
+        }
+    }
+
+    async fn verify(&self, solution: &str, _remote_ip: Option<&str>) -> Result<bool> {
+        Ok(self.check(solution, Utc::now().timestamp()))
+    }
+}
+
+/// The configured provider, or None if the forms are not protected by a challenge.
+pub fn from_options(
+    options: &CaptchaOptions,
+    jwt_secret: &SecUtf8,
+) -> Result<Option<Arc<dyn ChallengeProvider>>> {
+    let missing = |field: &str| {
+        anyhow!(
+            "captcha_options.{} is required for the {:?} provider",
+            field,
+            options.provider
+        )
+    };
+    let site_verify = |verify_url: &'static str| -> Result<Arc<dyn ChallengeProvider>> {
+        Ok(Arc::new(SiteVerifyProvider {
+            client: reqwest::Client::new(),
+            provider: options.provider,
+            verify_url,
+            site_key: options
+                .site_key
+                .clone()
+                .ok_or_else(|| missing("site_key"))?,
+            secret_key: options
+                .secret_key
+                .clone()
+                .ok_or_else(|| missing("secret_key"))?,
+        }))
+    };
+    Ok(match options.provider {
+        CaptchaProvider::None => None,
+        CaptchaProvider::HCaptcha => Some(site_verify("https://api.hcaptcha.com/siteverify")?),
+        CaptchaProvider::Turnstile => Some(site_verify(
+            "https://challenges.cloudflare.com/turnstile/v0/siteverify",
+        )?),
+        CaptchaProvider::ProofOfWork => {
+            if options.difficulty > 32 {
+                return Err(anyhow!("captcha_options.difficulty cannot exceed 32"));
+            }
+            Some(Arc::new(ProofOfWorkProvider::new(
+                jwt_secret,
+                options.difficulty,
+            )))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::configuration::CaptchaOptionsBuilder;
+    use lldap_auth::captcha::solve_proof_of_work;
+
+    #[test]
+    fn test_from_options() {
+        let secret = SecUtf8::from("secret");
+        assert!(from_options(&CaptchaOptions::default(), &secret)
+            .unwrap()
+            .is_none());
+        let hcaptcha = CaptchaOptionsBuilder::default()
+            .provider(CaptchaProvider::HCaptcha)
+            .site_key(Some("site".to_owned()))
+            .build()
+            .unwrap();
+        assert!(from_options(&hcaptcha, &secret).is_err());
+        let turnstile = CaptchaOptionsBuilder::default()
+            .provider(CaptchaProvider::Turnstile)
+            .site_key(Some("site".to_owned()))
+            .secret_key(Some(SecUtf8::from("key")))
+            .build()
+            .unwrap();
+        assert_eq!(
+            from_options(&turnstile, &secret)
+                .unwrap()
+                .unwrap()
+                .new_challenge(),
+            Challenge::Turnstile {
+                site_key: "site".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn test_proof_of_work() {
+        let provider = ProofOfWorkProvider::new(&SecUtf8::from("secret"), 8);
+        let now = 1_700_000_000;
+        let challenge = provider.challenge_at(now);
+        let solution = solve_proof_of_work(&challenge, 8);
+        assert!(!provider.check(&format!("{}:x", challenge), now));
+        assert!(provider.check(&solution, now + 10));
+        // A solution can only be used once.
+        assert!(!provider.check(&solution, now + 20));
+        // Expired.
+        let challenge = provider.challenge_at(now);
+        let solution = solve_proof_of_work(&challenge, 8);
+        assert!(!provider.check(&solution, now + PROOF_OF_WORK_VALIDITY_SECONDS + 1));
+        // Signed by another server.
+        let other = ProofOfWorkProvider::new(&SecUtf8::from("other"), 8);
+        let solution = solve_proof_of_work(&other.challenge_at(now), 8);
+        assert!(!provider.check(&solution, now));
+    }
+}
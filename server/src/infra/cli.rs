@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{path::PathBuf, str::FromStr};
 
 use clap::{builder::EnumValueParser, Parser};
 use lettre::message::Mailbox;
@@ -93,13 +93,45 @@ pub enum Command {
     Run(RunOpts),
     /// Test whether the LDAP and GraphQL server are responsive.
     #[clap(name = "healthcheck")]
-    HealthCheck(RunOpts),
+    HealthCheck(HealthCheckOpts),
     /// Send a test email.
     #[clap(name = "send_test_email")]
     SendTestEmail(TestEmailOpts),
     /// Create database schema.
     #[clap(name = "create_schema")]
-    CreateSchema(RunOpts),
+    CreateSchema(CreateSchemaOpts),
+    /// Run a quick end-to-end test of the server against a temporary database.
+    #[clap(name = "self_test", alias = "self-test")]
+    SelfTest(SelfTestOpts),
+    /// Fill the database with fake users and groups, for development and load testing.
+    #[clap(name = "seed")]
+    Seed(SeedOpts),
+    /// Import the users and groups of a Keycloak realm export.
+    #[clap(name = "import_keycloak", alias = "import-keycloak")]
+    ImportKeycloak(ImportKeycloakOpts),
+    /// Check that a backup of the SQLite database can be restored: restore it into a temporary
+    /// database, check its consistency and bind as a user.
+    #[clap(name = "verify_backup", alias = "verify-backup")]
+    VerifyBackup(VerifyBackupOpts),
+}
+
+/// How a command prints its result.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+#[clap(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Aligned fields, for humans.
+    #[default]
+    Table,
+    Json,
+    Yaml,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct OutputOpts {
+    /// Format of the result. With json and yaml, the logs go to stderr so that stdout only has
+    /// the result.
+    #[clap(long, value_enum, default_value_t, env = "LLDAP_OUTPUT")]
+    pub output: OutputFormat,
 }
 
 #[derive(Debug, Parser, Clone)]
@@ -176,6 +208,117 @@ pub struct RunOpts {
     pub ldaps_opts: LdapsOpts,
 }
 
+#[derive(Debug, Parser, Clone)]
+pub struct HealthCheckOpts {
+    #[clap(flatten)]
+    pub run_opts: RunOpts,
+
+    #[clap(flatten)]
+    pub output_opts: OutputOpts,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct CreateSchemaOpts {
+    #[clap(flatten)]
+    pub run_opts: RunOpts,
+
+    #[clap(flatten)]
+    pub output_opts: OutputOpts,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct SelfTestOpts {
+    /// Set verbose logging.
+    #[clap(short, long)]
+    pub verbose: bool,
+
+    #[clap(flatten)]
+    pub output_opts: OutputOpts,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct SeedOpts {
+    #[clap(flatten)]
+    pub general_config: GeneralConfigOpts,
+
+    /// Database connection URL
+    #[clap(short, long, env = "LLDAP_DATABASE_URL")]
+    pub database_url: Option<DatabaseUrl>,
+
+    /// Number of users to create.
+    #[clap(long, default_value = "1000")]
+    pub users: usize,
+
+    /// Number of groups to create.
+    #[clap(long, default_value = "20")]
+    pub groups: usize,
+
+    /// Maximum number of groups each user is added to.
+    #[clap(long, default_value = "3")]
+    pub memberships_per_user: usize,
+
+    /// Seed of the random generator, to generate the same data on every run.
+    #[clap(long)]
+    pub seed: Option<u64>,
+
+    #[clap(flatten)]
+    pub output_opts: OutputOpts,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct ImportKeycloakOpts {
+    #[clap(flatten)]
+    pub general_config: GeneralConfigOpts,
+
+    /// Database connection URL
+    #[clap(short, long, env = "LLDAP_DATABASE_URL")]
+    pub database_url: Option<DatabaseUrl>,
+
+    /// JSON file of the realm export, e.g. from `kc.sh export --realm <realm> --file <file>`.
+    pub file: PathBuf,
+
+    /// Adds the members of a Keycloak realm role to an LLDAP group, as `role=group`. Can be
+    /// repeated.
+    #[clap(long = "role-mapping")]
+    pub role_mappings: Vec<String>,
+
+    /// Send the new users a password reset link, through the configured password reset channel,
+    /// to pick their password.
+    #[clap(long)]
+    pub send_invitations: bool,
+
+    #[clap(flatten)]
+    pub output_opts: OutputOpts,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct VerifyBackupOpts {
+    #[clap(flatten)]
+    pub general_config: GeneralConfigOpts,
+
+    /// Path to the file that contains the private server key the backup was taken with.
+    #[clap(long, env = "LLDAP_SERVER_KEY_FILE")]
+    pub server_key_file: Option<String>,
+
+    /// Seed used to generate the private server key the backup was taken with.
+    #[clap(long, env = "LLDAP_SERVER_KEY_SEED")]
+    pub server_key_seed: Option<String>,
+
+    /// Copy of the SQLite database (e.g. users.db). It is not modified.
+    pub archive: PathBuf,
+
+    /// User to bind as. Default: the admin user of the configuration.
+    #[clap(long)]
+    pub bind_user: Option<String>,
+
+    /// Password to bind with. Default: the admin password of the configuration.
+    #[clap(long, env = "LLDAP_VERIFY_BACKUP_PASSWORD")]
+    pub bind_password: Option<String>,
+
+    #[clap(flatten)]
+    pub output_opts: OutputOpts,
+}
+
 #[derive(Debug, Parser, Clone)]
 pub struct TestEmailOpts {
     #[clap(flatten)]
@@ -185,6 +328,9 @@ pub struct TestEmailOpts {
     #[clap(long, env = "LLDAP_TEST_EMAIL_TO")]
     pub to: String,
 
+    #[clap(flatten)]
+    pub output_opts: OutputOpts,
+
     #[clap(flatten)]
     pub smtp_opts: SmtpOpts,
 }
@@ -0,0 +1,315 @@
+//! The results of the CLI commands, printed as aligned fields for humans, or as JSON or YAML for
+//! scripts. The JSON and YAML documents are a stable interface: fields are only ever added.
+
+use crate::infra::{
+    backup_verification::BackupReport, cli::OutputFormat, diagnostics::Severity,
+    healthcheck::HealthCheckReport, keycloak_import::ImportSummary, seed::SeedSummary,
+};
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+/// The result of a command.
+pub trait CommandOutput: Serialize {
+    /// The fields shown in the table output, in order.
+    fn table_rows(&self) -> Vec<(String, String)>;
+
+    /// Whether the command reached its goal, e.g. the backup can be restored.
+    fn is_success(&self) -> bool {
+        true
+    }
+}
+
+/// The document printed with `--output json|yaml`.
+#[derive(Serialize)]
+struct Document<'a, T> {
+    command: &'a str,
+    success: bool,
+    result: Option<&'a T>,
+    error: Option<String>,
+}
+
+fn render_table(rows: &[(String, String)]) -> String {
+    let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    rows.iter()
+        .map(|(name, value)| format!("{:width$}  {}\n", name, value, width = width))
+        .collect()
+}
+
+fn render<T: CommandOutput>(
+    format: OutputFormat,
+    command: &str,
+    result: &Result<T>,
+) -> Result<Option<String>> {
+    let document = match result {
+        Ok(output) => Document {
+            command,
+            success: output.is_success(),
+            result: Some(output),
+            error: None,
+        },
+        Err(e) => Document {
+            command,
+            success: false,
+            result: None,
+            error: Some(format!("{:#}", e)),
+        },
+    };
+    Ok(Some(match format {
+        OutputFormat::Table => match result {
+            Ok(output) => render_table(&output.table_rows()),
+            // The error is printed by `main`, as for every failure.
+            Err(_) => return Ok(None),
+        },
+        OutputFormat::Json => serde_json::to_string_pretty(&document)? + "\n",
+        OutputFormat::Yaml => serde_yaml::to_string(&document)?,
+    }))
+}
+
+/// Prints the result of `command` on stdout. Fails if the command did, for the exit code.
+pub fn print_result<T: CommandOutput>(
+    format: OutputFormat,
+    command: &str,
+    result: Result<T>,
+) -> Result<()> {
+    if let Some(rendered) = render(format, command, &result)? {
+        print!("{}", rendered);
+    }
+    if !result?.is_success() {
+        bail!("{} failed", command);
+    }
+    Ok(())
+}
+
+fn status(ok: bool) -> String {
+    if ok { "ok" } else { "failed" }.to_owned()
+}
+
+impl CommandOutput for HealthCheckReport {
+    fn table_rows(&self) -> Vec<(String, String)> {
+        [
+            ("ldap", &self.ldap),
+            ("ldaps", &self.ldaps),
+            ("api", &self.api),
+        ]
+        .into_iter()
+        .map(|(name, check)| {
+            let value = match &check.error {
+                Some(error) => format!("{}: {}", status(check.ok), error),
+                None => status(check.ok),
+            };
+            (name.to_owned(), value)
+        })
+        .collect()
+    }
+
+    fn is_success(&self) -> bool {
+        self.is_healthy()
+    }
+}
+
+impl CommandOutput for BackupReport {
+    fn table_rows(&self) -> Vec<(String, String)> {
+        let mut rows = vec![
+            ("restorable".to_owned(), self.is_restorable().to_string()),
+            ("users".to_owned(), self.users.to_string()),
+            ("groups".to_owned(), self.groups.to_string()),
+        ];
+        rows.extend(self.findings.iter().map(|finding| {
+            let severity = match finding.severity {
+                Severity::Warning => "warning",
+                Severity::Error => "error",
+            };
+            (
+                format!("{} [{}]", severity, finding.check),
+                finding.message.clone(),
+            )
+        }));
+        rows
+    }
+
+    fn is_success(&self) -> bool {
+        self.is_restorable()
+    }
+}
+
+impl CommandOutput for ImportSummary {
+    fn table_rows(&self) -> Vec<(String, String)> {
+        vec![
+            (
+                "created_users".to_owned(),
+                self.created_users.len().to_string(),
+            ),
+            ("created_groups".to_owned(), self.created_groups.to_string()),
+            ("skipped_users".to_owned(), self.skipped_users.to_string()),
+            ("memberships".to_owned(), self.memberships.to_string()),
+        ]
+    }
+}
+
+impl CommandOutput for SeedSummary {
+    fn table_rows(&self) -> Vec<(String, String)> {
+        vec![
+            ("users".to_owned(), self.users.to_string()),
+            ("groups".to_owned(), self.groups.to_string()),
+            ("memberships".to_owned(), self.memberships.to_string()),
+        ]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SelfTestReport {
+    pub duration_ms: u64,
+}
+
+impl CommandOutput for SelfTestReport {
+    fn table_rows(&self) -> Vec<(String, String)> {
+        vec![
+            ("self_test".to_owned(), "passed".to_owned()),
+            ("duration_ms".to_owned(), self.duration_ms.to_string()),
+        ]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TestEmailReport {
+    pub to: String,
+}
+
+impl CommandOutput for TestEmailReport {
+    fn table_rows(&self) -> Vec<(String, String)> {
+        vec![("sent_to".to_owned(), self.to.clone())]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SchemaReport {
+    pub schema_version: i16,
+}
+
+impl CommandOutput for SchemaReport {
+    fn table_rows(&self) -> Vec<(String, String)> {
+        vec![("schema_version".to_owned(), self.schema_version.to_string())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::{diagnostics::Finding, healthcheck::CheckOutcome};
+    use anyhow::anyhow;
+    use pretty_assertions::assert_eq;
+
+    fn backup_report() -> BackupReport {
+        BackupReport {
+            users: 3,
+            groups: 2,
+            findings: vec![Finding::new(
+                "bind",
+                Severity::Error,
+                "Could not bind".to_owned(),
+                "Check the password.",
+            )],
+        }
+    }
+
+    #[test]
+    fn test_render_table() {
+        assert_eq!(
+            render(OutputFormat::Table, "verify_backup", &Ok(backup_report()))
+                .unwrap()
+                .unwrap(),
+            "restorable    false\n\
+             users         3\n\
+             groups        2\n\
+             error [bind]  Could not bind\n"
+        );
+        assert_eq!(
+            render::<SeedSummary>(OutputFormat::Table, "seed", &Err(anyhow!("Boom"))).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_render_json() {
+        let rendered = render(OutputFormat::Json, "verify_backup", &Ok(backup_report()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&rendered).unwrap(),
+            serde_json::json!({
+                "command": "verify_backup",
+                "success": false,
+                "result": {
+                    "users": 3,
+                    "groups": 2,
+                    "findings": [{
+                        "check": "bind",
+                        "severity": "error",
+                        "message": "Could not bind",
+                        "hint": "Check the password.",
+                    }],
+                },
+                "error": null,
+            })
+        );
+        let rendered = render::<SeedSummary>(
+            OutputFormat::Json,
+            "seed",
+            &Err(anyhow!("Boom").context("while seeding")),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&rendered).unwrap(),
+            serde_json::json!({
+                "command": "seed",
+                "success": false,
+                "result": null,
+                "error": "while seeding: Boom",
+            })
+        );
+    }
+
+    #[test]
+    fn test_render_yaml() {
+        let report = HealthCheckReport {
+            ldap: CheckOutcome {
+                ok: true,
+                error: None,
+            },
+            ldaps: CheckOutcome {
+                ok: true,
+                error: None,
+            },
+            api: CheckOutcome {
+                ok: false,
+                error: Some("Connection refused".to_owned()),
+            },
+        };
+        assert_eq!(
+            render(OutputFormat::Yaml, "healthcheck", &Ok(report))
+                .unwrap()
+                .unwrap(),
+            "command: healthcheck
+success: false
+result:
+  ldap:
+    ok: true
+    error: null
+  ldaps:
+    ok: true
+    error: null
+  api:
+    ok: false
+    error: Connection refused
+error: null
+"
+        );
+    }
+
+    #[test]
+    fn test_print_result_fails_with_the_command() {
+        assert!(print_result(OutputFormat::Json, "seed", Ok(SeedSummary::default())).is_ok());
+        assert!(print_result(OutputFormat::Json, "verify_backup", Ok(backup_report())).is_err());
+    }
+}
@@ -1,14 +1,15 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 
 use crate::{
     domain::{
+        handler::UserCreationSource,
         sql_tables::{ConfigLocation, PrivateKeyHash, PrivateKeyInfo, PrivateKeyLocation},
-        types::{AttributeName, UserId},
+        types::{self, AttributeName, AttributeType, GroupDetails, GroupName, UserId},
     },
     infra::{
         cli::{
-            GeneralConfigOpts, LdapsOpts, RunOpts, SmtpEncryption, SmtpOpts, TestEmailOpts,
-            TrueFalseAlways,
+            GeneralConfigOpts, ImportKeycloakOpts, LdapsOpts, RunOpts, SeedOpts, SmtpEncryption,
+            SmtpOpts, TestEmailOpts, TrueFalseAlways, VerifyBackupOpts,
         },
         database_string::DatabaseUrl,
     },
@@ -63,6 +64,211 @@ impl std::default::Default for MailOptions {
     }
 }
 
+/// How the password reset links are sent to the users.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PasswordResetChannel {
+    /// By email, with the SMTP options.
+    #[default]
+    Email,
+    /// As a JSON POST request to `webhook_url`, e.g. for a chat bot.
+    Webhook,
+    /// As a form POST request to the SMS gateway at `sms_gateway_url`.
+    Sms,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct PasswordResetOptions {
+    #[builder(default)]
+    pub channel: PasswordResetChannel,
+    #[builder(default)]
+    pub webhook_url: Option<Url>,
+    /// Sent as the Authorization header to the webhook or the SMS gateway.
+    #[builder(default)]
+    pub authorization: Option<SecUtf8>,
+    #[builder(default)]
+    pub sms_gateway_url: Option<Url>,
+    /// User attribute holding the phone number the SMS is sent to.
+    #[builder(default = r#"AttributeName::from("phone")"#)]
+    pub sms_phone_attribute: AttributeName,
+    /// Names of the form fields of the phone number and of the message.
+    #[builder(default = r#"String::from("to")"#)]
+    pub sms_to_field: String,
+    #[builder(default = r#"String::from("message")"#)]
+    pub sms_message_field: String,
+}
+
+impl std::default::Default for PasswordResetOptions {
+    fn default() -> Self {
+        PasswordResetOptionsBuilder::default().build().unwrap()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct ExpiryMonitoringOptions {
+    /// Alert the admins when the LDAPS certificate expires in less than this.
+    #[builder(default = "14")]
+    pub certificate_warning_days: u32,
+    /// Alert the admins when the server key or the JWT secret file is older than this.
+    #[builder(default)]
+    pub key_rotation_days: Option<u32>,
+}
+
+impl std::default::Default for ExpiryMonitoringOptions {
+    fn default() -> Self {
+        ExpiryMonitoringOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// The checks of the environment run at startup: DNS, SMTP, database, clock and key files.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct DiagnosticsOptions {
+    #[builder(default = "true")]
+    pub enabled: bool,
+    /// Whether the findings are available to the admins at `/api/diagnostics`.
+    #[builder(default = "false")]
+    pub expose_api: bool,
+}
+
+impl std::default::Default for DiagnosticsOptions {
+    fn default() -> Self {
+        DiagnosticsOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// The Prometheus endpoint, at `/metrics` on the HTTP port.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct MetricsOptions {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// When set, the scrapers have to send it in an `Authorization: Bearer` header.
+    #[builder(default)]
+    pub bearer_token: Option<SecUtf8>,
+}
+
+/// Caps on the size of the directory, checked when creating users and groups.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct QuotaOptions {
+    /// Every user counts, including the admins and the disabled accounts.
+    #[builder(default)]
+    pub max_users: Option<u64>,
+    #[builder(default)]
+    pub max_groups: Option<u64>,
+}
+
+/// Where the fields of the user are found in the payloads of the HR system, as JSON pointers
+/// (RFC 6901), e.g. "/employee/workEmail".
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct ProvisioningFieldMapping {
+    #[builder(default = r#"String::from("/username")"#)]
+    pub user_id: String,
+    #[builder(default = r#"String::from("/email")"#)]
+    pub email: String,
+    #[builder(default = r#"Some(String::from("/firstName"))"#)]
+    pub first_name: Option<String>,
+    #[builder(default = r#"Some(String::from("/lastName"))"#)]
+    pub last_name: Option<String>,
+    /// Defaults to the first and last names.
+    #[builder(default)]
+    pub display_name: Option<String>,
+    /// When it isn't set, all the users in the payloads are active.
+    #[builder(default = r#"Some(String::from("/status"))"#)]
+    pub status: Option<String>,
+}
+
+impl std::default::Default for ProvisioningFieldMapping {
+    fn default() -> Self {
+        ProvisioningFieldMappingBuilder::default().build().unwrap()
+    }
+}
+
+/// The inbound webhook at `/api/provisioning/webhook`, for HR systems to create, update and
+/// disable the users.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct ProvisioningWebhookOptions {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// The key of the HMAC-SHA256 signature of the payloads. Required when enabled.
+    #[builder(default)]
+    pub secret: Option<SecUtf8>,
+    /// The header with the hex-encoded signature, optionally prefixed by "sha256=".
+    #[builder(default = r#"String::from("X-Signature-256")"#)]
+    pub signature_header: String,
+    /// The header with the time of the delivery, in seconds since the epoch. It is signed.
+    #[builder(default = r#"String::from("X-Webhook-Timestamp")"#)]
+    pub timestamp_header: String,
+    /// The header with the unique ID of the delivery. It is signed, and a delivery is only
+    /// accepted once.
+    #[builder(default = r#"String::from("X-Webhook-Id")"#)]
+    pub delivery_id_header: String,
+    /// How far the timestamp can be from the current time, in seconds.
+    #[builder(default = "300")]
+    pub max_age_seconds: u32,
+    #[builder(default)]
+    pub mapping: ProvisioningFieldMapping,
+    /// The values of the status field, compared without case, for which the user is disabled.
+    #[builder(default = r#"vec![String::from("inactive"), String::from("terminated")]"#)]
+    pub inactive_statuses: Vec<String>,
+}
+
+impl std::default::Default for ProvisioningWebhookOptions {
+    fn default() -> Self {
+        ProvisioningWebhookOptionsBuilder::default()
+            .build()
+            .unwrap()
+    }
+}
+
+/// Synchronous validation of the user and group creations and updates by an external policy
+/// service, which can reject or modify them. Enabled when `url` is set.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct ValidationWebhookOptions {
+    #[builder(default)]
+    pub url: Option<Url>,
+    /// Sent as the Authorization header to the webhook.
+    #[builder(default)]
+    pub authorization: Option<SecUtf8>,
+    #[builder(default = "5000")]
+    pub timeout_ms: u64,
+    /// Whether the changes are accepted when the webhook fails or times out, rather than refused.
+    #[builder(default = "false")]
+    pub fail_open: bool,
+}
+
+impl std::default::Default for ValidationWebhookOptions {
+    fn default() -> Self {
+        ValidationWebhookOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Chat services the admin alerts are sent to. Each one is enabled when all its fields are set.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct NotificationOptions {
+    /// Incoming webhook of a Slack (or compatible) channel.
+    #[builder(default)]
+    pub slack_webhook_url: Option<Url>,
+    #[builder(default)]
+    pub matrix_homeserver: Option<Url>,
+    #[builder(default)]
+    pub matrix_access_token: Option<SecUtf8>,
+    /// Internal ID of the room, e.g. "!abcdef:matrix.org".
+    #[builder(default)]
+    pub matrix_room_id: Option<String>,
+    #[builder(default)]
+    pub telegram_bot_token: Option<SecUtf8>,
+    #[builder(default)]
+    pub telegram_chat_id: Option<String>,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
 #[builder(pattern = "owned")]
 pub struct LdapsOptions {
@@ -74,6 +280,21 @@ pub struct LdapsOptions {
     pub cert_file: String,
     #[builder(default = r#"String::from("key.pem")"#)]
     pub key_file: String,
+    /// How many TLS sessions are kept for clients to resume them. 0 disables the resumption by
+    /// session ID.
+    #[builder(default = "256")]
+    pub session_cache_size: usize,
+    /// Whether to issue session tickets, so that clients can resume their session without the
+    /// server keeping it.
+    #[builder(default = "true")]
+    pub session_tickets: bool,
+    /// Whether clients of the plaintext LDAP port can upgrade their connection with StartTLS,
+    /// using the same certificate. It doesn't need the LDAPS listener to be enabled.
+    #[builder(default = "false")]
+    pub start_tls: bool,
+    /// Whether binds are refused on a connection that isn't encrypted with LDAPS or StartTLS.
+    #[builder(default = "false")]
+    pub require_tls_for_bind: bool,
 }
 
 impl std::default::Default for LdapsOptions {
@@ -82,6 +303,849 @@ impl std::default::Default for LdapsOptions {
     }
 }
 
+/// A local account whose connections to the LDAP socket are bound automatically.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LdapiPeerBind {
+    /// The Unix user ID of the connecting process.
+    pub uid: u32,
+    /// The LLDAP user that the connection is bound as.
+    pub user: UserId,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct LdapiOptions {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    #[builder(default = r#"String::from("/run/lldap/ldapi")"#)]
+    pub socket_path: String,
+    /// The permissions of the socket file: whoever can write to it can talk to the server.
+    #[builder(default = "0o660")]
+    pub socket_mode: u32,
+    /// The local accounts bound without a password, identified by the credentials of the
+    /// connecting process.
+    #[builder(default)]
+    pub peer_binds: Vec<LdapiPeerBind>,
+}
+
+impl std::default::Default for LdapiOptions {
+    fn default() -> Self {
+        LdapiOptionsBuilder::default().build().unwrap()
+    }
+}
+
+impl LdapiOptions {
+    /// The user that a connection from the given Unix user is bound as, if any.
+    pub fn get_peer_user(&self, uid: u32) -> Option<&UserId> {
+        self.peer_binds
+            .iter()
+            .find(|b| b.uid == uid)
+            .map(|b| &b.user)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct CacheOptions {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    #[builder(default = "60")]
+    pub ttl_seconds: u64,
+    #[builder(default = "1000")]
+    pub max_entries: usize,
+}
+
+impl std::default::Default for CacheOptions {
+    fn default() -> Self {
+        CacheOptionsBuilder::default().build().unwrap()
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct GeoIpOptions {
+    /// Path to a MaxMind-format country database (e.g. GeoLite2-Country.mmdb).
+    #[builder(default)]
+    pub country_database: Option<String>,
+    /// Path to a MaxMind-format ASN database (e.g. GeoLite2-ASN.mmdb).
+    #[builder(default)]
+    pub asn_database: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct AccountDeletionOptions {
+    /// Whether users can request the deletion of their own account.
+    #[builder(default = "false")]
+    pub enable_self_service: bool,
+    /// How long a confirmed deletion can still be cancelled by an admin.
+    #[builder(default = "30")]
+    pub grace_period_days: u32,
+}
+
+impl std::default::Default for AccountDeletionOptions {
+    fn default() -> Self {
+        AccountDeletionOptionsBuilder::default().build().unwrap()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct LoginThrottlingOptions {
+    /// Whether to lock out the users and addresses with too many failed logins.
+    #[builder(default = "true")]
+    pub enabled: bool,
+    /// The number of failed logins within the window that triggers a lockout.
+    #[builder(default = "5")]
+    pub max_failures: u32,
+    #[builder(default = "15")]
+    pub window_minutes: u32,
+    #[builder(default = "15")]
+    pub lockout_minutes: u32,
+}
+
+impl std::default::Default for LoginThrottlingOptions {
+    fn default() -> Self {
+        LoginThrottlingOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// A link shown on the login and password reset pages.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BrandingLink {
+    pub label: String,
+    pub url: Url,
+}
+
+/// Additions to the login and password reset pages, e.g. where to get help.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct BrandingOptions {
+    #[builder(default)]
+    pub login_links: Vec<BrandingLink>,
+    /// Plain text shown below the forms, e.g. who to contact.
+    #[builder(default)]
+    pub help_text: Option<String>,
+}
+
+impl std::default::Default for BrandingOptions {
+    fn default() -> Self {
+        BrandingOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// The lifetimes of the web sessions.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct SessionOptions {
+    /// The lifetime of the JWTs, renewed with the refresh token.
+    #[builder(default = "1440")]
+    pub access_token_minutes: u32,
+    #[builder(default = "30")]
+    pub refresh_token_days: u32,
+    /// The lifetime of the refresh token when the user ticks "Remember me".
+    #[builder(default = "90")]
+    pub remember_me_refresh_token_days: u32,
+    /// No session outlives this age, whatever the other lifetimes: the refresh tokens expire by
+    /// then, and the JWTs never outlive the refresh token they were renewed with.
+    #[builder(default = "90")]
+    pub max_session_age_days: u32,
+}
+
+impl std::default::Default for SessionOptions {
+    fn default() -> Self {
+        SessionOptionsBuilder::default().build().unwrap()
+    }
+}
+
+impl SessionOptions {
+    pub fn access_token_lifetime(&self) -> chrono::Duration {
+        chrono::Duration::minutes(self.access_token_minutes.into())
+    }
+
+    pub fn refresh_token_lifetime(&self, remember_me: bool) -> chrono::Duration {
+        let days = if remember_me {
+            self.remember_me_refresh_token_days
+        } else {
+            self.refresh_token_days
+        };
+        chrono::Duration::days(days.min(self.max_session_age_days).into())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct MaintenanceOptions {
+    /// Whether the server starts in maintenance mode, where only the admins can log in. The admins
+    /// can also toggle it at runtime.
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// Shown to the users whose login is refused.
+    #[builder(default = r#"String::from("LLDAP is under maintenance, please try again later.")"#)]
+    pub message: String,
+}
+
+impl std::default::Default for MaintenanceOptions {
+    fn default() -> Self {
+        MaintenanceOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// The hardening of the GraphQL endpoint, at `/api/graphql`.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct GraphqlOptions {
+    /// A JSON file mapping the hex SHA-256 hashes of the persisted queries to their text.
+    #[builder(default)]
+    pub persisted_queries_file: Option<String>,
+    /// Whether only the persisted queries can be sent. The queries of the web UI are always
+    /// persisted.
+    #[builder(default = "false")]
+    pub persisted_queries_only: bool,
+    /// Whether the requests authenticated with the session cookie must carry its CSRF token in the
+    /// `X-CSRF-Token` header, except for the read-only ones.
+    #[builder(default = "true")]
+    pub csrf_protection: bool,
+}
+
+impl std::default::Default for GraphqlOptions {
+    fn default() -> Self {
+        GraphqlOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// The challenge protecting the public forms (password reset, and optionally login) against bots.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptchaProvider {
+    #[default]
+    None,
+    #[serde(rename = "hcaptcha")]
+    HCaptcha,
+    /// Cloudflare Turnstile.
+    Turnstile,
+    /// A built-in proof of work solved by the browser, without any third-party service.
+    ProofOfWork,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct CaptchaOptions {
+    #[builder(default)]
+    pub provider: CaptchaProvider,
+    /// The public key of the hCaptcha or Turnstile widget.
+    #[builder(default)]
+    pub site_key: Option<String>,
+    #[builder(default)]
+    pub secret_key: Option<SecUtf8>,
+    /// The number of leading zero bits required by the proof of work.
+    #[builder(default = "20")]
+    pub difficulty: u8,
+    /// Whether the login forms require a solved challenge, and not only the password reset.
+    #[builder(default = "false")]
+    pub require_for_login: bool,
+}
+
+impl std::default::Default for CaptchaOptions {
+    fn default() -> Self {
+        CaptchaOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// The processing of the avatars uploaded through the API: they are converted to JPEG and scaled
+/// down.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct AvatarOptions {
+    /// Larger uploads are rejected.
+    #[builder(default = "2048")]
+    pub max_upload_size_kb: u64,
+    /// The larger images are scaled down to fit in a square of that size, in pixels.
+    #[builder(default = "512")]
+    pub max_dimension: u32,
+}
+
+impl std::default::Default for AvatarOptions {
+    fn default() -> Self {
+        AvatarOptionsBuilder::default().build().unwrap()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct AvatarSyncOptions {
+    /// Whether to fetch the avatars of the users that didn't upload one.
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// The avatar service: Gravatar, or a libravatar server.
+    #[builder(default = r#"String::from("https://www.gravatar.com/avatar/")"#)]
+    pub url: String,
+    #[builder(default = "24")]
+    pub refresh_interval_hours: u64,
+}
+
+impl std::default::Default for AvatarSyncOptions {
+    fn default() -> Self {
+        AvatarSyncOptionsBuilder::default().build().unwrap()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct LdapProxyOptions {
+    /// Whether to delegate the authentication and the user data to an upstream LDAP server.
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// The upstream server, as "host:port". Only plain LDAP is supported.
+    #[builder(default = r#"String::from("localhost:389")"#)]
+    pub server: String,
+    /// Service account used to read the users.
+    #[builder(default)]
+    pub bind_dn: String,
+    #[builder(default = r#"SecUtf8::from("")"#)]
+    pub bind_password: SecUtf8,
+    #[builder(default)]
+    pub user_base_dn: String,
+    #[builder(default = r#"String::from("person")"#)]
+    pub user_object_class: String,
+    /// Upstream attribute holding the user ID, e.g. "sAMAccountName" for Active Directory.
+    #[builder(default = r#"String::from("uid")"#)]
+    pub user_id_attribute: String,
+    /// How often the cached users are refreshed.
+    #[builder(default = "15")]
+    pub sync_interval_minutes: u64,
+}
+
+impl std::default::Default for LdapProxyOptions {
+    fn default() -> Self {
+        LdapProxyOptionsBuilder::default().build().unwrap()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct PamOptions {
+    /// Whether to check the password of `users` with PAM. Requires the `pam` feature.
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// The PAM service, i.e. the file in /etc/pam.d.
+    #[builder(default = r#"String::from("lldap")"#)]
+    pub service: String,
+    /// Users authenticated by PAM instead of their lldap password.
+    #[builder(default)]
+    pub users: Vec<UserId>,
+}
+
+impl std::default::Default for PamOptions {
+    fn default() -> Self {
+        PamOptionsBuilder::default().build().unwrap()
+    }
+}
+
+impl PamOptions {
+    pub fn is_pam_user(&self, user_id: &UserId) -> bool {
+        self.enabled && self.users.contains(user_id)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GroupPosixDefaults {
+    pub group: GroupName,
+    pub home_directory: Option<String>,
+    pub login_shell: Option<String>,
+    pub gid_number: Option<i64>,
+}
+
+/// Attribute naming the user entries in their DN, e.g. `uid=bob,ou=people,dc=example,dc=com`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UserRdnAttribute {
+    #[default]
+    Uid,
+    Cn,
+}
+
+impl UserRdnAttribute {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserRdnAttribute::Uid => "uid",
+            UserRdnAttribute::Cn => "cn",
+        }
+    }
+}
+
+/// The order of the attributes in the LDAP search results. The values of an attribute are always
+/// returned in the same order, e.g. the members sorted by user ID.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LdapAttributeOrder {
+    /// Sorted by name, ignoring the case.
+    #[default]
+    Alphabetical,
+    /// In the order the client listed them, followed by the ones selected with "*" or "+" sorted
+    /// by name.
+    Requested,
+}
+
+/// What to do when several users get the same display name, since some applications key the
+/// users by their `cn`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayNameUniqueness {
+    #[default]
+    Allow,
+    /// Accept the duplicate, and log a warning.
+    Warn,
+    /// Reject the creation or update of the user with a conflict error.
+    Enforce,
+}
+
+/// Which other users the regular users can see in the web UI and the GraphQL API.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DirectoryVisibility {
+    /// Only themselves.
+    #[default]
+    Nobody,
+    /// The members of their groups.
+    GroupMembers,
+    /// All the users, e.g. for a company directory.
+    Everyone,
+}
+
+/// Where the users and groups are stored.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// The database at `database_url`.
+    #[default]
+    Sql,
+    /// A fresh in-memory SQLite database, e.g. for tests. Requires the `memory-backend` feature.
+    Memory,
+}
+
+/// Caps on the values of an attribute in the LDAP search results.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LdapAttributeLimit {
+    pub attribute: AttributeName,
+    /// Extra values are left out, and the attribute is returned as `<name>;range=0-<n-1>`.
+    pub max_values: Option<usize>,
+    /// Bigger values (e.g. photos) are left out.
+    pub max_value_bytes: Option<usize>,
+}
+
+/// The entries that an attribute mapping applies to.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttributeMappingEntries {
+    #[default]
+    Users,
+    Groups,
+    All,
+}
+
+/// Serves an attribute under another name over LDAP, e.g. `{ name = "email", attribute = "mail" }`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LdapAttributeMapping {
+    /// The new name, as returned over LDAP.
+    pub name: String,
+    /// The attribute read for it, as named in LDAP or in the schema.
+    pub attribute: AttributeName,
+    #[serde(default)]
+    pub entries: AttributeMappingEntries,
+    /// Hides the original name from the entries: the attribute is only returned under the new
+    /// one. Filters on the original name still work.
+    #[serde(default)]
+    pub rename: bool,
+}
+
+impl LdapAttributeMapping {
+    pub fn attribute_name(&self) -> AttributeName {
+        AttributeName::from(self.name.as_str())
+    }
+
+    pub fn applies_to_users(&self) -> bool {
+        self.entries != AttributeMappingEntries::Groups
+    }
+
+    pub fn applies_to_groups(&self) -> bool {
+        self.entries != AttributeMappingEntries::Users
+    }
+}
+
+/// Puts the users whose field has the value in the group. The groups targeted by rules are
+/// managed by them: a member that matches none of the group's rules is removed.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GroupRule {
+    /// Recorded in the change feed with the memberships it changes.
+    pub name: String,
+    /// A user field, as named in LDAP or in the schema, e.g. "department" or "mail".
+    pub attribute: AttributeName,
+    /// Compared without case. One of the values of a list attribute has to match.
+    pub value: String,
+    pub group: GroupName,
+}
+
+/// A group that the new users join when they are created.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DefaultGroup {
+    pub group: GroupName,
+    /// Where the users have to come from to join, e.g. `["graphql", "ldap"]`. Any source if empty.
+    #[serde(default)]
+    pub sources: Vec<UserCreationSource>,
+}
+
+impl DefaultGroup {
+    pub fn applies_to(&self, source: UserCreationSource) -> bool {
+        self.sources.is_empty() || self.sources.contains(&source)
+    }
+}
+
+/// A regex that has to match the whole value, compiled when the configuration is loaded so that
+/// an invalid one is rejected at startup.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct AttributeRegex {
+    source: String,
+    regex: regex::Regex,
+}
+
+impl AttributeRegex {
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    pub fn is_match(&self, value: &str) -> bool {
+        self.regex.is_match(value)
+    }
+}
+
+impl TryFrom<String> for AttributeRegex {
+    type Error = regex::Error;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Ok(Self {
+            regex: regex::Regex::new(&format!("^(?:{})$", source))?,
+            source,
+        })
+    }
+}
+
+impl From<AttributeRegex> for String {
+    fn from(regex: AttributeRegex) -> Self {
+        regex.source
+    }
+}
+
+impl PartialEq for AttributeRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for AttributeRegex {}
+
+/// Why a value was rejected by an `AttributeValidationRule`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleViolation {
+    /// The option of the rule that failed: `regex`, `min`, `max` or `allowed_values`.
+    pub rule: &'static str,
+    pub message: String,
+}
+
+impl RuleViolation {
+    fn new(rule: &'static str, message: String) -> Self {
+        Self { rule, message }
+    }
+}
+
+/// Constraints on the values of a custom attribute, checked on every change.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AttributeValidationRule {
+    pub attribute: AttributeName,
+    /// The whole value has to match (string attributes).
+    pub regex: Option<AttributeRegex>,
+    /// Bounds on the value of integer attributes, or on the length of string attributes.
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+    /// The only accepted values (string attributes).
+    pub allowed_values: Option<Vec<String>>,
+}
+
+impl AttributeValidationRule {
+    fn check_bounds(&self, value: i64, what: &str) -> Result<(), RuleViolation> {
+        if let Some(min) = self.min.filter(|min| value < *min) {
+            return Err(RuleViolation::new(
+                "min",
+                format!("{} must be at least {}", what, min),
+            ));
+        }
+        if let Some(max) = self.max.filter(|max| value > *max) {
+            return Err(RuleViolation::new(
+                "max",
+                format!("{} must be at most {}", what, max),
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_string(&self, value: &str) -> Result<(), RuleViolation> {
+        if let Some(regex) = self.regex.as_ref().filter(|r| !r.is_match(value)) {
+            return Err(RuleViolation::new(
+                "regex",
+                format!("must match {}", regex.as_str()),
+            ));
+        }
+        self.check_bounds(value.chars().count() as i64, "the length")?;
+        if let Some(allowed_values) = &self.allowed_values {
+            if !allowed_values.iter().any(|v| v == value) {
+                return Err(RuleViolation::new(
+                    "allowed_values",
+                    format!("must be one of {}", allowed_values.join(", ")),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks a serialized attribute value, returning the reason why it is rejected.
+    pub fn check(
+        &self,
+        value: &types::Serialized,
+        attribute_type: AttributeType,
+        is_list: bool,
+    ) -> Result<(), RuleViolation> {
+        match (attribute_type, is_list) {
+            (AttributeType::String, false) => self.check_string(&value.unwrap::<String>()),
+            (AttributeType::String, true) => value
+                .unwrap::<Vec<String>>()
+                .iter()
+                .try_for_each(|v| self.check_string(v)),
+            (AttributeType::Integer, false) => {
+                self.check_bounds(value.unwrap::<i64>(), "the value")
+            }
+            (AttributeType::Integer, true) => value
+                .unwrap::<Vec<i64>>()
+                .into_iter()
+                .try_for_each(|v| self.check_bounds(v, "the value")),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct AttributeValidationOptions {
+    #[builder(default)]
+    pub users: Vec<AttributeValidationRule>,
+    #[builder(default)]
+    pub groups: Vec<AttributeValidationRule>,
+}
+
+/// A virtual user attribute, rendered at read time from the other fields of the user, e.g.
+/// `{ name = "mailNickname", template = "{lowercase(uid)}" }`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ComputedAttribute {
+    /// The name of the attribute, as returned over LDAP.
+    pub name: String,
+    /// `{field}` is replaced by the value of the field, `{lowercase(field)}` and
+    /// `{uppercase(field)}` change its case.
+    pub template: String,
+}
+
+impl ComputedAttribute {
+    pub fn attribute_name(&self) -> AttributeName {
+        AttributeName::from(self.name.as_str())
+    }
+}
+
+/// An extra entry served over LDAP, e.g. to add an `ou=policies` subtree.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StaticLdapEntry {
+    /// The full DN, under the base DN but outside of `ou=people` and `ou=groups`.
+    pub dn: String,
+    /// The values of each attribute, including `objectClass`.
+    pub attributes: BTreeMap<String, Vec<String>>,
+}
+
+/// An extra user attribute name, returned with the value of another attribute.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AttributeAlias {
+    pub alias: AttributeName,
+    pub attribute: AttributeName,
+}
+
+/// Adjustments of the LDAP entries to what a specific client expects.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct IntegrationProfile {
+    pub name: String,
+    /// Overrides `ldap_user_rdn`.
+    pub user_rdn: Option<UserRdnAttribute>,
+    pub user_object_classes: Vec<String>,
+    pub group_object_classes: Vec<String>,
+    pub user_attribute_aliases: Vec<AttributeAlias>,
+}
+
+impl IntegrationProfile {
+    fn builtin_profiles() -> Vec<IntegrationProfile> {
+        vec![
+            // DSM only lists the users and groups with the POSIX object classes.
+            IntegrationProfile {
+                name: "synology".to_owned(),
+                user_rdn: Some(UserRdnAttribute::Uid),
+                user_object_classes: vec!["shadowAccount".to_owned()],
+                group_object_classes: vec!["posixGroup".to_owned()],
+                ..Default::default()
+            },
+            // The Jellyfin LDAP plugin builds the bind DN from `uid`.
+            IntegrationProfile {
+                name: "jellyfin".to_owned(),
+                user_rdn: Some(UserRdnAttribute::Uid),
+                ..Default::default()
+            },
+            // Nextcloud's LDAP wizard offers the `groupOfNames` groups.
+            IntegrationProfile {
+                name: "nextcloud".to_owned(),
+                user_rdn: Some(UserRdnAttribute::Uid),
+                group_object_classes: vec!["groupOfNames".to_owned()],
+                ..Default::default()
+            },
+        ]
+    }
+
+    /// The attribute to read when the client asks for `attribute`.
+    pub fn resolve_user_attribute<'a>(&'a self, attribute: &'a AttributeName) -> &'a AttributeName {
+        self.user_attribute_aliases
+            .iter()
+            .find(|a| &a.alias == attribute)
+            .map(|a| &a.attribute)
+            .unwrap_or(attribute)
+    }
+}
+
+/// A service account, and the name of the integration profile applied to its LDAP sessions.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct IntegrationProfileAccount {
+    pub user: UserId,
+    pub profile: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct IntegrationProfilesOptions {
+    #[builder(default)]
+    pub accounts: Vec<IntegrationProfileAccount>,
+    /// Extra profiles, or replacements of the built-in ones with the same name.
+    #[builder(default)]
+    pub profiles: Vec<IntegrationProfile>,
+}
+
+impl IntegrationProfilesOptions {
+    pub fn get_profile(&self, name: &str) -> Option<IntegrationProfile> {
+        self.profiles
+            .iter()
+            .cloned()
+            .chain(IntegrationProfile::builtin_profiles())
+            .find(|p| p.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The profile of the service account, if it has one.
+    pub fn get_account_profile(&self, user_id: &UserId) -> Option<IntegrationProfile> {
+        self.accounts
+            .iter()
+            .find(|a| &a.user == user_id)
+            .and_then(|a| self.get_profile(&a.profile))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for account in &self.accounts {
+            if self.get_profile(&account.profile).is_none() {
+                bail!(
+                    r#"Unknown integration profile "{}" for the account "{}""#,
+                    account.profile,
+                    account.user
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Values returned over LDAP for `homeDirectory`, `loginShell` and `gidNumber` when the user has
+/// none stored. `{uid}` and `{email}` are replaced by the user's values.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct PosixDefaultsOptions {
+    #[builder(default)]
+    pub home_directory: Option<String>,
+    #[builder(default)]
+    pub login_shell: Option<String>,
+    /// The primary group of the users, usually the `gidNumber` of a "users" group.
+    #[builder(default)]
+    pub gid_number: Option<i64>,
+    /// Per-group overrides: the first entry matching one of the user's groups wins.
+    #[builder(default)]
+    pub groups: Vec<GroupPosixDefaults>,
+    /// The first `uidNumber` given to the users, then the highest one plus one.
+    #[builder(default = "10000")]
+    pub uid_number_start: i64,
+    /// The first `gidNumber` given to the groups, then the highest one plus one.
+    #[builder(default = "10000")]
+    pub gid_number_start: i64,
+}
+
+impl std::default::Default for PosixDefaultsOptions {
+    fn default() -> Self {
+        PosixDefaultsOptionsBuilder::default().build().unwrap()
+    }
+}
+
+impl PosixDefaultsOptions {
+    /// The LDAP attributes that have a default, for some users at least.
+    pub fn attributes(&self) -> Vec<&'static str> {
+        let mut attributes = Vec::new();
+        if self.home_directory.is_some() || self.groups.iter().any(|g| g.home_directory.is_some()) {
+            attributes.push("homeDirectory");
+        }
+        if self.login_shell.is_some() || self.groups.iter().any(|g| g.login_shell.is_some()) {
+            attributes.push("loginShell");
+        }
+        if self.gid_number.is_some() || self.groups.iter().any(|g| g.gid_number.is_some()) {
+            attributes.push("gidNumber");
+        }
+        attributes
+    }
+
+    pub fn get_gid_number(&self, groups: &[GroupDetails]) -> Option<i64> {
+        self.groups
+            .iter()
+            .filter(|d| groups.iter().any(|g| g.display_name == d.group))
+            .find_map(|d| d.gid_number)
+            .or(self.gid_number)
+    }
+
+    /// Whether the user's groups are needed to find the defaults.
+    pub fn depends_on_groups(&self) -> bool {
+        !self.groups.is_empty()
+    }
+
+    pub fn get_template(&self, attribute: &AttributeName, groups: &[GroupDetails]) -> Option<&str> {
+        let (default, select): (_, fn(&GroupPosixDefaults) -> Option<&str>) =
+            match attribute.as_str() {
+                "homedirectory" => (&self.home_directory, |g| g.home_directory.as_deref()),
+                "loginshell" => (&self.login_shell, |g| g.login_shell.as_deref()),
+                _ => return None,
+            };
+        self.groups
+            .iter()
+            .filter(|d| groups.iter().any(|g| g.display_name == d.group))
+            .find_map(select)
+            .or(default.as_deref())
+    }
+}
+
 #[derive(Clone, Deserialize, Serialize, derive_more::Debug)]
 #[debug(r#""{_0}""#)]
 pub struct HttpUrl(pub Url);
@@ -93,6 +1157,22 @@ pub struct Configuration {
     pub ldap_host: String,
     #[builder(default = "3890")]
     pub ldap_port: u16,
+    #[builder(default = "64")]
+    pub ldap_max_concurrent_operations: usize,
+    /// LDAP operations taking longer than this are logged with their details.
+    #[builder(default)]
+    pub ldap_slow_operation_threshold_ms: Option<u64>,
+    /// The maximum number of entries returned by an LDAP search, 0 for no limit.
+    #[builder(default = "0")]
+    pub ldap_search_size_limit: usize,
+    /// The maximum duration of an LDAP search, 0 for no limit.
+    #[builder(default = "0")]
+    pub ldap_search_time_limit_seconds: u64,
+    /// Logs why the LDAP searches that found nothing could not match, e.g. an unknown attribute.
+    #[builder(default = "false")]
+    pub ldap_filter_debug: bool,
+    #[builder(default = "1")]
+    pub server_workers: usize,
     #[builder(default = r#"String::from("0.0.0.0")"#)]
     pub http_host: String,
     #[builder(default = "17170")]
@@ -101,6 +1181,8 @@ pub struct Configuration {
     pub jwt_secret: SecUtf8,
     #[builder(default = r#"String::from("dc=example,dc=com")"#)]
     pub ldap_base_dn: String,
+    #[builder(default)]
+    pub ldap_user_rdn: UserRdnAttribute,
     #[builder(default = r#"UserId::new("admin")"#)]
     pub ldap_user_dn: UserId,
     #[builder(default)]
@@ -111,12 +1193,24 @@ pub struct Configuration {
     pub force_ldap_user_pass_reset: TrueFalseAlways,
     #[builder(default = "false")]
     pub force_update_private_key: bool,
+    #[builder(default)]
+    pub backend: BackendKind,
     #[builder(default = r#"DatabaseUrl::from("sqlite://users.db?mode=rwc")"#)]
     pub database_url: DatabaseUrl,
     #[builder(default)]
     pub ignored_user_attributes: Vec<AttributeName>,
     #[builder(default)]
     pub ignored_group_attributes: Vec<AttributeName>,
+    #[builder(default = "30")]
+    pub change_feed_retention_days: u32,
+    #[builder(default = "false")]
+    pub four_eyes_approval: bool,
+    #[builder(default = "false")]
+    pub read_only: bool,
+    #[builder(default)]
+    pub display_name_uniqueness: DisplayNameUniqueness,
+    #[builder(default)]
+    pub directory_visibility: DirectoryVisibility,
     #[builder(default = "false")]
     pub verbose: bool,
     #[builder(default = r#"String::from("server_key")"#)]
@@ -128,7 +1222,73 @@ pub struct Configuration {
     #[builder(default)]
     pub smtp_options: MailOptions,
     #[builder(default)]
+    pub password_reset_options: PasswordResetOptions,
+    #[builder(default)]
+    pub notification_options: NotificationOptions,
+    #[builder(default)]
+    pub expiry_monitoring_options: ExpiryMonitoringOptions,
+    #[builder(default)]
+    pub metrics_options: MetricsOptions,
+    #[builder(default)]
+    pub quota_options: QuotaOptions,
+    #[builder(default)]
+    pub diagnostics_options: DiagnosticsOptions,
+    #[builder(default)]
     pub ldaps_options: LdapsOptions,
+    #[builder(default)]
+    pub ldapi_options: LdapiOptions,
+    #[builder(default)]
+    pub cache_options: CacheOptions,
+    #[builder(default)]
+    pub geoip_options: GeoIpOptions,
+    #[builder(default)]
+    pub account_deletion_options: AccountDeletionOptions,
+    #[builder(default)]
+    pub login_throttling_options: LoginThrottlingOptions,
+    #[builder(default)]
+    pub session_options: SessionOptions,
+    #[builder(default)]
+    pub branding_options: BrandingOptions,
+    #[builder(default)]
+    pub validation_webhook_options: ValidationWebhookOptions,
+    #[builder(default)]
+    pub captcha_options: CaptchaOptions,
+    #[builder(default)]
+    pub maintenance_options: MaintenanceOptions,
+    #[builder(default)]
+    pub graphql_options: GraphqlOptions,
+    #[builder(default)]
+    pub avatar_options: AvatarOptions,
+    #[builder(default)]
+    pub avatar_sync_options: AvatarSyncOptions,
+    #[builder(default)]
+    pub provisioning_webhook_options: ProvisioningWebhookOptions,
+    #[builder(default)]
+    pub ldap_proxy_options: LdapProxyOptions,
+    #[builder(default)]
+    pub pam_options: PamOptions,
+    #[builder(default)]
+    pub posix_defaults: PosixDefaultsOptions,
+    #[builder(default)]
+    pub integration_profiles: IntegrationProfilesOptions,
+    #[builder(default)]
+    pub ldap_attribute_limits: Vec<LdapAttributeLimit>,
+    #[builder(default)]
+    pub ldap_attribute_mappings: Vec<LdapAttributeMapping>,
+    #[builder(default)]
+    pub attribute_validation: AttributeValidationOptions,
+    #[builder(default)]
+    pub group_rules: Vec<GroupRule>,
+    #[builder(default)]
+    pub default_groups: Vec<DefaultGroup>,
+    #[builder(default)]
+    pub computed_user_attributes: Vec<ComputedAttribute>,
+    #[builder(default)]
+    pub static_ldap_entries: Vec<StaticLdapEntry>,
+    #[builder(default = "false")]
+    pub ldap_active_directory_compatibility: bool,
+    #[builder(default)]
+    pub ldap_attribute_order: LdapAttributeOrder,
     #[builder(default = r#"HttpUrl(Url::parse("http://localhost").unwrap())"#)]
     pub http_url: HttpUrl,
     #[debug(skip)]
@@ -407,6 +1567,24 @@ impl TopLevelCommandOpts for RunOpts {
     }
 }
 
+impl TopLevelCommandOpts for SeedOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.general_config
+    }
+}
+
+impl TopLevelCommandOpts for ImportKeycloakOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.general_config
+    }
+}
+
+impl TopLevelCommandOpts for VerifyBackupOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.general_config
+    }
+}
+
 impl TopLevelCommandOpts for TestEmailOpts {
     fn general_config(&self) -> &GeneralConfigOpts {
         &self.general_config
@@ -453,6 +1631,36 @@ impl ConfigOverrider for RunOpts {
     }
 }
 
+impl ConfigOverrider for SeedOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.general_config.override_config(config);
+        if let Some(database_url) = self.database_url.as_ref() {
+            config.database_url = database_url.clone();
+        }
+    }
+}
+
+impl ConfigOverrider for ImportKeycloakOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.general_config.override_config(config);
+        if let Some(database_url) = self.database_url.as_ref() {
+            config.database_url = database_url.clone();
+        }
+    }
+}
+
+impl ConfigOverrider for VerifyBackupOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.general_config.override_config(config);
+        if let Some(path) = self.server_key_file.as_ref() {
+            config.key_file = path.to_string();
+        }
+        if let Some(seed) = self.server_key_seed.as_ref() {
+            config.key_seed = Some(SecUtf8::from(seed));
+        }
+    }
+}
+
 impl ConfigOverrider for TestEmailOpts {
     fn override_config(&self, config: &mut Configuration) {
         self.general_config.override_config(config);
@@ -626,6 +1834,135 @@ mod tests {
     use figment::Jail;
     use pretty_assertions::assert_eq;
 
+    #[test]
+    fn test_integration_profiles() {
+        let options = IntegrationProfilesOptions {
+            accounts: vec![
+                IntegrationProfileAccount {
+                    user: UserId::new("nas"),
+                    profile: "Synology".to_owned(),
+                },
+                IntegrationProfileAccount {
+                    user: UserId::new("cloud"),
+                    profile: "nextcloud".to_owned(),
+                },
+            ],
+            profiles: vec![IntegrationProfile {
+                name: "nextcloud".to_owned(),
+                user_object_classes: vec!["nextcloudUser".to_owned()],
+                ..Default::default()
+            }],
+        };
+        options.validate().unwrap();
+        assert_eq!(
+            options
+                .get_account_profile(&UserId::new("NAS"))
+                .unwrap()
+                .group_object_classes,
+            vec!["posixGroup".to_owned()]
+        );
+        // The configured profiles replace the built-in ones.
+        assert_eq!(
+            options
+                .get_account_profile(&UserId::new("cloud"))
+                .unwrap()
+                .user_object_classes,
+            vec!["nextcloudUser".to_owned()]
+        );
+        assert_eq!(options.get_account_profile(&UserId::new("bob")), None);
+        assert!(IntegrationProfilesOptions {
+            accounts: vec![IntegrationProfileAccount {
+                user: UserId::new("bob"),
+                profile: "unknown".to_owned(),
+            }],
+            profiles: Vec::new(),
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_session_lifetimes() {
+        let options = SessionOptionsBuilder::default()
+            .refresh_token_days(7)
+            .remember_me_refresh_token_days(365)
+            .build()
+            .unwrap();
+        assert_eq!(
+            options.refresh_token_lifetime(false),
+            chrono::Duration::days(7)
+        );
+        // Capped by the maximum session age.
+        assert_eq!(
+            options.refresh_token_lifetime(true),
+            chrono::Duration::days(90)
+        );
+        assert_eq!(options.access_token_lifetime(), chrono::Duration::days(1));
+    }
+
+    #[test]
+    fn test_attribute_validation_rule() {
+        let rule = AttributeValidationRule {
+            attribute: "department".into(),
+            regex: Some("[A-Z]+".to_owned().try_into().unwrap()),
+            max: Some(4),
+            ..Default::default()
+        };
+        let check = |value: &str| {
+            rule.check(
+                &types::Serialized::from(value),
+                AttributeType::String,
+                false,
+            )
+        };
+        assert_eq!(check("IT"), Ok(()));
+        assert_eq!(
+            check("IT2"),
+            Err(RuleViolation::new("regex", "must match [A-Z]+".to_owned()))
+        );
+        assert_eq!(
+            check("SALES"),
+            Err(RuleViolation::new(
+                "max",
+                "the length must be at most 4".to_owned()
+            ))
+        );
+        assert_eq!(
+            rule.check(
+                &types::Serialized::from(&vec!["IT".to_owned(), "HR".to_owned()]),
+                AttributeType::String,
+                true
+            ),
+            Ok(())
+        );
+        let rule = AttributeValidationRule {
+            attribute: "floor".into(),
+            min: Some(0),
+            allowed_values: Some(vec!["unused".to_owned()]),
+            ..Default::default()
+        };
+        assert_eq!(
+            rule.check(
+                &types::Serialized::from(&-1i64),
+                AttributeType::Integer,
+                false
+            ),
+            Err(RuleViolation::new(
+                "min",
+                "the value must be at least 0".to_owned()
+            ))
+        );
+        // The regexes are compiled when loading the configuration.
+        assert!(serde_json::from_str::<AttributeValidationRule>(
+            r#"{"attribute": "department", "regex": "[A-Z]+"}"#
+        )
+        .is_ok());
+        assert!(serde_json::from_str::<AttributeValidationRule>(
+            r#"{"attribute": "department", "regex": "("}"#
+        )
+        .is_err());
+    }
+
     #[test]
     fn check_generated_server_key() {
         assert_eq!(
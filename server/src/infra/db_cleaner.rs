@@ -1,21 +1,50 @@
-use crate::domain::{
-    model::{self, JwtRefreshStorageColumn, JwtStorageColumn, PasswordResetTokensColumn},
-    sql_tables::DbConnection,
+use crate::{
+    domain::{
+        handler::{AccountDeletionBackendHandler, MembershipExpiryBackendHandler},
+        model::{
+            self, ChangeFeedColumn, JwtRefreshStorageColumn, JwtStorageColumn,
+            LoginThrottlesColumn, PasswordResetTokensColumn, WebhookDeliveriesColumn,
+        },
+        sql_tables::DbConnection,
+    },
+    infra::{
+        leader_election::LeaderElection,
+        notifications::{AlertKind, Notifier},
+    },
 };
 use actix::prelude::{Actor, AsyncContext, Context};
 use cron::Schedule;
 use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
 use std::{str::FromStr, time::Duration};
-use tracing::{error, info, instrument};
+use tracing::{debug, error, info, instrument};
+
+// Name of the lease in the leader election.
+const JOB_NAME: &str = "db_cleaner";
+// Extra time on top of the interval between runs before the lease expires, so that the leader
+// has time to renew it.
+const LEASE_MARGIN: Duration = Duration::from_secs(5 * 60);
 
 // Define actor
-pub struct Scheduler {
+pub struct Scheduler<Backend> {
     schedule: Schedule,
     sql_pool: DbConnection,
+    backend_handler: Backend,
+    leader_election: LeaderElection,
+    change_feed_retention: chrono::Duration,
+    notifier: Notifier,
 }
 
 // Provide Actor implementation for our actor
-impl Actor for Scheduler {
+impl<Backend> Actor for Scheduler<Backend>
+where
+    Backend: AccountDeletionBackendHandler
+        + MembershipExpiryBackendHandler
+        + Clone
+        + Send
+        + Sync
+        + Unpin
+        + 'static,
+{
     type Context = Context<Self>;
 
     fn started(&mut self, context: &mut Context<Self>) {
@@ -31,14 +60,44 @@ impl Actor for Scheduler {
     }
 }
 
-impl Scheduler {
-    pub fn new(cron_expression: &str, sql_pool: DbConnection) -> Self {
+impl<Backend> Scheduler<Backend>
+where
+    Backend: AccountDeletionBackendHandler
+        + MembershipExpiryBackendHandler
+        + Clone
+        + Send
+        + Sync
+        + Unpin
+        + 'static,
+{
+    pub fn new(
+        cron_expression: &str,
+        sql_pool: DbConnection,
+        backend_handler: Backend,
+        change_feed_retention: chrono::Duration,
+        notifier: Notifier,
+    ) -> Self {
         let schedule = Schedule::from_str(cron_expression).unwrap();
-        Self { schedule, sql_pool }
+        let leader_election = LeaderElection::new(sql_pool.clone());
+        Self {
+            schedule,
+            sql_pool,
+            backend_handler,
+            leader_election,
+            change_feed_retention,
+            notifier,
+        }
     }
 
     fn schedule_task(&self, ctx: &mut Context<Self>) {
-        let future = actix::fut::wrap_future::<_, Self>(Self::cleanup_db(self.sql_pool.clone()));
+        let future = actix::fut::wrap_future::<_, Self>(Self::cleanup_db_if_leader(
+            self.sql_pool.clone(),
+            self.backend_handler.clone(),
+            self.leader_election.clone(),
+            self.duration_until_next() + LEASE_MARGIN,
+            self.change_feed_retention,
+            self.notifier.clone(),
+        ));
         ctx.spawn(future);
 
         ctx.run_later(self.duration_until_next(), move |this, ctx| {
@@ -46,8 +105,28 @@ impl Scheduler {
         });
     }
 
+    // With several instances sharing the DB, only one of them runs the cleanup.
+    async fn cleanup_db_if_leader(
+        sql_pool: DbConnection,
+        backend_handler: Backend,
+        leader_election: LeaderElection,
+        lease: Duration,
+        change_feed_retention: chrono::Duration,
+        notifier: Notifier,
+    ) {
+        match leader_election.try_acquire(JOB_NAME, lease).await {
+            Ok(true) => {
+                Self::cleanup_db(sql_pool, change_feed_retention).await;
+                Self::purge_deleted_accounts(backend_handler.clone()).await;
+                Self::purge_expired_memberships(backend_handler, notifier).await;
+            }
+            Ok(false) => debug!("Another instance is running the DB cleanup"),
+            Err(e) => error!("DB error while acquiring the DB cleanup lease: {}", e),
+        }
+    }
+
     #[instrument(skip_all)]
-    async fn cleanup_db(sql_pool: DbConnection) {
+    async fn cleanup_db(sql_pool: DbConnection, change_feed_retention: chrono::Duration) {
         if let Err(e) = model::JwtRefreshStorage::delete_many()
             .filter(JwtRefreshStorageColumn::ExpiryDate.lt(chrono::Utc::now().naive_utc()))
             .exec(&sql_pool)
@@ -69,6 +148,64 @@ impl Scheduler {
         {
             error!("DB error while cleaning up password reset tokens: {}", e);
         };
+        if let Err(e) = model::LoginThrottles::delete_many()
+            .filter(LoginThrottlesColumn::ExpiryDate.lt(chrono::Utc::now().naive_utc()))
+            .exec(&sql_pool)
+            .await
+        {
+            error!("DB error while cleaning up the login throttling: {}", e);
+        };
+        if let Err(e) = model::WebhookDeliveries::delete_many()
+            .filter(WebhookDeliveriesColumn::ExpiryDate.lt(chrono::Utc::now().naive_utc()))
+            .exec(&sql_pool)
+            .await
+        {
+            error!("DB error while cleaning up the webhook deliveries: {}", e);
+        };
+        if let Err(e) = model::ChangeFeed::delete_many()
+            .filter(
+                ChangeFeedColumn::ChangeDate
+                    .lt(chrono::Utc::now().naive_utc() - change_feed_retention),
+            )
+            .exec(&sql_pool)
+            .await
+        {
+            error!("DB error while cleaning up the change feed: {}", e);
+        };
+    }
+
+    #[instrument(skip_all)]
+    async fn purge_deleted_accounts(backend_handler: Backend) {
+        match backend_handler.purge_deleted_accounts().await {
+            Ok(deleted) if !deleted.is_empty() => {
+                info!(
+                    "Deleted the accounts after their grace period: {:?}",
+                    deleted
+                )
+            }
+            Ok(_) => {}
+            Err(e) => error!("DB error while purging deleted accounts: {}", e),
+        }
+    }
+
+    #[instrument(skip_all)]
+    async fn purge_expired_memberships(backend_handler: Backend, notifier: Notifier) {
+        match backend_handler.purge_expired_memberships().await {
+            Ok(removed) => {
+                for (user_id, group_name) in removed {
+                    notifier
+                        .alert(
+                            AlertKind::MembershipExpiry,
+                            format!(
+                                r#"The membership of "{}" in "{}" expired"#,
+                                user_id, group_name
+                            ),
+                        )
+                        .await;
+                }
+            }
+            Err(e) => error!("DB error while purging expired memberships: {}", e),
+        }
     }
 
     fn duration_until_next(&self) -> Duration {
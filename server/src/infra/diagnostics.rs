@@ -0,0 +1,372 @@
+use crate::{
+    domain::handler::BackendHandler,
+    infra::{
+        auth_service::check_if_token_is_valid,
+        configuration::Configuration,
+        tcp_server::{error_to_http_response, AppState, TcpError, TcpResult},
+    },
+};
+use actix_web::{web, HttpResponse};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, Statement};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tracing::{error, info, instrument, warn};
+
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(5);
+const SLOW_DATABASE: Duration = Duration::from_millis(100);
+// Beyond that, the tokens and the TOTP codes start to fail in confusing ways.
+const MAX_CLOCK_SKEW_SECONDS: i64 = 30;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A problem found by a check, with what to do about it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub check: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub hint: String,
+}
+
+impl Finding {
+    pub(crate) fn new(
+        check: &'static str,
+        severity: Severity,
+        message: String,
+        hint: &str,
+    ) -> Self {
+        Self {
+            check,
+            severity,
+            message,
+            hint: hint.to_owned(),
+        }
+    }
+}
+
+/// The findings of the checks run at startup. Empty when everything looks fine.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct DiagnosticsReport {
+    pub checked_at: Option<DateTime<Utc>>,
+    pub findings: Vec<Finding>,
+}
+
+impl DiagnosticsReport {
+    pub fn log(&self) {
+        if self.findings.is_empty() {
+            info!("Startup diagnostics: no problem found");
+        }
+        for finding in &self.findings {
+            match finding.severity {
+                Severity::Warning => warn!(
+                    "Diagnostics [{}]: {} Hint: {}",
+                    finding.check, finding.message, finding.hint
+                ),
+                Severity::Error => error!(
+                    "Diagnostics [{}]: {} Hint: {}",
+                    finding.check, finding.message, finding.hint
+                ),
+            }
+        }
+    }
+}
+
+async fn check_public_url(url: &url::Url) -> Option<Finding> {
+    let host = match url.host_str() {
+        Some(host) => host,
+        None => {
+            return Some(Finding::new(
+                "public_url",
+                Severity::Error,
+                format!("http_url `{}` has no host.", url),
+                "Set http_url to the URL the users open in their browser.",
+            ))
+        }
+    };
+    if host == "localhost" || host.starts_with("127.") || host == "[::1]" {
+        return Some(Finding::new(
+            "public_url",
+            Severity::Warning,
+            format!("http_url `{}` points to the local machine.", url),
+            "Set http_url to the public URL, the links in the emails use it.",
+        ));
+    }
+    let port = url.port_or_known_default().unwrap_or(80);
+    match tokio::time::timeout(NETWORK_TIMEOUT, tokio::net::lookup_host((host, port))).await {
+        Ok(Ok(_)) => None,
+        Ok(Err(e)) => Some(Finding::new(
+            "public_url",
+            Severity::Warning,
+            format!("Could not resolve the host of http_url `{}`: {}", url, e),
+            "Check the DNS records, or the DNS server of the container.",
+        )),
+        Err(_) => Some(Finding::new(
+            "public_url",
+            Severity::Warning,
+            format!("Timed out resolving the host of http_url `{}`.", url),
+            "Check the DNS server of the machine or container.",
+        )),
+    }
+}
+
+async fn check_smtp(config: &Configuration) -> Option<Finding> {
+    let options = &config.smtp_options;
+    if !options.enable_password_reset {
+        return None;
+    }
+    let address = (options.server.as_str(), options.port);
+    let error = match tokio::time::timeout(NETWORK_TIMEOUT, tokio::net::TcpStream::connect(address))
+        .await
+    {
+        Ok(Ok(_)) => return None,
+        Ok(Err(e)) => e.to_string(),
+        Err(_) => "timed out".to_owned(),
+    };
+    Some(Finding::new(
+        "smtp",
+        Severity::Error,
+        format!(
+            "Could not connect to the SMTP server {}:{}: {}",
+            options.server, options.port, error
+        ),
+        "Check smtp_options.server and smtp_options.port, and the outgoing firewall rules. \
+         Many hosting providers block the port 25.",
+    ))
+}
+
+/// Measures a round trip to the database, and returns the database server's clock.
+async fn query_database_time(sql_pool: &DatabaseConnection) -> Result<(Duration, i64)> {
+    let backend = sql_pool.get_database_backend();
+    let query = match backend {
+        DbBackend::Postgres => "SELECT CAST(EXTRACT(EPOCH FROM NOW()) AS BIGINT)",
+        DbBackend::MySql => "SELECT CAST(UNIX_TIMESTAMP() AS SIGNED)",
+        DbBackend::Sqlite => "SELECT CAST(strftime('%s', 'now') AS INTEGER)",
+    };
+    let start = Instant::now();
+    let result = sql_pool
+        .query_one(Statement::from_string(backend, query.to_owned()))
+        .await?
+        .ok_or_else(|| anyhow!("No result"))?;
+    let latency = start.elapsed();
+    Ok((latency, result.try_get_by_index(0)?))
+}
+
+fn check_database_latency(latency: Duration) -> Option<Finding> {
+    (latency > SLOW_DATABASE).then(|| {
+        Finding::new(
+            "database",
+            Severity::Warning,
+            format!("A trivial query to the database took {:?}.", latency),
+            "Check the network between LLDAP and the database, or the load of the database.",
+        )
+    })
+}
+
+fn check_clock_skew(database_time: i64, now: i64) -> Option<Finding> {
+    let skew = now - database_time;
+    (skew.abs() > MAX_CLOCK_SKEW_SECONDS).then(|| {
+        Finding::new(
+            "clock",
+            Severity::Warning,
+            format!(
+                "The clock differs from the database server's by {} seconds.",
+                skew
+            ),
+            "Synchronize the clocks with NTP, the expiry of the tokens depends on them.",
+        )
+    })
+}
+
+async fn check_database(sql_pool: &DatabaseConnection) -> Vec<Finding> {
+    match query_database_time(sql_pool).await {
+        Ok((latency, database_time)) => check_database_latency(latency)
+            .into_iter()
+            .chain(check_clock_skew(database_time, Utc::now().timestamp()))
+            .collect(),
+        Err(e) => vec![Finding::new(
+            "database",
+            Severity::Error,
+            format!("Could not query the database: {:#}", e),
+            "Check database_url and the logs of the database.",
+        )],
+    }
+}
+
+fn check_permissions(file: &str, mode: u32) -> Option<Finding> {
+    (mode & 0o077 != 0).then(|| {
+        Finding::new(
+            "key_files",
+            Severity::Warning,
+            format!(
+                "{} is accessible to other users (mode {:o}).",
+                file,
+                mode & 0o777
+            ),
+            "Restrict it to the user running LLDAP, e.g. with `chmod 600`.",
+        )
+    })
+}
+
+/// The files holding secrets: the server key, the LDAPS key and the JWT secret.
+fn get_key_files(config: &Configuration) -> Vec<String> {
+    let mut files = Vec::new();
+    if config
+        .key_seed
+        .as_ref()
+        .map_or(true, |seed| seed.unsecure().is_empty())
+    {
+        files.push(config.key_file.clone());
+    }
+    if config.ldaps_options.enabled || config.ldaps_options.start_tls {
+        files.push(config.ldaps_options.key_file.clone());
+    }
+    files.extend(std::env::var("LLDAP_JWT_SECRET_FILE").ok());
+    files.retain(|f| !f.is_empty());
+    files
+}
+
+#[cfg(unix)]
+fn check_key_files(config: &Configuration) -> Vec<Finding> {
+    use std::os::unix::fs::PermissionsExt;
+    get_key_files(config)
+        .into_iter()
+        .filter_map(|file| {
+            let mode = std::fs::metadata(&file).ok()?.permissions().mode();
+            check_permissions(&file, mode)
+        })
+        .collect()
+}
+
+#[cfg(not(unix))]
+fn check_key_files(_config: &Configuration) -> Vec<Finding> {
+    Vec::new()
+}
+
+/// Runs all the checks concurrently.
+#[instrument(skip_all, level = "info")]
+pub async fn run(config: &Configuration, sql_pool: &DatabaseConnection) -> DiagnosticsReport {
+    let (public_url, smtp, database) = tokio::join!(
+        check_public_url(&config.http_url.0),
+        check_smtp(config),
+        check_database(sql_pool),
+    );
+    DiagnosticsReport {
+        checked_at: Some(Utc::now()),
+        findings: public_url
+            .into_iter()
+            .chain(smtp)
+            .chain(database)
+            .chain(check_key_files(config))
+            .collect(),
+    }
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn get_diagnostics<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+) -> TcpResult<DiagnosticsReport>
+where
+    Backend: BackendHandler + 'static,
+{
+    let validation_result = check_if_token_is_valid(&data, bearer.token())
+        .map_err(|e| TcpError::UnauthorizedError(e.to_string()))?;
+    if !validation_result.is_admin() {
+        return Err(TcpError::UnauthorizedError(
+            "The diagnostics are only available to admins".to_owned(),
+        ));
+    }
+    Ok(data.diagnostics.as_ref().clone())
+}
+
+async fn get_diagnostics_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+) -> HttpResponse
+where
+    Backend: BackendHandler + 'static,
+{
+    get_diagnostics(data, bearer)
+        .await
+        .map(|report| HttpResponse::Ok().json(report))
+        .unwrap_or_else(error_to_http_response)
+}
+
+pub fn configure_endpoint<Backend>(cfg: &mut web::ServiceConfig)
+where
+    Backend: BackendHandler + 'static,
+{
+    cfg.service(
+        web::resource("/diagnostics").route(web::get().to(get_diagnostics_handler::<Backend>)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_check_public_url() {
+        let finding = check_public_url(&"http://localhost:17170".parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(finding.check, "public_url");
+        assert_eq!(finding.severity, Severity::Warning);
+        assert_eq!(
+            check_public_url(&"https://127.0.0.1".parse().unwrap())
+                .await
+                .map(|f| f.severity),
+            Some(Severity::Warning)
+        );
+    }
+
+    #[test]
+    fn test_check_database_latency() {
+        assert_eq!(check_database_latency(Duration::from_millis(3)), None);
+        assert_eq!(
+            check_database_latency(Duration::from_millis(300)).map(|f| f.check),
+            Some("database")
+        );
+    }
+
+    #[test]
+    fn test_check_clock_skew() {
+        assert_eq!(check_clock_skew(1_000, 1_010), None);
+        assert_eq!(check_clock_skew(1_000, 970), None);
+        let finding = check_clock_skew(1_000, 1_120).unwrap();
+        assert_eq!(
+            finding.message,
+            "The clock differs from the database server's by 120 seconds."
+        );
+        assert!(check_clock_skew(1_000, 900).is_some());
+    }
+
+    #[test]
+    fn test_check_permissions() {
+        assert_eq!(check_permissions("server_key", 0o100600), None);
+        assert_eq!(check_permissions("server_key", 0o100400), None);
+        let finding = check_permissions("server_key", 0o100644).unwrap();
+        assert_eq!(
+            finding.message,
+            "server_key is accessible to other users (mode 644)."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_database() {
+        let mut sql_opt = sea_orm::ConnectOptions::new("sqlite::memory:".to_owned());
+        sql_opt.max_connections(1).sqlx_logging(false);
+        let sql_pool = sea_orm::Database::connect(sql_opt).await.unwrap();
+        let (_, database_time) = query_database_time(&sql_pool).await.unwrap();
+        assert!((Utc::now().timestamp() - database_time).abs() <= 1);
+    }
+}
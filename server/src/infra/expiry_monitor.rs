@@ -0,0 +1,271 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use actix::prelude::{Actor, AsyncContext, Context};
+use anyhow::{anyhow, Context as AnyhowContext, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    domain::sql_tables::DbConnection,
+    infra::{
+        configuration::{Configuration, ExpiryMonitoringOptions},
+        leader_election::LeaderElection,
+        notifications::{AlertKind, Notifier},
+    },
+};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+// Name of the lease in the leader election.
+const JOB_NAME: &str = "expiry_monitor";
+// Extra time on top of the interval between checks before the lease expires.
+const LEASE_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+/// The expiry date of the certificate and the last changes of the keys, as of the last check.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExpiryStatus {
+    pub checked_at: Option<DateTime<Utc>>,
+    pub ldaps_certificate_not_after: Option<DateTime<Utc>>,
+    /// Last modification of the server key file, which encrypts the passwords.
+    pub private_key_modified: Option<DateTime<Utc>>,
+    /// Last modification of the file given in `LLDAP_JWT_SECRET_FILE`.
+    pub jwt_secret_modified: Option<DateTime<Utc>>,
+}
+
+/// The expiry date of the first certificate of a PEM file.
+pub fn read_certificate_expiry(cert_file: &str) -> Result<DateTime<Utc>> {
+    use std::{fs::File, io::BufReader};
+    let certs = rustls_pemfile::certs(&mut BufReader::new(
+        File::open(cert_file).with_context(|| format!("while opening {}", cert_file))?,
+    ))?;
+    let cert = certs
+        .first()
+        .ok_or_else(|| anyhow!("No certificate in {}", cert_file))?;
+    let (_, cert) = x509_parser::parse_x509_certificate(cert)
+        .map_err(|e| anyhow!("Invalid certificate in {}: {}", cert_file, e))?;
+    Utc.timestamp_opt(cert.validity().not_after.timestamp(), 0)
+        .single()
+        .ok_or_else(|| anyhow!("Invalid expiry date in {}", cert_file))
+}
+
+fn read_modification_time(file: &str) -> Result<DateTime<Utc>> {
+    Ok(std::fs::metadata(file)
+        .with_context(|| format!("while reading the metadata of {}", file))?
+        .modified()?
+        .into())
+}
+
+/// The reasons to warn the admins, if any.
+fn get_warnings(
+    status: &ExpiryStatus,
+    options: &ExpiryMonitoringOptions,
+    now: DateTime<Utc>,
+) -> Vec<(AlertKind, String)> {
+    let mut warnings = Vec::new();
+    if let Some(not_after) = status.ldaps_certificate_not_after {
+        let days_left = (not_after - now).num_days();
+        if not_after <= now {
+            warnings.push((
+                AlertKind::CertificateExpiry,
+                format!("The LDAPS certificate expired on {}", not_after),
+            ));
+        } else if days_left < options.certificate_warning_days.into() {
+            warnings.push((
+                AlertKind::CertificateExpiry,
+                format!(
+                    "The LDAPS certificate expires in {} days, on {}",
+                    days_left, not_after
+                ),
+            ));
+        }
+    }
+    if let Some(max_age) = options.key_rotation_days {
+        for (key, modified) in [
+            ("server private key", status.private_key_modified),
+            ("JWT secret", status.jwt_secret_modified),
+        ] {
+            if let Some(modified) = modified {
+                let age = (now - modified).num_days();
+                if age >= max_age.into() {
+                    warnings.push((
+                        AlertKind::KeyAge,
+                        format!(
+                            "The {} has not been rotated for {} days, since {}",
+                            key, age, modified
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+    warnings
+}
+
+/// Checks the certificate and the keys every day, and alerts the admins ahead of the expiry.
+#[derive(Default)]
+pub struct ExpiryMonitor {
+    options: ExpiryMonitoringOptions,
+    ldaps_cert_file: Option<String>,
+    private_key_file: Option<String>,
+    jwt_secret_file: Option<String>,
+    notifier: Notifier,
+    status: Mutex<ExpiryStatus>,
+}
+
+impl ExpiryMonitor {
+    pub fn new(config: &Configuration, notifier: Notifier) -> Self {
+        let non_empty = |s: &str| Some(s.to_owned()).filter(|s| !s.is_empty());
+        Self {
+            options: config.expiry_monitoring_options.clone(),
+            ldaps_cert_file: Some(config.ldaps_options.cert_file.clone())
+                .filter(|_| config.ldaps_options.enabled),
+            // A key generated from the seed is never written to disk.
+            private_key_file: non_empty(&config.key_file).filter(|_| {
+                config
+                    .key_seed
+                    .as_ref()
+                    .map_or(true, |seed| seed.unsecure().is_empty())
+            }),
+            jwt_secret_file: std::env::var("LLDAP_JWT_SECRET_FILE")
+                .ok()
+                .and_then(|f| non_empty(&f)),
+            notifier,
+            status: Mutex::default(),
+        }
+    }
+
+    pub fn status(&self) -> ExpiryStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    fn read_status(&self) -> ExpiryStatus {
+        let read = |file: &Option<String>, reader: fn(&str) -> Result<DateTime<Utc>>| {
+            file.as_deref().and_then(|file| {
+                reader(file)
+                    .map_err(|e| warn!("Could not check {}: {:#}", file, e))
+                    .ok()
+            })
+        };
+        ExpiryStatus {
+            checked_at: Some(Utc::now()),
+            ldaps_certificate_not_after: read(&self.ldaps_cert_file, read_certificate_expiry),
+            private_key_modified: read(&self.private_key_file, read_modification_time),
+            jwt_secret_modified: read(&self.jwt_secret_file, read_modification_time),
+        }
+    }
+
+    pub async fn check(&self) {
+        let status = self.read_status();
+        for (kind, message) in get_warnings(&status, &self.options, Utc::now()) {
+            warn!("{}", message);
+            self.notifier.alert(kind, message).await;
+        }
+        *self.status.lock().unwrap() = status;
+    }
+}
+
+/// Runs the checks of the monitor every day. With several instances sharing the DB, only one of
+/// them runs them, so that the admins are alerted once: the status of the others stays unchecked.
+pub struct ExpiryMonitorTask {
+    monitor: Arc<ExpiryMonitor>,
+    leader_election: LeaderElection,
+}
+
+impl Actor for ExpiryMonitorTask {
+    type Context = Context<Self>;
+
+    fn started(&mut self, context: &mut Context<Self>) {
+        info!("Certificate and key expiry monitoring started");
+        self.schedule_task(context);
+        context.run_interval(CHECK_INTERVAL, |this, ctx| this.schedule_task(ctx));
+    }
+}
+
+impl ExpiryMonitorTask {
+    pub fn new(monitor: Arc<ExpiryMonitor>, sql_pool: DbConnection) -> Self {
+        Self {
+            monitor,
+            leader_election: LeaderElection::new(sql_pool),
+        }
+    }
+
+    fn schedule_task(&self, ctx: &mut Context<Self>) {
+        let monitor = self.monitor.clone();
+        let leader_election = self.leader_election.clone();
+        ctx.spawn(actix::fut::wrap_future::<_, Self>(async move {
+            match leader_election
+                .try_acquire(JOB_NAME, CHECK_INTERVAL + LEASE_MARGIN)
+                .await
+            {
+                Ok(true) => monitor.check().await,
+                Ok(false) => debug!("Another instance is running the expiry monitoring"),
+                Err(e) => error!(
+                    "DB error while acquiring the expiry monitoring lease: {}",
+                    e
+                ),
+            }
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::configuration::ExpiryMonitoringOptionsBuilder;
+    use pretty_assertions::assert_eq;
+
+    fn date(day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, day, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_certificate_warnings() {
+        let options = ExpiryMonitoringOptions::default();
+        let status = |not_after| ExpiryStatus {
+            ldaps_certificate_not_after: Some(not_after),
+            ..Default::default()
+        };
+        assert_eq!(get_warnings(&status(date(30)), &options, date(1)), vec![]);
+        assert_eq!(
+            get_warnings(&status(date(11)), &options, date(1)),
+            vec![(
+                AlertKind::CertificateExpiry,
+                "The LDAPS certificate expires in 10 days, on 2024-03-11 12:00:00 UTC".to_owned()
+            )]
+        );
+        assert_eq!(
+            get_warnings(&status(date(1)), &options, date(2)),
+            vec![(
+                AlertKind::CertificateExpiry,
+                "The LDAPS certificate expired on 2024-03-01 12:00:00 UTC".to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_key_age_warnings() {
+        let status = ExpiryStatus {
+            private_key_modified: Some(date(1)),
+            jwt_secret_modified: Some(date(20)),
+            ..Default::default()
+        };
+        assert_eq!(
+            get_warnings(&status, &ExpiryMonitoringOptions::default(), date(25)),
+            vec![]
+        );
+        let options = ExpiryMonitoringOptionsBuilder::default()
+            .key_rotation_days(Some(10))
+            .build()
+            .unwrap();
+        assert_eq!(
+            get_warnings(&status, &options, date(25)),
+            vec![(
+                AlertKind::KeyAge,
+                "The server private key has not been rotated for 24 days, since 2024-03-01 12:00:00 UTC"
+                    .to_owned()
+            )]
+        );
+    }
+}
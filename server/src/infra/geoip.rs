@@ -0,0 +1,108 @@
+use std::{fmt, net::IpAddr};
+
+use anyhow::{Context, Result};
+use maxminddb::{geoip2, Reader};
+
+use crate::infra::configuration::GeoIpOptions;
+
+/// What is known about the origin of a connection.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GeoIpInfo {
+    /// ISO 3166-1 alpha-2 code of the country.
+    pub country: Option<String>,
+    pub asn: Option<u32>,
+    pub asn_organization: Option<String>,
+}
+
+impl fmt::Display for GeoIpInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "country: {}",
+            self.country.as_deref().unwrap_or("unknown")
+        )?;
+        match (self.asn, &self.asn_organization) {
+            (Some(asn), Some(org)) => write!(f, ", AS{} ({})", asn, org),
+            (Some(asn), None) => write!(f, ", AS{}", asn),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Looks up IP addresses in local MaxMind-format databases.
+pub struct GeoIpResolver {
+    country: Option<Reader<Vec<u8>>>,
+    asn: Option<Reader<Vec<u8>>>,
+}
+
+fn open_database(path: &Option<String>) -> Result<Option<Reader<Vec<u8>>>> {
+    path.as_ref()
+        .map(|path| {
+            Reader::open_readfile(path)
+                .with_context(|| format!("while opening the GeoIP database {}", path))
+        })
+        .transpose()
+}
+
+impl GeoIpResolver {
+    /// Returns None if no database is configured.
+    pub fn from_options(options: &GeoIpOptions) -> Result<Option<Self>> {
+        let resolver = Self {
+            country: open_database(&options.country_database)?,
+            asn: open_database(&options.asn_database)?,
+        };
+        Ok((resolver.country.is_some() || resolver.asn.is_some()).then_some(resolver))
+    }
+
+    pub fn lookup(&self, address: IpAddr) -> GeoIpInfo {
+        let mut info = GeoIpInfo::default();
+        if let Some(reader) = &self.country {
+            if let Ok(country) = reader.lookup::<geoip2::Country>(address) {
+                info.country = country.country.and_then(|c| c.iso_code).map(str::to_owned);
+            }
+        }
+        if let Some(reader) = &self.asn {
+            if let Ok(asn) = reader.lookup::<geoip2::Asn>(address) {
+                info.asn = asn.autonomous_system_number;
+                info.asn_organization = asn.autonomous_system_organization.map(str::to_owned);
+            }
+        }
+        info
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_no_database() {
+        assert!(GeoIpResolver::from_options(&GeoIpOptions::default())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_missing_database() {
+        let options = GeoIpOptions {
+            country_database: Some("/does/not/exist.mmdb".to_owned()),
+            asn_database: None,
+        };
+        assert!(GeoIpResolver::from_options(&options).is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(GeoIpInfo::default().to_string(), "country: unknown");
+        assert_eq!(
+            GeoIpInfo {
+                country: Some("DE".to_owned()),
+                asn: Some(3320),
+                asn_organization: Some("Deutsche Telekom AG".to_owned()),
+            }
+            .to_string(),
+            "country: DE, AS3320 (Deutsche Telekom AG)"
+        );
+    }
+}
@@ -1,32 +1,62 @@
 use crate::{
-    domain::{handler::BackendHandler, types::UserId},
+    domain::{error::DomainError, handler::BackendHandler, types::UserId},
     infra::{
         access_control::{
-            AccessControlledBackendHandler, AdminBackendHandler, ReadonlyBackendHandler,
-            UserReadableBackendHandler, UserWriteableBackendHandler, ValidationResults,
+            AccessControlledBackendHandler, AdminBackendHandler, DirectoryListerBackendHandler,
+            ReadonlyBackendHandler, UserReadableBackendHandler, UserWriteableBackendHandler,
+            ValidationResults,
         },
-        auth_service::check_if_token_is_valid,
+        auth_service::{check_if_token_is_valid, CookieSession},
         cli::ExportGraphQLSchemaOpts,
-        graphql::{mutation::Mutation, query::Query},
+        configuration::{AvatarOptions, ComputedAttribute, DirectoryVisibility, MailOptions},
+        expiry_monitor::ExpiryMonitor,
+        graphql::{
+            loaders::GroupMembersLoader,
+            mutation::Mutation,
+            persisted_queries::{PersistedQueries, PersistedQueryError},
+            query::Query,
+        },
+        ldap_metrics::LdapMetrics,
+        maintenance::MaintenanceMode,
+        read_only::ReadOnlyMode,
         tcp_server::AppState,
     },
 };
 use actix_web::FromRequest;
 use actix_web::HttpMessage;
-use actix_web::{error::JsonPayloadError, web, Error, HttpRequest, HttpResponse};
+use actix_web::{
+    error::{ErrorForbidden, JsonPayloadError},
+    web, Error, HttpRequest, HttpResponse,
+};
 use actix_web_httpauth::extractors::bearer::BearerAuth;
 use juniper::{
+    graphql_value,
     http::{
         graphiql::graphiql_source, playground::playground_source, GraphQLBatchRequest,
         GraphQLRequest,
     },
-    EmptySubscription, FieldError, RootNode, ScalarValue,
+    EmptySubscription, FieldError, InputValue, RootNode, ScalarValue,
 };
+use std::sync::Arc;
 use tracing::debug;
 
 pub struct Context<Handler: BackendHandler> {
     pub handler: AccessControlledBackendHandler<Handler>,
     pub validation_result: ValidationResults,
+    pub group_members: GroupMembersLoader,
+    /// Whether sensitive changes need the approval of a second admin.
+    pub four_eyes_approval: bool,
+    /// Which other users the regular users can see.
+    pub directory_visibility: DirectoryVisibility,
+    pub computed_user_attributes: Vec<ComputedAttribute>,
+    pub avatar_options: AvatarOptions,
+    pub ldap_metrics: Arc<LdapMetrics>,
+    pub maintenance: Arc<MaintenanceMode>,
+    pub read_only: Arc<ReadOnlyMode>,
+    pub expiry_monitor: Arc<ExpiryMonitor>,
+    /// To email the reviewers of the access reviews.
+    pub server_url: url::Url,
+    pub mail_options: MailOptions,
 }
 
 pub fn field_error_callback<'a>(
@@ -39,12 +69,40 @@ pub fn field_error_callback<'a>(
     }
 }
 
+/// Converts a backend error, with its kind in the `code` extension (e.g. `NOT_FOUND`). The
+/// attribute validation errors also name the attribute and the rule that failed.
+pub fn domain_error(error: DomainError) -> FieldError {
+    let code = error.code();
+    let extensions = match &error {
+        DomainError::AttributeValidationError {
+            attribute, rule, ..
+        } => {
+            let attribute = attribute.to_string();
+            let rule = rule.to_string();
+            graphql_value!({ "code": code, "attribute": attribute, "rule": rule })
+        }
+        _ => graphql_value!({ "code": code }),
+    };
+    FieldError::new(error, extensions)
+}
+
 impl<Handler: BackendHandler> Context<Handler> {
     #[cfg(test)]
     pub fn new_for_tests(handler: Handler, validation_result: ValidationResults) -> Self {
         Self {
             handler: AccessControlledBackendHandler::new(handler),
             validation_result,
+            group_members: GroupMembersLoader::default(),
+            four_eyes_approval: false,
+            directory_visibility: DirectoryVisibility::default(),
+            computed_user_attributes: Vec::new(),
+            avatar_options: AvatarOptions::default(),
+            ldap_metrics: Arc::default(),
+            maintenance: Arc::default(),
+            read_only: Arc::default(),
+            expiry_monitor: Arc::default(),
+            server_url: url::Url::parse("http://localhost").unwrap(),
+            mail_options: MailOptions::default(),
         }
     }
 
@@ -71,6 +129,14 @@ impl<Handler: BackendHandler> Context<Handler> {
         self.handler
             .get_readable_handler(&self.validation_result, user_id)
     }
+
+    pub async fn get_directory_lister_handler(
+        &self,
+    ) -> Result<Option<DirectoryListerBackendHandler<'_, Handler>>, DomainError> {
+        self.handler
+            .get_directory_lister_handler(&self.validation_result, self.directory_visibility)
+            .await
+    }
 }
 
 impl<Handler: BackendHandler> juniper::Context for Context<Handler> {}
@@ -119,33 +185,127 @@ async fn playground_route() -> Result<HttpResponse, Error> {
         .body(html))
 }
 
+#[derive(serde::Deserialize, Clone, PartialEq, Eq, Debug)]
+struct PersistedQueryExtension {
+    #[serde(rename = "sha256Hash")]
+    sha256_hash: String,
+}
+
+#[derive(serde::Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+struct RequestExtensions {
+    #[serde(rename = "persistedQuery")]
+    persisted_query: Option<PersistedQueryExtension>,
+}
+
+/// A GraphQL request whose query can be replaced by the hash of a persisted query.
+#[derive(serde::Deserialize, Debug)]
+#[serde(bound = "InputValue<S>: serde::Deserialize<'de>")]
+struct IncomingGraphQLRequest<S: ScalarValue> {
+    query: Option<String>,
+    #[serde(rename = "operationName")]
+    operation_name: Option<String>,
+    variables: Option<InputValue<S>>,
+    #[serde(default)]
+    extensions: RequestExtensions,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(untagged, bound = "InputValue<S>: serde::Deserialize<'de>")]
+enum IncomingGraphQLBatchRequest<S: ScalarValue> {
+    Single(IncomingGraphQLRequest<S>),
+    Batch(Vec<IncomingGraphQLRequest<S>>),
+}
+
 #[derive(serde::Deserialize, Clone, PartialEq, Debug)]
 struct GetGraphQLRequest {
-    query: String,
+    query: Option<String>,
     #[serde(rename = "operationName")]
     operation_name: Option<String>,
     variables: Option<String>,
+    extensions: Option<String>,
 }
 
-impl<S> From<GetGraphQLRequest> for GraphQLRequest<S>
-where
-    S: ScalarValue,
-{
-    fn from(get_req: GetGraphQLRequest) -> Self {
+impl GetGraphQLRequest {
+    fn into_request<S: ScalarValue>(self) -> Result<IncomingGraphQLRequest<S>, JsonPayloadError> {
         let GetGraphQLRequest {
             query,
             operation_name,
             variables,
-        } = get_req;
+            extensions,
+        } = self;
         let variables = variables.map(|s| serde_json::from_str(&s).unwrap());
-        Self::new(query, operation_name, variables)
+        let extensions = extensions
+            .map(|s| serde_json::from_str(&s))
+            .transpose()
+            .map_err(JsonPayloadError::Deserialize)?
+            .unwrap_or_default();
+        Ok(IncomingGraphQLRequest {
+            query,
+            operation_name,
+            variables,
+            extensions,
+        })
     }
 }
 
+/// Turns the incoming requests into the queries to run.
+pub struct QueryResolver<'a> {
+    persisted_queries: &'a PersistedQueries,
+}
+
+impl QueryResolver<'_> {
+    fn resolve<S: ScalarValue>(
+        &self,
+        request: IncomingGraphQLRequest<S>,
+    ) -> Result<GraphQLRequest<S>, PersistedQueryError> {
+        let hash = request
+            .extensions
+            .persisted_query
+            .as_ref()
+            .map(|p| p.sha256_hash.as_str());
+        let query = self.persisted_queries.resolve(request.query, hash)?;
+        Ok(GraphQLRequest::new(
+            query,
+            request.operation_name,
+            request.variables,
+        ))
+    }
+
+    fn resolve_batch<S: ScalarValue>(
+        &self,
+        request: IncomingGraphQLBatchRequest<S>,
+    ) -> Result<GraphQLBatchRequest<S>, PersistedQueryError> {
+        Ok(match request {
+            IncomingGraphQLBatchRequest::Single(request) => {
+                GraphQLBatchRequest::Single(self.resolve(request)?)
+            }
+            IncomingGraphQLBatchRequest::Batch(requests) => GraphQLBatchRequest::Batch(
+                requests
+                    .into_iter()
+                    .map(|request| self.resolve(request))
+                    .collect::<Result<_, _>>()?,
+            ),
+        })
+    }
+}
+
+fn persisted_query_error_response(error: PersistedQueryError) -> HttpResponse {
+    let (message, code) = error.message_and_code();
+    let mut response = match error {
+        // The Apollo clients then retry with the full query.
+        PersistedQueryError::NotFound => HttpResponse::Ok(),
+        _ => HttpResponse::BadRequest(),
+    };
+    response.json(serde_json::json!({
+        "errors": [{ "message": message, "extensions": { "code": code } }]
+    }))
+}
+
 /// Actix GraphQL Handler for GET requests
 pub async fn get_graphql_handler<Query, Mutation, Subscription, CtxT, S>(
     schema: &juniper::RootNode<'static, Query, Mutation, Subscription, S>,
     context: &CtxT,
+    resolver: &QueryResolver<'_>,
     req: HttpRequest,
 ) -> Result<HttpResponse, Error>
 where
@@ -159,7 +319,10 @@ where
     S: ScalarValue + Send + Sync,
 {
     let get_req = web::Query::<GetGraphQLRequest>::from_query(req.query_string())?;
-    let req = GraphQLRequest::from(get_req.into_inner());
+    let req = match resolver.resolve(get_req.into_inner().into_request()?) {
+        Ok(req) => req,
+        Err(e) => return Ok(persisted_query_error_response(e)),
+    };
     let gql_response = req.execute(schema, context).await;
     let body_response = serde_json::to_string(&gql_response)?;
     let mut response = match gql_response.is_ok() {
@@ -175,6 +338,7 @@ where
 pub async fn post_graphql_handler<Query, Mutation, Subscription, CtxT, S>(
     schema: &juniper::RootNode<'static, Query, Mutation, Subscription, S>,
     context: &CtxT,
+    resolver: &QueryResolver<'_>,
     req: HttpRequest,
     mut payload: actix_http::Payload,
 ) -> Result<HttpResponse, Error>
@@ -191,17 +355,26 @@ where
     let req = match req.content_type() {
         "application/json" => {
             let body = String::from_request(&req, &mut payload).await?;
-            serde_json::from_str::<GraphQLBatchRequest<S>>(&body)
+            serde_json::from_str::<IncomingGraphQLBatchRequest<S>>(&body)
                 .map_err(JsonPayloadError::Deserialize)
         }
         "application/graphql" => {
             let body = String::from_request(&req, &mut payload).await?;
-            Ok(GraphQLBatchRequest::Single(GraphQLRequest::new(
-                body, None, None,
-            )))
+            Ok(IncomingGraphQLBatchRequest::Single(
+                IncomingGraphQLRequest {
+                    query: Some(body),
+                    operation_name: None,
+                    variables: None,
+                    extensions: RequestExtensions::default(),
+                },
+            ))
         }
         _ => Err(JsonPayloadError::ContentType),
     }?;
+    let req = match resolver.resolve_batch(req) {
+        Ok(req) => req,
+        Err(e) => return Ok(persisted_query_error_response(e)),
+    };
     let gql_batch_response = req.execute(schema, context).await;
     let gql_response = serde_json::to_string(&gql_batch_response)?;
     let mut response = match gql_batch_response.is_ok() {
@@ -216,18 +389,44 @@ async fn graphql_route<Handler: BackendHandler + Clone>(
     payload: actix_web::web::Payload,
     data: web::Data<AppState<Handler>>,
 ) -> Result<HttpResponse, Error> {
+    let cookie_session = req.extensions().get::<CookieSession>().copied();
+    // Queries over GET can run mutations too.
+    if matches!(
+        cookie_session,
+        Some(CookieSession {
+            csrf_verified: false
+        })
+    ) {
+        return Err(ErrorForbidden("Missing or invalid CSRF token"));
+    }
     let mut inner_payload = payload.into_inner();
     let bearer = BearerAuth::from_request(&req, &mut inner_payload).await?;
     let validation_result = check_if_token_is_valid(&data, bearer.token())?;
     let context = Context::<Handler> {
         handler: data.backend_handler.clone(),
         validation_result,
+        group_members: GroupMembersLoader::default(),
+        four_eyes_approval: data.four_eyes_approval,
+        directory_visibility: data.directory_visibility,
+        computed_user_attributes: data.computed_user_attributes.clone(),
+        avatar_options: data.avatar_options.clone(),
+        ldap_metrics: data.ldap_metrics.clone(),
+        maintenance: data.maintenance.clone(),
+        read_only: data.read_only.clone(),
+        expiry_monitor: data.expiry_monitor.clone(),
+        server_url: data.server_url.clone(),
+        mail_options: data.mail_options.clone(),
+    };
+    let resolver = QueryResolver {
+        persisted_queries: &data.persisted_queries,
     };
     let schema = &schema();
     let context = &context;
     match *req.method() {
-        actix_http::Method::POST => post_graphql_handler(schema, context, req, inner_payload).await,
-        actix_http::Method::GET => get_graphql_handler(schema, context, req).await,
+        actix_http::Method::POST => {
+            post_graphql_handler(schema, context, &resolver, req, inner_payload).await
+        }
+        actix_http::Method::GET => get_graphql_handler(schema, context, &resolver, req).await,
         _ => Err(actix_web::error::UrlGenerationError::ResourceNotFound.into()),
     }
 }
@@ -0,0 +1,67 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    domain::{
+        error::Result,
+        handler::UserRequestFilter,
+        types::{GroupId, UserAndGroups},
+    },
+    infra::access_control::ReadonlyBackendHandler,
+};
+
+/// Batches the resolution of `Group::users` within a single request.
+///
+/// Groups returned by a list query are registered up front; the first time the members of any
+/// of them are requested, the members of all the registered groups are fetched in one query.
+#[derive(Default)]
+pub struct GroupMembersLoader {
+    pending: std::sync::Mutex<HashSet<GroupId>>,
+    loaded: tokio::sync::Mutex<HashMap<GroupId, Vec<UserAndGroups>>>,
+}
+
+impl GroupMembersLoader {
+    pub fn register(&self, group_ids: impl IntoIterator<Item = GroupId>) {
+        self.pending.lock().unwrap().extend(group_ids);
+    }
+
+    pub async fn load<Handler: ReadonlyBackendHandler>(
+        &self,
+        handler: &Handler,
+        group_id: GroupId,
+    ) -> Result<Vec<UserAndGroups>> {
+        let mut loaded = self.loaded.lock().await;
+        if let Some(users) = loaded.get(&group_id) {
+            return Ok(users.clone());
+        }
+        let batch = {
+            let mut pending = self.pending.lock().unwrap();
+            pending.insert(group_id);
+            pending.retain(|id| !loaded.contains_key(id));
+            let mut batch = pending.drain().collect::<Vec<_>>();
+            batch.sort();
+            batch
+        };
+        let filter = match batch.len() {
+            1 => UserRequestFilter::MemberOfId(group_id),
+            _ => UserRequestFilter::Or(
+                batch
+                    .iter()
+                    .copied()
+                    .map(UserRequestFilter::MemberOfId)
+                    .collect(),
+            ),
+        };
+        let users = handler.list_users(Some(filter), true).await?;
+        for id in &batch {
+            loaded.entry(*id).or_default();
+        }
+        for user in users {
+            for group in user.groups.iter().flatten() {
+                if batch.binary_search(&group.group_id).is_ok() {
+                    loaded.get_mut(&group.group_id).unwrap().push(user.clone());
+                }
+            }
+        }
+        Ok(loaded[&group_id].clone())
+    }
+}
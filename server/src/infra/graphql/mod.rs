@@ -1,3 +1,6 @@
 pub mod api;
+pub mod loaders;
 pub mod mutation;
+pub mod persisted_queries;
 pub mod query;
+pub mod user_export;
@@ -2,15 +2,18 @@ use std::sync::Arc;
 
 use crate::{
     domain::{
+        attribute_templates::AttributeTemplate,
         deserialize::deserialize_attribute_value,
         handler::{
             AttributeList, BackendHandler, CreateAttributeRequest, CreateGroupRequest,
-            CreateUserRequest, UpdateGroupRequest, UpdateUserRequest,
+            CreateUserRequest, UpdateGroupRequest, UpdateUserRequest, UserCreationSource,
         },
+        locale::get_preferred_language,
+        pending_changes::SensitiveChange,
         schema::PublicSchema,
         types::{
             AttributeName, AttributeType, AttributeValue as DomainAttributeValue, Email, GroupId,
-            JpegPhoto, LdapObjectClass, UserId,
+            JpegPhoto, LdapObjectClass, Serialized, User, UserId,
         },
     },
     infra::{
@@ -18,13 +21,23 @@ use crate::{
             AdminBackendHandler, ReadonlyBackendHandler, UserReadableBackendHandler,
             UserWriteableBackendHandler,
         },
-        graphql::api::{field_error_callback, Context},
+        avatar::process_avatar,
+        configuration::AvatarOptions,
+        graphql::{
+            api::{domain_error, field_error_callback, Context},
+            query::{get_group_by_uuid, AccessReview, GroupRuleChange, MaintenanceMode},
+            user_export::export_user_data,
+        },
+        mail::send_access_review_email,
     },
 };
 use anyhow::{anyhow, Context as AnyhowContext};
 use base64::Engine;
-use juniper::{graphql_object, FieldResult, GraphQLInputObject, GraphQLObject};
-use tracing::{debug, debug_span, Instrument, Span};
+use juniper::{
+    graphql_object, graphql_value, FieldError, FieldResult, GraphQLEnum, GraphQLInputObject,
+    GraphQLObject,
+};
+use tracing::{debug, debug_span, info, warn, Instrument, Span};
 
 #[derive(PartialEq, Eq, Debug)]
 /// The top-level GraphQL mutation type.
@@ -51,7 +64,8 @@ struct AttributeValue {
     /// If the attribute is not a list, the vector must contain exactly one element.
     /// Integers (signed 64 bits) are represented as strings.
     /// Dates are represented as strings in RFC3339 format, e.g. "2019-10-12T07:20:50.52Z".
-    /// JpegPhotos are represented as base64 encoded strings. They must be valid JPEGs.
+    /// JpegPhotos are represented as base64 encoded strings. They must be valid JPEGs, or PNGs for
+    /// the user attributes, which are converted and scaled down like the avatars.
     value: Vec<String>,
 }
 
@@ -64,10 +78,12 @@ pub struct CreateUserInput {
     display_name: Option<String>,
     first_name: Option<String>,
     last_name: Option<String>,
-    /// Base64 encoded JpegPhoto.
+    /// Base64 encoded JPEG or PNG image, converted to a JPEG and scaled down.
     avatar: Option<String>,
     /// User-defined attributes.
     attributes: Option<Vec<AttributeValue>>,
+    /// Stable identifier of the user in an external system. It can only be set at creation.
+    external_id: Option<String>,
 }
 
 #[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
@@ -86,7 +102,7 @@ pub struct UpdateUserInput {
     display_name: Option<String>,
     first_name: Option<String>,
     last_name: Option<String>,
-    /// Base64 encoded JpegPhoto.
+    /// Base64 encoded JPEG or PNG image, converted to a JPEG and scaled down.
     avatar: Option<String>,
     /// Attribute names to remove.
     /// They are processed before insertions.
@@ -111,14 +127,62 @@ pub struct UpdateGroupInput {
     insert_attributes: Option<Vec<AttributeValue>>,
 }
 
+#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+/// A group to review in an access review campaign.
+pub struct AccessReviewGroupInput {
+    group_id: i32,
+    /// The user asked to confirm or remove each member, e.g. the manager of the group.
+    reviewer: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, GraphQLEnum)]
+/// What an idempotent mutation had to do to reach the requested state.
+pub enum UpsertOutcome {
+    Created,
+    Updated,
+    /// The state already matched: nothing was written.
+    Unchanged,
+    /// The change is waiting for the approval of another admin (four-eyes mode).
+    Pending,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+pub struct UpsertResult {
+    outcome: UpsertOutcome,
+    /// Set when the outcome is `PENDING`.
+    pending_change_id: Option<i32>,
+}
+
+impl From<UpsertOutcome> for UpsertResult {
+    fn from(outcome: UpsertOutcome) -> Self {
+        Self {
+            outcome,
+            pending_change_id: None,
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Debug, GraphQLObject)]
 pub struct Success {
     ok: bool,
+    /// Set when the change was not applied yet, but is waiting for the approval of another
+    /// admin (four-eyes mode).
+    pending_change_id: Option<i32>,
 }
 
 impl Success {
     fn new() -> Self {
-        Self { ok: true }
+        Self {
+            ok: true,
+            pending_change_id: None,
+        }
+    }
+
+    fn pending(id: i32) -> Self {
+        Self {
+            ok: true,
+            pending_change_id: Some(id),
+        }
     }
 }
 
@@ -128,11 +192,37 @@ struct UnpackedAttributes {
     attributes: Vec<DomainAttributeValue>,
 }
 
+/// Converts the uploaded images of the photo attributes into JPEGs of the configured size.
+fn process_photo_attribute(
+    mut attribute: AttributeValue,
+    schema: &PublicSchema,
+    avatar_options: &AvatarOptions,
+) -> FieldResult<AttributeValue> {
+    if !matches!(
+        schema
+            .get_schema()
+            .user_attributes
+            .get_attribute_type(&attribute.name.as_str().into()),
+        Some((AttributeType::JpegPhoto, _))
+    ) {
+        return Ok(attribute);
+    }
+    for value in &mut attribute.value {
+        *value = String::from(&decode_photo(value, avatar_options)?);
+    }
+    Ok(attribute)
+}
+
 fn unpack_attributes(
     attributes: Vec<AttributeValue>,
     schema: &PublicSchema,
     is_admin: bool,
+    avatar_options: &AvatarOptions,
 ) -> FieldResult<UnpackedAttributes> {
+    let attributes = attributes
+        .into_iter()
+        .map(|attr| process_photo_attribute(attr, schema, avatar_options))
+        .collect::<FieldResult<Vec<_>>>()?;
     let email = attributes
         .iter()
         .find(|attr| attr.name == "mail")
@@ -160,6 +250,121 @@ fn unpack_attributes(
     })
 }
 
+fn decode_photo(photo: &str, avatar_options: &AvatarOptions) -> FieldResult<JpegPhoto> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(photo)
+        .context("Invalid base64 image")?;
+    Ok(process_avatar(&bytes, avatar_options)?)
+}
+
+fn decode_avatar(
+    avatar: Option<String>,
+    avatar_options: &AvatarOptions,
+) -> FieldResult<Option<JpegPhoto>> {
+    avatar
+        .map(|avatar| decode_photo(&avatar, avatar_options))
+        .transpose()
+}
+
+fn has_attribute_value(user: &User, name: &AttributeName, value: &Serialized) -> bool {
+    user.attributes
+        .iter()
+        .any(|attr| &attr.name == name && &attr.value == value)
+}
+
+/// Keeps the value only if it differs from what the user already has.
+fn changed_attribute<T: serde::Serialize>(user: &User, name: &str, value: Option<T>) -> Option<T> {
+    value.filter(|v| !has_attribute_value(user, &name.into(), &Serialized::from(v)))
+}
+
+/// Whether the change has to go through the four-eyes approval.
+fn needs_approval<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    change: &SensitiveChange,
+) -> bool {
+    context.four_eyes_approval && change.needs_approval()
+}
+
+fn check_sensitive_change(current_user: &UserId, change: &SensitiveChange) -> FieldResult<()> {
+    match change {
+        SensitiveChange::DeleteUser(user_id) if user_id == current_user => {
+            Err("Cannot delete current user".into())
+        }
+        SensitiveChange::RemoveUserFromGroup { user_id, group_id }
+            if user_id == current_user && *group_id == GroupId(1) =>
+        {
+            Err("Cannot remove admin rights for current user".into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Refuses the changes while the server is in read-only mode.
+fn check_not_read_only<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    span: &Span,
+) -> FieldResult<()> {
+    match context.read_only.refusal() {
+        Some(message) => {
+            span.in_scope(|| debug!("Refused in read-only mode"));
+            Err(FieldError::new(
+                message,
+                graphql_value!({ "code": "READ_ONLY" }),
+            ))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Applies the change right away, or records it for approval in four-eyes mode.
+async fn apply_or_submit_change<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    handler: &impl AdminBackendHandler,
+    change: SensitiveChange,
+) -> FieldResult<Success> {
+    check_sensitive_change(&context.validation_result.user, &change)?;
+    if needs_approval(context, &change) {
+        let id = handler
+            .create_pending_change(&context.validation_result.user, change)
+            .await?;
+        info!(
+            r#"Change {} by "{}" is waiting for approval"#,
+            id, &context.validation_result.user
+        );
+        return Ok(Success::pending(id));
+    }
+    apply_sensitive_change(handler, change).await?;
+    Ok(Success::new())
+}
+
+async fn apply_sensitive_change(
+    handler: &impl AdminBackendHandler,
+    change: SensitiveChange,
+) -> FieldResult<()> {
+    match change {
+        SensitiveChange::DeleteUser(user_id) => {
+            handler.delete_user(&user_id).await.map_err(domain_error)?
+        }
+        SensitiveChange::AddUserToGroup { user_id, group_id } => handler
+            .add_user_to_group(&user_id, group_id)
+            .await
+            .map_err(domain_error)?,
+        SensitiveChange::RemoveUserFromGroup { user_id, group_id } => handler
+            .remove_user_from_group(&user_id, group_id)
+            .await
+            .map_err(domain_error)?,
+        SensitiveChange::SetMembershipExpiry {
+            user_id,
+            group_id,
+            expiry_date,
+        } => handler
+            .set_membership_expiry(&user_id, group_id, expiry_date)
+            .await
+            .map_err(domain_error)?,
+    }
+    Ok(())
+}
+
 #[graphql_object(context = Context<Handler>)]
 impl<Handler: BackendHandler> Mutation<Handler> {
     async fn create_user(
@@ -167,6 +372,7 @@ impl<Handler: BackendHandler> Mutation<Handler> {
         user: CreateUserInput,
     ) -> FieldResult<super::query::User<Handler>> {
         let span = debug_span!("[GraphQL mutation] create_user");
+        check_not_read_only(context, &span)?;
         span.in_scope(|| {
             debug!("{:?}", &user.id);
         });
@@ -174,20 +380,18 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             .get_admin_handler()
             .ok_or_else(field_error_callback(&span, "Unauthorized user creation"))?;
         let user_id = UserId::new(&user.id);
-        let avatar = user
-            .avatar
-            .map(|bytes| base64::engine::general_purpose::STANDARD.decode(bytes))
-            .transpose()
-            .context("Invalid base64 image")?
-            .map(JpegPhoto::try_from)
-            .transpose()
-            .context("Provided image is not a valid JPEG")?;
+        let avatar = decode_avatar(user.avatar, &context.avatar_options)?;
         let schema = handler.get_schema().await?;
         let UnpackedAttributes {
             email,
             display_name,
             attributes,
-        } = unpack_attributes(user.attributes.unwrap_or_default(), &schema, true)?;
+        } = unpack_attributes(
+            user.attributes.unwrap_or_default(),
+            &schema,
+            true,
+            &context.avatar_options,
+        )?;
         handler
             .create_user(CreateUserRequest {
                 user_id: user_id.clone(),
@@ -201,18 +405,117 @@ impl<Handler: BackendHandler> Mutation<Handler> {
                 last_name: user.last_name,
                 avatar,
                 attributes,
+                external_id: user.external_id,
+                source: UserCreationSource::Graphql,
             })
             .instrument(span.clone())
-            .await?;
-        let user_details = handler.get_user_details(&user_id).instrument(span).await?;
+            .await
+            .map_err(domain_error)?;
+        let user_details = handler
+            .get_user_details(&user_id)
+            .instrument(span)
+            .await
+            .map_err(domain_error)?;
         super::query::User::<Handler>::from_user(user_details, Arc::new(schema))
     }
 
+    /// Creates the user if it doesn't exist, otherwise updates the given fields. Fields and
+    /// attributes that are not provided are left untouched, and nothing is written if the user
+    /// already matches.
+    async fn create_or_update_user(
+        context: &Context<Handler>,
+        user: CreateUserInput,
+    ) -> FieldResult<UpsertResult> {
+        let span = debug_span!("[GraphQL mutation] create_or_update_user");
+        check_not_read_only(context, &span)?;
+        span.in_scope(|| {
+            debug!("{:?}", &user.id);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(&span, "Unauthorized user creation"))?;
+        let user_id = UserId::new(&user.id);
+        let avatar = decode_avatar(user.avatar, &context.avatar_options)?;
+        let schema = handler.get_schema().await?;
+        let UnpackedAttributes {
+            email,
+            display_name,
+            attributes,
+        } = unpack_attributes(
+            user.attributes.unwrap_or_default(),
+            &schema,
+            true,
+            &context.avatar_options,
+        )?;
+        let email = user.email.map(Email::from).or(email);
+        let display_name = user.display_name.or(display_name);
+        let existing = match handler
+            .get_user_details(&user_id)
+            .instrument(span.clone())
+            .await
+        {
+            Ok(existing) => existing,
+            Err(DomainError::EntityNotFound(_)) => {
+                handler
+                    .create_user(CreateUserRequest {
+                        user_id,
+                        email: email
+                            .ok_or_else(|| anyhow!("Email is required when creating a new user"))?,
+                        display_name,
+                        first_name: user.first_name,
+                        last_name: user.last_name,
+                        avatar,
+                        attributes,
+                        external_id: user.external_id,
+                        source: UserCreationSource::Graphql,
+                    })
+                    .instrument(span)
+                    .await
+                    .map_err(domain_error)?;
+                return Ok(UpsertOutcome::Created.into());
+            }
+            Err(e) => return Err(domain_error(e)),
+        };
+        if user.external_id.is_some() && user.external_id != existing.external_id {
+            return Err("The external ID of a user cannot be changed".into());
+        }
+        let request = UpdateUserRequest {
+            email: email.filter(|e| e != &existing.email),
+            display_name: display_name.filter(|d| Some(d) != existing.display_name.as_ref()),
+            first_name: changed_attribute(&existing, "first_name", user.first_name),
+            last_name: changed_attribute(&existing, "last_name", user.last_name),
+            avatar: changed_attribute(&existing, "avatar", avatar),
+            insert_attributes: attributes
+                .into_iter()
+                .filter(|attr| !has_attribute_value(&existing, &attr.name, &attr.value))
+                .collect(),
+            delete_attributes: Vec::new(),
+            user_id,
+            actor: Some(context.validation_result.actor().clone()),
+        };
+        if request.email.is_none()
+            && request.display_name.is_none()
+            && request.first_name.is_none()
+            && request.last_name.is_none()
+            && request.avatar.is_none()
+            && request.insert_attributes.is_empty()
+        {
+            return Ok(UpsertOutcome::Unchanged.into());
+        }
+        handler
+            .update_user(request)
+            .instrument(span)
+            .await
+            .map_err(domain_error)?;
+        Ok(UpsertOutcome::Updated.into())
+    }
+
     async fn create_group(
         context: &Context<Handler>,
         name: String,
     ) -> FieldResult<super::query::Group<Handler>> {
         let span = debug_span!("[GraphQL mutation] create_group");
+        check_not_read_only(context, &span)?;
         span.in_scope(|| {
             debug!(?name);
         });
@@ -231,6 +534,7 @@ impl<Handler: BackendHandler> Mutation<Handler> {
         request: CreateGroupInput,
     ) -> FieldResult<super::query::Group<Handler>> {
         let span = debug_span!("[GraphQL mutation] create_group_with_details");
+        check_not_read_only(context, &span)?;
         span.in_scope(|| {
             debug!(?request);
         });
@@ -242,6 +546,7 @@ impl<Handler: BackendHandler> Mutation<Handler> {
         user: UpdateUserInput,
     ) -> FieldResult<Success> {
         let span = debug_span!("[GraphQL mutation] update_user");
+        check_not_read_only(context, &span)?;
         span.in_scope(|| {
             debug!(?user.id);
         });
@@ -250,21 +555,19 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             .get_writeable_handler(&user_id)
             .ok_or_else(field_error_callback(&span, "Unauthorized user update"))?;
         let is_admin = context.validation_result.is_admin();
-        let avatar = user
-            .avatar
-            .map(|bytes| base64::engine::general_purpose::STANDARD.decode(bytes))
-            .transpose()
-            .context("Invalid base64 image")?
-            .map(JpegPhoto::try_from)
-            .transpose()
-            .context("Provided image is not a valid JPEG")?;
+        let avatar = decode_avatar(user.avatar, &context.avatar_options)?;
         let schema = handler.get_schema().await?;
         let user_insert_attributes = user.insert_attributes.unwrap_or_default();
         let UnpackedAttributes {
             email,
             display_name,
             attributes: insert_attributes,
-        } = unpack_attributes(user_insert_attributes, &schema, is_admin)?;
+        } = unpack_attributes(
+            user_insert_attributes,
+            &schema,
+            is_admin,
+            &context.avatar_options,
+        )?;
         let display_name = display_name.or_else(|| {
             // If the display name is not inserted, but removed, reset it.
             user.remove_attributes
@@ -289,8 +592,33 @@ impl<Handler: BackendHandler> Mutation<Handler> {
                     .map(Into::into)
                     .collect(),
                 insert_attributes,
+                actor: Some(context.validation_result.actor().clone()),
             })
             .instrument(span)
+            .await
+            .map_err(domain_error)?;
+        Ok(Success::new())
+    }
+
+    /// Stops (or resumes) fetching the user's avatar from Gravatar/libravatar. Opting out removes
+    /// the fetched avatar.
+    async fn set_avatar_sync_opt_out(
+        context: &Context<Handler>,
+        user_id: String,
+        opt_out: bool,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] set_avatar_sync_opt_out");
+        check_not_read_only(context, &span)?;
+        span.in_scope(|| {
+            debug!(?user_id, opt_out);
+        });
+        let user_id = UserId::new(&user_id);
+        let handler = context
+            .get_writeable_handler(&user_id)
+            .ok_or_else(field_error_callback(&span, "Unauthorized user update"))?;
+        handler
+            .set_avatar_sync_opt_out(&user_id, opt_out)
+            .instrument(span)
             .await?;
         Ok(Success::new())
     }
@@ -300,6 +628,7 @@ impl<Handler: BackendHandler> Mutation<Handler> {
         group: UpdateGroupInput,
     ) -> FieldResult<Success> {
         let span = debug_span!("[GraphQL mutation] update_group");
+        check_not_read_only(context, &span)?;
         span.in_scope(|| {
             debug!(?group.id);
         });
@@ -339,7 +668,8 @@ impl<Handler: BackendHandler> Mutation<Handler> {
                 insert_attributes,
             })
             .instrument(span)
-            .await?;
+            .await
+            .map_err(domain_error)?;
         Ok(Success::new())
     }
 
@@ -349,6 +679,7 @@ impl<Handler: BackendHandler> Mutation<Handler> {
         group_id: i32,
     ) -> FieldResult<Success> {
         let span = debug_span!("[GraphQL mutation] add_user_to_group");
+        check_not_read_only(context, &span)?;
         span.in_scope(|| {
             debug!(?user_id, ?group_id);
         });
@@ -358,11 +689,90 @@ impl<Handler: BackendHandler> Mutation<Handler> {
                 &span,
                 "Unauthorized group membership modification",
             ))?;
-        handler
-            .add_user_to_group(&UserId::new(&user_id), GroupId(group_id))
-            .instrument(span)
+        apply_or_submit_change(
+            context,
+            handler,
+            SensitiveChange::AddUserToGroup {
+                user_id: UserId::new(&user_id),
+                group_id: GroupId(group_id),
+            },
+        )
+        .instrument(span)
+        .await
+    }
+
+    /// Same as `addUserToGroup`, with the UUID of the group, as served over LDAP in `entryUUID`.
+    async fn add_user_to_group_by_uuid(
+        context: &Context<Handler>,
+        user_id: String,
+        group_uuid: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] add_user_to_group_by_uuid");
+        check_not_read_only(context, &span)?;
+        span.in_scope(|| {
+            debug!(?user_id, ?group_uuid);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized group membership modification",
+            ))?;
+        let group = get_group_by_uuid(handler, &group_uuid)
+            .instrument(span.clone())
             .await?;
-        Ok(Success::new())
+        apply_or_submit_change(
+            context,
+            handler,
+            SensitiveChange::AddUserToGroup {
+                user_id: UserId::new(&user_id),
+                group_id: group.id,
+            },
+        )
+        .instrument(span)
+        .await
+    }
+
+    /// Adds the user to the group, unless they are already a member.
+    async fn ensure_group_membership(
+        context: &Context<Handler>,
+        user_id: String,
+        group_id: i32,
+    ) -> FieldResult<UpsertResult> {
+        let span = debug_span!("[GraphQL mutation] ensure_group_membership");
+        check_not_read_only(context, &span)?;
+        span.in_scope(|| {
+            debug!(?user_id, ?group_id);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized group membership modification",
+            ))?;
+        let user_id = UserId::new(&user_id);
+        let group_id = GroupId(group_id);
+        let groups = handler
+            .get_user_groups(&user_id)
+            .instrument(span.clone())
+            .await?;
+        if groups.iter().any(|g| g.group_id == group_id) {
+            return Ok(UpsertOutcome::Unchanged.into());
+        }
+        let result = apply_or_submit_change(
+            context,
+            handler,
+            SensitiveChange::AddUserToGroup { user_id, group_id },
+        )
+        .instrument(span)
+        .await?;
+        Ok(match result.pending_change_id {
+            Some(id) => UpsertResult {
+                outcome: UpsertOutcome::Pending,
+                pending_change_id: Some(id),
+            },
+            None => UpsertOutcome::Created.into(),
+        })
     }
 
     async fn remove_user_from_group(
@@ -371,6 +781,7 @@ impl<Handler: BackendHandler> Mutation<Handler> {
         group_id: i32,
     ) -> FieldResult<Success> {
         let span = debug_span!("[GraphQL mutation] remove_user_from_group");
+        check_not_read_only(context, &span)?;
         span.in_scope(|| {
             debug!(?user_id, ?group_id);
         });
@@ -380,20 +791,196 @@ impl<Handler: BackendHandler> Mutation<Handler> {
                 &span,
                 "Unauthorized group membership modification",
             ))?;
-        let user_id = UserId::new(&user_id);
-        if context.validation_result.user == user_id && group_id == 1 {
-            span.in_scope(|| debug!("Cannot remove admin rights for current user"));
-            return Err("Cannot remove admin rights for current user".into());
-        }
+        apply_or_submit_change(
+            context,
+            handler,
+            SensitiveChange::RemoveUserFromGroup {
+                user_id: UserId::new(&user_id),
+                group_id: GroupId(group_id),
+            },
+        )
+        .instrument(span)
+        .await
+    }
+
+    /// Same as `removeUserFromGroup`, with the UUID of the group, as served over LDAP in
+    /// `entryUUID`.
+    async fn remove_user_from_group_by_uuid(
+        context: &Context<Handler>,
+        user_id: String,
+        group_uuid: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] remove_user_from_group_by_uuid");
+        check_not_read_only(context, &span)?;
+        span.in_scope(|| {
+            debug!(?user_id, ?group_uuid);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized group membership modification",
+            ))?;
+        let group = get_group_by_uuid(handler, &group_uuid)
+            .instrument(span.clone())
+            .await?;
+        apply_or_submit_change(
+            context,
+            handler,
+            SensitiveChange::RemoveUserFromGroup {
+                user_id: UserId::new(&user_id),
+                group_id: group.id,
+            },
+        )
+        .instrument(span)
+        .await
+    }
+
+    /// Makes the membership temporary: it is removed once the expiry date is past. Without a date,
+    /// the membership becomes permanent again.
+    async fn set_membership_expiry(
+        context: &Context<Handler>,
+        user_id: String,
+        group_id: i32,
+        expiry_date: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] set_membership_expiry");
+        check_not_read_only(context, &span)?;
+        span.in_scope(|| {
+            debug!(?user_id, ?group_id, ?expiry_date);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized group membership modification",
+            ))?;
+        apply_or_submit_change(
+            context,
+            handler,
+            SensitiveChange::SetMembershipExpiry {
+                user_id: UserId::new(&user_id),
+                group_id: GroupId(group_id),
+                expiry_date: expiry_date.map(|d| d.naive_utc()),
+            },
+        )
+        .instrument(span)
+        .await
+    }
+
+    /// Makes a group a member of another group: over LDAP, its members are then also members of
+    /// the parent group. A group cannot end up containing itself, and the groups that grant
+    /// permissions (lldap_admin, lldap_password_manager, lldap_strict_readonly) cannot contain
+    /// groups.
+    async fn add_group_to_group(
+        context: &Context<Handler>,
+        member_group_id: i32,
+        parent_group_id: i32,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] add_group_to_group");
+        check_not_read_only(context, &span)?;
+        span.in_scope(|| {
+            debug!(?member_group_id, ?parent_group_id);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized group membership modification",
+            ))?;
         handler
-            .remove_user_from_group(&user_id, GroupId(group_id))
+            .add_group_to_group(GroupId(member_group_id), GroupId(parent_group_id))
             .instrument(span)
             .await?;
         Ok(Success::new())
     }
 
+    async fn remove_group_from_group(
+        context: &Context<Handler>,
+        member_group_id: i32,
+        parent_group_id: i32,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] remove_group_from_group");
+        check_not_read_only(context, &span)?;
+        span.in_scope(|| {
+            debug!(?member_group_id, ?parent_group_id);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized group membership modification",
+            ))?;
+        handler
+            .remove_group_from_group(GroupId(member_group_id), GroupId(parent_group_id))
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Starts an access review campaign, and emails each reviewer the link to their review page.
+    async fn start_access_review(
+        context: &Context<Handler>,
+        name: String,
+        groups: Vec<AccessReviewGroupInput>,
+    ) -> FieldResult<Vec<AccessReview>> {
+        let span = debug_span!("[GraphQL mutation] start_access_review");
+        check_not_read_only(context, &span)?;
+        span.in_scope(|| {
+            debug!(?name, ?groups);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access review creation",
+            ))?;
+        if groups.is_empty() {
+            return Err("An access review needs at least one group".into());
+        }
+        let groups = groups
+            .into_iter()
+            .map(|g| (GroupId(g.group_id), UserId::new(&g.reviewer)))
+            .collect::<Vec<_>>();
+        let invitations = handler
+            .start_access_review(&name, &context.validation_result.user, &groups)
+            .instrument(span.clone())
+            .await?;
+        let mut reviews = Vec::new();
+        for invitation in invitations {
+            let reviewer = handler
+                .get_user_details(&invitation.review.reviewer)
+                .instrument(span.clone())
+                .await?;
+            // The campaign is already recorded: a failed email doesn't undo it.
+            if let Err(e) = send_access_review_email(
+                reviewer
+                    .display_name
+                    .as_deref()
+                    .unwrap_or_else(|| reviewer.user_id.as_str()),
+                reviewer.email.as_str(),
+                get_preferred_language(&reviewer).as_deref(),
+                &invitation.review,
+                &invitation.token,
+                &context.server_url,
+                &context.mail_options,
+            )
+            .instrument(span.clone())
+            .await
+            {
+                warn!(
+                    r#"Could not send the access review email to "{}": {:#}"#,
+                    &reviewer.user_id, e
+                );
+            }
+            reviews.push(invitation.review.into());
+        }
+        Ok(reviews)
+    }
+
     async fn delete_user(context: &Context<Handler>, user_id: String) -> FieldResult<Success> {
         let span = debug_span!("[GraphQL mutation] delete_user");
+        check_not_read_only(context, &span)?;
         span.in_scope(|| {
             debug!(?user_id);
         });
@@ -401,16 +988,232 @@ impl<Handler: BackendHandler> Mutation<Handler> {
         let handler = context
             .get_admin_handler()
             .ok_or_else(field_error_callback(&span, "Unauthorized user deletion"))?;
-        if context.validation_result.user == user_id {
-            span.in_scope(|| debug!("Cannot delete current user"));
-            return Err("Cannot delete current user".into());
+        apply_or_submit_change(context, handler, SensitiveChange::DeleteUser(user_id))
+            .instrument(span)
+            .await
+    }
+
+    /// Applies a pending change. It must be approved by an admin other than the requester.
+    ///
+    /// The change is removed from the pending list before being applied, so that concurrent
+    /// approvals cannot apply it twice. If it fails, it has to be requested again.
+    async fn approve_pending_change(context: &Context<Handler>, id: i32) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] approve_pending_change");
+        check_not_read_only(context, &span)?;
+        span.in_scope(|| {
+            debug!(?id);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(&span, "Unauthorized change approval"))?;
+        let approver = &context.validation_result.user;
+        async {
+            let pending = handler.get_pending_change(id).await?;
+            if &pending.requested_by == approver {
+                return Err("A change must be approved by a different admin".into());
+            }
+            check_sensitive_change(approver, &pending.change)?;
+            // Only one approval gets to delete the row: the others fail here.
+            handler.delete_pending_change(id).await?;
+            apply_sensitive_change(handler, pending.change).await?;
+            info!(
+                r#"Change {} requested by "{}" was approved by "{}""#,
+                id, &pending.requested_by, approver
+            );
+            Ok(Success::new())
         }
-        handler.delete_user(&user_id).instrument(span).await?;
+        .instrument(span)
+        .await
+    }
+
+    /// Discards a pending change, e.g. to withdraw one's own request.
+    async fn reject_pending_change(context: &Context<Handler>, id: i32) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] reject_pending_change");
+        check_not_read_only(context, &span)?;
+        span.in_scope(|| {
+            debug!(?id);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(&span, "Unauthorized change rejection"))?;
+        handler.delete_pending_change(id).instrument(span).await?;
+        info!(
+            r#"Change {} was rejected by "{}""#,
+            id, &context.validation_result.user
+        );
         Ok(Success::new())
     }
 
+    /// Stops the deletion of an account requested by its user, and re-enables it.
+    async fn cancel_account_deletion(
+        context: &Context<Handler>,
+        user_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] cancel_account_deletion");
+        check_not_read_only(context, &span)?;
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized account deletion cancellation",
+            ))?;
+        let user_id = UserId::new(&user_id);
+        handler
+            .cancel_account_deletion(&user_id)
+            .instrument(span)
+            .await?;
+        info!(
+            r#"The deletion of "{}" was cancelled by "{}""#,
+            &user_id, &context.validation_result.user
+        );
+        Ok(Success::new())
+    }
+
+    /// Links an account at an external identity provider to the user, to log in as them.
+    async fn link_identity(
+        context: &Context<Handler>,
+        user_id: String,
+        provider: String,
+        subject: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] link_identity");
+        check_not_read_only(context, &span)?;
+        span.in_scope(|| {
+            debug!(?user_id, ?provider, ?subject);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(&span, "Unauthorized identity link"))?;
+        let user_id = UserId::new(&user_id);
+        handler
+            .add_identity_link(&user_id, &provider, &subject)
+            .instrument(span)
+            .await?;
+        info!(
+            r#"The {} identity "{}" was linked to "{}" by "{}""#,
+            &provider, &subject, &user_id, &context.validation_result.user
+        );
+        Ok(Success::new())
+    }
+
+    async fn unlink_identity(
+        context: &Context<Handler>,
+        provider: String,
+        subject: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] unlink_identity");
+        check_not_read_only(context, &span)?;
+        span.in_scope(|| {
+            debug!(?provider, ?subject);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(&span, "Unauthorized identity unlink"))?;
+        handler
+            .delete_identity_link(&provider, &subject)
+            .instrument(span)
+            .await?;
+        info!(
+            r#"The {} identity "{}" was unlinked by "{}""#,
+            &provider, &subject, &context.validation_result.user
+        );
+        Ok(Success::new())
+    }
+
+    /// Applies the group rules of the configuration to all the users, and returns the changes.
+    async fn apply_group_rules(context: &Context<Handler>) -> FieldResult<Vec<GroupRuleChange>> {
+        let span = debug_span!("[GraphQL mutation] apply_group_rules");
+        check_not_read_only(context, &span)?;
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized application of the group rules",
+            ))?;
+        let changes = handler.apply_group_rules().instrument(span).await?;
+        info!(
+            r#"{} memberships changed by the group rules, applied by "{}""#,
+            changes.len(),
+            &context.validation_result.user
+        );
+        Ok(changes.into_iter().map(Into::into).collect())
+    }
+
+    /// Turns the maintenance mode on or off. The message is kept if not given.
+    async fn set_maintenance_mode(
+        context: &Context<Handler>,
+        enabled: bool,
+        message: Option<String>,
+    ) -> FieldResult<MaintenanceMode> {
+        let span = debug_span!("[GraphQL mutation] set_maintenance_mode");
+        span.in_scope(|| {
+            debug!(?enabled, ?message);
+        });
+        context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized change of the maintenance mode",
+            ))?;
+        context.maintenance.set(enabled, message);
+        info!(
+            r#"Maintenance mode {} by "{}""#,
+            if enabled { "enabled" } else { "disabled" },
+            &context.validation_result.user
+        );
+        Ok(context.maintenance.status().into())
+    }
+
+    /// Turns the read-only mode on or off. While it is on, all the other changes are refused.
+    async fn set_read_only_mode(context: &Context<Handler>, enabled: bool) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] set_read_only_mode");
+        span.in_scope(|| {
+            debug!(?enabled);
+        });
+        context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized change of the read-only mode",
+            ))?;
+        context.read_only.set(enabled);
+        info!(
+            r#"Read-only mode {} by "{}""#,
+            if enabled { "enabled" } else { "disabled" },
+            &context.validation_result.user
+        );
+        Ok(Success::new())
+    }
+
+    /// Returns, as a JSON document, everything stored about the user: profile, attributes,
+    /// group memberships, recorded changes and sessions. Only the user themselves and the admins
+    /// can export it, not the accounts that can merely read the whole directory.
+    async fn export_user_data(context: &Context<Handler>, user_id: String) -> FieldResult<String> {
+        let span = debug_span!("[GraphQL mutation] export_user_data");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        let handler = context
+            .get_readable_handler(&user_id)
+            .filter(|_| {
+                context.validation_result.is_admin() || context.validation_result.user == user_id
+            })
+            .ok_or_else(field_error_callback(&span, "Unauthorized user data export"))?;
+        let export = export_user_data(handler, &user_id).instrument(span).await?;
+        info!(
+            r#"The data of "{}" was exported by "{}""#,
+            &user_id, &context.validation_result.user
+        );
+        Ok(serde_json::to_string_pretty(&export)?)
+    }
+
     async fn delete_group(context: &Context<Handler>, group_id: i32) -> FieldResult<Success> {
         let span = debug_span!("[GraphQL mutation] delete_group");
+        check_not_read_only(context, &span)?;
         span.in_scope(|| {
             debug!(?group_id);
         });
@@ -424,7 +1227,8 @@ impl<Handler: BackendHandler> Mutation<Handler> {
         handler
             .delete_group(GroupId(group_id))
             .instrument(span)
-            .await?;
+            .await
+            .map_err(domain_error)?;
         Ok(Success::new())
     }
 
@@ -437,6 +1241,7 @@ impl<Handler: BackendHandler> Mutation<Handler> {
         is_editable: bool,
     ) -> FieldResult<Success> {
         let span = debug_span!("[GraphQL mutation] add_user_attribute");
+        check_not_read_only(context, &span)?;
         span.in_scope(|| {
             debug!(?name, ?attribute_type, is_list, is_visible, is_editable);
         });
@@ -468,6 +1273,7 @@ impl<Handler: BackendHandler> Mutation<Handler> {
         is_editable: bool,
     ) -> FieldResult<Success> {
         let span = debug_span!("[GraphQL mutation] add_group_attribute");
+        check_not_read_only(context, &span)?;
         span.in_scope(|| {
             debug!(?name, ?attribute_type, is_list, is_visible, is_editable);
         });
@@ -495,6 +1301,7 @@ impl<Handler: BackendHandler> Mutation<Handler> {
         name: String,
     ) -> FieldResult<Success> {
         let span = debug_span!("[GraphQL mutation] delete_user_attribute");
+        check_not_read_only(context, &span)?;
         let name = AttributeName::from(name);
         span.in_scope(|| {
             debug!(?name);
@@ -526,6 +1333,7 @@ impl<Handler: BackendHandler> Mutation<Handler> {
         name: String,
     ) -> FieldResult<Success> {
         let span = debug_span!("[GraphQL mutation] delete_group_attribute");
+        check_not_read_only(context, &span)?;
         let name = AttributeName::from(name);
         span.in_scope(|| {
             debug!(?name);
@@ -552,11 +1360,90 @@ impl<Handler: BackendHandler> Mutation<Handler> {
         Ok(Success::new())
     }
 
+    /// Sets the value given to a user attribute when a user joins the group. `{uid}` and
+    /// `{email}` are replaced by the user's values. Existing values are only replaced if
+    /// `overwrite` is set.
+    async fn set_group_attribute_template(
+        context: &Context<Handler>,
+        group_id: i32,
+        attribute: String,
+        value: String,
+        overwrite: bool,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] set_group_attribute_template");
+        check_not_read_only(context, &span)?;
+        let attribute_name = AttributeName::from(attribute);
+        span.in_scope(|| {
+            debug!(?group_id, ?attribute_name, ?value, overwrite);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized attribute template modification",
+            ))?;
+        let schema = handler.get_schema().await?;
+        let attribute_schema = schema
+            .get_schema()
+            .user_attributes
+            .get_attribute_schema(&attribute_name)
+            .ok_or_else(|| anyhow!("Attribute {} is not defined in the schema", &attribute_name))?;
+        if attribute_schema.is_hardcoded {
+            return Err(anyhow!(
+                "Permission denied: Attribute {} cannot be templated",
+                &attribute_name
+            )
+            .into());
+        }
+        if attribute_schema.attribute_type != AttributeType::String || attribute_schema.is_list {
+            return Err(anyhow!(
+                "Attribute {} is not a single string attribute",
+                &attribute_name
+            )
+            .into());
+        }
+        handler
+            .set_attribute_template(AttributeTemplate {
+                group_id: GroupId(group_id),
+                attribute_name,
+                value,
+                overwrite,
+            })
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    async fn delete_group_attribute_template(
+        context: &Context<Handler>,
+        group_id: i32,
+        attribute: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] delete_group_attribute_template");
+        check_not_read_only(context, &span)?;
+        let attribute_name = AttributeName::from(attribute);
+        span.in_scope(|| {
+            debug!(?group_id, ?attribute_name);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized attribute template modification",
+            ))?;
+        handler
+            .delete_attribute_template(GroupId(group_id), &attribute_name)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
     async fn add_user_object_class(
         context: &Context<Handler>,
         name: String,
     ) -> FieldResult<Success> {
         let span = debug_span!("[GraphQL mutation] add_user_object_class");
+        check_not_read_only(context, &span)?;
         span.in_scope(|| {
             debug!(?name);
         });
@@ -578,6 +1465,7 @@ impl<Handler: BackendHandler> Mutation<Handler> {
         name: String,
     ) -> FieldResult<Success> {
         let span = debug_span!("[GraphQL mutation] add_group_object_class");
+        check_not_read_only(context, &span)?;
         span.in_scope(|| {
             debug!(?name);
         });
@@ -599,6 +1487,7 @@ impl<Handler: BackendHandler> Mutation<Handler> {
         name: String,
     ) -> FieldResult<Success> {
         let span = debug_span!("[GraphQL mutation] delete_user_object_class");
+        check_not_read_only(context, &span)?;
         span.in_scope(|| {
             debug!(?name);
         });
@@ -620,6 +1509,7 @@ impl<Handler: BackendHandler> Mutation<Handler> {
         name: String,
     ) -> FieldResult<Success> {
         let span = debug_span!("[GraphQL mutation] delete_group_object_class");
+        check_not_read_only(context, &span)?;
         span.in_scope(|| {
             debug!(?name);
         });
@@ -656,8 +1546,12 @@ async fn create_group_with_details<Handler: BackendHandler>(
         display_name: request.display_name.into(),
         attributes,
     };
-    let group_id = handler.create_group(request).await?;
-    let group_details = handler.get_group_details(group_id).instrument(span).await?;
+    let group_id = handler.create_group(request).await.map_err(domain_error)?;
+    let group_details = handler
+        .get_group_details(group_id)
+        .instrument(span)
+        .await
+        .map_err(domain_error)?;
     super::query::Group::<Handler>::from_group_details(group_details, Arc::new(schema))
 }
 
@@ -695,3 +1589,676 @@ fn deserialize_attribute(
         value: deserialized_values,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::{
+            handler::GroupRequestFilter,
+            identity_links::IdentityLink,
+            pending_changes::PendingChange,
+            types::{Group, GroupDetails},
+        },
+        infra::{
+            access_control::{Permission, ValidationResults},
+            graphql::query::Query,
+            read_only::READ_ONLY_MESSAGE,
+            test_utils::{setup_default_schema, MockTestBackendHandler},
+        },
+    };
+    use juniper::{execute, graphql_value, EmptySubscription, RootNode, Variables};
+    use mockall::predicate::eq;
+    use pretty_assertions::assert_eq;
+    use std::collections::HashSet;
+
+    async fn run_mutation(
+        mock: MockTestBackendHandler,
+        query: &str,
+    ) -> juniper::Value<juniper::DefaultScalarValue> {
+        let context =
+            Context::<MockTestBackendHandler>::new_for_tests(mock, ValidationResults::admin());
+        run_mutation_with_context(context, query).await
+    }
+
+    async fn run_mutation_with_context(
+        context: Context<MockTestBackendHandler>,
+        query: &str,
+    ) -> juniper::Value<juniper::DefaultScalarValue> {
+        let schema = RootNode::new(
+            Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+            EmptySubscription::<Context<MockTestBackendHandler>>::new(),
+        );
+        let (result, errors) = execute(query, None, &schema, &Variables::new(), &context)
+            .await
+            .unwrap();
+        assert_eq!(errors, vec![]);
+        result
+    }
+
+    const UPSERT_BOB: &str = r#"mutation {
+      createOrUpdateUser(user: {id: "bob", email: "bob@bobbers.on", firstName: "Bob"}) {
+        outcome
+      }
+    }"#;
+
+    fn bob() -> User {
+        User {
+            user_id: UserId::new("bob"),
+            email: "bob@bobbers.on".into(),
+            attributes: vec![DomainAttributeValue {
+                name: "first_name".into(),
+                value: Serialized::from("Bob"),
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn create_or_update_user_creates() {
+        let mut mock = MockTestBackendHandler::new();
+        setup_default_schema(&mut mock);
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| Err(DomainError::EntityNotFound("bob".to_owned())));
+        mock.expect_create_user()
+            .with(eq(CreateUserRequest {
+                user_id: UserId::new("bob"),
+                email: "bob@bobbers.on".into(),
+                first_name: Some("Bob".to_owned()),
+                source: UserCreationSource::Graphql,
+                ..Default::default()
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        assert_eq!(
+            run_mutation(mock, UPSERT_BOB).await,
+            graphql_value!({"createOrUpdateUser": {"outcome": "CREATED"}})
+        );
+    }
+
+    #[tokio::test]
+    async fn create_or_update_user_unchanged() {
+        let mut mock = MockTestBackendHandler::new();
+        setup_default_schema(&mut mock);
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| Ok(bob()));
+        mock.expect_update_user().never();
+        assert_eq!(
+            run_mutation(mock, UPSERT_BOB).await,
+            graphql_value!({"createOrUpdateUser": {"outcome": "UNCHANGED"}})
+        );
+    }
+
+    #[tokio::test]
+    async fn create_or_update_user_updates_only_changes() {
+        let mut mock = MockTestBackendHandler::new();
+        setup_default_schema(&mut mock);
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| {
+                Ok(User {
+                    email: "old@bobbers.on".into(),
+                    ..bob()
+                })
+            });
+        mock.expect_update_user()
+            .with(eq(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                email: Some("bob@bobbers.on".into()),
+                actor: Some(UserId::new("admin")),
+                ..Default::default()
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        assert_eq!(
+            run_mutation(mock, UPSERT_BOB).await,
+            graphql_value!({"createOrUpdateUser": {"outcome": "UPDATED"}})
+        );
+    }
+
+    #[tokio::test]
+    async fn ensure_group_membership() {
+        const QUERY: &str = r#"mutation {
+          ensureGroupMembership(userId: "bob", groupId: 3) {
+            outcome
+          }
+        }"#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| {
+                Ok(HashSet::from([GroupDetails {
+                    group_id: GroupId(3),
+                    display_name: "Bobbersons".into(),
+                    creation_date: chrono::Utc::now().naive_utc(),
+                    modified_date: chrono::Utc::now().naive_utc(),
+                    uuid: crate::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    attributes: Vec::new(),
+                }]))
+            });
+        mock.expect_add_user_to_group().never();
+        assert_eq!(
+            run_mutation(mock, QUERY).await,
+            graphql_value!({"ensureGroupMembership": {"outcome": "UNCHANGED"}})
+        );
+
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| Ok(HashSet::new()));
+        mock.expect_add_user_to_group()
+            .with(eq(UserId::new("bob")), eq(GroupId(3)))
+            .times(1)
+            .return_once(|_, _| Ok(()));
+        assert_eq!(
+            run_mutation(mock, QUERY).await,
+            graphql_value!({"ensureGroupMembership": {"outcome": "CREATED"}})
+        );
+    }
+
+    #[tokio::test]
+    async fn add_user_to_group_by_uuid() {
+        const QUERY: &str = r#"mutation {
+          addUserToGroupByUuid(userId: "bob", groupUuid: "a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8") {
+            ok
+          }
+        }"#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::Uuid(crate::uuid!(
+                "a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8"
+            )))))
+            .return_once(|_| {
+                Ok(vec![Group {
+                    id: GroupId(3),
+                    display_name: "Bobbersons".into(),
+                    creation_date: chrono::Utc::now().naive_utc(),
+                    modified_date: chrono::Utc::now().naive_utc(),
+                    uuid: crate::uuid!("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8"),
+                    users: Vec::new(),
+                    attributes: Vec::new(),
+                }])
+            });
+        mock.expect_add_user_to_group()
+            .with(eq(UserId::new("bob")), eq(GroupId(3)))
+            .times(1)
+            .return_once(|_, _| Ok(()));
+        assert_eq!(
+            run_mutation(mock, QUERY).await,
+            graphql_value!({"addUserToGroupByUuid": {"ok": true}})
+        );
+    }
+
+    fn four_eyes_context(
+        mock: MockTestBackendHandler,
+        user: &str,
+    ) -> Context<MockTestBackendHandler> {
+        let mut context = Context::<MockTestBackendHandler>::new_for_tests(
+            mock,
+            ValidationResults {
+                user: UserId::new(user),
+                ..ValidationResults::admin()
+            },
+        );
+        context.four_eyes_approval = true;
+        context
+    }
+
+    #[tokio::test]
+    async fn four_eyes_delete_user_is_pending() {
+        const QUERY: &str = r#"mutation {
+          deleteUser(userId: "bob") {
+            ok
+            pendingChangeId
+          }
+        }"#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_create_pending_change()
+            .with(
+                eq(UserId::new("admin")),
+                eq(SensitiveChange::DeleteUser(UserId::new("bob"))),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(3));
+        mock.expect_delete_user().never();
+        assert_eq!(
+            run_mutation_with_context(four_eyes_context(mock, "admin"), QUERY).await,
+            graphql_value!({"deleteUser": {"ok": true, "pendingChangeId": 3}})
+        );
+    }
+
+    #[tokio::test]
+    async fn four_eyes_regular_group_membership_is_applied() {
+        const QUERY: &str = r#"mutation {
+          addUserToGroup(userId: "bob", groupId: 3) {
+            pendingChangeId
+          }
+        }"#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_create_pending_change().never();
+        mock.expect_add_user_to_group()
+            .with(eq(UserId::new("bob")), eq(GroupId(3)))
+            .times(1)
+            .return_once(|_, _| Ok(()));
+        assert_eq!(
+            run_mutation_with_context(four_eyes_context(mock, "admin"), QUERY).await,
+            graphql_value!({"addUserToGroup": {"pendingChangeId": None}})
+        );
+    }
+
+    #[tokio::test]
+    async fn four_eyes_ensure_admin_membership_is_pending() {
+        const QUERY: &str = r#"mutation {
+          ensureGroupMembership(userId: "bob", groupId: 1) {
+            outcome
+            pendingChangeId
+          }
+        }"#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| Ok(HashSet::new()));
+        mock.expect_create_pending_change()
+            .with(
+                eq(UserId::new("admin")),
+                eq(SensitiveChange::AddUserToGroup {
+                    user_id: UserId::new("bob"),
+                    group_id: GroupId(1),
+                }),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(3));
+        mock.expect_add_user_to_group().never();
+        assert_eq!(
+            run_mutation_with_context(four_eyes_context(mock, "admin"), QUERY).await,
+            graphql_value!({"ensureGroupMembership": {"outcome": "PENDING", "pendingChangeId": 3}})
+        );
+    }
+
+    #[tokio::test]
+    async fn four_eyes_admin_membership_expiry_is_pending() {
+        const QUERY: &str = r#"mutation {
+          setMembershipExpiry(userId: "bob", groupId: 1, expiryDate: null) {
+            pendingChangeId
+          }
+        }"#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_create_pending_change()
+            .with(
+                eq(UserId::new("admin")),
+                eq(SensitiveChange::SetMembershipExpiry {
+                    user_id: UserId::new("bob"),
+                    group_id: GroupId(1),
+                    expiry_date: None,
+                }),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(3));
+        mock.expect_set_membership_expiry().never();
+        assert_eq!(
+            run_mutation_with_context(four_eyes_context(mock, "admin"), QUERY).await,
+            graphql_value!({"setMembershipExpiry": {"pendingChangeId": 3}})
+        );
+    }
+
+    fn pending_admin_membership() -> PendingChange {
+        PendingChange {
+            id: 3,
+            requested_by: UserId::new("admin"),
+            request_date: chrono::Utc::now().naive_utc(),
+            change: SensitiveChange::AddUserToGroup {
+                user_id: UserId::new("bob"),
+                group_id: GroupId(1),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn approve_pending_change() {
+        const QUERY: &str = r#"mutation {
+          approvePendingChange(id: 3) {
+            ok
+          }
+        }"#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_pending_change()
+            .with(eq(3))
+            .return_once(|_| Ok(pending_admin_membership()));
+        mock.expect_add_user_to_group()
+            .with(eq(UserId::new("bob")), eq(GroupId(1)))
+            .times(1)
+            .return_once(|_, _| Ok(()));
+        mock.expect_delete_pending_change()
+            .with(eq(3))
+            .times(1)
+            .return_once(|_| Ok(()));
+        assert_eq!(
+            run_mutation_with_context(four_eyes_context(mock, "other_admin"), QUERY).await,
+            graphql_value!({"approvePendingChange": {"ok": true}})
+        );
+    }
+
+    #[tokio::test]
+    async fn approve_already_claimed_pending_change_fails() {
+        const QUERY: &str = r#"mutation {
+          approvePendingChange(id: 3) {
+            ok
+          }
+        }"#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_pending_change()
+            .with(eq(3))
+            .return_once(|_| Ok(pending_admin_membership()));
+        // Another approval deleted it in the meantime.
+        mock.expect_delete_pending_change()
+            .with(eq(3))
+            .times(1)
+            .return_once(|_| Err(DomainError::EntityNotFound("No such pending change".into())));
+        mock.expect_add_user_to_group().never();
+        let context = four_eyes_context(mock, "other_admin");
+        let schema = RootNode::new(
+            Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+            EmptySubscription::<Context<MockTestBackendHandler>>::new(),
+        );
+        let (_, errors) = execute(QUERY, None, &schema, &Variables::new(), &context)
+            .await
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn approve_own_pending_change_fails() {
+        const QUERY: &str = r#"mutation {
+          approvePendingChange(id: 3) {
+            ok
+          }
+        }"#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_pending_change()
+            .with(eq(3))
+            .return_once(|_| Ok(pending_admin_membership()));
+        mock.expect_add_user_to_group().never();
+        mock.expect_delete_pending_change().never();
+        let context = four_eyes_context(mock, "admin");
+        let schema = RootNode::new(
+            Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+            EmptySubscription::<Context<MockTestBackendHandler>>::new(),
+        );
+        let (_, errors) = execute(QUERY, None, &schema, &Variables::new(), &context)
+            .await
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn read_only_mode_refuses_changes() {
+        const QUERY: &str = r#"mutation {
+          deleteUser(userId: "bob") {
+            ok
+          }
+        }"#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_delete_user().never();
+        let context =
+            Context::<MockTestBackendHandler>::new_for_tests(mock, ValidationResults::admin());
+        context.read_only.set(true);
+        let schema = RootNode::new(
+            Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+            EmptySubscription::<Context<MockTestBackendHandler>>::new(),
+        );
+        let (_, errors) = execute(QUERY, None, &schema, &Variables::new(), &context)
+            .await
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].error().message(), READ_ONLY_MESSAGE);
+        // The admins can still turn it off.
+        assert_eq!(
+            run_mutation_with_context(
+                context,
+                r#"mutation { setReadOnlyMode(enabled: false) { ok } }"#
+            )
+            .await,
+            graphql_value!({"setReadOnlyMode": {"ok": true}})
+        );
+    }
+
+    #[tokio::test]
+    async fn cancel_account_deletion() {
+        const QUERY: &str = r#"mutation {
+          cancelAccountDeletion(userId: "bob") {
+            ok
+          }
+        }"#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_cancel_account_deletion()
+            .with(eq(UserId::new("bob")))
+            .times(1)
+            .return_once(|_| Ok(()));
+        assert_eq!(
+            run_mutation(mock, QUERY).await,
+            graphql_value!({"cancelAccountDeletion": {"ok": true}})
+        );
+    }
+
+    #[tokio::test]
+    async fn link_identity() {
+        const QUERY: &str = r#"mutation {
+          linkIdentity(userId: "bob", provider: "google", subject: "1234") {
+            ok
+          }
+        }"#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_add_identity_link()
+            .withf(|user_id, provider, subject| {
+                user_id == &UserId::new("bob") && provider == "google" && subject == "1234"
+            })
+            .times(1)
+            .return_once(|user_id, provider, subject| {
+                Ok(IdentityLink {
+                    provider: provider.to_owned(),
+                    subject: subject.to_owned(),
+                    user_id: user_id.clone(),
+                    creation_date: chrono::Utc::now().naive_utc(),
+                })
+            });
+        assert_eq!(
+            run_mutation(mock, QUERY).await,
+            graphql_value!({"linkIdentity": {"ok": true}})
+        );
+    }
+
+    #[tokio::test]
+    async fn link_identity_requires_admin() {
+        const QUERY: &str = r#"mutation {
+          linkIdentity(userId: "bob", provider: "google", subject: "1234") {
+            ok
+          }
+        }"#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_add_identity_link().never();
+        let context = Context::<MockTestBackendHandler>::new_for_tests(
+            mock,
+            ValidationResults {
+                user: UserId::new("bob"),
+                permission: Permission::Regular,
+                impersonation: None,
+            },
+        );
+        let schema = RootNode::new(
+            Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+            EmptySubscription::<Context<MockTestBackendHandler>>::new(),
+        );
+        let (_, errors) = execute(QUERY, None, &schema, &Variables::new(), &context)
+            .await
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn set_avatar_sync_opt_out() {
+        const QUERY: &str = r#"mutation {
+          setAvatarSyncOptOut(userId: "bob", optOut: true) {
+            ok
+          }
+        }"#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_set_avatar_sync_opt_out()
+            .with(eq(UserId::new("bob")), eq(true))
+            .times(1)
+            .return_once(|_, _| Ok(()));
+        let context = Context::<MockTestBackendHandler>::new_for_tests(
+            mock,
+            ValidationResults {
+                user: UserId::new("bob"),
+                permission: Permission::Regular,
+                impersonation: None,
+            },
+        );
+        assert_eq!(
+            run_mutation_with_context(context, QUERY).await,
+            graphql_value!({"setAvatarSyncOptOut": {"ok": true}})
+        );
+    }
+
+    #[tokio::test]
+    async fn set_group_attribute_template_rejects_hardcoded_attributes() {
+        const QUERY: &str = r#"mutation {
+          setGroupAttributeTemplate(groupId: 3, attribute: "first_name", value: "{uid}", overwrite: false) {
+            ok
+          }
+        }"#;
+        let mut mock = MockTestBackendHandler::new();
+        setup_default_schema(&mut mock);
+        mock.expect_set_attribute_template().never();
+        let context =
+            Context::<MockTestBackendHandler>::new_for_tests(mock, ValidationResults::admin());
+        let schema = RootNode::new(
+            Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+            EmptySubscription::<Context<MockTestBackendHandler>>::new(),
+        );
+        let (_, errors) = execute(QUERY, None, &schema, &Variables::new(), &context)
+            .await
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn start_access_review_needs_groups() {
+        const QUERY: &str = r#"mutation {
+          startAccessReview(name: "Q3 review", groups: []) {
+            id
+          }
+        }"#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_start_access_review().never();
+        let context =
+            Context::<MockTestBackendHandler>::new_for_tests(mock, ValidationResults::admin());
+        let schema = RootNode::new(
+            Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+            EmptySubscription::<Context<MockTestBackendHandler>>::new(),
+        );
+        let (_, errors) = execute(QUERY, None, &schema, &Variables::new(), &context)
+            .await
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn delete_group_attribute_template() {
+        const QUERY: &str = r#"mutation {
+          deleteGroupAttributeTemplate(groupId: 3, attribute: "home_directory") {
+            ok
+          }
+        }"#;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_delete_attribute_template()
+            .with(eq(GroupId(3)), eq(AttributeName::from("home_directory")))
+            .times(1)
+            .return_once(|_, _| Ok(()));
+        assert_eq!(
+            run_mutation(mock, QUERY).await,
+            graphql_value!({"deleteGroupAttributeTemplate": {"ok": true}})
+        );
+    }
+
+    #[tokio::test]
+    async fn export_user_data() {
+        const QUERY: &str = r#"mutation {
+          exportUserData(userId: "bob")
+        }"#;
+        let mut mock = MockTestBackendHandler::new();
+        setup_default_schema(&mut mock);
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| Ok(bob()));
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| {
+                Ok(HashSet::from([GroupDetails {
+                    group_id: GroupId(3),
+                    display_name: "Bobbersons".into(),
+                    creation_date: chrono::Utc::now().naive_utc(),
+                    modified_date: chrono::Utc::now().naive_utc(),
+                    uuid: crate::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    attributes: Vec::new(),
+                }]))
+            });
+        mock.expect_list_changes_for_user()
+            .return_once(|_| Ok(Vec::new()));
+        mock.expect_list_user_sessions()
+            .return_once(|_| Ok(Vec::new()));
+        mock.expect_get_account_deletion().return_once(|_| Ok(None));
+        let result = run_mutation(mock, QUERY).await;
+        let export: serde_json::Value = serde_json::from_str(
+            result
+                .as_object_value()
+                .unwrap()
+                .get_field_value("exportUserData")
+                .unwrap()
+                .as_string_value()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(export["user"]["id"], "bob");
+        assert_eq!(export["user"]["attributes"]["first_name"][0], "Bob");
+        assert_eq!(export["groups"][0]["displayName"], "Bobbersons");
+        assert!(export["accountDeletion"].is_null());
+    }
+
+    #[tokio::test]
+    async fn export_user_data_requires_admin_or_self() {
+        const QUERY: &str = r#"mutation {
+          exportUserData(userId: "bob")
+        }"#;
+        let schema = RootNode::new(
+            Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+            EmptySubscription::<Context<MockTestBackendHandler>>::new(),
+        );
+        for permission in [Permission::Readonly, Permission::PasswordManager] {
+            let mut mock = MockTestBackendHandler::new();
+            mock.expect_get_user_details().never();
+            let context = Context::<MockTestBackendHandler>::new_for_tests(
+                mock,
+                ValidationResults {
+                    user: UserId::new("reader"),
+                    permission,
+                    impersonation: None,
+                },
+            );
+            let (_, errors) = execute(QUERY, None, &schema, &Variables::new(), &context)
+                .await
+                .unwrap();
+            assert_eq!(errors.len(), 1);
+        }
+    }
+}
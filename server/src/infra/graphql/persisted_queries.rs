@@ -0,0 +1,250 @@
+//! Persisted queries: the clients send the SHA-256 hash of a pre-registered query instead of its
+//! text, as in the Apollo protocol (`"extensions": {"persistedQuery": {"sha256Hash": "..."}}`).
+//! Unlike Apollo, the queries are never registered at runtime.
+//!
+//! The queries of the web UI are always persisted, so that it keeps working when only the persisted
+//! queries are allowed.
+
+use crate::infra::configuration::GraphqlOptions;
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use tracing::info;
+
+/// The hex SHA-256 hash of the query, as sent by the clients.
+pub fn query_hash(query: &str) -> String {
+    Sha256::digest(query.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The queries sent by the web UI, as they are sent: the whole file.
+const WEB_UI_QUERIES: &[&str] = &[
+    include_str!("../../../../app/queries/add_user_to_group.graphql"),
+    include_str!("../../../../app/queries/approve_pending_change.graphql"),
+    include_str!("../../../../app/queries/cancel_account_deletion.graphql"),
+    include_str!("../../../../app/queries/create_group.graphql"),
+    include_str!("../../../../app/queries/create_group_attribute.graphql"),
+    include_str!("../../../../app/queries/create_user.graphql"),
+    include_str!("../../../../app/queries/create_user_attribute.graphql"),
+    include_str!("../../../../app/queries/delete_group.graphql"),
+    include_str!("../../../../app/queries/delete_group_attribute.graphql"),
+    include_str!("../../../../app/queries/delete_user.graphql"),
+    include_str!("../../../../app/queries/delete_user_attribute.graphql"),
+    include_str!("../../../../app/queries/export_user_data.graphql"),
+    include_str!("../../../../app/queries/get_account_deletions.graphql"),
+    include_str!("../../../../app/queries/get_directory.graphql"),
+    include_str!("../../../../app/queries/get_group_attributes_schema.graphql"),
+    include_str!("../../../../app/queries/get_group_details.graphql"),
+    include_str!("../../../../app/queries/get_group_list.graphql"),
+    include_str!("../../../../app/queries/get_pending_changes.graphql"),
+    include_str!("../../../../app/queries/get_user_attributes_schema.graphql"),
+    include_str!("../../../../app/queries/get_user_details.graphql"),
+    include_str!("../../../../app/queries/get_user_preferences.graphql"),
+    include_str!("../../../../app/queries/get_user_profile_history.graphql"),
+    include_str!("../../../../app/queries/list_users.graphql"),
+    include_str!("../../../../app/queries/reject_pending_change.graphql"),
+    include_str!("../../../../app/queries/remove_user_from_group.graphql"),
+    include_str!("../../../../app/queries/search_groups.graphql"),
+    include_str!("../../../../app/queries/update_group.graphql"),
+    include_str!("../../../../app/queries/update_user.graphql"),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PersistedQueryError {
+    /// The hash is not registered.
+    NotFound,
+    /// The query sent along with the hash doesn't match it.
+    HashMismatch,
+    /// The query is not persisted, and only the persisted ones are allowed.
+    NotAllowed,
+    /// Neither a query nor a hash.
+    MissingQuery,
+}
+
+impl PersistedQueryError {
+    /// The error message, and the `code` extension of the error.
+    pub fn message_and_code(self) -> (&'static str, &'static str) {
+        match self {
+            // The message expected by the Apollo clients.
+            Self::NotFound => ("PersistedQueryNotFound", "PERSISTED_QUERY_NOT_FOUND"),
+            Self::HashMismatch => (
+                "The query doesn't match its persisted query hash",
+                "BAD_REQUEST",
+            ),
+            Self::NotAllowed => (
+                "Only the persisted queries are allowed",
+                "PERSISTED_QUERY_REQUIRED",
+            ),
+            Self::MissingQuery => ("Missing the query", "BAD_REQUEST"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PersistedQueries {
+    /// By hash.
+    queries: HashMap<String, String>,
+    only: bool,
+}
+
+fn parse_persisted_queries(content: &str) -> Result<HashMap<String, String>> {
+    let queries: HashMap<String, String> = serde_json::from_str(content)?;
+    queries
+        .into_iter()
+        .map(|(hash, query)| {
+            let hash = hash.to_ascii_lowercase();
+            if query_hash(&query) != hash {
+                bail!(r#"The hash "{}" is not the SHA-256 of its query"#, hash);
+            }
+            Ok((hash, query))
+        })
+        .collect()
+}
+
+fn web_ui_queries() -> HashMap<String, String> {
+    WEB_UI_QUERIES
+        .iter()
+        .map(|query| (query_hash(query), (*query).to_owned()))
+        .collect()
+}
+
+impl PersistedQueries {
+    pub fn from_options(options: &GraphqlOptions) -> Result<Self> {
+        let mut queries = web_ui_queries();
+        if let Some(path) = &options.persisted_queries_file {
+            let file_queries = std::fs::read_to_string(path)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| parse_persisted_queries(&content))
+                .with_context(|| format!("while reading the persisted queries in {}", path))?;
+            info!("Loaded {} persisted GraphQL queries", file_queries.len());
+            queries.extend(file_queries);
+        }
+        Ok(Self {
+            queries,
+            only: options.persisted_queries_only,
+        })
+    }
+
+    /// The text of the query to run.
+    pub fn resolve(
+        &self,
+        query: Option<String>,
+        hash: Option<&str>,
+    ) -> Result<String, PersistedQueryError> {
+        let hash = hash.map(str::to_ascii_lowercase);
+        let (query, is_persisted) = match (query, hash) {
+            (Some(query), Some(hash)) => {
+                if query_hash(&query) != hash {
+                    return Err(PersistedQueryError::HashMismatch);
+                }
+                let is_persisted = self.queries.contains_key(&hash);
+                (query, is_persisted)
+            }
+            (None, Some(hash)) => (
+                self.queries
+                    .get(&hash)
+                    .cloned()
+                    .ok_or(PersistedQueryError::NotFound)?,
+                true,
+            ),
+            (Some(query), None) => {
+                let is_persisted = self.queries.contains_key(&query_hash(&query));
+                (query, is_persisted)
+            }
+            (None, None) => return Err(PersistedQueryError::MissingQuery),
+        };
+        if self.only && !is_persisted {
+            return Err(PersistedQueryError::NotAllowed);
+        }
+        Ok(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::configuration::GraphqlOptionsBuilder;
+    use pretty_assertions::assert_eq;
+
+    const QUERY: &str = "query { me { id } }";
+
+    fn persisted_queries(only: bool) -> PersistedQueries {
+        PersistedQueries {
+            queries: HashMap::from([(query_hash(QUERY), QUERY.to_owned())]),
+            only,
+        }
+    }
+
+    #[test]
+    fn test_parse_persisted_queries() {
+        let content = format!(r#"{{"{}": "{}"}}"#, query_hash(QUERY).to_uppercase(), QUERY);
+        assert_eq!(
+            parse_persisted_queries(&content).unwrap(),
+            HashMap::from([(query_hash(QUERY), QUERY.to_owned())])
+        );
+        assert!(parse_persisted_queries(&format!(r#"{{"abcd": "{}"}}"#, QUERY)).is_err());
+    }
+
+    #[test]
+    fn test_web_ui_queries_are_persisted() {
+        let queries = PersistedQueries::from_options(
+            &GraphqlOptionsBuilder::default()
+                .persisted_queries_only(true)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(queries.queries.len(), WEB_UI_QUERIES.len());
+        let list_users = include_str!("../../../../app/queries/list_users.graphql");
+        assert_eq!(
+            queries.resolve(Some(list_users.to_owned()), None),
+            Ok(list_users.to_owned())
+        );
+        assert_eq!(
+            queries.resolve(Some("{ other }".to_owned()), None),
+            Err(PersistedQueryError::NotAllowed)
+        );
+    }
+
+    #[test]
+    fn test_resolve_by_hash() {
+        let queries = persisted_queries(false);
+        let hash = query_hash(QUERY);
+        assert_eq!(queries.resolve(None, Some(&hash)), Ok(QUERY.to_owned()));
+        assert_eq!(
+            queries.resolve(Some(QUERY.to_owned()), Some(&hash.to_uppercase())),
+            Ok(QUERY.to_owned())
+        );
+        assert_eq!(
+            queries.resolve(None, Some(&query_hash("{ other }"))),
+            Err(PersistedQueryError::NotFound)
+        );
+        assert_eq!(
+            queries.resolve(Some("{ other }".to_owned()), Some(&hash)),
+            Err(PersistedQueryError::HashMismatch)
+        );
+        assert_eq!(
+            queries.resolve(None, None),
+            Err(PersistedQueryError::MissingQuery)
+        );
+    }
+
+    #[test]
+    fn test_resolve_persisted_only() {
+        let queries = persisted_queries(true);
+        assert_eq!(
+            queries.resolve(Some(QUERY.to_owned()), None),
+            Ok(QUERY.to_owned())
+        );
+        assert_eq!(
+            queries.resolve(Some("{ other }".to_owned()), None),
+            Err(PersistedQueryError::NotAllowed)
+        );
+        assert_eq!(
+            persisted_queries(false).resolve(Some("{ other }".to_owned()), None),
+            Ok("{ other }".to_owned())
+        );
+    }
+}
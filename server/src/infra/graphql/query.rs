@@ -1,24 +1,49 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use crate::{
     domain::{
+        access_reviews::{
+            AccessReview as DomainAccessReview, AccessReviewDecision as DomainAccessReviewDecision,
+        },
+        account_deletions::AccountDeletion as DomainAccountDeletion,
+        attribute_templates::AttributeTemplate as DomainAttributeTemplate,
+        change_events::{ChangeEvent, ChangeFeedEntry},
+        computed_attributes::render_user_template,
         deserialize::deserialize_attribute_value,
-        handler::{BackendHandler, ReadSchemaBackendHandler},
-        ldap::utils::{map_user_field, UserFieldType},
+        error::DomainError,
+        group_rules::GroupRuleChange as DomainGroupRuleChange,
+        handler::{
+            BackendHandler, GroupRequestFilter, ReadSchemaBackendHandler, SubStringFilter,
+            UserListerBackendHandler,
+        },
+        identity_links::IdentityLink as DomainIdentityLink,
+        ldap::utils::{map_group_field, map_user_field, GroupFieldType, UserFieldType},
         model::UserColumn,
+        pending_changes::{PendingChange as DomainPendingChange, SensitiveChange},
+        profile_history::ProfileChange as DomainProfileChange,
         schema::PublicSchema,
         types::{
-            AttributeType, GroupDetails, GroupId, JpegPhoto, LdapObjectClass, Serialized, UserId,
+            AttributeName, AttributeType, GroupDetails, GroupId, JpegPhoto, LdapObjectClass,
+            Serialized, UserId, Uuid,
         },
     },
     infra::{
-        access_control::{ReadonlyBackendHandler, UserReadableBackendHandler},
-        graphql::api::{field_error_callback, Context},
+        access_control::{AdminBackendHandler, ReadonlyBackendHandler, UserReadableBackendHandler},
+        configuration::DirectoryVisibility,
+        expiry_monitor::ExpiryStatus as DomainExpiryStatus,
+        graphql::api::{domain_error, field_error_callback, Context},
+        ldap_metrics::{
+            LdapOperationStats as DomainLdapOperationStats,
+            TlsHandshakeStats as DomainTlsHandshakeStats,
+        },
+        maintenance::MaintenanceStatus,
     },
 };
 use anyhow::Context as AnyhowContext;
 use chrono::{NaiveDateTime, TimeZone};
-use juniper::{graphql_object, FieldResult, GraphQLInputObject};
+use juniper::{
+    graphql_object, FieldError, FieldResult, GraphQLEnum, GraphQLInputObject, GraphQLObject,
+};
 use serde::{Deserialize, Serialize};
 use tracing::{debug, debug_span, Instrument, Span};
 
@@ -110,6 +135,78 @@ pub struct EqualityConstraint {
     value: String,
 }
 
+#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+/// Like `RequestFilter`, for the groups. Only one of the fields can be set at a time.
+pub struct GroupFilter {
+    any: Option<Vec<GroupFilter>>,
+    all: Option<Vec<GroupFilter>>,
+    not: Option<Box<GroupFilter>>,
+    eq: Option<EqualityConstraint>,
+    /// Case-insensitive substring of the display name.
+    display_name_contains: Option<String>,
+}
+
+impl GroupFilter {
+    fn try_into_domain_filter(self, schema: &PublicSchema) -> FieldResult<GroupRequestFilter> {
+        match (
+            self.eq,
+            self.any,
+            self.all,
+            self.not,
+            self.display_name_contains,
+        ) {
+            (Some(eq), None, None, None, None) => {
+                match map_group_field(&eq.field.as_str().into(), schema) {
+                    GroupFieldType::DisplayName => {
+                        Ok(GroupRequestFilter::DisplayName(eq.value.into()))
+                    }
+                    GroupFieldType::Uuid => Ok(GroupRequestFilter::Uuid(
+                        Uuid::try_from(eq.value.as_str())
+                            .map_err(|e| format!("Invalid UUID: {:#}", e))?,
+                    )),
+                    GroupFieldType::Member => {
+                        Ok(GroupRequestFilter::Member(UserId::new(&eq.value)))
+                    }
+                    GroupFieldType::Attribute(name, typ, false) => {
+                        let value = deserialize_attribute_value(&[eq.value], typ, false)
+                            .context(format!("While deserializing attribute {}", &name))?;
+                        Ok(GroupRequestFilter::AttributeEquality(name, value))
+                    }
+                    GroupFieldType::Attribute(_, _, true) => {
+                        Err("Equality not supported for list fields".into())
+                    }
+                    GroupFieldType::NoMatch => {
+                        Err(format!("Unknown group filter: {}", &eq.field).into())
+                    }
+                    _ => Err(format!("Equality not supported for field {}", &eq.field).into()),
+                }
+            }
+            (None, Some(any), None, None, None) => Ok(GroupRequestFilter::Or(
+                any.into_iter()
+                    .map(|f| f.try_into_domain_filter(schema))
+                    .collect::<FieldResult<Vec<_>>>()?,
+            )),
+            (None, None, Some(all), None, None) => Ok(GroupRequestFilter::And(
+                all.into_iter()
+                    .map(|f| f.try_into_domain_filter(schema))
+                    .collect::<FieldResult<Vec<_>>>()?,
+            )),
+            (None, None, None, Some(not), None) => Ok(GroupRequestFilter::Not(Box::new(
+                (*not).try_into_domain_filter(schema)?,
+            ))),
+            (None, None, None, None, Some(substring)) => {
+                Ok(GroupRequestFilter::DisplayNameSubString(SubStringFilter {
+                    initial: None,
+                    any: vec![substring],
+                    final_: None,
+                }))
+            }
+            (None, None, None, None, None) => Err("No field specified in group filter".into()),
+            _ => Err("Multiple fields specified in group filter".into()),
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Debug)]
 /// The top-level GraphQL query type.
 pub struct Query<Handler: BackendHandler> {
@@ -138,15 +235,36 @@ impl<Handler: BackendHandler> Query<Handler> {
         });
         let user_id = urlencoding::decode(&user_id).context("Invalid user parameter")?;
         let user_id = UserId::new(&user_id);
+        if let Some(handler) = context.get_readable_handler(&user_id) {
+            let schema = Arc::new(self.get_schema(context, span.clone()).await?);
+            let user = handler
+                .get_user_details(&user_id)
+                .instrument(span)
+                .await
+                .map_err(domain_error)?;
+            return User::<Handler>::from_user(user, schema);
+        }
+        // Another user, visible through the directory.
         let handler = context
-            .get_readable_handler(&user_id)
+            .get_directory_lister_handler()
+            .await
+            .map_err(domain_error)?
             .ok_or_else(field_error_callback(
                 &span,
                 "Unauthorized access to user data",
             ))?;
         let schema = Arc::new(self.get_schema(context, span.clone()).await?);
-        let user = handler.get_user_details(&user_id).instrument(span).await?;
-        User::<Handler>::from_user(user, schema)
+        let user = handler
+            .list_users(Some(DomainRequestFilter::UserId(user_id)), true)
+            .instrument(span.clone())
+            .await
+            .map_err(domain_error)?
+            .pop()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to user data",
+            ))?;
+        User::<Handler>::from_user_and_groups(user, schema)
     }
 
     async fn users(
@@ -158,7 +276,9 @@ impl<Handler: BackendHandler> Query<Handler> {
             debug!(?filters);
         });
         let handler = context
-            .get_readonly_handler()
+            .get_directory_lister_handler()
+            .await
+            .map_err(domain_error)?
             .ok_or_else(field_error_callback(
                 &span,
                 "Unauthorized access to user list",
@@ -179,8 +299,66 @@ impl<Handler: BackendHandler> Query<Handler> {
             .collect()
     }
 
-    async fn groups(context: &Context<Handler>) -> FieldResult<Vec<Group<Handler>>> {
+    /// Whether the current user can browse the people directory.
+    fn directory_enabled(context: &Context<Handler>) -> bool {
+        context.validation_result.can_read_all()
+            || context.directory_visibility != DirectoryVisibility::Nobody
+    }
+
+    /// The users visible to the current user, with their groups, optionally those whose ID,
+    /// display name or email contains `search`.
+    async fn directory(
+        context: &Context<Handler>,
+        search: Option<String>,
+    ) -> FieldResult<Vec<User<Handler>>> {
+        let span = debug_span!("[GraphQL query] directory");
+        span.in_scope(|| {
+            debug!(?search);
+        });
+        let handler = context
+            .get_directory_lister_handler()
+            .await
+            .map_err(domain_error)?
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to the directory",
+            ))?;
+        let filter = search.filter(|s| !s.trim().is_empty()).map(|s| {
+            let substring = || SubStringFilter {
+                initial: None,
+                any: vec![s.trim().to_owned()],
+                final_: None,
+            };
+            DomainRequestFilter::Or(vec![
+                DomainRequestFilter::UserIdSubString(substring()),
+                DomainRequestFilter::SubString(UserColumn::DisplayName, substring()),
+                DomainRequestFilter::SubString(UserColumn::Email, substring()),
+            ])
+        });
+        let schema = Arc::new(self.get_schema(context, span.clone()).await?);
+        let users = handler
+            .list_users(filter, true)
+            .instrument(span)
+            .await
+            .map_err(domain_error)?;
+        users
+            .into_iter()
+            .map(|u| User::<Handler>::from_user_and_groups(u, schema.clone()))
+            .collect()
+    }
+
+    /// The groups, sorted by display name, optionally only those matching the filter. Without a
+    /// limit, all the groups after the offset are returned.
+    async fn groups(
+        context: &Context<Handler>,
+        filter: Option<GroupFilter>,
+        offset: Option<i32>,
+        limit: Option<i32>,
+    ) -> FieldResult<Vec<Group<Handler>>> {
         let span = debug_span!("[GraphQL query] groups");
+        span.in_scope(|| {
+            debug!(?filter, ?offset, ?limit);
+        });
         let handler = context
             .get_readonly_handler()
             .ok_or_else(field_error_callback(
@@ -188,7 +366,20 @@ impl<Handler: BackendHandler> Query<Handler> {
                 "Unauthorized access to group list",
             ))?;
         let schema = Arc::new(self.get_schema(context, span.clone()).await?);
-        let domain_groups = handler.list_groups(None).instrument(span).await?;
+        let filter = filter
+            .map(|f| f.try_into_domain_filter(&schema))
+            .transpose()?;
+        let domain_groups = handler
+            .list_groups(filter)
+            .instrument(span)
+            .await?
+            .into_iter()
+            .skip(offset.unwrap_or(0).max(0) as usize)
+            .take(limit.map(|l| l.max(0) as usize).unwrap_or(usize::MAX))
+            .collect::<Vec<_>>();
+        context
+            .group_members
+            .register(domain_groups.iter().map(|g| g.id));
         domain_groups
             .into_iter()
             .map(|g| Group::<Handler>::from_group(g, schema.clone()))
@@ -210,16 +401,213 @@ impl<Handler: BackendHandler> Query<Handler> {
         let group_details = handler
             .get_group_details(GroupId(group_id))
             .instrument(span)
-            .await?;
+            .await
+            .map_err(domain_error)?;
         Group::<Handler>::from_group_details(group_details, schema.clone())
     }
 
+    /// Looks up a group by its UUID, the `entryUUID` of its LDAP entry.
+    async fn group_by_uuid(
+        context: &Context<Handler>,
+        uuid: String,
+    ) -> FieldResult<Group<Handler>> {
+        let span = debug_span!("[GraphQL query] group_by_uuid");
+        span.in_scope(|| {
+            debug!(?uuid);
+        });
+        let handler = context
+            .get_readonly_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to group data",
+            ))?;
+        let schema = Arc::new(self.get_schema(context, span.clone()).await?);
+        let group = get_group_by_uuid(handler, &uuid).instrument(span).await?;
+        context.group_members.register([group.id]);
+        Group::<Handler>::from_group(group, schema)
+    }
+
     async fn schema(context: &Context<Handler>) -> FieldResult<Schema<Handler>> {
         let span = debug_span!("[GraphQL query] get_schema");
         self.get_schema(context, span).await.map(Into::into)
     }
+
+    /// The changes that happened after the one with the `since` sequence number (or from the
+    /// start of the feed), oldest first. At most `limit` (default 100, max 1000) are returned.
+    /// With MySQL or PostgreSQL, concurrent changes can be committed out of order: a change can
+    /// briefly appear before one with a lower sequence number.
+    async fn changes(
+        context: &Context<Handler>,
+        since: Option<i32>,
+        limit: Option<i32>,
+    ) -> FieldResult<Vec<Change>> {
+        let span = debug_span!("[GraphQL query] changes");
+        span.in_scope(|| {
+            debug!(?since, ?limit);
+        });
+        let handler = context
+            .get_readonly_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to the change feed",
+            ))?;
+        let limit = limit.unwrap_or(100).clamp(1, MAX_CHANGES_PER_REQUEST);
+        Ok(handler
+            .list_changes(since.unwrap_or(0), limit as u64)
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// The sensitive changes waiting for the approval of a second admin, in four-eyes mode.
+    async fn pending_changes(context: &Context<Handler>) -> FieldResult<Vec<PendingChange>> {
+        let span = debug_span!("[GraphQL query] pending_changes");
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to the pending changes",
+            ))?;
+        Ok(handler
+            .list_pending_changes()
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// Durations and entry counts of the LDAP operations since the server started.
+    async fn ldap_operation_stats(
+        context: &Context<Handler>,
+    ) -> FieldResult<Vec<LdapOperationStats>> {
+        let span = debug_span!("[GraphQL query] ldap_operation_stats");
+        context
+            .get_readonly_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to the LDAP operation stats",
+            ))?;
+        Ok(context
+            .ldap_metrics
+            .snapshot()
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// Durations and failures of the TLS handshakes of the LDAPS connections since the server
+    /// started.
+    async fn ldap_tls_handshake_stats(
+        context: &Context<Handler>,
+    ) -> FieldResult<TlsHandshakeStats> {
+        let span = debug_span!("[GraphQL query] ldap_tls_handshake_stats");
+        context
+            .get_readonly_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to the LDAP TLS handshake stats",
+            ))?;
+        Ok(context.ldap_metrics.tls_handshakes().into())
+    }
+
+    /// The expiry of the LDAPS certificate and the last changes of the keys, as of the last daily
+    /// check.
+    async fn expiry_status(context: &Context<Handler>) -> FieldResult<ExpiryStatus> {
+        let span = debug_span!("[GraphQL query] expiry_status");
+        context
+            .get_readonly_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to the expiry status",
+            ))?;
+        Ok(context.expiry_monitor.status().into())
+    }
+
+    /// Whether only the admins can log in, and the message shown to the other users.
+    async fn maintenance_mode(context: &Context<Handler>) -> FieldResult<MaintenanceMode> {
+        let span = debug_span!("[GraphQL query] maintenance_mode");
+        context
+            .get_readonly_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to the maintenance mode",
+            ))?;
+        Ok(context.maintenance.status().into())
+    }
+
+    /// Whether all the changes to the directory are refused.
+    async fn read_only_mode(context: &Context<Handler>) -> FieldResult<bool> {
+        let span = debug_span!("[GraphQL query] read_only_mode");
+        context
+            .get_readonly_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to the read-only mode",
+            ))?;
+        Ok(context.read_only.is_enabled())
+    }
+
+    /// What the group rules of the configuration would change if they were applied to all the
+    /// users now, e.g. after adding a rule. Nothing is changed.
+    async fn group_rules_preview(context: &Context<Handler>) -> FieldResult<Vec<GroupRuleChange>> {
+        let span = debug_span!("[GraphQL query] group_rules_preview");
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to the group rules",
+            ))?;
+        Ok(handler
+            .preview_group_rules()
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// The users who asked for their account to be deleted.
+    async fn account_deletions(context: &Context<Handler>) -> FieldResult<Vec<AccountDeletion>> {
+        let span = debug_span!("[GraphQL query] account_deletions");
+        let handler = context
+            .get_readonly_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to the account deletions",
+            ))?;
+        Ok(handler
+            .list_account_deletions()
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// The reviews of all the access review campaigns, the latest campaign first.
+    async fn access_reviews(context: &Context<Handler>) -> FieldResult<Vec<AccessReview>> {
+        let span = debug_span!("[GraphQL query] access_reviews");
+        let handler = context
+            .get_readonly_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to the access reviews",
+            ))?;
+        Ok(handler
+            .list_access_reviews()
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
 }
 
+const MAX_CHANGES_PER_REQUEST: i32 = 1000;
+
 impl<Handler: BackendHandler> Query<Handler> {
     async fn get_schema(
         &self,
@@ -237,6 +625,31 @@ impl<Handler: BackendHandler> Query<Handler> {
     }
 }
 
+/// Finds the group with the UUID, as served over LDAP in `entryUUID`.
+pub(crate) async fn get_group_by_uuid(
+    handler: &impl ReadonlyBackendHandler,
+    uuid: &str,
+) -> FieldResult<DomainGroup> {
+    let uuid = Uuid::try_from(uuid).map_err(|e| {
+        domain_error(DomainError::ValidationError(format!(
+            "Invalid UUID \"{}\": {}",
+            uuid, e
+        )))
+    })?;
+    handler
+        .list_groups(Some(GroupRequestFilter::Uuid(uuid.clone())))
+        .await
+        .map_err(domain_error)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| {
+            domain_error(DomainError::EntityNotFound(format!(
+                "No group with the UUID {}",
+                uuid
+            )))
+        })
+}
+
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
 /// Represents a single user.
 pub struct User<Handler: BackendHandler> {
@@ -249,7 +662,8 @@ pub struct User<Handler: BackendHandler> {
 
 impl<Handler: BackendHandler> User<Handler> {
     pub fn from_user(mut user: DomainUser, schema: Arc<PublicSchema>) -> FieldResult<Self> {
-        let attributes = AttributeValue::<Handler>::user_attributes_from_schema(&mut user, &schema);
+        let attributes =
+            AttributeValue::<Handler>::user_attributes_from_schema(&mut user, &schema)?;
         Ok(Self {
             user,
             attributes,
@@ -315,19 +729,70 @@ impl<Handler: BackendHandler> User<Handler> {
             .map(|a| String::from(&a.attribute.value.unwrap::<JpegPhoto>()))
     }
 
+    /// A BCP 47 language tag, e.g. "de-DE", used for the emails sent to the user.
+    fn preferred_language(&self) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|a| a.attribute.name.as_str() == "preferred_language")
+            .map(|a| a.attribute.value.unwrap())
+    }
+
+    /// An IANA timezone, e.g. "Europe/Berlin", used to display dates to the user.
+    fn timezone(&self) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|a| a.attribute.name.as_str() == "timezone")
+            .map(|a| a.attribute.value.unwrap())
+    }
+
     fn creation_date(&self) -> chrono::DateTime<chrono::Utc> {
         chrono::Utc.from_utc_datetime(&self.user.creation_date)
     }
 
+    /// The last change of the user, its attributes or its groups.
+    fn modified_date(&self) -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc.from_utc_datetime(&self.user.modified_date)
+    }
+
     fn uuid(&self) -> &str {
         self.user.uuid.as_str()
     }
 
+    /// The stable identifier of the user in an external system, set at creation.
+    fn external_id(&self) -> Option<&str> {
+        self.user.external_id.as_deref()
+    }
+
     /// User-defined attributes.
     fn attributes(&self) -> &[AttributeValue<Handler>] {
         &self.attributes
     }
 
+    /// The attributes rendered from the `computed_user_attributes` templates of the
+    /// configuration, when they are not empty.
+    fn computed_attributes(&self, context: &Context<Handler>) -> Vec<ComputedAttributeValue> {
+        let user = DomainUser {
+            attributes: self
+                .attributes
+                .iter()
+                .map(|a| a.attribute.clone())
+                .collect(),
+            ..self.user.clone()
+        };
+        context
+            .computed_user_attributes
+            .iter()
+            .filter_map(|computed| {
+                render_user_template(&computed.template, &user, &self.schema).map(|value| {
+                    ComputedAttributeValue {
+                        name: computed.name.clone(),
+                        value,
+                    }
+                })
+            })
+            .collect()
+    }
+
     /// The groups to which this user belongs.
     async fn groups(&self, context: &Context<Handler>) -> FieldResult<Vec<Group<Handler>>> {
         if let Some(groups) = &self.groups {
@@ -339,7 +804,10 @@ impl<Handler: BackendHandler> User<Handler> {
         });
         let handler = context
             .get_readable_handler(&self.user.user_id)
-            .expect("We shouldn't be able to get there without readable permission");
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to user data",
+            ))?;
         let domain_groups = handler
             .get_user_groups(&self.user.user_id)
             .instrument(span)
@@ -351,6 +819,73 @@ impl<Handler: BackendHandler> User<Handler> {
         groups.sort_by(|g1, g2| g1.display_name.cmp(&g2.display_name));
         Ok(groups)
     }
+
+    /// Whether the user refused to have their avatar fetched from Gravatar/libravatar.
+    async fn avatar_sync_opt_out(&self, context: &Context<Handler>) -> FieldResult<bool> {
+        let span = debug_span!("[GraphQL query] user::avatar_sync_opt_out");
+        span.in_scope(|| {
+            debug!(user_id = ?self.user.user_id);
+        });
+        let handler = context
+            .get_readable_handler(&self.user.user_id)
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to user data",
+            ))?;
+        Ok(handler
+            .get_avatar_sync_opt_out(&self.user.user_id)
+            .instrument(span)
+            .await?)
+    }
+
+    /// The accounts at external identity providers that log in as this user.
+    async fn identity_links(&self, context: &Context<Handler>) -> FieldResult<Vec<IdentityLink>> {
+        let span = debug_span!("[GraphQL query] user::identity_links");
+        span.in_scope(|| {
+            debug!(user_id = ?self.user.user_id);
+        });
+        let handler = context
+            .get_readable_handler(&self.user.user_id)
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to user data",
+            ))?;
+        Ok(handler
+            .list_identity_links(&self.user.user_id)
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// The changes made to the profile of the user, oldest first.
+    async fn profile_history(&self, context: &Context<Handler>) -> FieldResult<Vec<ProfileChange>> {
+        let span = debug_span!("[GraphQL query] user::profile_history");
+        span.in_scope(|| {
+            debug!(user_id = ?self.user.user_id);
+        });
+        let handler = context
+            .get_readable_handler(&self.user.user_id)
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to user data",
+            ))?;
+        Ok(handler
+            .list_profile_changes(&self.user.user_id)
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A virtual attribute of a user, rendered from a template.
+pub struct ComputedAttributeValue {
+    name: String,
+    value: String,
 }
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
@@ -359,6 +894,7 @@ pub struct Group<Handler: BackendHandler> {
     group_id: i32,
     display_name: String,
     creation_date: chrono::NaiveDateTime,
+    modified_date: chrono::NaiveDateTime,
     uuid: String,
     attributes: Vec<AttributeValue<Handler>>,
     schema: Arc<PublicSchema>,
@@ -371,11 +907,12 @@ impl<Handler: BackendHandler> Group<Handler> {
         schema: Arc<PublicSchema>,
     ) -> FieldResult<Group<Handler>> {
         let attributes =
-            AttributeValue::<Handler>::group_attributes_from_schema(&mut group, &schema);
+            AttributeValue::<Handler>::group_attributes_from_schema(&mut group, &schema)?;
         Ok(Self {
             group_id: group.id.0,
             display_name: group.display_name.to_string(),
             creation_date: group.creation_date,
+            modified_date: group.modified_date,
             uuid: group.uuid.into_string(),
             attributes,
             schema,
@@ -390,11 +927,12 @@ impl<Handler: BackendHandler> Group<Handler> {
         let attributes = AttributeValue::<Handler>::group_details_attributes_from_schema(
             &mut group_details,
             &schema,
-        );
+        )?;
         Ok(Self {
             group_id: group_details.group_id.0,
             display_name: group_details.display_name.to_string(),
             creation_date: group_details.creation_date,
+            modified_date: group_details.modified_date,
             uuid: group_details.uuid.into_string(),
             attributes,
             schema,
@@ -409,6 +947,7 @@ impl<Handler: BackendHandler> Clone for Group<Handler> {
             group_id: self.group_id,
             display_name: self.display_name.clone(),
             creation_date: self.creation_date,
+            modified_date: self.modified_date,
             uuid: self.uuid.clone(),
             attributes: self.attributes.clone(),
             schema: self.schema.clone(),
@@ -428,6 +967,10 @@ impl<Handler: BackendHandler> Group<Handler> {
     fn creation_date(&self) -> chrono::DateTime<chrono::Utc> {
         chrono::Utc.from_utc_datetime(&self.creation_date)
     }
+    /// The last change of the group, its attributes or its members.
+    fn modified_date(&self) -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc.from_utc_datetime(&self.modified_date)
+    }
     fn uuid(&self) -> String {
         self.uuid.clone()
     }
@@ -449,11 +992,9 @@ impl<Handler: BackendHandler> Group<Handler> {
                 &span,
                 "Unauthorized access to group data",
             ))?;
-        let domain_users = handler
-            .list_users(
-                Some(DomainRequestFilter::MemberOfId(GroupId(self.group_id))),
-                false,
-            )
+        let domain_users = context
+            .group_members
+            .load(handler, GroupId(self.group_id))
             .instrument(span)
             .await?;
         domain_users
@@ -461,6 +1002,93 @@ impl<Handler: BackendHandler> Group<Handler> {
             .map(|u| User::<Handler>::from_user_and_groups(u, self.schema.clone()))
             .collect()
     }
+
+    /// The members of the group, with the date they were added.
+    async fn memberships(
+        &self,
+        context: &Context<Handler>,
+    ) -> FieldResult<Vec<GroupMembership<Handler>>> {
+        let span = debug_span!("[GraphQL query] group::memberships");
+        span.in_scope(|| {
+            debug!(name = %self.display_name);
+        });
+        let handler = context
+            .get_readonly_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to group data",
+            ))?;
+        let memberships = handler
+            .list_group_memberships(GroupId(self.group_id))
+            .instrument(span.clone())
+            .await?;
+        let mut users = context
+            .group_members
+            .load(handler, GroupId(self.group_id))
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(|u| (u.user.user_id.clone(), u))
+            .collect::<HashMap<_, _>>();
+        memberships
+            .into_iter()
+            .filter_map(|m| Some((users.remove(&m.user_id)?, m)))
+            .map(|(user, m)| {
+                Ok(GroupMembership {
+                    user: User::<Handler>::from_user_and_groups(user, self.schema.clone())?,
+                    creation_date: m.creation_date,
+                    expiry_date: m.expiry_date,
+                })
+            })
+            .collect()
+    }
+
+    /// The attribute values given to users joining this group.
+    async fn attribute_templates(
+        &self,
+        context: &Context<Handler>,
+    ) -> FieldResult<Vec<AttributeTemplate>> {
+        let span = debug_span!("[GraphQL query] group::attribute_templates");
+        span.in_scope(|| {
+            debug!(name = %self.display_name);
+        });
+        let handler = context
+            .get_readonly_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to group data",
+            ))?;
+        Ok(handler
+            .list_attribute_templates(GroupId(self.group_id))
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+}
+
+/// A member of a group.
+pub struct GroupMembership<Handler: BackendHandler> {
+    user: User<Handler>,
+    creation_date: Option<NaiveDateTime>,
+    expiry_date: Option<NaiveDateTime>,
+}
+
+#[graphql_object(context = Context<Handler>)]
+impl<Handler: BackendHandler> GroupMembership<Handler> {
+    fn user(&self) -> &User<Handler> {
+        &self.user
+    }
+    /// Unknown for the memberships created before it was recorded.
+    fn member_since(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.creation_date
+            .map(|d| chrono::Utc.from_utc_datetime(&d))
+    }
+    /// When the membership will be removed. Null for the permanent memberships.
+    fn expires_at(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.expiry_date.map(|d| chrono::Utc.from_utc_datetime(&d))
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
@@ -671,130 +1299,565 @@ impl<Handler: BackendHandler> AttributeValue<Handler> {
             .map(|s| AttributeValue::<Handler>::from_domain(a, s.clone()))
     }
 
+    /// Turns the value of a hardcoded attribute into an attribute value, if it is set.
+    fn hardcoded_attribute(
+        attribute: &DomainAttributeSchema,
+        value: Option<Serialized>,
+    ) -> Option<Self> {
+        value.map(|value| {
+            AttributeValue::<Handler>::from_domain(
+                DomainAttributeValue {
+                    name: attribute.name.clone(),
+                    value,
+                },
+                attribute.clone(),
+            )
+        })
+    }
+
+    /// Removes the value of a hardcoded attribute that is stored with the other attributes.
+    fn take_stored_value(
+        attributes: &mut Vec<DomainAttributeValue>,
+        name: &AttributeName,
+    ) -> Option<Serialized> {
+        let index = attributes.iter().position(|a| &a.name == name)?;
+        Some(attributes.remove(index).value)
+    }
+
+    fn unknown_hardcoded_attribute(attribute: &DomainAttributeSchema) -> FieldError {
+        format!("Unexpected hardcoded attribute: {}", attribute.name).into()
+    }
+
     fn user_attributes_from_schema(
         user: &mut DomainUser,
         schema: &PublicSchema,
-    ) -> Vec<AttributeValue<Handler>> {
-        let user_attributes = std::mem::take(&mut user.attributes);
-        let mut all_attributes = schema
+    ) -> FieldResult<Vec<AttributeValue<Handler>>> {
+        let mut user_attributes = std::mem::take(&mut user.attributes);
+        let mut all_attributes = Vec::new();
+        for attribute in schema
             .get_schema()
             .user_attributes
             .attributes
             .iter()
             .filter(|a| a.is_hardcoded)
-            .flat_map(|attribute| {
-                let value = match attribute.name.as_str() {
-                    "user_id" => Some(Serialized::from(&user.user_id)),
-                    "creation_date" => Some(Serialized::from(&user.creation_date)),
-                    "mail" => Some(Serialized::from(&user.email)),
-                    "uuid" => Some(Serialized::from(&user.uuid)),
-                    "display_name" => user.display_name.as_ref().map(Serialized::from),
-                    "avatar" | "first_name" | "last_name" => None,
-                    _ => panic!("Unexpected hardcoded attribute: {}", attribute.name),
-                };
-                value.map(|v| (attribute, v))
-            })
-            .map(|(attribute, value)| {
-                AttributeValue::<Handler>::from_domain(
-                    DomainAttributeValue {
-                        name: attribute.name.clone(),
-                        value,
-                    },
-                    attribute.clone(),
-                )
-            })
-            .collect::<Vec<_>>();
-        user_attributes
-            .into_iter()
-            .flat_map(|a| {
-                AttributeValue::<Handler>::from_schema(a, &schema.get_schema().user_attributes)
-            })
-            .for_each(|value| all_attributes.push(value));
-        all_attributes
+        {
+            let value = match attribute.name.as_str() {
+                "user_id" => Some(Serialized::from(&user.user_id)),
+                "creation_date" => Some(Serialized::from(&user.creation_date)),
+                "mail" => Some(Serialized::from(&user.email)),
+                "uuid" => Some(Serialized::from(&user.uuid)),
+                "display_name" => user.display_name.as_ref().map(Serialized::from),
+                "avatar" | "first_name" | "last_name" | "preferred_language" | "sshpublickey"
+                | "timezone" | "uidnumber" => {
+                    Self::take_stored_value(&mut user_attributes, &attribute.name)
+                }
+                _ => return Err(Self::unknown_hardcoded_attribute(attribute)),
+            };
+            all_attributes.extend(Self::hardcoded_attribute(attribute, value));
+        }
+        all_attributes.extend(user_attributes.into_iter().flat_map(|a| {
+            AttributeValue::<Handler>::from_schema(a, &schema.get_schema().user_attributes)
+        }));
+        Ok(all_attributes)
     }
 
     fn group_attributes_from_schema(
         group: &mut DomainGroup,
         schema: &PublicSchema,
-    ) -> Vec<AttributeValue<Handler>> {
-        let group_attributes = std::mem::take(&mut group.attributes);
-        let mut all_attributes = schema
+    ) -> FieldResult<Vec<AttributeValue<Handler>>> {
+        let mut group_attributes = std::mem::take(&mut group.attributes);
+        let mut all_attributes = Vec::new();
+        for attribute in schema
             .get_schema()
             .group_attributes
             .attributes
             .iter()
             .filter(|a| a.is_hardcoded)
-            .map(|attribute| {
-                (
-                    attribute,
-                    match attribute.name.as_str() {
-                        "group_id" => Serialized::from(&(group.id.0 as i64)),
-                        "creation_date" => Serialized::from(&group.creation_date),
-                        "uuid" => Serialized::from(&group.uuid),
-                        "display_name" => Serialized::from(&group.display_name),
-                        _ => panic!("Unexpected hardcoded attribute: {}", attribute.name),
-                    },
-                )
-            })
-            .map(|(attribute, value)| {
-                AttributeValue::<Handler>::from_domain(
-                    DomainAttributeValue {
-                        name: attribute.name.clone(),
-                        value,
-                    },
-                    attribute.clone(),
-                )
-            })
-            .collect::<Vec<_>>();
-        group_attributes
-            .into_iter()
-            .flat_map(|a| {
-                AttributeValue::<Handler>::from_schema(a, &schema.get_schema().group_attributes)
-            })
-            .for_each(|value| all_attributes.push(value));
-        all_attributes
+        {
+            let value = match attribute.name.as_str() {
+                "group_id" => Some(Serialized::from(&(group.id.0 as i64))),
+                "creation_date" => Some(Serialized::from(&group.creation_date)),
+                "uuid" => Some(Serialized::from(&group.uuid)),
+                "display_name" => Some(Serialized::from(&group.display_name)),
+                "gidnumber" => Self::take_stored_value(&mut group_attributes, &attribute.name),
+                _ => return Err(Self::unknown_hardcoded_attribute(attribute)),
+            };
+            all_attributes.extend(Self::hardcoded_attribute(attribute, value));
+        }
+        all_attributes.extend(group_attributes.into_iter().flat_map(|a| {
+            AttributeValue::<Handler>::from_schema(a, &schema.get_schema().group_attributes)
+        }));
+        Ok(all_attributes)
     }
 
     fn group_details_attributes_from_schema(
         group: &mut GroupDetails,
         schema: &PublicSchema,
-    ) -> Vec<AttributeValue<Handler>> {
-        let group_attributes = std::mem::take(&mut group.attributes);
-        let mut all_attributes = schema
+    ) -> FieldResult<Vec<AttributeValue<Handler>>> {
+        let mut group_attributes = std::mem::take(&mut group.attributes);
+        let mut all_attributes = Vec::new();
+        for attribute in schema
             .get_schema()
             .group_attributes
             .attributes
             .iter()
             .filter(|a| a.is_hardcoded)
-            .map(|attribute| {
-                (
-                    attribute,
-                    match attribute.name.as_str() {
-                        "group_id" => Serialized::from(&(group.group_id.0 as i64)),
-                        "creation_date" => Serialized::from(&group.creation_date),
-                        "uuid" => Serialized::from(&group.uuid),
-                        "display_name" => Serialized::from(&group.display_name),
-                        _ => panic!("Unexpected hardcoded attribute: {}", attribute.name),
-                    },
-                )
-            })
-            .map(|(attribute, value)| {
-                AttributeValue::<Handler>::from_domain(
-                    DomainAttributeValue {
-                        name: attribute.name.clone(),
-                        value,
-                    },
-                    attribute.clone(),
-                )
-            })
-            .collect::<Vec<_>>();
-        group_attributes
-            .into_iter()
-            .flat_map(|a| {
-                AttributeValue::<Handler>::from_schema(a, &schema.get_schema().group_attributes)
-            })
-            .for_each(|value| all_attributes.push(value));
-        all_attributes
+        {
+            let value = match attribute.name.as_str() {
+                "group_id" => Some(Serialized::from(&(group.group_id.0 as i64))),
+                "creation_date" => Some(Serialized::from(&group.creation_date)),
+                "uuid" => Some(Serialized::from(&group.uuid)),
+                "display_name" => Some(Serialized::from(&group.display_name)),
+                "gidnumber" => Self::take_stored_value(&mut group_attributes, &attribute.name),
+                _ => return Err(Self::unknown_hardcoded_attribute(attribute)),
+            };
+            all_attributes.extend(Self::hardcoded_attribute(attribute, value));
+        }
+        all_attributes.extend(group_attributes.into_iter().flat_map(|a| {
+            AttributeValue::<Handler>::from_schema(a, &schema.get_schema().group_attributes)
+        }));
+        Ok(all_attributes)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, GraphQLEnum)]
+pub enum ChangeKind {
+    UserCreated,
+    UserUpdated,
+    UserDeleted,
+    PasswordChanged,
+    GroupCreated,
+    GroupUpdated,
+    GroupDeleted,
+    MembershipAdded,
+    MembershipRemoved,
+    MembershipAddedByRule,
+    MembershipRemovedByRule,
+    AccessReviewKept,
+    AccessReviewRevoked,
+    UserAttributeSchemaChanged,
+    GroupAttributeSchemaChanged,
+    UserObjectClassesChanged,
+    GroupObjectClassesChanged,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// An entry of the change feed.
+pub struct Change {
+    /// Pass the sequence number of the last change seen as `since` to get the following ones.
+    sequence: i32,
+    change_date: chrono::DateTime<chrono::Utc>,
+    kind: ChangeKind,
+    user_id: Option<String>,
+    group_id: Option<i32>,
+    /// The attribute or object class for schema changes, the rule for changes made by a group
+    /// rule, the reviewer for access review decisions.
+    name: Option<String>,
+}
+
+impl From<ChangeFeedEntry> for Change {
+    fn from(entry: ChangeFeedEntry) -> Self {
+        let (kind, user_id, group_id, name) = match entry.event {
+            ChangeEvent::UserCreated(u) => (ChangeKind::UserCreated, Some(u), None, None),
+            ChangeEvent::UserUpdated(u) => (ChangeKind::UserUpdated, Some(u), None, None),
+            ChangeEvent::UserDeleted(u) => (ChangeKind::UserDeleted, Some(u), None, None),
+            ChangeEvent::PasswordChanged(u) => (ChangeKind::PasswordChanged, Some(u), None, None),
+            ChangeEvent::GroupCreated(g) => (ChangeKind::GroupCreated, None, Some(g), None),
+            ChangeEvent::GroupUpdated(g) => (ChangeKind::GroupUpdated, None, Some(g), None),
+            ChangeEvent::GroupDeleted(g) => (ChangeKind::GroupDeleted, None, Some(g), None),
+            ChangeEvent::MembershipAdded { user_id, group_id } => (
+                ChangeKind::MembershipAdded,
+                Some(user_id),
+                Some(group_id),
+                None,
+            ),
+            ChangeEvent::MembershipRemoved { user_id, group_id } => (
+                ChangeKind::MembershipRemoved,
+                Some(user_id),
+                Some(group_id),
+                None,
+            ),
+            ChangeEvent::MembershipAddedByRule {
+                user_id,
+                group_id,
+                rule,
+            } => (
+                ChangeKind::MembershipAddedByRule,
+                Some(user_id),
+                Some(group_id),
+                Some(rule),
+            ),
+            ChangeEvent::MembershipRemovedByRule {
+                user_id,
+                group_id,
+                rule,
+            } => (
+                ChangeKind::MembershipRemovedByRule,
+                Some(user_id),
+                Some(group_id),
+                Some(rule),
+            ),
+            ChangeEvent::AccessReviewDecided {
+                group_id,
+                user_id,
+                reviewer,
+                keep,
+                ..
+            } => (
+                if keep {
+                    ChangeKind::AccessReviewKept
+                } else {
+                    ChangeKind::AccessReviewRevoked
+                },
+                Some(user_id),
+                Some(group_id),
+                Some(reviewer.to_string()),
+            ),
+            ChangeEvent::UserAttributeSchemaChanged(a) => (
+                ChangeKind::UserAttributeSchemaChanged,
+                None,
+                None,
+                Some(a.to_string()),
+            ),
+            ChangeEvent::GroupAttributeSchemaChanged(a) => (
+                ChangeKind::GroupAttributeSchemaChanged,
+                None,
+                None,
+                Some(a.to_string()),
+            ),
+            ChangeEvent::UserObjectClassesChanged(c) => (
+                ChangeKind::UserObjectClassesChanged,
+                None,
+                None,
+                Some(c.to_string()),
+            ),
+            ChangeEvent::GroupObjectClassesChanged(c) => (
+                ChangeKind::GroupObjectClassesChanged,
+                None,
+                None,
+                Some(c.to_string()),
+            ),
+        };
+        Self {
+            sequence: entry.sequence,
+            change_date: chrono::Utc.from_utc_datetime(&entry.change_date),
+            kind,
+            user_id: user_id.map(|u| u.to_string()),
+            group_id: group_id.map(|g| g.0),
+            name,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, GraphQLEnum)]
+pub enum PendingChangeKind {
+    DeleteUser,
+    AddUserToGroup,
+    RemoveUserFromGroup,
+    SetMembershipExpiry,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A sensitive change, waiting for the approval of an admin other than the requester.
+pub struct PendingChange {
+    id: i32,
+    requested_by: String,
+    request_date: chrono::DateTime<chrono::Utc>,
+    kind: PendingChangeKind,
+    user_id: String,
+    group_id: Option<i32>,
+    /// The requested expiry date of the membership, for `SET_MEMBERSHIP_EXPIRY`. Empty to make it
+    /// permanent.
+    expiry_date: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<DomainPendingChange> for PendingChange {
+    fn from(pending: DomainPendingChange) -> Self {
+        let (kind, user_id, group_id, expiry_date) = match pending.change {
+            SensitiveChange::DeleteUser(u) => (PendingChangeKind::DeleteUser, u, None, None),
+            SensitiveChange::AddUserToGroup { user_id, group_id } => (
+                PendingChangeKind::AddUserToGroup,
+                user_id,
+                Some(group_id),
+                None,
+            ),
+            SensitiveChange::RemoveUserFromGroup { user_id, group_id } => (
+                PendingChangeKind::RemoveUserFromGroup,
+                user_id,
+                Some(group_id),
+                None,
+            ),
+            SensitiveChange::SetMembershipExpiry {
+                user_id,
+                group_id,
+                expiry_date,
+            } => (
+                PendingChangeKind::SetMembershipExpiry,
+                user_id,
+                Some(group_id),
+                expiry_date,
+            ),
+        };
+        Self {
+            id: pending.id,
+            requested_by: pending.requested_by.to_string(),
+            request_date: chrono::Utc.from_utc_datetime(&pending.request_date),
+            kind,
+            user_id: user_id.to_string(),
+            group_id: group_id.map(|g| g.0),
+            expiry_date: expiry_date.map(|d| chrono::Utc.from_utc_datetime(&d)),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, GraphQLObject)]
+/// Statistics of a type of LDAP operation (e.g. "search") since the server started.
+pub struct LdapOperationStats {
+    operation: String,
+    count: i32,
+    /// Operations that took longer than the `ldap_slow_operation_threshold_ms` setting.
+    slow_count: i32,
+    /// Entries returned by the searches.
+    entries: i32,
+    total_duration_ms: f64,
+    max_duration_ms: f64,
+}
+
+impl From<(&'static str, DomainLdapOperationStats)> for LdapOperationStats {
+    fn from((operation, stats): (&'static str, DomainLdapOperationStats)) -> Self {
+        let to_i32 = |n: u64| i32::try_from(n).unwrap_or(i32::MAX);
+        Self {
+            operation: operation.to_owned(),
+            count: to_i32(stats.count),
+            slow_count: to_i32(stats.slow_count),
+            entries: to_i32(stats.entries),
+            total_duration_ms: stats.total_duration.as_secs_f64() * 1000.0,
+            max_duration_ms: stats.max_duration.as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, GraphQLObject)]
+/// Statistics of the TLS handshakes of the LDAPS connections.
+pub struct TlsHandshakeStats {
+    count: i32,
+    failures: i32,
+    total_duration_ms: f64,
+    max_duration_ms: f64,
+}
+
+impl From<DomainTlsHandshakeStats> for TlsHandshakeStats {
+    fn from(stats: DomainTlsHandshakeStats) -> Self {
+        let to_i32 = |n: u64| i32::try_from(n).unwrap_or(i32::MAX);
+        Self {
+            count: to_i32(stats.count),
+            failures: to_i32(stats.failures),
+            total_duration_ms: stats.total_duration.as_secs_f64() * 1000.0,
+            max_duration_ms: stats.max_duration.as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// The expiry of the LDAPS certificate and the last changes of the keys. The fields are null when
+/// they don't apply, e.g. without LDAPS, or before the first check.
+pub struct ExpiryStatus {
+    checked_at: Option<chrono::DateTime<chrono::Utc>>,
+    ldaps_certificate_not_after: Option<chrono::DateTime<chrono::Utc>>,
+    /// Last modification of the server key file.
+    private_key_modified: Option<chrono::DateTime<chrono::Utc>>,
+    /// Last modification of the file in LLDAP_JWT_SECRET_FILE.
+    jwt_secret_modified: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<DomainExpiryStatus> for ExpiryStatus {
+    fn from(status: DomainExpiryStatus) -> Self {
+        Self {
+            checked_at: status.checked_at,
+            ldaps_certificate_not_after: status.ldaps_certificate_not_after,
+            private_key_modified: status.private_key_modified,
+            jwt_secret_modified: status.jwt_secret_modified,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// When enabled, only the admins can log in, through LDAP or the web UI.
+pub struct MaintenanceMode {
+    enabled: bool,
+    message: String,
+}
+
+impl From<MaintenanceStatus> for MaintenanceMode {
+    fn from(status: MaintenanceStatus) -> Self {
+        Self {
+            enabled: status.enabled,
+            message: status.message,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A membership added or removed to follow a group rule of the configuration.
+pub struct GroupRuleChange {
+    user_id: String,
+    group_id: i32,
+    group_name: String,
+    /// The rule the user matches when added, the first rule of the group when removed.
+    rule: String,
+    added: bool,
+}
+
+impl From<DomainGroupRuleChange> for GroupRuleChange {
+    fn from(change: DomainGroupRuleChange) -> Self {
+        Self {
+            user_id: change.user_id.to_string(),
+            group_id: change.group_id.0,
+            group_name: change.group_name.to_string(),
+            rule: change.rule,
+            added: change.added,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A request from a user to delete their own account.
+pub struct AccountDeletion {
+    user_id: String,
+    request_date: chrono::DateTime<chrono::Utc>,
+    /// Set once the request is confirmed by email: the account is disabled until then, and
+    /// deleted afterwards.
+    deletion_date: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<DomainAccountDeletion> for AccountDeletion {
+    fn from(deletion: DomainAccountDeletion) -> Self {
+        Self {
+            user_id: deletion.user_id.to_string(),
+            request_date: chrono::Utc.from_utc_datetime(&deletion.request_date),
+            deletion_date: deletion
+                .deletion_date
+                .map(|d| chrono::Utc.from_utc_datetime(&d)),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// The review of the members of a group, as part of an access review campaign.
+pub struct AccessReview {
+    id: i32,
+    campaign_id: i32,
+    campaign_name: String,
+    created_by: String,
+    creation_date: chrono::DateTime<chrono::Utc>,
+    group_id: i32,
+    group_name: String,
+    group_uuid: String,
+    reviewer: String,
+    /// The members of the group when the campaign started.
+    decisions: Vec<AccessReviewDecision>,
+}
+
+impl From<DomainAccessReview> for AccessReview {
+    fn from(review: DomainAccessReview) -> Self {
+        Self {
+            id: review.id,
+            campaign_id: review.campaign_id,
+            campaign_name: review.campaign_name,
+            created_by: review.created_by.to_string(),
+            creation_date: chrono::Utc.from_utc_datetime(&review.creation_date),
+            group_id: review.group_id.0,
+            group_name: review.group_name.to_string(),
+            group_uuid: review.group_uuid.into_string(),
+            reviewer: review.reviewer.to_string(),
+            decisions: review.decisions.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+pub struct AccessReviewDecision {
+    user_id: String,
+    /// Null until the reviewer decides. A member that is not kept is removed from the group.
+    keep: Option<bool>,
+    decision_date: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<DomainAccessReviewDecision> for AccessReviewDecision {
+    fn from(decision: DomainAccessReviewDecision) -> Self {
+        Self {
+            user_id: decision.user_id.to_string(),
+            keep: decision.keep,
+            decision_date: decision
+                .decision_date
+                .map(|d| chrono::Utc.from_utc_datetime(&d)),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// An account at an external identity provider that logs in as a user.
+pub struct IdentityLink {
+    /// The name of the provider in the configuration.
+    provider: String,
+    /// The identifier of the account at the provider.
+    subject: String,
+    creation_date: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<DomainIdentityLink> for IdentityLink {
+    fn from(link: DomainIdentityLink) -> Self {
+        Self {
+            provider: link.provider,
+            subject: link.subject,
+            creation_date: chrono::Utc.from_utc_datetime(&link.creation_date),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A change of one field of a user profile.
+pub struct ProfileChange {
+    /// The field that changed: email, display_name or the name of an attribute.
+    field: String,
+    /// The rendered value before the change, absent if the field was not set.
+    old_value: Option<String>,
+    /// The rendered value after the change, absent if the field was removed.
+    new_value: Option<String>,
+    /// The user who made the change, absent for changes made by LLDAP itself.
+    actor: Option<String>,
+    change_date: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<DomainProfileChange> for ProfileChange {
+    fn from(change: DomainProfileChange) -> Self {
+        Self {
+            field: change.field,
+            old_value: change.old_value,
+            new_value: change.new_value,
+            actor: change.actor.map(|actor| actor.to_string()),
+            change_date: chrono::Utc.from_utc_datetime(&change.change_date),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A value given to a user attribute when the user joins a group.
+pub struct AttributeTemplate {
+    attribute_name: String,
+    /// `{uid}` and `{email}` are replaced by the user's values.
+    value: String,
+    /// Whether to replace a value the user already has.
+    overwrite: bool,
+}
+
+impl From<DomainAttributeTemplate> for AttributeTemplate {
+    fn from(template: DomainAttributeTemplate) -> Self {
+        Self {
+            attribute_name: template.attribute_name.to_string(),
+            value: template.value,
+            overwrite: template.overwrite,
+        }
     }
 }
 
@@ -803,6 +1866,7 @@ mod tests {
     use super::*;
     use crate::{
         domain::{
+            error::DomainError,
             handler::AttributeList,
             types::{AttributeName, AttributeType, LdapObjectClass, Serialized},
         },
@@ -831,6 +1895,123 @@ mod tests {
         )
     }
 
+    #[tokio::test]
+    async fn get_unknown_user_error_code() {
+        const QUERY: &str = r#"{
+          user(userId: "bob") {
+            id
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        setup_default_schema(&mut mock);
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| Err(DomainError::EntityNotFound("bob".to_owned())));
+
+        let context =
+            Context::<MockTestBackendHandler>::new_for_tests(mock, ValidationResults::admin());
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        let (_, errors) = execute(QUERY, None, &schema, &Variables::new(), &context)
+            .await
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].error().extensions(),
+            &graphql_value!({ "code": "NOT_FOUND" })
+        );
+    }
+
+    fn mock_with_hardcoded_user_attribute(name: &str) -> MockTestBackendHandler {
+        let name = AttributeName::from(name);
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_schema().returning(move || {
+            Ok(crate::domain::handler::Schema {
+                user_attributes: DomainAttributeList {
+                    attributes: vec![DomainAttributeSchema {
+                        name: name.clone(),
+                        attribute_type: AttributeType::Integer,
+                        is_list: false,
+                        is_visible: true,
+                        is_editable: false,
+                        is_hardcoded: true,
+                        is_readonly: true,
+                    }],
+                },
+                group_attributes: DomainAttributeList {
+                    attributes: Vec::new(),
+                },
+                extra_user_object_classes: Vec::new(),
+                extra_group_object_classes: Vec::new(),
+            })
+        });
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| {
+                Ok(DomainUser {
+                    user_id: UserId::new("bob"),
+                    email: "bob@bobbers.on".into(),
+                    attributes: vec![DomainAttributeValue {
+                        name: "uidnumber".into(),
+                        value: Serialized::from(&10001i64),
+                    }],
+                    ..Default::default()
+                })
+            });
+        mock
+    }
+
+    #[tokio::test]
+    async fn get_user_stored_hardcoded_attribute() {
+        const QUERY: &str = r#"{
+          user(userId: "bob") {
+            attributes {
+              name
+              value
+            }
+          }
+        }"#;
+        let context = Context::<MockTestBackendHandler>::new_for_tests(
+            mock_with_hardcoded_user_attribute("uidnumber"),
+            ValidationResults::admin(),
+        );
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        let (result, errors) = execute(QUERY, None, &schema, &Variables::new(), &context)
+            .await
+            .unwrap();
+        assert_eq!(errors, vec![]);
+        let attributes = result
+            .as_object_value()
+            .and_then(|o| o.get_field_value("user"))
+            .and_then(|u| u.as_object_value())
+            .and_then(|u| u.get_field_value("attributes"))
+            .and_then(|a| a.as_list_value())
+            .unwrap();
+        assert!(attributes.contains(&graphql_value!({
+            "name": "uidnumber",
+            "value": ["10001"],
+        })));
+    }
+
+    #[tokio::test]
+    async fn get_user_unknown_hardcoded_attribute() {
+        const QUERY: &str = r#"{
+          user(userId: "bob") {
+            id
+          }
+        }"#;
+        let context = Context::<MockTestBackendHandler>::new_for_tests(
+            mock_with_hardcoded_user_attribute("shoe_size"),
+            ValidationResults::admin(),
+        );
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        let (_, errors) = execute(QUERY, None, &schema, &Variables::new(), &context)
+            .await
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+    }
+
     #[tokio::test]
     async fn get_user_by_id() {
         const QUERY: &str = r#"{
@@ -908,6 +2089,7 @@ mod tests {
                     user_id: UserId::new("bob"),
                     email: "bob@bobbers.on".into(),
                     creation_date: chrono::Utc.timestamp_millis_opt(42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_millis_opt(42).unwrap().naive_utc(),
                     uuid: crate::uuid!("b1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
                     attributes: vec![
                         DomainAttributeValue {
@@ -927,6 +2109,7 @@ mod tests {
             group_id: GroupId(3),
             display_name: "Bobbersons".into(),
             creation_date: chrono::Utc.timestamp_nanos(42).naive_utc(),
+            modified_date: chrono::Utc.timestamp_nanos(42).naive_utc(),
             uuid: crate::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
             attributes: vec![DomainAttributeValue {
                 name: "club_name".into(),
@@ -937,6 +2120,7 @@ mod tests {
             group_id: GroupId(7),
             display_name: "Jefferees".into(),
             creation_date: chrono::Utc.timestamp_nanos(12).naive_utc(),
+            modified_date: chrono::Utc.timestamp_nanos(12).naive_utc(),
             uuid: crate::uuid!("b1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
             attributes: Vec::new(),
         });
@@ -963,6 +2147,14 @@ mod tests {
                             "name": "creation_date",
                             "value": ["1970-01-01T00:00:00.042+00:00"],
                           },
+                          {
+                            "name": "first_name",
+                            "value": ["Bob"],
+                          },
+                          {
+                            "name": "last_name",
+                            "value": ["Bobberson"],
+                          },
                           {
                             "name": "mail",
                             "value": ["bob@bobbers.on"],
@@ -974,14 +2166,6 @@ mod tests {
                           {
                             "name": "uuid",
                             "value": ["b1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8"],
-                          },
-                          {
-                            "name": "first_name",
-                            "value": ["Bob"],
-                          },
-                          {
-                            "name": "last_name",
-                            "value": ["Bobberson"],
                         }],
                         "groups": [{
                             "id": 3,
@@ -1127,6 +2311,296 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn list_users_as_group_member() {
+        const QUERY: &str = r#"{
+          users {
+            id
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        setup_default_schema(&mut mock);
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| {
+                let mut groups = HashSet::new();
+                groups.insert(GroupDetails {
+                    group_id: GroupId(3),
+                    display_name: "Bobbersons".into(),
+                    creation_date: chrono::Utc.timestamp_nanos(42).naive_utc(),
+                    modified_date: chrono::Utc.timestamp_nanos(42).naive_utc(),
+                    uuid: crate::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    attributes: Vec::new(),
+                });
+                Ok(groups)
+            });
+        mock.expect_list_users()
+            .with(
+                eq(Some(DomainRequestFilter::Or(vec![
+                    DomainRequestFilter::UserId(UserId::new("bob")),
+                    DomainRequestFilter::MemberOfId(GroupId(3)),
+                ]))),
+                eq(false),
+            )
+            .return_once(|_, _| {
+                Ok(vec![DomainUserAndGroups {
+                    user: DomainUser {
+                        user_id: UserId::new("robert"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+
+        let mut context = Context::<MockTestBackendHandler>::new_for_tests(
+            mock,
+            ValidationResults {
+                user: UserId::new("bob"),
+                permission: Permission::Regular,
+                impersonation: None,
+            },
+        );
+        context.directory_visibility = DirectoryVisibility::GroupMembers;
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        assert_eq!(
+            execute(QUERY, None, &schema, &Variables::new(), &context).await,
+            Ok((graphql_value!({"users": [{"id": "robert"}]}), vec![]))
+        );
+    }
+
+    #[tokio::test]
+    async fn list_users_hidden_directory() {
+        const QUERY: &str = r#"{
+          users {
+            id
+          }
+        }"#;
+
+        let context = Context::<MockTestBackendHandler>::new_for_tests(
+            MockTestBackendHandler::new(),
+            ValidationResults {
+                user: UserId::new("bob"),
+                permission: Permission::Regular,
+                impersonation: None,
+            },
+        );
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        let (result, errors) = execute(QUERY, None, &schema, &Variables::new(), &context)
+            .await
+            .unwrap();
+        assert!(result.is_null());
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn list_groups_with_users_batched() {
+        const QUERY: &str = r#"{
+          groups {
+            id
+            users {
+              id
+            }
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        setup_default_schema(&mut mock);
+        let make_group = |id: i32, name: &str| DomainGroup {
+            id: GroupId(id),
+            display_name: name.into(),
+            creation_date: chrono::Utc.timestamp_nanos(42).naive_utc(),
+            modified_date: chrono::Utc.timestamp_nanos(42).naive_utc(),
+            uuid: crate::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            users: Vec::new(),
+            attributes: Vec::new(),
+        };
+        let groups = vec![make_group(1, "admins"), make_group(2, "users")];
+        mock.expect_list_groups()
+            .with(eq(None))
+            .return_once(move |_| Ok(groups));
+        let make_details = |id: i32, name: &str| GroupDetails {
+            group_id: GroupId(id),
+            display_name: name.into(),
+            creation_date: chrono::Utc.timestamp_nanos(42).naive_utc(),
+            modified_date: chrono::Utc.timestamp_nanos(42).naive_utc(),
+            uuid: crate::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            attributes: Vec::new(),
+        };
+        let users = vec![
+            DomainUserAndGroups {
+                user: DomainUser {
+                    user_id: UserId::new("bob"),
+                    ..Default::default()
+                },
+                groups: Some(vec![make_details(1, "admins"), make_details(2, "users")]),
+            },
+            DomainUserAndGroups {
+                user: DomainUser {
+                    user_id: UserId::new("john"),
+                    ..Default::default()
+                },
+                groups: Some(vec![make_details(2, "users"), make_details(3, "other")]),
+            },
+        ];
+        // A single query for the members of all the groups.
+        mock.expect_list_users()
+            .with(
+                eq(Some(DomainRequestFilter::Or(vec![
+                    DomainRequestFilter::MemberOfId(GroupId(1)),
+                    DomainRequestFilter::MemberOfId(GroupId(2)),
+                ]))),
+                eq(true),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(users));
+
+        let context =
+            Context::<MockTestBackendHandler>::new_for_tests(mock, ValidationResults::admin());
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        assert_eq!(
+            execute(QUERY, None, &schema, &Variables::new(), &context).await,
+            Ok((
+                graphql_value!(
+                {
+                    "groups": [
+                        {
+                            "id": 1,
+                            "users": [{"id": "bob"}],
+                        },
+                        {
+                            "id": 2,
+                            "users": [{"id": "bob"}, {"id": "john"}],
+                        },
+                    ]
+                }),
+                vec![]
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn list_groups_with_filter_and_pagination() {
+        const QUERY: &str = r#"{
+          groups(filter: {any: [{displayNameContains: "adm"}, {eq: {field: "cn", value: "ops"}}]},
+                 offset: 1, limit: 1) {
+            id
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        setup_default_schema(&mut mock);
+        let make_group = |id: i32, name: &str| DomainGroup {
+            id: GroupId(id),
+            display_name: name.into(),
+            creation_date: chrono::Utc.timestamp_nanos(42).naive_utc(),
+            modified_date: chrono::Utc.timestamp_nanos(42).naive_utc(),
+            uuid: crate::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            users: Vec::new(),
+            attributes: Vec::new(),
+        };
+        let groups = vec![
+            make_group(1, "admins"),
+            make_group(2, "ops"),
+            make_group(3, "sysadmins"),
+        ];
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::Or(vec![
+                GroupRequestFilter::DisplayNameSubString(SubStringFilter {
+                    initial: None,
+                    any: vec!["adm".to_owned()],
+                    final_: None,
+                }),
+                GroupRequestFilter::DisplayName("ops".into()),
+            ]))))
+            .return_once(move |_| Ok(groups));
+
+        let context =
+            Context::<MockTestBackendHandler>::new_for_tests(mock, ValidationResults::admin());
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        assert_eq!(
+            execute(QUERY, None, &schema, &Variables::new(), &context).await,
+            Ok((graphql_value!({"groups": [{"id": 2}]}), vec![]))
+        );
+    }
+
+    #[tokio::test]
+    async fn get_group_by_uuid() {
+        const QUERY: &str = r#"{
+          groupByUuid(uuid: "A1A2A3A4-B1B2-C1C2-D1D2-D3D4D5D6D7D8") {
+            id
+            displayName
+            uuid
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        setup_default_schema(&mut mock);
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::Uuid(crate::uuid!(
+                "a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8"
+            )))))
+            .return_once(|_| {
+                Ok(vec![DomainGroup {
+                    id: GroupId(3),
+                    display_name: "admins".into(),
+                    creation_date: chrono::Utc.timestamp_nanos(42).naive_utc(),
+                    modified_date: chrono::Utc.timestamp_nanos(42).naive_utc(),
+                    uuid: crate::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    users: Vec::new(),
+                    attributes: Vec::new(),
+                }])
+            });
+
+        let context =
+            Context::<MockTestBackendHandler>::new_for_tests(mock, ValidationResults::admin());
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        assert_eq!(
+            execute(QUERY, None, &schema, &Variables::new(), &context).await,
+            Ok((
+                graphql_value!(
+                {
+                    "groupByUuid": {
+                        "id": 3,
+                        "displayName": "admins",
+                        "uuid": "a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8",
+                    }
+                }),
+                vec![]
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn get_group_by_invalid_uuid() {
+        const QUERY: &str = r#"{
+          groupByUuid(uuid: "admins") {
+            id
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        setup_default_schema(&mut mock);
+        mock.expect_list_groups().never();
+        let context =
+            Context::<MockTestBackendHandler>::new_for_tests(mock, ValidationResults::admin());
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        let (_, errors) = execute(QUERY, None, &schema, &Variables::new(), &context)
+            .await
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].error().extensions(),
+            &graphql_value!({ "code": "VALIDATION" })
+        );
+    }
+
     #[tokio::test]
     async fn get_schema() {
         const QUERY: &str = r#"{
@@ -1324,6 +2798,7 @@ mod tests {
             ValidationResults {
                 user: UserId::new("bob"),
                 permission: Permission::Regular,
+                impersonation: None,
             },
         );
 
@@ -1350,4 +2825,114 @@ mod tests {
             ))
         );
     }
+
+    #[tokio::test]
+    async fn list_changes() {
+        const QUERY: &str = r#"{
+          changes(since: 4, limit: 2) {
+            sequence
+            changeDate
+            kind
+            userId
+            groupId
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_changes()
+            .with(eq(4), eq(2))
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![
+                    ChangeFeedEntry {
+                        sequence: 5,
+                        change_date: chrono::Utc.timestamp_millis_opt(42).unwrap().naive_utc(),
+                        event: ChangeEvent::UserCreated(UserId::new("bob")),
+                    },
+                    ChangeFeedEntry {
+                        sequence: 6,
+                        change_date: chrono::Utc.timestamp_millis_opt(43).unwrap().naive_utc(),
+                        event: ChangeEvent::MembershipAdded {
+                            user_id: UserId::new("bob"),
+                            group_id: GroupId(3),
+                        },
+                    },
+                ])
+            });
+
+        let context =
+            Context::<MockTestBackendHandler>::new_for_tests(mock, ValidationResults::admin());
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        assert_eq!(
+            execute(QUERY, None, &schema, &Variables::new(), &context).await,
+            Ok((
+                graphql_value!(
+                {
+                    "changes": [
+                        {
+                            "sequence": 5,
+                            "changeDate": "1970-01-01T00:00:00.042+00:00",
+                            "kind": "USER_CREATED",
+                            "userId": "bob",
+                            "groupId": None,
+                        },
+                        {
+                            "sequence": 6,
+                            "changeDate": "1970-01-01T00:00:00.043+00:00",
+                            "kind": "MEMBERSHIP_ADDED",
+                            "userId": "bob",
+                            "groupId": 3,
+                        },
+                    ]
+                }),
+                vec![]
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn group_rules_preview() {
+        const QUERY: &str = r#"{
+          groupRulesPreview {
+            userId
+            groupName
+            rule
+            added
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_preview_group_rules().times(1).return_once(|| {
+            Ok(vec![DomainGroupRuleChange {
+                user_id: UserId::new("bob"),
+                group_id: GroupId(3),
+                group_name: "it-staff".into(),
+                rule: "it".to_owned(),
+                added: true,
+            }])
+        });
+
+        let context =
+            Context::<MockTestBackendHandler>::new_for_tests(mock, ValidationResults::admin());
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        assert_eq!(
+            execute(QUERY, None, &schema, &Variables::new(), &context).await,
+            Ok((
+                graphql_value!(
+                {
+                    "groupRulesPreview": [
+                        {
+                            "userId": "bob",
+                            "groupName": "it-staff",
+                            "rule": "it",
+                            "added": true,
+                        },
+                    ]
+                }),
+                vec![]
+            ))
+        );
+    }
 }
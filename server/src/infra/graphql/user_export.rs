@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    domain::{
+        account_deletions::AccountDeletion,
+        change_events::{ChangeEvent, ChangeFeedEntry},
+        error::Result,
+        types::{GroupDetails, SessionKind, User, UserId, UserSession},
+    },
+    infra::{access_control::UserReadableBackendHandler, graphql::query::serialize_attribute},
+};
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Serialize;
+
+/// Everything stored about a user, in a machine-readable form (e.g. for GDPR requests).
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserDataExport {
+    export_date: DateTime<Utc>,
+    user: ExportedUser,
+    groups: Vec<ExportedGroup>,
+    /// The changes to the user still retained in the change feed.
+    changes: Vec<ExportedChange>,
+    sessions: Vec<ExportedSession>,
+    account_deletion: Option<ExportedAccountDeletion>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportedUser {
+    id: String,
+    email: String,
+    display_name: Option<String>,
+    creation_date: DateTime<Utc>,
+    modified_date: DateTime<Utc>,
+    uuid: String,
+    external_id: Option<String>,
+    attributes: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportedGroup {
+    id: i32,
+    display_name: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportedChange {
+    sequence: i32,
+    date: DateTime<Utc>,
+    event: ChangeEvent,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportedSession {
+    kind: SessionKind,
+    expiry_date: DateTime<Utc>,
+    revoked: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportedAccountDeletion {
+    request_date: DateTime<Utc>,
+    deletion_date: Option<DateTime<Utc>>,
+}
+
+fn to_utc(date: chrono::NaiveDateTime) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&date)
+}
+
+impl From<GroupDetails> for ExportedGroup {
+    fn from(group: GroupDetails) -> Self {
+        Self {
+            id: group.group_id.0,
+            display_name: group.display_name.into_string(),
+        }
+    }
+}
+
+impl From<ChangeFeedEntry> for ExportedChange {
+    fn from(entry: ChangeFeedEntry) -> Self {
+        Self {
+            sequence: entry.sequence,
+            date: to_utc(entry.change_date),
+            event: entry.event,
+        }
+    }
+}
+
+impl From<UserSession> for ExportedSession {
+    fn from(session: UserSession) -> Self {
+        Self {
+            kind: session.kind,
+            expiry_date: to_utc(session.expiry_date),
+            revoked: session.revoked,
+        }
+    }
+}
+
+impl From<AccountDeletion> for ExportedAccountDeletion {
+    fn from(deletion: AccountDeletion) -> Self {
+        Self {
+            request_date: to_utc(deletion.request_date),
+            deletion_date: deletion.deletion_date.map(to_utc),
+        }
+    }
+}
+
+pub async fn export_user_data<Handler: UserReadableBackendHandler>(
+    handler: &Handler,
+    user_id: &UserId,
+) -> Result<UserDataExport> {
+    let User {
+        user_id,
+        email,
+        display_name,
+        creation_date,
+        modified_date,
+        uuid,
+        external_id,
+        attributes,
+    } = handler.get_user_details(user_id).await?;
+    let schema = UserReadableBackendHandler::get_schema(handler).await?;
+    let user_attributes = &schema.get_schema().user_attributes;
+    let attributes = attributes
+        .into_iter()
+        .filter_map(|a| {
+            user_attributes
+                .get_attribute_schema(&a.name)
+                .map(|s| (a.name.to_string(), serialize_attribute(&a, s)))
+        })
+        .collect();
+    let mut groups = handler
+        .get_user_groups(&user_id)
+        .await?
+        .into_iter()
+        .map(ExportedGroup::from)
+        .collect::<Vec<_>>();
+    groups.sort_by_key(|g| g.id);
+    Ok(UserDataExport {
+        export_date: Utc::now(),
+        changes: handler
+            .list_changes_for_user(&user_id)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+        sessions: handler
+            .list_user_sessions(&user_id)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+        account_deletion: handler
+            .get_account_deletion(&user_id)
+            .await?
+            .map(Into::into),
+        user: ExportedUser {
+            id: user_id.to_string(),
+            email: email.into_string(),
+            display_name,
+            creation_date: to_utc(creation_date),
+            modified_date: to_utc(modified_date),
+            uuid: uuid.to_string(),
+            external_id,
+            attributes,
+        },
+        groups,
+    })
+}
@@ -0,0 +1,150 @@
+use crate::{
+    domain::{
+        handler::{BackendHandler, GroupRequestFilter, UserRequestFilter},
+        types::{Email, GroupName},
+    },
+    infra::{
+        access_control::ReadonlyBackendHandler,
+        auth_service::check_if_token_is_valid,
+        tcp_server::{error_to_http_response, AppState, TcpError, TcpResult},
+    },
+};
+use actix_web::{web, HttpResponse};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use tracing::instrument;
+
+/// Lists the emails of the members of a group, to use it as a distribution list.
+pub async fn get_group_member_emails(
+    handler: &impl ReadonlyBackendHandler,
+    group_name: &GroupName,
+) -> TcpResult<Vec<Email>> {
+    if handler
+        .list_groups(Some(GroupRequestFilter::DisplayName(group_name.clone())))
+        .await?
+        .is_empty()
+    {
+        return Err(TcpError::NotFoundError(format!(
+            r#"Group "{}" not found"#,
+            group_name
+        )));
+    }
+    let mut emails = handler
+        .list_users(Some(UserRequestFilter::MemberOf(group_name.clone())), false)
+        .await?
+        .into_iter()
+        .map(|u| u.user.email)
+        .collect::<Vec<_>>();
+    emails.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    Ok(emails)
+}
+
+#[instrument(skip(data, bearer), level = "debug")]
+async fn get_group_emails<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+    group_name: web::Path<String>,
+) -> TcpResult<Vec<Email>>
+where
+    Backend: BackendHandler + 'static,
+{
+    let validation_result = check_if_token_is_valid(&data, bearer.token())
+        .map_err(|e| TcpError::UnauthorizedError(e.to_string()))?;
+    let handler = data
+        .backend_handler
+        .get_readonly_handler(&validation_result)
+        .ok_or_else(|| {
+            TcpError::UnauthorizedError("Listing group emails requires read access".to_owned())
+        })?;
+    get_group_member_emails(handler, &GroupName::from(group_name.into_inner())).await
+}
+
+async fn get_group_emails_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+    group_name: web::Path<String>,
+) -> HttpResponse
+where
+    Backend: BackendHandler + 'static,
+{
+    get_group_emails(data, bearer, group_name)
+        .await
+        .map(|emails| HttpResponse::Ok().json(emails))
+        .unwrap_or_else(error_to_http_response)
+}
+
+pub fn configure_endpoint<Backend>(cfg: &mut web::ServiceConfig)
+where
+    Backend: BackendHandler + 'static,
+{
+    cfg.service(
+        web::resource("/groups/{name}/emails")
+            .route(web::get().to(get_group_emails_handler::<Backend>)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::types::{Group, GroupId, User, UserAndGroups, Uuid},
+        infra::test_utils::MockTestBackendHandler,
+    };
+    use mockall::predicate::eq;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_get_group_member_emails() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::DisplayName("team".into()))))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![Group {
+                    id: GroupId(1),
+                    display_name: "team".into(),
+                    creation_date: chrono::Utc::now().naive_utc(),
+                    modified_date: chrono::Utc::now().naive_utc(),
+                    uuid: Uuid::from_name_and_date("team", &chrono::Utc::now().naive_utc()),
+                    users: Vec::new(),
+                    attributes: Vec::new(),
+                }])
+            });
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::MemberOf("team".into()))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(["john@example.com", "bob@example.com"]
+                    .into_iter()
+                    .map(|email| UserAndGroups {
+                        user: User {
+                            email: email.into(),
+                            ..Default::default()
+                        },
+                        groups: None,
+                    })
+                    .collect())
+            });
+        assert_eq!(
+            get_group_member_emails(&mock, &"team".into())
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|e| e.into_string())
+                .collect::<Vec<_>>(),
+            vec!["bob@example.com", "john@example.com"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_group_member_emails_unknown_group() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups().return_once(|_| Ok(Vec::new()));
+        assert!(matches!(
+            get_group_member_emails(&mock, &"team".into()).await,
+            Err(TcpError::NotFoundError(_))
+        ));
+    }
+}
@@ -1,4 +1,7 @@
-use crate::infra::{configuration::LdapsOptions, ldap_server::read_certificates};
+use crate::infra::{
+    configuration::{Configuration, LdapsOptions},
+    ldap_server::read_certificates,
+};
 use anyhow::{anyhow, bail, ensure, Context, Result};
 use futures_util::SinkExt;
 use ldap3_proto::{
@@ -8,10 +11,12 @@ use ldap3_proto::{
     },
     LdapCodec,
 };
+use serde::Serialize;
+use std::time::Duration;
 use tokio::net::TcpStream;
 use tokio_rustls::TlsConnector as RustlsTlsConnector;
 use tokio_util::codec::{FramedRead, FramedWrite};
-use tracing::{debug, info, instrument};
+use tracing::{debug, error, info, instrument};
 
 async fn check_ldap_endpoint<Stream>(stream: Stream) -> Result<()>
 where
@@ -158,3 +163,62 @@ pub async fn check_api(port: u16) -> Result<()> {
     info!("Success");
     Ok(())
 }
+
+/// The outcome of one of the checks.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CheckOutcome {
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+impl From<Result<()>> for CheckOutcome {
+    fn from(result: Result<()>) -> Self {
+        match result {
+            Ok(()) => Self {
+                ok: true,
+                error: None,
+            },
+            Err(e) => {
+                error!("Error running the health check: {:#}", e);
+                Self {
+                    ok: false,
+                    error: Some(format!("{:#}", e)),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct HealthCheckReport {
+    pub ldap: CheckOutcome,
+    pub ldaps: CheckOutcome,
+    pub api: CheckOutcome,
+}
+
+impl HealthCheckReport {
+    pub fn is_healthy(&self) -> bool {
+        [&self.ldap, &self.ldaps, &self.api].iter().all(|c| c.ok)
+    }
+}
+
+/// Runs the checks concurrently, each of them with a timeout.
+pub async fn run(config: &Configuration) -> HealthCheckReport {
+    use tokio::time::timeout;
+    let delay = Duration::from_millis(3000);
+    let with_timeout = |result: Result<Result<()>, tokio::time::error::Elapsed>| -> CheckOutcome {
+        result
+            .unwrap_or_else(|_| Err(anyhow!("No answer after {:?}", delay)))
+            .into()
+    };
+    let (ldap, ldaps, api) = tokio::join!(
+        timeout(delay, check_ldap(config.ldap_port)),
+        timeout(delay, check_ldaps(&config.ldaps_options)),
+        timeout(delay, check_api(config.http_port)),
+    );
+    HealthCheckReport {
+        ldap: with_timeout(ldap),
+        ldaps: with_timeout(ldaps),
+        api: with_timeout(api),
+    }
+}
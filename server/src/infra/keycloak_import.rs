@@ -0,0 +1,399 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    domain::{
+        handler::{
+            BackendHandler, CreateGroupRequest, CreateUserRequest, GroupBackendHandler,
+            GroupListerBackendHandler, UserBackendHandler, UserCreationSource,
+            UserListerBackendHandler,
+        },
+        types::{GroupId, GroupName, UserId},
+    },
+    infra::{reset_delivery::ResetDelivery, tcp_backend_handler::TcpBackendHandler},
+};
+
+/// The parts of a Keycloak realm export (`kc.sh export --realm ...`) that LLDAP can use.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct KeycloakRealm {
+    pub users: Vec<KeycloakUser>,
+    pub groups: Vec<KeycloakGroup>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeycloakUser {
+    /// The Keycloak UUID of the user, kept as the external ID.
+    #[serde(default)]
+    pub id: Option<String>,
+    pub username: String,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub first_name: Option<String>,
+    #[serde(default)]
+    pub last_name: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Set for the users backing the service accounts of the clients.
+    #[serde(default)]
+    pub service_account_client_id: Option<String>,
+    /// Paths of the groups, e.g. `/staff/admins`.
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default)]
+    pub realm_roles: Vec<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeycloakGroup {
+    pub name: String,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub realm_roles: Vec<String>,
+    #[serde(default)]
+    pub sub_groups: Vec<KeycloakGroup>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct KeycloakImportOptions {
+    /// The members of a realm role, directly or through one of their groups, are added to the
+    /// LLDAP group.
+    pub role_mappings: Vec<(String, GroupName)>,
+}
+
+/// Parses a role mapping given as `role=group`.
+pub fn parse_role_mapping(mapping: &str) -> Result<(String, GroupName)> {
+    match mapping.split_once('=') {
+        Some((role, group)) if !role.is_empty() && !group.is_empty() => {
+            Ok((role.to_owned(), GroupName::from(group)))
+        }
+        _ => Err(anyhow!(
+            r#"Invalid role mapping "{}", expected "role=group""#,
+            mapping
+        )),
+    }
+}
+
+/// The name of a Keycloak group in LLDAP, where groups are flat: the path without the leading
+/// slash, e.g. `staff/admins` for a subgroup.
+fn group_name(path: &str) -> GroupName {
+    GroupName::from(path.trim_start_matches('/'))
+}
+
+/// Flattens the group tree, with the realm roles of each group by group path.
+fn flatten_groups(
+    groups: &[KeycloakGroup],
+    parent_path: &str,
+    roles_by_group: &mut HashMap<String, Vec<String>>,
+) {
+    for group in groups {
+        let path = group
+            .path
+            .clone()
+            .unwrap_or_else(|| format!("{}/{}", parent_path, group.name));
+        flatten_groups(&group.sub_groups, &path, roles_by_group);
+        roles_by_group.insert(path, group.realm_roles.clone());
+    }
+}
+
+fn to_create_user_request(user: &KeycloakUser) -> Option<CreateUserRequest> {
+    let email = user.email.as_deref().filter(|e| !e.is_empty())?;
+    let display_name = [user.first_name.as_deref(), user.last_name.as_deref()]
+        .into_iter()
+        .flatten()
+        .filter(|n| !n.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some(CreateUserRequest {
+        user_id: UserId::new(&user.username),
+        email: email.into(),
+        display_name: Some(display_name).filter(|n| !n.is_empty()),
+        first_name: user.first_name.clone(),
+        last_name: user.last_name.clone(),
+        external_id: user.id.clone(),
+        source: UserCreationSource::Import,
+        ..Default::default()
+    })
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ImportSummary {
+    /// The new users, who don't have a password yet.
+    pub created_users: Vec<UserId>,
+    pub created_groups: usize,
+    pub skipped_users: usize,
+    pub memberships: usize,
+}
+
+async fn get_or_create_group<Handler: GroupBackendHandler>(
+    handler: &Handler,
+    groups: &mut HashMap<GroupName, GroupId>,
+    summary: &mut ImportSummary,
+    name: GroupName,
+) -> Result<GroupId> {
+    if let Some(id) = groups.get(&name) {
+        return Ok(*id);
+    }
+    let id = handler
+        .create_group(CreateGroupRequest {
+            display_name: name.clone(),
+            ..Default::default()
+        })
+        .await
+        .with_context(|| format!("while creating the group {}", name))?;
+    summary.created_groups += 1;
+    groups.insert(name, id);
+    Ok(id)
+}
+
+/// Creates the users and groups of the realm that don't exist yet, and adds the users to their
+/// groups and to the groups their roles are mapped to. Users without an email, disabled users
+/// and service accounts are skipped. The passwords can't be imported: Keycloak's hashes don't
+/// work with OPAQUE.
+pub async fn import<Handler: BackendHandler>(
+    handler: &Handler,
+    realm: &KeycloakRealm,
+    options: &KeycloakImportOptions,
+) -> Result<ImportSummary> {
+    let mut summary = ImportSummary::default();
+    let mut groups: HashMap<GroupName, GroupId> = handler
+        .list_groups(None)
+        .await?
+        .into_iter()
+        .map(|g| (g.display_name, g.id))
+        .collect();
+    let existing_users: HashSet<UserId> = handler
+        .list_users(None, false)
+        .await?
+        .into_iter()
+        .map(|u| u.user.user_id)
+        .collect();
+    let mut roles_by_group = HashMap::new();
+    flatten_groups(&realm.groups, "", &mut roles_by_group);
+    let mut group_paths = roles_by_group.keys().cloned().collect::<Vec<_>>();
+    group_paths.sort();
+    for path in group_paths {
+        get_or_create_group(handler, &mut groups, &mut summary, group_name(&path)).await?;
+    }
+
+    for user in &realm.users {
+        if let Some(client) = &user.service_account_client_id {
+            info!(
+                "Skipping {}, the service account of the client {}",
+                user.username, client
+            );
+            summary.skipped_users += 1;
+            continue;
+        }
+        if !user.enabled {
+            warn!("Skipping the disabled user {}", user.username);
+            summary.skipped_users += 1;
+            continue;
+        }
+        let request = match to_create_user_request(user) {
+            Some(request) => request,
+            None => {
+                warn!("Skipping {}, who has no email", user.username);
+                summary.skipped_users += 1;
+                continue;
+            }
+        };
+        let user_id = request.user_id.clone();
+        if existing_users.contains(&user_id) {
+            info!("{} already exists, updating the memberships only", user_id);
+        } else {
+            handler
+                .create_user(request)
+                .await
+                .with_context(|| format!("while creating the user {}", user_id))?;
+            summary.created_users.push(user_id.clone());
+        }
+
+        let roles = user
+            .realm_roles
+            .iter()
+            .chain(
+                user.groups
+                    .iter()
+                    .flat_map(|path| roles_by_group.get(path).into_iter().flatten()),
+            )
+            .collect::<HashSet<_>>();
+        let mut target_groups = user
+            .groups
+            .iter()
+            .map(|p| group_name(p))
+            .collect::<Vec<_>>();
+        target_groups.extend(
+            options
+                .role_mappings
+                .iter()
+                .filter(|(role, _)| roles.contains(&role))
+                .map(|(_, group)| group.clone()),
+        );
+        let mut current_groups = handler
+            .get_user_groups(&user_id)
+            .await?
+            .into_iter()
+            .map(|g| g.group_id)
+            .collect::<HashSet<_>>();
+        for name in target_groups {
+            let group_id = get_or_create_group(handler, &mut groups, &mut summary, name).await?;
+            if current_groups.insert(group_id) {
+                handler.add_user_to_group(&user_id, group_id).await?;
+                summary.memberships += 1;
+            }
+        }
+    }
+    info!(
+        "Created {} users and {} groups, added {} memberships, skipped {} users",
+        summary.created_users.len(),
+        summary.created_groups,
+        summary.memberships,
+        summary.skipped_users
+    );
+    Ok(summary)
+}
+
+/// Sends a password reset link to each user, so that they can pick a password.
+pub async fn send_invitations<Handler: TcpBackendHandler + UserBackendHandler>(
+    handler: &Handler,
+    delivery: &dyn ResetDelivery,
+    users: &[UserId],
+) -> Result<()> {
+    for user_id in users {
+        let token = handler
+            .start_password_reset(user_id)
+            .await?
+            .ok_or_else(|| anyhow!("User {} not found", user_id))?;
+        let user = handler.get_user_details(user_id).await?;
+        if let Err(e) = delivery.send_reset_link(&user, &token).await {
+            warn!("Could not send the invitation to {}: {:#}", user_id, e);
+        }
+    }
+    info!("Sent {} invitations", users.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::sql_backend_handler::{tests::*, SqlBackendHandler};
+    use pretty_assertions::assert_eq;
+
+    const REALM: &str = r#"{
+        "realm": "example",
+        "groups": [
+            {
+                "name": "staff",
+                "path": "/staff",
+                "realmRoles": ["admin"],
+                "subGroups": [{"name": "workshop", "path": "/staff/workshop", "subGroups": []}]
+            },
+            {"name": "members", "path": "/members", "subGroups": []}
+        ],
+        "users": [
+            {
+                "id": "5b2e5a0c-8c4f-4b1e-9d3a-7f0e2c1d4a6b",
+                "username": "alice",
+                "email": "alice@example.com",
+                "firstName": "Alice",
+                "lastName": "Liddell",
+                "enabled": true,
+                "groups": ["/staff/workshop", "/staff"]
+            },
+            {
+                "username": "bob",
+                "email": "bob@example.com",
+                "enabled": true,
+                "realmRoles": ["default-roles-example", "auditor"],
+                "groups": ["/members"]
+            },
+            {"username": "carol", "enabled": true},
+            {"username": "dave", "email": "dave@example.com", "enabled": false},
+            {
+                "username": "service-account-grafana",
+                "enabled": true,
+                "serviceAccountClientId": "grafana"
+            }
+        ]
+    }"#;
+
+    async fn get_group_names(handler: &SqlBackendHandler, user: &str) -> Vec<String> {
+        let mut names = handler
+            .get_user_groups(&UserId::new(user))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|g| g.display_name.to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_parse_role_mapping() {
+        assert_eq!(
+            parse_role_mapping("admin=lldap_admin").unwrap(),
+            ("admin".to_owned(), GroupName::from("lldap_admin"))
+        );
+        assert!(parse_role_mapping("admin").is_err());
+        assert!(parse_role_mapping("=lldap_admin").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import() {
+        let handler = SqlBackendHandler::new(get_default_config(), get_initialized_db().await);
+        let realm: KeycloakRealm = serde_json::from_str(REALM).unwrap();
+        let options = KeycloakImportOptions {
+            role_mappings: vec![
+                ("admin".to_owned(), GroupName::from("admins")),
+                ("auditor".to_owned(), GroupName::from("members")),
+            ],
+        };
+        let summary = import(&handler, &realm, &options).await.unwrap();
+        assert_eq!(
+            summary,
+            ImportSummary {
+                created_users: vec![UserId::new("alice"), UserId::new("bob")],
+                created_groups: 4,
+                skipped_users: 3,
+                memberships: 4,
+            }
+        );
+        let alice = handler
+            .get_user_details(&UserId::new("alice"))
+            .await
+            .unwrap();
+        assert_eq!(alice.display_name.as_deref(), Some("Alice Liddell"));
+        assert_eq!(
+            alice.external_id.as_deref(),
+            Some("5b2e5a0c-8c4f-4b1e-9d3a-7f0e2c1d4a6b")
+        );
+        assert_eq!(
+            get_group_names(&handler, "alice").await,
+            vec!["admins", "staff", "staff/workshop"]
+        );
+        assert_eq!(get_group_names(&handler, "bob").await, vec!["members"]);
+
+        // Importing again doesn't duplicate anything.
+        let summary = import(&handler, &realm, &options).await.unwrap();
+        assert_eq!(
+            summary,
+            ImportSummary {
+                skipped_users: 3,
+                ..Default::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use std::{net::IpAddr, sync::Arc};
+
+use chrono::NaiveDateTime;
+use tracing::error;
+
+use crate::{
+    domain::types::UserId,
+    infra::{configuration::LoginThrottlingOptions, tcp_backend_handler::TcpBackendHandler},
+};
+
+/// Slows down the brute force attacks on the LDAP binds: after too many failed binds within the
+/// window, the user (and separately the client address) is locked out for a while. Uses the same
+/// limits and the same counters as the web login, stored in the database: they are shared by all
+/// the LDAP sessions and all the instances, and survive a restart.
+#[derive(Default)]
+pub struct LdapBindLimiter {
+    options: LoginThrottlingOptions,
+    /// Without a store, nothing is counted.
+    store: Option<Arc<dyn TcpBackendHandler + Send>>,
+}
+
+impl LdapBindLimiter {
+    pub fn new(options: &LoginThrottlingOptions, store: Arc<dyn TcpBackendHandler + Send>) -> Self {
+        Self {
+            options: options.clone(),
+            store: Some(store),
+        }
+    }
+
+    /// The keys under which the failed binds are counted, in the same format as for the web
+    /// login.
+    pub fn keys(user_id: &UserId, client_ip: Option<IpAddr>) -> Vec<String> {
+        std::iter::once(Self::user_key(user_id))
+            .chain(client_ip.map(|ip| format!("ip:{}", ip)))
+            .collect()
+    }
+
+    fn user_key(user_id: &UserId) -> String {
+        format!("user:{}", user_id)
+    }
+
+    fn store(&self) -> Option<&(dyn TcpBackendHandler + Send)> {
+        self.store.as_deref().filter(|_| self.options.enabled)
+    }
+
+    /// The end of the lockout of any of the keys, if one is locked out. A failure to read the
+    /// counters is only logged: the bind needs the database anyway.
+    pub async fn get_lockout(&self, keys: &[String]) -> Option<NaiveDateTime> {
+        let store = self.store()?;
+        let mut lockout = None;
+        for key in keys {
+            match store.get_login_lockout(key).await {
+                Ok(locked_until) => lockout = std::cmp::max(lockout, locked_until),
+                Err(e) => error!("Could not read the failed binds of {}: {}", key, e),
+            }
+        }
+        lockout
+    }
+
+    /// Counts a failed bind. Returns the end of the lockout if this failure triggered one.
+    pub async fn record_failure(&self, keys: &[String]) -> Option<NaiveDateTime> {
+        let store = self.store()?;
+        let mut lockout = None;
+        for key in keys {
+            match store.record_login_failure(key, &self.options).await {
+                Ok(locked_until) => lockout = std::cmp::max(lockout, locked_until),
+                Err(e) => error!("Could not count the failed bind of {}: {}", key, e),
+            }
+        }
+        lockout
+    }
+
+    /// Forgets the failed binds of the user, after a successful one.
+    pub async fn clear(&self, user_id: &UserId) {
+        let Some(store) = self.store() else {
+            return;
+        };
+        if let Err(e) = store.clear_login_failures(&Self::user_key(user_id)).await {
+            error!("Could not reset the failed binds of {}: {}", user_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::sql_backend_handler::{tests::*, SqlBackendHandler},
+        infra::configuration::LoginThrottlingOptionsBuilder,
+    };
+    use pretty_assertions::assert_eq;
+
+    async fn limiter(options: LoginThrottlingOptionsBuilder) -> LdapBindLimiter {
+        let handler = SqlBackendHandler::new(get_default_config(), get_initialized_db().await);
+        LdapBindLimiter::new(&options.build().unwrap(), Arc::new(handler))
+    }
+
+    #[tokio::test]
+    async fn test_bind_lockout() {
+        let limiter = limiter(LoginThrottlingOptionsBuilder::default().max_failures(3)).await;
+        let keys = LdapBindLimiter::keys(&UserId::new("bob"), "192.0.2.1".parse().ok());
+        assert_eq!(keys, vec!["user:bob".to_owned(), "ip:192.0.2.1".to_owned()]);
+        assert_eq!(limiter.record_failure(&keys).await, None);
+        assert_eq!(limiter.record_failure(&keys).await, None);
+        let locked_until = limiter.record_failure(&keys).await.unwrap();
+        assert_eq!(limiter.get_lockout(&keys).await, Some(locked_until));
+        // The address stays locked out for the other users.
+        let other_keys = LdapBindLimiter::keys(&UserId::new("patrick"), "192.0.2.1".parse().ok());
+        assert_eq!(limiter.get_lockout(&other_keys).await, Some(locked_until));
+        // A successful bind only resets the user.
+        limiter.clear(&UserId::new("bob")).await;
+        assert_eq!(limiter.get_lockout(&keys).await, Some(locked_until));
+        assert_eq!(
+            limiter
+                .get_lockout(&LdapBindLimiter::keys(&UserId::new("bob"), None))
+                .await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bind_throttling_disabled() {
+        let limiter = limiter(
+            LoginThrottlingOptionsBuilder::default()
+                .enabled(false)
+                .max_failures(1),
+        )
+        .await;
+        let keys = LdapBindLimiter::keys(&UserId::new("bob"), None);
+        assert_eq!(limiter.record_failure(&keys).await, None);
+        assert_eq!(limiter.get_lockout(&keys).await, None);
+    }
+}
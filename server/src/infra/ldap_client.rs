@@ -0,0 +1,110 @@
+use anyhow::{anyhow, bail, Context, Result};
+use futures_util::SinkExt;
+use ldap3_proto::{
+    proto::{
+        LdapBindCred, LdapBindRequest, LdapBindResponse, LdapDerefAliases, LdapFilter, LdapMsg,
+        LdapOp, LdapResultCode, LdapSearchRequest, LdapSearchResultEntry, LdapSearchScope,
+    },
+    LdapCodec,
+};
+use tokio::{
+    io::{ReadHalf, WriteHalf},
+    net::TcpStream,
+};
+use tokio_util::codec::{FramedRead, FramedWrite};
+use tracing::debug;
+
+/// Minimal LDAP client, to talk to an upstream server or to test our own.
+pub struct LdapClient {
+    reader: FramedRead<ReadHalf<TcpStream>, LdapCodec>,
+    writer: FramedWrite<WriteHalf<TcpStream>, LdapCodec>,
+    next_msgid: i32,
+}
+
+impl LdapClient {
+    pub async fn connect(server: &str) -> Result<Self> {
+        let stream = TcpStream::connect(server)
+            .await
+            .with_context(|| format!("while connecting to the LDAP server {}", server))?;
+        let (r, w) = tokio::io::split(stream);
+        Ok(Self {
+            reader: FramedRead::new(r, LdapCodec::default()),
+            writer: FramedWrite::new(w, LdapCodec::default()),
+            next_msgid: 1,
+        })
+    }
+
+    pub async fn send(&mut self, op: LdapOp) -> Result<()> {
+        let msgid = self.next_msgid;
+        self.next_msgid += 1;
+        self.writer
+            .send(LdapMsg {
+                msgid,
+                op,
+                ctrl: vec![],
+            })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn receive(&mut self) -> Result<LdapOp> {
+        use tokio_stream::StreamExt;
+        let msg = self
+            .reader
+            .next()
+            .await
+            .ok_or_else(|| anyhow!("The LDAP server closed the connection"))?
+            .context("Invalid answer from the LDAP server")?;
+        Ok(msg.op)
+    }
+
+    /// Returns whether the credentials are valid.
+    pub async fn bind(&mut self, dn: &str, password: &str) -> Result<bool> {
+        self.send(LdapOp::BindRequest(LdapBindRequest {
+            dn: dn.to_string(),
+            cred: LdapBindCred::Simple(password.to_string()),
+        }))
+        .await?;
+        match self.receive().await? {
+            LdapOp::BindResponse(LdapBindResponse { res, .. }) => match res.code {
+                LdapResultCode::Success => Ok(true),
+                LdapResultCode::InvalidCredentials => Ok(false),
+                code => bail!("Bind failed with {:?}: {}", code, res.message),
+            },
+            op => bail!("Unexpected answer to the bind: {:?}", op),
+        }
+    }
+
+    pub async fn search(
+        &mut self,
+        base: &str,
+        filter: LdapFilter,
+        attrs: &[&str],
+    ) -> Result<Vec<LdapSearchResultEntry>> {
+        self.send(LdapOp::SearchRequest(LdapSearchRequest {
+            base: base.to_string(),
+            scope: LdapSearchScope::Subtree,
+            aliases: LdapDerefAliases::Never,
+            sizelimit: 0,
+            timelimit: 0,
+            typesonly: false,
+            filter,
+            attrs: attrs.iter().map(|a| a.to_string()).collect(),
+        }))
+        .await?;
+        let mut entries = Vec::new();
+        loop {
+            match self.receive().await? {
+                LdapOp::SearchResultEntry(entry) => entries.push(entry),
+                LdapOp::SearchResultDone(res) => {
+                    if !matches!(res.code, LdapResultCode::Success) {
+                        bail!("Search failed with {:?}: {}", res.code, res.message);
+                    }
+                    return Ok(entries);
+                }
+                // E.g. referrals, which are not followed.
+                op => debug!("Ignoring answer {:?}", op),
+            }
+        }
+    }
+}
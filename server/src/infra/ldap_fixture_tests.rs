@@ -0,0 +1,190 @@
+//! Golden tests of the LDAP searches. Each `.ldif` file of `tests/ldap_fixtures` holds a search
+//! request, then the responses expected for it, in LDIF:
+//!
+//! ```text
+//! # Comments are only allowed in the request.
+//! base: ou=people,dc=example,dc=com
+//! scope: sub
+//! filter: (uid=bob)
+//! attributes: uid mail
+//!
+//! dn: uid=bob,ou=people,dc=example,dc=com
+//! mail: bob@bob.bob
+//! uid: bob
+//!
+//! result: Success
+//! ```
+//!
+//! The scope is one of `base`, `one` and `sub` (the default), and `typesonly: true` is accepted.
+//! The searches run as an admin, against an in-memory database with the users and groups of
+//! `TestFixture`. To record the responses of a new fixture, or after a deliberate change, run the
+//! tests with `LLDAP_UPDATE_FIXTURES=1` and review the diff.
+
+use crate::{
+    domain::{
+        ldap::filter_parser::parse_filter,
+        sql_backend_handler::{
+            tests::{insert_group, insert_membership, insert_user_no_password, TestFixture},
+            SqlBackendHandler,
+        },
+        types::UserId,
+    },
+    infra::ldap_handler::LdapHandler,
+};
+use base64::Engine;
+use ldap3_proto::{
+    proto::{LdapDerefAliases, LdapOp, LdapSearchRequest, LdapSearchScope},
+    LdapFilter, LdapResultCode, LdapSearchResultEntry,
+};
+use pretty_assertions::assert_eq;
+use std::path::{Path, PathBuf};
+
+const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/ldap_fixtures");
+
+struct Fixture {
+    /// The request, as written in the file.
+    request_text: String,
+    request: LdapSearchRequest,
+    expected: String,
+}
+
+fn parse_fixture(path: &Path) -> Fixture {
+    let content = std::fs::read_to_string(path).unwrap();
+    let (request_text, expected) = content
+        .split_once("\n\n")
+        .unwrap_or_else(|| panic!("{}: missing the expected responses", path.display()));
+    let mut request = LdapSearchRequest {
+        base: String::new(),
+        scope: LdapSearchScope::Subtree,
+        aliases: LdapDerefAliases::Never,
+        sizelimit: 0,
+        timelimit: 0,
+        typesonly: false,
+        filter: LdapFilter::Present("objectClass".to_owned()),
+        attrs: Vec::new(),
+    };
+    for line in request_text.lines().filter(|l| !l.starts_with('#')) {
+        let (key, value) = line
+            .split_once(':')
+            .unwrap_or_else(|| panic!(r#"{}: invalid line "{}""#, path.display(), line));
+        let value = value.trim();
+        match key.to_ascii_lowercase().as_str() {
+            "base" => request.base = value.to_owned(),
+            "scope" => {
+                request.scope = match value {
+                    "base" => LdapSearchScope::Base,
+                    "one" => LdapSearchScope::OneLevel,
+                    "sub" => LdapSearchScope::Subtree,
+                    _ => panic!(r#"{}: invalid scope "{}""#, path.display(), value),
+                }
+            }
+            "filter" => {
+                request.filter = parse_filter(value)
+                    .unwrap_or_else(|e| panic!("{}: {}", path.display(), e.message))
+            }
+            "attributes" => request.attrs = value.split_whitespace().map(str::to_owned).collect(),
+            "typesonly" => request.typesonly = value == "true",
+            _ => panic!(r#"{}: unknown key "{}""#, path.display(), key),
+        }
+    }
+    Fixture {
+        request_text: request_text.to_owned(),
+        request,
+        expected: expected.to_owned(),
+    }
+}
+
+/// A value is written as is, unless LDIF requires it to be encoded in base64.
+fn render_value(attribute: &str, value: &[u8]) -> String {
+    match std::str::from_utf8(value) {
+        Ok(value)
+            if !value.starts_with([' ', ':', '<'])
+                && !value.ends_with(' ')
+                && !value.contains(['\n', '\r', '\0']) =>
+        {
+            format!("{}: {}\n", attribute, value)
+        }
+        _ => format!(
+            "{}:: {}\n",
+            attribute,
+            base64::engine::general_purpose::STANDARD.encode(value)
+        ),
+    }
+}
+
+fn render_entry(entry: &LdapSearchResultEntry) -> String {
+    let mut rendered = format!("dn: {}\n", entry.dn);
+    for attribute in &entry.attributes {
+        if attribute.vals.is_empty() {
+            rendered.push_str(&format!("{}:\n", attribute.atype));
+        }
+        for value in &attribute.vals {
+            rendered.push_str(&render_value(&attribute.atype, value));
+        }
+    }
+    rendered
+}
+
+fn render_responses(responses: impl Iterator<Item = LdapOp>) -> String {
+    responses
+        .map(|response| match response {
+            LdapOp::SearchResultEntry(entry) => render_entry(&entry),
+            LdapOp::SearchResultDone(result) if result.message.is_empty() => {
+                format!("result: {:?}\n", result.code)
+            }
+            LdapOp::SearchResultDone(result) => {
+                format!("result: {:?}\nmessage: {}\n", result.code, result.message)
+            }
+            response => format!("# unexpected response: {:?}\n", response),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+async fn setup_backend() -> SqlBackendHandler {
+    let fixture = TestFixture::new().await;
+    let handler = fixture.handler;
+    insert_user_no_password(&handler, "admin").await;
+    let admin_group = insert_group(&handler, "lldap_admin").await;
+    insert_membership(&handler, admin_group, "admin").await;
+    handler
+}
+
+fn list_fixtures() -> Vec<PathBuf> {
+    let mut paths: Vec<_> = std::fs::read_dir(FIXTURES_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "ldif"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[tokio::test]
+async fn test_ldap_fixtures() {
+    let backend = setup_backend().await;
+    let update = std::env::var("LLDAP_UPDATE_FIXTURES").is_ok_and(|v| v == "1");
+    let fixtures = list_fixtures();
+    assert!(!fixtures.is_empty());
+    for path in fixtures {
+        let fixture = parse_fixture(&path);
+        let mut ldap_handler = LdapHandler::new_for_tests(backend.clone(), "dc=example,dc=com");
+        assert_eq!(
+            ldap_handler
+                .bind_authenticated_user(UserId::new("admin"))
+                .await
+                .0,
+            LdapResultCode::Success
+        );
+        let (responses, _) = ldap_handler
+            .handle_ldap_message_with_controls(LdapOp::SearchRequest(fixture.request), &[])
+            .await
+            .unwrap();
+        let rendered = render_responses(responses);
+        if update {
+            std::fs::write(&path, format!("{}\n\n{}", fixture.request_text, rendered)).unwrap();
+        } else {
+            assert_eq!(rendered, fixture.expected, "{}", path.display());
+        }
+    }
+}
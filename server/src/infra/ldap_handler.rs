@@ -1,45 +1,162 @@
 use crate::{
     domain::{
+        account_deletions::AccountDeletion,
+        change_events::ChangeEvent,
+        error::DomainError,
         handler::{
-            BackendHandler, BindRequest, CreateUserRequest, LoginHandler, ReadSchemaBackendHandler,
+            AccountDeletionBackendHandler, BackendHandler, BindRequest, ChangeFeedBackendHandler,
+            CreateUserRequest, LoginHandler, ReadSchemaBackendHandler, UserListerBackendHandler,
+            UserRequestFilter,
         },
         ldap::{
-            error::{LdapError, LdapResult},
+            error::{domain_error_code, LdapError, LdapResult},
+            filter_debug::{explain_filter, SearchedEntries},
             group::{convert_groups_to_ldap_op, get_groups_list},
-            user::{convert_users_to_ldap_op, get_user_list},
+            static_entry::{get_builtin_entries, get_static_entries, StaticEntry},
+            subschema::{get_subschema_entry, SUBSCHEMA_DN},
+            user::{convert_users_to_ldap_op, get_user_list, get_user_update},
             utils::{
-                get_user_id_from_distinguished_name, is_subtree, parse_distinguished_name, LdapInfo,
+                format_distinguished_name, get_user_id_from_distinguished_name,
+                get_user_id_from_distinguished_name_or_plain_name, is_password_attribute,
+                is_subtree, map_user_field, parse_distinguished_name, parse_ldap_attribute_value,
+                LdapInfo, UserFieldType,
             },
         },
+        nested_groups::GroupHierarchy,
         opaque_handler::OpaqueHandler,
         schema::PublicSchema,
-        types::{AttributeName, Email, Group, JpegPhoto, UserAndGroups, UserId},
+        types::{AttributeName, AttributeValue, Email, Group, JpegPhoto, UserAndGroups, UserId},
     },
-    infra::access_control::{
-        AccessControlledBackendHandler, AdminBackendHandler, UserAndGroupListerBackendHandler,
-        UserReadableBackendHandler, ValidationResults,
+    infra::{
+        access_control::{
+            AccessControlledBackendHandler, AdminBackendHandler, Permission,
+            UserAndGroupListerBackendHandler, UserReadableBackendHandler,
+            UserWriteableBackendHandler, ValidationResults,
+        },
+        configuration::{
+            ComputedAttribute, IntegrationProfile, IntegrationProfilesOptions, LdapAttributeLimit,
+            LdapAttributeMapping, LdapAttributeOrder, PosixDefaultsOptions, UserRdnAttribute,
+        },
+        ldap_bind_limiter::LdapBindLimiter,
+        ldap_metrics::format_filter,
+        ldap_sync::{
+            make_cookie, parse_cookie, persist_responses, refresh_responses, ChangedEntries,
+            SyncResponse, SyncedEntry, SyncedEntryId, MAX_INCREMENTAL_CHANGES, SYNC_REQUEST_OID,
+        },
+        maintenance::MaintenanceMode,
+        read_only::{ReadOnlyMode, READ_ONLY_MESSAGE},
     },
 };
 use anyhow::Result;
-use ldap3_proto::proto::{
-    LdapAddRequest, LdapBindCred, LdapBindRequest, LdapBindResponse, LdapCompareRequest,
-    LdapDerefAliases, LdapExtendedRequest, LdapExtendedResponse, LdapFilter, LdapModify,
-    LdapModifyRequest, LdapModifyType, LdapOp, LdapPartialAttribute, LdapPasswordModifyRequest,
-    LdapResult as LdapResultOp, LdapResultCode, LdapSearchRequest, LdapSearchResultEntry,
-    LdapSearchScope,
+use ldap3_proto::{
+    control::LdapControl,
+    proto::{
+        LdapAddRequest, LdapBindCred, LdapBindRequest, LdapBindResponse, LdapCompareRequest,
+        LdapDerefAliases, LdapExtendedRequest, LdapExtendedResponse, LdapFilter, LdapModify,
+        LdapModifyRequest, LdapModifyType, LdapOp, LdapPartialAttribute, LdapPasswordModifyRequest,
+        LdapResult as LdapResultOp, LdapResultCode, LdapSearchRequest, LdapSearchResultEntry,
+        LdapSearchScope, SyncRequestMode,
+    },
 };
-use std::collections::HashMap;
-use tracing::{debug, instrument, warn};
+use std::{collections::HashMap, net::IpAddr, sync::Arc, time::Duration};
+use tokio::sync::broadcast::{
+    self,
+    error::{RecvError, TryRecvError},
+};
+use tracing::{debug, info, instrument, warn};
+
+/// OID of the StartTLS extended operation, from RFC 4511.
+pub const START_TLS_OID: &str = "1.3.6.1.4.1.1466.20037";
+/// OID of the Password Modify extended operation, from RFC 3062.
+const PASSWORD_MODIFY_OID: &str = "1.3.6.1.4.1.4203.1.11.1";
+/// OID of the Simple Paged Results control, from RFC 2696.
+const PAGED_RESULTS_OID: &str = "1.2.840.113556.1.4.319";
+
+// Paged searches of a session that are kept until the client reads the next page.
+const MAX_PAGED_SEARCHES: usize = 8;
+
+/// The remaining entries of a search returned page by page with the Simple Paged Results
+/// control (RFC 2696).
+struct PagedSearch {
+    cookie: Vec<u8>,
+    request: LdapSearchRequest,
+    entries: Vec<LdapOp>,
+    total: usize,
+}
+
+/// A synchronization in refreshAndPersist mode (RFC 4533), which sends the changes of the
+/// entries until the client abandons it.
+struct PersistentSync {
+    message_id: i32,
+    request: LdapSearchRequest,
+    /// The entries as the client knows them.
+    entries: HashMap<uuid::Uuid, SyncedEntry>,
+    changes: broadcast::Receiver<ChangeEvent>,
+}
+
+// The attributes of an add request that fill the fields of the new user.
+const NEW_USER_FIELDS: &[&str] = &[
+    "uid",
+    "mail",
+    "email",
+    "cn",
+    "givenname",
+    "sn",
+    "avatar",
+    "externalid",
+    "userpassword",
+];
+// The user attributes set from those fields.
+const NEW_USER_FIELD_ATTRIBUTES: &[&str] = &["first_name", "last_name", "avatar"];
+
+/// Whether the value is a password hash in the `{SCHEME}hash` format, e.g. `{SSHA}...`, which
+/// can't be turned into an OPAQUE registration.
+fn is_hashed_password(password: &[u8]) -> bool {
+    match password
+        .strip_prefix(b"{")
+        .and_then(|rest| rest.iter().position(|c| *c == b'}').map(|end| &rest[..end]))
+    {
+        Some(scheme) => {
+            !scheme.is_empty()
+                && scheme
+                    .iter()
+                    .all(|c| c.is_ascii_alphanumeric() || *c == b'-')
+        }
+        None => false,
+    }
+}
+
+fn make_paged_results_control(size: usize, cookie: Vec<u8>) -> LdapControl {
+    LdapControl::SimplePagedResults {
+        size: size.try_into().unwrap_or(i64::MAX),
+        cookie,
+    }
+}
+
+/// Whether the connection of an LDAP session is encrypted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionSecurity {
+    Plaintext,
+    /// Plaintext, but it can be upgraded with StartTLS.
+    StartTlsAvailable,
+    Tls,
+    /// A Unix domain socket: the traffic never leaves the host.
+    LocalSocket,
+}
 
 #[derive(Debug)]
 enum SearchScope {
     Global,
+    // The base DN entry itself, or the organizational units right below it.
+    TopEntries,
     Users,
     Groups,
     User(LdapFilter),
     Group(LdapFilter),
     UserOuOnly,
     GroupOuOnly,
+    // The children of a user or group entry, of which there are none.
+    NoChildren,
     Unknown,
     Invalid,
 }
@@ -54,12 +171,16 @@ fn get_search_scope(
     base_dn: &[(String, String)],
     dn_parts: &[(String, String)],
     ldap_scope: &LdapSearchScope,
+    user_rdn: UserRdnAttribute,
 ) -> SearchScope {
     let base_dn_len = base_dn.len();
     if !is_subtree(dn_parts, base_dn) {
         SearchScope::Invalid
     } else if dn_parts.len() == base_dn_len {
-        SearchScope::Global
+        match ldap_scope {
+            LdapSearchScope::Base | LdapSearchScope::OneLevel => SearchScope::TopEntries,
+            _ => SearchScope::Global,
+        }
     } else if dn_parts.len() == base_dn_len + 1
         && dn_parts[0] == ("ou".to_string(), "people".to_string())
     {
@@ -76,13 +197,22 @@ fn get_search_scope(
         } else {
             SearchScope::Groups
         }
+    } else if dn_parts.len() == base_dn_len + 2
+        && matches!(ldap_scope, LdapSearchScope::OneLevel)
+        && (dn_parts[1] == ("ou".to_string(), "people".to_string())
+            || dn_parts[1] == ("ou".to_string(), "groups".to_string()))
+    {
+        SearchScope::NoChildren
     } else if dn_parts.len() == base_dn_len + 2
         && dn_parts[1] == ("ou".to_string(), "people".to_string())
     {
-        SearchScope::User(LdapFilter::Equality(
-            dn_parts[0].0.clone(),
-            dn_parts[0].1.clone(),
-        ))
+        // The RDN of the user entries holds the user ID, whatever its attribute name.
+        let attribute = if dn_parts[0].0 == user_rdn.as_str() {
+            "uid".to_string()
+        } else {
+            dn_parts[0].0.clone()
+        };
+        SearchScope::User(LdapFilter::Equality(attribute, dn_parts[0].1.clone()))
     } else if dn_parts.len() == base_dn_len + 2
         && dn_parts[1] == ("ou".to_string(), "groups".to_string())
     {
@@ -156,7 +286,12 @@ fn make_modify_response(code: LdapResultCode, message: String) -> LdapOp {
     })
 }
 
-fn root_dse_response(base_dn: &str) -> LdapOp {
+/// The entry describing the server, returned to the clients probing it before anything else.
+fn root_dse_response(base_dn: &str, connection_security: ConnectionSecurity) -> LdapOp {
+    let mut supported_extensions = vec![PASSWORD_MODIFY_OID.as_bytes().to_vec()];
+    if connection_security == ConnectionSecurity::StartTlsAvailable {
+        supported_extensions.push(START_TLS_OID.as_bytes().to_vec());
+    }
     LdapOp::SearchResultEntry(LdapSearchResultEntry {
         dn: "".to_string(),
         attributes: vec![
@@ -180,12 +315,14 @@ fn root_dse_response(base_dn: &str) -> LdapOp {
             },
             LdapPartialAttribute {
                 atype: "supportedExtension".to_string(),
-                // Password modification extension.
-                vals: vec![b"1.3.6.1.4.1.4203.1.11.1".to_vec()],
+                vals: supported_extensions,
             },
             LdapPartialAttribute {
                 atype: "supportedControl".to_string(),
-                vals: vec![],
+                vals: vec![
+                    PAGED_RESULTS_OID.as_bytes().to_vec(),
+                    SYNC_REQUEST_OID.as_bytes().to_vec(),
+                ],
             },
             LdapPartialAttribute {
                 atype: "supportedFeatures".to_string(),
@@ -200,6 +337,10 @@ fn root_dse_response(base_dn: &str) -> LdapOp {
                 atype: "namingContexts".to_string(),
                 vals: vec![base_dn.to_string().into_bytes()],
             },
+            LdapPartialAttribute {
+                atype: "subschemaSubentry".to_string(),
+                vals: vec![SUBSCHEMA_DN.as_bytes().to_vec()],
+            },
             LdapPartialAttribute {
                 atype: "isGlobalCatalogReady".to_string(),
                 vals: vec![b"false".to_vec()],
@@ -208,17 +349,192 @@ fn root_dse_response(base_dn: &str) -> LdapOp {
     })
 }
 
+/// Fetches the emails of the members of the groups, to return them as the groups' `mail` if it
+/// is requested.
+async fn get_member_emails(
+    backend_handler: &impl UserListerBackendHandler,
+    groups: &[Group],
+    attributes: &[String],
+    ldap_info: &LdapInfo,
+) -> LdapResult<HashMap<UserId, Email>> {
+    let is_mail = |attribute: &String| {
+        ldap_info
+            .resolve_group_attribute(&AttributeName::from(attribute.as_str()))
+            .as_str()
+            == "mail"
+    };
+    if groups.is_empty() || !attributes.iter().any(is_mail) {
+        return Ok(HashMap::new());
+    }
+    let filter = UserRequestFilter::Or(
+        groups
+            .iter()
+            .map(|g| UserRequestFilter::MemberOfId(g.id))
+            .collect(),
+    );
+    Ok(backend_handler
+        .list_users(Some(filter), false)
+        .await
+        .map_err(|e| LdapError {
+            code: domain_error_code(&e),
+            message: format!("Error while listing the group members: {:#}", e),
+        })?
+        .into_iter()
+        .map(|u| (u.user.user_id, u.user.email))
+        .collect())
+}
+
+/// Caps the values of the entry's attributes, as configured in `ldap_attribute_limits`.
+fn apply_attribute_limits(entry: &mut LdapSearchResultEntry, limits: &[LdapAttributeLimit]) {
+    let dn = &entry.dn;
+    entry.attributes.retain_mut(|attribute| {
+        let name = AttributeName::from(attribute.atype.as_str());
+        let limit = match limits.iter().find(|l| l.attribute == name) {
+            Some(limit) => limit,
+            None => return true,
+        };
+        if let Some(max_bytes) = limit.max_value_bytes {
+            let count = attribute.vals.len();
+            attribute.vals.retain(|v| v.len() <= max_bytes);
+            if attribute.vals.len() != count {
+                warn!(
+                    r#"Left out {} value(s) of "{}" bigger than {} bytes in "{}""#,
+                    count - attribute.vals.len(),
+                    &attribute.atype,
+                    max_bytes,
+                    dn
+                );
+                if attribute.vals.is_empty() {
+                    return false;
+                }
+            }
+        }
+        if let Some(max_values) = limit.max_values {
+            if attribute.vals.len() > max_values {
+                attribute.vals.truncate(max_values);
+                // Same convention as Active Directory's ranged retrieval.
+                attribute.atype = format!(
+                    "{};range=0-{}",
+                    attribute.atype,
+                    max_values.saturating_sub(1)
+                );
+            }
+        }
+        true
+    });
+}
+
+/// The server-side maxima of the searches, 0 for no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub max_entries: usize,
+    pub max_time_seconds: u64,
+}
+
+/// The stricter of the limit requested by the client and of the server's, where 0 means no limit.
+fn effective_limit(requested: i32, maximum: u64) -> Option<u64> {
+    match (u64::try_from(requested).unwrap_or(0), maximum) {
+        (0, 0) => None,
+        (0, limit) | (limit, 0) => Some(limit),
+        (requested, maximum) => Some(requested.min(maximum)),
+    }
+}
+
+/// The responses to an LDAP operation. The entries of a search are only built as they are sent,
+/// so that a large search doesn't hold all of them in memory at once.
+pub type LdapResponses = Box<dyn Iterator<Item = LdapOp> + Send>;
+
+fn single_response(response: LdapOp) -> LdapResponses {
+    Box::new(std::iter::once(response))
+}
+
+/// Keeps the first entries of the results, ending them with `sizeLimitExceeded` if some were
+/// left out.
+fn apply_size_limit(mut results: LdapResponses, limit: usize) -> LdapResponses {
+    let mut entries = 0;
+    Box::new(std::iter::from_fn(move || {
+        if entries > limit {
+            return None;
+        }
+        let op = results.next()?;
+        if matches!(op, LdapOp::SearchResultEntry(_)) {
+            entries += 1;
+            if entries > limit {
+                return Some(make_search_error(
+                    LdapResultCode::SizeLimitExceeded,
+                    format!("The search matched more than {} entries", limit),
+                ));
+            }
+        }
+        Some(op)
+    }))
+}
+
+/// The settings of the LDAP sessions, the same for all the connections to the server.
+#[derive(Clone, Default)]
+pub struct LdapSessionSettings {
+    pub base_dn: String,
+    pub ignored_user_attributes: Vec<AttributeName>,
+    pub ignored_group_attributes: Vec<AttributeName>,
+    pub posix_defaults: PosixDefaultsOptions,
+    pub user_rdn: UserRdnAttribute,
+    pub integration_profiles: IntegrationProfilesOptions,
+    pub attribute_limits: Vec<LdapAttributeLimit>,
+    pub attribute_mappings: Vec<LdapAttributeMapping>,
+    pub computed_user_attributes: Vec<ComputedAttribute>,
+    pub static_entries: Vec<StaticEntry>,
+    pub active_directory_compatibility: bool,
+    pub attribute_order: LdapAttributeOrder,
+    pub filter_debug: bool,
+    pub require_tls_for_bind: bool,
+    pub maintenance: Arc<MaintenanceMode>,
+    pub read_only: Arc<ReadOnlyMode>,
+    pub bind_limiter: Arc<LdapBindLimiter>,
+    pub search_limits: SearchLimits,
+}
+
 pub struct LdapHandler<Backend> {
     user_info: Option<ValidationResults>,
     backend_handler: AccessControlledBackendHandler<Backend>,
     ldap_info: LdapInfo,
+    integration_profiles: IntegrationProfilesOptions,
     session_uuid: uuid::Uuid,
+    connection_security: ConnectionSecurity,
+    require_tls_for_bind: bool,
+    maintenance: Arc<MaintenanceMode>,
+    read_only: Arc<ReadOnlyMode>,
+    bind_limiter: Arc<LdapBindLimiter>,
+    /// The address of the client, if known, to count the failed binds against.
+    client_ip: Option<IpAddr>,
+    search_limits: SearchLimits,
+    start_tls_requested: bool,
+    paged_searches: Vec<PagedSearch>,
+    next_paged_search_id: u64,
+    persistent_sync: Option<PersistentSync>,
 }
 
 impl<Backend> LdapHandler<Backend> {
     pub fn session_uuid(&self) -> &uuid::Uuid {
         &self.session_uuid
     }
+
+    /// Whether the last operation accepted a StartTLS request, after which the connection has to
+    /// go through the TLS handshake before reading the next message. Resets the flag.
+    pub fn take_start_tls_request(&mut self) -> bool {
+        std::mem::take(&mut self.start_tls_requested)
+    }
+
+    /// Called once the StartTLS handshake succeeded.
+    pub fn set_tls_established(&mut self) {
+        self.connection_security = ConnectionSecurity::Tls;
+    }
+
+    /// The DN of the user bound in this session, if any.
+    pub fn bound_dn(&self) -> Option<String> {
+        self.user_info
+            .as_ref()
+            .map(|u| self.ldap_info.user_dn(&u.user))
+    }
 }
 
 impl<Backend: LoginHandler> LdapHandler<Backend> {
@@ -236,27 +552,49 @@ impl<Backend: OpaqueHandler> LdapHandler<Backend> {
 impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend> {
     pub fn new(
         backend_handler: AccessControlledBackendHandler<Backend>,
-        mut ldap_base_dn: String,
-        ignored_user_attributes: Vec<AttributeName>,
-        ignored_group_attributes: Vec<AttributeName>,
+        settings: &LdapSessionSettings,
         session_uuid: uuid::Uuid,
+        connection_security: ConnectionSecurity,
+        client_ip: Option<IpAddr>,
     ) -> Self {
-        ldap_base_dn.make_ascii_lowercase();
+        let base_dn = parse_distinguished_name(&settings.base_dn).unwrap_or_else(|_| {
+            panic!(
+                "Invalid value for ldap_base_dn in configuration: {}",
+                settings.base_dn
+            )
+        });
         Self {
             user_info: None,
             backend_handler,
             ldap_info: LdapInfo {
-                base_dn: parse_distinguished_name(&ldap_base_dn).unwrap_or_else(|_| {
-                    panic!(
-                        "Invalid value for ldap_base_dn in configuration: {}",
-                        ldap_base_dn
-                    )
-                }),
-                base_dn_str: ldap_base_dn,
-                ignored_user_attributes,
-                ignored_group_attributes,
+                base_dn_str: format_distinguished_name(&base_dn),
+                base_dn,
+                ignored_user_attributes: settings.ignored_user_attributes.clone(),
+                ignored_group_attributes: settings.ignored_group_attributes.clone(),
+                posix_defaults: settings.posix_defaults.clone(),
+                user_rdn: settings.user_rdn,
+                profile: IntegrationProfile::default(),
+                attribute_limits: settings.attribute_limits.clone(),
+                attribute_mappings: settings.attribute_mappings.clone(),
+                computed_user_attributes: settings.computed_user_attributes.clone(),
+                static_entries: settings.static_entries.clone(),
+                active_directory_compatibility: settings.active_directory_compatibility,
+                attribute_order: settings.attribute_order,
+                filter_debug: settings.filter_debug,
             },
+            integration_profiles: settings.integration_profiles.clone(),
             session_uuid,
+            connection_security,
+            require_tls_for_bind: settings.require_tls_for_bind,
+            maintenance: settings.maintenance.clone(),
+            read_only: settings.read_only.clone(),
+            bind_limiter: settings.bind_limiter.clone(),
+            client_ip,
+            search_limits: settings.search_limits,
+            start_tls_requested: false,
+            paged_searches: Vec::new(),
+            next_paged_search_id: 0,
+            persistent_sync: None,
         }
     }
 
@@ -264,10 +602,13 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
     pub fn new_for_tests(backend_handler: Backend, ldap_base_dn: &str) -> Self {
         Self::new(
             AccessControlledBackendHandler::new(backend_handler),
-            ldap_base_dn.to_string(),
-            vec![],
-            vec![],
+            &LdapSessionSettings {
+                base_dn: ldap_base_dn.to_string(),
+                ..Default::default()
+            },
             uuid::Uuid::parse_str("550e8400-e29b-41d4-a716-446655440000").unwrap(),
+            ConnectionSecurity::Plaintext,
+            None,
         )
     }
 
@@ -279,8 +620,20 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 "Anonymous bind not allowed".to_string(),
             );
         }
-        let user_id = match get_user_id_from_distinguished_name(
-            &request.dn.to_ascii_lowercase(),
+        if self.require_tls_for_bind
+            && !matches!(
+                self.connection_security,
+                ConnectionSecurity::Tls | ConnectionSecurity::LocalSocket
+            )
+        {
+            return (
+                LdapResultCode::ConfidentialityRequired,
+                "TLS is required to bind, use StartTLS or LDAPS".to_string(),
+            );
+        }
+        // Some clients bind with the full DN, others with the plain user ID.
+        let user_id = match get_user_id_from_distinguished_name_or_plain_name(
+            &request.dn,
             &self.ldap_info.base_dn,
             &self.ldap_info.base_dn_str,
         ) {
@@ -295,6 +648,18 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 "SASL not supported".to_string(),
             );
         };
+        let throttling_keys = LdapBindLimiter::keys(&user_id, self.client_ip);
+        if let Some(locked_until) = self.bind_limiter.get_lockout(&throttling_keys).await {
+            warn!(
+                event = "ldap_bind_refused",
+                user = %user_id,
+                client_ip = ?self.client_ip,
+                %locked_until,
+                "Refusing the bind of {}, locked out after too many failed binds",
+                &user_id
+            );
+            return (LdapResultCode::InvalidCredentials, "".to_string());
+        }
         match self
             .get_login_handler()
             .bind(BindRequest {
@@ -304,16 +669,87 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
             .await
         {
             Ok(()) => {
-                self.user_info = self
-                    .backend_handler
-                    .get_permissions_for_user(user_id)
-                    .await
-                    .ok();
-                debug!("Success!");
-                (LdapResultCode::Success, "".to_string())
+                self.bind_limiter.clear(&user_id).await;
+                self.bind_authenticated_user(user_id).await
+            }
+            Err(_) => {
+                let lockout = self.bind_limiter.record_failure(&throttling_keys).await;
+                if let Some(locked_until) = lockout {
+                    warn!(
+                        event = "ldap_bind_lockout",
+                        user = %user_id,
+                        client_ip = ?self.client_ip,
+                        %locked_until,
+                        "Too many failed binds for {}, locked out until {}",
+                        &user_id,
+                        locked_until
+                    );
+                }
+                (LdapResultCode::InvalidCredentials, "".to_string())
+            }
+        }
+    }
+
+    /// Binds the session as a user whose identity is already established: by a password, or by
+    /// the credentials of the process at the other end of a local socket.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn bind_authenticated_user(&mut self, user_id: UserId) -> (LdapResultCode, String) {
+        // A refused bind leaves the session unbound.
+        self.user_info = None;
+        let user_info = match self
+            .backend_handler
+            .get_permissions_for_user(user_id.clone())
+            .await
+        {
+            Ok(user_info) => user_info,
+            Err(e) => {
+                warn!("Could not get the permissions of {}: {}", &user_id, e);
+                return (
+                    LdapResultCode::OperationsError,
+                    format!("Could not get the permissions of {}", &user_id),
+                );
+            }
+        };
+        // Accounts waiting for their deletion are disabled.
+        match <Backend as AccountDeletionBackendHandler>::get_account_deletion(
+            self.backend_handler.unsafe_get_handler(),
+            &user_id,
+        )
+        .await
+        {
+            Ok(Some(AccountDeletion {
+                deletion_date: Some(_),
+                ..
+            })) => {
+                info!(
+                    r#"Refusing the bind of "{}", the account is scheduled for deletion"#,
+                    &user_id
+                );
+                return (LdapResultCode::InvalidCredentials, "".to_string());
+            }
+            Ok(_) => {}
+            Err(e) => {
+                warn!("Could not check the account of {}: {}", &user_id, e);
+                return (
+                    LdapResultCode::OperationsError,
+                    format!("Could not check the account of {}", &user_id),
+                );
             }
-            Err(_) => (LdapResultCode::InvalidCredentials, "".to_string()),
         }
+        if let Some(message) = self
+            .maintenance
+            .refusal(user_info.permission == Permission::Admin)
+        {
+            debug!("Refusing the bind of {} during maintenance", &user_id);
+            return (LdapResultCode::Unavailable, message);
+        }
+        self.ldap_info.profile = self
+            .integration_profiles
+            .get_account_profile(&user_id)
+            .unwrap_or_default();
+        self.user_info = Some(user_info);
+        debug!(profile = %self.ldap_info.profile.name, "Success!");
+        (LdapResultCode::Success, "".to_string())
     }
 
     async fn change_password<B: OpaqueHandler>(
@@ -344,6 +780,30 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         Ok(())
     }
 
+    /// Whether the target of a modification is an admin, or None if the bound user can't even
+    /// read it.
+    async fn is_target_user_admin(
+        &self,
+        credentials: &ValidationResults,
+        uid: &UserId,
+    ) -> LdapResult<Option<bool>> {
+        let handler = match self.backend_handler.get_readable_handler(credentials, uid) {
+            Some(handler) => handler,
+            None => return Ok(None),
+        };
+        Ok(Some(
+            handler
+                .get_user_groups(uid)
+                .await
+                .map_err(|e| LdapError {
+                    code: LdapResultCode::OperationsError,
+                    message: format!("Internal error while requesting user's groups: {:#?}", e),
+                })?
+                .iter()
+                .any(|g| g.display_name == "lldap_admin".into()),
+        ))
+    }
+
     async fn do_password_modification(
         &mut self,
         request: &LdapPasswordModifyRequest,
@@ -352,67 +812,97 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
             code: LdapResultCode::InsufficentAccessRights,
             message: "No user currently bound".to_string(),
         })?;
-        match (&request.user_identity, &request.new_password) {
-            (Some(user), Some(password)) => {
-                match get_user_id_from_distinguished_name(
-                    &user.to_ascii_lowercase(),
-                    &self.ldap_info.base_dn,
-                    &self.ldap_info.base_dn_str,
-                ) {
-                    Ok(uid) => {
-                        let user_is_admin = self
-                            .backend_handler
-                            .get_readable_handler(credentials, &uid)
-                            .expect("Unexpected permission error")
-                            .get_user_groups(&uid)
-                            .await
-                            .map_err(|e| LdapError {
-                                code: LdapResultCode::OperationsError,
-                                message: format!(
-                                    "Internal error while requesting user's groups: {:#?}",
-                                    e
-                                ),
-                            })?
-                            .iter()
-                            .any(|g| g.display_name == "lldap_admin".into());
-                        if !credentials.can_change_password(&uid, user_is_admin) {
-                            Err(LdapError {
-                                code: LdapResultCode::InsufficentAccessRights,
-                                message: format!(
-                                    r#"User `{}` cannot modify the password of user `{}`"#,
-                                    &credentials.user, &uid
-                                ),
-                            })
-                        } else if let Err(e) = self
-                            .change_password(self.get_opaque_handler(), uid, password.as_bytes())
-                            .await
-                        {
-                            Err(LdapError {
-                                code: LdapResultCode::Other,
-                                message: format!("Error while changing the password: {:#?}", e),
-                            })
-                        } else {
-                            Ok(vec![make_extended_response(
-                                LdapResultCode::Success,
-                                "".to_string(),
-                            )])
-                        }
-                    }
-                    Err(e) => Err(LdapError {
-                        code: LdapResultCode::InvalidDNSyntax,
-                        message: format!("Invalid username: {}", e),
-                    }),
-                }
+        let password = request.new_password.as_ref().ok_or_else(|| LdapError {
+            code: LdapResultCode::ConstraintViolation,
+            message: "Missing the new password, generating one is not supported".to_string(),
+        })?;
+        // Without an identity, the bound user changes their own password.
+        let uid = match &request.user_identity {
+            Some(user) => get_user_id_from_distinguished_name(
+                user,
+                &self.ldap_info.base_dn,
+                &self.ldap_info.base_dn_str,
+            )
+            .map_err(|e| LdapError {
+                code: LdapResultCode::InvalidDNSyntax,
+                message: format!("Invalid username: {}", e),
+            })?,
+            None => credentials.user.clone(),
+        };
+        let denied = || LdapError {
+            code: LdapResultCode::InsufficentAccessRights,
+            message: format!(
+                r#"User `{}` cannot modify the password of user `{}`"#,
+                &credentials.user, &uid
+            ),
+        };
+        let user_is_admin = self
+            .is_target_user_admin(credentials, &uid)
+            .await?
+            .ok_or_else(denied)?;
+        if !credentials.can_change_password(&uid, user_is_admin) {
+            return Err(denied());
+        }
+        if let Some(old_password) = &request.old_password {
+            if self
+                .get_login_handler()
+                .bind(BindRequest {
+                    name: uid.clone(),
+                    password: old_password.clone(),
+                })
+                .await
+                .is_err()
+            {
+                return Err(LdapError {
+                    code: LdapResultCode::InvalidCredentials,
+                    message: "The old password is wrong".to_string(),
+                });
             }
-            _ => Err(LdapError {
-                code: LdapResultCode::ConstraintViolation,
-                message: "Missing either user_id or password".to_string(),
-            }),
         }
+        self.change_password(self.get_opaque_handler(), uid, password.as_bytes())
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::Other,
+                message: format!("Error while changing the password: {:#?}", e),
+            })?;
+        Ok(vec![make_extended_response(
+            LdapResultCode::Success,
+            "".to_string(),
+        )])
+    }
+
+    fn do_start_tls(&mut self) -> LdapOp {
+        let (code, message) = match self.connection_security {
+            ConnectionSecurity::Tls => (
+                LdapResultCode::OperationsError,
+                "TLS is already established".to_string(),
+            ),
+            ConnectionSecurity::Plaintext | ConnectionSecurity::LocalSocket => (
+                LdapResultCode::Unavailable,
+                "StartTLS is not enabled on this server".to_string(),
+            ),
+            ConnectionSecurity::StartTlsAvailable => {
+                self.start_tls_requested = true;
+                (LdapResultCode::Success, "".to_string())
+            }
+        };
+        LdapOp::ExtendedResponse(LdapExtendedResponse {
+            res: LdapResultOp {
+                code,
+                matcheddn: "".to_string(),
+                message,
+                referral: vec![],
+            },
+            name: Some(START_TLS_OID.to_string()),
+            value: None,
+        })
     }
 
     #[instrument(skip_all, level = "debug")]
     async fn do_extended_request(&mut self, request: &LdapExtendedRequest) -> Vec<LdapOp> {
+        if request.name == START_TLS_OID {
+            return vec![self.do_start_tls()];
+        }
         match LdapPasswordModifyRequest::try_from(request) {
             Ok(password_request) => self
                 .do_password_modification(&password_request)
@@ -471,6 +961,47 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         Ok(())
     }
 
+    async fn modify_user_attributes(
+        &self,
+        user_id: &UserId,
+        credentials: &ValidationResults,
+        changes: &[&LdapModify],
+    ) -> LdapResult<()> {
+        let backend_handler = self
+            .backend_handler
+            .get_writeable_handler(credentials, user_id)
+            .ok_or_else(|| LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: format!(
+                    r#"User `{}` cannot modify the user `{}`"#,
+                    &credentials.user, user_id
+                ),
+            })?;
+        let backend_error = |e: DomainError| LdapError {
+            code: domain_error_code(&e),
+            message: format!("Could not modify the user: {:#}", e),
+        };
+        let user = backend_handler
+            .get_user_details(user_id)
+            .await
+            .map_err(backend_error)?;
+        let schema = UserReadableBackendHandler::get_schema(backend_handler)
+            .await
+            .map_err(backend_error)?;
+        let mut update = get_user_update(
+            &user,
+            changes,
+            &self.ldap_info,
+            &schema,
+            credentials.is_admin(),
+        )?;
+        update.actor = Some(credentials.actor().clone());
+        backend_handler
+            .update_user(update)
+            .await
+            .map_err(backend_error)
+    }
+
     async fn handle_modify_request(
         &mut self,
         request: &LdapModifyRequest,
@@ -483,6 +1014,12 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 message: "No user currently bound".to_string(),
             })?
             .clone();
+        if credentials.is_readonly() {
+            return Err(LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: format!(r#"User `{}` has read-only access"#, &credentials.user),
+            });
+        }
         match get_user_id_from_distinguished_name(
             &request.dn,
             &self.ldap_info.base_dn,
@@ -490,18 +1027,24 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         ) {
             Ok(uid) => {
                 let user_is_admin = self
-                    .backend_handler
-                    .get_readable_handler(&credentials, &uid)
-                    .expect("Unexpected permission error")
-                    .get_user_groups(&uid)
-                    .await
-                    .map_err(|e| LdapError {
-                        code: LdapResultCode::OperationsError,
-                        message: format!("Internal error while requesting user's groups: {:#?}", e),
-                    })?
+                    .is_target_user_admin(&credentials, &uid)
+                    .await?
+                    .ok_or_else(|| LdapError {
+                        code: LdapResultCode::InsufficentAccessRights,
+                        message: format!(
+                            r#"User `{}` cannot modify user `{}`"#,
+                            &credentials.user, &uid
+                        ),
+                    })?;
+                let (password_changes, attribute_changes): (Vec<_>, Vec<_>) = request
+                    .changes
                     .iter()
-                    .any(|g| g.display_name == "lldap_admin".into());
-                for change in &request.changes {
+                    .partition(|c| c.modification.atype.eq_ignore_ascii_case("userpassword"));
+                if !attribute_changes.is_empty() {
+                    self.modify_user_attributes(&uid, &credentials, &attribute_changes)
+                        .await?;
+                }
+                for change in password_changes {
                     self.handle_modify_change(uid.clone(), &credentials, user_is_admin, change)
                         .await?
                 }
@@ -528,18 +1071,51 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         &mut self,
         request: &LdapSearchRequest,
     ) -> LdapResult<Vec<LdapOp>> {
+        Ok(self.stream_search_or_dse(request).await?.collect())
+    }
+
+    async fn stream_search_or_dse(&self, request: &LdapSearchRequest) -> LdapResult<LdapResponses> {
+        // The filter is usually "(objectClass=*)", but some clients send other ones that the root
+        // DSE matches too: there is nothing else to return for an empty base anyway.
         if request.base.is_empty() && request.scope == LdapSearchScope::Base {
-            if let LdapFilter::Present(attribute) = &request.filter {
-                if attribute.to_ascii_lowercase() == "objectclass" {
-                    debug!("rootDSE request");
-                    return Ok(vec![
-                        root_dse_response(&self.ldap_info.base_dn_str),
-                        make_search_success(),
-                    ]);
-                }
-            }
+            debug!("rootDSE request");
+            return Ok(Box::new(
+                [
+                    root_dse_response(&self.ldap_info.base_dn_str, self.connection_security),
+                    make_search_success(),
+                ]
+                .into_iter(),
+            ));
+        }
+        if request.base.eq_ignore_ascii_case(SUBSCHEMA_DN) && request.scope == LdapSearchScope::Base
+        {
+            debug!("Subschema request");
+            return Ok(Box::new(
+                self.do_subschema_search(request).await?.into_iter(),
+            ));
         }
-        self.do_search(request).await
+        self.stream_search(request).await
+    }
+
+    async fn do_subschema_search(&self, request: &LdapSearchRequest) -> LdapResult<Vec<LdapOp>> {
+        let user_info = self.user_info.as_ref().ok_or_else(|| LdapError {
+            code: LdapResultCode::InsufficentAccessRights,
+            message: "No user currently bound".to_string(),
+        })?;
+        let schema = PublicSchema::from(
+            self.backend_handler
+                .get_user_restricted_lister_handler(user_info)
+                .get_schema()
+                .await
+                .map_err(|e| LdapError {
+                    code: LdapResultCode::OperationsError,
+                    message: format!("Unable to get schema: {:#}", e),
+                })?,
+        );
+        Ok(vec![
+            get_subschema_entry(&schema, &request.attrs),
+            make_search_success(),
+        ])
     }
 
     async fn do_search_internal(
@@ -548,9 +1124,32 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         request: &LdapSearchRequest,
         schema: &PublicSchema,
     ) -> LdapResult<InternalSearchResults> {
-        let dn_parts = parse_distinguished_name(&request.base.to_ascii_lowercase())?;
-        let scope = get_search_scope(&self.ldap_info.base_dn, &dn_parts, &request.scope);
+        let dn_parts = parse_distinguished_name(&request.base)?;
+        let scope = get_search_scope(
+            &self.ldap_info.base_dn,
+            &dn_parts,
+            &request.scope,
+            self.ldap_info.effective_user_rdn(),
+        );
         debug!(?request.base, ?scope);
+        let hierarchy = if matches!(
+            scope,
+            SearchScope::Global
+                | SearchScope::Users
+                | SearchScope::Groups
+                | SearchScope::User(_)
+                | SearchScope::Group(_)
+        ) {
+            backend_handler
+                .get_group_hierarchy()
+                .await
+                .map_err(|e| LdapError {
+                    code: LdapResultCode::OperationsError,
+                    message: format!("Unable to get the nested groups: {:#}", e),
+                })?
+        } else {
+            GroupHierarchy::default()
+        };
         // Disambiguate the lifetimes.
         fn cast<'a, T, R>(x: T) -> T
         where
@@ -568,9 +1167,11 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 &self.ldap_info,
                 filter,
                 need_groups,
+                &request.attrs,
                 &request.base,
                 backend_handler,
                 schema,
+                &hierarchy,
             )
             .await
         });
@@ -581,10 +1182,26 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 &request.base,
                 backend_handler,
                 schema,
+                &hierarchy,
             )
             .await
         });
-        Ok(match scope {
+        let searched = match scope {
+            SearchScope::Global => Some(SearchedEntries {
+                users: true,
+                groups: true,
+            }),
+            SearchScope::Users | SearchScope::User(_) => Some(SearchedEntries {
+                users: true,
+                groups: false,
+            }),
+            SearchScope::Groups | SearchScope::Group(_) => Some(SearchedEntries {
+                users: false,
+                groups: true,
+            }),
+            _ => None,
+        };
+        let results = match scope {
             SearchScope::Global => {
                 let users = get_user_list(&request.filter).await;
                 let groups = get_group_list(&request.filter).await;
@@ -606,6 +1223,21 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                     (Ok(users), Ok(groups)) => InternalSearchResults::UsersAndGroups(users, groups),
                 }
             }
+            SearchScope::TopEntries => {
+                let entries: Vec<_> =
+                    get_builtin_entries(&self.ldap_info.base_dn_str, &self.ldap_info.base_dn)
+                        .into_iter()
+                        // A static entry can replace the base DN entry.
+                        .filter(|e| {
+                            !self
+                                .ldap_info
+                                .static_entries
+                                .iter()
+                                .any(|s| s.dn_parts() == e.dn_parts())
+                        })
+                        .collect();
+                InternalSearchResults::Raw(get_static_entries(&entries, &dn_parts, request))
+            }
             SearchScope::Users => InternalSearchResults::UsersAndGroups(
                 get_user_list(&request.filter).await?,
                 Vec::new(),
@@ -631,6 +1263,17 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                     }],
                 })])
             }
+            SearchScope::NoChildren => InternalSearchResults::Empty,
+            SearchScope::Unknown
+                if self
+                    .ldap_info
+                    .static_entries
+                    .iter()
+                    .any(|e| e.is_under(&dn_parts)) =>
+            {
+                // Only the static entries are served below this DN.
+                InternalSearchResults::Empty
+            }
             SearchScope::Unknown => {
                 warn!(
                     r#"The requested search tree "{}" matches neither the user subtree "ou=people,{}" nor the group subtree "ou=groups,{}""#,
@@ -646,81 +1289,534 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 );
                 InternalSearchResults::Empty
             }
-        })
-    }
-
-    #[instrument(skip_all, level = "debug")]
-    pub async fn do_search(&self, request: &LdapSearchRequest) -> LdapResult<Vec<LdapOp>> {
-        let user_info = self.user_info.as_ref().ok_or_else(|| LdapError {
-            code: LdapResultCode::InsufficentAccessRights,
-            message: "No user currently bound".to_string(),
-        })?;
-        let backend_handler = self
-            .backend_handler
-            .get_user_restricted_lister_handler(user_info);
-
-        let schema =
-            PublicSchema::from(backend_handler.get_schema().await.map_err(|e| LdapError {
-                code: LdapResultCode::OperationsError,
-                message: format!("Unable to get schema: {:#}", e),
-            })?);
-        let search_results = self
-            .do_search_internal(&backend_handler, request, &schema)
-            .await?;
-        let mut results = match search_results {
-            InternalSearchResults::UsersAndGroups(users, groups) => {
-                convert_users_to_ldap_op(users, &request.attrs, &self.ldap_info, &schema)
-                    .chain(convert_groups_to_ldap_op(
-                        groups,
-                        &request.attrs,
-                        &self.ldap_info,
-                        &backend_handler.user_filter,
-                        &schema,
-                    ))
-                    .collect()
-            }
-            InternalSearchResults::Raw(raw_results) => raw_results,
-            InternalSearchResults::Empty => Vec::new(),
         };
-        if !matches!(results.last(), Some(LdapOp::SearchResultDone(_))) {
-            results.push(make_search_success());
+        if let (true, Some(searched), InternalSearchResults::UsersAndGroups(users, groups)) =
+            (self.ldap_info.filter_debug, searched, &results)
+        {
+            if users.is_empty() && groups.is_empty() {
+                self.explain_empty_search(request, schema, searched);
+            }
         }
         Ok(results)
     }
 
-    #[instrument(skip_all, level = "debug")]
-    async fn do_create_user(&self, request: LdapAddRequest) -> LdapResult<Vec<LdapOp>> {
-        let backend_handler = self
-            .user_info
-            .as_ref()
-            .and_then(|u| self.backend_handler.get_admin_handler(u))
-            .ok_or_else(|| LdapError {
-                code: LdapResultCode::InsufficentAccessRights,
-                message: "Unauthorized write".to_string(),
-            })?;
-        let user_id = get_user_id_from_distinguished_name(
-            &request.dn,
-            &self.ldap_info.base_dn,
-            &self.ldap_info.base_dn_str,
-        )?;
-        fn parse_attribute(mut attr: LdapPartialAttribute) -> LdapResult<(String, Vec<u8>)> {
-            if attr.vals.len() > 1 {
-                Err(LdapError {
-                    code: LdapResultCode::ConstraintViolation,
-                    message: format!("Expected a single value for attribute {}", attr.atype),
-                })
-            } else {
-                attr.atype.make_ascii_lowercase();
-                match attr.vals.pop() {
-                    Some(val) => Ok((attr.atype, val)),
-                    None => Err(LdapError {
-                        code: LdapResultCode::ConstraintViolation,
-                        message: format!("Missing value for attribute {}", attr.atype),
-                    }),
+    /// Logs why the filter of a search that found nothing could not match.
+    fn explain_empty_search(
+        &self,
+        request: &LdapSearchRequest,
+        schema: &PublicSchema,
+        searched: SearchedEntries,
+    ) {
+        let explanations = explain_filter(&self.ldap_info, &request.filter, schema, searched);
+        if explanations.is_empty() {
+            info!(
+                r#"The search under "{}" with the filter {} found nothing, but every part of the filter can match"#,
+                &request.base,
+                format_filter(&request.filter)
+            );
+        } else {
+            warn!(
+                r#"The search under "{}" with the filter {} found nothing: {}"#,
+                &request.base,
+                format_filter(&request.filter),
+                explanations.join(", ")
+            );
+        }
+    }
+
+    /// Returns the next `size` entries of the search, with the cookie to get the following ones.
+    /// The search runs in full on the first page, and the rest is kept in the session.
+    #[instrument(skip_all, level = "debug", fields(size))]
+    async fn do_paged_search(
+        &mut self,
+        request: &LdapSearchRequest,
+        size: i64,
+        cookie: &[u8],
+    ) -> (Vec<LdapOp>, LdapControl) {
+        let (mut entries, total) = if cookie.is_empty() {
+            let mut results = match self.do_search_or_dse(request).await {
+                Ok(results) => results,
+                Err(e) => {
+                    return (
+                        vec![make_search_error(e.code, e.message)],
+                        make_paged_results_control(0, vec![]),
+                    )
+                }
+            };
+            match results.pop() {
+                Some(LdapOp::SearchResultDone(LdapResultOp {
+                    code: LdapResultCode::Success,
+                    ..
+                })) => {}
+                done => {
+                    results.extend(done);
+                    return (results, make_paged_results_control(0, vec![]));
+                }
+            }
+            let total = results.len();
+            (results, total)
+        } else {
+            match self
+                .paged_searches
+                .iter()
+                .position(|s| s.cookie == cookie && s.request == *request)
+            {
+                Some(index) => {
+                    let search = self.paged_searches.remove(index);
+                    (search.entries, search.total)
+                }
+                None => {
+                    return (
+                        vec![make_search_error(
+                            LdapResultCode::UnwillingToPerform,
+                            "Invalid or expired paged results cookie".to_string(),
+                        )],
+                        make_paged_results_control(0, vec![]),
+                    )
+                }
+            }
+        };
+        // A size of 0 abandons the search.
+        let page_size = usize::try_from(size).unwrap_or(0).min(entries.len());
+        let remaining = entries.split_off(page_size);
+        let next_cookie = if remaining.is_empty() || page_size == 0 {
+            Vec::new()
+        } else {
+            self.next_paged_search_id += 1;
+            let cookie = self.next_paged_search_id.to_be_bytes().to_vec();
+            if self.paged_searches.len() >= MAX_PAGED_SEARCHES {
+                self.paged_searches.remove(0);
+            }
+            self.paged_searches.push(PagedSearch {
+                cookie: cookie.clone(),
+                request: request.clone(),
+                entries: remaining,
+                total,
+            });
+            cookie
+        };
+        entries.push(make_search_success());
+        (entries, make_paged_results_control(total, next_cookie))
+    }
+
+    pub async fn do_search(&self, request: &LdapSearchRequest) -> LdapResult<Vec<LdapOp>> {
+        Ok(self.stream_search(request).await?.collect())
+    }
+
+    /// Runs the search within the size and time limits of the request and of the server. The time
+    /// limit covers the queries to the backend, the entries are then built as they are consumed.
+    #[instrument(skip_all, level = "debug")]
+    async fn stream_search(&self, request: &LdapSearchRequest) -> LdapResult<LdapResponses> {
+        let search = self.stream_unlimited_search(request);
+        let mut results =
+            match effective_limit(request.timelimit, self.search_limits.max_time_seconds) {
+                Some(seconds) => tokio::time::timeout(Duration::from_secs(seconds), search)
+                    .await
+                    .map_err(|_| LdapError {
+                        code: LdapResultCode::TimeLimitExceeded,
+                        message: format!("The search took longer than {} seconds", seconds),
+                    })??,
+                None => search.await?,
+            };
+        if let Some(limit) = effective_limit(
+            request.sizelimit,
+            self.search_limits
+                .max_entries
+                .try_into()
+                .unwrap_or(u64::MAX),
+        ) {
+            results = apply_size_limit(results, limit.try_into().unwrap_or(usize::MAX));
+        }
+        Ok(results)
+    }
+
+    async fn stream_unlimited_search(
+        &self,
+        request: &LdapSearchRequest,
+    ) -> LdapResult<LdapResponses> {
+        let user_info = self.user_info.as_ref().ok_or_else(|| LdapError {
+            code: LdapResultCode::InsufficentAccessRights,
+            message: "No user currently bound".to_string(),
+        })?;
+        let backend_handler = self
+            .backend_handler
+            .get_user_restricted_lister_handler(user_info);
+
+        let schema =
+            PublicSchema::from(backend_handler.get_schema().await.map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Unable to get schema: {:#}", e),
+            })?);
+        let search_results = self
+            .do_search_internal(&backend_handler, request, &schema)
+            .await?;
+        let mut results: LdapResponses = match search_results {
+            InternalSearchResults::UsersAndGroups(users, groups) => {
+                let member_emails =
+                    get_member_emails(&backend_handler, &groups, &request.attrs, &self.ldap_info)
+                        .await?;
+                // The entries are built from owned copies, since they outlive the search.
+                let ldap_info = Arc::new(self.ldap_info.clone());
+                let schema = Arc::new(schema);
+                Box::new(
+                    convert_users_to_ldap_op(
+                        users,
+                        &request.attrs,
+                        ldap_info.clone(),
+                        schema.clone(),
+                    )
+                    .chain(convert_groups_to_ldap_op(
+                        groups,
+                        &request.attrs,
+                        ldap_info,
+                        backend_handler.user_filter.clone(),
+                        member_emails,
+                        schema,
+                    )),
+                )
+            }
+            InternalSearchResults::Raw(raw_results)
+                if matches!(raw_results.last(), Some(LdapOp::SearchResultDone(_))) =>
+            {
+                // An error, there are no entries to process.
+                return Ok(Box::new(raw_results.into_iter()));
+            }
+            InternalSearchResults::Raw(raw_results) => Box::new(raw_results.into_iter()),
+            InternalSearchResults::Empty => Box::new(std::iter::empty()),
+        };
+        if user_info.is_readonly() {
+            results = Box::new(results.map(|mut op| {
+                if let LdapOp::SearchResultEntry(entry) = &mut op {
+                    entry
+                        .attributes
+                        .retain(|attribute| !is_password_attribute(&attribute.atype));
+                }
+                op
+            }));
+        }
+        if !self.ldap_info.static_entries.is_empty() {
+            let base = parse_distinguished_name(&request.base)?;
+            results = Box::new(results.chain(get_static_entries(
+                &self.ldap_info.static_entries,
+                &base,
+                request,
+            )));
+        }
+        if !self.ldap_info.attribute_limits.is_empty() {
+            let attribute_limits = self.ldap_info.attribute_limits.clone();
+            results = Box::new(results.map(move |mut op| {
+                if let LdapOp::SearchResultEntry(entry) = &mut op {
+                    apply_attribute_limits(entry, &attribute_limits);
+                }
+                op
+            }));
+        }
+        Ok(Box::new(
+            results.chain(std::iter::once(make_search_success())),
+        ))
+    }
+
+    /// The user and group entries returned by the search, with their UUID, for a
+    /// synchronization. The other entries are not synchronized.
+    async fn get_synced_entries(
+        &self,
+        request: &LdapSearchRequest,
+    ) -> LdapResult<Vec<SyncedEntry>> {
+        let user_info = self.user_info.as_ref().ok_or_else(|| LdapError {
+            code: LdapResultCode::InsufficentAccessRights,
+            message: "No user currently bound".to_string(),
+        })?;
+        let backend_handler = self
+            .backend_handler
+            .get_user_restricted_lister_handler(user_info);
+        let schema =
+            PublicSchema::from(backend_handler.get_schema().await.map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Unable to get schema: {:#}", e),
+            })?);
+        let (users, groups) = match self
+            .do_search_internal(&backend_handler, request, &schema)
+            .await?
+        {
+            InternalSearchResults::UsersAndGroups(users, groups) => (users, groups),
+            InternalSearchResults::Raw(raw_results) => match raw_results.into_iter().last() {
+                Some(LdapOp::SearchResultDone(result)) => {
+                    return Err(LdapError {
+                        code: result.code,
+                        message: result.message,
+                    })
+                }
+                _ => (Vec::new(), Vec::new()),
+            },
+            InternalSearchResults::Empty => (Vec::new(), Vec::new()),
+        };
+        let member_emails =
+            get_member_emails(&backend_handler, &groups, &request.attrs, &self.ldap_info).await?;
+        let ids: Vec<_> = users
+            .iter()
+            .map(|u| {
+                (
+                    SyncedEntryId::User(u.user.user_id.clone()),
+                    u.user.uuid.clone(),
+                )
+            })
+            .chain(
+                groups
+                    .iter()
+                    .map(|g| (SyncedEntryId::Group(g.id), g.uuid.clone())),
+            )
+            .collect();
+        let ldap_info = Arc::new(self.ldap_info.clone());
+        let schema = Arc::new(schema);
+        // Each user and group gives exactly one entry.
+        let entries =
+            convert_users_to_ldap_op(users, &request.attrs, ldap_info.clone(), schema.clone())
+                .chain(convert_groups_to_ldap_op(
+                    groups,
+                    &request.attrs,
+                    ldap_info,
+                    backend_handler.user_filter.clone(),
+                    member_emails,
+                    schema,
+                ));
+        Ok(ids
+            .into_iter()
+            .zip(entries)
+            .filter_map(|((id, uuid), op)| {
+                let mut entry = match op {
+                    LdapOp::SearchResultEntry(entry) => entry,
+                    _ => return None,
+                };
+                if user_info.is_readonly() {
+                    entry
+                        .attributes
+                        .retain(|attribute| !is_password_attribute(&attribute.atype));
+                }
+                apply_attribute_limits(&mut entry, &self.ldap_info.attribute_limits);
+                match uuid::Uuid::parse_str(uuid.as_str()) {
+                    Ok(uuid) => Some(SyncedEntry { id, uuid, entry }),
+                    Err(e) => {
+                        warn!("Invalid UUID for {}, not synchronized: {}", entry.dn, e);
+                        None
+                    }
+                }
+            })
+            .collect())
+    }
+
+    async fn get_last_change_sequence(&self) -> LdapResult<i32> {
+        self.backend_handler
+            .unsafe_get_handler()
+            .get_last_change_sequence()
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Unable to read the change feed: {:#}", e),
+            })
+    }
+
+    /// Runs the refresh phase of a search with the Sync Request control (RFC 4533). In
+    /// refreshAndPersist mode, the session then follows the changes of the entries, see
+    /// `wait_for_sync_changes`.
+    #[instrument(skip(self, request, cookie), level = "debug")]
+    pub async fn do_sync_search(
+        &mut self,
+        message_id: i32,
+        request: &LdapSearchRequest,
+        mode: &SyncRequestMode,
+        cookie: Option<&[u8]>,
+    ) -> Vec<SyncResponse> {
+        self.sync_refresh(message_id, request, mode, cookie)
+            .await
+            .unwrap_or_else(|e: LdapError| vec![(make_search_error(e.code, e.message), vec![])])
+    }
+
+    async fn sync_refresh(
+        &mut self,
+        message_id: i32,
+        request: &LdapSearchRequest,
+        mode: &SyncRequestMode,
+        cookie: Option<&[u8]>,
+    ) -> LdapResult<Vec<SyncResponse>> {
+        let persist = matches!(mode, SyncRequestMode::RefreshAndPersist);
+        if persist && self.persistent_sync.is_some() {
+            return Err(LdapError {
+                code: LdapResultCode::UnwillingToPerform,
+                message: "Only one persistent synchronization per connection is supported"
+                    .to_string(),
+            });
+        }
+        // The change feed is not tied to any user: the entries themselves are read with the
+        // permissions of the bound user.
+        let backend_handler = self.backend_handler.unsafe_get_handler();
+        // Subscribed before reading the entries, so that no change is missed in between.
+        let changes = backend_handler.subscribe_to_changes();
+        let last = self.get_last_change_sequence().await?;
+        let changed = match cookie.and_then(parse_cookie) {
+            None => ChangedEntries::everything(),
+            Some(since) => {
+                let feed = backend_handler
+                    .list_changes(since, MAX_INCREMENTAL_CHANGES + 1)
+                    .await
+                    .map_err(|e| LdapError {
+                        code: LdapResultCode::OperationsError,
+                        message: format!("Unable to read the change feed: {:#}", e),
+                    })?;
+                ChangedEntries::from_feed(since, last, &feed, MAX_INCREMENTAL_CHANGES)
+            }
+        };
+        let entries = self.get_synced_entries(request).await?;
+        let responses = refresh_responses(&entries, &changed, make_cookie(last), mode);
+        if persist {
+            self.persistent_sync = Some(PersistentSync {
+                message_id,
+                request: request.clone(),
+                entries: entries.into_iter().map(|e| (e.uuid, e)).collect(),
+                changes,
+            });
+        }
+        Ok(responses)
+    }
+
+    /// Waits for a change that can affect the entries of the persistent synchronization, if
+    /// there is one. Unlike `get_sync_updates`, this can be interrupted without losing anything.
+    pub async fn wait_for_sync_changes(&mut self) {
+        if let Some(sync) = self.persistent_sync.as_mut() {
+            loop {
+                match sync.changes.recv().await {
+                    Ok(event) if !event.affects_searches() => {}
+                    // Some changes were missed, but the entries are compared in full anyway.
+                    Ok(_) | Err(RecvError::Lagged(_)) => return,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
+        std::future::pending().await
+    }
+
+    /// The updates of the entries of the persistent synchronization since the last ones, with the
+    /// ID of the message of the synchronization. An error ends the synchronization.
+    #[instrument(skip_all, level = "debug")]
+    pub async fn get_sync_updates(&mut self) -> Option<(i32, Vec<SyncResponse>)> {
+        let mut sync = self.persistent_sync.take()?;
+        // The changes made in a row are sent together.
+        loop {
+            match sync.changes.try_recv() {
+                Ok(_) | Err(TryRecvError::Lagged(_)) => {}
+                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+            }
+        }
+        let updates = match self.get_last_change_sequence().await {
+            Ok(last) => self
+                .get_synced_entries(&sync.request)
+                .await
+                .map(|entries| (last, entries)),
+            Err(e) => Err(e),
+        };
+        match updates {
+            Ok((last, entries)) => {
+                let responses = persist_responses(&sync.entries, &entries, &make_cookie(last));
+                sync.entries = entries.into_iter().map(|e| (e.uuid, e)).collect();
+                let message_id = sync.message_id;
+                self.persistent_sync = Some(sync);
+                Some((message_id, responses))
+            }
+            Err(e) => {
+                warn!("Ending the persistent synchronization: {}", e.message);
+                Some((
+                    sync.message_id,
+                    vec![(make_search_error(e.code, e.message), vec![])],
+                ))
+            }
+        }
+    }
+
+    /// The attributes of a new user other than the fixed fields, kept if the schema defines them.
+    async fn get_new_user_attributes(
+        &self,
+        backend_handler: &impl AdminBackendHandler,
+        attributes: &HashMap<String, Vec<Vec<u8>>>,
+    ) -> LdapResult<Vec<AttributeValue>> {
+        let other_attributes = attributes
+            .iter()
+            .filter(|(name, _)| !NEW_USER_FIELDS.contains(&name.as_str()))
+            .collect::<Vec<_>>();
+        if other_attributes.is_empty() {
+            return Ok(Vec::new());
+        }
+        let schema = UserReadableBackendHandler::get_schema(backend_handler)
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Unable to get schema: {:#}", e),
+            })?;
+        let mut new_attributes = Vec::new();
+        for (name, values) in other_attributes {
+            let field = self
+                .ldap_info
+                .resolve_user_attribute(&AttributeName::from(name.as_str()));
+            let (attribute, typ, is_list) = match map_user_field(&field, &schema) {
+                UserFieldType::Attribute(attribute, typ, is_list)
+                    if !NEW_USER_FIELD_ATTRIBUTES.contains(&attribute.as_str()) =>
+                {
+                    (attribute, typ, is_list)
                 }
+                _ => {
+                    warn!("Ignoring the attribute `{}` of the new user", name);
+                    continue;
+                }
+            };
+            if schema
+                .get_schema()
+                .user_attributes
+                .get_attribute_schema(&attribute)
+                .is_some_and(|a| a.is_readonly)
+            {
+                warn!(
+                    "Ignoring the read-only attribute `{}` of the new user",
+                    name
+                );
+                continue;
+            }
+            let value =
+                parse_ldap_attribute_value(values, typ, is_list).map_err(|e| LdapError {
+                    code: LdapResultCode::InvalidAttributeSyntax,
+                    message: format!("Invalid value for the attribute `{}`: {}", name, e),
+                })?;
+            new_attributes.push(AttributeValue {
+                name: attribute,
+                value,
+            });
+        }
+        Ok(new_attributes)
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn do_create_user(&self, request: LdapAddRequest) -> LdapResult<Vec<LdapOp>> {
+        let backend_handler = self
+            .user_info
+            .as_ref()
+            .and_then(|u| self.backend_handler.get_admin_handler(u))
+            .ok_or_else(|| LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: "Unauthorized write".to_string(),
+            })?;
+        let user_id = get_user_id_from_distinguished_name(
+            &request.dn,
+            &self.ldap_info.base_dn,
+            &self.ldap_info.base_dn_str,
+        )?;
+        fn parse_attribute(mut attr: LdapPartialAttribute) -> LdapResult<(String, Vec<Vec<u8>>)> {
+            if attr.vals.is_empty() {
+                return Err(LdapError {
+                    code: LdapResultCode::ConstraintViolation,
+                    message: format!("Missing value for attribute {}", attr.atype),
+                });
             }
+            attr.atype.make_ascii_lowercase();
+            Ok((attr.atype, attr.vals))
         }
-        let attributes: HashMap<String, Vec<u8>> = request
+        let attributes: HashMap<String, Vec<Vec<u8>>> = request
             .attributes
             .into_iter()
             .filter(|a| !a.atype.eq_ignore_ascii_case("objectclass"))
@@ -737,15 +1833,37 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 })
                 .map(str::to_owned)
         }
-        let get_attribute = |name| {
-            attributes
-                .get(name)
-                .map(Vec::as_slice)
-                .map(decode_attribute_value)
+        let get_single_value = |name: &str| {
+            attributes.get(name).map(|vals| match vals.as_slice() {
+                [val] => Ok(val.as_slice()),
+                _ => Err(LdapError {
+                    code: LdapResultCode::ConstraintViolation,
+                    message: format!("Expected a single value for attribute {}", name),
+                }),
+            })
         };
+        let get_attribute =
+            |name| get_single_value(name).map(|val| val.and_then(decode_attribute_value));
+        if let Some(uid) = get_attribute("uid").transpose()? {
+            if UserId::new(&uid) != user_id {
+                return Err(LdapError {
+                    code: LdapResultCode::NamingViolation,
+                    message: format!(r#"The uid "{}" doesn't match the DN"#, uid),
+                });
+            }
+        }
+        let password = get_single_value("userpassword").transpose()?;
+        if password.map_or(false, is_hashed_password) {
+            return Err(LdapError {
+                code: LdapResultCode::UnwillingToPerform,
+                message: "Hashed passwords are not supported, send the password in clear text \
+                          over an encrypted connection"
+                    .to_string(),
+            });
+        }
         backend_handler
             .create_user(CreateUserRequest {
-                user_id,
+                user_id: user_id.clone(),
                 email: Email::from(
                     get_attribute("mail")
                         .or_else(|| get_attribute("email"))
@@ -755,27 +1873,44 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 display_name: get_attribute("cn").transpose()?,
                 first_name: get_attribute("givenname").transpose()?,
                 last_name: get_attribute("sn").transpose()?,
-                avatar: attributes
-                    .get("avatar")
-                    .map(Vec::as_slice)
+                avatar: get_single_value("avatar")
+                    .transpose()?
                     .map(JpegPhoto::try_from)
                     .transpose()
                     .map_err(|e| LdapError {
                         code: LdapResultCode::ConstraintViolation,
                         message: format!("Invalid JPEG photo: {:#?}", e),
                     })?,
-                ..Default::default()
+                external_id: get_attribute("externalid").transpose()?,
+                attributes: self
+                    .get_new_user_attributes(backend_handler, &attributes)
+                    .await?,
+                source: UserCreationSource::Ldap,
             })
             .await
             .map_err(|e| LdapError {
-                code: LdapResultCode::OperationsError,
+                code: domain_error_code(&e),
                 message: format!("Could not create user: {:#?}", e),
             })?;
+        if let Some(password) = password {
+            self.change_password(self.get_opaque_handler(), user_id, password)
+                .await
+                .map_err(|e| LdapError {
+                    code: LdapResultCode::Other,
+                    message: format!(
+                        "The user was created, but setting the password failed: {:#?}",
+                        e
+                    ),
+                })?;
+        }
         Ok(vec![make_add_error(LdapResultCode::Success, String::new())])
     }
 
     #[instrument(skip_all, level = "debug")]
     pub async fn do_compare(&mut self, request: LdapCompareRequest) -> LdapResult<Vec<LdapOp>> {
+        if request.atype.eq_ignore_ascii_case("userPassword") {
+            return self.do_compare_password(request).await;
+        }
         let req = make_search_request::<String>(
             &self.ldap_info.base_dn_str,
             LdapFilter::Equality("dn".to_string(), request.dn.to_string()),
@@ -824,7 +1959,124 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         }
     }
 
+    /// The password is never stored nor returned, so it is checked like a bind instead: no stored
+    /// secret is compared byte by byte, and the failures count towards the lockout of the user.
+    async fn do_compare_password(
+        &mut self,
+        request: LdapCompareRequest,
+    ) -> LdapResult<Vec<LdapOp>> {
+        let credentials = self.user_info.as_ref().ok_or_else(|| LdapError {
+            code: LdapResultCode::InsufficentAccessRights,
+            message: "No user currently bound".to_string(),
+        })?;
+        let user_id = get_user_id_from_distinguished_name(
+            &request.dn,
+            &self.ldap_info.base_dn,
+            &self.ldap_info.base_dn_str,
+        )
+        .map_err(|e| LdapError {
+            code: LdapResultCode::InvalidDNSyntax,
+            message: format!("Invalid username: {}", e),
+        })?;
+        if !credentials.is_admin() && credentials.user != user_id {
+            return Err(LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: format!(
+                    r#"User `{}` cannot compare the password of user `{}`"#,
+                    &credentials.user, &user_id
+                ),
+            });
+        }
+        let throttling_keys = LdapBindLimiter::keys(&user_id, self.client_ip);
+        let lockout = self.bind_limiter.get_lockout(&throttling_keys).await;
+        let matches = if let Some(locked_until) = lockout {
+            warn!(
+                event = "ldap_compare_refused",
+                user = %user_id,
+                client_ip = ?self.client_ip,
+                %locked_until,
+                "Refusing to compare the password of {}, locked out after too many failures",
+                &user_id
+            );
+            false
+        } else {
+            let password = String::from_utf8(request.val).unwrap_or_default();
+            let matches = !password.is_empty()
+                && self
+                    .get_login_handler()
+                    .bind(BindRequest {
+                        name: user_id.clone(),
+                        password,
+                    })
+                    .await
+                    .is_ok();
+            if !matches {
+                self.bind_limiter.record_failure(&throttling_keys).await;
+            }
+            matches
+        };
+        Ok(vec![LdapOp::CompareResult(LdapResultOp {
+            code: if matches {
+                LdapResultCode::CompareTrue
+            } else {
+                LdapResultCode::CompareFalse
+            },
+            matcheddn: request.dn,
+            message: "".to_string(),
+            referral: vec![],
+        })])
+    }
+
+    /// Same as `handle_ldap_message`, with the controls of the request, and with the entries of
+    /// the searches built as they are consumed. Also returns the control to attach to the final
+    /// response, if any.
+    pub async fn handle_ldap_message_with_controls(
+        &mut self,
+        ldap_op: LdapOp,
+        controls: &[LdapControl],
+    ) -> Option<(LdapResponses, Option<LdapControl>)> {
+        if let LdapOp::SearchRequest(request) = &ldap_op {
+            let paging = controls.iter().find_map(|c| match c {
+                LdapControl::SimplePagedResults { size, cookie } => Some((*size, cookie)),
+                _ => None,
+            });
+            if let Some((size, cookie)) = paging {
+                // The following pages are kept in the session, so the whole result is built.
+                let (results, control) = self.do_paged_search(request, size, cookie).await;
+                return Some((Box::new(results.into_iter()), Some(control)));
+            }
+            let results =
+                self.stream_search_or_dse(request)
+                    .await
+                    .unwrap_or_else(|e: LdapError| {
+                        single_response(make_search_error(e.code, e.message))
+                    });
+            return Some((results, None));
+        }
+        self.handle_ldap_message(ldap_op)
+            .await
+            .map(|results| (Box::new(results.into_iter()) as LdapResponses, None))
+    }
+
+    /// The refusal of a change while the server is read-only.
+    fn read_only_refusal(&self, ldap_op: &LdapOp) -> Option<LdapOp> {
+        let message = self.read_only.refusal()?.to_owned();
+        let code = LdapResultCode::UnwillingToPerform;
+        match ldap_op {
+            LdapOp::AddRequest(_) => Some(make_add_error(code, message)),
+            LdapOp::ModifyRequest(_) => Some(make_modify_response(code, message)),
+            LdapOp::ExtendedRequest(request) if request.name == PASSWORD_MODIFY_OID => {
+                Some(make_extended_response(code, message))
+            }
+            _ => None,
+        }
+    }
+
     pub async fn handle_ldap_message(&mut self, ldap_op: LdapOp) -> Option<Vec<LdapOp>> {
+        if let Some(refusal) = self.read_only_refusal(&ldap_op) {
+            debug!("Refusing a change in read-only mode");
+            return Some(vec![refusal]);
+        }
         Some(match ldap_op {
             LdapOp::BindRequest(request) => {
                 let (code, message) = self.do_bind(&request).await;
@@ -864,6 +2116,19 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 .do_compare(request)
                 .await
                 .unwrap_or_else(|e: LdapError| vec![make_search_error(e.code, e.message)]),
+            LdapOp::AbandonRequest(message_id) => {
+                // Only a persistent synchronization lasts long enough to be abandoned. There is
+                // no response to an abandon request.
+                if self
+                    .persistent_sync
+                    .as_ref()
+                    .is_some_and(|s| s.message_id == message_id)
+                {
+                    debug!("Persistent synchronization abandoned");
+                    self.persistent_sync = None;
+                }
+                vec![]
+            }
             op => vec![make_extended_response(
                 LdapResultCode::UnwillingToPerform,
                 format!("Unsupported operation: {:#?}", op),
@@ -877,11 +2142,20 @@ mod tests {
     use super::*;
     use crate::{
         domain::{handler::*, types::*},
-        infra::test_utils::{setup_default_schema, MockTestBackendHandler},
+        infra::{
+            configuration::{
+                AttributeAlias, AttributeMappingEntries, GroupPosixDefaults,
+                IntegrationProfileAccount,
+            },
+            test_utils::{setup_default_schema, MockTestBackendHandler},
+        },
         uuid,
     };
     use chrono::TimeZone;
-    use ldap3_proto::proto::{LdapDerefAliases, LdapSearchScope, LdapSubstringFilter};
+    use ldap3_proto::proto::{
+        LdapDerefAliases, LdapIntermediateResponse, LdapSearchScope, LdapSubstringFilter,
+        SyncStateValue,
+    };
     use mockall::predicate::eq;
     use pretty_assertions::assert_eq;
     use std::collections::HashSet;
@@ -905,6 +2179,7 @@ mod tests {
         mut mock: MockTestBackendHandler,
         group: &str,
     ) -> LdapHandler<MockTestBackendHandler> {
+        mock.expect_get_account_deletion().returning(|_| Ok(None));
         mock.expect_bind()
             .with(eq(BindRequest {
                 name: UserId::new("test"),
@@ -920,6 +2195,7 @@ mod tests {
                     group_id: GroupId(42),
                     display_name: group.into(),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                     uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
                     attributes: Vec::new(),
                 });
@@ -959,6 +2235,7 @@ mod tests {
     #[tokio::test]
     async fn test_bind() {
         let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_account_deletion().returning(|_| Ok(None));
         mock.expect_bind()
             .with(eq(crate::domain::handler::BindRequest {
                 name: UserId::new("bob"),
@@ -990,23 +2267,310 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_admin_bind() {
+    async fn test_bind_plain_user_id() {
         let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_account_deletion().returning(|_| Ok(None));
         mock.expect_bind()
             .with(eq(crate::domain::handler::BindRequest {
-                name: UserId::new("test"),
+                name: UserId::new("bob"),
                 password: "pass".to_string(),
             }))
-            .times(1)
-            .return_once(|_| Ok(()));
+            .times(2)
+            .returning(|_| Ok(()));
         mock.expect_get_user_groups()
-            .with(eq(UserId::new("test")))
-            .return_once(|_| {
+            .with(eq(UserId::new("bob")))
+            .returning(|_| Ok(HashSet::new()));
+        let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
+        for dn in ["bob", "UID=Bob, OU=people, DC=Example, DC=com"] {
+            let request = LdapBindRequest {
+                dn: dn.to_string(),
+                cred: LdapBindCred::Simple("pass".to_string()),
+            };
+            assert_eq!(
+                ldap_handler.do_bind(&request).await,
+                (LdapResultCode::Success, "".to_string()),
+                "{}",
+                dn
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bind_lockout() {
+        let mut mock = MockTestBackendHandler::new();
+        // The password is not even checked once the user is locked out.
+        mock.expect_bind().times(2).returning(|_| {
+            Err(crate::domain::error::DomainError::AuthenticationError(
+                "Wrong".to_owned(),
+            ))
+        });
+        let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
+        let store = crate::domain::sql_backend_handler::SqlBackendHandler::new(
+            crate::domain::sql_backend_handler::tests::get_default_config(),
+            crate::domain::sql_backend_handler::tests::get_initialized_db().await,
+        );
+        ldap_handler.bind_limiter = Arc::new(LdapBindLimiter::new(
+            &crate::infra::configuration::LoginThrottlingOptionsBuilder::default()
+                .max_failures(2)
+                .build()
+                .unwrap(),
+            Arc::new(store),
+        ));
+        let request = LdapBindRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        for _ in 0..3 {
+            assert_eq!(
+                ldap_handler.do_bind(&request).await,
+                (LdapResultCode::InvalidCredentials, "".to_string()),
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bind_during_maintenance() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_account_deletion().returning(|_| Ok(None));
+        mock.expect_bind().times(2).returning(|_| Ok(()));
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| Ok(HashSet::new()));
+        let mut admin_groups = HashSet::new();
+        admin_groups.insert(GroupDetails {
+            group_id: GroupId(1),
+            display_name: "lldap_admin".into(),
+            creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+            modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+            uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            attributes: Vec::new(),
+        });
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("admin")))
+            .return_once(|_| Ok(admin_groups));
+        let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
+        ldap_handler.maintenance = Arc::new(MaintenanceMode::new(
+            &crate::infra::configuration::MaintenanceOptionsBuilder::default()
+                .enabled(true)
+                .message("Migrating".to_owned())
+                .build()
+                .unwrap(),
+        ));
+        let bind = |name: &str| LdapBindRequest {
+            dn: format!("uid={},ou=people,dc=example,dc=com", name),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&bind("bob")).await,
+            (LdapResultCode::Unavailable, "Migrating".to_string())
+        );
+        assert_eq!(ldap_handler.bound_dn(), None);
+        assert_eq!(
+            ldap_handler.do_bind(&bind("admin")).await,
+            (LdapResultCode::Success, "".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_changes_in_read_only_mode() {
+        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
+        ldap_handler.read_only = Arc::new(ReadOnlyMode::new(true));
+        let request = LdapOp::ModifyRequest(LdapModifyRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+            changes: vec![LdapModify {
+                operation: LdapModifyType::Replace,
+                modification: LdapPartialAttribute {
+                    atype: "userPassword".to_owned(),
+                    vals: vec![b"password".to_vec()],
+                },
+            }],
+        });
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_response(
+                LdapResultCode::UnwillingToPerform,
+                READ_ONLY_MESSAGE.to_owned(),
+            )])
+        );
+        let request = LdapOp::ExtendedRequest(
+            LdapPasswordModifyRequest {
+                user_identity: Some("uid=bob,ou=people,dc=example,dc=com".to_owned()),
+                old_password: None,
+                new_password: Some("password".to_owned()),
+            }
+            .into(),
+        );
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_extended_response(
+                LdapResultCode::UnwillingToPerform,
+                READ_ONLY_MESSAGE.to_owned(),
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_start_tls() {
+        let mut ldap_handler =
+            LdapHandler::new_for_tests(MockTestBackendHandler::new(), "dc=example,dc=com");
+        let start_tls = || {
+            LdapOp::ExtendedRequest(LdapExtendedRequest {
+                name: START_TLS_OID.to_string(),
+                value: None,
+            })
+        };
+        let response_code = |response: Option<Vec<LdapOp>>| match response.as_deref() {
+            Some([LdapOp::ExtendedResponse(response)]) => response.res.code,
+            _ => panic!("Unexpected response: {:?}", response),
+        };
+        assert_eq!(
+            response_code(ldap_handler.handle_ldap_message(start_tls()).await),
+            LdapResultCode::Unavailable
+        );
+        assert!(!ldap_handler.take_start_tls_request());
+
+        ldap_handler.connection_security = ConnectionSecurity::StartTlsAvailable;
+        assert_eq!(
+            response_code(ldap_handler.handle_ldap_message(start_tls()).await),
+            LdapResultCode::Success
+        );
+        assert!(ldap_handler.take_start_tls_request());
+        assert!(!ldap_handler.take_start_tls_request());
+
+        ldap_handler.set_tls_established();
+        assert_eq!(
+            response_code(ldap_handler.handle_ldap_message(start_tls()).await),
+            LdapResultCode::OperationsError
+        );
+        assert!(!ldap_handler.take_start_tls_request());
+    }
+
+    #[tokio::test]
+    async fn test_bind_requires_tls() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_account_deletion().returning(|_| Ok(None));
+        mock.expect_bind().times(1).return_once(|_| Ok(()));
+        mock.expect_get_user_groups()
+            .return_once(|_| Ok(HashSet::new()));
+        let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
+        ldap_handler.connection_security = ConnectionSecurity::StartTlsAvailable;
+        ldap_handler.require_tls_for_bind = true;
+        let request = LdapBindRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::ConfidentialityRequired
+        );
+        ldap_handler.set_tls_established();
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::Success
+        );
+    }
+
+    #[tokio::test]
+    async fn test_local_socket_bind() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_account_deletion().returning(|_| Ok(None));
+        mock.expect_bind().times(1).return_once(|_| Ok(()));
+        mock.expect_get_user_groups()
+            .times(2)
+            .returning(|_| Ok(HashSet::new()));
+        let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
+        ldap_handler.connection_security = ConnectionSecurity::LocalSocket;
+        ldap_handler.require_tls_for_bind = true;
+        // The connections from a configured local account are bound without a password.
+        assert_eq!(
+            ldap_handler
+                .bind_authenticated_user(UserId::new("bob"))
+                .await
+                .0,
+            LdapResultCode::Success
+        );
+        assert_eq!(
+            ldap_handler.bound_dn().as_deref(),
+            Some("uid=bob,ou=people,dc=example,dc=com")
+        );
+        // The traffic doesn't leave the host, so a password can be sent without TLS.
+        let request = LdapBindRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::Success
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bind_without_permissions() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| {
+                Err(crate::domain::error::DomainError::InternalError(
+                    "Error getting groups".to_string(),
+                ))
+            });
+        let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
+        assert_eq!(
+            ldap_handler
+                .bind_authenticated_user(UserId::new("bob"))
+                .await
+                .0,
+            LdapResultCode::OperationsError
+        );
+        assert_eq!(ldap_handler.bound_dn(), None);
+    }
+
+    #[tokio::test]
+    async fn test_bind_account_scheduled_for_deletion() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| Ok(HashSet::new()));
+        mock.expect_get_account_deletion()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| {
+                Ok(Some(AccountDeletion {
+                    user_id: UserId::new("bob"),
+                    request_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    deletion_date: Some(chrono::Utc.timestamp_opt(84, 42).unwrap().naive_utc()),
+                }))
+            });
+        let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
+        assert_eq!(
+            ldap_handler
+                .bind_authenticated_user(UserId::new("bob"))
+                .await
+                .0,
+            LdapResultCode::InvalidCredentials
+        );
+        assert_eq!(ldap_handler.bound_dn(), None);
+    }
+
+    #[tokio::test]
+    async fn test_admin_bind() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_account_deletion().returning(|_| Ok(None));
+        mock.expect_bind()
+            .with(eq(crate::domain::handler::BindRequest {
+                name: UserId::new("test"),
+                password: "pass".to_string(),
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("test")))
+            .return_once(|_| {
                 let mut set = HashSet::new();
                 set.insert(GroupDetails {
                     group_id: GroupId(42),
                     display_name: "lldap_admin".into(),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                     uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
                     attributes: Vec::new(),
                 });
@@ -1078,6 +2642,182 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_search_paged_results() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(eq(Some(true.into())), eq(false))
+            .times(1)
+            .return_once(|_, _| {
+                Ok(["alice", "bob", "carol"]
+                    .into_iter()
+                    .map(|id| UserAndGroups {
+                        user: User {
+                            user_id: UserId::new(id),
+                            ..Default::default()
+                        },
+                        groups: None,
+                    })
+                    .collect())
+            });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+        let request = LdapOp::SearchRequest(make_user_search_request::<String>(
+            LdapFilter::And(vec![]),
+            vec!["1.1".to_string()],
+        ));
+        let entry = |id: &str| {
+            LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                dn: format!("uid={},ou=people,dc=example,dc=com", id),
+                attributes: vec![],
+            })
+        };
+        let paging = |cookie: Vec<u8>| vec![LdapControl::SimplePagedResults { size: 2, cookie }];
+
+        let (results, control) = ldap_handler
+            .handle_ldap_message_with_controls(request.clone(), &paging(vec![]))
+            .await
+            .unwrap();
+        assert_eq!(
+            results.collect::<Vec<_>>(),
+            vec![entry("alice"), entry("bob"), make_search_success()]
+        );
+        let cookie = match control {
+            Some(LdapControl::SimplePagedResults { size: 3, cookie }) if !cookie.is_empty() => {
+                cookie
+            }
+            control => panic!("Unexpected control: {:?}", control),
+        };
+
+        // The search isn't run again for the next page.
+        let (results, control) = ldap_handler
+            .handle_ldap_message_with_controls(request.clone(), &paging(cookie.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            results.collect::<Vec<_>>(),
+            vec![entry("carol"), make_search_success()]
+        );
+        assert_eq!(
+            control,
+            Some(LdapControl::SimplePagedResults {
+                size: 3,
+                cookie: vec![]
+            })
+        );
+
+        // The cookie can't be reused once the search is over.
+        let (results, _) = ldap_handler
+            .handle_ldap_message_with_controls(request, &paging(cookie))
+            .await
+            .unwrap();
+        assert_eq!(
+            results.collect::<Vec<_>>(),
+            vec![make_search_error(
+                LdapResultCode::UnwillingToPerform,
+                "Invalid or expired paged results cookie".to_string()
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_search() {
+        let mut mock = MockTestBackendHandler::new();
+        let (sender, receiver) = broadcast::channel(4);
+        mock.expect_subscribe_to_changes()
+            .return_once(move || receiver);
+        let make_user = |email: &str| UserAndGroups {
+            user: User {
+                user_id: UserId::new("bob"),
+                email: email.into(),
+                uuid: uuid!("698e1d5f-7a40-3151-8745-b9b8a37839da"),
+                ..Default::default()
+            },
+            groups: None,
+        };
+        let mut sequence = mockall::Sequence::new();
+        mock.expect_get_last_change_sequence()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .return_once(|| Ok(7));
+        let bob = make_user("bob@bob.bob");
+        mock.expect_list_users()
+            .with(eq(Some(true.into())), eq(false))
+            .times(1)
+            .in_sequence(&mut sequence)
+            .return_once(|_, _| Ok(vec![bob]));
+        mock.expect_get_last_change_sequence()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .return_once(|| Ok(8));
+        let robert = make_user("robert@bob.bob");
+        mock.expect_list_users()
+            .with(eq(Some(true.into())), eq(false))
+            .times(1)
+            .in_sequence(&mut sequence)
+            .return_once(|_, _| Ok(vec![robert]));
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
+        let request = make_user_search_request(LdapFilter::And(vec![]), vec!["mail"]);
+        let entry = |email: &str| {
+            LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                attributes: vec![LdapPartialAttribute {
+                    atype: "mail".to_string(),
+                    vals: vec![email.as_bytes().to_vec()],
+                }],
+            })
+        };
+        let sync_state = |state, cookie: Option<&[u8]>| {
+            vec![LdapControl::SyncState {
+                state,
+                entry_uuid: uuid::Uuid::parse_str("698e1d5f-7a40-3151-8745-b9b8a37839da").unwrap(),
+                cookie: cookie.map(<[u8]>::to_vec),
+            }]
+        };
+        assert_eq!(
+            ldap_handler
+                .do_sync_search(3, &request, &SyncRequestMode::RefreshAndPersist, None)
+                .await,
+            vec![
+                (entry("bob@bob.bob"), sync_state(SyncStateValue::Add, None)),
+                (
+                    LdapOp::IntermediateResponse(
+                        LdapIntermediateResponse::SyncInfoRefreshPresent {
+                            cookie: Some(b"7".to_vec()),
+                            done: true,
+                        }
+                    ),
+                    vec![]
+                ),
+            ]
+        );
+        sender
+            .send(ChangeEvent::PasswordChanged(UserId::new("bob")))
+            .unwrap();
+        sender
+            .send(ChangeEvent::UserUpdated(UserId::new("bob")))
+            .unwrap();
+        ldap_handler.wait_for_sync_changes().await;
+        assert_eq!(
+            ldap_handler.get_sync_updates().await,
+            Some((
+                3,
+                vec![(
+                    entry("robert@bob.bob"),
+                    sync_state(SyncStateValue::Modify, Some(b"8"))
+                )]
+            ))
+        );
+        // There is no response to an abandon request.
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(LdapOp::AbandonRequest(3))
+                .await,
+            Some(vec![])
+        );
+        assert_eq!(ldap_handler.get_sync_updates().await, None);
+    }
+
     #[tokio::test]
     async fn test_search_member_of() {
         let mut mock = MockTestBackendHandler::new();
@@ -1094,6 +2834,7 @@ mod tests {
                         group_id: GroupId(42),
                         display_name: "rockstars".into(),
                         creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                        modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                         uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
                         attributes: Vec::new(),
                     }]),
@@ -1109,48 +2850,518 @@ mod tests {
             ldap_handler.do_search_or_dse(&request).await,
             Ok(vec![
                 LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
-                    attributes: vec![LdapPartialAttribute {
-                        atype: "memberOf".to_string(),
-                        vals: vec![b"cn=rockstars,ou=groups,dc=example,dc=com".to_vec()]
-                    }],
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "memberOf".to_string(),
+                        vals: vec![b"cn=rockstars,ou=groups,dc=example,dc=com".to_vec()]
+                    }],
+                }),
+                make_search_success(),
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_posix_defaults() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(eq(Some(true.into())), eq(true))
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![
+                    UserAndGroups {
+                        user: User {
+                            user_id: UserId::new("bob"),
+                            ..Default::default()
+                        },
+                        groups: Some(vec![GroupDetails {
+                            group_id: GroupId(42),
+                            display_name: "rockstars".into(),
+                            creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                            modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                            uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                            attributes: Vec::new(),
+                        }]),
+                    },
+                    UserAndGroups {
+                        user: User {
+                            user_id: UserId::new("jim"),
+                            ..Default::default()
+                        },
+                        groups: Some(vec![]),
+                    },
+                ])
+            });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+        ldap_handler.ldap_info.posix_defaults = PosixDefaultsOptions {
+            home_directory: Some("/home/{uid}".to_owned()),
+            login_shell: Some("/bin/sh".to_owned()),
+            gid_number: Some(10000),
+            groups: vec![GroupPosixDefaults {
+                group: "RockStars".into(),
+                home_directory: None,
+                login_shell: Some("/bin/bash".to_owned()),
+                gid_number: Some(500),
+            }],
+            ..Default::default()
+        };
+
+        let request = make_user_search_request(
+            LdapFilter::And(vec![]),
+            vec!["homeDirectory", "loginShell", "gidNumber"],
+        );
+        let make_entry = |user: &str, shell: &str, gid: &str| {
+            LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                dn: format!("uid={},ou=people,dc=example,dc=com", user),
+                attributes: vec![
+                    LdapPartialAttribute {
+                        atype: "gidNumber".to_string(),
+                        vals: vec![gid.as_bytes().to_vec()],
+                    },
+                    LdapPartialAttribute {
+                        atype: "homeDirectory".to_string(),
+                        vals: vec![format!("/home/{}", user).into_bytes()],
+                    },
+                    LdapPartialAttribute {
+                        atype: "loginShell".to_string(),
+                        vals: vec![shell.as_bytes().to_vec()],
+                    },
+                ],
+            })
+        };
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                make_entry("bob", "/bin/bash", "500"),
+                make_entry("jim", "/bin/sh", "10000"),
+                make_search_success(),
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_computed_attributes() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(eq(Some(true.into())), eq(false))
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        display_name: Some("Bob".to_owned()),
+                        attributes: vec![
+                            AttributeValue {
+                                name: "first_name".into(),
+                                value: Serialized::from("Robert"),
+                            },
+                            AttributeValue {
+                                name: "last_name".into(),
+                                value: Serialized::from("Smith"),
+                            },
+                        ],
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+        ldap_handler.ldap_info.computed_user_attributes = vec![
+            ComputedAttribute {
+                name: "displayName".to_owned(),
+                template: "{firstName} {lastName}".to_owned(),
+            },
+            ComputedAttribute {
+                name: "mailNickname".to_owned(),
+                template: "{uppercase(uid)}".to_owned(),
+            },
+        ];
+        let request = make_user_search_request(
+            LdapFilter::And(vec![]),
+            vec!["cn", "displayName", "mailNickname"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec![b"Bob".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "displayName".to_string(),
+                            vals: vec![b"Robert Smith".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "mailNickname".to_string(),
+                            vals: vec![b"BOB".to_vec()],
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_computed_attributes_with_sql_backend() {
+        // Unlike the mock, the SQL backend only loads the requested attributes.
+        let fixture = crate::domain::sql_backend_handler::tests::TestFixture::new().await;
+        let mut ldap_handler = LdapHandler::new_for_tests(fixture.handler, "dc=example,dc=com");
+        ldap_handler.user_info = Some(ValidationResults::admin());
+        ldap_handler.ldap_info.computed_user_attributes = vec![ComputedAttribute {
+            name: "mailNickname".to_owned(),
+            template: "{firstName}.{lowercase(lastName)}".to_owned(),
+        }];
+        let request = make_user_search_request(
+            LdapFilter::Equality("uid".to_string(), "bob".to_string()),
+            vec!["mailNickname"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "mailNickname".to_string(),
+                        vals: vec![b"first bob.last bob".to_vec()],
+                    }],
+                }),
+                make_search_success(),
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_size_limit() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users().times(2).returning(|_, _| {
+            Ok(["bob", "jim", "patrick"]
+                .into_iter()
+                .map(|id| UserAndGroups {
+                    user: User {
+                        user_id: UserId::new(id),
+                        ..Default::default()
+                    },
+                    groups: None,
+                })
+                .collect())
+        });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        ldap_handler.search_limits = SearchLimits {
+            max_entries: 2,
+            max_time_seconds: 0,
+        };
+        let entry = |id: &str| {
+            LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                dn: format!("uid={},ou=people,dc=example,dc=com", id),
+                attributes: vec![LdapPartialAttribute {
+                    atype: "uid".to_owned(),
+                    vals: vec![id.as_bytes().to_vec()],
+                }],
+            })
+        };
+        // The server's limit.
+        let mut request = make_user_search_request(LdapFilter::And(vec![]), vec!["uid"]);
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                entry("bob"),
+                entry("jim"),
+                make_search_error(
+                    LdapResultCode::SizeLimitExceeded,
+                    "The search matched more than 2 entries".to_owned()
+                ),
+            ]),
+        );
+        // A lower limit requested by the client.
+        request.sizelimit = 1;
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                entry("bob"),
+                make_search_error(
+                    LdapResultCode::SizeLimitExceeded,
+                    "The search matched more than 1 entries".to_owned()
+                ),
+            ]),
+        );
+    }
+
+    #[test]
+    fn test_effective_limit() {
+        assert_eq!(effective_limit(0, 0), None);
+        assert_eq!(effective_limit(10, 0), Some(10));
+        assert_eq!(effective_limit(0, 10), Some(10));
+        assert_eq!(effective_limit(100, 10), Some(10));
+        assert_eq!(effective_limit(5, 10), Some(5));
+        assert_eq!(effective_limit(-1, 0), None);
+    }
+
+    #[test]
+    fn test_size_limit_stops_the_stream() {
+        // The entries past the limit are never built, so this terminates.
+        let entries = std::iter::repeat_with(|| {
+            LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+                attributes: vec![],
+            })
+        });
+        let results: Vec<_> = apply_size_limit(Box::new(entries), 2).collect();
+        assert_eq!(results.len(), 3);
+        assert_eq!(
+            results.last(),
+            Some(&make_search_error(
+                LdapResultCode::SizeLimitExceeded,
+                "The search matched more than 2 entries".to_owned()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_user_as_scope() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    true.into(),
+                    UserRequestFilter::UserId(UserId::new("bob")),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
+        let request = LdapSearchRequest {
+            base: "uid=bob,ou=people,Dc=example,dc=com".to_string(),
+            scope: LdapSearchScope::Base,
+            aliases: LdapDerefAliases::Never,
+            sizelimit: 0,
+            timelimit: 0,
+            typesonly: false,
+            filter: LdapFilter::And(vec![]),
+            attrs: vec!["1.1".to_string()],
+        };
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_user_as_scope_with_cn_rdn() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    true.into(),
+                    UserRequestFilter::UserId(UserId::new("bob")),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+        ldap_handler.ldap_info.user_rdn = UserRdnAttribute::Cn;
+
+        let request = make_search_request(
+            "cn=bob,ou=people,dc=example,dc=com",
+            LdapFilter::And(vec![]),
+            vec!["uid"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "uid".to_string(),
+                        vals: vec![b"bob".to_vec()],
+                    }],
+                }),
+                make_search_success(),
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_active_directory_attributes() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    true.into(),
+                    UserRequestFilter::UserId(UserId::new("bob")),
+                    UserRequestFilter::UserId(UserId::new("bob")),
+                    false.into(),
+                    UserRequestFilter::Equality(
+                        UserColumn::Uuid,
+                        "a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8".to_owned(),
+                    ),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        ldap_handler.ldap_info.active_directory_compatibility = true;
+        let request = make_user_search_request(
+            LdapFilter::And(vec![
+                LdapFilter::Equality("objectClass".to_owned(), "user".to_owned()),
+                LdapFilter::Equality("sAMAccountName".to_owned(), "bob".to_owned()),
+                LdapFilter::Equality("userPrincipalName".to_owned(), "bob@Example.com".to_owned()),
+                LdapFilter::Equality("userPrincipalName".to_owned(), "bob@other.com".to_owned()),
+                LdapFilter::Equality(
+                    "objectGUID".to_owned(),
+                    "A1A2A3A4-B1B2-C1C2-D1D2-D3D4D5D6D7D8".to_owned(),
+                ),
+            ]),
+            vec!["sAMAccountName", "userPrincipalName", "objectGUID"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "objectGUID".to_string(),
+                            vals: vec![vec![
+                                0xa4, 0xa3, 0xa2, 0xa1, 0xb2, 0xb1, 0xc2, 0xc1, 0xd1, 0xd2, 0xd3,
+                                0xd4, 0xd5, 0xd6, 0xd7, 0xd8,
+                            ]],
+                        },
+                        LdapPartialAttribute {
+                            atype: "sAMAccountName".to_string(),
+                            vals: vec![b"bob".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "userPrincipalName".to_string(),
+                            vals: vec![b"bob@example.com".to_vec()],
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_with_integration_profile() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_account_deletion().returning(|_| Ok(None));
+        mock.expect_bind()
+            .with(eq(BindRequest {
+                name: UserId::new("service"),
+                password: "pass".to_string(),
+            }))
+            .return_once(|_| Ok(()));
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("service")))
+            .return_once(|_| {
+                Ok(HashSet::from([GroupDetails {
+                    group_id: GroupId(42),
+                    display_name: "lldap_strict_readonly".into(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    attributes: Vec::new(),
+                }]))
+            });
+        setup_default_schema(&mut mock);
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::UserId(UserId::new("bob")))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
+        ldap_handler.integration_profiles = IntegrationProfilesOptions {
+            accounts: vec![IntegrationProfileAccount {
+                user: UserId::new("service"),
+                profile: "custom".to_owned(),
+            }],
+            profiles: vec![IntegrationProfile {
+                name: "custom".to_owned(),
+                user_rdn: Some(UserRdnAttribute::Cn),
+                user_object_classes: vec!["shadowAccount".to_owned()],
+                group_object_classes: Vec::new(),
+                user_attribute_aliases: vec![AttributeAlias {
+                    alias: "login".into(),
+                    attribute: "uid".into(),
+                }],
+            }],
+        };
+        let request = LdapBindRequest {
+            dn: "uid=service,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::Success
+        );
+
+        let request = make_user_search_request(
+            LdapFilter::Equality("login".to_string(), "bob".to_string()),
+            vec!["login", "objectClass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "login".to_string(),
+                            vals: vec![b"bob".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![
+                                b"inetOrgPerson".to_vec(),
+                                b"posixAccount".to_vec(),
+                                b"mailAccount".to_vec(),
+                                b"person".to_vec(),
+                                b"customUserClass".to_vec(),
+                                b"shadowAccount".to_vec(),
+                            ],
+                        },
+                    ],
                 }),
                 make_search_success(),
             ]),
         );
     }
 
-    #[tokio::test]
-    async fn test_search_user_as_scope() {
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_users()
-            .with(
-                eq(Some(UserRequestFilter::And(vec![
-                    true.into(),
-                    UserRequestFilter::UserId(UserId::new("bob")),
-                ]))),
-                eq(false),
-            )
-            .times(1)
-            .return_once(|_, _| Ok(vec![]));
-        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
-
-        let request = LdapSearchRequest {
-            base: "uid=bob,ou=people,Dc=example,dc=com".to_string(),
-            scope: LdapSearchScope::Base,
-            aliases: LdapDerefAliases::Never,
-            sizelimit: 0,
-            timelimit: 0,
-            typesonly: false,
-            filter: LdapFilter::And(vec![]),
-            attrs: vec!["1.1".to_string()],
-        };
-        assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![make_search_success()]),
-        );
-    }
-
     #[tokio::test]
     async fn test_bind_invalid_dn() {
         let mock = MockTestBackendHandler::new();
@@ -1229,6 +3440,75 @@ mod tests {
                 .expect("parsing failed"),
             parsed_dn
         );
+        assert_eq!(
+            parse_distinguished_name("OU=People, DC=Example, DC=Com").expect("parsing failed"),
+            parsed_dn
+        );
+    }
+
+    #[test]
+    fn test_parse_distinguished_name_escapes() {
+        assert_eq!(
+            parse_distinguished_name(r"cn=Smith\, John,ou=groups").unwrap(),
+            vec![
+                ("cn".to_string(), "smith, john".to_string()),
+                ("ou".to_string(), "groups".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse_distinguished_name(r"cn=\ padded\ ,cn=C\2b\2B,cn=caf\C3\A9,cn=a+b").unwrap(),
+            vec![
+                ("cn".to_string(), " padded ".to_string()),
+                ("cn".to_string(), "c++".to_string()),
+                ("cn".to_string(), "café".to_string()),
+                ("cn".to_string(), "a+b".to_string()),
+            ]
+        );
+        for invalid in [
+            "",
+            "dc=example,",
+            "dc=example,,dc=com",
+            "example",
+            "d c=example",
+            r"cn=a\",
+            r"cn=\2",
+            r"cn=\2g",
+            r"cn=\ff",
+        ] {
+            assert_eq!(
+                parse_distinguished_name(invalid).unwrap_err().code,
+                LdapResultCode::InvalidDNSyntax,
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_distinguished_name() {
+        let parts = parse_distinguished_name(r"cn=\#Smith\, John\ ,DC=Example, DC=Com").unwrap();
+        assert_eq!(
+            format_distinguished_name(&parts),
+            r"cn=\#smith\, john\ ,dc=example,dc=com"
+        );
+        assert_eq!(
+            parse_distinguished_name(&format_distinguished_name(&parts)).unwrap(),
+            parts
+        );
+    }
+
+    #[test]
+    fn test_get_user_id_from_mixed_case_distinguished_name() {
+        let base_dn = parse_distinguished_name("dc=example,dc=com").unwrap();
+        assert_eq!(
+            get_user_id_from_distinguished_name(
+                "uid=Bob, OU=People, DC=Example, DC=Com",
+                &base_dn,
+                "dc=example,dc=com"
+            )
+            .unwrap(),
+            UserId::new("bob")
+        );
     }
 
     #[tokio::test]
@@ -1277,10 +3557,15 @@ mod tests {
                             },
                         ],
                         uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                        external_id: None,
                         creation_date: Utc
                             .with_ymd_and_hms(2014, 7, 8, 9, 10, 11)
                             .unwrap()
                             .naive_utc(),
+                        modified_date: Utc
+                            .with_ymd_and_hms(2014, 7, 8, 9, 10, 11)
+                            .unwrap()
+                            .naive_utc(),
                     },
                     groups: None,
                 },
@@ -1300,6 +3585,7 @@ mod tests {
                 "createTimestamp",
                 "entryUuid",
                 "jpegPhoto",
+                "thumbnailPhoto",
             ],
         );
         assert_eq!(
@@ -1389,6 +3675,10 @@ mod tests {
                             atype: "sn".to_string(),
                             vals: vec![b"Cricket".to_vec()]
                         },
+                        LdapPartialAttribute {
+                            atype: "thumbnailPhoto".to_string(),
+                            vals: vec![JpegPhoto::for_tests().into_bytes()]
+                        },
                         LdapPartialAttribute {
                             atype: "uid".to_string(),
                             vals: vec![b"jim".to_vec()]
@@ -1412,6 +3702,7 @@ mod tests {
                         id: GroupId(1),
                         display_name: "group_1".into(),
                         creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                        modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                         users: vec![UserId::new("bob"), UserId::new("john")],
                         uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
                         attributes: Vec::new(),
@@ -1420,6 +3711,7 @@ mod tests {
                         id: GroupId(3),
                         display_name: "BestGroup".into(),
                         creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                        modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                         users: vec![UserId::new("john")],
                         uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
                         attributes: Vec::new(),
@@ -1442,55 +3734,275 @@ mod tests {
             ldap_handler.do_search_or_dse(&request).await,
             Ok(vec![
                 LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
+                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec![b"group_1".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "entryDN".to_string(),
+                            vals: vec![b"uid=group_1,ou=groups,dc=example,dc=com".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "entryUuid".to_string(),
+                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![b"groupOfUniqueNames".to_vec(),]
+                        },
+                        LdapPartialAttribute {
+                            atype: "uniqueMember".to_string(),
+                            vals: vec![
+                                b"uid=bob,ou=people,dc=example,dc=com".to_vec(),
+                                b"uid=john,ou=people,dc=example,dc=com".to_vec(),
+                            ]
+                        },
+                    ],
+                }),
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=BestGroup,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec![b"BestGroup".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "entryDN".to_string(),
+                            vals: vec![b"uid=BestGroup,ou=groups,dc=example,dc=com".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "entryUuid".to_string(),
+                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![b"groupOfUniqueNames".to_vec(),]
+                        },
+                        LdapPartialAttribute {
+                            atype: "uniqueMember".to_string(),
+                            vals: vec![b"uid=john,ou=people,dc=example,dc=com".to_vec()]
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups_mail() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups()
+            .with(eq(Some(true.into())))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![Group {
+                    id: GroupId(1),
+                    display_name: "group_1".into(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    users: vec![UserId::new("bob"), UserId::new("john")],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    attributes: Vec::new(),
+                }])
+            });
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Or(vec![
+                    UserRequestFilter::MemberOfId(GroupId(1)),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![
+                    UserAndGroups {
+                        user: User {
+                            user_id: UserId::new("bob"),
+                            email: "bob@bobmail.bob".into(),
+                            ..Default::default()
+                        },
+                        groups: None,
+                    },
+                    UserAndGroups {
+                        user: User {
+                            user_id: UserId::new("john"),
+                            email: "john@doe.com".into(),
+                            ..Default::default()
+                        },
+                        groups: None,
+                    },
+                ])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_group_search_request(LdapFilter::And(vec![]), vec!["cn", "mail"]);
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec![b"group_1".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "mail".to_string(),
+                            vals: vec![b"bob@bobmail.bob".to_vec(), b"john@doe.com".to_vec()]
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_attribute_limits() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups()
+            .with(eq(Some(true.into())))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![Group {
+                    id: GroupId(1),
+                    display_name: "group_1".into(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    users: vec![UserId::new("bob"), UserId::new("john")],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    attributes: Vec::new(),
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        ldap_handler.ldap_info.attribute_limits = vec![
+            LdapAttributeLimit {
+                attribute: "member".into(),
+                max_values: Some(1),
+                max_value_bytes: None,
+            },
+            LdapAttributeLimit {
+                attribute: "cn".into(),
+                max_values: None,
+                max_value_bytes: Some(4),
+            },
+        ];
+        let request = make_group_search_request(LdapFilter::And(vec![]), vec!["cn", "member"]);
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "member;range=0-0".to_string(),
+                        vals: vec![b"uid=bob,ou=people,dc=example,dc=com".to_vec()],
+                    }],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_attribute_mappings() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Equality(
+                    UserColumn::LowercaseEmail,
+                    "bob@bob.bob".to_owned(),
+                ))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        email: "bob@bob.bob".into(),
+                        display_name: Some("Bob".to_owned()),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::DisplayName("group_1".into()))))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![Group {
+                    id: GroupId(1),
+                    display_name: "group_1".into(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    users: vec![],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    attributes: Vec::new(),
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        ldap_handler.ldap_info.attribute_mappings = vec![
+            LdapAttributeMapping {
+                name: "email".to_owned(),
+                attribute: "mail".into(),
+                entries: AttributeMappingEntries::Users,
+                rename: true,
+            },
+            LdapAttributeMapping {
+                name: "fullName".to_owned(),
+                attribute: "cn".into(),
+                entries: AttributeMappingEntries::All,
+                rename: false,
+            },
+            LdapAttributeMapping {
+                name: "name".to_owned(),
+                attribute: "cn".into(),
+                entries: AttributeMappingEntries::Groups,
+                rename: true,
+            },
+        ];
+        let request = make_user_search_request(
+            LdapFilter::Equality("email".to_string(), "bob@bob.bob".to_string()),
+            vec!["email", "mail", "fullName"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
                     attributes: vec![
                         LdapPartialAttribute {
-                            atype: "cn".to_string(),
-                            vals: vec![b"group_1".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "entryDN".to_string(),
-                            vals: vec![b"uid=group_1,ou=groups,dc=example,dc=com".to_vec()],
-                        },
-                        LdapPartialAttribute {
-                            atype: "entryUuid".to_string(),
-                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
-                        },
-                        LdapPartialAttribute {
-                            atype: "objectClass".to_string(),
-                            vals: vec![b"groupOfUniqueNames".to_vec(),]
+                            atype: "email".to_string(),
+                            vals: vec![b"bob@bob.bob".to_vec()],
                         },
                         LdapPartialAttribute {
-                            atype: "uniqueMember".to_string(),
-                            vals: vec![
-                                b"uid=bob,ou=people,dc=example,dc=com".to_vec(),
-                                b"uid=john,ou=people,dc=example,dc=com".to_vec(),
-                            ]
+                            atype: "fullName".to_string(),
+                            vals: vec![b"Bob".to_vec()],
                         },
                     ],
                 }),
+                make_search_success(),
+            ])
+        );
+        let request = make_group_search_request(
+            LdapFilter::Equality("name".to_string(), "group_1".to_string()),
+            vec!["cn", "fullName", "name"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
                 LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "cn=BestGroup,ou=groups,dc=example,dc=com".to_string(),
+                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
                     attributes: vec![
                         LdapPartialAttribute {
-                            atype: "cn".to_string(),
-                            vals: vec![b"BestGroup".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "entryDN".to_string(),
-                            vals: vec![b"uid=BestGroup,ou=groups,dc=example,dc=com".to_vec()],
-                        },
-                        LdapPartialAttribute {
-                            atype: "entryUuid".to_string(),
-                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
-                        },
-                        LdapPartialAttribute {
-                            atype: "objectClass".to_string(),
-                            vals: vec![b"groupOfUniqueNames".to_vec(),]
+                            atype: "fullName".to_string(),
+                            vals: vec![b"group_1".to_vec()],
                         },
                         LdapPartialAttribute {
-                            atype: "uniqueMember".to_string(),
-                            vals: vec![b"uid=john,ou=people,dc=example,dc=com".to_vec()]
+                            atype: "name".to_string(),
+                            vals: vec![b"group_1".to_vec()],
                         },
                     ],
                 }),
@@ -1527,6 +4039,7 @@ mod tests {
                     display_name: "group_1".into(),
                     id: GroupId(1),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                     users: vec![],
                     uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
                     attributes: Vec::new(),
@@ -1598,6 +4111,7 @@ mod tests {
                     display_name: "group_1".into(),
                     id: GroupId(1),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                     users: vec![],
                     uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
                     attributes: Vec::new(),
@@ -1959,6 +4473,43 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_search_filters_modify_timestamp() {
+        use chrono::prelude::*;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::ModifiedSince(
+                    Utc.with_ymd_and_hms(2024, 1, 31, 23, 59, 59)
+                        .unwrap()
+                        .naive_utc(),
+                ))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::GreaterOrEqual("modifyTimestamp".to_owned(), "20240131235959Z".to_owned()),
+            vec!["objectclass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
+        let request = make_user_search_request(
+            LdapFilter::GreaterOrEqual("modifyTimestamp".to_owned(), "yesterday".to_owned()),
+            vec!["objectclass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Err(LdapError {
+                code: LdapResultCode::InvalidAttributeSyntax,
+                message: "Invalid timestamp: yesterday".to_owned(),
+            })
+        );
+    }
+
     #[tokio::test]
     async fn test_search_filters_custom_object_class() {
         let mut mock = MockTestBackendHandler::new();
@@ -2000,6 +4551,139 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_search_ssh_public_keys() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_schema().returning(|| {
+            Ok(Schema {
+                user_attributes: AttributeList {
+                    attributes: vec![AttributeSchema {
+                        name: "sshpublickey".into(),
+                        attribute_type: AttributeType::String,
+                        is_list: true,
+                        is_visible: true,
+                        is_editable: true,
+                        is_hardcoded: true,
+                        is_readonly: false,
+                    }],
+                },
+                group_attributes: AttributeList {
+                    attributes: Vec::new(),
+                },
+                extra_user_object_classes: Vec::new(),
+                extra_group_object_classes: Vec::new(),
+            })
+        });
+        let keys = vec![
+            "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIAABAgMEBQYHCAkKCwwNDg8QERITFBUWFxgZGhscHR4f bob@laptop"
+                .to_owned(),
+            "ssh-rsa AAAAB3NzaC1yc2EAAAADAQABAAAAAQU= bob@desktop".to_owned(),
+        ];
+        let stored_keys = keys.clone();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    UserRequestFilter::CustomAttributePresent("sshpublickey".into()),
+                    UserRequestFilter::UserId(UserId::new("bob")),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(move |_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        attributes: vec![AttributeValue {
+                            name: "sshpublickey".into(),
+                            value: Serialized::from(&stored_keys),
+                        }],
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        // The search of openssh-ldap-publickey.
+        let request = make_user_search_request(
+            LdapFilter::And(vec![
+                LdapFilter::Equality("objectClass".to_owned(), "ldapPublicKey".to_owned()),
+                LdapFilter::Equality("uid".to_owned(), "bob".to_owned()),
+            ]),
+            vec!["objectClass", "sshPublicKey"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![
+                                b"inetOrgPerson".to_vec(),
+                                b"posixAccount".to_vec(),
+                                b"mailAccount".to_vec(),
+                                b"person".to_vec(),
+                                b"ldapPublicKey".to_vec(),
+                            ]
+                        },
+                        LdapPartialAttribute {
+                            atype: "sshPublicKey".to_string(),
+                            vals: keys.into_iter().map(String::into_bytes).collect(),
+                        },
+                    ]
+                }),
+                make_search_success()
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_object_class_with_sql_backend() {
+        let fixture = crate::domain::sql_backend_handler::tests::TestFixture::new().await;
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                insert_attributes: vec![AttributeValue {
+                    name: "sshpublickey".into(),
+                    value: Serialized::from(&vec![
+                        "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIAABAgMEBQYHCAkKCwwNDg8QERITFBUWFxgZGhscHR4f"
+                            .to_owned(),
+                    ]),
+                }],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let mut ldap_handler = LdapHandler::new_for_tests(fixture.handler, "dc=example,dc=com");
+        ldap_handler.user_info = Some(ValidationResults::admin());
+        // The key itself is not requested, but it decides the object classes.
+        let request = make_user_search_request(
+            LdapFilter::Equality("uid".to_owned(), "bob".to_owned()),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "objectClass".to_string(),
+                        vals: vec![
+                            b"inetOrgPerson".to_vec(),
+                            b"posixAccount".to_vec(),
+                            b"mailAccount".to_vec(),
+                            b"person".to_vec(),
+                            b"ldapPublicKey".to_vec(),
+                        ]
+                    }],
+                }),
+                make_search_success()
+            ])
+        );
+    }
+
     #[tokio::test]
     async fn test_search_both() {
         let mut mock = MockTestBackendHandler::new();
@@ -2032,6 +4716,7 @@ mod tests {
                     id: GroupId(1),
                     display_name: "group_1".into(),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                     users: vec![UserId::new("bob"), UserId::new("john")],
                     uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
                     attributes: Vec::new(),
@@ -2116,6 +4801,7 @@ mod tests {
                     id: GroupId(1),
                     display_name: "group_1".into(),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                     users: vec![UserId::new("bob"), UserId::new("john")],
                     uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
                     attributes: Vec::new(),
@@ -2124,8 +4810,7 @@ mod tests {
         let mut ldap_handler = setup_bound_admin_handler(mock).await;
 
         // Test simple wildcard
-        let request =
-            make_search_request("dc=example,dc=com", LdapFilter::And(vec![]), vec!["*", "+"]);
+        let request = make_search_request("dc=example,dc=com", LdapFilter::And(vec![]), vec!["*"]);
 
         // all: "objectclass", "dn", "uid", "mail", "givenname", "sn", "cn"
         // Operational: "createtimestamp"
@@ -2166,6 +4851,14 @@ mod tests {
                         atype: "mail".to_string(),
                         vals: vec![b"bob@bobmail.bob".to_vec()],
                     },
+                    LdapPartialAttribute {
+                        atype: "modifytimestamp".to_string(),
+                        vals: vec![chrono::Utc
+                            .timestamp_opt(0, 0)
+                            .unwrap()
+                            .to_rfc3339()
+                            .into_bytes()],
+                    },
                     LdapPartialAttribute {
                         atype: "objectclass".to_string(),
                         vals: vec![
@@ -2194,6 +4887,14 @@ mod tests {
                         atype: "cn".to_string(),
                         vals: vec![b"group_1".to_vec()],
                     },
+                    LdapPartialAttribute {
+                        atype: "createtimestamp".to_string(),
+                        vals: vec![chrono::Utc
+                            .timestamp_opt(42, 42)
+                            .unwrap()
+                            .to_rfc3339()
+                            .into_bytes()],
+                    },
                     LdapPartialAttribute {
                         atype: "entryuuid".to_string(),
                         vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
@@ -2206,6 +4907,14 @@ mod tests {
                             b"uid=john,ou=people,dc=example,dc=com".to_vec(),
                         ],
                     },
+                    LdapPartialAttribute {
+                        atype: "modifytimestamp".to_string(),
+                        vals: vec![chrono::Utc
+                            .timestamp_opt(42, 42)
+                            .unwrap()
+                            .to_rfc3339()
+                            .into_bytes()],
+                    },
                     LdapPartialAttribute {
                         atype: "objectclass".to_string(),
                         vals: vec![b"groupOfUniqueNames".to_vec()],
@@ -2243,34 +4952,163 @@ mod tests {
             expected_result
         );
 
+        // "1.1" is ignored along with other attributes.
         let request3 = make_search_request(
             "dc=example,dc=com",
             LdapFilter::And(vec![]),
-            vec!["*", "+", "+"],
+            vec!["*", "1.1", "*"],
+        );
+
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request3).await,
+            expected_result
+        );
+
+        let request4 =
+            make_search_request("dc=example,dc=com", LdapFilter::And(vec![]), vec![""; 0]);
+
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request4).await,
+            expected_result
+        );
+
+        let request5 = make_search_request(
+            "dc=example,dc=com",
+            LdapFilter::And(vec![]),
+            vec!["objectclass", "dn", "uid", "*"],
+        );
+
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request5).await,
+            expected_result
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_operational_attributes() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users().times(2).returning(|_, _| {
+            Ok(vec![UserAndGroups {
+                user: User {
+                    user_id: UserId::new("bob_1"),
+                    email: "bob@bobmail.bob".into(),
+                    uuid: uuid!("b4ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    ..Default::default()
+                },
+                groups: None,
+            }])
+        });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let operational_attributes = vec![
+            LdapPartialAttribute {
+                atype: "createtimestamp".to_string(),
+                vals: vec![chrono::Utc
+                    .timestamp_opt(0, 0)
+                    .unwrap()
+                    .to_rfc3339()
+                    .into_bytes()],
+            },
+            LdapPartialAttribute {
+                atype: "entrydn".to_string(),
+                vals: vec![b"uid=bob_1,ou=people,dc=example,dc=com".to_vec()],
+            },
+            LdapPartialAttribute {
+                atype: "entryuuid".to_string(),
+                vals: vec![b"b4ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
+            },
+            LdapPartialAttribute {
+                atype: "modifytimestamp".to_string(),
+                vals: vec![chrono::Utc
+                    .timestamp_opt(0, 0)
+                    .unwrap()
+                    .to_rfc3339()
+                    .into_bytes()],
+            },
+        ];
+        let request = make_user_search_request(LdapFilter::And(vec![]), vec!["+"]);
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                    attributes: operational_attributes.clone(),
+                }),
+                make_search_success()
+            ])
+        );
+        // The unknown attributes are skipped.
+        let request =
+            make_user_search_request(LdapFilter::And(vec![]), vec!["mail", "+", "notAnAttribute"]);
+        let mut attributes = operational_attributes;
+        attributes.insert(
+            3,
+            LdapPartialAttribute {
+                atype: "mail".to_string(),
+                vals: vec![b"bob@bobmail.bob".to_vec()],
+            },
         );
-
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request3).await,
-            expected_result
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                    attributes,
+                }),
+                make_search_success()
+            ])
         );
+    }
 
-        let request4 =
-            make_search_request("dc=example,dc=com", LdapFilter::And(vec![]), vec![""; 0]);
-
+    #[tokio::test]
+    async fn test_search_attribute_order() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users().times(2).returning(|_, _| {
+            Ok(vec![UserAndGroups {
+                user: User {
+                    user_id: UserId::new("bob_1"),
+                    email: "bob@bobmail.bob".into(),
+                    display_name: Some("Bob".to_string()),
+                    ..Default::default()
+                },
+                groups: None,
+            }])
+        });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request =
+            make_user_search_request(LdapFilter::And(vec![]), vec!["UID", "mail", "uid", "CN"]);
+        let make_attribute = |atype: &str, value: &[u8]| LdapPartialAttribute {
+            atype: atype.to_string(),
+            vals: vec![value.to_vec()],
+        };
+        // The first casing of an attribute in the request wins.
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request4).await,
-            expected_result
-        );
-
-        let request5 = make_search_request(
-            "dc=example,dc=com",
-            LdapFilter::And(vec![]),
-            vec!["objectclass", "dn", "uid", "*"],
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        make_attribute("CN", b"Bob"),
+                        make_attribute("mail", b"bob@bobmail.bob"),
+                        make_attribute("UID", b"bob_1"),
+                    ],
+                }),
+                make_search_success()
+            ])
         );
-
+        ldap_handler.ldap_info.attribute_order = LdapAttributeOrder::Requested;
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request5).await,
-            expected_result
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        make_attribute("UID", b"bob_1"),
+                        make_attribute("mail", b"bob@bobmail.bob"),
+                        make_attribute("CN", b"Bob"),
+                    ],
+                }),
+                make_search_success()
+            ])
         );
     }
 
@@ -2402,6 +5240,105 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_modify_user_attributes() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_custom_user_schema(&mut mock);
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .returning(|_| Ok(HashSet::new()));
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .returning(|_| {
+                Ok(User {
+                    user_id: UserId::new("bob"),
+                    email: "bob@example.com".into(),
+                    display_name: None,
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    uuid: uuid!("698e1d5f-7a40-3151-8745-b9b8a37839da"),
+                    external_id: None,
+                    attributes: vec![
+                        AttributeValue {
+                            name: "nickname".into(),
+                            value: Serialized::from("Bobby"),
+                        },
+                        AttributeValue {
+                            name: "phone".into(),
+                            value: Serialized::from(&vec!["123".to_owned()]),
+                        },
+                    ],
+                })
+            });
+        mock.expect_update_user()
+            .with(eq(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                email: Some("bob@bob.bob".into()),
+                delete_attributes: vec!["nickname".into()],
+                insert_attributes: vec![AttributeValue {
+                    name: "phone".into(),
+                    value: Serialized::from(&vec!["123".to_owned(), "456".to_owned()]),
+                }],
+                actor: Some(UserId::new("test")),
+                ..Default::default()
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let change = |operation, atype: &str, vals: &[&str]| LdapModify {
+            operation,
+            modification: LdapPartialAttribute {
+                atype: atype.to_owned(),
+                vals: vals.iter().map(|v| v.as_bytes().to_vec()).collect(),
+            },
+        };
+        let request = |changes| {
+            LdapOp::ModifyRequest(LdapModifyRequest {
+                dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                changes,
+            })
+        };
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(request(vec![
+                    change(LdapModifyType::Add, "phone", &["456"]),
+                    change(LdapModifyType::Delete, "nickname", &[]),
+                    change(LdapModifyType::Replace, "mail", &["bob@bob.bob"]),
+                ]))
+                .await,
+            Some(vec![make_modify_response(
+                LdapResultCode::Success,
+                "".to_string(),
+            )])
+        );
+        for (modification, code) in [
+            (
+                change(LdapModifyType::Add, "phone", &["123"]),
+                LdapResultCode::AttributeOrValueExists,
+            ),
+            (
+                change(LdapModifyType::Replace, "badge", &["7"]),
+                LdapResultCode::InsufficentAccessRights,
+            ),
+            (
+                change(LdapModifyType::Replace, "nickname", &["a", "b"]),
+                LdapResultCode::InvalidAttributeSyntax,
+            ),
+        ] {
+            let response = ldap_handler
+                .handle_ldap_message(request(vec![modification]))
+                .await;
+            assert!(
+                matches!(
+                    response.as_deref(),
+                    Some([LdapOp::ModifyResponse(result)]) if result.code == code
+                ),
+                "{:?}",
+                response
+            );
+        }
+    }
+
     #[tokio::test]
     async fn test_password_change_password_manager() {
         let mut mock = MockTestBackendHandler::new();
@@ -2450,6 +5387,107 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_password_change_own_password() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_account_deletion().returning(|_| Ok(None));
+        mock.expect_bind()
+            .with(eq(BindRequest {
+                name: UserId::new("test"),
+                password: "pass".to_string(),
+            }))
+            .times(2)
+            .returning(|_| Ok(()));
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("test")))
+            .returning(|_| Ok(HashSet::new()));
+        setup_default_schema(&mut mock);
+        use lldap_auth::*;
+        let mut rng = rand::rngs::OsRng;
+        let registration_start_request =
+            opaque::client::registration::start_registration("password".as_bytes(), &mut rng)
+                .unwrap();
+        let request = registration::ClientRegistrationStartRequest {
+            username: "test".into(),
+            registration_start_request: registration_start_request.message,
+        };
+        let start_response = opaque::server::registration::start_registration(
+            &opaque::server::ServerSetup::new(&mut rng),
+            request.registration_start_request,
+            &request.username,
+        )
+        .unwrap();
+        mock.expect_registration_start().times(1).return_once(|_| {
+            Ok(registration::ServerRegistrationStartResponse {
+                server_data: "".to_string(),
+                registration_response: start_response.message,
+            })
+        });
+        mock.expect_registration_finish()
+            .times(1)
+            .return_once(|_| Ok(()));
+        let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
+        let request = LdapBindRequest {
+            dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::Success
+        );
+        // Without an identity, the bound user's password is changed.
+        let request = LdapOp::ExtendedRequest(
+            LdapPasswordModifyRequest {
+                user_identity: None,
+                old_password: Some("pass".to_string()),
+                new_password: Some("password".to_string()),
+            }
+            .into(),
+        );
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_extended_response(
+                LdapResultCode::Success,
+                "".to_string(),
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_password_change_wrong_old_password() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .returning(|_| Ok(HashSet::new()));
+        mock.expect_bind()
+            .with(eq(BindRequest {
+                name: UserId::new("bob"),
+                password: "wrong".to_string(),
+            }))
+            .times(1)
+            .return_once(|_| {
+                Err(crate::domain::error::DomainError::AuthenticationError(
+                    "bob".to_string(),
+                ))
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = LdapOp::ExtendedRequest(
+            LdapPasswordModifyRequest {
+                user_identity: Some("uid=bob,ou=people,dc=example,dc=com".to_string()),
+                old_password: Some("wrong".to_string()),
+                new_password: Some("password".to_string()),
+            }
+            .into(),
+        );
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_extended_response(
+                LdapResultCode::InvalidCredentials,
+                "The old password is wrong".to_string(),
+            )])
+        );
+    }
+
     #[tokio::test]
     async fn test_password_change_errors() {
         let mut mock = MockTestBackendHandler::new();
@@ -2469,7 +5507,7 @@ mod tests {
             ldap_handler.handle_ldap_message(request).await,
             Some(vec![make_extended_response(
                 LdapResultCode::ConstraintViolation,
-                "Missing either user_id or password".to_string(),
+                "Missing the new password, generating one is not supported".to_string(),
             )])
         );
         let request = LdapOp::ExtendedRequest(
@@ -2508,6 +5546,7 @@ mod tests {
             group_id: GroupId(0),
             display_name: "lldap_admin".into(),
             creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+            modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
             uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
             attributes: Vec::new(),
         });
@@ -2558,6 +5597,44 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_password_change_unauthorized_regular_user() {
+        let mut ldap_handler =
+            setup_bound_handler_with_group(MockTestBackendHandler::new(), "regular").await;
+        let request = LdapOp::ExtendedRequest(
+            LdapPasswordModifyRequest {
+                user_identity: Some("uid=bob,ou=people,dc=example,dc=com".to_string()),
+                old_password: None,
+                new_password: Some("password".to_string()),
+            }
+            .into(),
+        );
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_extended_response(
+                LdapResultCode::InsufficentAccessRights,
+                "User `test` cannot modify the password of user `bob`".to_string(),
+            )])
+        );
+        let request = LdapOp::ModifyRequest(LdapModifyRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+            changes: vec![LdapModify {
+                operation: LdapModifyType::Replace,
+                modification: LdapPartialAttribute {
+                    atype: "userPassword".to_owned(),
+                    vals: vec![b"password".to_vec()],
+                },
+            }],
+        });
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_response(
+                LdapResultCode::InsufficentAccessRights,
+                "User `test` cannot modify user `bob`".to_owned(),
+            )])
+        );
+    }
+
     #[tokio::test]
     async fn test_search_root_dse() {
         let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
@@ -2574,7 +5651,120 @@ mod tests {
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
             Ok(vec![
-                root_dse_response("dc=example,dc=com"),
+                root_dse_response("dc=example,dc=com", ConnectionSecurity::Plaintext),
+                make_search_success()
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_root_dse_unbound() {
+        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
+        ldap_handler.user_info = None;
+        ldap_handler.connection_security = ConnectionSecurity::StartTlsAvailable;
+        let request = LdapSearchRequest {
+            base: "".to_string(),
+            scope: LdapSearchScope::Base,
+            aliases: LdapDerefAliases::Never,
+            sizelimit: 0,
+            timelimit: 0,
+            typesonly: false,
+            filter: LdapFilter::Equality("objectclass".to_string(), "*".to_string()),
+            attrs: vec!["namingContexts".to_string(), "vendorName".to_string()],
+        };
+        let results = ldap_handler.do_search_or_dse(&request).await.unwrap();
+        assert_eq!(results.len(), 2);
+        let attributes = match &results[0] {
+            LdapOp::SearchResultEntry(entry) => &entry.attributes,
+            other => panic!("Unexpected result: {:?}", other),
+        };
+        let get = |name: &str| {
+            attributes
+                .iter()
+                .find(|a| a.atype == name)
+                .map(|a| a.vals.clone())
+                .unwrap()
+        };
+        assert_eq!(get("namingContexts"), vec![b"dc=example,dc=com".to_vec()]);
+        assert_eq!(get("supportedLDAPVersion"), vec![b"3".to_vec()]);
+        assert_eq!(get("vendorName"), vec![b"LLDAP".to_vec()]);
+        assert_eq!(
+            get("supportedExtension"),
+            vec![
+                PASSWORD_MODIFY_OID.as_bytes().to_vec(),
+                START_TLS_OID.as_bytes().to_vec()
+            ]
+        );
+        assert!(attributes.iter().all(|a| !a.vals.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn test_search_subschema() {
+        let mut ldap_handler = setup_bound_readonly_handler(MockTestBackendHandler::new()).await;
+        let mut request = make_search_request(
+            "cn=subschema",
+            LdapFilter::Equality("objectClass".to_string(), "subschema".to_string()),
+            vec!["objectClasses", "attributeTypes"],
+        );
+        request.scope = LdapSearchScope::Base;
+        let results = ldap_handler.do_search_or_dse(&request).await.unwrap();
+        assert_eq!(results.len(), 2);
+        match &results[0] {
+            LdapOp::SearchResultEntry(entry) => {
+                assert_eq!(entry.dn, "cn=Subschema");
+                assert_eq!(
+                    entry
+                        .attributes
+                        .iter()
+                        .map(|a| a.atype.as_str())
+                        .collect::<Vec<_>>(),
+                    vec!["attributeTypes", "objectClasses"]
+                );
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        ldap_handler.user_info = None;
+        assert_eq!(
+            ldap_handler
+                .do_search_or_dse(&request)
+                .await
+                .unwrap_err()
+                .code,
+            LdapResultCode::InsufficentAccessRights
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_static_entries() {
+        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
+        ldap_handler.ldap_info.static_entries = vec![StaticEntry::new(
+            &crate::infra::configuration::StaticLdapEntry {
+                dn: "cn=default,ou=policies,dc=example,dc=com".to_owned(),
+                attributes: [
+                    ("objectClass".to_owned(), vec!["pwdPolicy".to_owned()]),
+                    ("pwdMinLength".to_owned(), vec!["12".to_owned()]),
+                ]
+                .into_iter()
+                .collect(),
+            },
+            &ldap_handler.ldap_info.base_dn,
+        )
+        .unwrap()];
+        let request = make_search_request(
+            "ou=policies,dc=example,dc=com",
+            LdapFilter::Equality("objectClass".to_owned(), "pwdpolicy".to_owned()),
+            vec!["pwdMinLength"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=default,ou=policies,dc=example,dc=com".to_owned(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "pwdMinLength".to_owned(),
+                        vals: vec![b"12".to_vec()],
+                    }],
+                }),
                 make_search_success()
             ])
         );
@@ -2588,6 +5778,7 @@ mod tests {
                 user_id: UserId::new("bob"),
                 email: "".into(),
                 display_name: Some("Bob".to_string()),
+                source: UserCreationSource::Ldap,
                 ..Default::default()
             }))
             .times(1)
@@ -2606,6 +5797,103 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_create_user_with_password() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_create_user()
+            .with(eq(CreateUserRequest {
+                user_id: UserId::new("bob"),
+                email: "bob@example.com".into(),
+                display_name: Some("Bob Bobberson".to_string()),
+                first_name: Some("Bob".to_string()),
+                last_name: Some("Bobberson".to_string()),
+                source: UserCreationSource::Ldap,
+                ..Default::default()
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        use lldap_auth::*;
+        let mut rng = rand::rngs::OsRng;
+        let registration_start_request =
+            opaque::client::registration::start_registration("password".as_bytes(), &mut rng)
+                .unwrap();
+        let start_response = opaque::server::registration::start_registration(
+            &opaque::server::ServerSetup::new(&mut rng),
+            registration_start_request.message,
+            &UserId::new("bob"),
+        )
+        .unwrap();
+        mock.expect_registration_start()
+            .withf(|request| request.username == UserId::new("bob"))
+            .times(1)
+            .return_once(|_| {
+                Ok(registration::ServerRegistrationStartResponse {
+                    server_data: "".to_string(),
+                    registration_response: start_response.message,
+                })
+            });
+        mock.expect_registration_finish()
+            .times(1)
+            .return_once(|_| Ok(()));
+        let ldap_handler = setup_bound_admin_handler(mock).await;
+        let attribute = |atype: &str, value: &str| LdapPartialAttribute {
+            atype: atype.to_owned(),
+            vals: vec![value.as_bytes().to_vec()],
+        };
+        let request = LdapAddRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+            attributes: vec![
+                attribute("uid", "bob"),
+                attribute("mail", "bob@example.com"),
+                attribute("cn", "Bob Bobberson"),
+                attribute("givenName", "Bob"),
+                attribute("sn", "Bobberson"),
+                attribute("userPassword", "password"),
+            ],
+        };
+        assert_eq!(
+            ldap_handler.do_create_user(request).await,
+            Ok(vec![make_add_error(LdapResultCode::Success, String::new())])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_user_rejected_attributes() {
+        let ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
+        let request = |atype: &str, value: &str| LdapAddRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+            attributes: vec![LdapPartialAttribute {
+                atype: atype.to_owned(),
+                vals: vec![value.as_bytes().to_vec()],
+            }],
+        };
+        assert_eq!(
+            ldap_handler
+                .do_create_user(request("uid", "alice"))
+                .await
+                .unwrap_err()
+                .code,
+            LdapResultCode::NamingViolation
+        );
+        assert_eq!(
+            ldap_handler
+                .do_create_user(request("userPassword", "{SSHA}c2FsdGVkaGFzaA=="))
+                .await
+                .unwrap_err()
+                .code,
+            LdapResultCode::UnwillingToPerform
+        );
+    }
+
+    #[test]
+    fn test_is_hashed_password() {
+        assert!(is_hashed_password(b"{SSHA}c2FsdGVkaGFzaA=="));
+        assert!(is_hashed_password(b"{PBKDF2-SHA512}10000$abc"));
+        assert!(!is_hashed_password(b"password"));
+        assert!(!is_hashed_password(b"{not a scheme}"));
+        assert!(!is_hashed_password(b"{}"));
+    }
+
     #[tokio::test]
     async fn test_create_user_wrong_ou() {
         let ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
@@ -2622,6 +5910,72 @@ mod tests {
         );
     }
 
+    fn expect_custom_user_schema(mock: &mut MockTestBackendHandler) {
+        mock.expect_get_schema().returning(|| {
+            let attribute = |name: &str, attribute_type, is_list, is_readonly| AttributeSchema {
+                name: name.into(),
+                attribute_type,
+                is_list,
+                is_visible: true,
+                is_editable: true,
+                is_hardcoded: false,
+                is_readonly,
+            };
+            Ok(Schema {
+                user_attributes: AttributeList {
+                    attributes: vec![
+                        attribute("phone", AttributeType::String, true, false),
+                        attribute("nickname", AttributeType::String, false, false),
+                        attribute("badge", AttributeType::Integer, false, true),
+                    ],
+                },
+                group_attributes: AttributeList {
+                    attributes: Vec::new(),
+                },
+                extra_user_object_classes: Vec::new(),
+                extra_group_object_classes: Vec::new(),
+            })
+        });
+    }
+
+    #[tokio::test]
+    async fn test_create_user_custom_attributes() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_custom_user_schema(&mut mock);
+        mock.expect_create_user()
+            .with(eq(CreateUserRequest {
+                user_id: UserId::new("bob"),
+                email: "bob@example.com".into(),
+                attributes: vec![AttributeValue {
+                    name: "phone".into(),
+                    value: Serialized::from(&vec!["123".to_owned(), "456".to_owned()]),
+                }],
+                source: UserCreationSource::Ldap,
+                ..Default::default()
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        let ldap_handler = setup_bound_admin_handler(mock).await;
+        let attribute = |atype: &str, vals: &[&str]| LdapPartialAttribute {
+            atype: atype.to_owned(),
+            vals: vals.iter().map(|v| v.as_bytes().to_vec()).collect(),
+        };
+        let request = LdapAddRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+            attributes: vec![
+                attribute("mail", &["bob@example.com"]),
+                attribute("phone", &["123", "456"]),
+                // Read-only, and not in the schema.
+                attribute("badge", &["42"]),
+                attribute("description", &["Builder"]),
+            ],
+        };
+        assert_eq!(
+            ldap_handler.do_create_user(request).await,
+            Ok(vec![make_add_error(LdapResultCode::Success, String::new())])
+        );
+    }
+
     #[tokio::test]
     async fn test_create_user_multiple_object_class() {
         let mut mock = MockTestBackendHandler::new();
@@ -2630,6 +5984,7 @@ mod tests {
                 user_id: UserId::new("bob"),
                 email: "".into(),
                 display_name: Some("Bob".to_string()),
+                source: UserCreationSource::Ldap,
                 ..Default::default()
             }))
             .times(1)
@@ -2735,6 +6090,7 @@ mod tests {
                 id: GroupId(1),
                 display_name: "group".into(),
                 creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                 users: vec![UserId::new("bob")],
                 uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
                 attributes: Vec::new(),
@@ -2829,6 +6185,7 @@ mod tests {
                 id: GroupId(1),
                 display_name: "group".into(),
                 creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                 users: vec![UserId::new("bob")],
                 uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
                 attributes: Vec::new(),
@@ -2852,6 +6209,63 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_compare_password() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_bind()
+            .withf(|request| request.name == UserId::new("bob"))
+            .returning(|request| {
+                if request.password == "correct horse" {
+                    Ok(())
+                } else {
+                    Err(crate::domain::error::DomainError::AuthenticationError(
+                        "Wrong password".to_owned(),
+                    ))
+                }
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let dn = "uid=bob,ou=people,dc=example,dc=com";
+        let compare = |password: &str| LdapCompareRequest {
+            dn: dn.to_string(),
+            atype: "userPassword".to_owned(),
+            val: password.as_bytes().to_vec(),
+        };
+        let result = |code| {
+            Ok(vec![LdapOp::CompareResult(LdapResultOp {
+                code,
+                matcheddn: dn.to_string(),
+                message: "".to_string(),
+                referral: vec![],
+            })])
+        };
+        assert_eq!(
+            ldap_handler.do_compare(compare("correct horse")).await,
+            result(LdapResultCode::CompareTrue)
+        );
+        assert_eq!(
+            ldap_handler.do_compare(compare("battery staple")).await,
+            result(LdapResultCode::CompareFalse)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compare_password_of_another_user() {
+        let mut ldap_handler =
+            setup_bound_password_manager_handler(MockTestBackendHandler::new()).await;
+        let request = LdapCompareRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+            atype: "userPassword".to_owned(),
+            val: b"correct horse".to_vec(),
+        };
+        assert_eq!(
+            ldap_handler.do_compare(request).await,
+            Err(LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: "User `test` cannot compare the password of user `bob`".to_owned(),
+            })
+        );
+    }
+
     #[tokio::test]
     async fn test_user_ou_search() {
         let mut ldap_handler = setup_bound_readonly_handler(MockTestBackendHandler::new()).await;
@@ -2880,6 +6294,66 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_search_scope_on_base_dn() {
+        let mut ldap_handler = setup_bound_readonly_handler(MockTestBackendHandler::new()).await;
+        let make_request = |scope| LdapSearchRequest {
+            base: "dc=example,dc=com".to_owned(),
+            scope,
+            aliases: LdapDerefAliases::Never,
+            sizelimit: 0,
+            timelimit: 0,
+            typesonly: false,
+            filter: LdapFilter::Present("objectClass".to_owned()),
+            attrs: vec!["objectClass".to_owned()],
+        };
+        let get_dns = |results: LdapResult<Vec<LdapOp>>| {
+            results
+                .unwrap()
+                .into_iter()
+                .filter_map(|op| match op {
+                    LdapOp::SearchResultEntry(e) => Some(e.dn),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            get_dns(
+                ldap_handler
+                    .do_search_or_dse(&make_request(LdapSearchScope::Base))
+                    .await
+            ),
+            vec!["dc=example,dc=com"]
+        );
+        assert_eq!(
+            get_dns(
+                ldap_handler
+                    .do_search_or_dse(&make_request(LdapSearchScope::OneLevel))
+                    .await
+            ),
+            vec!["ou=people,dc=example,dc=com", "ou=groups,dc=example,dc=com"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_one_level_under_user() {
+        let mut ldap_handler = setup_bound_readonly_handler(MockTestBackendHandler::new()).await;
+        let request = LdapSearchRequest {
+            base: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+            scope: LdapSearchScope::OneLevel,
+            aliases: LdapDerefAliases::Never,
+            sizelimit: 0,
+            timelimit: 0,
+            typesonly: false,
+            filter: LdapFilter::And(vec![]),
+            attrs: Vec::new(),
+        };
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
+    }
+
     #[tokio::test]
     async fn test_custom_attribute_read() {
         let mut mock = MockTestBackendHandler::new();
@@ -2901,6 +6375,7 @@ mod tests {
                 id: GroupId(1),
                 display_name: "group".into(),
                 creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                 users: vec![UserId::new("bob")],
                 uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
                 attributes: vec![AttributeValue {
@@ -2980,4 +6455,133 @@ mod tests {
             ]),
         );
     }
+
+    fn setup_shadow_attribute_mock() -> MockTestBackendHandler {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users().times(1).return_once(|_, _| {
+            Ok(vec![UserAndGroups {
+                user: User {
+                    user_id: UserId::new("test"),
+                    attributes: vec![AttributeValue {
+                        name: "shadowLastChange".into(),
+                        value: Serialized::from("19000"),
+                    }],
+                    ..Default::default()
+                },
+                groups: None,
+            }])
+        });
+        mock.expect_get_schema().returning(|| {
+            Ok(crate::domain::handler::Schema {
+                user_attributes: AttributeList {
+                    attributes: vec![AttributeSchema {
+                        name: "shadowLastChange".into(),
+                        attribute_type: AttributeType::String,
+                        is_list: false,
+                        is_visible: true,
+                        is_editable: true,
+                        is_hardcoded: false,
+                        is_readonly: false,
+                    }],
+                },
+                group_attributes: AttributeList {
+                    attributes: Vec::new(),
+                },
+                extra_user_object_classes: Vec::new(),
+                extra_group_object_classes: Vec::new(),
+            })
+        });
+        mock
+    }
+
+    #[tokio::test]
+    async fn test_search_readonly_hides_password_attributes() {
+        let request =
+            make_user_search_request(LdapFilter::And(vec![]), vec!["uid", "shadowLastChange"]);
+        let mut ldap_handler = setup_bound_readonly_handler(setup_shadow_attribute_mock()).await;
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "uid".to_owned(),
+                        vals: vec![b"test".to_vec()],
+                    }],
+                }),
+                make_search_success()
+            ]),
+        );
+        let mut ldap_handler = setup_bound_admin_handler(setup_shadow_attribute_mock()).await;
+        match &ldap_handler.do_search_or_dse(&request).await.unwrap()[0] {
+            LdapOp::SearchResultEntry(entry) => assert!(entry
+                .attributes
+                .iter()
+                .any(|a| a.atype.eq_ignore_ascii_case("shadowLastChange"))),
+            op => panic!("Unexpected result: {:?}", op),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_modify_readonly() {
+        let mut ldap_handler = setup_bound_readonly_handler(MockTestBackendHandler::new()).await;
+        // Not even their own entry.
+        let request = LdapOp::ModifyRequest(LdapModifyRequest {
+            dn: "uid=test,ou=people,dc=example,dc=com".to_owned(),
+            changes: vec![LdapModify {
+                operation: LdapModifyType::Replace,
+                modification: LdapPartialAttribute {
+                    atype: "userPassword".to_owned(),
+                    vals: vec![b"password".to_vec()],
+                },
+            }],
+        });
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_response(
+                LdapResultCode::InsufficentAccessRights,
+                "User `test` has read-only access".to_owned(),
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_explain_filter() {
+        let mut mock = MockTestBackendHandler::new();
+        setup_default_schema(&mut mock);
+        let schema = PublicSchema::from(mock.get_schema().await.unwrap());
+        let ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
+        let users = SearchedEntries {
+            users: true,
+            groups: false,
+        };
+        let filter = LdapFilter::And(vec![
+            LdapFilter::Equality("uid".to_owned(), "bob".to_owned()),
+            LdapFilter::Equality("objectClass".to_owned(), "groupOfNames".to_owned()),
+            LdapFilter::Present("shoeSize".to_owned()),
+            LdapFilter::Equality("modifyTimestamp".to_owned(), "20200101000000Z".to_owned()),
+            LdapFilter::Not(Box::new(LdapFilter::Present("shoeSize".to_owned()))),
+        ]);
+        assert_eq!(
+            explain_filter(&ldap_handler.ldap_info, &filter, &schema, users),
+            vec![
+                "(objectClass=groupOfNames): no entry can have this value".to_owned(),
+                "(shoeSize=*): unknown attribute".to_owned(),
+                "(modifyTimestamp=20200101000000Z): unsupported construct: Only >= filters are supported on modifyTimestamp".to_owned(),
+            ]
+        );
+        // The parts that can match are not explained.
+        assert_eq!(
+            explain_filter(
+                &ldap_handler.ldap_info,
+                &LdapFilter::Equality("cn".to_owned(), "admins".to_owned()),
+                &schema,
+                SearchedEntries {
+                    users: true,
+                    groups: true,
+                },
+            ),
+            Vec::<String>::new()
+        );
+    }
 }
@@ -0,0 +1,238 @@
+use std::{collections::BTreeMap, sync::Mutex, time::Duration};
+
+use ldap3_proto::{proto::LdapOp, LdapFilter};
+
+/// Aggregated statistics of a type of LDAP operation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LdapOperationStats {
+    pub count: u64,
+    /// Operations that took longer than `ldap_slow_operation_threshold_ms`.
+    pub slow_count: u64,
+    /// Entries returned by the searches.
+    pub entries: u64,
+    pub total_duration: Duration,
+    pub max_duration: Duration,
+}
+
+/// Statistics of the TLS handshakes of the LDAPS connections.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TlsHandshakeStats {
+    pub count: u64,
+    pub failures: u64,
+    pub total_duration: Duration,
+    pub max_duration: Duration,
+}
+
+/// Durations and entry counts of the LDAP operations since the server started, per type of
+/// operation, and durations of the LDAPS handshakes.
+#[derive(Debug, Default)]
+pub struct LdapMetrics {
+    slow_operation_threshold: Option<Duration>,
+    operations: Mutex<BTreeMap<&'static str, LdapOperationStats>>,
+    tls_handshakes: Mutex<TlsHandshakeStats>,
+}
+
+impl LdapMetrics {
+    pub fn new(slow_operation_threshold: Option<Duration>) -> Self {
+        Self {
+            slow_operation_threshold,
+            operations: Mutex::default(),
+            tls_handshakes: Mutex::default(),
+        }
+    }
+
+    pub fn logs_slow_operations(&self) -> bool {
+        self.slow_operation_threshold.is_some()
+    }
+
+    /// Adds the operation to the statistics, and returns whether it was slow.
+    pub fn record(&self, operation: &'static str, duration: Duration, entries: usize) -> bool {
+        let slow = self
+            .slow_operation_threshold
+            .is_some_and(|threshold| duration >= threshold);
+        let mut operations = self.operations.lock().unwrap();
+        let stats = operations.entry(operation).or_default();
+        stats.count += 1;
+        stats.slow_count += u64::from(slow);
+        stats.entries += entries as u64;
+        stats.total_duration += duration;
+        stats.max_duration = stats.max_duration.max(duration);
+        slow
+    }
+
+    /// The statistics of each type of operation, sorted by name.
+    pub fn snapshot(&self) -> Vec<(&'static str, LdapOperationStats)> {
+        self.operations
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, stats)| (*name, stats.clone()))
+            .collect()
+    }
+
+    pub fn record_tls_handshake(&self, duration: Duration, success: bool) {
+        let mut stats = self.tls_handshakes.lock().unwrap();
+        stats.count += 1;
+        stats.failures += u64::from(!success);
+        stats.total_duration += duration;
+        stats.max_duration = stats.max_duration.max(duration);
+    }
+
+    pub fn tls_handshakes(&self) -> TlsHandshakeStats {
+        self.tls_handshakes.lock().unwrap().clone()
+    }
+}
+
+pub fn operation_name(op: &LdapOp) -> &'static str {
+    match op {
+        LdapOp::BindRequest(_) => "bind",
+        LdapOp::SearchRequest(_) => "search",
+        LdapOp::ModifyRequest(_) => "modify",
+        LdapOp::AddRequest(_) => "add",
+        LdapOp::DelRequest(_) => "delete",
+        LdapOp::CompareRequest(_) => "compare",
+        LdapOp::ExtendedRequest(_) => "extended",
+        LdapOp::UnbindRequest => "unbind",
+        _ => "other",
+    }
+}
+
+/// Escapes the characters that have a meaning in a filter value, as described in RFC 4515.
+fn escape_filter_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '*' | '(' | ')' | '\\' | '\0') {
+            escaped.push_str(&format!("\\{:02x}", c as u8));
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// The filter in the LDAP string representation, e.g. `(&(objectClass=person)(uid=b*))`.
+pub fn format_filter(filter: &LdapFilter) -> String {
+    let join = |filters: &[LdapFilter]| filters.iter().map(format_filter).collect::<String>();
+    let escape = |value: Option<&str>| value.map(escape_filter_value).unwrap_or_default();
+    match filter {
+        LdapFilter::And(filters) => format!("(&{})", join(filters)),
+        LdapFilter::Or(filters) => format!("(|{})", join(filters)),
+        LdapFilter::Not(filter) => format!("(!{})", format_filter(filter)),
+        LdapFilter::Equality(field, value) => {
+            format!("({}={})", field, escape_filter_value(value))
+        }
+        LdapFilter::Approx(field, value) => format!("({}~={})", field, escape_filter_value(value)),
+        LdapFilter::GreaterOrEqual(field, value) => {
+            format!("({}>={})", field, escape_filter_value(value))
+        }
+        LdapFilter::Present(field) => format!("({}=*)", field),
+        LdapFilter::Substring(field, substring) => format!(
+            "({}={}*{}{})",
+            field,
+            escape(substring.initial.as_deref()),
+            substring
+                .any
+                .iter()
+                .map(|s| format!("{}*", escape_filter_value(s)))
+                .collect::<String>(),
+            escape(substring.final_.as_deref())
+        ),
+        filter => format!("{:?}", filter),
+    }
+}
+
+/// The details of the operation worth logging when it is slow: its target and, for searches,
+/// the scope and filter.
+pub fn describe_operation(op: &LdapOp) -> String {
+    match op {
+        LdapOp::BindRequest(request) => format!(r#"dn="{}""#, request.dn),
+        LdapOp::SearchRequest(request) => format!(
+            r#"base="{}" scope={:?} filter={} attributes={:?}"#,
+            request.base,
+            request.scope,
+            format_filter(&request.filter),
+            request.attrs
+        ),
+        LdapOp::ModifyRequest(request) => format!(r#"dn="{}""#, request.dn),
+        LdapOp::AddRequest(request) => format!(r#"dn="{}""#, request.dn),
+        LdapOp::DelRequest(dn) => format!(r#"dn="{}""#, dn),
+        LdapOp::CompareRequest(request) => format!(r#"dn="{}""#, request.dn),
+        LdapOp::ExtendedRequest(request) => format!("name={}", request.name),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ldap3_proto::LdapSubstringFilter;
+
+    #[test]
+    fn test_record() {
+        let metrics = LdapMetrics::new(Some(Duration::from_millis(100)));
+        assert!(!metrics.record("search", Duration::from_millis(30), 2));
+        assert!(metrics.record("search", Duration::from_millis(500), 40));
+        assert!(!metrics.record("bind", Duration::from_millis(10), 0));
+        assert_eq!(
+            metrics.snapshot(),
+            vec![
+                (
+                    "bind",
+                    LdapOperationStats {
+                        count: 1,
+                        slow_count: 0,
+                        entries: 0,
+                        total_duration: Duration::from_millis(10),
+                        max_duration: Duration::from_millis(10),
+                    }
+                ),
+                (
+                    "search",
+                    LdapOperationStats {
+                        count: 2,
+                        slow_count: 1,
+                        entries: 42,
+                        total_duration: Duration::from_millis(530),
+                        max_duration: Duration::from_millis(500),
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_tls_handshake() {
+        let metrics = LdapMetrics::default();
+        metrics.record_tls_handshake(Duration::from_millis(20), true);
+        metrics.record_tls_handshake(Duration::from_millis(5), false);
+        assert_eq!(
+            metrics.tls_handshakes(),
+            TlsHandshakeStats {
+                count: 2,
+                failures: 1,
+                total_duration: Duration::from_millis(25),
+                max_duration: Duration::from_millis(20),
+            }
+        );
+    }
+
+    #[test]
+    fn test_format_filter() {
+        let filter = LdapFilter::And(vec![
+            LdapFilter::Equality("objectClass".to_owned(), "person".to_owned()),
+            LdapFilter::Not(Box::new(LdapFilter::Present("mail".to_owned()))),
+            LdapFilter::Or(vec![LdapFilter::Substring(
+                "cn".to_owned(),
+                LdapSubstringFilter {
+                    initial: Some("b".to_owned()),
+                    any: vec!["o".to_owned()],
+                    final_: None,
+                },
+            )]),
+        ]);
+        assert_eq!(
+            format_filter(&filter),
+            "(&(objectClass=person)(!(mail=*))(|(cn=b*o*)))"
+        );
+    }
+}
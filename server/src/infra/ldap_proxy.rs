@@ -0,0 +1,355 @@
+use crate::{
+    domain::{
+        handler::{
+            CreateUserRequest, UpdateUserRequest, UserBackendHandler, UserCreationSource,
+            UserListerBackendHandler,
+        },
+        sql_tables::DbConnection,
+        types::{User, UserId},
+    },
+    infra::{
+        configuration::LdapProxyOptions,
+        ldap_client::LdapClient,
+        leader_election::LeaderElection,
+        notifications::{AlertKind, Notifier},
+    },
+};
+use actix::prelude::{Actor, AsyncContext, Context};
+use anyhow::{bail, Result};
+use ldap3_proto::proto::{LdapFilter, LdapSearchResultEntry};
+use std::{collections::HashMap, time::Duration};
+use tracing::{debug, error, info, instrument, warn};
+
+// Name of the lease in the leader election.
+const JOB_NAME: &str = "ldap_proxy_sync";
+// Extra time on top of the interval between runs before the lease expires.
+const LEASE_MARGIN: Duration = Duration::from_secs(60);
+
+/// Opens a connection bound as the service account.
+async fn connect_as_service_account(options: &LdapProxyOptions) -> Result<LdapClient> {
+    let mut connection = LdapClient::connect(&options.server).await?;
+    if !connection
+        .bind(&options.bind_dn, options.bind_password.unsecure())
+        .await?
+    {
+        bail!("Invalid credentials for the upstream service account");
+    }
+    Ok(connection)
+}
+
+fn user_filter(options: &LdapProxyOptions) -> LdapFilter {
+    LdapFilter::Equality("objectClass".to_string(), options.user_object_class.clone())
+}
+
+/// Checks the password of a user against the upstream server.
+#[instrument(skip_all, level = "debug", err, fields(user = %user_id))]
+pub async fn check_upstream_password(
+    options: &LdapProxyOptions,
+    user_id: &UserId,
+    password: &str,
+) -> Result<bool> {
+    // Simple binds with an empty password are anonymous binds, which always succeed.
+    if password.is_empty() {
+        return Ok(false);
+    }
+    let mut connection = connect_as_service_account(options).await?;
+    let entries = connection
+        .search(
+            &options.user_base_dn,
+            LdapFilter::And(vec![
+                user_filter(options),
+                LdapFilter::Equality(options.user_id_attribute.clone(), user_id.to_string()),
+            ]),
+            &["1.1"],
+        )
+        .await?;
+    match entries.as_slice() {
+        [] => Ok(false),
+        [entry] => connection.bind(&entry.dn, password).await,
+        _ => bail!("Several upstream entries match the user"),
+    }
+}
+
+/// A user, as read from the upstream server.
+#[derive(Debug, PartialEq, Eq)]
+struct UpstreamUser {
+    user_id: UserId,
+    email: String,
+    display_name: Option<String>,
+    first_name: Option<String>,
+    last_name: Option<String>,
+}
+
+impl UpstreamUser {
+    /// Returns `None` for entries without a user ID or an email.
+    fn from_entry(entry: LdapSearchResultEntry, user_id_attribute: &str) -> Option<Self> {
+        let mut values = HashMap::new();
+        for attribute in entry.attributes {
+            if let Some(value) = attribute.vals.into_iter().next() {
+                if let Ok(value) = String::from_utf8(value) {
+                    values.insert(attribute.atype.to_ascii_lowercase(), value);
+                }
+            }
+        }
+        Some(Self {
+            user_id: UserId::new(&values.remove(&user_id_attribute.to_ascii_lowercase())?),
+            email: values.remove("mail")?,
+            display_name: values.remove("displayname").or_else(|| values.remove("cn")),
+            first_name: values.remove("givenname"),
+            last_name: values.remove("sn"),
+        })
+    }
+
+    /// The changes to bring the cached user up to date, if any.
+    fn get_update(&self, cached: &User) -> Option<UpdateUserRequest> {
+        let get_attribute = |name: &str| {
+            cached
+                .attributes
+                .iter()
+                .find(|a| a.name.as_str() == name)
+                .map(|a| a.value.unwrap::<String>())
+        };
+        if cached.email.as_str() == self.email
+            && cached.display_name == self.display_name
+            && get_attribute("first_name") == self.first_name
+            && get_attribute("last_name") == self.last_name
+        {
+            return None;
+        }
+        Some(UpdateUserRequest {
+            user_id: self.user_id.clone(),
+            email: Some(self.email.clone().into()),
+            display_name: Some(self.display_name.clone().unwrap_or_default()),
+            first_name: Some(self.first_name.clone().unwrap_or_default()),
+            last_name: Some(self.last_name.clone().unwrap_or_default()),
+            ..Default::default()
+        })
+    }
+}
+
+/// Periodically copies the users of the upstream LDAP server to the local database, which serves
+/// all the reads.
+pub struct LdapProxySync<Backend> {
+    options: LdapProxyOptions,
+    backend_handler: Backend,
+    leader_election: LeaderElection,
+    notifier: Notifier,
+}
+
+impl<Backend> Actor for LdapProxySync<Backend>
+where
+    Backend: UserBackendHandler + UserListerBackendHandler + Clone + Send + Sync + Unpin + 'static,
+{
+    type Context = Context<Self>;
+
+    fn started(&mut self, context: &mut Context<Self>) {
+        info!("Upstream LDAP sync started, from {}", &self.options.server);
+        self.schedule_task(context);
+        context.run_interval(self.interval(), |this, ctx| this.schedule_task(ctx));
+    }
+}
+
+impl<Backend> LdapProxySync<Backend>
+where
+    Backend: UserBackendHandler + UserListerBackendHandler + Clone + Send + Sync + Unpin + 'static,
+{
+    pub fn new(
+        options: LdapProxyOptions,
+        sql_pool: DbConnection,
+        backend_handler: Backend,
+        notifier: Notifier,
+    ) -> Self {
+        Self {
+            options,
+            backend_handler,
+            leader_election: LeaderElection::new(sql_pool),
+            notifier,
+        }
+    }
+
+    fn schedule_task(&self, ctx: &mut Context<Self>) {
+        let future = actix::fut::wrap_future::<_, Self>(Self::sync_users_if_leader(
+            self.options.clone(),
+            self.backend_handler.clone(),
+            self.leader_election.clone(),
+            self.notifier.clone(),
+            self.interval() + LEASE_MARGIN,
+        ));
+        ctx.spawn(future);
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs(self.options.sync_interval_minutes.max(1) * 60)
+    }
+
+    // With several instances sharing the DB, only one of them syncs the users.
+    async fn sync_users_if_leader(
+        options: LdapProxyOptions,
+        backend_handler: Backend,
+        leader_election: LeaderElection,
+        notifier: Notifier,
+        lease: Duration,
+    ) {
+        match leader_election.try_acquire(JOB_NAME, lease).await {
+            Ok(true) => {
+                if let Err(e) = Self::sync_users(&options, &backend_handler).await {
+                    error!("Error while syncing the upstream LDAP users: {:#}", e);
+                    notifier
+                        .alert(
+                            AlertKind::SyncFailure,
+                            format!("Could not sync the users from {}: {:#}", options.server, e),
+                        )
+                        .await;
+                }
+            }
+            Ok(false) => debug!("Another instance is running the upstream LDAP sync"),
+            Err(e) => error!(
+                "DB error while acquiring the upstream LDAP sync lease: {}",
+                e
+            ),
+        }
+    }
+
+    #[instrument(skip_all)]
+    async fn sync_users(options: &LdapProxyOptions, backend_handler: &Backend) -> Result<()> {
+        let upstream_users = connect_as_service_account(options)
+            .await?
+            .search(
+                &options.user_base_dn,
+                user_filter(options),
+                &[
+                    options.user_id_attribute.as_str(),
+                    "mail",
+                    "displayName",
+                    "cn",
+                    "givenName",
+                    "sn",
+                ],
+            )
+            .await?
+            .into_iter()
+            .filter_map(|entry| {
+                let dn = entry.dn.clone();
+                let user = UpstreamUser::from_entry(entry, &options.user_id_attribute);
+                if user.is_none() {
+                    warn!(r#"Skipping upstream entry "{}" without ID or email"#, dn);
+                }
+                user
+            });
+        let cached_users = backend_handler
+            .list_users(None, false)
+            .await?
+            .into_iter()
+            .map(|u| (u.user.user_id.clone(), u.user))
+            .collect::<HashMap<_, _>>();
+        for upstream_user in upstream_users {
+            let result = match cached_users.get(&upstream_user.user_id) {
+                Some(cached) => match upstream_user.get_update(cached) {
+                    Some(request) => backend_handler.update_user(request).await,
+                    None => Ok(()),
+                },
+                None => {
+                    backend_handler
+                        .create_user(CreateUserRequest {
+                            user_id: upstream_user.user_id.clone(),
+                            email: upstream_user.email.clone().into(),
+                            display_name: upstream_user.display_name.clone(),
+                            first_name: upstream_user.first_name.clone(),
+                            last_name: upstream_user.last_name.clone(),
+                            source: UserCreationSource::LdapSync,
+                            ..Default::default()
+                        })
+                        .await
+                }
+            };
+            if let Err(e) = result {
+                error!(
+                    r#"DB error while caching the upstream user "{}": {}"#,
+                    &upstream_user.user_id, e
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::{AttributeValue, Serialized};
+    use ldap3_proto::proto::LdapPartialAttribute;
+    use pretty_assertions::assert_eq;
+
+    fn make_entry(attributes: &[(&str, &str)]) -> LdapSearchResultEntry {
+        LdapSearchResultEntry {
+            dn: "cn=bob,ou=users,dc=upstream".to_string(),
+            attributes: attributes
+                .iter()
+                .map(|(atype, value)| LdapPartialAttribute {
+                    atype: atype.to_string(),
+                    vals: vec![value.as_bytes().to_vec()],
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_upstream_user_from_entry() {
+        assert_eq!(
+            UpstreamUser::from_entry(
+                make_entry(&[
+                    ("sAMAccountName", "bob"),
+                    ("mail", "bob@example.com"),
+                    ("cn", "Bob B"),
+                    ("givenName", "Bob"),
+                ]),
+                "samaccountname"
+            ),
+            Some(UpstreamUser {
+                user_id: UserId::new("bob"),
+                email: "bob@example.com".to_string(),
+                display_name: Some("Bob B".to_string()),
+                first_name: Some("Bob".to_string()),
+                last_name: None,
+            })
+        );
+        assert_eq!(
+            UpstreamUser::from_entry(make_entry(&[("uid", "bob")]), "uid"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_upstream_user_get_update() {
+        let upstream = UpstreamUser {
+            user_id: UserId::new("bob"),
+            email: "bob@example.com".to_string(),
+            display_name: Some("Bob B".to_string()),
+            first_name: Some("Bob".to_string()),
+            last_name: None,
+        };
+        let mut cached = User {
+            user_id: UserId::new("bob"),
+            email: "bob@example.com".into(),
+            display_name: Some("Bob B".to_string()),
+            attributes: vec![AttributeValue {
+                name: "first_name".into(),
+                value: Serialized::from("Bob"),
+            }],
+            ..Default::default()
+        };
+        assert_eq!(upstream.get_update(&cached), None);
+        cached.email = "old@example.com".into();
+        assert_eq!(
+            upstream.get_update(&cached),
+            Some(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                email: Some("bob@example.com".into()),
+                display_name: Some("Bob B".to_string()),
+                first_name: Some("Bob".to_string()),
+                last_name: Some(String::new()),
+                ..Default::default()
+            })
+        );
+    }
+}
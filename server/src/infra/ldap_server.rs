@@ -1,38 +1,133 @@
 use crate::{
     domain::{
         handler::{BackendHandler, LoginHandler},
+        ldap::{static_entry::StaticEntry, utils::parse_distinguished_name},
         opaque_handler::OpaqueHandler,
-        types::AttributeName,
+        types::UserId,
     },
     infra::{
         access_control::AccessControlledBackendHandler,
-        configuration::{Configuration, LdapsOptions},
-        ldap_handler::LdapHandler,
+        backend::ServerBackendHandler,
+        configuration::{Configuration, LdapiOptions, LdapsOptions},
+        ldap_bind_limiter::LdapBindLimiter,
+        ldap_handler::{
+            ConnectionSecurity, LdapHandler, LdapResponses, LdapSessionSettings, SearchLimits,
+        },
+        ldap_metrics::{describe_operation, operation_name, LdapMetrics},
+        maintenance::MaintenanceMode,
+        read_only::ReadOnlyMode,
     },
 };
 use actix_rt::net::TcpStream;
 use actix_server::ServerBuilder;
 use actix_service::{fn_service, ServiceFactoryExt};
-use anyhow::{anyhow, Context, Result};
-use ldap3_proto::{control::LdapControl, proto::LdapMsg, proto::LdapOp, LdapCodec};
+use anyhow::{anyhow, bail, Context, Result};
+use ldap3_proto::{
+    control::LdapControl,
+    proto::{LdapMsg, LdapOp, LdapResultCode},
+    LdapCodec,
+};
 use rustls::PrivateKey;
+use std::{net::IpAddr, sync::Arc, time::Instant};
+use tokio::sync::{Semaphore, SemaphorePermit};
 use tokio_rustls::TlsAcceptor as RustlsTlsAcceptor;
 use tokio_util::codec::{FramedRead, FramedWrite};
-use tracing::{debug, error, info, instrument};
+use tracing::{debug, error, info, instrument, warn};
 use uuid::Uuid;
 
+// How many entries of a search result are sent before giving other connections a chance to run.
+const RESPONSES_BETWEEN_YIELDS: usize = 64;
+
+type ResponseWithControls = (LdapOp, Vec<LdapControl>);
+
+/// Everything that the sessions of all the listeners share.
+struct LdapServerContext<Backend> {
+    backend_handler: Backend,
+    settings: LdapSessionSettings,
+    /// Bounds the number of operations processed at the same time, over all the connections.
+    operation_limiter: Semaphore,
+    metrics: Arc<LdapMetrics>,
+}
+
+/// Attaches the controls to the responses of an operation: the paged searches return their own
+/// control with the final response, with the cookie of the next page.
+fn attach_search_controls(
+    results: LdapResponses,
+    mut response_control: Option<LdapControl>,
+) -> impl Iterator<Item = ResponseWithControls> + Send {
+    let mut entries = 0;
+    results.map(move |response| {
+        let controls = match response {
+            LdapOp::SearchResultDone(_) => {
+                vec![response_control
+                    .take()
+                    .unwrap_or(LdapControl::SimplePagedResults {
+                        size: entries,
+                        cookie: vec![],
+                    })]
+            }
+            LdapOp::SearchResultEntry(_) => {
+                entries += 1;
+                vec![]
+            }
+            _ => vec![],
+        };
+        (response, controls)
+    })
+}
+
+/// Sends the responses to the message, each with its controls. Returns the number of entries.
+async fn send_responses<Writer>(
+    resp: &mut Writer,
+    msgid: i32,
+    responses: impl Iterator<Item = ResponseWithControls>,
+) -> Result<usize>
+where
+    Writer: futures_util::Sink<LdapMsg> + Unpin,
+    <Writer as futures_util::Sink<LdapMsg>>::Error: std::error::Error + Send + Sync + 'static,
+{
+    use futures_util::SinkExt;
+    let mut responses = responses.peekable();
+    if responses.peek().is_none() {
+        debug!("No response");
+    }
+    let mut entries = 0;
+    for (index, (response, controls)) in responses.enumerate() {
+        if index % RESPONSES_BETWEEN_YIELDS == RESPONSES_BETWEEN_YIELDS - 1 {
+            // Let the other connections on this worker make progress during a large search.
+            tokio::task::yield_now().await;
+        }
+        debug!(?response);
+        if matches!(response, LdapOp::SearchResultEntry(_)) {
+            entries += 1;
+        }
+        resp.send(LdapMsg {
+            msgid,
+            op: response,
+            ctrl: controls,
+        })
+        .await
+        .context("while sending a response: {:#}")?
+    }
+    resp.flush()
+        .await
+        .context("while flushing responses: {:#}")?;
+    Ok(entries)
+}
+
 #[instrument(skip_all, level = "info", name = "LDAP request", fields(session_id = %session.session_uuid()))]
 async fn handle_ldap_message<Backend, Writer>(
     msg: Result<LdapMsg, std::io::Error>,
     resp: &mut Writer,
     session: &mut LdapHandler<Backend>,
+    operation_slot: SemaphorePermit<'_>,
+    metrics: &LdapMetrics,
 ) -> Result<bool>
 where
     Backend: BackendHandler + LoginHandler + OpaqueHandler,
     Writer: futures_util::Sink<LdapMsg> + Unpin,
     <Writer as futures_util::Sink<LdapMsg>>::Error: std::error::Error + Send + Sync + 'static,
 {
-    use futures_util::SinkExt;
     let msg = msg.context("while receiving LDAP op")?;
     for control in msg.ctrl.iter() {
         if let LdapControl::Unknown { oid, .. } = control {
@@ -40,47 +135,62 @@ where
         }
     }
     debug!(?msg);
-    match session.handle_ldap_message(msg.op).await {
-        None => return Ok(false),
-        Some(result) => {
-            if result.is_empty() {
-                debug!("No response");
-            }
-            let results: i64 = result.len().try_into().unwrap();
-            for response in result.into_iter() {
-                debug!(?response);
-                let controls = if matches!(response, LdapOp::SearchResultDone(_)) {
-                    vec![LdapControl::SimplePagedResults {
-                        size: results - 1, // Avoid counting SearchResultDone as a result
-                        cookie: vec![],
-                    }]
-                } else {
-                    vec![]
-                };
-                resp.send(LdapMsg {
-                    msgid: msg.msgid,
-                    op: response,
-                    ctrl: controls,
-                })
-                .await
-                .context("while sending a response: {:#}")?
-            }
-
-            resp.flush()
+    let operation = operation_name(&msg.op);
+    // Captured before the operation is consumed, in case it turns out to be slow.
+    let slow_log_context = metrics
+        .logs_slow_operations()
+        .then(|| (describe_operation(&msg.op), session.bound_dn()));
+    let start = Instant::now();
+    let sync_request = msg.ctrl.iter().find_map(|control| match control {
+        LdapControl::SyncRequest { mode, cookie, .. } => Some((mode.clone(), cookie.clone())),
+        _ => None,
+    });
+    let responses: Option<Box<dyn Iterator<Item = ResponseWithControls> + Send>> =
+        match (msg.op, sync_request) {
+            (LdapOp::SearchRequest(request), Some((mode, cookie))) => Some(Box::new(
+                session
+                    .do_sync_search(msg.msgid, &request, &mode, cookie.as_deref())
+                    .await
+                    .into_iter(),
+            )),
+            (op, _) => session
+                .handle_ldap_message_with_controls(op, &msg.ctrl)
                 .await
-                .context("while flushing responses: {:#}")?
-        }
+                .map(|(results, response_control)| {
+                    Box::new(attach_search_controls(results, response_control)) as _
+                }),
+        };
+    // The backend is done: a slow client shouldn't hold the slot while its responses are sent.
+    drop(operation_slot);
+    // The entries of a search are built as they are sent, so the duration includes the sending.
+    let (keep_going, entries) = match responses {
+        None => (false, 0),
+        Some(responses) => (true, send_responses(resp, msg.msgid, responses).await?),
+    };
+    let duration = start.elapsed();
+    debug!(operation, ?duration, entries);
+    if metrics.record(operation, duration, entries) {
+        let (details, bound_dn) = slow_log_context.unwrap_or_default();
+        warn!(
+            "Slow LDAP {} operation: {:?}, {} entries, bound as {}: {}",
+            operation,
+            duration,
+            entries,
+            bound_dn.as_deref().unwrap_or("<not bound>"),
+            details
+        );
     }
-    Ok(true)
+    Ok(keep_going)
 }
 
-async fn handle_ldap_stream<Stream, Backend>(
+/// Serves the requests of a session until the client disconnects, or until it asks for StartTLS.
+/// Returns the stream, and whether it has to be upgraded to TLS.
+async fn serve_ldap_requests<Stream, Backend>(
     stream: Stream,
-    backend_handler: Backend,
-    ldap_base_dn: String,
-    ignored_user_attributes: Vec<AttributeName>,
-    ignored_group_attributes: Vec<AttributeName>,
-) -> Result<Stream>
+    session: &mut LdapHandler<Backend>,
+    operation_limiter: &Semaphore,
+    metrics: &LdapMetrics,
+) -> Result<(Stream, bool)>
 where
     Backend: BackendHandler + LoginHandler + OpaqueHandler + 'static,
     Stream: tokio::io::AsyncRead + tokio::io::AsyncWrite + std::marker::Unpin,
@@ -91,26 +201,110 @@ where
     let mut requests = FramedRead::new(r, LdapCodec::default());
     let mut resp = FramedWrite::new(w, LdapCodec::default());
 
+    let mut start_tls = false;
+    // Operations of a single connection are processed in order, one at a time, while the
+    // connections compete for a bounded number of slots, held until the responses are ready. The
+    // updates of a persistent synchronization are sent in between.
+    loop {
+        let msg = tokio::select! {
+            msg = requests.next() => msg,
+            () = session.wait_for_sync_changes() => {
+                let operation_slot = operation_limiter
+                    .acquire()
+                    .await
+                    .context("while waiting for an operation slot")?;
+                let updates = session.get_sync_updates().await;
+                drop(operation_slot);
+                if let Some((msgid, updates)) = updates {
+                    send_responses(&mut resp, msgid, updates.into_iter())
+                        .await
+                        .context("while sending synchronization updates")?;
+                }
+                continue;
+            }
+        };
+        let msg = match msg {
+            Some(msg) => msg,
+            None => break,
+        };
+        let operation_slot = operation_limiter
+            .acquire()
+            .await
+            .context("while waiting for an operation slot")?;
+        if !handle_ldap_message(msg, &mut resp, session, operation_slot, metrics)
+            .await
+            .context("while handling incoming messages")?
+        {
+            break;
+        }
+        if session.take_start_tls_request() {
+            start_tls = true;
+            break;
+        }
+    }
+    Ok((requests.into_inner().unsplit(resp.into_inner()), start_tls))
+}
+
+async fn handle_ldap_stream<Stream, Backend>(
+    stream: Stream,
+    context: &LdapServerContext<Backend>,
+    client_ip: Option<IpAddr>,
+    connection_security: ConnectionSecurity,
+    start_tls_acceptor: Option<RustlsTlsAcceptor>,
+    peer_user: Option<UserId>,
+) -> Result<()>
+where
+    Backend: BackendHandler + LoginHandler + OpaqueHandler + Clone + 'static,
+    Stream: tokio::io::AsyncRead + tokio::io::AsyncWrite + std::marker::Unpin,
+{
     let session_uuid = Uuid::new_v4();
     let mut session = LdapHandler::new(
-        AccessControlledBackendHandler::new(backend_handler),
-        ldap_base_dn,
-        ignored_user_attributes,
-        ignored_group_attributes,
+        AccessControlledBackendHandler::new(context.backend_handler.clone()),
+        &context.settings,
         session_uuid,
+        connection_security,
+        client_ip,
     );
 
     info!("LDAP session start: {}", session_uuid);
-    while let Some(msg) = requests.next().await {
-        if !handle_ldap_message(msg, &mut resp, &mut session)
-            .await
-            .context("while handling incoming messages")?
-        {
-            break;
+    if let Some(user_id) = peer_user {
+        let (code, message) = session.bind_authenticated_user(user_id.clone()).await;
+        if code != LdapResultCode::Success {
+            warn!(
+                "Could not bind the local connection as {}: {}",
+                user_id, message
+            );
+        } else if let Some(dn) = session.bound_dn() {
+            info!("Bound the local connection as {}", dn);
         }
     }
+    let (stream, start_tls) = serve_ldap_requests(
+        stream,
+        &mut session,
+        &context.operation_limiter,
+        &context.metrics,
+    )
+    .await?;
+    // The session only accepts StartTLS when it was given an acceptor.
+    if let (true, Some(tls_acceptor)) = (start_tls, start_tls_acceptor) {
+        let start = Instant::now();
+        let tls_stream = tls_acceptor.accept(stream).await;
+        context
+            .metrics
+            .record_tls_handshake(start.elapsed(), tls_stream.is_ok());
+        let tls_stream = tls_stream.context("during the StartTLS handshake")?;
+        debug!("StartTLS handshake done");
+        session.set_tls_established();
+        serve_ldap_requests(
+            tls_stream,
+            &mut session,
+            &context.operation_limiter,
+            &context.metrics,
+        )
+        .await?;
+    }
     info!("LDAP session end: {}", session_uuid);
-    Ok(requests.into_inner().unsplit(resp.into_inner()))
+    Ok(())
 }
 
 fn read_private_key(key_file: &str) -> Result<PrivateKey> {
@@ -160,44 +354,111 @@ pub fn read_certificates(
 
 fn get_tls_acceptor(ldaps_options: &LdapsOptions) -> Result<RustlsTlsAcceptor> {
     let (certs, private_key) = read_certificates(ldaps_options)?;
-    let server_config = std::sync::Arc::new(
-        rustls::ServerConfig::builder()
-            .with_safe_defaults()
-            .with_no_client_auth()
-            .with_single_cert(certs, private_key)?,
-    );
-    Ok(server_config.into())
+    let mut server_config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, private_key)?;
+    // Clients that reconnect for every query can resume their previous session instead of paying
+    // for a full handshake: with a session ID (TLS 1.2) or with a ticket.
+    if ldaps_options.session_cache_size == 0 {
+        server_config.session_storage = Arc::new(rustls::server::NoServerSessionStorage {});
+    } else {
+        server_config.session_storage =
+            rustls::server::ServerSessionMemoryCache::new(ldaps_options.session_cache_size);
+    }
+    if ldaps_options.session_tickets {
+        server_config.ticketer = rustls::Ticketer::new()
+            .map_err(|_| anyhow!("Could not generate the TLS session ticket keys"))?;
+    }
+    Ok(Arc::new(server_config).into())
 }
 
 pub fn build_ldap_server<Backend>(
     config: &Configuration,
     backend_handler: Backend,
+    metrics: Arc<LdapMetrics>,
+    maintenance: Arc<MaintenanceMode>,
+    read_only: Arc<ReadOnlyMode>,
     server_builder: ServerBuilder,
 ) -> Result<ServerBuilder>
 where
-    Backend: BackendHandler + LoginHandler + OpaqueHandler + Clone + 'static,
+    Backend: ServerBackendHandler,
 {
-    let context = (
+    config.integration_profiles.validate()?;
+    let base_dn = parse_distinguished_name(&config.ldap_base_dn)
+        .map_err(|e| anyhow!("Invalid ldap_base_dn: {}", e.message))?;
+    let static_entries = config
+        .static_ldap_entries
+        .iter()
+        .map(|entry| StaticEntry::new(entry, &base_dn).map_err(|e| anyhow!("{}", e.message)))
+        .collect::<Result<Vec<_>>>()?;
+    let bind_limiter = Arc::new(LdapBindLimiter::new(
+        &config.login_throttling_options,
+        Arc::new(backend_handler.clone()),
+    ));
+    let context = Arc::new(LdapServerContext {
         backend_handler,
-        config.ldap_base_dn.clone(),
-        config.ignored_user_attributes.clone(),
-        config.ignored_group_attributes.clone(),
-    );
+        settings: LdapSessionSettings {
+            base_dn: config.ldap_base_dn.clone(),
+            ignored_user_attributes: config.ignored_user_attributes.clone(),
+            ignored_group_attributes: config.ignored_group_attributes.clone(),
+            posix_defaults: config.posix_defaults.clone(),
+            user_rdn: config.ldap_user_rdn,
+            integration_profiles: config.integration_profiles.clone(),
+            attribute_limits: config.ldap_attribute_limits.clone(),
+            attribute_mappings: config.ldap_attribute_mappings.clone(),
+            computed_user_attributes: config.computed_user_attributes.clone(),
+            static_entries,
+            active_directory_compatibility: config.ldap_active_directory_compatibility,
+            attribute_order: config.ldap_attribute_order,
+            filter_debug: config.ldap_filter_debug,
+            require_tls_for_bind: config.ldaps_options.require_tls_for_bind,
+            maintenance,
+            read_only,
+            bind_limiter,
+            search_limits: SearchLimits {
+                max_entries: config.ldap_search_size_limit,
+                max_time_seconds: config.ldap_search_time_limit_seconds,
+            },
+        },
+        operation_limiter: Semaphore::new(config.ldap_max_concurrent_operations.max(1)),
+        metrics,
+    });
+
+    let ldaps_options = &config.ldaps_options;
+    if ldaps_options.require_tls_for_bind && !ldaps_options.enabled && !ldaps_options.start_tls {
+        bail!("ldaps_options.require_tls_for_bind needs either LDAPS or StartTLS to be enabled");
+    }
+    let tls_acceptor = if ldaps_options.enabled || ldaps_options.start_tls {
+        Some(get_tls_acceptor(ldaps_options).context("while setting up the SSL certificate")?)
+    } else {
+        None
+    };
+    let start_tls_acceptor = tls_acceptor.clone().filter(|_| ldaps_options.start_tls);
 
     let context_for_tls = context.clone();
+    let context_for_ldapi = context.clone();
 
     let binder = move || {
         let context = context.clone();
+        let start_tls_acceptor = start_tls_acceptor.clone();
         fn_service(move |stream: TcpStream| {
             let context = context.clone();
+            let start_tls_acceptor = start_tls_acceptor.clone();
             async move {
-                let (handler, base_dn, ignored_user_attributes, ignored_group_attributes) = context;
+                let client_ip = stream.peer_addr().ok().map(|address| address.ip());
+                let connection_security = if start_tls_acceptor.is_some() {
+                    ConnectionSecurity::StartTlsAvailable
+                } else {
+                    ConnectionSecurity::Plaintext
+                };
                 handle_ldap_stream(
                     stream,
-                    handler,
-                    base_dn,
-                    ignored_user_attributes,
-                    ignored_group_attributes,
+                    &context,
+                    client_ip,
+                    connection_security,
+                    start_tls_acceptor,
+                    None,
                 )
                 .await
             }
@@ -209,48 +470,117 @@ where
     let server_builder = server_builder
         .bind("ldap", (config.ldap_host.clone(), config.ldap_port), binder)
         .with_context(|| format!("while binding to the port {}", config.ldap_port));
-    if config.ldaps_options.enabled {
-        let tls_context = (
-            context_for_tls,
-            get_tls_acceptor(&config.ldaps_options)
-                .context("while setting up the SSL certificate")?,
-        );
-        let tls_binder = move || {
-            let tls_context = tls_context.clone();
-            fn_service(move |stream: TcpStream| {
+    let server_builder =
+        if let (true, Some(tls_acceptor)) = (config.ldaps_options.enabled, tls_acceptor) {
+            let tls_context = (context_for_tls, tls_acceptor);
+            let tls_binder = move || {
                 let tls_context = tls_context.clone();
-                async move {
-                    let (
-                        (handler, base_dn, ignored_user_attributes, ignored_group_attributes),
-                        tls_acceptor,
-                    ) = tls_context;
-                    let tls_stream = tls_acceptor.accept(stream).await?;
-                    handle_ldap_stream(
-                        tls_stream,
-                        handler,
-                        base_dn,
-                        ignored_user_attributes,
-                        ignored_group_attributes,
-                    )
-                    .await
-                }
+                fn_service(move |stream: TcpStream| {
+                    let tls_context = tls_context.clone();
+                    async move {
+                        let (context, tls_acceptor) = tls_context;
+                        let client_ip = stream.peer_addr().ok().map(|address| address.ip());
+                        let start = Instant::now();
+                        let tls_stream = tls_acceptor.accept(stream).await;
+                        context
+                            .metrics
+                            .record_tls_handshake(start.elapsed(), tls_stream.is_ok());
+                        let tls_stream = tls_stream.context("during the TLS handshake")?;
+                        handle_ldap_stream(
+                            tls_stream,
+                            &context,
+                            client_ip,
+                            ConnectionSecurity::Tls,
+                            None,
+                            None,
+                        )
+                        .await
+                    }
+                })
+                .map_err(|err: anyhow::Error| error!("[LDAPS] Service Error: {:#}", err))
+            };
+
+            info!(
+                "Starting the LDAPS server on port {}",
+                config.ldaps_options.port
+            );
+            server_builder.and_then(|s| {
+                s.bind(
+                    "ldaps",
+                    (config.ldap_host.clone(), config.ldaps_options.port),
+                    tls_binder,
+                )
+                .with_context(|| format!("while binding to the port {}", config.ldaps_options.port))
             })
-            .map_err(|err: anyhow::Error| error!("[LDAPS] Service Error: {:#}", err))
+        } else {
+            server_builder
         };
-
-        info!(
-            "Starting the LDAPS server on port {}",
-            config.ldaps_options.port
-        );
-        server_builder.and_then(|s| {
-            s.bind(
-                "ldaps",
-                (config.ldap_host.clone(), config.ldaps_options.port),
-                tls_binder,
-            )
-            .with_context(|| format!("while binding to the port {}", config.ldaps_options.port))
-        })
+    if config.ldapi_options.enabled {
+        bind_ldapi(server_builder?, &config.ldapi_options, context_for_ldapi)
     } else {
         server_builder
     }
 }
+
+/// Serves LDAP on a Unix domain socket, for the clients running on the same host. The connections
+/// from the configured local accounts are bound without a password.
+#[cfg(unix)]
+fn bind_ldapi<Backend>(
+    server_builder: ServerBuilder,
+    ldapi_options: &LdapiOptions,
+    context: Arc<LdapServerContext<Backend>>,
+) -> Result<ServerBuilder>
+where
+    Backend: BackendHandler + LoginHandler + OpaqueHandler + Clone + 'static,
+{
+    use actix_rt::net::UnixStream;
+    use std::os::unix::fs::PermissionsExt;
+    let ldapi_context = (context, ldapi_options.clone());
+    let ldapi_binder = move || {
+        let ldapi_context = ldapi_context.clone();
+        fn_service(move |stream: UnixStream| {
+            let ldapi_context = ldapi_context.clone();
+            async move {
+                let (context, ldapi_options) = ldapi_context;
+                let peer_uid = stream
+                    .peer_cred()
+                    .context("while reading the credentials of the local client")?
+                    .uid();
+                debug!(peer_uid, "Local LDAP connection");
+                let peer_user = ldapi_options.get_peer_user(peer_uid).cloned();
+                handle_ldap_stream(
+                    stream,
+                    &context,
+                    None,
+                    ConnectionSecurity::LocalSocket,
+                    None,
+                    peer_user,
+                )
+                .await
+            }
+        })
+        .map_err(|err: anyhow::Error| error!("[LDAPI] Service Error: {:#}", err))
+    };
+
+    let socket_path = &ldapi_options.socket_path;
+    info!("Starting the LDAPI server on {}", socket_path);
+    // A socket left behind by a previous run is replaced.
+    let server_builder = server_builder
+        .bind_uds("ldapi", socket_path, ldapi_binder)
+        .with_context(|| format!("while binding to the socket {}", socket_path))?;
+    std::fs::set_permissions(
+        socket_path,
+        std::fs::Permissions::from_mode(ldapi_options.socket_mode),
+    )
+    .with_context(|| format!("while setting the permissions of {}", socket_path))?;
+    Ok(server_builder)
+}
+
+#[cfg(not(unix))]
+fn bind_ldapi<Backend>(
+    _: ServerBuilder,
+    _: &LdapiOptions,
+    _: Arc<LdapServerContext<Backend>>,
+) -> Result<ServerBuilder> {
+    bail!("The LDAPI listener is only available on Unix")
+}
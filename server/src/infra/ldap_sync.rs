@@ -0,0 +1,384 @@
+use crate::domain::{
+    change_events::{ChangeEvent, ChangeFeedEntry},
+    types::{GroupId, UserId},
+};
+use ldap3_proto::{
+    control::LdapControl,
+    proto::{
+        LdapIntermediateResponse, LdapOp, LdapResult, LdapResultCode, LdapSearchResultEntry,
+        SyncRequestMode, SyncStateValue,
+    },
+};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// OID of the Sync Request control, from RFC 4533.
+pub const SYNC_REQUEST_OID: &str = "1.3.6.1.4.1.4203.1.9.1.1";
+
+// Past this many changes since the cookie of the client, the whole content is sent again.
+pub const MAX_INCREMENTAL_CHANGES: u64 = 1000;
+
+/// A response of a synchronization, with its controls.
+pub type SyncResponse = (LdapOp, Vec<LdapControl>);
+
+/// The user or group behind a synchronized entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncedEntryId {
+    User(UserId),
+    Group(GroupId),
+}
+
+/// An entry returned by a synchronized search. Only the users and groups are synchronized: the
+/// other entries don't have a UUID, and never change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncedEntry {
+    pub id: SyncedEntryId,
+    pub uuid: Uuid,
+    pub entry: LdapSearchResultEntry,
+}
+
+/// The cookie given to the clients is the sequence number of the last change they know of.
+pub fn make_cookie(sequence: i32) -> Vec<u8> {
+    sequence.to_string().into_bytes()
+}
+
+pub fn parse_cookie(cookie: &[u8]) -> Option<i32> {
+    std::str::from_utf8(cookie).ok()?.parse().ok()
+}
+
+/// The entries that may have changed since the cookie of the client, from the change feed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ChangedEntries {
+    all_users: bool,
+    all_groups: bool,
+    users: HashSet<UserId>,
+}
+
+impl ChangedEntries {
+    pub fn everything() -> Self {
+        Self {
+            all_users: true,
+            all_groups: true,
+            users: HashSet::new(),
+        }
+    }
+
+    /// `changes` are the first changes that follow the `since` sequence in the feed, at most
+    /// `limit`, and `last` the sequence of the last one. If the feed doesn't go back to `since`
+    /// anymore, or if there are too many changes, everything is considered changed.
+    pub fn from_feed(since: i32, last: i32, changes: &[ChangeFeedEntry], limit: u64) -> Self {
+        let is_complete = last >= since
+            && changes.len() as u64 <= limit
+            && changes.first().map_or(true, |c| c.sequence == since + 1);
+        if !is_complete {
+            return Self::everything();
+        }
+        let mut changed = Self::default();
+        for change in changes {
+            changed.add(&change.event);
+        }
+        changed
+    }
+
+    fn add(&mut self, event: &ChangeEvent) {
+        match event {
+            _ if !event.affects_searches() => {}
+            ChangeEvent::UserCreated(user_id) | ChangeEvent::UserUpdated(user_id) => {
+                self.users.insert(user_id.clone());
+            }
+            // The user is no longer a member of the groups.
+            ChangeEvent::UserDeleted(_) => self.all_groups = true,
+            // With the nested groups, the member is also added to or removed from the groups that
+            // contain this one.
+            ChangeEvent::MembershipAdded { user_id, .. }
+            | ChangeEvent::MembershipRemoved { user_id, .. }
+            | ChangeEvent::MembershipAddedByRule { user_id, .. }
+            | ChangeEvent::MembershipRemovedByRule { user_id, .. } => {
+                self.users.insert(user_id.clone());
+                self.all_groups = true;
+            }
+            // A change of a group can change the `memberOf` of any user, through the nested
+            // groups. The schema changes affect every entry.
+            _ => *self = Self::everything(),
+        }
+    }
+
+    pub fn contains(&self, id: &SyncedEntryId) -> bool {
+        match id {
+            SyncedEntryId::User(user_id) => self.all_users || self.users.contains(user_id),
+            SyncedEntryId::Group(_) => self.all_groups,
+        }
+    }
+}
+
+fn entry_with_state(
+    entry: LdapSearchResultEntry,
+    uuid: Uuid,
+    state: SyncStateValue,
+    cookie: Option<Vec<u8>>,
+) -> SyncResponse {
+    (
+        LdapOp::SearchResultEntry(entry),
+        vec![LdapControl::SyncState {
+            state,
+            entry_uuid: uuid,
+            cookie,
+        }],
+    )
+}
+
+/// The responses of the refresh phase: the entries that changed since the cookie, then the UUIDs
+/// of the others, which the client keeps. The client deletes the entries that were not
+/// mentioned. The phase ends with the new cookie: with the end of the search in refreshOnly mode,
+/// or with an intermediate response before the updates in refreshAndPersist mode.
+pub fn refresh_responses(
+    entries: &[SyncedEntry],
+    changed: &ChangedEntries,
+    cookie: Vec<u8>,
+    mode: &SyncRequestMode,
+) -> Vec<SyncResponse> {
+    let mut responses = Vec::new();
+    let mut unchanged = Vec::new();
+    for entry in entries {
+        if changed.contains(&entry.id) {
+            responses.push(entry_with_state(
+                entry.entry.clone(),
+                entry.uuid,
+                SyncStateValue::Add,
+                None,
+            ));
+        } else {
+            unchanged.push(entry.uuid);
+        }
+    }
+    if !unchanged.is_empty() {
+        responses.push((
+            LdapOp::IntermediateResponse(LdapIntermediateResponse::SyncInfoIdSet {
+                cookie: None,
+                refresh_deletes: false,
+                syncuuids: unchanged,
+            }),
+            vec![],
+        ));
+    }
+    responses.push(match mode {
+        SyncRequestMode::RefreshOnly => (
+            LdapOp::SearchResultDone(LdapResult {
+                code: LdapResultCode::Success,
+                matcheddn: "".to_string(),
+                message: "".to_string(),
+                referral: vec![],
+            }),
+            vec![LdapControl::SyncDone {
+                cookie: Some(cookie),
+                refresh_deletes: false,
+            }],
+        ),
+        SyncRequestMode::RefreshAndPersist => (
+            LdapOp::IntermediateResponse(LdapIntermediateResponse::SyncInfoRefreshPresent {
+                cookie: Some(cookie),
+                done: true,
+            }),
+            vec![],
+        ),
+    });
+    responses
+}
+
+/// The updates of a persistent synchronization, from the entries that the client has to the
+/// current ones. The deleted entries are sent with their DN only.
+pub fn persist_responses(
+    known: &HashMap<Uuid, SyncedEntry>,
+    current: &[SyncedEntry],
+    cookie: &[u8],
+) -> Vec<SyncResponse> {
+    let mut responses = Vec::new();
+    for entry in current {
+        let state = match known.get(&entry.uuid) {
+            None => SyncStateValue::Add,
+            Some(known) if known.entry != entry.entry => SyncStateValue::Modify,
+            Some(_) => continue,
+        };
+        responses.push(entry_with_state(
+            entry.entry.clone(),
+            entry.uuid,
+            state,
+            Some(cookie.to_vec()),
+        ));
+    }
+    let current_uuids: HashSet<_> = current.iter().map(|e| e.uuid).collect();
+    let mut deleted: Vec<_> = known
+        .values()
+        .filter(|e| !current_uuids.contains(&e.uuid))
+        .collect();
+    deleted.sort_by(|e1, e2| e1.entry.dn.cmp(&e2.entry.dn));
+    for entry in deleted {
+        responses.push(entry_with_state(
+            LdapSearchResultEntry {
+                dn: entry.entry.dn.clone(),
+                attributes: vec![],
+            },
+            entry.uuid,
+            SyncStateValue::Delete,
+            Some(cookie.to_vec()),
+        ));
+    }
+    responses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ldap3_proto::proto::LdapPartialAttribute;
+    use pretty_assertions::assert_eq;
+
+    fn change(sequence: i32, event: ChangeEvent) -> ChangeFeedEntry {
+        ChangeFeedEntry {
+            sequence,
+            change_date: chrono::NaiveDateTime::default(),
+            event,
+        }
+    }
+
+    fn user_entry(name: &str, uuid: u128, mail: &str) -> SyncedEntry {
+        SyncedEntry {
+            id: SyncedEntryId::User(UserId::new(name)),
+            uuid: Uuid::from_u128(uuid),
+            entry: LdapSearchResultEntry {
+                dn: format!("uid={},ou=people,dc=example,dc=com", name),
+                attributes: vec![LdapPartialAttribute {
+                    atype: "mail".to_owned(),
+                    vals: vec![mail.as_bytes().to_vec()],
+                }],
+            },
+        }
+    }
+
+    #[test]
+    fn test_cookie() {
+        assert_eq!(parse_cookie(&make_cookie(42)), Some(42));
+        assert_eq!(parse_cookie(b"rid=000,csn=20240101"), None);
+    }
+
+    #[test]
+    fn test_changed_entries_from_feed() {
+        let bob = UserId::new("bob");
+        let changes = vec![
+            change(
+                5,
+                ChangeEvent::MembershipAdded {
+                    user_id: bob.clone(),
+                    group_id: GroupId(3),
+                },
+            ),
+            change(6, ChangeEvent::PasswordChanged(UserId::new("john"))),
+        ];
+        let changed = ChangedEntries::from_feed(4, 6, &changes, 10);
+        assert!(changed.contains(&SyncedEntryId::User(bob)));
+        assert!(!changed.contains(&SyncedEntryId::User(UserId::new("john"))));
+        assert!(changed.contains(&SyncedEntryId::Group(GroupId(1))));
+        assert_eq!(
+            ChangedEntries::from_feed(6, 6, &[], 10),
+            ChangedEntries::default()
+        );
+        // The changes after 3 were removed from the feed.
+        assert_eq!(
+            ChangedEntries::from_feed(3, 6, &changes, 10),
+            ChangedEntries::everything()
+        );
+        // A cookie from the future.
+        assert_eq!(
+            ChangedEntries::from_feed(8, 6, &[], 10),
+            ChangedEntries::everything()
+        );
+        assert_eq!(
+            ChangedEntries::from_feed(4, 6, &changes, 1),
+            ChangedEntries::everything()
+        );
+        assert_eq!(
+            ChangedEntries::from_feed(
+                4,
+                5,
+                &[change(5, ChangeEvent::GroupUpdated(GroupId(3)))],
+                10
+            ),
+            ChangedEntries::everything()
+        );
+    }
+
+    #[test]
+    fn test_refresh_responses() {
+        let bob = user_entry("bob", 1, "bob@example.com");
+        let john = user_entry("john", 2, "john@example.com");
+        let mut changed = ChangedEntries::default();
+        changed.add(&ChangeEvent::UserUpdated(UserId::new("john")));
+        assert_eq!(
+            refresh_responses(
+                &[bob.clone(), john.clone()],
+                &changed,
+                make_cookie(7),
+                &SyncRequestMode::RefreshAndPersist
+            ),
+            vec![
+                entry_with_state(john.entry, john.uuid, SyncStateValue::Add, None),
+                (
+                    LdapOp::IntermediateResponse(LdapIntermediateResponse::SyncInfoIdSet {
+                        cookie: None,
+                        refresh_deletes: false,
+                        syncuuids: vec![bob.uuid],
+                    }),
+                    vec![]
+                ),
+                (
+                    LdapOp::IntermediateResponse(
+                        LdapIntermediateResponse::SyncInfoRefreshPresent {
+                            cookie: Some(b"7".to_vec()),
+                            done: true,
+                        }
+                    ),
+                    vec![]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_persist_responses() {
+        let bob = user_entry("bob", 1, "bob@example.com");
+        let john = user_entry("john", 2, "john@example.com");
+        let known = [bob.clone(), john.clone()]
+            .into_iter()
+            .map(|e| (e.uuid, e))
+            .collect();
+        let new_bob = user_entry("bob", 1, "robert@example.com");
+        let patrick = user_entry("patrick", 3, "patrick@example.com");
+        let cookie = make_cookie(9);
+        assert_eq!(
+            persist_responses(&known, &[new_bob.clone(), patrick.clone()], &cookie),
+            vec![
+                entry_with_state(
+                    new_bob.entry,
+                    new_bob.uuid,
+                    SyncStateValue::Modify,
+                    Some(cookie.clone())
+                ),
+                entry_with_state(
+                    patrick.entry,
+                    patrick.uuid,
+                    SyncStateValue::Add,
+                    Some(cookie.clone())
+                ),
+                entry_with_state(
+                    LdapSearchResultEntry {
+                        dn: john.entry.dn,
+                        attributes: vec![],
+                    },
+                    john.uuid,
+                    SyncStateValue::Delete,
+                    Some(cookie.clone())
+                ),
+            ]
+        );
+        assert_eq!(persist_responses(&known, &[bob, john], &cookie), vec![]);
+    }
+}
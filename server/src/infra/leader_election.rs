@@ -0,0 +1,112 @@
+use crate::domain::{
+    model::{self, LeaderLeasesColumn},
+    sql_tables::DbConnection,
+};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, Condition, DbErr, EntityTrait, QueryFilter, Set,
+};
+use std::time::Duration;
+use tracing::{debug, instrument};
+use uuid::Uuid;
+
+/// Elects, among the instances sharing a database, the one that runs a singleton job.
+///
+/// Each job has a lease row in the DB. An instance holds the lease until it expires, and renews
+/// it every time it runs the job; another instance can only take over once the lease expired.
+#[derive(Clone)]
+pub struct LeaderElection {
+    sql_pool: DbConnection,
+    holder: String,
+}
+
+impl LeaderElection {
+    pub fn new(sql_pool: DbConnection) -> Self {
+        // On Kubernetes, HOSTNAME is the pod name, which makes the holder easy to identify.
+        let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "lldap".to_owned());
+        Self::with_holder(sql_pool, format!("{}-{}", host, Uuid::new_v4()))
+    }
+
+    fn with_holder(sql_pool: DbConnection, holder: String) -> Self {
+        Self { sql_pool, holder }
+    }
+
+    /// Acquires or renews the lease for the job. Returns false if another instance holds it.
+    #[instrument(skip(self), level = "debug", ret, err)]
+    pub async fn try_acquire(&self, job: &str, lease: Duration) -> Result<bool, DbErr> {
+        let now = chrono::Utc::now().naive_utc();
+        let expiry_date = now + chrono::Duration::from_std(lease).unwrap();
+        let renewed = model::LeaderLeases::update_many()
+            .col_expr(LeaderLeasesColumn::Holder, Expr::value(self.holder.clone()))
+            .col_expr(LeaderLeasesColumn::ExpiryDate, Expr::value(expiry_date))
+            .filter(LeaderLeasesColumn::Name.eq(job))
+            .filter(
+                Condition::any()
+                    .add(LeaderLeasesColumn::Holder.eq(self.holder.as_str()))
+                    .add(LeaderLeasesColumn::ExpiryDate.lt(now)),
+            )
+            .exec(&self.sql_pool)
+            .await?;
+        if renewed.rows_affected > 0 {
+            return Ok(true);
+        }
+        if model::LeaderLeases::find_by_id(job.to_owned())
+            .one(&self.sql_pool)
+            .await?
+            .is_some()
+        {
+            return Ok(false);
+        }
+        let inserted = model::leader_leases::ActiveModel {
+            name: Set(job.to_owned()),
+            holder: Set(self.holder.clone()),
+            expiry_date: Set(expiry_date),
+        }
+        .insert(&self.sql_pool)
+        .await;
+        if let Err(e) = &inserted {
+            // Most likely another instance created the lease at the same time.
+            debug!("Could not create the lease: {}", e);
+        }
+        Ok(inserted.is_ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::jwt_sql_tables::init_table;
+    use crate::domain::sql_backend_handler::tests::get_initialized_db;
+
+    #[tokio::test]
+    async fn test_single_leader() {
+        let sql_pool = get_initialized_db().await;
+        init_table(&sql_pool).await.unwrap();
+        let first = LeaderElection::with_holder(sql_pool.clone(), "first".to_owned());
+        let second = LeaderElection::with_holder(sql_pool, "second".to_owned());
+        let lease = Duration::from_secs(60);
+        assert!(first.try_acquire("job", lease).await.unwrap());
+        assert!(!second.try_acquire("job", lease).await.unwrap());
+        // Renewing works.
+        assert!(first.try_acquire("job", lease).await.unwrap());
+        // Leases are per job.
+        assert!(second.try_acquire("other_job", lease).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_expired_lease_is_taken_over() {
+        let sql_pool = get_initialized_db().await;
+        init_table(&sql_pool).await.unwrap();
+        let first = LeaderElection::with_holder(sql_pool.clone(), "first".to_owned());
+        let second = LeaderElection::with_holder(sql_pool, "second".to_owned());
+        assert!(first.try_acquire("job", Duration::ZERO).await.unwrap());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(second
+            .try_acquire("job", Duration::from_secs(60))
+            .await
+            .unwrap());
+        assert!(!first
+            .try_acquire("job", Duration::from_secs(60))
+            .await
+            .unwrap());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::infra::configuration::Configuration;
+use crate::infra::{cli::OutputFormat, configuration::Configuration};
 use actix_web::{
     dev::{ServiceRequest, ServiceResponse},
     Error,
@@ -54,6 +54,26 @@ pub fn init(config: &Configuration) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Like `init`, but with the logs on stderr when the result of the command is printed on stdout
+/// for a script.
+pub fn init_for_output(config: &Configuration, format: OutputFormat) -> anyhow::Result<()> {
+    if format == OutputFormat::Table {
+        return init(config);
+    }
+    let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        EnvFilter::new(if config.verbose {
+            "sqlx=warn,reqwest=warn,debug"
+        } else {
+            "sqlx=warn,reqwest=warn,info"
+        })
+    });
+    tracing_subscriber::registry()
+        .with(env_filter)
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .init();
+    Ok(())
+}
+
 #[cfg(test)]
 pub fn init_for_tests() {
     if let Err(e) = tracing_subscriber::FmtSubscriber::builder()
@@ -1,4 +1,7 @@
-use crate::infra::{cli::SmtpEncryption, configuration::MailOptions};
+use crate::{
+    domain::access_reviews::AccessReview,
+    infra::{cli::SmtpEncryption, configuration::MailOptions, reset_delivery::reset_url},
+};
 use anyhow::{anyhow, Ok, Result};
 use lettre::{
     message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
@@ -79,21 +82,37 @@ async fn send_email(
     }
 }
 
-pub async fn send_password_reset_email(
+/// The languages in which the emails are written, English being the fallback.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MailLanguage {
+    English,
+    German,
+}
+
+impl MailLanguage {
+    /// Picks the language from the primary subtag of the user's preferred language.
+    fn from_preferred_language(language: Option<&str>) -> Self {
+        let primary = language
+            .and_then(|l| l.split('-').next())
+            .unwrap_or_default();
+        if primary.eq_ignore_ascii_case("de") {
+            MailLanguage::German
+        } else {
+            MailLanguage::English
+        }
+    }
+}
+
+fn password_reset_email(
+    language: MailLanguage,
     username: &str,
-    to: &str,
-    token: &str,
-    server_url: &url::Url,
-    options: &MailOptions,
-) -> Result<()> {
-    let to = to.parse()?;
-    let mut reset_url = server_url.clone();
-    reset_url
-        .path_segments_mut()
-        .unwrap()
-        .extend(["reset-password", "step2", token]);
-    let body = format!(
-        "Hello {},
+    reset_url: &url::Url,
+) -> (&'static str, String) {
+    match language {
+        MailLanguage::English => (
+            "[LLDAP] Password reset requested",
+            format!(
+                "Hello {},
 This email has been sent to you in order to validate your identity.
 If you did not initiate the process your credentials might have been
 compromised. You should reset your password and contact an administrator.
@@ -101,22 +120,169 @@ compromised. You should reset your password and contact an administrator.
 To reset your password please visit the following URL: {}
 
 Please contact an administrator if you did not initiate the process.",
-        username, reset_url
+                username, reset_url
+            ),
+        ),
+        MailLanguage::German => (
+            "[LLDAP] Zurücksetzen des Passworts angefordert",
+            format!(
+                "Hallo {},
+diese E-Mail wurde Ihnen gesendet, um Ihre Identität zu bestätigen.
+Falls Sie den Vorgang nicht selbst gestartet haben, wurden Ihre
+Zugangsdaten möglicherweise kompromittiert. Sie sollten Ihr Passwort
+zurücksetzen und einen Administrator kontaktieren.
+
+Um Ihr Passwort zurückzusetzen, besuchen Sie bitte die folgende URL: {}
+
+Bitte kontaktieren Sie einen Administrator, falls Sie den Vorgang nicht
+selbst gestartet haben.",
+                username, reset_url
+            ),
+        ),
+    }
+}
+
+fn account_deletion_email(
+    language: MailLanguage,
+    username: &str,
+    confirm_url: &url::Url,
+) -> (&'static str, String) {
+    match language {
+        MailLanguage::English => (
+            "[LLDAP] Account deletion requested",
+            format!(
+                "Hello {},
+You asked for your account to be deleted.
+
+To confirm the deletion please visit the following URL: {}
+Your account will be disabled immediately, and permanently deleted
+after a grace period.
+
+If you did not make this request, you can ignore this email and
+contact an administrator.",
+                username, confirm_url
+            ),
+        ),
+        MailLanguage::German => (
+            "[LLDAP] Löschung des Kontos angefordert",
+            format!(
+                "Hallo {},
+Sie haben die Löschung Ihres Kontos beantragt.
+
+Um die Löschung zu bestätigen, besuchen Sie bitte die folgende URL: {}
+Ihr Konto wird sofort deaktiviert und nach einer Karenzzeit
+endgültig gelöscht.
+
+Falls Sie diese Anfrage nicht gestellt haben, können Sie diese E-Mail
+ignorieren und einen Administrator kontaktieren.",
+                username, confirm_url
+            ),
+        ),
+    }
+}
+
+fn access_review_email(
+    language: MailLanguage,
+    username: &str,
+    campaign: &str,
+    group: &str,
+    review_url: &url::Url,
+) -> (&'static str, String) {
+    match language {
+        MailLanguage::English => (
+            "[LLDAP] Access review",
+            format!(
+                "Hello {},
+You were asked to review the members of the group \"{}\", as part of
+the access review \"{}\".
+
+Please confirm or remove each member at the following URL: {}",
+                username, group, campaign, review_url
+            ),
+        ),
+        MailLanguage::German => (
+            "[LLDAP] Überprüfung der Zugriffsrechte",
+            format!(
+                "Hallo {},
+Sie wurden gebeten, die Mitglieder der Gruppe \"{}\" im Rahmen der
+Überprüfung \"{}\" zu kontrollieren.
+
+Bitte bestätigen oder entfernen Sie jedes Mitglied unter der folgenden
+URL: {}",
+                username, group, campaign, review_url
+            ),
+        ),
+    }
+}
+
+pub async fn send_password_reset_email(
+    username: &str,
+    to: &str,
+    language: Option<&str>,
+    token: &str,
+    server_url: &url::Url,
+    options: &MailOptions,
+) -> Result<()> {
+    let to = to.parse()?;
+    let (subject, body) = password_reset_email(
+        MailLanguage::from_preferred_language(language),
+        username,
+        &reset_url(server_url, token),
     );
-    let res = send_email(
-        to,
-        "[LLDAP] Password reset requested",
-        body,
-        options,
-        server_url,
-    )
-    .await;
+    let res = send_email(to, subject, body, options, server_url).await;
     if res.is_err() {
         sleep(Duration::from_secs(3)).await;
     }
     res
 }
 
+pub async fn send_account_deletion_email(
+    username: &str,
+    to: &str,
+    language: Option<&str>,
+    token: &str,
+    server_url: &url::Url,
+    options: &MailOptions,
+) -> Result<()> {
+    let to = to.parse()?;
+    let mut confirm_url = server_url.clone();
+    confirm_url
+        .path_segments_mut()
+        .unwrap()
+        .extend(["delete-account", token]);
+    let (subject, body) = account_deletion_email(
+        MailLanguage::from_preferred_language(language),
+        username,
+        &confirm_url,
+    );
+    send_email(to, subject, body, options, server_url).await
+}
+
+pub async fn send_access_review_email(
+    username: &str,
+    to: &str,
+    language: Option<&str>,
+    review: &AccessReview,
+    token: &str,
+    server_url: &url::Url,
+    options: &MailOptions,
+) -> Result<()> {
+    let to = to.parse()?;
+    let mut review_url = server_url.clone();
+    review_url
+        .path_segments_mut()
+        .unwrap()
+        .extend(["access-review", token]);
+    let (subject, body) = access_review_email(
+        MailLanguage::from_preferred_language(language),
+        username,
+        &review.campaign_name,
+        review.group_name.as_str(),
+        &review_url,
+    );
+    send_email(to, subject, body, options, server_url).await
+}
+
 pub async fn send_test_email(to: Mailbox, options: &MailOptions) -> Result<()> {
     send_email(
         to,
@@ -127,3 +293,46 @@ pub async fn send_test_email(to: Mailbox, options: &MailOptions) -> Result<()> {
     )
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mail_language() {
+        assert_eq!(
+            MailLanguage::from_preferred_language(None),
+            MailLanguage::English
+        );
+        assert_eq!(
+            MailLanguage::from_preferred_language(Some("de-AT")),
+            MailLanguage::German
+        );
+        assert_eq!(
+            MailLanguage::from_preferred_language(Some("DE")),
+            MailLanguage::German
+        );
+        assert_eq!(
+            MailLanguage::from_preferred_language(Some("fr-FR")),
+            MailLanguage::English
+        );
+    }
+
+    #[test]
+    fn test_password_reset_email_language() {
+        let url = url::Url::parse("https://ldap.example.com/reset-password/step2/abc").unwrap();
+        let (subject, body) = password_reset_email(MailLanguage::German, "Bob", &url);
+        assert!(subject.contains("Passwort"));
+        assert!(body.starts_with("Hallo Bob,"));
+        assert!(body.contains(url.as_str()));
+    }
+
+    #[test]
+    fn test_access_review_email() {
+        let url = url::Url::parse("https://ldap.example.com/access-review/abc").unwrap();
+        let (_, body) = access_review_email(MailLanguage::English, "Bob", "Q3", "admins", &url);
+        assert!(body.contains(r#"the group "admins""#));
+        assert!(body.contains(r#"the access review "Q3""#));
+        assert!(body.contains(url.as_str()));
+    }
+}
@@ -0,0 +1,87 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    RwLock,
+};
+
+use crate::infra::configuration::MaintenanceOptions;
+
+/// Whether only the admins can log in, e.g. during a migration or a restore. Shared by the LDAP
+/// and HTTP servers, and toggled at runtime by the admins.
+#[derive(Debug, Default)]
+pub struct MaintenanceMode {
+    enabled: AtomicBool,
+    message: RwLock<String>,
+}
+
+/// A snapshot of the maintenance mode.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+    pub message: String,
+}
+
+impl MaintenanceMode {
+    pub fn new(options: &MaintenanceOptions) -> Self {
+        Self {
+            enabled: AtomicBool::new(options.enabled),
+            message: RwLock::new(options.message.clone()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn message(&self) -> String {
+        self.message.read().unwrap().clone()
+    }
+
+    pub fn status(&self) -> MaintenanceStatus {
+        MaintenanceStatus {
+            enabled: self.is_enabled(),
+            message: self.message(),
+        }
+    }
+
+    /// Turns the maintenance mode on or off, keeping the previous message if none is given.
+    pub fn set(&self, enabled: bool, message: Option<String>) {
+        if let Some(message) = message.filter(|m| !m.trim().is_empty()) {
+            *self.message.write().unwrap() = message;
+        }
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// The error message for a login refused to a non-admin user, if any.
+    pub fn refusal(&self, is_admin: bool) -> Option<String> {
+        if self.is_enabled() && !is_admin {
+            Some(self.message())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maintenance_mode() {
+        let maintenance = MaintenanceMode::new(&MaintenanceOptions::default());
+        assert_eq!(maintenance.refusal(false), None);
+        maintenance.set(true, Some("Restoring a backup".to_owned()));
+        assert_eq!(maintenance.refusal(true), None);
+        assert_eq!(
+            maintenance.refusal(false),
+            Some("Restoring a backup".to_owned())
+        );
+        maintenance.set(false, None);
+        assert_eq!(
+            maintenance.status(),
+            MaintenanceStatus {
+                enabled: false,
+                message: "Restoring a backup".to_owned()
+            }
+        );
+    }
+}
@@ -1,19 +1,48 @@
 pub mod access_control;
 pub mod auth_service;
+pub mod avatar;
+pub mod avatar_sync;
+pub mod backend;
+pub mod backup_verification;
+pub mod cache_invalidator;
+pub mod captcha;
 pub mod cli;
+pub mod cli_output;
 pub mod configuration;
 pub mod database_string;
 pub mod db_cleaner;
+pub mod diagnostics;
+pub mod expiry_monitor;
+pub mod geoip;
 pub mod graphql;
+pub mod group_emails;
 pub mod healthcheck;
-pub mod jwt_sql_tables;
+pub mod keycloak_import;
+pub mod ldap_bind_limiter;
+pub mod ldap_client;
 pub mod ldap_handler;
+pub mod ldap_metrics;
+pub mod ldap_proxy;
 pub mod ldap_server;
+pub mod ldap_sync;
+pub mod leader_election;
 pub mod logging;
 pub mod mail;
+pub mod maintenance;
+pub mod notifications;
+pub mod pam_auth;
+pub mod prometheus;
+pub mod provisioning_webhook;
+pub mod read_only;
+pub mod reset_delivery;
+pub mod seed;
+pub mod self_test;
 pub mod sql_backend_handler;
 pub mod tcp_backend_handler;
 pub mod tcp_server;
+pub mod validation_webhook;
 
+#[cfg(test)]
+mod ldap_fixture_tests;
 #[cfg(test)]
 pub mod test_utils;
@@ -0,0 +1,246 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use secstr::SecUtf8;
+use serde::Serialize;
+use tracing::{debug, warn};
+use url::Url;
+
+use crate::infra::configuration::NotificationOptions;
+
+/// What an alert is about.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AlertKind {
+    /// An account was locked after too many failed logins.
+    Lockout,
+    /// A periodic sync (e.g. with an upstream LDAP server) failed.
+    SyncFailure,
+    /// The LDAPS certificate expires soon.
+    CertificateExpiry,
+    /// A key has not been rotated for longer than configured.
+    KeyAge,
+    /// A temporary group membership reached its expiry date and was removed.
+    MembershipExpiry,
+}
+
+impl AlertKind {
+    fn title(&self) -> &'static str {
+        match self {
+            AlertKind::Lockout => "Account locked",
+            AlertKind::SyncFailure => "Sync failed",
+            AlertKind::CertificateExpiry => "Certificate expiring",
+            AlertKind::KeyAge => "Key not rotated",
+            AlertKind::MembershipExpiry => "Membership expired",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Alert {
+    pub kind: AlertKind,
+    pub message: String,
+}
+
+impl Alert {
+    fn text(&self) -> String {
+        format!("[LLDAP] {}: {}", self.kind.title(), self.message)
+    }
+}
+
+/// A chat service the admin alerts are sent to.
+#[async_trait]
+trait NotificationSink: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn send(&self, client: &reqwest::Client, text: &str) -> Result<()>;
+}
+
+async fn send_json(request: reqwest::RequestBuilder, body: &impl Serialize) -> Result<()> {
+    request
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_string(body)?)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+struct SlackSink {
+    webhook_url: Url,
+}
+
+#[async_trait]
+impl NotificationSink for SlackSink {
+    fn name(&self) -> &'static str {
+        "Slack"
+    }
+
+    async fn send(&self, client: &reqwest::Client, text: &str) -> Result<()> {
+        send_json(
+            client.post(self.webhook_url.clone()),
+            &serde_json::json!({ "text": text }),
+        )
+        .await
+    }
+}
+
+struct MatrixSink {
+    homeserver: Url,
+    access_token: SecUtf8,
+    room_id: String,
+}
+
+#[async_trait]
+impl NotificationSink for MatrixSink {
+    fn name(&self) -> &'static str {
+        "Matrix"
+    }
+
+    async fn send(&self, client: &reqwest::Client, text: &str) -> Result<()> {
+        // The transaction ID makes the request idempotent.
+        let transaction_id = format!("lldap-{:016x}", rand::random::<u64>());
+        let mut url = self.homeserver.clone();
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("Invalid homeserver URL"))?
+            .pop_if_empty()
+            .extend([
+                "_matrix",
+                "client",
+                "v3",
+                "rooms",
+                self.room_id.as_str(),
+                "send",
+                "m.room.message",
+                transaction_id.as_str(),
+            ]);
+        send_json(
+            client.put(url).bearer_auth(self.access_token.unsecure()),
+            &serde_json::json!({ "msgtype": "m.text", "body": text }),
+        )
+        .await
+    }
+}
+
+struct TelegramSink {
+    bot_token: SecUtf8,
+    chat_id: String,
+}
+
+#[async_trait]
+impl NotificationSink for TelegramSink {
+    fn name(&self) -> &'static str {
+        "Telegram"
+    }
+
+    async fn send(&self, client: &reqwest::Client, text: &str) -> Result<()> {
+        send_json(
+            client.post(format!(
+                "https://api.telegram.org/bot{}/sendMessage",
+                self.bot_token.unsecure()
+            )),
+            &serde_json::json!({ "chat_id": self.chat_id, "text": text }),
+        )
+        .await
+    }
+}
+
+/// Sends the admin alerts to all the configured chat services. Cheap to clone.
+#[derive(Clone, Default)]
+pub struct Notifier {
+    client: reqwest::Client,
+    sinks: Arc<Vec<Box<dyn NotificationSink>>>,
+}
+
+impl Notifier {
+    pub fn from_options(options: &NotificationOptions) -> Self {
+        let mut sinks: Vec<Box<dyn NotificationSink>> = Vec::new();
+        if let Some(webhook_url) = &options.slack_webhook_url {
+            sinks.push(Box::new(SlackSink {
+                webhook_url: webhook_url.clone(),
+            }));
+        }
+        if let (Some(homeserver), Some(access_token), Some(room_id)) = (
+            &options.matrix_homeserver,
+            &options.matrix_access_token,
+            &options.matrix_room_id,
+        ) {
+            sinks.push(Box::new(MatrixSink {
+                homeserver: homeserver.clone(),
+                access_token: access_token.clone(),
+                room_id: room_id.clone(),
+            }));
+        }
+        if let (Some(bot_token), Some(chat_id)) =
+            (&options.telegram_bot_token, &options.telegram_chat_id)
+        {
+            sinks.push(Box::new(TelegramSink {
+                bot_token: bot_token.clone(),
+                chat_id: chat_id.clone(),
+            }));
+        }
+        Self {
+            client: reqwest::Client::new(),
+            sinks: Arc::new(sinks),
+        }
+    }
+
+    /// Sends the alert to every sink. Failures are only logged: alerts are best-effort.
+    pub async fn alert(&self, kind: AlertKind, message: impl Into<String>) {
+        let alert = Alert {
+            kind,
+            message: message.into(),
+        };
+        if self.sinks.is_empty() {
+            debug!("No notification sink for the alert {:?}", alert);
+            return;
+        }
+        let text = alert.text();
+        futures_util::future::join_all(self.sinks.iter().map(|sink| async {
+            if let Err(e) = sink
+                .send(&self.client, &text)
+                .await
+                .with_context(|| format!("while sending an alert to {}", sink.name()))
+            {
+                warn!("{:#}", e);
+            }
+        }))
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::configuration::NotificationOptionsBuilder;
+
+    #[test]
+    fn test_alert_text() {
+        assert_eq!(
+            Alert {
+                kind: AlertKind::SyncFailure,
+                message: "upstream unreachable".to_owned(),
+            }
+            .text(),
+            "[LLDAP] Sync failed: upstream unreachable"
+        );
+    }
+
+    #[test]
+    fn test_from_options() {
+        let notifier = Notifier::from_options(
+            &NotificationOptionsBuilder::default()
+                .slack_webhook_url(Some(Url::parse("https://hooks.slack.com/x").unwrap()))
+                // Incomplete: no room.
+                .matrix_homeserver(Some(Url::parse("https://matrix.org").unwrap()))
+                .matrix_access_token(Some(SecUtf8::from("token")))
+                .telegram_bot_token(Some(SecUtf8::from("123:abc")))
+                .telegram_chat_id(Some("-100".to_owned()))
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(
+            notifier.sinks.iter().map(|s| s.name()).collect::<Vec<_>>(),
+            vec!["Slack", "Telegram"]
+        );
+    }
+}
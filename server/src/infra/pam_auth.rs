@@ -0,0 +1,32 @@
+use crate::{domain::types::UserId, infra::configuration::PamOptions};
+use anyhow::Result;
+
+/// Checks the password of a user with the host's PAM stack.
+#[cfg(feature = "pam")]
+pub async fn check_pam_password(
+    options: &PamOptions,
+    user_id: &UserId,
+    password: &str,
+) -> Result<bool> {
+    use anyhow::anyhow;
+    let service = options.service.clone();
+    let user = user_id.to_string();
+    let password = password.to_owned();
+    // PAM modules can block for a while, e.g. to slow down brute force attempts.
+    tokio::task::spawn_blocking(move || {
+        let mut authenticator = pam::Authenticator::with_password(&service)
+            .map_err(|e| anyhow!("Could not start the PAM service {}: {:?}", &service, e))?;
+        authenticator.get_handler().set_credentials(user, password);
+        Ok(authenticator.authenticate().is_ok())
+    })
+    .await?
+}
+
+#[cfg(not(feature = "pam"))]
+pub async fn check_pam_password(
+    _options: &PamOptions,
+    _user_id: &UserId,
+    _password: &str,
+) -> Result<bool> {
+    anyhow::bail!("LLDAP was built without the `pam` feature")
+}
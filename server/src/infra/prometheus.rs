@@ -0,0 +1,282 @@
+use std::fmt::{Display, Write};
+
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use tracing::instrument;
+
+use crate::{
+    domain::{
+        handler::{
+            AccountDeletionBackendHandler, BackendHandler, GroupListerBackendHandler,
+            PendingChangeBackendHandler, UserListerBackendHandler,
+        },
+        secret_tokens::constant_time_eq,
+    },
+    infra::{
+        configuration::QuotaOptions,
+        expiry_monitor::ExpiryStatus,
+        ldap_metrics::LdapOperationStats,
+        tcp_server::{error_to_http_response, AppState, TcpError, TcpResult},
+    },
+};
+
+/// The size of the directory and of the queues waiting for an action, at the time of the scrape.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DirectoryStats {
+    pub users: usize,
+    pub groups: usize,
+    /// Accounts disabled after a confirmed deletion request, until they are deleted.
+    pub disabled_users: usize,
+    /// Deletion requests waiting for the email confirmation.
+    pub unconfirmed_account_deletions: usize,
+    /// Sensitive changes waiting for the approval of a second admin.
+    pub pending_changes: usize,
+}
+
+async fn get_directory_stats(handler: &impl BackendHandler) -> TcpResult<DirectoryStats> {
+    let account_deletions = handler.list_account_deletions().await?;
+    let disabled_users = account_deletions
+        .iter()
+        .filter(|d| d.deletion_date.is_some())
+        .count();
+    Ok(DirectoryStats {
+        users: handler.list_users(None, false).await?.len(),
+        groups: handler.list_groups(None).await?.len(),
+        disabled_users,
+        unconfirmed_account_deletions: account_deletions.len() - disabled_users,
+        pending_changes: handler.list_pending_changes().await?.len(),
+    })
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
+
+fn write_gauge(out: &mut String, name: &str, help: &str, value: impl Display) {
+    write_header(out, name, "gauge", help);
+    writeln!(out, "{} {}", name, value).unwrap();
+}
+
+fn write_operation_metric(
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    operations: &[(&str, LdapOperationStats)],
+    value: impl Fn(&LdapOperationStats) -> String,
+) {
+    write_header(out, name, kind, help);
+    for (operation, stats) in operations {
+        writeln!(
+            out,
+            "{}{{operation=\"{}\"}} {}",
+            name,
+            operation,
+            value(stats)
+        )
+        .unwrap();
+    }
+}
+
+/// The metrics in the Prometheus text format.
+pub fn render_metrics(
+    stats: &DirectoryStats,
+    quotas: &QuotaOptions,
+    operations: &[(&str, LdapOperationStats)],
+    expiry: &ExpiryStatus,
+) -> String {
+    let mut out = String::new();
+    write_gauge(
+        &mut out,
+        "lldap_users_total",
+        "Number of users.",
+        stats.users,
+    );
+    write_gauge(
+        &mut out,
+        "lldap_groups_total",
+        "Number of groups.",
+        stats.groups,
+    );
+    if let Some(max_users) = quotas.max_users {
+        write_gauge(
+            &mut out,
+            "lldap_users_limit",
+            "Maximum number of users, from quota_options.max_users.",
+            max_users,
+        );
+    }
+    if let Some(max_groups) = quotas.max_groups {
+        write_gauge(
+            &mut out,
+            "lldap_groups_limit",
+            "Maximum number of groups, from quota_options.max_groups.",
+            max_groups,
+        );
+    }
+    write_gauge(
+        &mut out,
+        "lldap_disabled_users",
+        "Number of users disabled until the deletion of their account.",
+        stats.disabled_users,
+    );
+    write_gauge(
+        &mut out,
+        "lldap_unconfirmed_account_deletions",
+        "Number of account deletion requests waiting for the email confirmation.",
+        stats.unconfirmed_account_deletions,
+    );
+    write_gauge(
+        &mut out,
+        "lldap_pending_changes",
+        "Number of sensitive changes waiting for the approval of a second admin.",
+        stats.pending_changes,
+    );
+    if let Some(not_after) = expiry.ldaps_certificate_not_after {
+        write_gauge(
+            &mut out,
+            "lldap_ldaps_certificate_expiry_timestamp_seconds",
+            "Expiry date of the LDAPS certificate, as of the last daily check.",
+            not_after.timestamp(),
+        );
+    }
+    write_operation_metric(
+        &mut out,
+        "lldap_ldap_operations_total",
+        "counter",
+        "Number of LDAP operations since the server started.",
+        operations,
+        |s| s.count.to_string(),
+    );
+    write_operation_metric(
+        &mut out,
+        "lldap_ldap_slow_operations_total",
+        "counter",
+        "Number of LDAP operations slower than ldap_slow_operation_threshold_ms.",
+        operations,
+        |s| s.slow_count.to_string(),
+    );
+    write_operation_metric(
+        &mut out,
+        "lldap_ldap_operation_duration_seconds_total",
+        "counter",
+        "Total duration of the LDAP operations since the server started.",
+        operations,
+        |s| s.total_duration.as_secs_f64().to_string(),
+    );
+    out
+}
+
+fn check_bearer_token<Backend>(data: &AppState<Backend>, request: &HttpRequest) -> TcpResult<()> {
+    let token = match &data.metrics_options.bearer_token {
+        Some(token) => token,
+        None => return Ok(()),
+    };
+    let expected = format!("Bearer {}", token.unsecure());
+    match request.headers().get(header::AUTHORIZATION) {
+        Some(value) if constant_time_eq(value.as_bytes(), expected.as_bytes()) => Ok(()),
+        _ => Err(TcpError::UnauthorizedError(
+            "Missing or invalid metrics token".to_owned(),
+        )),
+    }
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn get_metrics<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+) -> TcpResult<String>
+where
+    Backend: BackendHandler + 'static,
+{
+    check_bearer_token(&data, &request)?;
+    let stats = get_directory_stats(data.backend_handler.unsafe_get_handler()).await?;
+    Ok(render_metrics(
+        &stats,
+        &data.quota_options,
+        &data.ldap_metrics.snapshot(),
+        &data.expiry_monitor.status(),
+    ))
+}
+
+async fn get_metrics_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+) -> HttpResponse
+where
+    Backend: BackendHandler + 'static,
+{
+    get_metrics(data, request)
+        .await
+        .map(|metrics| {
+            HttpResponse::Ok()
+                .content_type("text/plain; version=0.0.4")
+                .body(metrics)
+        })
+        .unwrap_or_else(error_to_http_response)
+}
+
+pub fn configure_endpoint<Backend>(cfg: &mut web::ServiceConfig)
+where
+    Backend: BackendHandler + 'static,
+{
+    cfg.service(web::resource("/metrics").route(web::get().to(get_metrics_handler::<Backend>)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    #[test]
+    fn test_render_metrics() {
+        let stats = DirectoryStats {
+            users: 12,
+            groups: 3,
+            disabled_users: 1,
+            unconfirmed_account_deletions: 2,
+            pending_changes: 0,
+        };
+        let operations = [(
+            "search",
+            LdapOperationStats {
+                count: 5,
+                slow_count: 1,
+                entries: 20,
+                total_duration: Duration::from_millis(1500),
+                max_duration: Duration::from_millis(800),
+            },
+        )];
+        let expiry = ExpiryStatus {
+            ldaps_certificate_not_after: Some(Utc.timestamp_opt(1_800_000_000, 0).unwrap()),
+            ..Default::default()
+        };
+        let quotas = QuotaOptions {
+            max_users: Some(100),
+            max_groups: None,
+        };
+        let metrics = render_metrics(&stats, &quotas, &operations, &expiry);
+        let samples = metrics
+            .lines()
+            .filter(|l| !l.starts_with('#'))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            samples,
+            vec![
+                "lldap_users_total 12",
+                "lldap_groups_total 3",
+                "lldap_users_limit 100",
+                "lldap_disabled_users 1",
+                "lldap_unconfirmed_account_deletions 2",
+                "lldap_pending_changes 0",
+                "lldap_ldaps_certificate_expiry_timestamp_seconds 1800000000",
+                r#"lldap_ldap_operations_total{operation="search"} 5"#,
+                r#"lldap_ldap_slow_operations_total{operation="search"} 1"#,
+                r#"lldap_ldap_operation_duration_seconds_total{operation="search"} 1.5"#,
+            ]
+        );
+        assert!(metrics.contains("# TYPE lldap_users_total gauge\n"));
+    }
+}
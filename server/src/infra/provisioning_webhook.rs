@@ -0,0 +1,566 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::Sha256;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    domain::{
+        error::DomainError,
+        handler::{
+            AccountDeletionBackendHandler, BackendHandler, CreateUserRequest, UpdateUserRequest,
+            UserBackendHandler, UserCreationSource,
+        },
+        types::UserId,
+    },
+    infra::{
+        configuration::ProvisioningWebhookOptions,
+        tcp_backend_handler::TcpBackendHandler,
+        tcp_server::{error_to_http_response, AppState, TcpError, TcpResult},
+    },
+};
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Checks the hex-encoded HMAC-SHA256 of "<delivery ID>.<timestamp>.<body>", optionally prefixed
+/// by "sha256=" like GitHub does, in constant time. Signing the delivery ID and the timestamp
+/// with the body keeps them from being changed to replay a payload.
+pub fn verify_signature(
+    secret: &[u8],
+    delivery_id: &str,
+    timestamp: &str,
+    body: &[u8],
+    signature: &str,
+) -> bool {
+    let signature = signature.trim();
+    let signature = signature.strip_prefix("sha256=").unwrap_or(signature);
+    let signature = match decode_hex(signature) {
+        Some(signature) => signature,
+        None => return false,
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(delivery_id.as_bytes());
+    mac.update(b".");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Parses the timestamp of a delivery, in seconds since the epoch. Returns None if it is further
+/// than `max_age` from `now`, in either direction.
+pub fn parse_timestamp(
+    timestamp: &str,
+    now: chrono::DateTime<chrono::Utc>,
+    max_age: chrono::Duration,
+) -> Option<chrono::DateTime<chrono::Utc>> {
+    let timestamp = timestamp.trim().parse::<i64>().ok()?;
+    let timestamp = chrono::DateTime::<chrono::Utc>::from_timestamp(timestamp, 0)?;
+    if (now - timestamp).abs() > max_age {
+        return None;
+    }
+    Some(timestamp)
+}
+
+/// The state of a user in the HR system, as extracted from a payload.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProvisioningEvent {
+    pub user_id: UserId,
+    pub email: Option<String>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub display_name: Option<String>,
+    pub active: bool,
+}
+
+/// The value at the JSON pointer, if it is a non-empty string or a number (employee IDs often
+/// are).
+fn get_field(payload: &Value, pointer: &str) -> Option<String> {
+    match payload.pointer(pointer)? {
+        Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_owned()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+pub fn parse_event(
+    payload: &Value,
+    options: &ProvisioningWebhookOptions,
+) -> Result<ProvisioningEvent, String> {
+    let mapping = &options.mapping;
+    // An empty pointer disables the field, rather than pointing to the whole payload.
+    let get_optional_field = |pointer: &Option<String>| {
+        pointer
+            .as_deref()
+            .filter(|p| !p.is_empty())
+            .and_then(|p| get_field(payload, p))
+    };
+    let user_id = get_field(payload, &mapping.user_id)
+        .ok_or_else(|| format!("Missing user ID at `{}`", mapping.user_id))?;
+    let first_name = get_optional_field(&mapping.first_name);
+    let last_name = get_optional_field(&mapping.last_name);
+    let display_name = get_optional_field(&mapping.display_name).or_else(|| {
+        Some(
+            [first_name.as_deref(), last_name.as_deref()]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(" "),
+        )
+        .filter(|n| !n.is_empty())
+    });
+    let active = match get_optional_field(&mapping.status) {
+        Some(status) => !options
+            .inactive_statuses
+            .iter()
+            .any(|s| s.eq_ignore_ascii_case(&status)),
+        None => true,
+    };
+    Ok(ProvisioningEvent {
+        user_id: UserId::new(&user_id),
+        email: get_field(payload, &mapping.email),
+        first_name,
+        last_name,
+        display_name,
+        active,
+    })
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProvisioningAction {
+    Created,
+    Updated,
+    /// Updated, and restored after having been disabled.
+    Reactivated,
+    /// Disabled until the deletion of the account, after the grace period.
+    Disabled,
+    /// Nothing to do: an inactive user that doesn't exist or is already disabled.
+    Ignored,
+    /// Left untouched: the user is an admin.
+    Protected,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ProvisioningResult {
+    pub user_id: UserId,
+    pub action: ProvisioningAction,
+}
+
+/// Creates, updates or disables the user to match the HR system. Disabled users go through the
+/// same grace period as deleted accounts, during which they are restored if they become active
+/// again. The members of lldap_admin are never modified.
+pub async fn apply_event<Handler: BackendHandler>(
+    handler: &Handler,
+    event: ProvisioningEvent,
+    grace_period: chrono::Duration,
+) -> TcpResult<ProvisioningAction> {
+    let exists = match handler.get_user_details(&event.user_id).await {
+        Ok(_) => true,
+        Err(DomainError::EntityNotFound(_)) => false,
+        Err(e) => return Err(e.into()),
+    };
+    // A compromised or misconfigured HR system must not lock the admins out.
+    if exists
+        && handler
+            .get_user_groups(&event.user_id)
+            .await?
+            .iter()
+            .any(|g| g.display_name == "lldap_admin".into())
+    {
+        warn!(
+            "Provisioning webhook: skipped {}, a member of lldap_admin",
+            event.user_id
+        );
+        return Ok(ProvisioningAction::Protected);
+    }
+    let disabled = handler
+        .get_account_deletion(&event.user_id)
+        .await?
+        .map_or(false, |d| d.deletion_date.is_some());
+    if !event.active {
+        if !exists || disabled {
+            return Ok(ProvisioningAction::Ignored);
+        }
+        let token = handler.request_account_deletion(&event.user_id).await?;
+        handler
+            .confirm_account_deletion(&token, grace_period)
+            .await?;
+        return Ok(ProvisioningAction::Disabled);
+    }
+    if !exists {
+        let email = event.email.ok_or_else(|| {
+            TcpError::BadRequest(format!("Cannot create {} without an email", event.user_id))
+        })?;
+        handler
+            .create_user(CreateUserRequest {
+                user_id: event.user_id,
+                email: email.into(),
+                display_name: event.display_name,
+                first_name: event.first_name,
+                last_name: event.last_name,
+                source: UserCreationSource::ProvisioningWebhook,
+                ..Default::default()
+            })
+            .await?;
+        return Ok(ProvisioningAction::Created);
+    }
+    handler
+        .update_user(UpdateUserRequest {
+            user_id: event.user_id.clone(),
+            email: event.email.map(Into::into),
+            display_name: event.display_name,
+            first_name: event.first_name,
+            last_name: event.last_name,
+            ..Default::default()
+        })
+        .await?;
+    if disabled {
+        handler.cancel_account_deletion(&event.user_id).await?;
+        return Ok(ProvisioningAction::Reactivated);
+    }
+    Ok(ProvisioningAction::Updated)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn post_webhook<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    body: web::Bytes,
+) -> TcpResult<ProvisioningResult>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let options = &data.provisioning_webhook_options;
+    let get_header = |name: &str| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|h| h.to_str().ok())
+            .ok_or_else(|| TcpError::UnauthorizedError(format!("Missing header {}", name)))
+    };
+    let signature = get_header(&options.signature_header)?;
+    let timestamp = get_header(&options.timestamp_header)?;
+    let delivery_id = get_header(&options.delivery_id_header)?;
+    let valid = options.secret.as_ref().map_or(false, |s| {
+        verify_signature(
+            s.unsecure().as_bytes(),
+            delivery_id,
+            timestamp,
+            &body,
+            signature,
+        )
+    });
+    if !valid {
+        return Err(TcpError::UnauthorizedError("Invalid signature".to_owned()));
+    }
+    let max_age = chrono::Duration::seconds(options.max_age_seconds.into());
+    let timestamp = parse_timestamp(timestamp, chrono::Utc::now(), max_age)
+        .ok_or_else(|| TcpError::UnauthorizedError("Stale delivery".to_owned()))?;
+    if delivery_id.is_empty() || delivery_id.len() > 255 {
+        return Err(TcpError::BadRequest("Invalid delivery ID".to_owned()));
+    }
+    data.check_read_only()?;
+    // The delivery is refused as stale after `max_age`, so it only needs to be remembered until
+    // then.
+    if !data
+        .get_tcp_handler()
+        .register_webhook_delivery(delivery_id, (timestamp + max_age).naive_utc())
+        .await?
+    {
+        return Err(TcpError::UnauthorizedError(format!(
+            "Delivery {} was already received",
+            delivery_id
+        )));
+    }
+    let result = process_delivery(&data, &body).await;
+    if result.is_err() {
+        // Let the sender retry it.
+        data.get_tcp_handler()
+            .forget_webhook_delivery(delivery_id)
+            .await?;
+    }
+    result
+}
+
+async fn process_delivery<Backend>(
+    data: &AppState<Backend>,
+    body: &[u8],
+) -> TcpResult<ProvisioningResult>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let options = &data.provisioning_webhook_options;
+    let payload: Value = serde_json::from_slice(body)
+        .map_err(|e| TcpError::BadRequest(format!("Invalid JSON payload: {}", e)))?;
+    let event = parse_event(&payload, options).map_err(TcpError::BadRequest)?;
+    let user_id = event.user_id.clone();
+    let handler = data.backend_handler.unsafe_get_handler();
+    let grace_period =
+        chrono::Duration::days(data.account_deletion_options.grace_period_days.into());
+    let action = apply_event(handler, event, grace_period).await?;
+    if action == ProvisioningAction::Disabled {
+        // Log the user out everywhere.
+        let new_blacklisted_jwt_hashes = data.get_tcp_handler().blacklist_jwts(&user_id).await?;
+        let mut jwt_blacklist = data.jwt_blacklist.write().unwrap();
+        for jwt_hash in new_blacklisted_jwt_hashes {
+            jwt_blacklist.insert(jwt_hash);
+        }
+    }
+    info!("Provisioning webhook: {:?} {}", action, user_id);
+    Ok(ProvisioningResult { user_id, action })
+}
+
+async fn post_webhook_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    body: web::Bytes,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    post_webhook(data, request, body)
+        .await
+        .map(|result| HttpResponse::Ok().json(result))
+        .unwrap_or_else(error_to_http_response)
+}
+
+pub fn configure_endpoint<Backend>(cfg: &mut web::ServiceConfig)
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    cfg.service(
+        web::resource("/provisioning/webhook")
+            .route(web::post().to(post_webhook_handler::<Backend>)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::sql_backend_handler::{tests::*, SqlBackendHandler},
+        infra::configuration::ProvisioningFieldMappingBuilder,
+    };
+    use pretty_assertions::assert_eq;
+
+    fn sign(secret: &[u8], delivery_id: &str, timestamp: &str, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+        mac.update(format!("{}.{}.", delivery_id, timestamp).as_bytes());
+        mac.update(body);
+        format!("{:x}", mac.finalize().into_bytes())
+    }
+
+    fn event(user_id: &str, active: bool) -> ProvisioningEvent {
+        ProvisioningEvent {
+            user_id: UserId::new(user_id),
+            email: Some(format!("{}@example.com", user_id)),
+            first_name: Some("Ada".to_owned()),
+            last_name: Some("Lovelace".to_owned()),
+            display_name: Some("Ada Lovelace".to_owned()),
+            active,
+        }
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let body = br#"{"username":"ada"}"#;
+        let signature = sign(b"secret", "delivery-1", "1700000000", body);
+        assert!(verify_signature(
+            b"secret",
+            "delivery-1",
+            "1700000000",
+            body,
+            &signature
+        ));
+        assert!(verify_signature(
+            b"secret",
+            "delivery-1",
+            "1700000000",
+            body,
+            &format!("sha256={}", signature.to_uppercase())
+        ));
+        assert!(!verify_signature(
+            b"other secret",
+            "delivery-1",
+            "1700000000",
+            body,
+            &signature
+        ));
+        assert!(!verify_signature(
+            b"secret",
+            "delivery-1",
+            "1700000000",
+            b"{}",
+            &signature
+        ));
+        // The delivery ID and the timestamp can't be changed to replay the payload.
+        assert!(!verify_signature(
+            b"secret",
+            "delivery-2",
+            "1700000000",
+            body,
+            &signature
+        ));
+        assert!(!verify_signature(
+            b"secret",
+            "delivery-1",
+            "1700000001",
+            body,
+            &signature
+        ));
+        assert!(!verify_signature(
+            b"secret",
+            "delivery-1",
+            "1700000000",
+            body,
+            "not hex"
+        ));
+        assert!(!verify_signature(
+            b"secret",
+            "delivery-1",
+            "1700000000",
+            body,
+            ""
+        ));
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        let now = chrono::DateTime::<chrono::Utc>::from_timestamp(1_700_000_000, 0).unwrap();
+        let max_age = chrono::Duration::seconds(300);
+        assert_eq!(parse_timestamp("1700000000", now, max_age), Some(now));
+        assert_eq!(
+            parse_timestamp("1699999700", now, max_age),
+            Some(now - max_age)
+        );
+        assert_eq!(
+            parse_timestamp("1700000300", now, max_age),
+            Some(now + max_age)
+        );
+        assert_eq!(parse_timestamp("1699999699", now, max_age), None);
+        assert_eq!(parse_timestamp("1700000301", now, max_age), None);
+        assert_eq!(parse_timestamp("yesterday", now, max_age), None);
+        assert_eq!(parse_timestamp("", now, max_age), None);
+    }
+
+    #[test]
+    fn test_parse_event() {
+        // Personio-style payload.
+        let options = ProvisioningWebhookOptions {
+            mapping: ProvisioningFieldMappingBuilder::default()
+                .user_id("/employee/id".to_owned())
+                .email("/employee/attributes/email".to_owned())
+                .first_name(Some("/employee/attributes/first_name".to_owned()))
+                .last_name(None)
+                .status(Some("/employee/attributes/status".to_owned()))
+                .build()
+                .unwrap(),
+            ..Default::default()
+        };
+        let payload = serde_json::json!({
+            "employee": {
+                "id": 1234,
+                "attributes": {
+                    "email": "ada@example.com",
+                    "first_name": "Ada",
+                    "status": "Inactive",
+                },
+            },
+        });
+        assert_eq!(
+            parse_event(&payload, &options).unwrap(),
+            ProvisioningEvent {
+                user_id: UserId::new("1234"),
+                email: Some("ada@example.com".to_owned()),
+                first_name: Some("Ada".to_owned()),
+                last_name: None,
+                display_name: Some("Ada".to_owned()),
+                active: false,
+            }
+        );
+        assert!(parse_event(&serde_json::json!({"employee": {}}), &options).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_apply_event() {
+        let handler = SqlBackendHandler::new(get_default_config(), get_initialized_db().await);
+        let grace_period = chrono::Duration::days(30);
+        let ada = UserId::new("ada");
+        assert_eq!(
+            apply_event(&handler, event("ada", false), grace_period)
+                .await
+                .unwrap(),
+            ProvisioningAction::Ignored
+        );
+        assert_eq!(
+            apply_event(&handler, event("ada", true), grace_period)
+                .await
+                .unwrap(),
+            ProvisioningAction::Created
+        );
+        let mut renamed = event("ada", true);
+        renamed.display_name = Some("Ada King".to_owned());
+        assert_eq!(
+            apply_event(&handler, renamed, grace_period).await.unwrap(),
+            ProvisioningAction::Updated
+        );
+        assert_eq!(
+            handler.get_user_details(&ada).await.unwrap().display_name,
+            Some("Ada King".to_owned())
+        );
+        assert_eq!(
+            apply_event(&handler, event("ada", false), grace_period)
+                .await
+                .unwrap(),
+            ProvisioningAction::Disabled
+        );
+        assert!(handler
+            .get_account_deletion(&ada)
+            .await
+            .unwrap()
+            .unwrap()
+            .deletion_date
+            .is_some());
+        assert_eq!(
+            apply_event(&handler, event("ada", false), grace_period)
+                .await
+                .unwrap(),
+            ProvisioningAction::Ignored
+        );
+        assert_eq!(
+            apply_event(&handler, event("ada", true), grace_period)
+                .await
+                .unwrap(),
+            ProvisioningAction::Reactivated
+        );
+        assert_eq!(handler.get_account_deletion(&ada).await.unwrap(), None);
+
+        let admin_group = insert_group(&handler, "lldap_admin").await;
+        insert_membership(&handler, admin_group, "ada").await;
+        let mut renamed = event("ada", false);
+        renamed.display_name = Some("Mallory".to_owned());
+        assert_eq!(
+            apply_event(&handler, renamed, grace_period).await.unwrap(),
+            ProvisioningAction::Protected
+        );
+        assert_eq!(handler.get_account_deletion(&ada).await.unwrap(), None);
+        assert_eq!(
+            handler.get_user_details(&ada).await.unwrap().display_name,
+            Some("Ada King".to_owned())
+        );
+
+        let mut no_email = event("bob", true);
+        no_email.email = None;
+        assert!(apply_event(&handler, no_email, grace_period).await.is_err());
+    }
+}
@@ -0,0 +1,36 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The error returned for the changes refused in read-only mode.
+pub const READ_ONLY_MESSAGE: &str = "LLDAP is in read-only mode, no changes are accepted";
+
+/// Whether all the changes to the directory are refused, e.g. on a replica or during an incident.
+/// Shared by the LDAP and HTTP servers, and toggled at runtime by the admins.
+#[derive(Debug, Default)]
+pub struct ReadOnlyMode {
+    enabled: AtomicBool,
+}
+
+impl ReadOnlyMode {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled: AtomicBool::new(enabled),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// The error message for a refused change, if any.
+    pub fn refusal(&self) -> Option<&'static str> {
+        if self.is_enabled() {
+            Some(READ_ONLY_MESSAGE)
+        } else {
+            None
+        }
+    }
+}
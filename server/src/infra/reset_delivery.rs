@@ -0,0 +1,266 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use secstr::SecUtf8;
+use serde::Serialize;
+use url::Url;
+
+use crate::{
+    domain::{
+        locale::get_preferred_language,
+        types::{AttributeName, User},
+    },
+    infra::{
+        configuration::{MailOptions, PasswordResetChannel, PasswordResetOptions},
+        mail,
+    },
+};
+
+/// The page where the users pick their new password.
+pub fn reset_url(server_url: &Url, token: &str) -> Url {
+    let mut reset_url = server_url.clone();
+    reset_url
+        .path_segments_mut()
+        .unwrap()
+        .extend(["reset-password", "step2", token]);
+    reset_url
+}
+
+/// A way of sending the password reset links to the users.
+#[async_trait]
+pub trait ResetDelivery: Send + Sync {
+    async fn send_reset_link(&self, user: &User, token: &str) -> Result<()>;
+}
+
+struct EmailDelivery {
+    server_url: Url,
+    options: MailOptions,
+}
+
+#[async_trait]
+impl ResetDelivery for EmailDelivery {
+    async fn send_reset_link(&self, user: &User, token: &str) -> Result<()> {
+        mail::send_password_reset_email(
+            user.display_name
+                .as_deref()
+                .unwrap_or_else(|| user.user_id.as_str()),
+            user.email.as_str(),
+            get_preferred_language(user).as_deref(),
+            token,
+            &self.server_url,
+            &self.options,
+        )
+        .await
+    }
+}
+
+async fn post(
+    request: reqwest::RequestBuilder,
+    authorization: &Option<SecUtf8>,
+) -> Result<reqwest::Response> {
+    let request = match authorization {
+        Some(authorization) => {
+            request.header(reqwest::header::AUTHORIZATION, authorization.unsecure())
+        }
+        None => request,
+    };
+    Ok(request.send().await?.error_for_status()?)
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    user_id: &'a str,
+    display_name: Option<&'a str>,
+    email: &'a str,
+    reset_url: String,
+}
+
+struct WebhookDelivery {
+    client: reqwest::Client,
+    server_url: Url,
+    url: Url,
+    authorization: Option<SecUtf8>,
+}
+
+#[async_trait]
+impl ResetDelivery for WebhookDelivery {
+    async fn send_reset_link(&self, user: &User, token: &str) -> Result<()> {
+        let payload = WebhookPayload {
+            user_id: user.user_id.as_str(),
+            display_name: user.display_name.as_deref(),
+            email: user.email.as_str(),
+            reset_url: reset_url(&self.server_url, token).to_string(),
+        };
+        post(
+            self.client
+                .post(self.url.clone())
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(serde_json::to_string(&payload)?),
+            &self.authorization,
+        )
+        .await
+        .context("while calling the password reset webhook")?;
+        Ok(())
+    }
+}
+
+struct SmsDelivery {
+    client: reqwest::Client,
+    server_url: Url,
+    url: Url,
+    authorization: Option<SecUtf8>,
+    phone_attribute: AttributeName,
+    to_field: String,
+    message_field: String,
+}
+
+impl SmsDelivery {
+    fn get_phone_number(&self, user: &User) -> Option<String> {
+        user.attributes
+            .iter()
+            .find(|a| a.name == self.phone_attribute)
+            .map(|a| a.value.unwrap::<String>())
+            .filter(|phone| !phone.trim().is_empty())
+    }
+}
+
+#[async_trait]
+impl ResetDelivery for SmsDelivery {
+    async fn send_reset_link(&self, user: &User, token: &str) -> Result<()> {
+        let phone = self.get_phone_number(user).ok_or_else(|| {
+            anyhow!(
+                "The user {} has no phone number in the attribute {}",
+                user.user_id,
+                self.phone_attribute
+            )
+        })?;
+        let message = format!(
+            "LLDAP password reset for {}: {}",
+            user.user_id,
+            reset_url(&self.server_url, token)
+        );
+        post(
+            self.client.post(self.url.clone()).form(&[
+                (self.to_field.as_str(), phone.as_str()),
+                (self.message_field.as_str(), message.as_str()),
+            ]),
+            &self.authorization,
+        )
+        .await
+        .context("while calling the SMS gateway")?;
+        Ok(())
+    }
+}
+
+/// The configured delivery, or None if the password reset is disabled. The email channel is
+/// enabled by `smtp_options.enable_password_reset`, the others as soon as they are selected.
+pub fn from_options(
+    options: &PasswordResetOptions,
+    mail_options: &MailOptions,
+    server_url: &Url,
+) -> Result<Option<Arc<dyn ResetDelivery>>> {
+    let server_url = server_url.clone();
+    let missing_url = |field: &str| {
+        anyhow!(
+            "password_reset_options.{} is required for the {:?} channel",
+            field,
+            options.channel
+        )
+    };
+    Ok(match options.channel {
+        PasswordResetChannel::Email if !mail_options.enable_password_reset => None,
+        PasswordResetChannel::Email => Some(Arc::new(EmailDelivery {
+            server_url,
+            options: mail_options.clone(),
+        })),
+        PasswordResetChannel::Webhook => Some(Arc::new(WebhookDelivery {
+            client: reqwest::Client::new(),
+            server_url,
+            url: options
+                .webhook_url
+                .clone()
+                .ok_or_else(|| missing_url("webhook_url"))?,
+            authorization: options.authorization.clone(),
+        })),
+        PasswordResetChannel::Sms => Some(Arc::new(SmsDelivery {
+            client: reqwest::Client::new(),
+            server_url,
+            url: options
+                .sms_gateway_url
+                .clone()
+                .ok_or_else(|| missing_url("sms_gateway_url"))?,
+            authorization: options.authorization.clone(),
+            phone_attribute: options.sms_phone_attribute.clone(),
+            to_field: options.sms_to_field.clone(),
+            message_field: options.sms_message_field.clone(),
+        })),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::types::{AttributeValue, Serialized, UserId},
+        infra::configuration::PasswordResetOptionsBuilder,
+    };
+
+    #[test]
+    fn test_reset_url() {
+        assert_eq!(
+            reset_url(&Url::parse("https://lldap.example.com/").unwrap(), "abc").as_str(),
+            "https://lldap.example.com/reset-password/step2/abc"
+        );
+    }
+
+    #[test]
+    fn test_from_options() {
+        let server_url = Url::parse("http://localhost").unwrap();
+        let mail_options = MailOptions::default();
+        assert!(
+            from_options(&PasswordResetOptions::default(), &mail_options, &server_url)
+                .unwrap()
+                .is_none()
+        );
+        let webhook = PasswordResetOptionsBuilder::default()
+            .channel(PasswordResetChannel::Webhook)
+            .build()
+            .unwrap();
+        assert!(from_options(&webhook, &mail_options, &server_url).is_err());
+        let webhook = PasswordResetOptionsBuilder::default()
+            .channel(PasswordResetChannel::Webhook)
+            .webhook_url(Some(Url::parse("http://bot.local/reset").unwrap()))
+            .build()
+            .unwrap();
+        assert!(from_options(&webhook, &mail_options, &server_url)
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_get_phone_number() {
+        let delivery = SmsDelivery {
+            client: reqwest::Client::new(),
+            server_url: Url::parse("http://localhost").unwrap(),
+            url: Url::parse("http://sms.local/send").unwrap(),
+            authorization: None,
+            phone_attribute: "Phone".into(),
+            to_field: "to".to_owned(),
+            message_field: "message".to_owned(),
+        };
+        let mut user = User {
+            user_id: UserId::new("bob"),
+            ..Default::default()
+        };
+        assert_eq!(delivery.get_phone_number(&user), None);
+        user.attributes.push(AttributeValue {
+            name: "phone".into(),
+            value: Serialized::from("+49 341 1234"),
+        });
+        assert_eq!(
+            delivery.get_phone_number(&user),
+            Some("+49 341 1234".to_owned())
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use crate::domain::{
+    handler::{
+        CreateGroupRequest, CreateUserRequest, GroupBackendHandler, UserBackendHandler,
+        UserCreationSource,
+    },
+    types::{GroupId, UserId},
+};
+use anyhow::{Context, Result};
+use rand::{rngs::SmallRng, seq::SliceRandom, Rng, SeedableRng};
+use serde::Serialize;
+use tracing::info;
+
+const FIRST_NAMES: &[&str] = &[
+    "Anna", "Ben", "Clara", "David", "Emma", "Felix", "Greta", "Hugo", "Ida", "Jonas", "Karla",
+    "Leon", "Mia", "Noah", "Olga", "Paul", "Quinn", "Rosa", "Simon", "Tara", "Umar", "Vera",
+    "William", "Xenia", "Yusuf", "Zoe",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Schmidt",
+    "Mueller",
+    "Garcia",
+    "Nguyen",
+    "Kowalski",
+    "Rossi",
+    "Smith",
+    "Johansson",
+    "Yilmaz",
+    "Dubois",
+    "Novak",
+    "Silva",
+    "Tanaka",
+    "Okafor",
+    "Weber",
+    "Fischer",
+    "Jensen",
+    "Costa",
+];
+
+const GROUP_NAMES: &[&str] = &[
+    "engineering",
+    "marketing",
+    "sales",
+    "support",
+    "finance",
+    "legal",
+    "design",
+    "operations",
+    "research",
+    "workshop",
+];
+
+#[derive(Clone, Debug)]
+pub struct SeedOptions {
+    pub users: usize,
+    pub groups: usize,
+    /// Maximum number of groups each user is added to.
+    pub memberships_per_user: usize,
+    /// Seed of the random generator, to get the same data on every run.
+    pub seed: Option<u64>,
+}
+
+/// What was created.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SeedSummary {
+    pub users: usize,
+    pub groups: usize,
+    pub memberships: usize,
+}
+
+fn fake_user(rng: &mut impl Rng, index: usize) -> CreateUserRequest {
+    let first_name = FIRST_NAMES.choose(rng).unwrap();
+    let last_name = LAST_NAMES.choose(rng).unwrap();
+    let user_id = format!(
+        "{}.{}.{}",
+        first_name.to_lowercase(),
+        last_name.to_lowercase(),
+        index
+    );
+    CreateUserRequest {
+        email: format!("{}@example.com", user_id).into(),
+        user_id: UserId::new(&user_id),
+        display_name: Some(format!("{} {}", first_name, last_name)),
+        first_name: Some(first_name.to_string()),
+        last_name: Some(last_name.to_string()),
+        source: UserCreationSource::Import,
+        ..Default::default()
+    }
+}
+
+fn fake_group_name(index: usize) -> String {
+    format!(
+        "{}-{}",
+        GROUP_NAMES[index % GROUP_NAMES.len()],
+        index / GROUP_NAMES.len() + 1
+    )
+}
+
+/// Creates fake users and groups, and adds each user to a random set of groups.
+pub async fn seed<Handler: GroupBackendHandler + UserBackendHandler>(
+    handler: &Handler,
+    options: &SeedOptions,
+) -> Result<SeedSummary> {
+    let mut rng = match options.seed {
+        Some(seed) => SmallRng::seed_from_u64(seed),
+        None => SmallRng::from_entropy(),
+    };
+    let mut groups: Vec<GroupId> = Vec::with_capacity(options.groups);
+    for index in 0..options.groups {
+        let display_name = fake_group_name(index);
+        groups.push(
+            handler
+                .create_group(CreateGroupRequest {
+                    display_name: display_name.as_str().into(),
+                    ..Default::default()
+                })
+                .await
+                .with_context(|| format!("while creating the group {}", display_name))?,
+        );
+    }
+    info!("Created {} groups", groups.len());
+    let memberships_per_user = options.memberships_per_user.min(groups.len());
+    let mut memberships = 0;
+    for index in 0..options.users {
+        let request = fake_user(&mut rng, index);
+        let user_id = request.user_id.clone();
+        handler
+            .create_user(request)
+            .await
+            .with_context(|| format!("while creating the user {}", user_id))?;
+        let count = rng.gen_range(0..=memberships_per_user);
+        for group_id in groups.choose_multiple(&mut rng, count) {
+            handler.add_user_to_group(&user_id, *group_id).await?;
+            memberships += 1;
+        }
+        if (index + 1) % 1000 == 0 {
+            info!("Created {} users", index + 1);
+        }
+    }
+    info!("Created {} users", options.users);
+    Ok(SeedSummary {
+        users: options.users,
+        groups: groups.len(),
+        memberships,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{GroupListerBackendHandler, UserListerBackendHandler},
+        sql_backend_handler::{tests::*, SqlBackendHandler},
+    };
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_seed() {
+        let handler = SqlBackendHandler::new(get_default_config(), get_initialized_db().await);
+        let summary = seed(
+            &handler,
+            &SeedOptions {
+                users: 30,
+                groups: 12,
+                memberships_per_user: 3,
+                seed: Some(42),
+            },
+        )
+        .await
+        .unwrap();
+        let users = handler.list_users(None, true).await.unwrap();
+        assert_eq!(users.len(), 30);
+        assert!(users.iter().all(|u| u.groups.as_ref().unwrap().len() <= 3));
+        assert_eq!(
+            summary.memberships,
+            users
+                .iter()
+                .map(|u| u.groups.as_ref().unwrap().len())
+                .sum::<usize>()
+        );
+        let groups = handler.list_groups(None).await.unwrap();
+        assert_eq!(groups.len(), 12);
+        assert!(groups
+            .iter()
+            .any(|g| g.display_name.as_str() == "engineering-2"));
+    }
+}
@@ -0,0 +1,218 @@
+use crate::infra::{
+    configuration::{Configuration, ConfigurationBuilder},
+    database_string::DatabaseUrl,
+    ldap_client::LdapClient,
+};
+use anyhow::{ensure, Context, Result};
+use ldap3_proto::proto::LdapFilter;
+use lldap_auth::{login, opaque, registration};
+use rand::distributions::{Alphanumeric, DistString};
+use secstr::SecUtf8;
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::{info, instrument};
+
+/// Returns two distinct ports that are free at the time of the call.
+pub(crate) fn get_free_ports() -> Result<(u16, u16)> {
+    let bind =
+        || std::net::TcpListener::bind("127.0.0.1:0").context("while looking for a free port");
+    let (first, second) = (bind()?, bind()?);
+    Ok((first.local_addr()?.port(), second.local_addr()?.port()))
+}
+
+pub(crate) fn random_secret() -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), 32)
+}
+
+/// Configuration for a throwaway server listening on local ports, with its database at
+/// `database_path`.
+pub fn get_config(database_path: &std::path::Path, verbose: bool) -> Result<Configuration> {
+    let (ldap_port, http_port) = get_free_ports()?;
+    ConfigurationBuilder::default()
+        .ldap_host("127.0.0.1".to_owned())
+        .ldap_port(ldap_port)
+        .http_host("127.0.0.1".to_owned())
+        .http_port(http_port)
+        .database_url(DatabaseUrl::from(
+            format!("sqlite://{}?mode=rwc", database_path.display()).as_str(),
+        ))
+        .jwt_secret(SecUtf8::from(random_secret()))
+        .ldap_user_pass(SecUtf8::from(random_secret()))
+        .key_file(String::new())
+        .key_seed(Some(SecUtf8::from(random_secret())))
+        .verbose(verbose)
+        .build()
+}
+
+struct HttpClient {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl HttpClient {
+    async fn send(&self, path: &str, token: Option<&str>, body: &impl Serialize) -> Result<String> {
+        let mut request = self
+            .client
+            .post(format!("{}{}", self.base_url, path))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_string(body)?);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("while calling {}", path))?
+            .error_for_status()
+            .with_context(|| format!("while calling {}", path))?;
+        Ok(response.text().await?)
+    }
+
+    async fn post<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        token: Option<&str>,
+        body: &impl Serialize,
+    ) -> Result<T> {
+        serde_json::from_str(&self.send(path, token, body).await?)
+            .with_context(|| format!("Invalid answer from {}", path))
+    }
+}
+
+#[instrument(skip_all, level = "info", err)]
+async fn check_ldap_bind(config: &Configuration, user_dn: &str) -> Result<()> {
+    let mut client = LdapClient::connect(&format!("127.0.0.1:{}", config.ldap_port)).await?;
+    ensure!(
+        client
+            .bind(user_dn, config.ldap_user_pass.unsecure())
+            .await?,
+        "The admin could not bind"
+    );
+    let entries = client
+        .search(
+            &format!("ou=people,{}", config.ldap_base_dn),
+            LdapFilter::Equality("uid".to_owned(), config.ldap_user_dn.to_string()),
+            &["uid", "mail"],
+        )
+        .await?;
+    ensure!(
+        entries.len() == 1 && entries[0].dn == user_dn,
+        "Expected the admin in the search results, got {:?}",
+        entries
+    );
+    info!("Success");
+    Ok(())
+}
+
+#[instrument(skip_all, level = "info", err)]
+async fn check_login(config: &Configuration, client: &HttpClient) -> Result<String> {
+    let response: login::ServerLoginResponse = client
+        .post(
+            "/auth/simple/login",
+            None,
+            &login::ClientSimpleLoginRequest {
+                username: config.ldap_user_dn.clone(),
+                password: config.ldap_user_pass.unsecure().to_owned(),
+                remember_me: false,
+            },
+        )
+        .await?;
+    let user: serde_json::Value = client
+        .post(
+            "/api/graphql",
+            Some(&response.token),
+            &serde_json::json!({
+                "query": "query GetUser($id: String!) { user(userId: $id) { id } }",
+                "variables": { "id": config.ldap_user_dn.as_str() },
+            }),
+        )
+        .await?;
+    ensure!(
+        user["data"]["user"]["id"] == config.ldap_user_dn.as_str(),
+        "Unexpected GraphQL answer: {}",
+        user
+    );
+    info!("Success");
+    Ok(response.token)
+}
+
+#[instrument(skip_all, level = "info", err)]
+async fn check_password_change(
+    config: &Configuration,
+    client: &HttpClient,
+    token: &str,
+    user_dn: &str,
+) -> Result<()> {
+    let new_password = random_secret();
+    let mut rng = rand::rngs::OsRng;
+    let registration_start_request =
+        opaque::client::registration::start_registration(new_password.as_bytes(), &mut rng)?;
+    let response: registration::ServerRegistrationStartResponse = client
+        .post(
+            "/auth/opaque/register/start",
+            Some(token),
+            &registration::ClientRegistrationStartRequest {
+                username: config.ldap_user_dn.clone(),
+                registration_start_request: registration_start_request.message,
+            },
+        )
+        .await?;
+    let registration_finish = opaque::client::registration::finish_registration(
+        registration_start_request.state,
+        response.registration_response,
+        &mut rng,
+    )?;
+    client
+        .send(
+            "/auth/opaque/register/finish",
+            Some(token),
+            &registration::ClientRegistrationFinishRequest {
+                server_data: response.server_data,
+                registration_upload: registration_finish.message,
+            },
+        )
+        .await?;
+
+    let mut ldap = LdapClient::connect(&format!("127.0.0.1:{}", config.ldap_port)).await?;
+    ensure!(
+        ldap.bind(user_dn, &new_password).await?,
+        "Could not bind with the new password"
+    );
+    ensure!(
+        !ldap.bind(user_dn, config.ldap_user_pass.unsecure()).await?,
+        "Could still bind with the old password"
+    );
+    info!("Success");
+    Ok(())
+}
+
+/// Runs the checks against a server started with `config`: an LDAP bind and search, a login
+/// and a GraphQL query, and a password change.
+pub async fn run(config: &Configuration) -> Result<()> {
+    let user_dn = format!(
+        "uid={},ou=people,{}",
+        config.ldap_user_dn, config.ldap_base_dn
+    );
+    let client = HttpClient {
+        client: reqwest::Client::new(),
+        base_url: format!("http://127.0.0.1:{}", config.http_port),
+    };
+    check_ldap_bind(config, &user_dn).await?;
+    let token = check_login(config, &client).await?;
+    check_password_change(config, &client, &token, &user_dn).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_config() {
+        let config = get_config(std::path::Path::new("/tmp/lldap-test.db"), false).unwrap();
+        assert_eq!(
+            config.database_url.to_string(),
+            "sqlite:///tmp/lldap-test.db?mode=rwc"
+        );
+        assert_ne!(config.ldap_port, config.http_port);
+        assert_ne!(config.ldap_user_pass, SecUtf8::from("password"));
+    }
+}
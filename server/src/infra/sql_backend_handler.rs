@@ -1,27 +1,29 @@
-use super::tcp_backend_handler::TcpBackendHandler;
+use super::{configuration::LoginThrottlingOptions, tcp_backend_handler::TcpBackendHandler};
 use crate::domain::{
     error::*,
-    model::{self, JwtRefreshStorageColumn, JwtStorageColumn, PasswordResetTokensColumn},
+    model::{
+        self, JwtRefreshStorageColumn, JwtStorageColumn, LoginThrottlesColumn,
+        PasswordResetTokensColumn, WebhookDeliveriesColumn,
+    },
+    secret_tokens::{check_verifier, split_token, SecretToken},
     sql_backend_handler::SqlBackendHandler,
     types::UserId,
 };
 use async_trait::async_trait;
 use chrono::NaiveDateTime;
 use sea_orm::{
-    sea_query::{Cond, Expr},
-    ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, QuerySelect,
+    sea_query::{Cond, Expr, OnConflict},
+    ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, QuerySelect, Set,
 };
 use std::collections::HashSet;
 use tracing::{debug, instrument};
 
-fn gen_random_string(len: usize) -> String {
-    use rand::{distributions::Alphanumeric, rngs::SmallRng, Rng, SeedableRng};
-    let mut rng = SmallRng::from_entropy();
-    std::iter::repeat(())
-        .map(|()| rng.sample(Alphanumeric))
-        .map(char::from)
-        .take(len)
-        .collect()
+/// The refresh tokens are found by a hash of their selector, which fits in the primary key. The
+/// hash is stored, so it must not change between builds.
+fn refresh_token_key(selector: &str) -> i64 {
+    use sha2::{Digest, Sha256};
+    let hash = Sha256::digest(selector.as_bytes());
+    i64::from_be_bytes(hash[..8].try_into().unwrap())
 }
 
 #[async_trait]
@@ -41,26 +43,22 @@ impl TcpBackendHandler for SqlBackendHandler {
     }
 
     #[instrument(skip_all, level = "debug")]
-    async fn create_refresh_token(&self, user: &UserId) -> Result<(String, chrono::Duration)> {
+    async fn create_refresh_token(
+        &self,
+        user: &UserId,
+        lifetime: chrono::Duration,
+    ) -> Result<String> {
         debug!(?user);
-        // TODO: Initialize the rng only once. Maybe Arc<Cell>?
-        let refresh_token = gen_random_string(100);
-        let refresh_token_hash = {
-            use std::collections::hash_map::DefaultHasher;
-            use std::hash::{Hash, Hasher};
-            let mut s = DefaultHasher::new();
-            refresh_token.hash(&mut s);
-            s.finish()
-        };
-        let duration = chrono::Duration::days(30);
+        let refresh_token = SecretToken::generate();
         let new_token = model::jwt_refresh_storage::Model {
-            refresh_token_hash: refresh_token_hash as i64,
+            refresh_token_hash: refresh_token_key(&refresh_token.selector),
+            verifier_hash: refresh_token.hash,
             user_id: user.clone(),
-            expiry_date: chrono::Utc::now().naive_utc() + duration,
+            expiry_date: chrono::Utc::now().naive_utc() + lifetime,
         }
         .into_active_model();
         new_token.insert(&self.sql_pool).await?;
-        Ok((refresh_token, duration))
+        Ok(refresh_token.token)
     }
 
     #[instrument(skip_all, level = "debug")]
@@ -83,14 +81,24 @@ impl TcpBackendHandler for SqlBackendHandler {
     }
 
     #[instrument(skip_all, level = "debug")]
-    async fn check_token(&self, refresh_token_hash: u64, user: &UserId) -> Result<bool> {
+    async fn check_token(
+        &self,
+        refresh_token: &str,
+        user: &UserId,
+    ) -> Result<Option<NaiveDateTime>> {
         debug!(?user);
+        let Some((selector, verifier)) = split_token(refresh_token) else {
+            return Ok(None);
+        };
+        // The expired tokens are only deleted periodically.
         Ok(
-            model::JwtRefreshStorage::find_by_id(refresh_token_hash as i64)
+            model::JwtRefreshStorage::find_by_id(refresh_token_key(selector))
                 .filter(JwtRefreshStorageColumn::UserId.eq(user))
+                .filter(JwtRefreshStorageColumn::ExpiryDate.gt(chrono::Utc::now().naive_utc()))
                 .one(&self.sql_pool)
                 .await?
-                .is_some(),
+                .filter(|t| check_verifier(verifier, &t.verifier_hash))
+                .map(|t| t.expiry_date),
         )
     }
 
@@ -120,10 +128,21 @@ impl TcpBackendHandler for SqlBackendHandler {
     }
 
     #[instrument(skip_all, level = "debug")]
-    async fn delete_refresh_token(&self, refresh_token_hash: u64) -> Result<()> {
-        model::JwtRefreshStorage::delete_by_id(refresh_token_hash as i64)
-            .exec(&self.sql_pool)
-            .await?;
+    async fn delete_refresh_token(&self, refresh_token: &str) -> Result<()> {
+        let Some((selector, verifier)) = split_token(refresh_token) else {
+            return Ok(());
+        };
+        // Knowing the selector isn't enough to log someone out.
+        let key = refresh_token_key(selector);
+        if model::JwtRefreshStorage::find_by_id(key)
+            .one(&self.sql_pool)
+            .await?
+            .is_some_and(|t| check_verifier(verifier, &t.verifier_hash))
+        {
+            model::JwtRefreshStorage::delete_by_id(key)
+                .exec(&self.sql_pool)
+                .await?;
+        }
         Ok(())
     }
 
@@ -139,40 +158,337 @@ impl TcpBackendHandler for SqlBackendHandler {
             return Ok(None);
         }
 
-        let token = gen_random_string(100);
+        let token = SecretToken::generate();
         let duration = chrono::Duration::minutes(10);
 
         let new_token = model::password_reset_tokens::Model {
-            token: token.clone(),
+            token: token.selector,
+            verifier_hash: token.hash,
             user_id: user.clone(),
             expiry_date: chrono::Utc::now().naive_utc() + duration,
         }
         .into_active_model();
         new_token.insert(&self.sql_pool).await?;
-        Ok(Some(token))
+        Ok(Some(token.token))
     }
 
     #[instrument(skip_all, level = "debug", ret)]
     async fn get_user_id_for_password_reset_token(&self, token: &str) -> Result<UserId> {
-        Ok(model::PasswordResetTokens::find_by_id(token.to_owned())
+        let invalid = || DomainError::EntityNotFound("Invalid reset token".to_owned());
+        let (selector, verifier) = split_token(token).ok_or_else(invalid)?;
+        Ok(model::PasswordResetTokens::find_by_id(selector.to_owned())
             .filter(PasswordResetTokensColumn::ExpiryDate.gt(chrono::Utc::now().naive_utc()))
             .one(&self.sql_pool)
             .await?
-            .ok_or_else(|| DomainError::EntityNotFound("Invalid reset token".to_owned()))?
+            .filter(|t| check_verifier(verifier, &t.verifier_hash))
+            .ok_or_else(invalid)?
             .user_id)
     }
 
     #[instrument(skip_all, level = "debug")]
     async fn delete_password_reset_token(&self, token: &str) -> Result<()> {
-        let result = model::PasswordResetTokens::delete_by_id(token.to_owned())
+        let not_found = || DomainError::EntityNotFound("No such password reset token".to_owned());
+        let (selector, _) = split_token(token).ok_or_else(not_found)?;
+        let result = model::PasswordResetTokens::delete_by_id(selector.to_owned())
             .exec(&self.sql_pool)
             .await?;
         if result.rows_affected == 0 {
-            return Err(DomainError::EntityNotFound(format!(
-                "No such password reset token: '{}'",
-                token
-            )));
+            return Err(not_found());
         }
         Ok(())
     }
+
+    #[instrument(skip_all, level = "debug", ret)]
+    async fn get_login_lockout(&self, key: &str) -> Result<Option<NaiveDateTime>> {
+        let now = chrono::Utc::now().naive_utc();
+        Ok(model::LoginThrottles::find_by_id(key.to_owned())
+            .one(&self.sql_pool)
+            .await?
+            .and_then(|t| t.locked_until)
+            .filter(|locked_until| locked_until > &now))
+    }
+
+    #[instrument(skip(self, options), level = "debug", ret)]
+    async fn record_login_failure(
+        &self,
+        key: &str,
+        options: &LoginThrottlingOptions,
+    ) -> Result<Option<NaiveDateTime>> {
+        let now = chrono::Utc::now().naive_utc();
+        let window = chrono::Duration::minutes(options.window_minutes.into());
+        let (failures, window_start) = match model::LoginThrottles::find_by_id(key.to_owned())
+            .one(&self.sql_pool)
+            .await?
+        {
+            Some(t) if t.window_start + window > now => (t.failures + 1, t.window_start),
+            _ => (1, now),
+        };
+        let locked_until = if failures as u32 >= options.max_failures {
+            Some(now + chrono::Duration::minutes(options.lockout_minutes.into()))
+        } else {
+            None
+        };
+        // The failures that triggered a lockout don't count towards the next one.
+        let (failures, window_start) = match locked_until {
+            Some(_) => (0, now),
+            None => (failures, window_start),
+        };
+        model::LoginThrottles::insert(model::login_throttles::ActiveModel {
+            key: Set(key.to_owned()),
+            failures: Set(failures),
+            window_start: Set(window_start),
+            locked_until: Set(locked_until),
+            expiry_date: Set(std::cmp::max(
+                window_start + window,
+                locked_until.unwrap_or(now),
+            )),
+        })
+        .on_conflict(
+            OnConflict::column(LoginThrottlesColumn::Key)
+                .update_columns([
+                    LoginThrottlesColumn::Failures,
+                    LoginThrottlesColumn::WindowStart,
+                    LoginThrottlesColumn::LockedUntil,
+                    LoginThrottlesColumn::ExpiryDate,
+                ])
+                .to_owned(),
+        )
+        .exec(&self.sql_pool)
+        .await?;
+        Ok(locked_until)
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn clear_login_failures(&self, key: &str) -> Result<()> {
+        model::LoginThrottles::delete_by_id(key.to_owned())
+            .exec(&self.sql_pool)
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", ret)]
+    async fn register_webhook_delivery(
+        &self,
+        delivery_id: &str,
+        expiry_date: NaiveDateTime,
+    ) -> Result<bool> {
+        // The insertion fails on the existing rows, so that two concurrent replays can't both
+        // get through.
+        let inserted = model::WebhookDeliveries::insert(model::webhook_deliveries::ActiveModel {
+            delivery_id: Set(delivery_id.to_owned()),
+            expiry_date: Set(expiry_date),
+        })
+        .on_conflict(
+            OnConflict::column(WebhookDeliveriesColumn::DeliveryId)
+                .do_nothing()
+                .to_owned(),
+        )
+        .exec_without_returning(&self.sql_pool)
+        .await?;
+        Ok(inserted == 1)
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn forget_webhook_delivery(&self, delivery_id: &str) -> Result<()> {
+        model::WebhookDeliveries::delete_by_id(delivery_id.to_owned())
+            .exec(&self.sql_pool)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::{
+            jwt_sql_tables::init_table,
+            sql_backend_handler::tests::{
+                get_default_config, get_initialized_db, insert_user_no_password,
+            },
+        },
+        infra::configuration::LoginThrottlingOptionsBuilder,
+    };
+
+    #[tokio::test]
+    async fn test_login_throttling() {
+        let sql_pool = get_initialized_db().await;
+        init_table(&sql_pool).await.unwrap();
+        let handler = SqlBackendHandler::new(get_default_config(), sql_pool.clone());
+        let options = LoginThrottlingOptionsBuilder::default()
+            .max_failures(3)
+            .build()
+            .unwrap();
+        for _ in 0..2 {
+            assert_eq!(
+                handler
+                    .record_login_failure("user:bob", &options)
+                    .await
+                    .unwrap(),
+                None
+            );
+        }
+        assert_eq!(handler.get_login_lockout("user:bob").await.unwrap(), None);
+        let locked_until = handler
+            .record_login_failure("user:bob", &options)
+            .await
+            .unwrap()
+            .unwrap();
+        // The lockout is stored in the DB, so it survives a restart.
+        let restarted = SqlBackendHandler::new(get_default_config(), sql_pool);
+        assert_eq!(
+            restarted.get_login_lockout("user:bob").await.unwrap(),
+            Some(locked_until)
+        );
+        assert_eq!(
+            restarted.get_login_lockout("ip:192.0.2.1").await.unwrap(),
+            None
+        );
+        restarted.clear_login_failures("user:bob").await.unwrap();
+        assert_eq!(restarted.get_login_lockout("user:bob").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_deliveries() {
+        let sql_pool = get_initialized_db().await;
+        init_table(&sql_pool).await.unwrap();
+        let handler = SqlBackendHandler::new(get_default_config(), sql_pool);
+        let expiry_date = chrono::Utc::now().naive_utc() + chrono::Duration::minutes(5);
+        assert!(handler
+            .register_webhook_delivery("delivery-1", expiry_date)
+            .await
+            .unwrap());
+        assert!(!handler
+            .register_webhook_delivery("delivery-1", expiry_date)
+            .await
+            .unwrap());
+        assert!(handler
+            .register_webhook_delivery("delivery-2", expiry_date)
+            .await
+            .unwrap());
+        handler.forget_webhook_delivery("delivery-1").await.unwrap();
+        assert!(handler
+            .register_webhook_delivery("delivery-1", expiry_date)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_expiry() {
+        let sql_pool = get_initialized_db().await;
+        init_table(&sql_pool).await.unwrap();
+        let handler = SqlBackendHandler::new(get_default_config(), sql_pool);
+        insert_user_no_password(&handler, "bob").await;
+        let bob = UserId::new("bob");
+        let token = handler
+            .create_refresh_token(&bob, chrono::Duration::days(7))
+            .await
+            .unwrap();
+        let expiry_date = handler.check_token(&token, &bob).await.unwrap().unwrap();
+        assert!(expiry_date > chrono::Utc::now().naive_utc() + chrono::Duration::days(6));
+        assert_eq!(
+            handler
+                .check_token(&token, &UserId::new("patrick"))
+                .await
+                .unwrap(),
+            None
+        );
+        let expired = handler
+            .create_refresh_token(&bob, chrono::Duration::seconds(-1))
+            .await
+            .unwrap();
+        assert_eq!(handler.check_token(&expired, &bob).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_tokens_are_stored_hashed() {
+        let sql_pool = get_initialized_db().await;
+        init_table(&sql_pool).await.unwrap();
+        let handler = SqlBackendHandler::new(get_default_config(), sql_pool);
+        insert_user_no_password(&handler, "bob").await;
+        let bob = UserId::new("bob");
+        let refresh_token = handler
+            .create_refresh_token(&bob, chrono::Duration::days(7))
+            .await
+            .unwrap();
+        let reset_token = handler.start_password_reset(&bob).await.unwrap().unwrap();
+        let stored = serde_json::to_string(&(
+            model::JwtRefreshStorage::find()
+                .all(&handler.sql_pool)
+                .await
+                .unwrap(),
+            model::PasswordResetTokens::find()
+                .all(&handler.sql_pool)
+                .await
+                .unwrap(),
+        ))
+        .unwrap();
+        for token in [&refresh_token, &reset_token] {
+            let (_, verifier) = split_token(token).unwrap();
+            assert!(!stored.contains(verifier), "{}", stored);
+        }
+
+        // Only the exact token is accepted.
+        let tampered = |token: &str| format!("{}0", token);
+        assert!(handler
+            .check_token(&refresh_token, &bob)
+            .await
+            .unwrap()
+            .is_some());
+        assert_eq!(
+            handler
+                .check_token(&tampered(&refresh_token), &bob)
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            handler
+                .get_user_id_for_password_reset_token(&reset_token)
+                .await
+                .unwrap(),
+            bob
+        );
+        assert!(handler
+            .get_user_id_for_password_reset_token(&tampered(&reset_token))
+            .await
+            .is_err());
+        assert!(handler
+            .get_user_id_for_password_reset_token(&reset_token[..20])
+            .await
+            .is_err());
+        handler
+            .delete_password_reset_token(&reset_token)
+            .await
+            .unwrap();
+        assert!(handler
+            .get_user_id_for_password_reset_token(&reset_token)
+            .await
+            .is_err());
+
+        // The selector alone can't revoke a refresh token.
+        handler
+            .delete_refresh_token(&tampered(&refresh_token))
+            .await
+            .unwrap();
+        assert!(handler
+            .check_token(&refresh_token, &bob)
+            .await
+            .unwrap()
+            .is_some());
+        handler.delete_refresh_token(&refresh_token).await.unwrap();
+        assert_eq!(
+            handler.check_token(&refresh_token, &bob).await.unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_refresh_token_key_is_stable() {
+        // The keys are stored: they must not depend on the build.
+        assert_eq!(
+            refresh_token_key("abcdefghijklmnopqrst"),
+            -2493324446868321388
+        );
+    }
 }
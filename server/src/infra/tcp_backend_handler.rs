@@ -2,21 +2,33 @@ use async_trait::async_trait;
 use chrono::NaiveDateTime;
 use std::collections::HashSet;
 
-use crate::domain::{error::Result, types::UserId};
+use crate::{
+    domain::{error::Result, types::UserId},
+    infra::configuration::LoginThrottlingOptions,
+};
 
 #[async_trait]
 pub trait TcpBackendHandler: Sync {
     async fn get_jwt_blacklist(&self) -> anyhow::Result<HashSet<u64>>;
-    async fn create_refresh_token(&self, user: &UserId) -> Result<(String, chrono::Duration)>;
+    async fn create_refresh_token(
+        &self,
+        user: &UserId,
+        lifetime: chrono::Duration,
+    ) -> Result<String>;
     async fn register_jwt(
         &self,
         user: &UserId,
         jwt_hash: u64,
         expiry_date: NaiveDateTime,
     ) -> Result<()>;
-    async fn check_token(&self, refresh_token_hash: u64, user: &UserId) -> Result<bool>;
+    /// The expiry date of the refresh token, if it is valid.
+    async fn check_token(
+        &self,
+        refresh_token: &str,
+        user: &UserId,
+    ) -> Result<Option<NaiveDateTime>>;
     async fn blacklist_jwts(&self, user: &UserId) -> Result<HashSet<u64>>;
-    async fn delete_refresh_token(&self, refresh_token_hash: u64) -> Result<()>;
+    async fn delete_refresh_token(&self, refresh_token: &str) -> Result<()>;
 
     /// Request a token to reset a user's password.
     /// If the user doesn't exist, returns `Ok(None)`, otherwise `Ok(Some(token))`.
@@ -26,4 +38,27 @@ pub trait TcpBackendHandler: Sync {
     async fn get_user_id_for_password_reset_token(&self, token: &str) -> Result<UserId>;
 
     async fn delete_password_reset_token(&self, token: &str) -> Result<()>;
+
+    /// The end of the lockout of a user or client address, if it is locked out.
+    async fn get_login_lockout(&self, key: &str) -> Result<Option<NaiveDateTime>>;
+
+    /// Counts a failed login. Returns the end of the lockout if this failure triggered one.
+    async fn record_login_failure(
+        &self,
+        key: &str,
+        options: &LoginThrottlingOptions,
+    ) -> Result<Option<NaiveDateTime>>;
+
+    async fn clear_login_failures(&self, key: &str) -> Result<()>;
+
+    /// Remembers a delivery of the provisioning webhook until the expiry date. Returns false if
+    /// it was already delivered.
+    async fn register_webhook_delivery(
+        &self,
+        delivery_id: &str,
+        expiry_date: NaiveDateTime,
+    ) -> Result<bool>;
+
+    /// Forgets a delivery that could not be processed, so that it can be retried.
+    async fn forget_webhook_delivery(&self, delivery_id: &str) -> Result<()>;
 }
@@ -1,30 +1,92 @@
 use crate::{
     domain::{
         error::DomainError,
-        handler::{BackendHandler, LoginHandler},
+        handler::{
+            AccessReviewBackendHandler, AccountDeletionBackendHandler, BackendHandler, LoginHandler,
+        },
         opaque_handler::OpaqueHandler,
     },
     infra::{
         access_control::{AccessControlledBackendHandler, ReadonlyBackendHandler},
         auth_service,
-        configuration::{Configuration, MailOptions},
+        backend::ServerBackendHandler,
+        captcha::{self, ChallengeProvider},
+        configuration::{
+            AccountDeletionOptions, AvatarOptions, BrandingOptions, ComputedAttribute,
+            Configuration, DirectoryVisibility, LoginThrottlingOptions, MailOptions,
+            MetricsOptions, ProvisioningWebhookOptions, QuotaOptions, SessionOptions,
+        },
+        diagnostics::DiagnosticsReport,
+        expiry_monitor::ExpiryMonitor,
+        geoip::GeoIpResolver,
+        graphql::persisted_queries::PersistedQueries,
+        ldap_metrics::LdapMetrics,
         logging::CustomRootSpanBuilder,
+        maintenance::MaintenanceMode,
+        read_only::ReadOnlyMode,
+        reset_delivery::{self, ResetDelivery},
         tcp_backend_handler::*,
     },
 };
 use actix_files::Files;
-use actix_http::{header, HttpServiceBuilder};
+use actix_http::HttpServiceBuilder;
 use actix_server::ServerBuilder;
 use actix_service::map_config;
-use actix_web::{dev::AppConfig, guard, web, App, HttpResponse, Responder};
-use anyhow::{Context, Result};
+use actix_web::{
+    dev::{AppConfig, HttpServiceFactory},
+    guard,
+    http::header,
+    middleware, web, App, HttpResponse, Responder,
+};
+use anyhow::{bail, Context, Result};
 use hmac::Hmac;
 use sha2::Sha512;
 use std::collections::HashSet;
-use std::sync::RwLock;
+use std::sync::{Arc, RwLock};
 use tracing::info;
 
-async fn index<Backend>(data: web::Data<AppState<Backend>>) -> actix_web::Result<impl Responder> {
+// The app's files don't have versioned names: browsers should revalidate them on every load, which
+// costs a round-trip with a 304 response when they haven't changed.
+const REVALIDATE: &str = "no-cache";
+// Fonts never change.
+const CACHE_FONTS: &str = "public, max-age=604800";
+
+/// Serves a generated text file, with an ETag derived from its content.
+fn text_response_with_etag(
+    content: String,
+    content_type: &'static str,
+    if_none_match: Option<web::Header<header::IfNoneMatch>>,
+) -> HttpResponse {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    content.hash(&mut hasher);
+    let etag = header::EntityTag::new_strong(format!("{:x}", hasher.finish()));
+    let not_modified = match if_none_match.map(web::Header::into_inner) {
+        Some(header::IfNoneMatch::Any) => true,
+        Some(header::IfNoneMatch::Items(tags)) => tags.iter().any(|t| t.weak_eq(&etag)),
+        None => false,
+    };
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header(header::ETag(etag))
+        .insert_header((header::CACHE_CONTROL, REVALIDATE));
+    if not_modified {
+        response.finish()
+    } else {
+        response
+            .insert_header((header::CONTENT_TYPE, content_type))
+            .body(content)
+    }
+}
+
+async fn index<Backend>(
+    data: web::Data<AppState<Backend>>,
+    if_none_match: Option<web::Header<header::IfNoneMatch>>,
+) -> actix_web::Result<impl Responder> {
     let mut file = std::fs::read_to_string(r"./app/index.html")?;
 
     if data.server_url.path() != "/" {
@@ -34,9 +96,11 @@ async fn index<Backend>(data: web::Data<AppState<Backend>>) -> actix_web::Result
         );
     }
 
-    Ok(file
-        .customize()
-        .insert_header((header::CONTENT_TYPE, "text/html; charset=utf-8")))
+    Ok(text_response_with_etag(
+        file,
+        "text/html; charset=utf-8",
+        if_none_match,
+    ))
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -51,6 +115,10 @@ pub enum TcpError {
     NotFoundError(String),
     #[error("Unauthorized: `{0}`")]
     UnauthorizedError(String),
+    #[error("Too many requests: `{0}`")]
+    TooManyRequests(String),
+    #[error("Service unavailable: `{0}`")]
+    ServiceUnavailable(String),
 }
 
 pub type TcpResult<T> = std::result::Result<T, TcpError>;
@@ -67,18 +135,27 @@ pub(crate) fn error_to_http_response(error: TcpError) -> HttpResponse {
             | DomainError::UnknownCryptoError(_) => HttpResponse::InternalServerError(),
             DomainError::Base64DecodeError(_)
             | DomainError::BinarySerializationError(_)
+            | DomainError::ValidationError(_)
+            | DomainError::AttributeValidationError { .. }
             | DomainError::EntityNotFound(_) => HttpResponse::BadRequest(),
+            DomainError::Conflict(_) => HttpResponse::Conflict(),
+            DomainError::PermissionDenied(_) | DomainError::QuotaExceeded(_) => {
+                HttpResponse::Forbidden()
+            }
         },
         TcpError::BadRequest(_) => HttpResponse::BadRequest(),
         TcpError::NotFoundError(_) => HttpResponse::NotFound(),
         TcpError::InternalServerError(_) => HttpResponse::InternalServerError(),
         TcpError::UnauthorizedError(_) => HttpResponse::Unauthorized(),
+        TcpError::TooManyRequests(_) => HttpResponse::TooManyRequests(),
+        TcpError::ServiceUnavailable(_) => HttpResponse::ServiceUnavailable(),
     }
     .body(error.to_string())
 }
 
 async fn main_js_handler<Backend>(
     data: web::Data<AppState<Backend>>,
+    if_none_match: Option<web::Header<header::IfNoneMatch>>,
 ) -> actix_web::Result<impl Responder> {
     let mut file = std::fs::read_to_string(r"./app/static/main.js")?;
 
@@ -86,13 +163,20 @@ async fn main_js_handler<Backend>(
         file = file.replace("/pkg/", format!("{}/pkg/", data.server_url.path()).as_str());
     }
 
-    Ok(file
-        .customize()
-        .insert_header((header::CONTENT_TYPE, "text/javascript")))
+    Ok(text_response_with_etag(
+        file,
+        "text/javascript",
+        if_none_match,
+    ))
 }
 
 async fn wasm_handler() -> actix_web::Result<impl Responder> {
-    Ok(actix_files::NamedFile::open_async("./app/pkg/lldap_app_bg.wasm").await?)
+    Ok(
+        actix_files::NamedFile::open_async("./app/pkg/lldap_app_bg.wasm")
+            .await?
+            .customize()
+            .insert_header((header::CACHE_CONTROL, REVALIDATE)),
+    )
 }
 
 async fn wasm_handler_compressed() -> actix_web::Result<impl Responder> {
@@ -101,10 +185,28 @@ async fn wasm_handler_compressed() -> actix_web::Result<impl Responder> {
             .await?
             .customize()
             .insert_header(header::ContentEncoding::Gzip)
-            .insert_header((header::CONTENT_TYPE, "application/wasm")),
+            .insert_header((header::CONTENT_TYPE, "application/wasm"))
+            .insert_header((header::CACHE_CONTROL, REVALIDATE)),
     )
 }
 
+/// Serves the files from a directory, with the given Cache-Control header. They are compressed
+/// with gzip, brotli or zstd depending on the Accept-Encoding header.
+///
+/// Only the static assets are compressed: a compressed response that mixes a secret (e.g. a CSRF
+/// token) with content chosen by an attacker leaks the secret through its length (BREACH).
+fn static_files(
+    mount_path: &str,
+    directory: &str,
+    cache_control: &'static str,
+) -> impl HttpServiceFactory {
+    web::scope(mount_path)
+        .wrap(middleware::Compress::default())
+        .wrap(middleware::DefaultHeaders::new().add((header::CACHE_CONTROL, cache_control)))
+        .service(Files::new("", directory))
+}
+
+#[allow(clippy::too_many_arguments)]
 fn http_config<Backend>(
     cfg: &mut web::ServiceConfig,
     backend_handler: Backend,
@@ -112,42 +214,121 @@ fn http_config<Backend>(
     jwt_blacklist: HashSet<u64>,
     server_url: url::Url,
     mail_options: MailOptions,
+    password_reset_delivery: Option<Arc<dyn ResetDelivery>>,
+    geoip: Option<Arc<GeoIpResolver>>,
+    four_eyes_approval: bool,
+    directory_visibility: DirectoryVisibility,
+    account_deletion_options: AccountDeletionOptions,
+    login_throttling_options: LoginThrottlingOptions,
+    session_options: SessionOptions,
+    branding_options: BrandingOptions,
+    captcha: Option<Arc<dyn ChallengeProvider>>,
+    captcha_required_for_login: bool,
+    computed_user_attributes: Vec<ComputedAttribute>,
+    avatar_options: AvatarOptions,
+    ldap_metrics: Arc<LdapMetrics>,
+    maintenance: Arc<MaintenanceMode>,
+    read_only: Arc<ReadOnlyMode>,
+    expiry_monitor: Arc<ExpiryMonitor>,
+    metrics_options: MetricsOptions,
+    quota_options: QuotaOptions,
+    diagnostics: Arc<DiagnosticsReport>,
+    expose_diagnostics: bool,
+    provisioning_webhook_options: ProvisioningWebhookOptions,
+    persisted_queries: Arc<PersistedQueries>,
+    csrf_protection: bool,
 ) where
-    Backend: TcpBackendHandler + BackendHandler + LoginHandler + OpaqueHandler + Clone + 'static,
+    Backend: ServerBackendHandler,
 {
-    let enable_password_reset = mail_options.enable_password_reset;
+    let enable_password_reset = password_reset_delivery.is_some();
+    let enable_account_deletion = account_deletion_options.enable_self_service;
+    let enable_metrics = metrics_options.enabled;
+    let enable_provisioning_webhook = provisioning_webhook_options.enabled;
     cfg.app_data(web::Data::new(AppState::<Backend> {
         backend_handler: AccessControlledBackendHandler::new(backend_handler),
         jwt_key: hmac::Mac::new_from_slice(jwt_secret.unsecure().as_bytes()).unwrap(),
         jwt_blacklist: RwLock::new(jwt_blacklist),
         server_url,
         mail_options,
+        password_reset_delivery,
+        geoip,
+        four_eyes_approval,
+        directory_visibility,
+        account_deletion_options,
+        login_throttling_options,
+        session_options,
+        branding_options,
+        captcha,
+        captcha_required_for_login,
+        computed_user_attributes,
+        avatar_options,
+        ldap_metrics,
+        maintenance,
+        read_only,
+        expiry_monitor,
+        metrics_options,
+        quota_options,
+        diagnostics,
+        provisioning_webhook_options,
+        persisted_queries,
     }))
     .route(
         "/health",
         web::get().to(|| async { HttpResponse::Ok().finish() }),
     )
-    .service(
-        web::scope("/auth")
-            .configure(|cfg| auth_service::configure_server::<Backend>(cfg, enable_password_reset)),
-    )
+    .service(web::scope("/auth").configure(|cfg| {
+        auth_service::configure_server::<Backend>(
+            cfg,
+            enable_password_reset,
+            enable_account_deletion,
+            csrf_protection,
+        )
+    }))
     // API endpoint.
     .service(
         web::scope("/api")
-            .wrap(auth_service::CookieToHeaderTranslatorFactory)
-            .configure(super::graphql::api::configure_endpoint::<Backend>),
+            .wrap(auth_service::CookieToHeaderTranslatorFactory { csrf_protection })
+            .configure(super::graphql::api::configure_endpoint::<Backend>)
+            .configure(super::group_emails::configure_endpoint::<Backend>)
+            .configure(|cfg| {
+                if expose_diagnostics {
+                    super::diagnostics::configure_endpoint::<Backend>(cfg);
+                }
+            })
+            .configure(|cfg| {
+                if enable_provisioning_webhook {
+                    super::provisioning_webhook::configure_endpoint::<Backend>(cfg);
+                }
+            }),
     )
+    .configure(|cfg| {
+        if enable_metrics {
+            super::prometheus::configure_endpoint::<Backend>(cfg);
+        }
+    })
     .service(
         web::resource("/pkg/lldap_app_bg.wasm.gz").route(web::route().to(wasm_handler_compressed)),
     )
-    .service(web::resource("/pkg/lldap_app_bg.wasm").route(web::route().to(wasm_handler)))
-    .service(web::resource("/static/main.js").route(web::route().to(main_js_handler::<Backend>)))
+    .service(
+        web::resource("/pkg/lldap_app_bg.wasm")
+            .wrap(middleware::Compress::default())
+            .route(web::route().to(wasm_handler)),
+    )
+    .service(
+        web::resource("/static/main.js")
+            .wrap(middleware::Compress::default())
+            .route(web::route().to(main_js_handler::<Backend>)),
+    )
     // Serve the /pkg path with the compiled WASM app.
-    .service(Files::new("/pkg", "./app/pkg"))
-    // Serve static files
-    .service(Files::new("/static", "./app/static"))
+    .service(static_files("/pkg", "./app/pkg", REVALIDATE))
     // Serve static fonts
-    .service(Files::new("/static/fonts", "./app/static/fonts"))
+    .service(static_files(
+        "/static/fonts",
+        "./app/static/fonts",
+        CACHE_FONTS,
+    ))
+    // Serve static files
+    .service(static_files("/static", "./app/static", REVALIDATE))
     // Default to serve index.html for unknown routes, to support routing.
     .default_service(web::route().guard(guard::Get()).to(index::<Backend>));
 }
@@ -158,12 +339,49 @@ pub(crate) struct AppState<Backend> {
     pub jwt_blacklist: RwLock<HashSet<u64>>,
     pub server_url: url::Url,
     pub mail_options: MailOptions,
+    pub password_reset_delivery: Option<Arc<dyn ResetDelivery>>,
+    pub geoip: Option<Arc<GeoIpResolver>>,
+    pub four_eyes_approval: bool,
+    pub directory_visibility: DirectoryVisibility,
+    pub account_deletion_options: AccountDeletionOptions,
+    pub login_throttling_options: LoginThrottlingOptions,
+    pub session_options: SessionOptions,
+    pub branding_options: BrandingOptions,
+    pub captcha: Option<Arc<dyn ChallengeProvider>>,
+    pub captcha_required_for_login: bool,
+    pub computed_user_attributes: Vec<ComputedAttribute>,
+    pub avatar_options: AvatarOptions,
+    pub ldap_metrics: Arc<LdapMetrics>,
+    pub maintenance: Arc<MaintenanceMode>,
+    pub read_only: Arc<ReadOnlyMode>,
+    pub expiry_monitor: Arc<ExpiryMonitor>,
+    pub metrics_options: MetricsOptions,
+    pub quota_options: QuotaOptions,
+    pub diagnostics: Arc<DiagnosticsReport>,
+    pub provisioning_webhook_options: ProvisioningWebhookOptions,
+    pub persisted_queries: Arc<PersistedQueries>,
 }
 
+impl<Backend> AppState<Backend> {
+    /// Refuses the changes in read-only mode.
+    pub fn check_read_only(&self) -> TcpResult<()> {
+        match self.read_only.refusal() {
+            Some(message) => Err(TcpError::ServiceUnavailable(message.to_owned())),
+            None => Ok(()),
+        }
+    }
+}
 impl<Backend: BackendHandler> AppState<Backend> {
     pub fn get_readonly_handler(&self) -> &impl ReadonlyBackendHandler {
         self.backend_handler.unsafe_get_handler()
     }
+    pub fn get_account_deletion_handler(&self) -> &impl AccountDeletionBackendHandler {
+        self.backend_handler.unsafe_get_handler()
+    }
+    // The review pages are authorized by their token.
+    pub fn get_access_review_handler(&self) -> &impl AccessReviewBackendHandler {
+        self.backend_handler.unsafe_get_handler()
+    }
 }
 impl<Backend: TcpBackendHandler> AppState<Backend> {
     pub fn get_tcp_handler(&self) -> &impl TcpBackendHandler {
@@ -184,10 +402,15 @@ impl<Backend: LoginHandler> AppState<Backend> {
 pub async fn build_tcp_server<Backend>(
     config: &Configuration,
     backend_handler: Backend,
+    ldap_metrics: Arc<LdapMetrics>,
+    maintenance: Arc<MaintenanceMode>,
+    read_only: Arc<ReadOnlyMode>,
+    expiry_monitor: Arc<ExpiryMonitor>,
+    diagnostics: Arc<DiagnosticsReport>,
     server_builder: ServerBuilder,
 ) -> Result<ServerBuilder>
 where
-    Backend: TcpBackendHandler + BackendHandler + LoginHandler + OpaqueHandler + Clone + 'static,
+    Backend: ServerBackendHandler,
 {
     let jwt_secret = config.jwt_secret.clone();
     let jwt_blacklist = backend_handler
@@ -196,6 +419,28 @@ where
         .context("while getting the jwt blacklist")?;
     let server_url = config.http_url.0.clone();
     let mail_options = config.smtp_options.clone();
+    let password_reset_delivery =
+        reset_delivery::from_options(&config.password_reset_options, &mail_options, &server_url)?;
+    let geoip = GeoIpResolver::from_options(&config.geoip_options)?.map(Arc::new);
+    let four_eyes_approval = config.four_eyes_approval;
+    let directory_visibility = config.directory_visibility;
+    let account_deletion_options = config.account_deletion_options.clone();
+    let login_throttling_options = config.login_throttling_options.clone();
+    let session_options = config.session_options.clone();
+    let branding_options = config.branding_options.clone();
+    let captcha = captcha::from_options(&config.captcha_options, &config.jwt_secret)?;
+    let captcha_required_for_login = config.captcha_options.require_for_login;
+    let computed_user_attributes = config.computed_user_attributes.clone();
+    let avatar_options = config.avatar_options.clone();
+    let metrics_options = config.metrics_options.clone();
+    let quota_options = config.quota_options.clone();
+    let expose_diagnostics = config.diagnostics_options.expose_api;
+    let provisioning_webhook_options = config.provisioning_webhook_options.clone();
+    if provisioning_webhook_options.enabled && provisioning_webhook_options.secret.is_none() {
+        bail!("The provisioning webhook requires provisioning_webhook_options.secret");
+    }
+    let persisted_queries = Arc::new(PersistedQueries::from_options(&config.graphql_options)?);
+    let csrf_protection = config.graphql_options.csrf_protection;
     let verbose = config.verbose;
     info!("Starting the API/web server on port {}", config.http_port);
     server_builder
@@ -208,6 +453,24 @@ where
                 let jwt_blacklist = jwt_blacklist.clone();
                 let server_url = server_url.clone();
                 let mail_options = mail_options.clone();
+                let password_reset_delivery = password_reset_delivery.clone();
+                let geoip = geoip.clone();
+                let account_deletion_options = account_deletion_options.clone();
+                let login_throttling_options = login_throttling_options.clone();
+                let session_options = session_options.clone();
+                let branding_options = branding_options.clone();
+                let captcha = captcha.clone();
+                let computed_user_attributes = computed_user_attributes.clone();
+                let avatar_options = avatar_options.clone();
+                let ldap_metrics = ldap_metrics.clone();
+                let maintenance = maintenance.clone();
+                let read_only = read_only.clone();
+                let expiry_monitor = expiry_monitor.clone();
+                let metrics_options = metrics_options.clone();
+                let quota_options = quota_options.clone();
+                let diagnostics = diagnostics.clone();
+                let provisioning_webhook_options = provisioning_webhook_options.clone();
+                let persisted_queries = persisted_queries.clone();
                 HttpServiceBuilder::default()
                     .finish(map_config(
                         App::new()
@@ -223,6 +486,29 @@ where
                                     jwt_blacklist,
                                     server_url,
                                     mail_options,
+                                    password_reset_delivery,
+                                    geoip,
+                                    four_eyes_approval,
+                                    directory_visibility,
+                                    account_deletion_options,
+                                    login_throttling_options,
+                                    session_options,
+                                    branding_options,
+                                    captcha,
+                                    captcha_required_for_login,
+                                    computed_user_attributes,
+                                    avatar_options,
+                                    ldap_metrics,
+                                    maintenance,
+                                    read_only,
+                                    expiry_monitor,
+                                    metrics_options,
+                                    quota_options,
+                                    diagnostics,
+                                    expose_diagnostics,
+                                    provisioning_webhook_options,
+                                    persisted_queries,
+                                    csrf_protection,
                                 )
                             }),
                         |_| AppConfig::default(),
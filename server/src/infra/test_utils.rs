@@ -1,7 +1,21 @@
-use crate::domain::{error::Result, handler::*, opaque_handler::*, types::*};
+use crate::domain::{
+    access_reviews::{AccessReview, AccessReviewInvitation},
+    account_deletions::AccountDeletion,
+    attribute_templates::AttributeTemplate,
+    change_events::{ChangeEvent, ChangeFeedEntry},
+    error::Result,
+    group_rules::GroupRuleChange,
+    handler::*,
+    identity_links::{ExternalIdentity, IdentityLink},
+    opaque_handler::*,
+    pending_changes::{PendingChange, SensitiveChange},
+    profile_history::ProfileChange,
+    types::*,
+};
 
 use async_trait::async_trait;
 use std::collections::HashSet;
+use tokio::sync::broadcast;
 
 mockall::mock! {
     pub TestBackendHandler{}
@@ -19,6 +33,7 @@ mockall::mock! {
     #[async_trait]
     impl GroupBackendHandler for TestBackendHandler {
         async fn get_group_details(&self, group_id: GroupId) -> Result<GroupDetails>;
+        async fn list_group_memberships(&self, group_id: GroupId) -> Result<Vec<GroupMembership>>;
         async fn update_group(&self, request: UpdateGroupRequest) -> Result<()>;
         async fn create_group(&self, request: CreateGroupRequest) -> Result<GroupId>;
         async fn delete_group(&self, group_id: GroupId) -> Result<()>;
@@ -36,6 +51,7 @@ mockall::mock! {
         async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>>;
         async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
         async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+        async fn list_user_sessions(&self, user_id: &UserId) -> Result<Vec<UserSession>>;
     }
     #[async_trait]
     impl ReadSchemaBackendHandler for TestBackendHandler {
@@ -53,6 +69,75 @@ mockall::mock! {
         async fn delete_group_object_class(&self, name: &LdapObjectClass) -> Result<()>;
     }
     #[async_trait]
+    impl ChangeFeedBackendHandler for TestBackendHandler {
+        async fn list_changes(&self, since: i32, limit: u64) -> Result<Vec<ChangeFeedEntry>>;
+        async fn list_changes_for_user(&self, user_id: &UserId) -> Result<Vec<ChangeFeedEntry>>;
+        async fn get_last_change_sequence(&self) -> Result<i32>;
+        fn subscribe_to_changes(&self) -> broadcast::Receiver<ChangeEvent>;
+    }
+    #[async_trait]
+    impl PendingChangeBackendHandler for TestBackendHandler {
+        async fn create_pending_change(&self, requested_by: &UserId, change: SensitiveChange) -> Result<i32>;
+        async fn list_pending_changes(&self) -> Result<Vec<PendingChange>>;
+        async fn get_pending_change(&self, id: i32) -> Result<PendingChange>;
+        async fn delete_pending_change(&self, id: i32) -> Result<()>;
+    }
+    #[async_trait]
+    impl AccountDeletionBackendHandler for TestBackendHandler {
+        async fn request_account_deletion(&self, user_id: &UserId) -> Result<String>;
+        async fn confirm_account_deletion(&self, token: &str, grace_period: chrono::Duration) -> Result<AccountDeletion>;
+        async fn cancel_account_deletion(&self, user_id: &UserId) -> Result<()>;
+        async fn get_account_deletion(&self, user_id: &UserId) -> Result<Option<AccountDeletion>>;
+        async fn list_account_deletions(&self) -> Result<Vec<AccountDeletion>>;
+        async fn purge_deleted_accounts(&self) -> Result<Vec<UserId>>;
+    }
+    #[async_trait]
+    impl AttributeTemplateBackendHandler for TestBackendHandler {
+        async fn list_attribute_templates(&self, group_id: GroupId) -> Result<Vec<AttributeTemplate>>;
+        async fn set_attribute_template(&self, template: AttributeTemplate) -> Result<()>;
+        async fn delete_attribute_template(&self, group_id: GroupId, attribute_name: &AttributeName) -> Result<()>;
+    }
+    #[async_trait]
+    impl AvatarSyncBackendHandler for TestBackendHandler {
+        async fn get_avatar_sync_opt_out(&self, user_id: &UserId) -> Result<bool>;
+        async fn set_avatar_sync_opt_out(&self, user_id: &UserId, opt_out: bool) -> Result<()>;
+        async fn list_avatar_sync_candidates(&self) -> Result<Vec<(UserId, Email)>>;
+        async fn store_synced_avatar(&self, user_id: &UserId, avatar: Option<JpegPhoto>) -> Result<()>;
+    }
+    #[async_trait]
+    impl GroupRuleBackendHandler for TestBackendHandler {
+        async fn preview_group_rules(&self) -> Result<Vec<GroupRuleChange>>;
+        async fn apply_group_rules(&self) -> Result<Vec<GroupRuleChange>>;
+    }
+    #[async_trait]
+    impl IdentityLinkBackendHandler for TestBackendHandler {
+        async fn list_identity_links(&self, user_id: &UserId) -> Result<Vec<IdentityLink>>;
+        async fn add_identity_link(&self, user_id: &UserId, provider: &str, subject: &str) -> Result<IdentityLink>;
+        async fn delete_identity_link(&self, provider: &str, subject: &str) -> Result<()>;
+        async fn resolve_external_identity(&self, identity: &ExternalIdentity) -> Result<Option<UserId>>;
+    }
+    #[async_trait]
+    impl MembershipExpiryBackendHandler for TestBackendHandler {
+        async fn set_membership_expiry(&self, user_id: &UserId, group_id: GroupId, expiry_date: Option<chrono::NaiveDateTime>) -> Result<()>;
+        async fn purge_expired_memberships(&self) -> Result<Vec<(UserId, GroupName)>>;
+    }
+    #[async_trait]
+    impl AccessReviewBackendHandler for TestBackendHandler {
+        async fn start_access_review(&self, name: &str, created_by: &UserId, groups: &[(GroupId, UserId)]) -> Result<Vec<AccessReviewInvitation>>;
+        async fn list_access_reviews(&self) -> Result<Vec<AccessReview>>;
+        async fn get_access_review(&self, token: &str) -> Result<AccessReview>;
+        async fn decide_access_review(&self, token: &str, user_id: &UserId, keep: bool) -> Result<()>;
+    }
+    #[async_trait]
+    impl NestedGroupBackendHandler for TestBackendHandler {
+        async fn add_group_to_group(&self, member: GroupId, parent: GroupId) -> Result<()>;
+        async fn remove_group_from_group(&self, member: GroupId, parent: GroupId) -> Result<()>;
+    }
+    #[async_trait]
+    impl ProfileHistoryBackendHandler for TestBackendHandler {
+        async fn list_profile_changes(&self, user_id: &UserId) -> Result<Vec<ProfileChange>>;
+    }
+    #[async_trait]
     impl BackendHandler for TestBackendHandler {}
     #[async_trait]
     impl OpaqueHandler for TestBackendHandler {
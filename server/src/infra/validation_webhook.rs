@@ -0,0 +1,209 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use secstr::SecUtf8;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::{debug, warn};
+use url::Url;
+
+use crate::{
+    domain::error::{DomainError, Result},
+    infra::configuration::ValidationWebhookOptions,
+};
+
+/// The change submitted to the validation webhook.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookOperation {
+    CreateUser,
+    UpdateUser,
+    CreateGroup,
+    UpdateGroup,
+}
+
+#[derive(Serialize)]
+struct ReviewRequest<'a, T> {
+    operation: WebhookOperation,
+    object: &'a T,
+}
+
+/// The decision of the webhook. When `object` is set, it replaces the submitted change.
+#[derive(Deserialize)]
+struct ReviewResponse<T> {
+    allowed: bool,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    object: Option<T>,
+}
+
+/// Submits the user and group changes to an external policy service before they are applied,
+/// like the admission controllers of Kubernetes: the service can reject a change, or return a
+/// modified version of it.
+pub struct ValidationWebhook {
+    client: reqwest::Client,
+    url: Url,
+    authorization: Option<SecUtf8>,
+    timeout: Duration,
+    fail_open: bool,
+}
+
+impl ValidationWebhook {
+    /// The webhook, if it is configured.
+    pub fn from_options(options: &ValidationWebhookOptions) -> Option<Self> {
+        Some(Self {
+            client: reqwest::Client::new(),
+            url: options.url.clone()?,
+            authorization: options.authorization.clone(),
+            timeout: Duration::from_millis(options.timeout_ms),
+            fail_open: options.fail_open,
+        })
+    }
+
+    async fn call<T: Serialize + DeserializeOwned>(
+        &self,
+        operation: WebhookOperation,
+        object: &T,
+    ) -> anyhow::Result<ReviewResponse<T>> {
+        let request = self
+            .client
+            .post(self.url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_string(&ReviewRequest { operation, object })?);
+        let request = match &self.authorization {
+            Some(authorization) => {
+                request.header(reqwest::header::AUTHORIZATION, authorization.unsecure())
+            }
+            None => request,
+        };
+        let body = tokio::time::timeout(self.timeout, async {
+            request.send().await?.error_for_status()?.text().await
+        })
+        .await
+        .map_err(|_| anyhow!("no answer after {:?}", self.timeout))??;
+        serde_json::from_str(&body).context("while parsing the answer")
+    }
+
+    /// Returns the change to apply: the submitted one, or the one modified by the webhook.
+    pub async fn review<T: Serialize + DeserializeOwned>(
+        &self,
+        operation: WebhookOperation,
+        object: T,
+    ) -> Result<T> {
+        match self.call(operation, &object).await {
+            Ok(response) => apply_response(operation, object, response),
+            Err(e) if self.fail_open => {
+                warn!(
+                    "The validation webhook failed, accepting the {:?} anyway: {:#}",
+                    operation, e
+                );
+                Ok(object)
+            }
+            Err(e) => Err(DomainError::InternalError(format!(
+                "The validation webhook failed: {:#}",
+                e
+            ))),
+        }
+    }
+}
+
+fn apply_response<T>(
+    operation: WebhookOperation,
+    object: T,
+    response: ReviewResponse<T>,
+) -> Result<T> {
+    if !response.allowed {
+        return Err(DomainError::ValidationError(match response.message {
+            Some(message) => format!("Rejected by the validation webhook: {}", message),
+            None => "Rejected by the validation webhook".to_owned(),
+        }));
+    }
+    match response.object {
+        Some(modified) => {
+            debug!("The validation webhook modified the {:?}", operation);
+            Ok(modified)
+        }
+        None => Ok(object),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::handler::CreateGroupRequest, infra::configuration::ValidationWebhookOptionsBuilder,
+    };
+    use pretty_assertions::assert_eq;
+
+    fn group_request(name: &str) -> CreateGroupRequest {
+        CreateGroupRequest {
+            display_name: name.into(),
+            attributes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_apply_response() {
+        let response = |json: &str| serde_json::from_str::<ReviewResponse<_>>(json).unwrap();
+        assert_eq!(
+            apply_response(
+                WebhookOperation::CreateGroup,
+                group_request("admins"),
+                response(r#"{"allowed": true}"#)
+            )
+            .unwrap(),
+            group_request("admins")
+        );
+        assert_eq!(
+            apply_response(
+                WebhookOperation::CreateGroup,
+                group_request("admins"),
+                response(
+                    r#"{"allowed": true, "object": {"display_name": "team-admins", "attributes": []}}"#
+                )
+            )
+            .unwrap(),
+            group_request("team-admins")
+        );
+        assert_eq!(
+            apply_response(
+                WebhookOperation::CreateGroup,
+                group_request("admins"),
+                response(r#"{"allowed": false, "message": "Reserved name"}"#)
+            )
+            .unwrap_err()
+            .to_string(),
+            "Validation error: `Rejected by the validation webhook: Reserved name`"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_webhook() {
+        let options = |fail_open| {
+            ValidationWebhookOptionsBuilder::default()
+                // Nothing listens on the port 1.
+                .url(Some(Url::parse("http://127.0.0.1:1/validate").unwrap()))
+                .fail_open(fail_open)
+                .build()
+                .unwrap()
+        };
+        let webhook = ValidationWebhook::from_options(&options(true)).unwrap();
+        assert_eq!(
+            webhook
+                .review(WebhookOperation::CreateGroup, group_request("admins"))
+                .await
+                .unwrap(),
+            group_request("admins")
+        );
+        let webhook = ValidationWebhook::from_options(&options(false)).unwrap();
+        assert!(webhook
+            .review(WebhookOperation::CreateGroup, group_request("admins"))
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_disabled_webhook() {
+        assert!(ValidationWebhook::from_options(&ValidationWebhookOptions::default()).is_none());
+    }
+}
@@ -0,0 +1,295 @@
+#![forbid(unsafe_code)]
+#![forbid(non_ascii_idents)]
+// TODO: Remove next line when it stops warning about async functions.
+#![allow(clippy::blocks_in_conditions)]
+
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    domain::{
+        computed_attributes::check_template,
+        handler::{
+            CreateGroupRequest, CreateUserRequest, GroupBackendHandler, GroupListerBackendHandler,
+            GroupRequestFilter, UserBackendHandler, UserListerBackendHandler, UserRequestFilter,
+        },
+        sql_backend_handler::SqlBackendHandler,
+        sql_opaque_handler::register_password,
+        sql_tables::{get_private_key_info, set_private_key_info},
+    },
+    infra::{
+        avatar_sync::AvatarSync,
+        backend::{get_database_url, ServerBackendHandler},
+        cache_invalidator::CacheInvalidator,
+        configuration::{compare_private_key_hashes, Configuration},
+        database_string::DatabaseUrl,
+        db_cleaner::Scheduler,
+        diagnostics::{self, DiagnosticsReport},
+        expiry_monitor::{ExpiryMonitor, ExpiryMonitorTask},
+        ldap_metrics::LdapMetrics,
+        ldap_proxy::LdapProxySync,
+        maintenance::MaintenanceMode,
+        notifications::Notifier,
+        read_only::ReadOnlyMode,
+    },
+};
+use actix::Actor;
+use actix_server::ServerBuilder;
+use anyhow::{anyhow, bail, Context, Result};
+use futures_util::TryFutureExt;
+use sea_orm::{Database, DatabaseConnection};
+use tracing::{info, instrument, span, warn, Instrument, Level};
+
+pub mod domain;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+pub mod infra;
+#[cfg(feature = "test-harness")]
+pub mod testing;
+
+async fn create_admin_user(handler: &SqlBackendHandler, config: &Configuration) -> Result<()> {
+    let pass_length = config.ldap_user_pass.unsecure().len();
+    assert!(
+        pass_length >= 8,
+        "Minimum password length is 8 characters, got {} characters",
+        pass_length
+    );
+    handler
+        .create_user(CreateUserRequest {
+            user_id: config.ldap_user_dn.clone(),
+            email: config.ldap_user_email.clone().into(),
+            display_name: Some("Administrator".to_string()),
+            ..Default::default()
+        })
+        .and_then(|_| {
+            register_password(handler, config.ldap_user_dn.clone(), &config.ldap_user_pass)
+        })
+        .await
+        .context("Error creating admin user")?;
+    let groups = handler
+        .list_groups(Some(GroupRequestFilter::DisplayName("lldap_admin".into())))
+        .await?;
+    assert_eq!(groups.len(), 1);
+    handler
+        .add_user_to_group(&config.ldap_user_dn, groups[0].id)
+        .await
+        .context("Error adding admin user to group")
+}
+
+async fn ensure_group_exists(handler: &SqlBackendHandler, group_name: &str) -> Result<()> {
+    if handler
+        .list_groups(Some(GroupRequestFilter::DisplayName(group_name.into())))
+        .await?
+        .is_empty()
+    {
+        warn!("Could not find {} group, trying to create it", group_name);
+        handler
+            .create_group(CreateGroupRequest {
+                display_name: group_name.into(),
+                ..Default::default()
+            })
+            .await
+            .context(format!("while creating {} group", group_name))?;
+    }
+    Ok(())
+}
+
+pub async fn setup_sql_tables(database_url: &DatabaseUrl) -> Result<DatabaseConnection> {
+    let sql_pool = {
+        let num_connections = if database_url.db_type() == "sqlite" {
+            1
+        } else {
+            5
+        };
+        let mut sql_opt = sea_orm::ConnectOptions::new(database_url.to_string());
+        sql_opt
+            .max_connections(num_connections)
+            .sqlx_logging(true)
+            .sqlx_logging_level(log::LevelFilter::Debug);
+        if database_url.to_string().contains(":memory:") {
+            // The in-memory database disappears with its connection: keep it open.
+            sql_opt
+                .min_connections(1)
+                .max_lifetime(Duration::from_secs(u32::MAX.into()));
+        }
+        Database::connect(sql_opt).await?
+    };
+    domain::sql_tables::init_table(&sql_pool)
+        .await
+        .context("while creating base tables")?;
+    domain::jwt_sql_tables::init_table(&sql_pool)
+        .await
+        .context("while creating jwt tables")?;
+    Ok(sql_pool)
+}
+
+/// Binds the LDAP and HTTP servers, which only rely on the backend traits.
+async fn build_servers<Backend: ServerBackendHandler>(
+    config: &Configuration,
+    backend_handler: Backend,
+    expiry_monitor: Arc<ExpiryMonitor>,
+    diagnostics: Arc<DiagnosticsReport>,
+) -> Result<ServerBuilder> {
+    let ldap_metrics = Arc::new(LdapMetrics::new(
+        config
+            .ldap_slow_operation_threshold_ms
+            .map(Duration::from_millis),
+    ));
+    let maintenance = Arc::new(MaintenanceMode::new(&config.maintenance_options));
+    let read_only = Arc::new(ReadOnlyMode::new(config.read_only));
+    let server_builder = infra::ldap_server::build_ldap_server(
+        config,
+        backend_handler.clone(),
+        ldap_metrics.clone(),
+        maintenance.clone(),
+        read_only.clone(),
+        actix_server::Server::build(),
+    )
+    .context("while binding the LDAP server")?;
+    infra::tcp_server::build_tcp_server(
+        config,
+        backend_handler,
+        ldap_metrics,
+        maintenance,
+        read_only,
+        expiry_monitor,
+        diagnostics,
+        server_builder,
+    )
+    .await
+    .context("while binding the TCP server")
+}
+
+#[instrument(skip_all)]
+pub async fn set_up_server(config: Configuration) -> Result<ServerBuilder> {
+    info!("Starting LLDAP version {}", env!("CARGO_PKG_VERSION"));
+
+    if config.pam_options.enabled && !cfg!(feature = "pam") {
+        bail!("PAM authentication is enabled, but LLDAP was built without the `pam` feature");
+    }
+    for attribute in &config.computed_user_attributes {
+        check_template(&attribute.template).map_err(|e| {
+            anyhow!(
+                r#"Invalid template for the computed attribute "{}": {}"#,
+                attribute.name,
+                e
+            )
+        })?;
+    }
+    let sql_pool = setup_sql_tables(&get_database_url(&config)?).await?;
+    let diagnostics = if config.diagnostics_options.enabled {
+        let report = diagnostics::run(&config, &sql_pool).await;
+        report.log();
+        report
+    } else {
+        DiagnosticsReport::default()
+    };
+    let private_key_info = config.get_private_key_info();
+    let force_update_private_key = config.force_update_private_key;
+    match (
+        compare_private_key_hashes(
+            get_private_key_info(&sql_pool).await?.as_ref(),
+            &private_key_info,
+        ),
+        force_update_private_key,
+    ) {
+        (Ok(false), true) => {
+            bail!("The private key has not changed, but force_update_private_key/LLDAP_FORCE_UPDATE_PRIVATE_KEY is set to true. Please set force_update_private_key to false and restart the server.");
+        }
+        (Ok(true), _) | (Err(_), true) => {
+            set_private_key_info(&sql_pool, private_key_info).await?;
+        }
+        (Ok(false), false) => {}
+        (Err(e), false) => {
+            return Err(anyhow!("The private key encoding the passwords has changed since last successful startup. Changing the private key will invalidate all existing passwords. If you want to proceed, restart the server with the CLI arg --force-update-private-key=true or the env variable LLDAP_FORCE_UPDATE_PRIVATE_KEY=true. You probably also want --force-ldap-user-pass-reset / LLDAP_FORCE_LDAP_USER_PASS_RESET=true to reset the admin password to the value in the configuration.").context(e));
+        }
+    }
+    let backend_handler = SqlBackendHandler::new(config.clone(), sql_pool.clone());
+    ensure_group_exists(&backend_handler, "lldap_admin").await?;
+    ensure_group_exists(&backend_handler, "lldap_password_manager").await?;
+    ensure_group_exists(&backend_handler, "lldap_strict_readonly").await?;
+    backend_handler
+        .assign_missing_posix_ids()
+        .await
+        .context("while assigning the uidNumber and gidNumber")?;
+    let admin_present = if let Ok(admins) = backend_handler
+        .list_users(
+            Some(UserRequestFilter::MemberOf("lldap_admin".into())),
+            false,
+        )
+        .await
+    {
+        !admins.is_empty()
+    } else {
+        false
+    };
+    if !admin_present {
+        warn!("Could not find an admin user, trying to create the user \"admin\" with the config-provided password");
+        create_admin_user(&backend_handler, &config)
+            .await
+            .map_err(|e| anyhow!("Error setting up admin login/account: {:#}", e))
+            .context("while creating the admin user")?;
+    } else if config.force_ldap_user_pass_reset.is_positive() {
+        let span = if config.force_ldap_user_pass_reset.is_yes() {
+            span!(
+                Level::WARN,
+                "Forcing admin password reset to the config-provided password"
+            )
+        } else {
+            span!(Level::INFO, "Resetting admin password")
+        };
+        register_password(
+            &backend_handler,
+            config.ldap_user_dn.clone(),
+            &config.ldap_user_pass,
+        )
+        .instrument(span)
+        .await
+        .context(format!(
+            "while resetting admin password for {}",
+            &config.ldap_user_dn
+        ))?;
+    }
+    if config.force_update_private_key || config.force_ldap_user_pass_reset.is_yes() {
+        bail!("Restart the server without --force-update-private-key or --force-ldap-user-pass-reset to continue.");
+    }
+    let notifier = Notifier::from_options(&config.notification_options);
+    let expiry_monitor = Arc::new(ExpiryMonitor::new(&config, notifier.clone()));
+    let server_builder = build_servers(
+        &config,
+        backend_handler.clone(),
+        expiry_monitor.clone(),
+        Arc::new(diagnostics),
+    )
+    .await?;
+    ExpiryMonitorTask::new(expiry_monitor, sql_pool.clone()).start();
+    if config.cache_options.enabled {
+        CacheInvalidator::new(backend_handler.clone()).start();
+    }
+    if config.avatar_sync_options.enabled {
+        AvatarSync::new(
+            config.avatar_sync_options.clone(),
+            sql_pool.clone(),
+            backend_handler.clone(),
+        )
+        .start();
+    }
+    if config.ldap_proxy_options.enabled {
+        LdapProxySync::new(
+            config.ldap_proxy_options.clone(),
+            sql_pool.clone(),
+            backend_handler.clone(),
+            notifier.clone(),
+        )
+        .start();
+    }
+    // Run every hour.
+    let scheduler = Scheduler::new(
+        "0 0 * * * * *",
+        sql_pool,
+        backend_handler,
+        chrono::Duration::days(config.change_feed_retention_days.into()),
+        notifier,
+    );
+    scheduler.start();
+    Ok(server_builder)
+}
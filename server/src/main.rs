@@ -3,191 +3,21 @@
 // TODO: Remove next line when it stops warning about async functions.
 #![allow(clippy::blocks_in_conditions)]
 
-use std::time::Duration;
-
-use crate::{
+use anyhow::{Context, Result};
+use lldap::{
     domain::{
-        handler::{
-            CreateGroupRequest, CreateUserRequest, GroupBackendHandler, GroupListerBackendHandler,
-            GroupRequestFilter, UserBackendHandler, UserListerBackendHandler, UserRequestFilter,
-        },
-        sql_backend_handler::SqlBackendHandler,
-        sql_opaque_handler::register_password,
-        sql_tables::{get_private_key_info, set_private_key_info},
+        sql_backend_handler::SqlBackendHandler, sql_tables::LAST_SCHEMA_VERSION, types::UserId,
     },
     infra::{
+        self,
+        backend::get_database_url,
         cli::*,
-        configuration::{compare_private_key_hashes, Configuration},
-        database_string::DatabaseUrl,
-        db_cleaner::Scheduler,
+        cli_output::{print_result, SchemaReport, SelfTestReport, TestEmailReport},
         healthcheck, mail,
     },
+    set_up_server, setup_sql_tables,
 };
-use actix::Actor;
-use actix_server::ServerBuilder;
-use anyhow::{anyhow, bail, Context, Result};
-use futures_util::TryFutureExt;
-use sea_orm::{Database, DatabaseConnection};
-use tracing::{debug, error, info, instrument, span, warn, Instrument, Level};
-
-mod domain;
-mod infra;
-
-async fn create_admin_user(handler: &SqlBackendHandler, config: &Configuration) -> Result<()> {
-    let pass_length = config.ldap_user_pass.unsecure().len();
-    assert!(
-        pass_length >= 8,
-        "Minimum password length is 8 characters, got {} characters",
-        pass_length
-    );
-    handler
-        .create_user(CreateUserRequest {
-            user_id: config.ldap_user_dn.clone(),
-            email: config.ldap_user_email.clone().into(),
-            display_name: Some("Administrator".to_string()),
-            ..Default::default()
-        })
-        .and_then(|_| {
-            register_password(handler, config.ldap_user_dn.clone(), &config.ldap_user_pass)
-        })
-        .await
-        .context("Error creating admin user")?;
-    let groups = handler
-        .list_groups(Some(GroupRequestFilter::DisplayName("lldap_admin".into())))
-        .await?;
-    assert_eq!(groups.len(), 1);
-    handler
-        .add_user_to_group(&config.ldap_user_dn, groups[0].id)
-        .await
-        .context("Error adding admin user to group")
-}
-
-async fn ensure_group_exists(handler: &SqlBackendHandler, group_name: &str) -> Result<()> {
-    if handler
-        .list_groups(Some(GroupRequestFilter::DisplayName(group_name.into())))
-        .await?
-        .is_empty()
-    {
-        warn!("Could not find {} group, trying to create it", group_name);
-        handler
-            .create_group(CreateGroupRequest {
-                display_name: group_name.into(),
-                ..Default::default()
-            })
-            .await
-            .context(format!("while creating {} group", group_name))?;
-    }
-    Ok(())
-}
-
-async fn setup_sql_tables(database_url: &DatabaseUrl) -> Result<DatabaseConnection> {
-    let sql_pool = {
-        let num_connections = if database_url.db_type() == "sqlite" {
-            1
-        } else {
-            5
-        };
-        let mut sql_opt = sea_orm::ConnectOptions::new(database_url.to_string());
-        sql_opt
-            .max_connections(num_connections)
-            .sqlx_logging(true)
-            .sqlx_logging_level(log::LevelFilter::Debug);
-        Database::connect(sql_opt).await?
-    };
-    domain::sql_tables::init_table(&sql_pool)
-        .await
-        .context("while creating base tables")?;
-    infra::jwt_sql_tables::init_table(&sql_pool)
-        .await
-        .context("while creating jwt tables")?;
-    Ok(sql_pool)
-}
-
-#[instrument(skip_all)]
-async fn set_up_server(config: Configuration) -> Result<ServerBuilder> {
-    info!("Starting LLDAP version {}", env!("CARGO_PKG_VERSION"));
-
-    let sql_pool = setup_sql_tables(&config.database_url).await?;
-    let private_key_info = config.get_private_key_info();
-    let force_update_private_key = config.force_update_private_key;
-    match (
-        compare_private_key_hashes(
-            get_private_key_info(&sql_pool).await?.as_ref(),
-            &private_key_info,
-        ),
-        force_update_private_key,
-    ) {
-        (Ok(false), true) => {
-            bail!("The private key has not changed, but force_update_private_key/LLDAP_FORCE_UPDATE_PRIVATE_KEY is set to true. Please set force_update_private_key to false and restart the server.");
-        }
-        (Ok(true), _) | (Err(_), true) => {
-            set_private_key_info(&sql_pool, private_key_info).await?;
-        }
-        (Ok(false), false) => {}
-        (Err(e), false) => {
-            return Err(anyhow!("The private key encoding the passwords has changed since last successful startup. Changing the private key will invalidate all existing passwords. If you want to proceed, restart the server with the CLI arg --force-update-private-key=true or the env variable LLDAP_FORCE_UPDATE_PRIVATE_KEY=true. You probably also want --force-ldap-user-pass-reset / LLDAP_FORCE_LDAP_USER_PASS_RESET=true to reset the admin password to the value in the configuration.").context(e));
-        }
-    }
-    let backend_handler = SqlBackendHandler::new(config.clone(), sql_pool.clone());
-    ensure_group_exists(&backend_handler, "lldap_admin").await?;
-    ensure_group_exists(&backend_handler, "lldap_password_manager").await?;
-    ensure_group_exists(&backend_handler, "lldap_strict_readonly").await?;
-    let admin_present = if let Ok(admins) = backend_handler
-        .list_users(
-            Some(UserRequestFilter::MemberOf("lldap_admin".into())),
-            false,
-        )
-        .await
-    {
-        !admins.is_empty()
-    } else {
-        false
-    };
-    if !admin_present {
-        warn!("Could not find an admin user, trying to create the user \"admin\" with the config-provided password");
-        create_admin_user(&backend_handler, &config)
-            .await
-            .map_err(|e| anyhow!("Error setting up admin login/account: {:#}", e))
-            .context("while creating the admin user")?;
-    } else if config.force_ldap_user_pass_reset.is_positive() {
-        let span = if config.force_ldap_user_pass_reset.is_yes() {
-            span!(
-                Level::WARN,
-                "Forcing admin password reset to the config-provided password"
-            )
-        } else {
-            span!(Level::INFO, "Resetting admin password")
-        };
-        register_password(
-            &backend_handler,
-            config.ldap_user_dn.clone(),
-            &config.ldap_user_pass,
-        )
-        .instrument(span)
-        .await
-        .context(format!(
-            "while resetting admin password for {}",
-            &config.ldap_user_dn
-        ))?;
-    }
-    if config.force_update_private_key || config.force_ldap_user_pass_reset.is_yes() {
-        bail!("Restart the server without --force-update-private-key or --force-ldap-user-pass-reset to continue.");
-    }
-    let server_builder = infra::ldap_server::build_ldap_server(
-        &config,
-        backend_handler.clone(),
-        actix_server::Server::build(),
-    )
-    .context("while binding the LDAP server")?;
-    let server_builder =
-        infra::tcp_server::build_tcp_server(&config, backend_handler, server_builder)
-            .await
-            .context("while binding the TCP server")?;
-    // Run every hour.
-    let scheduler = Scheduler::new("0 0 * * * * *", sql_pool);
-    scheduler.start();
-    Ok(server_builder)
-}
+use tracing::{debug, info, warn};
 
 async fn run_server_command(opts: RunOpts) -> Result<()> {
     debug!("CLI: {:#?}", &opts);
@@ -195,59 +25,182 @@ async fn run_server_command(opts: RunOpts) -> Result<()> {
     let config = infra::configuration::init(opts)?;
     infra::logging::init(&config)?;
 
-    let server = set_up_server(config).await?.workers(1);
+    let workers = config.server_workers.max(1);
+    let server = set_up_server(config).await?.workers(workers);
 
     server.run().await.context("while starting the server")
 }
 
 async fn send_test_email_command(opts: TestEmailOpts) -> Result<()> {
+    let output = opts.output_opts.output;
     let to = opts.to.parse()?;
-    let config = infra::configuration::init(opts)?;
-    infra::logging::init(&config)?;
+    let config = infra::configuration::init(opts.clone())?;
+    infra::logging::init_for_output(&config, output)?;
 
-    mail::send_test_email(to, &config.smtp_options)
+    let result = mail::send_test_email(to, &config.smtp_options)
         .await
-        .context("Could not send email: {:#}")
+        .context("Could not send email")
+        .map(|()| TestEmailReport { to: opts.to });
+    print_result(output, "send_test_email", result)
 }
 
-async fn run_healthcheck(opts: RunOpts) -> Result<()> {
+async fn run_healthcheck(opts: HealthCheckOpts) -> Result<()> {
     debug!("CLI: {:#?}", &opts);
-    let config = infra::configuration::init(opts)?;
-    infra::logging::init(&config)?;
+    let output = opts.output_opts.output;
+    let config = infra::configuration::init(opts.run_opts)?;
+    infra::logging::init_for_output(&config, output)?;
 
     info!("Starting healthchecks");
+    print_result(output, "healthcheck", Ok(healthcheck::run(&config).await))
+}
 
-    use tokio::time::timeout;
-    let delay = Duration::from_millis(3000);
-    let (ldap, ldaps, api) = tokio::join!(
-        timeout(delay, healthcheck::check_ldap(config.ldap_port)),
-        timeout(delay, healthcheck::check_ldaps(&config.ldaps_options)),
-        timeout(delay, healthcheck::check_api(config.http_port)),
+async fn self_test_command(opts: SelfTestOpts) -> Result<()> {
+    let output = opts.output_opts.output;
+    let database_path =
+        std::env::temp_dir().join(format!("lldap-self-test-{:016x}.db", rand::random::<u64>()));
+    let config = infra::self_test::get_config(&database_path, opts.verbose)?;
+    infra::logging::init_for_output(&config, output)?;
+    info!(
+        "Starting the self-test with a temporary database at {}",
+        database_path.display()
     );
 
-    let failure = [ldap, ldaps, api]
-        .into_iter()
-        .flat_map(|res| {
-            if let Err(e) = &res {
-                error!("Error running the health check: {:#}", e);
-            }
-            res
-        })
-        .any(|r| r.is_err());
-    if failure {
-        bail!("Healthcheck failed")
+    let start = std::time::Instant::now();
+    let result: Result<()> = async {
+        let server = set_up_server(config.clone()).await?.workers(1).run();
+        let handle = server.handle();
+        actix_rt::spawn(server);
+        let result = infra::self_test::run(&config).await;
+        handle.stop(true).await;
+        result
+    }
+    .await;
+    if let Err(e) = std::fs::remove_file(&database_path) {
+        warn!("Could not remove the temporary database: {:#}", e);
+    }
+    let result = result.context("Self-test failed").map(|()| {
+        info!("Self-test passed");
+        SelfTestReport {
+            duration_ms: start.elapsed().as_millis() as u64,
+        }
+    });
+    print_result(output, "self_test", result)
+}
+
+async fn seed_command(opts: SeedOpts) -> Result<()> {
+    debug!("CLI: {:#?}", &opts);
+    let output = opts.output_opts.output;
+    let config = infra::configuration::init(opts.clone())?;
+    infra::logging::init_for_output(&config, output)?;
+    let sql_pool = setup_sql_tables(&get_database_url(&config)?).await?;
+    let backend_handler = SqlBackendHandler::new(config, sql_pool);
+    let result = infra::seed::seed(
+        &backend_handler,
+        &infra::seed::SeedOptions {
+            users: opts.users,
+            groups: opts.groups,
+            memberships_per_user: opts.memberships_per_user,
+            seed: opts.seed,
+        },
+    )
+    .await
+    .context("while seeding the database");
+    print_result(output, "seed", result)
+}
+
+async fn import_keycloak_command(opts: ImportKeycloakOpts) -> Result<()> {
+    debug!("CLI: {:#?}", &opts);
+    let output = opts.output_opts.output;
+    let config = infra::configuration::init(opts.clone())?;
+    infra::logging::init_for_output(&config, output)?;
+    let options = infra::keycloak_import::KeycloakImportOptions {
+        role_mappings: opts
+            .role_mappings
+            .iter()
+            .map(|m| infra::keycloak_import::parse_role_mapping(m))
+            .collect::<Result<_>>()?,
+    };
+    let delivery = if opts.send_invitations {
+        Some(
+            infra::reset_delivery::from_options(
+                &config.password_reset_options,
+                &config.smtp_options,
+                &config.http_url.0,
+            )?
+            .context("--send-invitations needs the password reset to be enabled")?,
+        )
     } else {
-        Ok(())
+        None
+    };
+    let realm = serde_json::from_str(
+        &std::fs::read_to_string(&opts.file)
+            .with_context(|| format!("while reading {}", opts.file.display()))?,
+    )
+    .context("while parsing the Keycloak realm export")?;
+    let sql_pool = setup_sql_tables(&get_database_url(&config)?).await?;
+    let backend_handler = SqlBackendHandler::new(config, sql_pool);
+    let result = async {
+        let summary = infra::keycloak_import::import(&backend_handler, &realm, &options)
+            .await
+            .context("while importing the Keycloak realm")?;
+        if let Some(delivery) = delivery {
+            infra::keycloak_import::send_invitations(
+                &backend_handler,
+                delivery.as_ref(),
+                &summary.created_users,
+            )
+            .await?;
+        }
+        Ok(summary)
     }
+    .await;
+    print_result(output, "import_keycloak", result)
 }
 
-async fn create_schema_command(opts: RunOpts) -> Result<()> {
+async fn verify_backup_command(opts: VerifyBackupOpts) -> Result<()> {
     debug!("CLI: {:#?}", &opts);
-    let config = infra::configuration::init(opts)?;
-    infra::logging::init(&config)?;
-    setup_sql_tables(&config.database_url).await?;
-    info!("Schema created successfully.");
-    Ok(())
+    let output = opts.output_opts.output;
+    let config = infra::configuration::init(opts.clone())?;
+    infra::logging::init_for_output(&config, output)?;
+    let bind_user = opts
+        .bind_user
+        .map(|user| UserId::new(&user))
+        .unwrap_or_else(|| config.ldap_user_dn.clone());
+    let bind_password = opts
+        .bind_password
+        .unwrap_or_else(|| config.ldap_user_pass.unsecure().to_owned());
+    let result = infra::backup_verification::verify_backup(
+        &opts.archive,
+        &config,
+        bind_user,
+        &bind_password,
+    )
+    .await
+    .context("while verifying the backup");
+    if let Ok(report) = &result {
+        report.log();
+        if report.is_restorable() {
+            info!("{} can be restored", opts.archive.display());
+        }
+    }
+    print_result(output, "verify_backup", result)
+        .with_context(|| format!("{} cannot be restored", opts.archive.display()))
+}
+
+async fn create_schema_command(opts: CreateSchemaOpts) -> Result<()> {
+    debug!("CLI: {:#?}", &opts);
+    let output = opts.output_opts.output;
+    let config = infra::configuration::init(opts.run_opts)?;
+    infra::logging::init_for_output(&config, output)?;
+    let result = setup_sql_tables(&config.database_url)
+        .await
+        .map(|_| SchemaReport {
+            schema_version: LAST_SCHEMA_VERSION.0,
+        });
+    if result.is_ok() {
+        info!("Schema created successfully.");
+    }
+    print_result(output, "create_schema", result)
 }
 
 #[actix::main]
@@ -259,5 +212,9 @@ async fn main() -> Result<()> {
         Command::HealthCheck(opts) => run_healthcheck(opts).await,
         Command::SendTestEmail(opts) => send_test_email_command(opts).await,
         Command::CreateSchema(opts) => create_schema_command(opts).await,
+        Command::SelfTest(opts) => self_test_command(opts).await,
+        Command::Seed(opts) => seed_command(opts).await,
+        Command::ImportKeycloak(opts) => import_keycloak_command(opts).await,
+        Command::VerifyBackup(opts) => verify_backup_command(opts).await,
     }
 }
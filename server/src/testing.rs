@@ -0,0 +1,162 @@
+//! In-process LLDAP server for the integration tests of applications using LLDAP.
+//!
+//! ```no_run
+//! let server = lldap::testing::TestServer::start().unwrap();
+//! // Connect to `server.ldap_url()` as `server.admin_dn()` with `server.admin_password()`.
+//! ```
+//!
+//! The server runs on its own thread with an in-memory database, so it works from both sync and
+//! async tests, and it is stopped when the `TestServer` is dropped.
+
+use crate::{
+    infra::{
+        configuration::{BackendKind, Configuration, ConfigurationBuilder},
+        self_test::{get_free_ports, random_secret},
+    },
+    set_up_server,
+};
+use anyhow::{anyhow, Result};
+use secstr::SecUtf8;
+use tokio::sync::oneshot;
+
+pub struct TestServer {
+    config: Configuration,
+    stop: Option<oneshot::Sender<()>>,
+    thread: Option<std::thread::JoinHandle<Result<()>>>,
+}
+
+impl TestServer {
+    /// Starts a server with the default configuration, listening on free local ports.
+    pub fn start() -> Result<Self> {
+        Self::start_with_config(ConfigurationBuilder::default())
+    }
+
+    /// Starts a server with the given configuration. The hosts, ports, backend, admin password
+    /// and private key are always overridden.
+    pub fn start_with_config(config: ConfigurationBuilder) -> Result<Self> {
+        let (ldap_port, http_port) = get_free_ports()?;
+        let config = config
+            .ldap_host("127.0.0.1".to_owned())
+            .ldap_port(ldap_port)
+            .http_host("127.0.0.1".to_owned())
+            .http_port(http_port)
+            .backend(BackendKind::Memory)
+            .ldap_user_pass(SecUtf8::from(random_secret()))
+            .key_file(String::new())
+            .key_seed(Some(SecUtf8::from(random_secret())))
+            .build()?;
+        let (ready_sender, ready_receiver) = std::sync::mpsc::channel();
+        let (stop_sender, stop_receiver) = oneshot::channel();
+        let server_config = config.clone();
+        let thread = std::thread::spawn(move || {
+            actix_rt::System::new().block_on(async move {
+                let server = match set_up_server(server_config).await {
+                    Ok(builder) => builder.workers(1).run(),
+                    Err(e) => {
+                        let message = format!("{:#}", e);
+                        let _ = ready_sender.send(Err(e));
+                        return Err(anyhow!(message));
+                    }
+                };
+                let handle = server.handle();
+                let _ = ready_sender.send(Ok(()));
+                tokio::select! {
+                    result = server => result?,
+                    _ = stop_receiver => handle.stop(true).await,
+                }
+                Ok(())
+            })
+        });
+        ready_receiver
+            .recv()
+            .map_err(|_| anyhow!("The test server thread stopped unexpectedly"))??;
+        Ok(Self {
+            config,
+            stop: Some(stop_sender),
+            thread: Some(thread),
+        })
+    }
+
+    pub fn ldap_port(&self) -> u16 {
+        self.config.ldap_port
+    }
+
+    pub fn http_port(&self) -> u16 {
+        self.config.http_port
+    }
+
+    /// E.g. `ldap://127.0.0.1:38901`.
+    pub fn ldap_url(&self) -> String {
+        format!("ldap://127.0.0.1:{}", self.config.ldap_port)
+    }
+
+    /// E.g. `http://127.0.0.1:38902`, the base of the web UI and of the GraphQL API.
+    pub fn http_url(&self) -> String {
+        format!("http://127.0.0.1:{}", self.config.http_port)
+    }
+
+    pub fn base_dn(&self) -> &str {
+        &self.config.ldap_base_dn
+    }
+
+    /// The DN of the admin user, to bind with.
+    pub fn admin_dn(&self) -> String {
+        format!(
+            "uid={},ou=people,{}",
+            self.config.ldap_user_dn, self.config.ldap_base_dn
+        )
+    }
+
+    pub fn admin_password(&self) -> &str {
+        self.config.ldap_user_pass.unsecure()
+    }
+
+    /// Stops the server, and returns the error that made it stop early, if any.
+    pub fn stop(mut self) -> Result<()> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .map_err(|_| anyhow!("The test server thread panicked"))?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::ldap_client::LdapClient;
+
+    #[test]
+    fn test_start_and_bind() {
+        let server = TestServer::start().unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let mut client = LdapClient::connect(&format!("127.0.0.1:{}", server.ldap_port()))
+                .await
+                .unwrap();
+            assert!(client
+                .bind(&server.admin_dn(), server.admin_password())
+                .await
+                .unwrap());
+            assert!(!client.bind(&server.admin_dn(), "wrong").await.unwrap());
+        });
+        server.stop().unwrap();
+    }
+}
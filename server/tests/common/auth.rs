@@ -12,6 +12,7 @@ pub fn get_token(client: &Client) -> String {
             serde_json::to_string(&lldap_auth::login::ClientSimpleLoginRequest {
                 username: username.into(),
                 password,
+                remember_me: false,
             })
             .expect("Failed to encode the username/password as json to log in"),
         )
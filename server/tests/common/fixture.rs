@@ -109,6 +109,7 @@ impl LLDAPFixture {
                     first_name: None,
                     last_name: None,
                     attributes: None,
+                    external_id: None,
                 },
             },
         )